@@ -0,0 +1,521 @@
+//! Smoothed-aggregation algebraic multigrid, the [`crate::Preconditioner::Amg`] of CG.
+//!
+//! The normal matrix couples the free vertices along the shots, so its graph is the cave
+//! graph itself and aggregating neighbouring stations is natural: a chain coarsens by a
+//! factor of about three per level. Each level keeps its prolongator, a translation of each
+//! aggregate smoothed by a damped Jacobi step, and the Galerkin coarse operator `P^T A P`,
+//! until the coarse size is reached; that last level is factored densely. One V-cycle with the
+//! same damped Jacobi sweep before and after the coarse correction is a symmetric positive
+//! definite operator, as CG requires.
+
+use nalgebra_sparse::CsrMatrix;
+
+/// Largest number of levels, the coarsest included.
+const MAX_LEVELS: usize = 25;
+
+/// Damped Jacobi sweeps before, and again after, the coarse-grid correction.
+const SMOOTHING_SWEEPS: usize = 1;
+
+/// Couplings weaker than this fraction of `sqrt(a_ii a_jj)` are ignored by the aggregation,
+/// which otherwise merges the fill-in of the coarse operators into ever larger aggregates.
+const STRENGTH_THRESHOLD: f64 = 0.08;
+
+/// Coarsening stops when a level keeps more than this fraction of the rows of the finer one.
+const MIN_COARSENING: f64 = 0.9;
+
+/// One level of the hierarchy, but the coarsest.
+struct Level {
+    a: CsrMatrix<f64>,
+    inv_diag: Vec<f64>,
+    /// Damping of the Jacobi smoother, `4 / (3 rho(D^-1 A))`.
+    omega: f64,
+    /// Prolongator from the next coarser level, and its transpose.
+    p: CsrMatrix<f64>,
+    p_t: CsrMatrix<f64>,
+}
+
+/// Multigrid hierarchy of a normal matrix, built once and shared by both axes.
+pub(crate) struct Amg {
+    levels: Vec<Level>,
+    coarsest: Coarsest,
+}
+
+/// Solver of the coarsest level.
+enum Coarsest {
+    Dense(DenseCholesky),
+    /// Jacobi scaling, when coarsening stalled above the coarse size (e.g. rows without
+    /// couplings) and a dense factor would be too large.
+    Diagonal(Vec<f64>),
+}
+
+impl Amg {
+    /// Builds the hierarchy of `a`, coarsening until at most `coarse_size` rows remain.
+    pub(crate) fn new(a: &CsrMatrix<f64>, coarse_size: usize) -> Amg {
+        let mut levels = Vec::new();
+        let mut a = a.clone();
+        // Near-null space (the rigid translation) expressed at the current level.
+        let mut translation = vec![1.0; a.nrows()];
+        while a.nrows() > coarse_size.max(1) && levels.len() + 1 < MAX_LEVELS {
+            let inv_diag = inverse_diagonal(&a);
+            let (aggregates, count) = aggregate(&a);
+            if count as f64 > MIN_COARSENING * a.nrows() as f64 {
+                break;
+            }
+            let omega = 4.0 / (3.0 * spectral_radius(&a, &inv_diag));
+            let p = prolongator(&a, &inv_diag, omega, &aggregates, &mut translation, count);
+            let p_t = transpose(&p);
+            let coarse = multiply(&p_t, &multiply(&a, &p));
+            levels.push(Level {
+                a: std::mem::replace(&mut a, coarse),
+                inv_diag,
+                omega,
+                p,
+                p_t,
+            });
+        }
+
+        let coarsest = match a.nrows() <= coarse_size.max(1) {
+            true => Coarsest::Dense(DenseCholesky::new(&a)),
+            false => Coarsest::Diagonal(inverse_diagonal(&a)),
+        };
+        Amg { levels, coarsest }
+    }
+
+    /// `z = M^-1 r`, one V-cycle from a zero initial guess.
+    pub(crate) fn apply(&self, r: &[f64], z: &mut [f64]) {
+        let x = self.cycle(0, r);
+        for (z, x) in z.iter_mut().zip(x) {
+            *z = x;
+        }
+    }
+
+    fn cycle(&self, level: usize, b: &[f64]) -> Vec<f64> {
+        let Some(l) = self.levels.get(level) else {
+            return match &self.coarsest {
+                Coarsest::Dense(cholesky) => cholesky.solve(b),
+                Coarsest::Diagonal(inv_diag) => {
+                    b.iter().zip(inv_diag).map(|(b, d)| b * d).collect()
+                }
+            };
+        };
+        let mut x = vec![0.0; b.len()];
+        let mut ax = vec![0.0; b.len()];
+        let smooth = |x: &mut [f64], ax: &mut [f64]| {
+            for _ in 0..SMOOTHING_SWEEPS {
+                spmv(&l.a, x, ax);
+                for i in 0..x.len() {
+                    x[i] += l.omega * l.inv_diag[i] * (b[i] - ax[i]);
+                }
+            }
+        };
+
+        smooth(&mut x, &mut ax);
+        spmv(&l.a, &x, &mut ax);
+        let residual: Vec<f64> = b.iter().zip(&ax).map(|(b, ax)| b - ax).collect();
+        let mut coarse_b = vec![0.0; l.p_t.nrows()];
+        spmv(&l.p_t, &residual, &mut coarse_b);
+        let coarse_x = self.cycle(level + 1, &coarse_b);
+        spmv(&l.p, &coarse_x, &mut ax);
+        for (x, correction) in x.iter_mut().zip(&ax) {
+            *x += correction;
+        }
+        smooth(&mut x, &mut ax);
+        x
+    }
+}
+
+/// Greedy aggregation over the strong couplings of `a`, see [`STRENGTH_THRESHOLD`]: the
+/// aggregate of each row and their count.
+///
+/// A first pass makes an aggregate of every row whose neighbours are all still free, with
+/// those neighbours. Rows left over join the aggregate of their strongest neighbour from that
+/// pass, and the few that have none group with their free neighbours.
+fn aggregate(a: &CsrMatrix<f64>) -> (Vec<usize>, usize) {
+    const FREE: usize = usize::MAX;
+    let n = a.nrows();
+    let (offsets, columns, values) = (a.row_offsets(), a.col_indices(), a.values());
+    let diagonal: Vec<f64> = (0..n)
+        .map(|i| {
+            (offsets[i]..offsets[i + 1])
+                .filter(|&k| columns[k] == i)
+                .map(|k| values[k].abs())
+                .sum()
+        })
+        .collect();
+    let diagonal = &diagonal;
+    let neighbours = |i: usize| {
+        (offsets[i]..offsets[i + 1])
+            .filter(move |&k| {
+                let j = columns[k];
+                j != i && values[k].abs() > STRENGTH_THRESHOLD * (diagonal[i] * diagonal[j]).sqrt()
+            })
+            .map(|k| (columns[k], values[k].abs()))
+    };
+
+    let mut aggregates = vec![FREE; n];
+    let mut count = 0;
+    for i in 0..n {
+        if aggregates[i] == FREE && neighbours(i).all(|(j, _)| aggregates[j] == FREE) {
+            aggregates[i] = count;
+            for (j, _) in neighbours(i) {
+                aggregates[j] = count;
+            }
+            count += 1;
+        }
+    }
+
+    let roots = aggregates.clone();
+    for (i, aggregate) in aggregates.iter_mut().enumerate() {
+        if *aggregate == FREE {
+            let strongest = neighbours(i)
+                .filter(|&(j, _)| roots[j] != FREE)
+                .max_by(|a, b| a.1.total_cmp(&b.1));
+            if let Some((j, _)) = strongest {
+                *aggregate = roots[j];
+            }
+        }
+    }
+
+    for i in 0..n {
+        if aggregates[i] == FREE {
+            aggregates[i] = count;
+            for (j, _) in neighbours(i) {
+                if aggregates[j] == FREE {
+                    aggregates[j] = count;
+                }
+            }
+            count += 1;
+        }
+    }
+    (aggregates, count)
+}
+
+/// Smoothed prolongator `(I - omega D^-1 A) P0`. Column `g` of the tentative `P0` is the
+/// `near_null` vector restricted to aggregate `g` and normalized, so that `P0` reproduces it
+/// exactly; `near_null` is replaced by its coarse counterpart, the norms of those pieces.
+fn prolongator(
+    a: &CsrMatrix<f64>,
+    inv_diag: &[f64],
+    omega: f64,
+    aggregates: &[usize],
+    near_null: &mut Vec<f64>,
+    count: usize,
+) -> CsrMatrix<f64> {
+    let mut norms = vec![0.0; count];
+    for (&g, b) in aggregates.iter().zip(near_null.iter()) {
+        norms[g] += b * b;
+    }
+    for norm in &mut norms {
+        *norm = f64::sqrt(*norm);
+    }
+    let tentative: Vec<f64> = aggregates
+        .iter()
+        .zip(near_null.iter())
+        .map(|(&g, b)| b / norms[g])
+        .collect();
+    *near_null = norms;
+
+    let (offsets, columns, values) = (a.row_offsets(), a.col_indices(), a.values());
+    let mut rows = RowBuilder::new(count);
+    for i in 0..a.nrows() {
+        rows.add(aggregates[i], tentative[i]);
+        for k in offsets[i]..offsets[i + 1] {
+            let j = columns[k];
+            rows.add(
+                aggregates[j],
+                -omega * inv_diag[i] * values[k] * tentative[j],
+            );
+        }
+        rows.end_row();
+    }
+    rows.build()
+}
+
+/// Upper bound of the spectral radius of `D^-1 A` from its Gershgorin circles.
+fn spectral_radius(a: &CsrMatrix<f64>, inv_diag: &[f64]) -> f64 {
+    let (offsets, values) = (a.row_offsets(), a.values());
+    let radius = (0..a.nrows())
+        .map(|i| {
+            inv_diag[i]
+                * values[offsets[i]..offsets[i + 1]]
+                    .iter()
+                    .map(|v| v.abs())
+                    .sum::<f64>()
+        })
+        .fold(0.0, f64::max);
+    if radius > 0.0 { radius } else { 1.0 }
+}
+
+/// Inverse of the diagonal of `a`; rows without a positive diagonal get 1.
+fn inverse_diagonal(a: &CsrMatrix<f64>) -> Vec<f64> {
+    crate::inverse_diagonal(a).iter().copied().collect()
+}
+
+pub(crate) fn transpose(a: &CsrMatrix<f64>) -> CsrMatrix<f64> {
+    let (offsets, columns, values) = (a.row_offsets(), a.col_indices(), a.values());
+    let mut t_offsets = vec![0; a.ncols() + 1];
+    for &j in columns {
+        t_offsets[j + 1] += 1;
+    }
+    for j in 0..a.ncols() {
+        t_offsets[j + 1] += t_offsets[j];
+    }
+    let mut next = t_offsets.clone();
+    let mut t_columns = vec![0; columns.len()];
+    let mut t_values = vec![0.0; values.len()];
+    for i in 0..a.nrows() {
+        for k in offsets[i]..offsets[i + 1] {
+            let slot = &mut next[columns[k]];
+            t_columns[*slot] = i;
+            t_values[*slot] = values[k];
+            *slot += 1;
+        }
+    }
+    CsrMatrix::try_from_csr_data(a.ncols(), a.nrows(), t_offsets, t_columns, t_values)
+        .expect("transposed CSR data is valid")
+}
+
+/// Sparse product `a * b`, row by row.
+pub(crate) fn multiply(a: &CsrMatrix<f64>, b: &CsrMatrix<f64>) -> CsrMatrix<f64> {
+    let (a_offsets, a_columns, a_values) = (a.row_offsets(), a.col_indices(), a.values());
+    let (b_offsets, b_columns, b_values) = (b.row_offsets(), b.col_indices(), b.values());
+    let mut rows = RowBuilder::new(b.ncols());
+    for i in 0..a.nrows() {
+        for k in a_offsets[i]..a_offsets[i + 1] {
+            let j = a_columns[k];
+            for l in b_offsets[j]..b_offsets[j + 1] {
+                rows.add(b_columns[l], a_values[k] * b_values[l]);
+            }
+        }
+        rows.end_row();
+    }
+    rows.build()
+}
+
+/// Accumulates the entries of a CSR matrix one row at a time, summing duplicates.
+pub(crate) struct RowBuilder {
+    offsets: Vec<usize>,
+    columns: Vec<usize>,
+    values: Vec<f64>,
+    /// Dense accumulator of the current row and the columns it touched.
+    row: Vec<f64>,
+    touched: Vec<usize>,
+    seen: Vec<bool>,
+}
+
+impl RowBuilder {
+    pub(crate) fn new(ncols: usize) -> RowBuilder {
+        RowBuilder {
+            offsets: vec![0],
+            columns: Vec::new(),
+            values: Vec::new(),
+            row: vec![0.0; ncols],
+            touched: Vec::new(),
+            seen: vec![false; ncols],
+        }
+    }
+
+    pub(crate) fn add(&mut self, column: usize, value: f64) {
+        if !self.seen[column] {
+            self.seen[column] = true;
+            self.touched.push(column);
+        }
+        self.row[column] += value;
+    }
+
+    pub(crate) fn end_row(&mut self) {
+        self.touched.sort_unstable();
+        for &j in &self.touched {
+            self.columns.push(j);
+            self.values.push(self.row[j]);
+            self.row[j] = 0.0;
+            self.seen[j] = false;
+        }
+        self.touched.clear();
+        self.offsets.push(self.columns.len());
+    }
+
+    pub(crate) fn build(self) -> CsrMatrix<f64> {
+        let (nrows, ncols) = (self.offsets.len() - 1, self.row.len());
+        CsrMatrix::try_from_csr_data(nrows, ncols, self.offsets, self.columns, self.values)
+            .expect("accumulated CSR data is valid")
+    }
+}
+
+/// `y = A x` on slices.
+fn spmv(a: &CsrMatrix<f64>, x: &[f64], y: &mut [f64]) {
+    let (offsets, columns, values) = (a.row_offsets(), a.col_indices(), a.values());
+    for (i, y) in y.iter_mut().enumerate() {
+        *y = (offsets[i]..offsets[i + 1])
+            .map(|k| values[k] * x[columns[k]])
+            .sum();
+    }
+}
+
+/// Dense Cholesky factor `L` (row-major, lower triangle) of the coarsest operator, also
+/// used for the blocks of [`crate::Preconditioner::AdditiveSchwarz`].
+///
+/// An island of free vertices with no fixed one makes the matrix only semi-definite. Pivots
+/// that vanish relative to the largest diagonal are zeroed, and the matching unknowns solve
+/// to 0: the result stays a symmetric positive semi-definite pseudo-inverse.
+pub(crate) struct DenseCholesky {
+    n: usize,
+    l: Vec<f64>,
+}
+
+impl DenseCholesky {
+    fn new(a: &CsrMatrix<f64>) -> DenseCholesky {
+        let n = a.nrows();
+        let mut dense = vec![0.0; n * n];
+        let (offsets, columns, values) = (a.row_offsets(), a.col_indices(), a.values());
+        for i in 0..n {
+            for k in offsets[i]..offsets[i + 1] {
+                dense[i * n + columns[k]] += values[k];
+            }
+        }
+        DenseCholesky::factor(n, dense)
+    }
+
+    /// Factors the symmetric `n x n` row-major matrix `l` in place.
+    pub(crate) fn factor(n: usize, mut l: Vec<f64>) -> DenseCholesky {
+        let threshold = 1e-12 * (0..n).map(|i| l[i * n + i].abs()).fold(0.0, f64::max);
+
+        for j in 0..n {
+            let pivot = l[j * n + j] - (0..j).map(|k| l[j * n + k].powi(2)).sum::<f64>();
+            let pivot = if pivot > threshold { pivot.sqrt() } else { 0.0 };
+            l[j * n + j] = pivot;
+            for i in j + 1..n {
+                let dot: f64 = (0..j).map(|k| l[i * n + k] * l[j * n + k]).sum();
+                l[i * n + j] = match pivot > 0.0 {
+                    true => (l[i * n + j] - dot) / pivot,
+                    false => 0.0,
+                };
+            }
+        }
+        for i in 0..n {
+            for j in i + 1..n {
+                l[i * n + j] = 0.0;
+            }
+        }
+        DenseCholesky { n, l }
+    }
+
+    /// `x = (L L^T)^+ b`.
+    pub(crate) fn solve(&self, b: &[f64]) -> Vec<f64> {
+        let (n, l) = (self.n, &self.l);
+        let mut x = b.to_vec();
+        for i in 0..n {
+            let dot: f64 = (0..i).map(|k| l[i * n + k] * x[k]).sum();
+            let pivot = l[i * n + i];
+            x[i] = if pivot > 0.0 {
+                (x[i] - dot) / pivot
+            } else {
+                0.0
+            };
+        }
+        for i in (0..n).rev() {
+            let dot: f64 = (i + 1..n).map(|k| l[k * n + i] * x[k]).sum();
+            let pivot = l[i * n + i];
+            x[i] = if pivot > 0.0 {
+                (x[i] - dot) / pivot
+            } else {
+                0.0
+            };
+        }
+        x
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra::DVector;
+    use nalgebra_sparse::CooMatrix;
+
+    /// Normal matrix of a chain of `n` free stations between two anchors, with a loop shot
+    /// every `loop_every` stations.
+    fn chain(n: usize, loop_every: usize) -> CsrMatrix<f64> {
+        let mut coo = CooMatrix::new(n, n);
+        let mut couple = |i: usize, j: usize, w: f64| {
+            coo.push(i, i, w);
+            coo.push(j, j, w);
+            coo.push(i, j, -w);
+            coo.push(j, i, -w);
+        };
+        for i in 0..n - 1 {
+            couple(i, i + 1, 1.0 + (i % 3) as f64);
+        }
+        for i in (0..n.saturating_sub(loop_every)).step_by(loop_every) {
+            couple(i, i + loop_every, 0.5);
+        }
+        coo.push(0, 0, 1.0);
+        coo.push(n - 1, n - 1, 1.0);
+        CsrMatrix::from(&coo)
+    }
+
+    fn dense_solve(a: &CsrMatrix<f64>, b: &DVector<f64>) -> DVector<f64> {
+        nalgebra::DMatrix::from(a).cholesky().unwrap().solve(b)
+    }
+
+    #[test]
+    fn aggregation_covers_every_row() {
+        let a = chain(300, 40);
+        let (aggregates, count) = aggregate(&a);
+        assert_eq!(aggregates.len(), 300);
+        assert!(aggregates.iter().all(|&g| g < count));
+        // A chain coarsens by about three.
+        assert!((90..=110).contains(&count), "{count}");
+        let mut sizes = vec![0; count];
+        for &g in &aggregates {
+            sizes[g] += 1;
+        }
+        assert!(sizes.iter().all(|&s| s > 0));
+    }
+
+    #[test]
+    fn coarse_sizes_above_the_matrix_give_a_direct_solve() {
+        let a = chain(50, 10);
+        let b = DVector::from_fn(50, |i, _| (i as f64 * 0.3).sin());
+        let amg = Amg::new(&a, 64);
+        assert!(amg.levels.is_empty());
+        let mut z = DVector::zeros(50);
+        amg.apply(b.as_slice(), z.as_mut_slice());
+        assert!((z - dense_solve(&a, &b)).norm() < 1e-10);
+    }
+
+    #[test]
+    fn v_cycle_is_symmetric_positive_definite() {
+        let a = chain(2000, 40);
+        let amg = Amg::new(&a, 16);
+        assert!(amg.levels.len() >= 3);
+        let u = DVector::from_fn(2000, |i, _| ((i * 7919) % 101) as f64 / 101.0 - 0.5);
+        let v = DVector::from_fn(2000, |i, _| (i as f64 * 0.01).cos());
+        let (mut mu, mut mv) = (DVector::zeros(2000), DVector::zeros(2000));
+        amg.apply(u.as_slice(), mu.as_mut_slice());
+        amg.apply(v.as_slice(), mv.as_mut_slice());
+        // Symmetric and positive definite, as CG requires.
+        assert!((mu.dot(&v) - u.dot(&mv)).abs() < 1e-9 * mu.norm() * v.norm());
+        assert!(mu.dot(&u) > 0.0 && mv.dot(&v) > 0.0);
+    }
+
+    #[test]
+    fn dense_cholesky_pseudo_inverts_semi_definite_blocks() {
+        // A free island of two stations: [[1, -1], [-1, 1]] has the null space (1, 1).
+        let mut coo = CooMatrix::new(3, 3);
+        for (i, j, v) in [
+            (0, 0, 2.0),
+            (1, 1, 1.0),
+            (2, 2, 1.0),
+            (1, 2, -1.0),
+            (2, 1, -1.0),
+        ] {
+            coo.push(i, j, v);
+        }
+        let cholesky = DenseCholesky::new(&CsrMatrix::from(&coo));
+        let x = cholesky.solve(&[4.0, 1.0, -1.0]);
+        assert!((x[0] - 2.0).abs() < 1e-12);
+        assert!(x.iter().all(|x| x.is_finite()));
+        // The particular solution satisfies the consistent island equations.
+        assert!((x[1] - x[2] - 1.0).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,203 @@
+//! Re-solve after a single anchor moves, for dragging a fixed station interactively.
+//!
+//! Moving a fixed vertex leaves the normal equations matrix alone, its fixity being the same:
+//! the anchor only enters the right-hand sides of its free neighbors, as `w * x_fixed` per
+//! enabled edge. [`GraphContext::resolve_anchor_move`] shifts those entries of the cached
+//! system in place, keeps its preconditioner, and solves from the previous solution within a
+//! time budget. A drag moves the anchor a little each frame, so the warm start is close and
+//! few iterations are needed; a solve cut short by the budget is approximate, see
+//! [`SolveStats::stop_reason`], and the next frame carries on from it.
+//!
+//! The result is stored like that of [`crate::graph_solve`], with the time spent updating the
+//! right-hand sides in [`SolveStats::assemble_ms`]. Without cached normal equations, they are
+//! assembled at the new position instead.
+
+use crate::{
+    COMPASS_ERR_INVALID_ARGUMENT, COMPASS_ERR_PANIC, COMPASS_OK, GraphContext, Solution,
+    SolveError, SolveOptions, SolveStats, Stopwatch, Tolerance, deadline, write_stats,
+};
+use std::ffi::{c_double, c_int};
+
+impl GraphContext {
+    /// Moves the fixed `vertex` to `(x, y)` and solves again within `max_ms` milliseconds, 0
+    /// = no limit, with the default iterations and tolerance of [`SolveOptions`]. See the
+    /// [module documentation](self).
+    pub fn resolve_anchor_move(
+        &mut self,
+        vertex: usize,
+        x: f64,
+        y: f64,
+        max_ms: f64,
+    ) -> Result<&Solution, SolveError> {
+        let clock = Stopwatch::start();
+        let invalid = |message: String| SolveError {
+            code: COMPASS_ERR_INVALID_ARGUMENT,
+            message,
+        };
+        if vertex >= self.graph.num_vertices() {
+            return Err(invalid(format!("vertex {vertex} out of range")));
+        }
+        if !self.graph.fixed[vertex] {
+            return Err(invalid(format!("vertex {vertex} is not fixed")));
+        }
+        if !(x.is_finite() && y.is_finite()) {
+            return Err(invalid(format!("position ({x}, {y}) is not finite")));
+        }
+        if max_ms < 0.0 || max_ms.is_nan() {
+            return Err(invalid(format!("time budget of {max_ms} ms is negative")));
+        }
+        let (shift_x, shift_y) = (x - self.graph.x[vertex], y - self.graph.y[vertex]);
+        let ties = self.ties(vertex);
+        if let Some(system) = &mut self.system {
+            for (row, w) in ties
+                .iter()
+                .filter_map(|&(v, w)| Some((system.mapping[v]?, w)))
+            {
+                system.bx[row] += w * shift_x;
+                system.by[row] += w * shift_y;
+            }
+        }
+        (self.graph.x[vertex], self.graph.y[vertex]) = (x, y);
+        let update_ms = clock.ms();
+
+        let defaults = SolveOptions::default();
+        let tolerance = Tolerance {
+            deadline: match max_ms {
+                0.0 => None,
+                ms => deadline(ms / 1e3),
+            },
+            ..Tolerance::from(defaults.tolerance)
+        };
+        let iterations = defaults.iterations as usize;
+        self.solve(iterations, tolerance, Default::default(), None, &|| false)?;
+        let Some(solution) = &mut self.solution else {
+            unreachable!("stored by the solve");
+        };
+        solution.stats.assemble_ms += update_ms;
+        solution.stats.total_ms = clock.ms();
+        Ok(solution)
+    }
+}
+
+/// Moves the fixed `vertex` of the graph behind `handle` to `(new_x, new_y)` and solves again
+/// within `max_ms` milliseconds, 0 = no limit, see [`GraphContext::resolve_anchor_move`]. Read
+/// the result with [`crate::graph_get_coordinates`]; `stats` may be null.
+///
+/// # Returns
+///
+/// * [`COMPASS_OK`], also when the budget ran out first: see [`SolveStats::stop_reason`].
+/// * [`COMPASS_ERR_INVALID_ARGUMENT`] for a null handle, a vertex out of range or not fixed, a
+///   position that is not finite or a negative or NaN budget; nothing changes.
+/// * The error of the solve otherwise, the anchor staying at its new position.
+#[unsafe(no_mangle)]
+pub extern "C" fn graph_resolve_anchor_move(
+    handle: *mut GraphContext,
+    vertex: c_int,
+    new_x: c_double,
+    new_y: c_double,
+    max_ms: c_double,
+    stats: *mut SolveStats,
+) -> c_int {
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        // Safety: We assume the caller guarantees a valid (or null) handle.
+        let (Some(ctx), Ok(vertex)) = (unsafe { handle.as_mut() }, usize::try_from(vertex)) else {
+            return COMPASS_ERR_INVALID_ARGUMENT;
+        };
+        match ctx.resolve_anchor_move(vertex, new_x, new_y, max_ms) {
+            Ok(solution) => {
+                write_stats(stats, &solution.stats);
+                COMPASS_OK
+            }
+            Err(err) => err.code,
+        }
+    }));
+
+    result.unwrap_or_else(|_| {
+        eprintln!("Panic caught in graph_resolve_anchor_move");
+        COMPASS_ERR_PANIC
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{COMPASS_STOP_CONVERGED, COMPASS_STOP_TIME_BUDGET, Graph, graph_free, graph_solve};
+
+    /// Anchors 0 and 5 at both ends of a misclosed traverse with a loop 1-2-3-1.
+    fn traverse() -> Graph {
+        let mut graph = Graph::default();
+        for v in 0..6 {
+            graph.add_vertex(10.0 * v as f64, 0.0, 0.0, v == 0 || v == 5);
+        }
+        for (u, v, dx, dy) in [
+            (0, 1, 10.1, 0.2),
+            (1, 2, 9.8, -0.1),
+            (2, 3, 10.2, 0.3),
+            (3, 1, -20.1, 0.0),
+            (3, 4, 9.9, 0.1),
+            (4, 5, 10.0, -0.2),
+        ] {
+            graph.add_edge(u, v, dx, dy, 0.0, 1.0);
+        }
+        graph
+    }
+
+    #[test]
+    fn moving_an_anchor_matches_a_full_solve() {
+        let handle = GraphContext::into_raw(traverse());
+        let mut stats = SolveStats {
+            struct_size: size_of::<SolveStats>(),
+            ..SolveStats::default()
+        };
+        let options = SolveOptions {
+            tolerance: 1e-12,
+            ..SolveOptions::default()
+        };
+        assert_eq!(graph_solve(handle, &options, &mut stats), COMPASS_OK);
+        let code = graph_resolve_anchor_move(handle, 5, 52.0, 3.0, 10_000.0, &mut stats);
+        assert_eq!(
+            (code, stats.stop_reason),
+            (COMPASS_OK, COMPASS_STOP_CONVERGED)
+        );
+
+        let mut moved = traverse();
+        (moved.x[5], moved.y[5]) = (52.0, 3.0);
+        let full = moved.solve(10_000, 1e-8).unwrap();
+        {
+            let ctx = unsafe { &*handle };
+            let (x, y) = ctx.coordinates();
+            for v in 0..6 {
+                assert!((x[v] - full.x[v]).abs() < 1e-6 && (y[v] - full.y[v]).abs() < 1e-6);
+            }
+            assert_eq!((x[5], y[5]), (52.0, 3.0));
+            // The shifted right-hand sides are those of the normal equations at the new
+            // position.
+            let system = ctx.system.as_ref().unwrap();
+            let assembled = moved.normal_equations(&[]).unwrap();
+            assert!((&system.bx - &assembled.bx).amax() < 1e-12);
+            assert!((&system.by - &assembled.by).amax() < 1e-12);
+            assert_eq!(system.matrix, assembled.matrix);
+        }
+
+        // A budget too short for the solve leaves an approximate result.
+        let code = graph_resolve_anchor_move(handle, 0, -40.0, 7.0, 1e-9, &mut stats);
+        assert_eq!(
+            (code, stats.stop_reason),
+            (COMPASS_OK, COMPASS_STOP_TIME_BUDGET)
+        );
+
+        for (vertex, x) in [(1, 0.0), (6, 0.0), (0, f64::NAN)] {
+            let code = graph_resolve_anchor_move(handle, vertex, x, 0.0, 0.0, &mut stats);
+            assert_eq!(code, COMPASS_ERR_INVALID_ARGUMENT);
+        }
+        let code = graph_resolve_anchor_move(handle, 0, 0.0, 0.0, -1.0, &mut stats);
+        assert_eq!(code, COMPASS_ERR_INVALID_ARGUMENT);
+        assert_eq!(unsafe { &*handle }.graph.x[0], -40.0);
+        graph_free(handle);
+        let null = std::ptr::null_mut();
+        assert_eq!(
+            graph_resolve_anchor_move(null, 0, 0.0, 0.0, 0.0, &mut stats),
+            COMPASS_ERR_INVALID_ARGUMENT
+        );
+    }
+}
@@ -0,0 +1,63 @@
+//! Coarse performance check of [`Solution::find_near_duplicates`] on a synthetic network of
+//! a million stations: fails when the search takes more than [`BUDGET`]. Being a bench target
+//! it never runs with the tests:
+//!
+//! ```text
+//! cargo bench --features fixtures --bench duplicates [-- RADIUS]
+//! ```
+//!
+//! `RADIUS` defaults to [`DEFAULT_RADIUS`]. The observed shots chained from the first station
+//! stand in for an adjustment, which would only move the stations by the loop misclosures.
+
+use graph_solver::fixtures::synthetic_cave;
+use graph_solver::{Graph, Solution};
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+
+const SEED: u64 = 1;
+const STATIONS: usize = 1_000_000;
+const DEFAULT_RADIUS: f64 = 0.5;
+const BUDGET: Duration = Duration::from_secs(1);
+
+fn main() -> ExitCode {
+    // Cargo passes `--bench` to bench targets; the radius is the first number.
+    let radius = std::env::args()
+        .skip(1)
+        .find_map(|arg| arg.parse::<f64>().ok())
+        .unwrap_or(DEFAULT_RADIUS);
+    let graph = synthetic_cave(SEED, STATIONS);
+    let solution = traverse(&graph);
+    let start = Instant::now();
+    let pairs = solution.find_near_duplicates(&graph, radius);
+    let elapsed = start.elapsed();
+    println!(
+        "{} pairs within {radius} among {} stations in {elapsed:.2?} (budget {BUDGET:?})",
+        pairs.len(),
+        graph.num_vertices()
+    );
+    match elapsed <= BUDGET {
+        true => ExitCode::SUCCESS,
+        false => ExitCode::FAILURE,
+    }
+}
+
+/// Coordinates of each station from the first shot that reaches it.
+fn traverse(graph: &Graph) -> Solution {
+    let n = graph.num_vertices();
+    let (mut x, mut y) = (vec![0.0; n], vec![0.0; n]);
+    let mut placed = vec![false; n];
+    placed[0] = true;
+    for e in 0..graph.num_edges() {
+        let (from, to) = (graph.from[e], graph.to[e]);
+        if placed[from] && !placed[to] {
+            x[to] = x[from] + graph.dx[e];
+            y[to] = y[from] + graph.dy[e];
+            placed[to] = true;
+        }
+    }
+    Solution {
+        x,
+        y,
+        ..Solution::default()
+    }
+}
@@ -0,0 +1,43 @@
+//! Performance regression check: solves the [`GOLDEN`] fixtures and fails when one of them
+//! takes more than a set factor of its recorded Conjugate Gradient iterations. Being a bench
+//! target it never runs with the tests:
+//!
+//! ```text
+//! cargo bench --features fixtures --bench golden [-- FACTOR]
+//! ```
+//!
+//! `FACTOR` defaults to [`DEFAULT_REGRESSION_FACTOR`].
+
+use graph_solver::fixtures::{DEFAULT_REGRESSION_FACTOR, GOLDEN, check_golden};
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    // Cargo passes `--bench` to bench targets; the factor is the first number.
+    let factor = std::env::args()
+        .skip(1)
+        .find_map(|arg| arg.parse::<f64>().ok())
+        .unwrap_or(DEFAULT_REGRESSION_FACTOR);
+    let regressions = match check_golden(factor) {
+        Ok(regressions) => regressions,
+        Err(err) => {
+            eprintln!("golden solve failed: {}", err.message);
+            return ExitCode::FAILURE;
+        }
+    };
+    for regression in &regressions {
+        let fixture = &regression.fixture;
+        eprintln!(
+            "{}: {} iterations, recorded {} (factor {factor})",
+            fixture.name, regression.iterations, fixture.iterations
+        );
+    }
+    println!(
+        "{} of {} golden fixtures regressed",
+        regressions.len(),
+        GOLDEN.len()
+    );
+    match regressions.is_empty() {
+        true => ExitCode::SUCCESS,
+        false => ExitCode::FAILURE,
+    }
+}
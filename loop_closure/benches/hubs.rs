@@ -0,0 +1,104 @@
+//! Solve time of a network with a hub station: a square grid of stations with its first
+//! station also tied by a surface shot to one station in `SPOKE_EVERY`, the kind of entrance
+//! a whole survey project radiates from. The row of the hub holds tens of thousands of
+//! nonzeros, which the product of CG gives a thread of its own.
+//!
+//! ```text
+//! cargo bench --features fixtures --bench hubs
+//! ```
+//!
+//! The `hub_product` group times the product alone with the rows split by nonzeros, as the
+//! solve does, against the baseline split into runs of as many rows. The `hub` group times
+//! whole solves; Jacobi-scaled CG needs too many iterations on a million stations, so it
+//! stops at the smaller grid.
+
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use graph_solver::fixtures::{NormalProduct, RowSplit};
+use graph_solver::{DEFAULT_AMG_COARSE_SIZE, Graph, LinearSolver, Preconditioner};
+
+const SIDES: [usize; 2] = [300, 1_000];
+const SPOKE_EVERY: usize = 7;
+const ITERATIONS: usize = 1_000_000;
+const TOLERANCE: f64 = 1e-8;
+/// Products per timed run of `hub_product`, the iterations of a short solve.
+const PRODUCTS: usize = 100;
+
+/// A `side` by `side` grid of 10 m shots whose station 0 is tied to one station in
+/// [`SPOKE_EVERY`], with two fixed corners. The shots are exact but for a 1 cm shift of the
+/// spokes, so that the adjustment has something to do.
+fn star_plus_grid(side: usize) -> Graph {
+    let mut graph = Graph::default();
+    for i in 0..side * side {
+        graph.add_vertex(0.0, 0.0, 0.0, false);
+        let (x, y) = ((i % side) as f64 * 10.0, (i / side) as f64 * 10.0);
+        if i % side > 0 {
+            graph.add_edge(i - 1, i, 10.0, 0.0, 0.0, 1.0);
+        }
+        if i >= side {
+            graph.add_edge(i - side, i, 0.0, 10.0, 0.0, 1.0);
+        }
+        if i > 0 && i % SPOKE_EVERY == 0 {
+            let weight = 1.0 / (x * x + y * y);
+            graph.add_edge(0, i, x + 0.01, y, 0.0, weight);
+        }
+    }
+    for v in [0, side * side - 1] {
+        graph.fixed[v] = true;
+        (graph.x[v], graph.y[v]) = ((v % side) as f64 * 10.0, (v / side) as f64 * 10.0);
+    }
+    graph
+}
+
+fn hub(c: &mut Criterion) {
+    let preconditioners = [
+        ("jacobi", Preconditioner::Jacobi, SIDES[0]),
+        (
+            "amg",
+            Preconditioner::Amg {
+                coarse_size: DEFAULT_AMG_COARSE_SIZE,
+            },
+            usize::MAX,
+        ),
+    ];
+    let mut group = c.benchmark_group("hub");
+    group.sample_size(10);
+    for side in SIDES {
+        let graph = star_plus_grid(side);
+        for (name, preconditioner, largest) in preconditioners {
+            if side > largest {
+                continue;
+            }
+            let id = BenchmarkId::new(name, side * side);
+            group.bench_with_input(id, &graph, |b, graph| {
+                b.iter(|| {
+                    graph
+                        .solve_with(
+                            ITERATIONS,
+                            TOLERANCE,
+                            LinearSolver::ConjugateGradient,
+                            preconditioner,
+                        )
+                        .expect("star-plus-grid networks solve")
+                })
+            });
+        }
+    }
+    group.finish();
+}
+
+fn hub_product(c: &mut Criterion) {
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let mut group = c.benchmark_group("hub_product");
+    group.sample_size(10);
+    for side in SIDES {
+        let mut product = NormalProduct::new(&star_plus_grid(side)).expect("normal matrix");
+        for (name, split) in [("rows", RowSplit::Rows), ("nonzeros", RowSplit::Nonzeros)] {
+            let id = BenchmarkId::new(name, side * side);
+            group.bench_function(id, |b| b.iter(|| product.run(split, threads, PRODUCTS)));
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, hub_product, hub);
+criterion_main!(benches);
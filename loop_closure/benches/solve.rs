@@ -0,0 +1,106 @@
+//! Solve time benchmarks over the synthetic networks of [`graph_solver::fixtures`], at 1k,
+//! 100k and 1M stations: the assembly of the normal equations alone, Conjugate Gradient with
+//! each preconditioner, and the Chebyshev semi-iteration under AMG.
+//!
+//! ```text
+//! cargo bench --features fixtures --bench solve
+//! ```
+//!
+//! Unpreconditioned and Jacobi-scaled CG need too many iterations on a million stations to be
+//! timed in a reasonable run; they stop at 100k.
+
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use graph_solver::fixtures::synthetic_cave;
+use graph_solver::{
+    DEFAULT_AMG_COARSE_SIZE, DEFAULT_SCHWARZ_BLOCK_SIZE, DEFAULT_SCHWARZ_OVERLAP, LinearSolver,
+    Preconditioner,
+};
+
+const SEED: u64 = 1;
+const SIZES: [usize; 3] = [1_000, 100_000, 1_000_000];
+const ITERATIONS: usize = 1_000_000;
+const TOLERANCE: f64 = 1e-8;
+
+fn assembly(c: &mut Criterion) {
+    let mut group = c.benchmark_group("assembly");
+    group.sample_size(10);
+    for n in SIZES {
+        let graph = synthetic_cave(SEED, n);
+        group.bench_with_input(BenchmarkId::from_parameter(n), &graph, |b, graph| {
+            b.iter(|| graph.matrix_stats().expect("synthetic networks assemble"))
+        });
+    }
+    group.finish();
+}
+
+fn conjugate_gradient(c: &mut Criterion) {
+    let preconditioners = [
+        ("none", Preconditioner::None, 100_000),
+        ("jacobi", Preconditioner::Jacobi, 100_000),
+        (
+            "amg",
+            Preconditioner::Amg {
+                coarse_size: DEFAULT_AMG_COARSE_SIZE,
+            },
+            usize::MAX,
+        ),
+        (
+            "schwarz",
+            Preconditioner::AdditiveSchwarz {
+                block_size: DEFAULT_SCHWARZ_BLOCK_SIZE,
+                overlap: DEFAULT_SCHWARZ_OVERLAP,
+            },
+            usize::MAX,
+        ),
+    ];
+    let mut group = c.benchmark_group("cg");
+    group.sample_size(10);
+    for n in SIZES {
+        let graph = synthetic_cave(SEED, n);
+        for (name, preconditioner, largest) in preconditioners {
+            if n > largest {
+                continue;
+            }
+            group.bench_with_input(BenchmarkId::new(name, n), &graph, |b, graph| {
+                b.iter(|| {
+                    graph
+                        .solve_with(
+                            ITERATIONS,
+                            TOLERANCE,
+                            LinearSolver::ConjugateGradient,
+                            preconditioner,
+                        )
+                        .expect("synthetic networks solve")
+                })
+            });
+        }
+    }
+    group.finish();
+}
+
+fn chebyshev(c: &mut Criterion) {
+    let preconditioner = Preconditioner::Amg {
+        coarse_size: DEFAULT_AMG_COARSE_SIZE,
+    };
+    let mut group = c.benchmark_group("chebyshev");
+    group.sample_size(10);
+    for n in SIZES {
+        let graph = synthetic_cave(SEED, n);
+        group.bench_with_input(BenchmarkId::new("amg", n), &graph, |b, graph| {
+            b.iter(|| {
+                graph
+                    .solve_with(
+                        ITERATIONS,
+                        TOLERANCE,
+                        LinearSolver::Chebyshev,
+                        preconditioner,
+                    )
+                    .expect("synthetic networks solve")
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, assembly, conjugate_gradient, chebyshev);
+criterion_main!(benches);
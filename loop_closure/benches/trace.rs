@@ -0,0 +1,52 @@
+//! Cost of the profiling spans of [`graph_solver::trace`]: the same 1k station solve with
+//! no subscriber, with the forwarding subscriber installed but its callback cleared, and with
+//! a callback. `no_subscriber` and `cleared` should not differ beyond noise.
+//!
+//! ```text
+//! cargo bench --features fixtures,tracing --bench trace
+//! ```
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use graph_solver::fixtures::synthetic_cave;
+use graph_solver::trace::compass_set_trace_callback;
+use graph_solver::{COMPASS_OK, Graph, LinearSolver, Preconditioner};
+use std::ffi::{c_char, c_void};
+use std::hint::black_box;
+
+const SEED: u64 = 1;
+const STATIONS: usize = 1_000;
+
+extern "C" fn ignore(_name: *const c_char, micros: u64, _fields: *const c_char, _: *mut c_void) {
+    black_box(micros);
+}
+
+fn solve(graph: &Graph) {
+    graph
+        .solve_with(
+            1_000_000,
+            1e-8,
+            LinearSolver::ConjugateGradient,
+            Preconditioner::Jacobi,
+        )
+        .expect("synthetic networks solve");
+}
+
+fn spans(c: &mut Criterion) {
+    let graph = synthetic_cave(SEED, STATIONS);
+    let mut group = c.benchmark_group("trace");
+    group.sample_size(20);
+    // The subscriber stays installed once registered, so the runs go in this order.
+    group.bench_function("no_subscriber", |b| b.iter(|| solve(&graph)));
+    let registered = compass_set_trace_callback(Some(ignore), std::ptr::null_mut());
+    assert_eq!(registered, COMPASS_OK);
+    group.bench_function("callback", |b| b.iter(|| solve(&graph)));
+    assert_eq!(
+        compass_set_trace_callback(None, std::ptr::null_mut()),
+        COMPASS_OK
+    );
+    group.bench_function("cleared", |b| b.iter(|| solve(&graph)));
+    group.finish();
+}
+
+criterion_group!(benches, spans);
+criterion_main!(benches);
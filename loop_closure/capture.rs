@@ -0,0 +1,675 @@
+//! Capture of the inputs of a [`solve_graph_least_squares_ex`] call, and its replay from Rust.
+//!
+//! A crash or a hang reported from the Java tooling can rarely be reproduced without the
+//! caller's data. With [`SolveOptions::capture_path`] set, the call writes everything it reads
+//! to that file before solving: the counts, the vertex and edge arrays, the scalar options and
+//! the optional per-vertex and per-edge arrays. Once the solve returns, the result code and
+//! the coordinates left in the caller's arrays are appended. [`replay_capture`] runs the same
+//! entry point on the captured inputs and compares its outputs with the recorded ones, when
+//! the call got that far; `examples/replay_ffi.rs` does so from the command line.
+//!
+//! Layout (all integers and floats little-endian):
+//!
+//! | Field            | Type    | Notes                                           |
+//! |------------------|---------|-------------------------------------------------|
+//! | magic            | 8 bytes | `LCCAPTUR`                                      |
+//! | version          | u32     | [`CAPTURE_VERSION`]                             |
+//! | inputs length    | u64     |                                                 |
+//! | inputs checksum  | u64     | FNV-1a 64 of the inputs                         |
+//! | inputs           |         | counts, arrays, scalar options, option arrays   |
+//! | outputs length   | u64     | this section is missing if the call never returned |
+//! | outputs checksum | u64     | FNV-1a 64 of the outputs                        |
+//! | outputs          |         | result code, coordinates                        |
+//!
+//! The scalar options are stored as two count-prefixed lists, integers then floats, in field
+//! order; the fields missing from a capture of an older release keep their defaults. The
+//! cancel flag and the error message, moved vertex and edge residual buffers are not captured: the replay
+//! provides its own. Captures of more than [`CAPTURE_MAX_BYTES`] are neither written nor
+//! read. The vertical stage of [`crate::two_stage::solve_graph_least_squares_2plus1`] is not
+//! captured.
+
+use crate::{
+    COMPASS_ERR_IO, Fnv1a, GraphView, SolveError, SolveOptions, SolveStats,
+    solve_graph_least_squares_ex,
+};
+use std::ffi::{c_char, c_int};
+use std::io::Write;
+use std::path::Path;
+
+/// Format version written in capture headers.
+pub const CAPTURE_VERSION: u32 = 1;
+
+/// Largest capture written or read, in bytes.
+pub const CAPTURE_MAX_BYTES: u64 = 1 << 30;
+
+const MAGIC: &[u8; 8] = b"LCCAPTUR";
+const HEADER_LEN: usize = 8 + 4 + 8 + 8;
+
+/// The integer options, in capture order. Fields are only ever appended.
+fn integers(o: &mut SolveOptions) -> [&mut c_int; 18] {
+    [
+        &mut o.iterations,
+        &mut o.solver,
+        &mut o.preconditioner,
+        &mut o.num_classes,
+        &mut o.verify_fixed,
+        &mut o.error_capacity,
+        &mut o.true_residual_interval,
+        &mut o.keep_isolated,
+        &mut o.convergence_criterion,
+        &mut o.preset,
+        &mut o.iterations_x,
+        &mut o.iterations_y,
+        &mut o.moved_capacity,
+        &mut o.error_on_trivial,
+        &mut o.s_transform,
+        &mut o.datum_station,
+        &mut o.datum_azimuth_station,
+        &mut o.clamp_to_bounds,
+    ]
+}
+
+/// The floating point options, in capture order. Fields are only ever appended.
+fn floats(o: &mut SolveOptions) -> [&mut f64; 12] {
+    [
+        &mut o.tolerance,
+        &mut o.centroid_stiffness,
+        &mut o.breakdown_tolerance,
+        &mut o.guess_margin,
+        &mut o.time_budget,
+        &mut o.tolerance_x,
+        &mut o.tolerance_y,
+        &mut o.moved_threshold,
+        &mut o.bounds_min_x,
+        &mut o.bounds_min_y,
+        &mut o.bounds_max_x,
+        &mut o.bounds_max_y,
+    ]
+}
+
+/// The inputs of a captured call, and its outputs if it returned.
+#[derive(Default)]
+pub struct Capture {
+    /// Scalar options; the pointer fields are null.
+    pub options: SolveOptions,
+    pub x: Vec<f64>,
+    pub y: Vec<f64>,
+    /// Fixed flags, widened from [`SolveOptions::fixed_bytes`] when given as bytes.
+    pub fixed: Vec<c_int>,
+    pub from: Vec<c_int>,
+    pub to: Vec<c_int>,
+    pub dx: Vec<f64>,
+    pub dy: Vec<f64>,
+    pub weight: Vec<f64>,
+    /// The option arrays given to the call, see the fields of [`SolveOptions`] of the same
+    /// names.
+    pub passive: Option<Vec<c_int>>,
+    pub frozen: Option<Vec<c_int>>,
+    pub edge_enabled: Option<Vec<c_int>>,
+    pub centroid_weights: Option<Vec<f64>>,
+    pub edge_class: Option<Vec<c_int>>,
+    pub class_multipliers: Option<Vec<f64>>,
+    pub adjustable: Option<Vec<c_int>>,
+    pub reversed: Option<Vec<c_int>>,
+    /// What the call returned, `None` if it never did.
+    pub recorded: Option<Outputs>,
+}
+
+/// Result code of a call and the coordinates it left in the caller's arrays.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Outputs {
+    pub code: c_int,
+    pub x: Vec<f64>,
+    pub y: Vec<f64>,
+}
+
+/// A replayed call, see [`Capture::replay`].
+#[derive(Debug, Clone, Default)]
+pub struct Replay {
+    pub outputs: Outputs,
+    pub stats: SolveStats,
+    /// Error message of the call, empty if none.
+    pub message: String,
+    /// The outputs of the captured call, if it returned.
+    pub recorded: Option<Outputs>,
+}
+
+impl Replay {
+    /// Largest difference between the replayed and the recorded coordinates, `None` without
+    /// recorded outputs. Infinite where only one of them is NaN.
+    pub fn divergence(&self) -> Option<f64> {
+        let recorded = self.recorded.as_ref()?;
+        let (actual, expected) = (&self.outputs, recorded);
+        let pairs = actual.x.iter().zip(&expected.x);
+        let pairs = pairs.chain(actual.y.iter().zip(&expected.y));
+        Some(
+            pairs.fold(0.0, |max: f64, (a, e)| match (a.is_nan(), e.is_nan()) {
+                (true, true) => max,
+                (false, false) => max.max((a - e).abs()),
+                _ => f64::INFINITY,
+            }),
+        )
+    }
+}
+
+impl Capture {
+    /// Reads the capture at `path`. Unreadable, corrupted, oversized or newer-version files
+    /// fail with [`COMPASS_ERR_IO`].
+    pub fn load(path: impl AsRef<Path>) -> Result<Capture, SolveError> {
+        let path = path.as_ref();
+        let io = |err: std::io::Error| SolveError {
+            code: COMPASS_ERR_IO,
+            message: format!("{}: {err}", path.display()),
+        };
+        let size = std::fs::metadata(path).map_err(io)?.len();
+        if size > CAPTURE_MAX_BYTES {
+            return Err(SolveError {
+                code: COMPASS_ERR_IO,
+                message: format!(
+                    "{}: {size} bytes exceed the capture limit of {CAPTURE_MAX_BYTES}",
+                    path.display()
+                ),
+            });
+        }
+        let data = std::fs::read(path).map_err(io)?;
+        Capture::from_bytes(&data).map_err(|err| SolveError {
+            message: format!("{}: {}", path.display(), err.message),
+            ..err
+        })
+    }
+
+    /// Decodes a capture, see [`Capture::load`].
+    pub fn from_bytes(data: &[u8]) -> Result<Capture, SolveError> {
+        let mut input = Reader { data, pos: 0 };
+        if input.take(MAGIC.len())? != MAGIC {
+            return Err(corrupt("not a capture file"));
+        }
+        let version = input.u32()?;
+        if version > CAPTURE_VERSION {
+            return Err(SolveError {
+                code: COMPASS_ERR_IO,
+                message: format!(
+                    "capture version {version} is newer than supported ({CAPTURE_VERSION})"
+                ),
+            });
+        }
+        let mut inputs = input.section()?;
+        let n = inputs.length()?;
+        let m = inputs.length()?;
+        let mut capture = Capture {
+            x: inputs.f64s(n)?,
+            y: inputs.f64s(n)?,
+            fixed: inputs.i32s(n)?,
+            from: inputs.i32s(m)?,
+            to: inputs.i32s(m)?,
+            dx: inputs.f64s(m)?,
+            dy: inputs.f64s(m)?,
+            weight: inputs.f64s(m)?,
+            ..Capture::default()
+        };
+        let options = &mut capture.options;
+        let count = inputs.u32()? as usize;
+        let values = inputs.i32s(count)?;
+        for (field, value) in integers(options).into_iter().zip(values) {
+            *field = value;
+        }
+        let count = inputs.u32()? as usize;
+        let values = inputs.f64s(count)?;
+        for (field, value) in floats(options).into_iter().zip(values) {
+            *field = value;
+        }
+        let classes = options.num_classes.max(0) as usize;
+        capture.passive = inputs.optional(|r| r.i32s(n))?;
+        capture.frozen = inputs.optional(|r| r.i32s(n))?;
+        capture.edge_enabled = inputs.optional(|r| r.i32s(m))?;
+        capture.centroid_weights = inputs.optional(|r| r.f64s(n))?;
+        capture.edge_class = inputs.optional(|r| r.i32s(m))?;
+        capture.class_multipliers = inputs.optional(|r| r.f64s(classes))?;
+        capture.adjustable = inputs.optional(|r| r.i32s(n))?;
+        capture.reversed = inputs.optional(|r| r.i32s(m))?;
+        inputs.end()?;
+
+        if input.pos < data.len() {
+            let mut outputs = input.section()?;
+            capture.recorded = Some(Outputs {
+                code: outputs.i32()?,
+                x: outputs.f64s(n)?,
+                y: outputs.f64s(n)?,
+            });
+            outputs.end()?;
+        }
+        input.end()?;
+        Ok(capture)
+    }
+
+    /// Calls [`solve_graph_least_squares_ex`] on the captured inputs.
+    pub fn replay(&self) -> Replay {
+        let (mut x, mut y) = (self.x.clone(), self.y.clone());
+        let mut message = [0 as c_char; 256];
+        let mut moved = vec![0; self.options.moved_capacity.max(0) as usize];
+        fn pointer<T>(values: &Option<Vec<T>>) -> *const T {
+            values.as_ref().map_or(std::ptr::null(), |v| v.as_ptr())
+        }
+        let options = SolveOptions {
+            struct_size: size_of::<SolveOptions>(),
+            passive: pointer(&self.passive),
+            frozen: pointer(&self.frozen),
+            edge_enabled: pointer(&self.edge_enabled),
+            centroid_weights: pointer(&self.centroid_weights),
+            edge_class: pointer(&self.edge_class),
+            class_multipliers: pointer(&self.class_multipliers),
+            adjustable: pointer(&self.adjustable),
+            reversed: pointer(&self.reversed),
+            error_message: message.as_mut_ptr(),
+            error_capacity: message.len() as c_int,
+            moved_vertices: match moved.is_empty() {
+                true => std::ptr::null_mut(),
+                false => moved.as_mut_ptr(),
+            },
+            ..self.options
+        };
+        let mut stats = SolveStats {
+            struct_size: size_of::<SolveStats>(),
+            ..SolveStats::default()
+        };
+        let code = solve_graph_least_squares_ex(
+            self.x.len() as c_int,
+            x.as_mut_ptr(),
+            y.as_mut_ptr(),
+            self.fixed.as_ptr(),
+            self.from.len() as c_int,
+            self.from.as_ptr(),
+            self.to.as_ptr(),
+            self.dx.as_ptr(),
+            self.dy.as_ptr(),
+            self.weight.as_ptr(),
+            &options,
+            &mut stats,
+        );
+        // Safety: The solve writes a NUL-terminated message within the buffer, or nothing.
+        let message = match message.contains(&0) {
+            true => unsafe { std::ffi::CStr::from_ptr(message.as_ptr()) }
+                .to_string_lossy()
+                .into_owned(),
+            false => String::new(),
+        };
+        Replay {
+            outputs: Outputs { code, x, y },
+            stats,
+            message,
+            recorded: self.recorded.clone(),
+        }
+    }
+}
+
+/// Loads the capture at `path` and replays it, see [`Capture::replay`].
+pub fn replay_capture(path: impl AsRef<Path>) -> Result<Replay, SolveError> {
+    Ok(Capture::load(path)?.replay())
+}
+
+/// Writes the inputs of a call to `path`, replacing the file. `fixed` are the effective
+/// fixed flags and `options` the options as read, whose option arrays the caller guarantees.
+pub(crate) fn record_inputs(
+    path: &Path,
+    options: &SolveOptions,
+    (x, y): (&[f64], &[f64]),
+    graph: &GraphView,
+) -> Result<(), String> {
+    let (n, m) = (x.len(), graph.from.len());
+    let mut out = Writer::default();
+    out.put_u64(n as u64);
+    out.put_u64(m as u64);
+    out.put_f64s(x);
+    out.put_f64s(y);
+    out.put_i32s(graph.fixed);
+    out.put_i32s(graph.from);
+    out.put_i32s(graph.to);
+    for values in [graph.dx, graph.dy, graph.weight] {
+        out.put_f64s(values);
+    }
+    let mut scalars = SolveOptions { ..*options };
+    let values = integers(&mut scalars);
+    out.put_u32(values.len() as u32);
+    for value in values {
+        out.put_i32s(&[*value]);
+    }
+    let values = floats(&mut scalars);
+    out.put_u32(values.len() as u32);
+    for value in values {
+        out.put_f64s(&[*value]);
+    }
+    // Safety: The caller guarantees the lengths of the non-null option arrays.
+    let slice = |p: *const c_int, len: usize| (!p.is_null()).then(|| unsafe { raw(p, len) });
+    let classes = options.num_classes.max(0) as usize;
+    out.put_optional_i32s(slice(options.passive, n));
+    out.put_optional_i32s(slice(options.frozen, n));
+    out.put_optional_i32s(graph.enabled);
+    let weights = options.centroid_weights;
+    out.put_optional_f64s((!weights.is_null()).then(|| unsafe { raw(weights, n) }));
+    out.put_optional_i32s(slice(options.edge_class, m));
+    let multipliers = options.class_multipliers;
+    out.put_optional_f64s((!multipliers.is_null()).then(|| unsafe { raw(multipliers, classes) }));
+    out.put_optional_i32s(slice(options.adjustable, n));
+    out.put_optional_i32s(slice(options.reversed, m));
+
+    // The outputs appended by `record_outputs` count towards the limit.
+    let size = HEADER_LEN + out.bytes.len() + 16 + 4 + 16 * n;
+    if size as u64 > CAPTURE_MAX_BYTES {
+        return Err(format!(
+            "capture of {size} bytes exceeds the limit of {CAPTURE_MAX_BYTES}"
+        ));
+    }
+    let capture = out.section(MAGIC, CAPTURE_VERSION);
+    std::fs::write(path, capture).map_err(|err| format!("{}: {err}", path.display()))
+}
+
+/// Appends the outputs of the call whose inputs [`record_inputs`] wrote to `path`.
+pub(crate) fn record_outputs(
+    path: &Path,
+    code: c_int,
+    (x, y): (&[f64], &[f64]),
+) -> std::io::Result<()> {
+    let mut out = Writer::default();
+    out.put_i32s(&[code]);
+    out.put_f64s(x);
+    out.put_f64s(y);
+    let outputs = out.section(&[], 0);
+    std::fs::OpenOptions::new()
+        .append(true)
+        .open(path)?
+        .write_all(&outputs)
+}
+
+/// Slice over `len` values at `p`, empty for 0.
+///
+/// # Safety
+///
+/// `p` is non-null and points to `len` values.
+unsafe fn raw<'a, T>(p: *const T, len: usize) -> &'a [T] {
+    match len {
+        0 => &[],
+        len => unsafe { std::slice::from_raw_parts(p, len) },
+    }
+}
+
+fn corrupt(message: &str) -> SolveError {
+    SolveError {
+        code: COMPASS_ERR_IO,
+        message: format!("corrupted capture: {message}"),
+    }
+}
+
+#[derive(Default)]
+struct Writer {
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    fn put_u32(&mut self, v: u32) {
+        self.bytes.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn put_u64(&mut self, v: u64) {
+        self.bytes.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn put_i32s(&mut self, values: &[i32]) {
+        for &v in values {
+            self.bytes.extend_from_slice(&v.to_le_bytes());
+        }
+    }
+
+    fn put_f64s(&mut self, values: &[f64]) {
+        for &v in values {
+            self.bytes.extend_from_slice(&v.to_le_bytes());
+        }
+    }
+
+    fn put_optional_i32s(&mut self, values: Option<&[i32]>) {
+        self.bytes.push(values.is_some() as u8);
+        self.put_i32s(values.unwrap_or_default());
+    }
+
+    fn put_optional_f64s(&mut self, values: Option<&[f64]>) {
+        self.bytes.push(values.is_some() as u8);
+        self.put_f64s(values.unwrap_or_default());
+    }
+
+    /// The written bytes as a section: `magic` and `version` when not empty, then the
+    /// length, checksum and bytes.
+    fn section(self, magic: &[u8], version: u32) -> Vec<u8> {
+        let payload = self.bytes;
+        let mut checksum = Fnv1a::new();
+        checksum.write(&payload);
+        let mut section = Vec::with_capacity(HEADER_LEN + payload.len());
+        if !magic.is_empty() {
+            section.extend_from_slice(magic);
+            section.extend_from_slice(&version.to_le_bytes());
+        }
+        section.extend_from_slice(&(payload.len() as u64).to_le_bytes());
+        section.extend_from_slice(&checksum.finish().to_le_bytes());
+        section.extend_from_slice(&payload);
+        section
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], SolveError> {
+        let end = self
+            .pos
+            .checked_add(n)
+            .filter(|&end| end <= self.data.len())
+            .ok_or_else(|| corrupt("unexpected end of data"))?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], SolveError> {
+        Ok(self.take(N)?.try_into().expect("took N bytes"))
+    }
+
+    fn i32(&mut self) -> Result<i32, SolveError> {
+        Ok(i32::from_le_bytes(self.array()?))
+    }
+
+    fn u32(&mut self) -> Result<u32, SolveError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    fn u64(&mut self) -> Result<u64, SolveError> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    /// A count, checked against the remaining data so that a corrupted value cannot trigger
+    /// a huge allocation.
+    fn length(&mut self) -> Result<usize, SolveError> {
+        let len = self.u64()?;
+        if len > (self.data.len() - self.pos) as u64 {
+            return Err(corrupt("length out of range"));
+        }
+        Ok(len as usize)
+    }
+
+    /// The bytes of `count` values of `size` bytes.
+    fn values(&mut self, count: usize, size: usize) -> Result<&'a [u8], SolveError> {
+        let len = count.checked_mul(size);
+        self.take(len.ok_or_else(|| corrupt("length out of range"))?)
+    }
+
+    fn i32s(&mut self, count: usize) -> Result<Vec<i32>, SolveError> {
+        let bytes = self.values(count, 4)?.chunks_exact(4);
+        Ok(bytes
+            .map(|chunk| i32::from_le_bytes(chunk.try_into().expect("chunks of 4 bytes")))
+            .collect())
+    }
+
+    fn f64s(&mut self, count: usize) -> Result<Vec<f64>, SolveError> {
+        let bytes = self.values(count, 8)?.chunks_exact(8);
+        Ok(bytes
+            .map(|chunk| f64::from_le_bytes(chunk.try_into().expect("chunks of 8 bytes")))
+            .collect())
+    }
+
+    fn optional<T>(
+        &mut self,
+        read: impl FnOnce(&mut Self) -> Result<T, SolveError>,
+    ) -> Result<Option<T>, SolveError> {
+        match self.take(1)?[0] {
+            0 => Ok(None),
+            _ => read(self).map(Some),
+        }
+    }
+
+    /// Reader over the next section, whose checksum is verified.
+    fn section(&mut self) -> Result<Reader<'a>, SolveError> {
+        let len = self.length()?;
+        let stored_checksum = self.u64()?;
+        let data = self.take(len)?;
+        let mut checksum = Fnv1a::new();
+        checksum.write(data);
+        if checksum.finish() != stored_checksum {
+            return Err(corrupt("checksum mismatch"));
+        }
+        Ok(Reader { data, pos: 0 })
+    }
+
+    fn end(&self) -> Result<(), SolveError> {
+        match self.pos == self.data.len() {
+            true => Ok(()),
+            false => Err(corrupt("trailing data")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{COMPASS_OK, Graph};
+
+    fn graph() -> Graph {
+        let mut graph = Graph::default();
+        graph.add_vertex(0.0, 0.0, 0.0, true);
+        graph.add_vertex(1.0, 0.0, 0.0, false);
+        graph.add_vertex(1.0, 1.0, 0.0, false);
+        graph.add_vertex(5.0, 5.0, 0.0, false);
+        graph.add_edge(0, 1, 1.0, 0.1, 0.0, 1.0);
+        graph.add_edge(1, 2, 0.1, 1.0, 0.0, 1.0);
+        graph.add_edge(2, 0, -1.0, -1.0, 0.0, 1.0);
+        graph.add_edge(2, 3, 0.0, 1.0, 0.0, 1.0);
+        graph
+    }
+
+    /// Solves `graph` through the FFI, capturing the call to `path`.
+    fn solve_captured(graph: &Graph, path: &Path, options: SolveOptions) -> Outputs {
+        let fixed: Vec<c_int> = graph.fixed.iter().map(|&f| f as c_int).collect();
+        let from: Vec<c_int> = graph.from.iter().map(|&v| v as c_int).collect();
+        let to: Vec<c_int> = graph.to.iter().map(|&v| v as c_int).collect();
+        let (mut x, mut y) = (graph.x.clone(), graph.y.clone());
+        let path = std::ffi::CString::new(path.to_str().unwrap()).unwrap();
+        let options = SolveOptions {
+            capture_path: path.as_ptr(),
+            ..options
+        };
+        let code = solve_graph_least_squares_ex(
+            graph.num_vertices() as c_int,
+            x.as_mut_ptr(),
+            y.as_mut_ptr(),
+            fixed.as_ptr(),
+            graph.num_edges() as c_int,
+            from.as_ptr(),
+            to.as_ptr(),
+            graph.dx.as_ptr(),
+            graph.dy.as_ptr(),
+            graph.weight.as_ptr(),
+            &options,
+            std::ptr::null_mut(),
+        );
+        Outputs { code, x, y }
+    }
+
+    #[test]
+    fn replay_reproduces_the_captured_call() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("solve.capture");
+        let graph = graph();
+        let passive = [0, 0, 0, 1];
+        let enabled = [1, 1, 1, 1];
+        let reversed = [0, 1, 0, 0];
+        let options = SolveOptions {
+            tolerance: 1e-12,
+            passive: passive.as_ptr(),
+            edge_enabled: enabled.as_ptr(),
+            reversed: reversed.as_ptr(),
+            preconditioner: 1,
+            ..SolveOptions::default()
+        };
+        let outputs = solve_captured(&graph, &path, options);
+        assert_eq!(outputs.code, COMPASS_OK);
+
+        let capture = Capture::load(&path).unwrap();
+        assert_eq!(capture.x, graph.x);
+        assert_eq!(capture.fixed, [1, 0, 0, 0]);
+        assert_eq!(capture.passive.as_deref(), Some(&passive[..]));
+        assert_eq!(capture.edge_enabled.as_deref(), Some(&enabled[..]));
+        assert_eq!(capture.frozen, None);
+        assert_eq!(capture.reversed.as_deref(), Some(&reversed[..]));
+        assert_eq!(capture.options.tolerance, 1e-12);
+        assert_eq!(capture.options.preconditioner, 1);
+        assert!(capture.options.capture_path.is_null());
+        assert_eq!(capture.recorded.as_ref(), Some(&outputs));
+
+        let replay = replay_capture(&path).unwrap();
+        assert_eq!(replay.outputs, outputs);
+        assert_eq!(replay.divergence(), Some(0.0));
+        assert!(replay.stats.iterations > 0);
+        assert_eq!(replay.stats.passive_vertices, 1);
+    }
+
+    #[test]
+    fn captures_without_outputs_still_replay() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("solve.capture");
+        solve_captured(&graph(), &path, SolveOptions::default());
+        let data = std::fs::read(&path).unwrap();
+        let inputs_len = HEADER_LEN + u64::from_le_bytes(data[12..20].try_into().unwrap()) as usize;
+
+        // A call that never returned leaves the inputs only.
+        let capture = Capture::from_bytes(&data[..inputs_len]).unwrap();
+        assert_eq!(capture.recorded, None);
+        let replay = capture.replay();
+        assert_eq!(replay.outputs.code, COMPASS_OK);
+        assert_eq!(replay.divergence(), None);
+
+        let error = |data: &[u8]| Capture::from_bytes(data).err().unwrap().message;
+        assert_eq!(
+            error(&data[..inputs_len - 1]),
+            "corrupted capture: unexpected end of data"
+        );
+        let mut corrupted = data.clone();
+        corrupted[HEADER_LEN] ^= 1;
+        assert_eq!(error(&corrupted), "corrupted capture: checksum mismatch");
+        let mut newer = data.clone();
+        newer[8..12].copy_from_slice(&(CAPTURE_VERSION + 1).to_le_bytes());
+        assert_eq!(
+            error(&newer),
+            format!("capture version 2 is newer than supported ({CAPTURE_VERSION})")
+        );
+        assert_eq!(error(b"LCSNAPSH"), "corrupted capture: not a capture file");
+    }
+
+    #[test]
+    fn unwritable_captures_fail_the_call_before_the_solve() {
+        let dir = tempfile::tempdir().unwrap();
+        let graph = graph();
+        let path = dir.path().join("missing/solve.capture");
+        let outputs = solve_captured(&graph, &path, SolveOptions::default());
+        assert_eq!(outputs.code, COMPASS_ERR_IO);
+        assert_eq!((outputs.x, outputs.y), (graph.x, graph.y));
+    }
+}
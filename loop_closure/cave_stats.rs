@@ -0,0 +1,489 @@
+//! Post-adjustment cave statistics: surveyed length, horizontal extent and vertical range,
+//! for the whole network and per connected component.
+//!
+//! Lengths are the 3D lengths of the observed `(dx, dy, dz)` vectors. Extents use the
+//! adjusted X/Y coordinates and the Z coordinates of the graph, which the horizontal
+//! adjustment does not change. Passive (splay) vertices and the shots reaching them, as well
+//! as disabled edges, are left out.
+
+use crate::{COMPASS_ERR_INVALID_ARGUMENT, COMPASS_ERR_PANIC, Graph, GraphContext, Solution};
+use std::ffi::{c_double, c_int};
+use std::slice;
+
+/// Size of a cave or of one of its connected components.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct CaveExtent {
+    /// Number of stations.
+    pub stations: c_int,
+    /// Number of shots.
+    pub edges: c_int,
+    /// Sum of the shot lengths.
+    pub surveyed_length: c_double,
+    /// Bounding box of the stations.
+    pub min_x: c_double,
+    pub max_x: c_double,
+    pub min_y: c_double,
+    pub max_y: c_double,
+    pub min_z: c_double,
+    pub max_z: c_double,
+    /// Largest horizontal distance between two stations.
+    pub max_horizontal_distance: c_double,
+}
+
+impl CaveExtent {
+    /// Vertical range `max_z - min_z`, i.e. the depth of the cave when the graph carries
+    /// elevations.
+    pub fn vertical_range(&self) -> f64 {
+        self.max_z - self.min_z
+    }
+}
+
+/// Result of [`Solution::statistics`].
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct CaveStats {
+    /// The whole network.
+    pub total: CaveExtent,
+    /// One entry per connected component, ordered by their lowest station index.
+    pub components: Vec<CaveExtent>,
+}
+
+impl Solution {
+    /// Surveyed length, extent and vertical range of the adjusted network and of each of its
+    /// connected components.
+    pub fn statistics(&self, graph: &Graph) -> CaveStats {
+        cave_stats(&self.x, &self.y, graph, &|_| false, &|_| true)
+    }
+}
+
+impl GraphContext {
+    /// [`Solution::statistics`] at the current [`GraphContext::coordinates`], leaving out the
+    /// disabled edges.
+    pub fn statistics(&self) -> CaveStats {
+        let (x, y) = self.coordinates();
+        cave_stats(x, y, &self.graph, &|_| false, &|e| self.is_edge_enabled(e))
+    }
+}
+
+/// Implementation of the statistics at coordinates `x` / `y` (Z from `graph`), skipping the
+/// vertices for which `is_passive` is true, their edges, and the edges for which `is_enabled`
+/// is false.
+pub(crate) fn cave_stats(
+    x: &[f64],
+    y: &[f64],
+    graph: &Graph,
+    is_passive: &dyn Fn(usize) -> bool,
+    is_enabled: &dyn Fn(usize) -> bool,
+) -> CaveStats {
+    let n = graph.num_vertices();
+    let edges: Vec<usize> = (0..graph.num_edges())
+        .filter(|&e| is_enabled(e) && !is_passive(graph.from[e]) && !is_passive(graph.to[e]))
+        .collect();
+
+    let mut parent: Vec<usize> = (0..n).collect();
+    fn root(parent: &mut [usize], mut v: usize) -> usize {
+        while parent[v] != v {
+            parent[v] = parent[parent[v]];
+            v = parent[v];
+        }
+        v
+    }
+    for &e in &edges {
+        let (a, b) = (
+            root(&mut parent, graph.from[e]),
+            root(&mut parent, graph.to[e]),
+        );
+        if a != b {
+            parent[a.max(b)] = a.min(b);
+        }
+    }
+
+    // Component index of each root, in order of lowest station index.
+    let mut component = vec![usize::MAX; n];
+    let mut stations: Vec<Vec<usize>> = Vec::new();
+    for v in (0..n).filter(|&v| !is_passive(v)) {
+        let r = root(&mut parent, v);
+        if component[r] == usize::MAX {
+            component[r] = stations.len();
+            stations.push(Vec::new());
+        }
+        stations[component[r]].push(v);
+    }
+
+    let extent = |vertices: &[usize]| {
+        if vertices.is_empty() {
+            return CaveExtent::default();
+        }
+        let mut extent = CaveExtent {
+            stations: vertices.len() as c_int,
+            min_x: f64::INFINITY,
+            max_x: f64::NEG_INFINITY,
+            min_y: f64::INFINITY,
+            max_y: f64::NEG_INFINITY,
+            min_z: f64::INFINITY,
+            max_z: f64::NEG_INFINITY,
+            ..Default::default()
+        };
+        for &v in vertices {
+            extent.min_x = extent.min_x.min(x[v]);
+            extent.max_x = extent.max_x.max(x[v]);
+            extent.min_y = extent.min_y.min(y[v]);
+            extent.max_y = extent.max_y.max(y[v]);
+            extent.min_z = extent.min_z.min(graph.z[v]);
+            extent.max_z = extent.max_z.max(graph.z[v]);
+        }
+        let points: Vec<(f64, f64)> = vertices.iter().map(|&v| (x[v], y[v])).collect();
+        extent.max_horizontal_distance = diameter(points);
+        extent
+    };
+
+    let all: Vec<usize> = stations.iter().flatten().copied().collect();
+    let mut total = extent(&all);
+    let mut components: Vec<CaveExtent> = stations.iter().map(|s| extent(s)).collect();
+    for &e in &edges {
+        let length = (graph.dx[e].powi(2) + graph.dy[e].powi(2) + graph.dz[e].powi(2)).sqrt();
+        let c = &mut components[component[root(&mut parent, graph.from[e])]];
+        c.edges += 1;
+        c.surveyed_length += length;
+        total.edges += 1;
+        total.surveyed_length += length;
+    }
+    CaveStats { total, components }
+}
+
+/// Largest distance between two of `points`, over their convex hull (monotone chain).
+fn diameter(mut points: Vec<(f64, f64)>) -> f64 {
+    points.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.total_cmp(&b.1)));
+    points.dedup();
+    let cross = |o: (f64, f64), a: (f64, f64), b: (f64, f64)| {
+        (a.0 - o.0) * (b.1 - o.1) - (a.1 - o.1) * (b.0 - o.0)
+    };
+    let mut hull: Vec<(f64, f64)> = Vec::with_capacity(points.len() + 1);
+    for &p in &points {
+        while hull.len() >= 2 && cross(hull[hull.len() - 2], hull[hull.len() - 1], p) <= 0.0 {
+            hull.pop();
+        }
+        hull.push(p);
+    }
+    let lower = hull.len() + 1;
+    for &p in points.iter().rev().skip(1) {
+        while hull.len() >= lower && cross(hull[hull.len() - 2], hull[hull.len() - 1], p) <= 0.0 {
+            hull.pop();
+        }
+        hull.push(p);
+    }
+
+    let mut max: f64 = 0.0;
+    for (i, a) in hull.iter().enumerate() {
+        for b in &hull[i + 1..] {
+            max = max.max((b.0 - a.0).hypot(b.1 - a.1));
+        }
+    }
+    max
+}
+
+/// Fills `out_total` with the statistics of the network behind `handle` at its current
+/// coordinates, see [`GraphContext::statistics`], and `out_components` with those of each
+/// connected component when `capacity` is large enough.
+///
+/// # Returns
+///
+/// * The number of components. They are written only if `capacity` is at least that
+///   number, so a first call with a null `out_components` and a capacity of 0 sizes the
+///   buffer.
+/// * [`COMPASS_ERR_INVALID_ARGUMENT`] for a null handle or `out_total`.
+#[unsafe(no_mangle)]
+pub extern "C" fn graph_cave_statistics(
+    handle: *const GraphContext,
+    out_total: *mut CaveExtent,
+    out_components: *mut CaveExtent,
+    capacity: c_int,
+) -> c_int {
+    let Some(ctx) = (unsafe { handle.as_ref() }) else {
+        return COMPASS_ERR_INVALID_ARGUMENT;
+    };
+    if out_total.is_null() {
+        return COMPASS_ERR_INVALID_ARGUMENT;
+    }
+    let result = std::panic::catch_unwind(|| {
+        let stats = ctx.statistics();
+        write_stats(&stats, out_total, out_components, capacity)
+    });
+
+    result.unwrap_or_else(|_| {
+        eprintln!("Panic caught in graph_cave_statistics");
+        COMPASS_ERR_PANIC
+    })
+}
+
+/// Same as [`graph_cave_statistics`] for a network given as raw arrays, as passed to
+/// [`crate::solve_graph_least_squares_ex`] after the solve.
+///
+/// `z`, `observed_dz`, `passive` and `edge_enabled` may be null: Z and dz then count as 0,
+/// no vertex is passive and every edge is enabled. Returns as [`graph_cave_statistics`],
+/// with [`COMPASS_ERR_INVALID_ARGUMENT`] for a negative count too.
+#[unsafe(no_mangle)]
+pub extern "C" fn cave_statistics(
+    num_vertices: c_int,
+    x: *const c_double,
+    y: *const c_double,
+    z: *const c_double,
+    passive: *const c_int,
+    num_edges: c_int,
+    from: *const c_int,
+    to: *const c_int,
+    observed_dx: *const c_double,
+    observed_dy: *const c_double,
+    observed_dz: *const c_double,
+    edge_enabled: *const c_int,
+    out_total: *mut CaveExtent,
+    out_components: *mut CaveExtent,
+    capacity: c_int,
+) -> c_int {
+    let (Ok(n_verts), Ok(n_edges)) = (usize::try_from(num_vertices), usize::try_from(num_edges))
+    else {
+        return COMPASS_ERR_INVALID_ARGUMENT;
+    };
+    if out_total.is_null() {
+        return COMPASS_ERR_INVALID_ARGUMENT;
+    }
+    let result = std::panic::catch_unwind(|| {
+        // Safety: The caller guarantees valid arrays of the given lengths for the non-null
+        // pointers.
+        let optional = |ptr: *const c_double, len: usize| match ptr.is_null() {
+            true => vec![0.0; len],
+            false => unsafe { slice::from_raw_parts(ptr, len) }.to_vec(),
+        };
+        let flags = |ptr: *const c_int, len: usize| match ptr.is_null() {
+            true => None,
+            false => Some(unsafe { slice::from_raw_parts(ptr, len) }),
+        };
+        let x = unsafe { slice::from_raw_parts(x, n_verts) };
+        let y = unsafe { slice::from_raw_parts(y, n_verts) };
+        let from = unsafe { slice::from_raw_parts(from, n_edges) };
+        let to = unsafe { slice::from_raw_parts(to, n_edges) };
+        let passive = flags(passive, n_verts);
+        let enabled = flags(edge_enabled, n_edges);
+        if from
+            .iter()
+            .chain(to)
+            .any(|&v| v < 0 || v as usize >= n_verts)
+        {
+            return COMPASS_ERR_INVALID_ARGUMENT;
+        }
+
+        let graph = Graph {
+            x: x.to_vec(),
+            y: y.to_vec(),
+            z: optional(z, n_verts),
+            fixed: vec![false; n_verts],
+            from: from.iter().map(|&v| v as usize).collect(),
+            to: to.iter().map(|&v| v as usize).collect(),
+            dx: unsafe { slice::from_raw_parts(observed_dx, n_edges) }.to_vec(),
+            dy: unsafe { slice::from_raw_parts(observed_dy, n_edges) }.to_vec(),
+            dz: optional(observed_dz, n_edges),
+            ..Default::default()
+        };
+        let stats = cave_stats(
+            x,
+            y,
+            &graph,
+            &|v| passive.is_some_and(|p| p[v] != 0),
+            &|e| enabled.is_none_or(|enabled| enabled[e] != 0),
+        );
+        write_stats(&stats, out_total, out_components, capacity)
+    });
+
+    result.unwrap_or_else(|_| {
+        eprintln!("Panic caught in cave_statistics");
+        COMPASS_ERR_PANIC
+    })
+}
+
+fn write_stats(
+    stats: &CaveStats,
+    out_total: *mut CaveExtent,
+    out_components: *mut CaveExtent,
+    capacity: c_int,
+) -> c_int {
+    // Safety: The caller guarantees valid output pointers; `out_total` was checked for null.
+    unsafe { *out_total = stats.total };
+    let count = stats.components.len();
+    if !out_components.is_null() && capacity.max(0) as usize >= count {
+        unsafe { slice::from_raw_parts_mut(out_components, count) }
+            .copy_from_slice(&stats.components);
+    }
+    count as c_int
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ptr;
+
+    /// Two caves: a 3-4-5 dogleg with a 12 m drop plus a splay at vertex 5, and a separate
+    /// 2 m passage.
+    fn network() -> Graph {
+        let mut graph = Graph::default();
+        for (x, y, z) in [
+            (0.0, 0.0, 0.0),
+            (3.0, 4.0, -12.0),
+            (6.0, 0.0, -5.0),
+            (10.0, 10.0, 2.0),
+            (10.0, 12.0, 2.0),
+            (0.0, 1.0, 0.0),
+        ] {
+            graph.add_vertex(x, y, z, false);
+        }
+        graph.add_edge(0, 1, 3.0, 4.0, -12.0, 1.0);
+        graph.add_edge(1, 2, 3.0, -4.0, 0.0, 1.0);
+        graph.add_edge(3, 4, 0.0, 2.0, 0.0, 1.0);
+        graph.add_edge(0, 5, 0.0, 1.0, 0.0, 1.0);
+        graph
+    }
+
+    fn extent(
+        stations: c_int,
+        edges: c_int,
+        length: f64,
+        bounds: [f64; 6],
+        diameter: f64,
+    ) -> CaveExtent {
+        let [min_x, max_x, min_y, max_y, min_z, max_z] = bounds;
+        CaveExtent {
+            stations,
+            edges,
+            surveyed_length: length,
+            min_x,
+            max_x,
+            min_y,
+            max_y,
+            min_z,
+            max_z,
+            max_horizontal_distance: diameter,
+        }
+    }
+
+    #[test]
+    fn statistics_of_a_hand_computed_network() {
+        let graph = network();
+        let solution = Solution {
+            x: graph.x.clone(),
+            y: graph.y.clone(),
+            ..Default::default()
+        };
+        let stats = solution.statistics(&graph);
+        let total = extent(
+            6,
+            4,
+            21.0,
+            [0.0, 10.0, 0.0, 12.0, -12.0, 2.0],
+            244f64.sqrt(),
+        );
+        assert_eq!(stats.total, total);
+        assert_eq!(stats.total.vertical_range(), 14.0);
+        assert_eq!(
+            stats.components,
+            [
+                extent(4, 3, 19.0, [0.0, 6.0, 0.0, 4.0, -12.0, 0.0], 37f64.sqrt()),
+                extent(2, 1, 2.0, [10.0, 10.0, 10.0, 12.0, 2.0, 2.0], 2.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn raw_arrays_leave_out_passive_vertices_and_disabled_edges() {
+        let graph = network();
+        let from: Vec<c_int> = graph.from.iter().map(|&v| v as c_int).collect();
+        let to: Vec<c_int> = graph.to.iter().map(|&v| v as c_int).collect();
+        let passive = [0, 0, 0, 0, 0, 1];
+        let enabled = [1, 0, 1, 1];
+        let call = |passive: *const c_int, enabled: *const c_int, out: &mut [CaveExtent]| {
+            let mut total = CaveExtent::default();
+            let count = cave_statistics(
+                6,
+                graph.x.as_ptr(),
+                graph.y.as_ptr(),
+                graph.z.as_ptr(),
+                passive,
+                4,
+                from.as_ptr(),
+                to.as_ptr(),
+                graph.dx.as_ptr(),
+                graph.dy.as_ptr(),
+                graph.dz.as_ptr(),
+                enabled,
+                &mut total,
+                out.as_mut_ptr(),
+                out.len() as c_int,
+            );
+            (count, total)
+        };
+
+        let mut components = [CaveExtent::default(); 3];
+        let (count, total) = call(passive.as_ptr(), ptr::null(), &mut []);
+        assert_eq!(count, 2);
+        assert_eq!(total.stations, 5);
+        assert_eq!(total.surveyed_length, 20.0);
+        assert_eq!(components[0], CaveExtent::default());
+        assert_eq!(call(passive.as_ptr(), ptr::null(), &mut components).0, 2);
+        assert_eq!(
+            components[0],
+            extent(3, 2, 18.0, [0.0, 6.0, 0.0, 4.0, -12.0, 0.0], 6.0)
+        );
+
+        // Without the middle shot of the dogleg vertex 2 stands alone.
+        let (count, total) = call(passive.as_ptr(), enabled.as_ptr(), &mut components);
+        assert_eq!(count, 3);
+        assert_eq!(total.surveyed_length, 15.0);
+        assert_eq!(
+            components[1],
+            extent(1, 0, 0.0, [6.0, 6.0, 0.0, 0.0, -5.0, -5.0], 0.0)
+        );
+
+        let mut bad = from.clone();
+        bad[0] = 6;
+        let mut total = CaveExtent::default();
+        let code = cave_statistics(
+            6,
+            graph.x.as_ptr(),
+            graph.y.as_ptr(),
+            ptr::null(),
+            ptr::null(),
+            4,
+            bad.as_ptr(),
+            to.as_ptr(),
+            graph.dx.as_ptr(),
+            graph.dy.as_ptr(),
+            ptr::null(),
+            ptr::null(),
+            &mut total,
+            ptr::null_mut(),
+            0,
+        );
+        assert_eq!(code, COMPASS_ERR_INVALID_ARGUMENT);
+    }
+
+    #[test]
+    fn context_statistics_follow_the_edge_mask() {
+        let handle = GraphContext::into_raw(network());
+        let ctx = unsafe { &mut *handle };
+        ctx.set_edge_enabled(1, false).unwrap();
+        let mut total = CaveExtent::default();
+        let mut components = [CaveExtent::default(); 3];
+        let count = graph_cave_statistics(handle, &mut total, components.as_mut_ptr(), 3);
+        assert_eq!(count, 3);
+        assert_eq!(total.edges, 3);
+        assert_eq!(total.surveyed_length, 16.0);
+        assert_eq!(components.to_vec(), ctx.statistics().components);
+        assert_eq!(
+            graph_cave_statistics(ptr::null(), &mut total, ptr::null_mut(), 0),
+            COMPASS_ERR_INVALID_ARGUMENT
+        );
+        assert_eq!(
+            graph_cave_statistics(handle, ptr::null_mut(), ptr::null_mut(), 0),
+            COMPASS_ERR_INVALID_ARGUMENT
+        );
+        crate::graph_free(handle);
+    }
+}
@@ -0,0 +1,412 @@
+//! Weighted centroid constraint, against the drift of a network between adjustments.
+//!
+//! A network tied to its anchors by weak edges only, or not at all, moves as a whole when new
+//! data comes in. The constraint holds the weighted centroid `c(x) = aᵀ x` of a vertex set at
+//! its value `c0` before the adjustment, `a` the normalised vertex weights, by adding
+//! `W (aᵀ x - c0)²` to the objective of each axis. The normal matrix gains the rank-one term
+//! `W a aᵀ`, which is never assembled: the unconstrained solution `x_N` is corrected by
+//! Sherman-Morrison, `x = x_N - q (aᵀ x_N - c0) / (aᵀ q + 1 / W)` with `N q = a`, one extra
+//! solve shared by both axes and run before the adjustment. An infinite `W` holds the centroid
+//! exactly.
+//!
+//! When the set touches a free network (see [`crate::SolveStats::free_networks`]) the term
+//! pins its translation instead, which costs nothing in the residuals: the touched networks
+//! are translated together to bring the centroid back exactly, whatever the weight.
+//!
+//! Fixed vertices, and free vertices no edge ties to the network, count in the centroid with
+//! their coordinates as constants.
+
+use crate::{
+    COMPASS_ERR_CANCELLED, COMPASS_ERR_INVALID_ARGUMENT, COMPASS_RESULT_APPROXIMATE, Convergence,
+    Graph, LinearSolver, NormalEquations, Preconditioner, Solution, SolveError, SolveOptions,
+    SolveStats, Tolerance, completed,
+};
+use nalgebra::DVector;
+use std::ffi::c_int;
+use std::slice;
+
+/// Weighted centroid of a vertex set held at its value before the adjustment, see
+/// [`SolveOptions::centroid_weights`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Centroid {
+    /// Weight of each vertex in the centroid (length `num_vertices`). 0 leaves the vertex out.
+    pub weights: Vec<f64>,
+    /// Weight `W` of the constraint against the edges. `f64::INFINITY` holds the centroid
+    /// exactly, 0 only reports the shift.
+    pub stiffness: f64,
+}
+
+impl Centroid {
+    /// Centroid of the vertices weighted by `weights`, held exactly.
+    pub fn hard(weights: Vec<f64>) -> Centroid {
+        Centroid {
+            weights,
+            stiffness: f64::INFINITY,
+        }
+    }
+
+    /// The weighted centroid of `x` / `y`, `None` when the weights sum to zero.
+    pub fn center(&self, x: &[f64], y: &[f64]) -> Option<(f64, f64)> {
+        let total: f64 = self.weights.iter().sum();
+        if total <= 0.0 {
+            return None;
+        }
+        let mean = |c: &[f64]| self.weights.iter().zip(c).map(|(w, c)| w * c).sum::<f64>() / total;
+        Some((mean(x), mean(y)))
+    }
+
+    /// Checks the weights of a graph of `num_vertices` vertices.
+    fn validate(&self, num_vertices: usize) -> Result<(), SolveError> {
+        let invalid = |message: String| {
+            Err(SolveError {
+                code: COMPASS_ERR_INVALID_ARGUMENT,
+                message,
+            })
+        };
+        if self.weights.len() != num_vertices {
+            return invalid(format!(
+                "expected {num_vertices} centroid weights, got {}",
+                self.weights.len()
+            ));
+        }
+        if let Some(v) = self
+            .weights
+            .iter()
+            .position(|w| !(w.is_finite() && *w >= 0.0))
+        {
+            return invalid(format!("invalid centroid weight for vertex {v}"));
+        }
+        if self.stiffness.is_nan() || self.stiffness < 0.0 {
+            return invalid(format!("invalid centroid stiffness {}", self.stiffness));
+        }
+        Ok(())
+    }
+}
+
+impl Graph {
+    /// Same as [`Graph::solve`], holding the weighted centroid of `centroid` at its value at
+    /// the input coordinates. The shift of the centroid the adjustment would have made without
+    /// the constraint is reported in the solution stats.
+    pub fn solve_centroid(
+        &self,
+        centroid: &Centroid,
+        iterations: usize,
+        tolerance: f64,
+    ) -> Result<Solution, SolveError> {
+        let tolerance = Tolerance::from(tolerance);
+        let system = self.normal_equations(&[])?;
+        let method = Default::default();
+        let cancelled = || false;
+        let n = self.num_vertices();
+        let xy = (&self.x[..], &self.y[..]);
+        let hold = prepare(
+            &system, centroid, n, xy, method, iterations, tolerance, &cancelled,
+        )?;
+        let mut solution = self.solve_system(
+            &system, &self.x, &self.y, iterations, tolerance, method, &cancelled,
+        )?;
+        hold.apply(
+            &system,
+            &mut solution.x,
+            &mut solution.y,
+            &mut solution.stats,
+        );
+        Ok(solution)
+    }
+}
+
+/// The centroid of [`SolveOptions::centroid_weights`], `None` when the pointer is null.
+pub(crate) fn from_options(options: &SolveOptions, num_vertices: usize) -> Option<Centroid> {
+    if options.centroid_weights.is_null() {
+        return None;
+    }
+    // Safety: The caller guarantees `num_vertices` weights.
+    let weights = unsafe { slice::from_raw_parts(options.centroid_weights, num_vertices) };
+    Some(Centroid {
+        weights: weights.to_vec(),
+        stiffness: options.centroid_stiffness,
+    })
+}
+
+/// Checks `centroid` against a graph of `num_vertices` vertices, then prepares its
+/// [`Hold`] on `system` from the coordinates before the adjustment. Fails with
+/// [`COMPASS_ERR_CANCELLED`] if `cancelled` fired.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn prepare<'a>(
+    system: &NormalEquations,
+    centroid: &'a Centroid,
+    num_vertices: usize,
+    xy: (&[f64], &[f64]),
+    method: (LinearSolver, Preconditioner),
+    iterations: usize,
+    tolerance: Tolerance,
+    cancelled: &(dyn Fn() -> bool + Sync),
+) -> Result<Hold<'a>, SolveError> {
+    centroid.validate(num_vertices)?;
+    Hold::new(
+        system, centroid, xy, method, iterations, tolerance, cancelled,
+    )
+    .ok_or_else(cancelled_error)
+}
+
+fn cancelled_error() -> SolveError {
+    SolveError {
+        code: COMPASS_ERR_CANCELLED,
+        message: "solve cancelled".to_string(),
+    }
+}
+
+/// A centroid constraint prepared before the adjustment, applied to its result.
+pub(crate) struct Hold<'a> {
+    centroid: &'a Centroid,
+    /// The centroid before the adjustment, `None` for an empty set.
+    target: Option<(f64, f64)>,
+    correction: Correction,
+    /// Convergence of the solve of `N q = a`.
+    convergence: Convergence,
+}
+
+/// Correction of the unconstrained solution per unit of centroid shift, over the unknowns.
+enum Correction {
+    /// The centroid only moves with constants, or the constraint has no weight.
+    None,
+    /// Common translation of the touched free networks: `u` is 1 on their unknowns, and
+    /// `x -= u * shift / (aᵀ u)`.
+    Translate { u: DVector<f64>, gain: f64 },
+    /// Sherman-Morrison update `x -= q * shift / (aᵀ q + 1 / W)`.
+    Update { q: DVector<f64>, gain: f64 },
+}
+
+impl<'a> Hold<'a> {
+    fn new(
+        system: &NormalEquations,
+        centroid: &'a Centroid,
+        (x, y): (&[f64], &[f64]),
+        method: (LinearSolver, Preconditioner),
+        iterations: usize,
+        tolerance: Tolerance,
+        cancelled: &(dyn Fn() -> bool + Sync),
+    ) -> Option<Hold<'a>> {
+        let mut hold = Hold {
+            centroid,
+            target: centroid.center(x, y),
+            correction: Correction::None,
+            convergence: Convergence::default(),
+        };
+        let total: f64 = centroid.weights.iter().sum();
+        if hold.target.is_none() || centroid.stiffness == 0.0 {
+            return Some(hold);
+        }
+
+        // Unknowns without couplings never move, so they count as constants.
+        let offsets = system.matrix.row_offsets();
+        let mut a = DVector::zeros(system.size());
+        for (v, index) in system.mapping.iter().enumerate() {
+            if let Some(i) = *index
+                && offsets[i] < offsets[i + 1]
+            {
+                a[i] = centroid.weights[v] / total;
+            }
+        }
+        if a.amax() == 0.0 {
+            return Some(hold);
+        }
+
+        let mut u = DVector::zeros(system.size());
+        for rows in &system.null_space.components {
+            if rows.iter().any(|&i| a[i] > 0.0) {
+                for &i in rows {
+                    u[i] = 1.0;
+                }
+            }
+        }
+        hold.correction = if u.amax() > 0.0 {
+            let gain = 1.0 / a.dot(&u);
+            Correction::Translate { u, gain }
+        } else {
+            let run = system.runner(method, cancelled);
+            let zeros = DVector::zeros(system.size());
+            let (q, convergence) = completed(run(&a, &zeros, (iterations, tolerance)))?;
+            hold.convergence = convergence;
+            let gain = 1.0 / (a.dot(&q) + 1.0 / centroid.stiffness);
+            Correction::Update { q, gain }
+        };
+        Some(hold)
+    }
+
+    /// Applies the constraint to the unconstrained solution `x` / `y` of `system` and records
+    /// the prevented shift and the extra iterations in `stats`.
+    pub(crate) fn apply(
+        &self,
+        system: &NormalEquations,
+        x: &mut [f64],
+        y: &mut [f64],
+        stats: &mut SolveStats,
+    ) {
+        let (Some((x0, y0)), Some((cx, cy))) = (self.target, self.centroid.center(x, y)) else {
+            return;
+        };
+        let shift = (cx - x0, cy - y0);
+        stats.centroid_shift_x = shift.0;
+        stats.centroid_shift_y = shift.1;
+        let iterations = stats.iterations as usize + self.convergence.iterations;
+        stats.iterations = iterations.min(c_int::MAX as usize) as c_int;
+        stats.residual_norm = stats.residual_norm.max(self.convergence.residual_norm);
+
+        let (direction, gain) = match &self.correction {
+            Correction::None => return,
+            Correction::Translate { u, gain } => (u, gain),
+            Correction::Update { q, gain } => {
+                // An inexact `q` still holds the centroid, but moves the other vertices
+                // off the least squares solution.
+                if !self.convergence.converged {
+                    stats.result_quality = COMPASS_RESULT_APPROXIMATE;
+                }
+                (q, gain)
+            }
+        };
+        for (v, index) in system.mapping.iter().enumerate() {
+            if let Some(i) = *index {
+                x[v] -= direction[i] * shift.0 * gain;
+                y[v] -= direction[i] * shift.1 * gain;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{COMPASS_OK, GraphContext, graph_free, graph_solve};
+
+    /// A 3 x 3 grid of free vertices, 10 m apart, tied to a fixed GPS station (vertex 9) by a
+    /// single weak shot that disagrees with the grid by (0.3, -0.2).
+    fn soft_grid() -> Graph {
+        let mut graph = Graph::default();
+        for i in 0..9 {
+            graph.add_vertex(10.0 * (i % 3) as f64, 10.0 * (i / 3) as f64, 0.0, false);
+        }
+        graph.add_vertex(0.0, 0.0, 0.0, true);
+        for i in 0..9 {
+            if i % 3 < 2 {
+                graph.add_edge(i, i + 1, 10.02, 0.01, 0.0, 1.0);
+            }
+            if i / 3 < 2 {
+                graph.add_edge(i, i + 3, -0.01, 9.98, 0.0, 1.0);
+            }
+        }
+        graph.add_edge(9, 0, 0.3, -0.2, 0.0, 0.01);
+        graph
+    }
+
+    fn grid_weights() -> Vec<f64> {
+        let mut weights = vec![1.0; 10];
+        weights[9] = 0.0;
+        weights
+    }
+
+    #[test]
+    fn hard_centroid_stays_put_and_reports_the_prevented_shift() {
+        let graph = soft_grid();
+        let centroid = Centroid::hard(grid_weights());
+        let before = centroid.center(&graph.x, &graph.y).unwrap();
+
+        let free = graph.solve(10_000, 1e-12).unwrap();
+        let drift = centroid.center(&free.x, &free.y).unwrap();
+        assert!((drift.0 - before.0 - 0.3).abs() < 0.05, "{drift:?}");
+        assert!((drift.1 - before.1 + 0.2).abs() < 0.05, "{drift:?}");
+
+        let held = graph.solve_centroid(&centroid, 10_000, 1e-12).unwrap();
+        let after = centroid.center(&held.x, &held.y).unwrap();
+        assert!((after.0 - before.0).abs() < 1e-9 && (after.1 - before.1).abs() < 1e-9);
+        assert!((held.stats.centroid_shift_x - (drift.0 - before.0)).abs() < 1e-9);
+        assert!((held.stats.centroid_shift_y - (drift.1 - before.1)).abs() < 1e-9);
+        // The weak shot barely bends the grid, so it keeps the shape of the free solution.
+        for v in 1..9 {
+            let shape = |s: &Solution| (s.x[v] - s.x[0], s.y[v] - s.y[0]);
+            let (a, b) = (shape(&held), shape(&free));
+            assert!((a.0 - b.0).abs() < 1e-2 && (a.1 - b.1).abs() < 1e-2);
+        }
+    }
+
+    #[test]
+    fn finite_stiffness_matches_a_shot_to_the_initial_position() {
+        // Holding a single vertex with weight W is a shot of weight W to a fixed station at
+        // its initial position.
+        let graph = soft_grid();
+        let mut weights = vec![0.0; 10];
+        weights[4] = 1.0;
+        let centroid = Centroid {
+            weights,
+            stiffness: 0.5,
+        };
+        let held = graph.solve_centroid(&centroid, 10_000, 1e-12).unwrap();
+
+        let mut tied = graph.clone();
+        tied.add_vertex(graph.x[4], graph.y[4], 0.0, true);
+        tied.add_edge(10, 4, 0.0, 0.0, 0.0, 0.5);
+        let expected = tied.solve(10_000, 1e-12).unwrap();
+        for v in 0..10 {
+            assert!((held.x[v] - expected.x[v]).abs() < 1e-8, "x[{v}]");
+            assert!((held.y[v] - expected.y[v]).abs() < 1e-8, "y[{v}]");
+        }
+        assert!(held.stats.centroid_shift_x != 0.0);
+    }
+
+    #[test]
+    fn free_networks_are_translated_back() {
+        let mut graph = soft_grid();
+        let last = graph.num_edges() - 1;
+        graph.weight[last] = 0.0;
+        let centroid = Centroid {
+            weights: grid_weights(),
+            stiffness: 1e-3,
+        };
+        let before = centroid.center(&graph.x, &graph.y).unwrap();
+        let held = graph.solve_centroid(&centroid, 10_000, 1e-12).unwrap();
+        let after = centroid.center(&held.x, &held.y).unwrap();
+        assert!((after.0 - before.0).abs() < 1e-9 && (after.1 - before.1).abs() < 1e-9);
+    }
+
+    #[test]
+    fn ffi_holds_the_centroid_and_checks_the_weights() {
+        let graph = soft_grid();
+        let handle = GraphContext::into_raw(graph.clone());
+        let mut weights = grid_weights();
+        let mut options = SolveOptions {
+            tolerance: 1e-12,
+            centroid_weights: weights.as_ptr(),
+            centroid_stiffness: f64::INFINITY,
+            ..SolveOptions::default()
+        };
+        let mut stats = SolveStats {
+            struct_size: size_of::<SolveStats>(),
+            ..SolveStats::default()
+        };
+        assert_eq!(graph_solve(handle, &options, &mut stats), COMPASS_OK);
+        let ctx = unsafe { &*handle };
+        let (x, y) = ctx.coordinates();
+        let centroid = Centroid::hard(weights.clone());
+        let (before, after) = (
+            centroid.center(&graph.x, &graph.y).unwrap(),
+            centroid.center(x, y).unwrap(),
+        );
+        assert!((after.0 - before.0).abs() < 1e-9 && (after.1 - before.1).abs() < 1e-9);
+        assert!(stats.centroid_shift_x > 0.2 && stats.centroid_shift_y < -0.1);
+
+        weights[3] = -1.0;
+        options.centroid_weights = weights.as_ptr();
+        assert_eq!(
+            graph_solve(handle, &options, &mut stats),
+            COMPASS_ERR_INVALID_ARGUMENT
+        );
+        let weights = grid_weights();
+        options.centroid_weights = weights.as_ptr();
+        options.centroid_stiffness = f64::NAN;
+        assert_eq!(
+            graph_solve(handle, &options, &mut stats),
+            COMPASS_ERR_INVALID_ARGUMENT
+        );
+        graph_free(handle);
+    }
+}
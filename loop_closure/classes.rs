@@ -0,0 +1,326 @@
+//! Edge classes with per-class weight multipliers, e.g. surface GPS traverse legs against
+//! underground shots.
+//!
+//! Each edge belongs to a small integer class, and the weight of an edge is multiplied by the
+//! multiplier of its class, so that whole kinds of data are weighted against each other with
+//! one knob per class. Tuning the knobs is cheap on a [`GraphContext`]: a change of the
+//! classes or the multipliers between two [`crate::graph_solve`] refills the values of the
+//! cached normal equations in place, keeping the vertex mapping, the sparsity pattern and the
+//! free networks, and only the preconditioner is rebuilt.
+//!
+//! [`GraphContext::class_reports`] gives the residual statistics per class, to see whether
+//! one kind of data drags the rest of the network around.
+
+use crate::{
+    COMPASS_ERR_BUFFER_TOO_SMALL, COMPASS_ERR_INVALID_ARGUMENT, GraphContext, GroupReport,
+    SolveError, SolveOptions, group_reports, write_column,
+};
+use std::ffi::{c_double, c_int};
+use std::slice;
+
+/// Class of each edge and weight multiplier of each class.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EdgeClasses {
+    /// Class of each edge, an index into `multipliers`. Edges past its end keep their weight.
+    pub class: Vec<c_int>,
+    /// Weight multiplier of each class, positive.
+    pub multipliers: Vec<f64>,
+}
+
+impl EdgeClasses {
+    /// Multiplier of the weight of edge `e`.
+    pub fn multiplier(&self, e: usize) -> f64 {
+        self.class
+            .get(e)
+            .map_or(1.0, |&class| self.multipliers[class as usize])
+    }
+
+    /// `weight` scaled by the multiplier of each edge.
+    pub fn scale(&self, weight: &[f64]) -> Vec<f64> {
+        weight
+            .iter()
+            .enumerate()
+            .map(|(e, w)| w * self.multiplier(e))
+            .collect()
+    }
+
+    /// Checks the classes of a graph of `num_edges` edges.
+    pub fn validate(&self, num_edges: usize) -> Result<(), SolveError> {
+        let invalid = |message: String| {
+            Err(SolveError {
+                code: COMPASS_ERR_INVALID_ARGUMENT,
+                message,
+            })
+        };
+        if self.class.len() != num_edges {
+            return invalid(format!(
+                "expected {num_edges} edge classes, got {}",
+                self.class.len()
+            ));
+        }
+        if let Some(class) = self
+            .multipliers
+            .iter()
+            .position(|m| !(m.is_finite() && *m > 0.0))
+        {
+            return invalid(format!("invalid weight multiplier for class {class}"));
+        }
+        let classes = self.multipliers.len();
+        if let Some(e) = self
+            .class
+            .iter()
+            .position(|&class| class < 0 || class as usize >= classes)
+        {
+            return invalid(format!("class {} of edge {e} out of range", self.class[e]));
+        }
+        Ok(())
+    }
+}
+
+/// The classes of [`SolveOptions::edge_class`], `None` when the pointer is null.
+pub(crate) fn from_options(options: &SolveOptions, num_edges: usize) -> Option<EdgeClasses> {
+    if options.edge_class.is_null() {
+        return None;
+    }
+    // Safety: The caller guarantees `num_edges` classes and `num_classes` multipliers.
+    let class = unsafe { slice::from_raw_parts(options.edge_class, num_edges) };
+    let multipliers = if options.class_multipliers.is_null() || options.num_classes <= 0 {
+        &[][..]
+    } else {
+        unsafe { slice::from_raw_parts(options.class_multipliers, options.num_classes as usize) }
+    };
+    Some(EdgeClasses {
+        class: class.to_vec(),
+        multipliers: multipliers.to_vec(),
+    })
+}
+
+impl GraphContext {
+    /// The edge classes of the next solves, `None` for the plain edge weights.
+    pub fn edge_classes(&self) -> Option<&EdgeClasses> {
+        self.classes.as_ref()
+    }
+
+    /// Sets the edge classes of the next solves, see the module documentation. Cached
+    /// normal equations are refilled in place.
+    pub fn set_edge_classes(&mut self, classes: Option<EdgeClasses>) -> Result<(), SolveError> {
+        if let Some(classes) = &classes {
+            classes.validate(self.graph.num_edges())?;
+        }
+        if self.classes != classes {
+            self.classes = classes;
+            self.refill();
+        }
+        Ok(())
+    }
+
+    /// Residual statistics per edge class at the current [`GraphContext::coordinates`], with
+    /// [`GroupReport::group`] holding the class. Edges are in class 0 without classes. The
+    /// standardized residuals use the edge weights before the class multipliers, so that
+    /// the classes are measured against the precision of their observations. Disabled edges
+    /// are left out.
+    pub fn class_reports(&self) -> Vec<GroupReport> {
+        let class: Vec<i32> = (0..self.graph.num_edges())
+            .map(|e| {
+                self.classes
+                    .as_ref()
+                    .and_then(|classes| classes.class.get(e).copied())
+                    .unwrap_or(0)
+            })
+            .collect();
+        let (x, y) = self.coordinates();
+        let graph = self.observed_graph();
+        group_reports(x, y, &graph, &class, &|e| self.is_edge_enabled(e))
+    }
+}
+
+/// Copies the residual statistics per edge class of the graph behind `handle` into parallel
+/// caller buffers of `capacity` elements, most suspicious class first, see
+/// [`GraphContext::class_reports`]. The classes are those of the last [`crate::graph_solve`].
+/// Any output pointer may be null to skip that column.
+///
+/// # Returns
+///
+/// * The number of classes written, at most the number of classes passed to the solve.
+/// * [`COMPASS_ERR_BUFFER_TOO_SMALL`] if `capacity` is below the number of classes among the
+///   enabled edges; nothing is written.
+/// * [`COMPASS_ERR_INVALID_ARGUMENT`] for a null handle.
+#[unsafe(no_mangle)]
+pub extern "C" fn graph_class_reports(
+    handle: *const GraphContext,
+    capacity: c_int,
+    out_class: *mut c_int,
+    out_edges: *mut c_int,
+    out_weighted_rms: *mut c_double,
+    out_max_standardized: *mut c_double,
+    out_suspicion: *mut c_double,
+) -> c_int {
+    let Some(ctx) = (unsafe { handle.as_ref() }) else {
+        return COMPASS_ERR_INVALID_ARGUMENT;
+    };
+    let reports = ctx.class_reports();
+    if (capacity.max(0) as usize) < reports.len() {
+        return COMPASS_ERR_BUFFER_TOO_SMALL;
+    }
+    write_column(out_class, &reports, |r| r.group);
+    write_column(out_edges, &reports, |r| r.edges as c_int);
+    write_column(out_weighted_rms, &reports, |r| r.weighted_rms);
+    write_column(out_max_standardized, &reports, |r| r.max_standardized);
+    write_column(out_suspicion, &reports, |r| r.suspicion);
+    reports.len() as c_int
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{COMPASS_OK, Graph, SolveStats, graph_free, graph_solve};
+
+    /// A square of underground shots hanging from anchor 0, closed through a second anchor by
+    /// two surface legs that disagree with the shots by 30 cm.
+    fn network() -> (Graph, EdgeClasses) {
+        let mut graph = Graph::default();
+        for (x, y, fixed) in [
+            (0.0, 0.0, true),
+            (10.0, 0.0, false),
+            (10.0, 10.0, false),
+            (0.0, 10.0, false),
+            (20.3, 10.0, true),
+        ] {
+            graph.add_vertex(x, y, 0.0, fixed);
+        }
+        graph.add_edge(0, 1, 10.0, 0.02, 0.0, 1.0);
+        graph.add_edge(1, 2, 0.01, 10.0, 0.0, 1.0);
+        graph.add_edge(2, 3, -10.0, 0.0, 0.0, 1.0);
+        graph.add_edge(3, 0, 0.0, -10.01, 0.0, 1.0);
+        graph.add_edge(2, 4, 10.0, 0.0, 0.0, 2.0);
+        graph.add_edge(4, 1, -10.0, -10.0, 0.0, 2.0);
+        let classes = EdgeClasses {
+            class: vec![0, 0, 0, 0, 1, 1],
+            multipliers: vec![1.0, 0.01],
+        };
+        (graph, classes)
+    }
+
+    fn solve(ctx: &mut GraphContext) -> (Vec<f64>, Vec<f64>) {
+        ctx.solve(10_000, 1e-14.into(), Default::default(), None, &|| false)
+            .unwrap();
+        let (x, y) = ctx.coordinates();
+        (x.to_vec(), y.to_vec())
+    }
+
+    #[test]
+    fn multipliers_scale_the_weights_in_place() {
+        let (graph, classes) = network();
+        let mut ctx = *unsafe { Box::from_raw(GraphContext::into_raw(graph.clone())) };
+        solve(&mut ctx);
+        let pattern = ctx.system.as_ref().unwrap().matrix.col_indices().to_vec();
+
+        ctx.set_edge_classes(Some(classes.clone())).unwrap();
+        // The cached system is refilled, not dropped.
+        let system = ctx.system.as_ref().unwrap();
+        assert_eq!(system.matrix.col_indices(), pattern);
+        let mut scaled = graph.clone();
+        scaled.weight = classes.scale(&graph.weight);
+        assert_eq!(scaled.weight, [1.0, 1.0, 1.0, 1.0, 0.02, 0.02]);
+        let fresh = scaled.normal_equations(&[]).unwrap();
+        assert_eq!(system.matrix.values(), fresh.matrix.values());
+        assert_eq!((&system.bx, &system.by), (&fresh.bx, &fresh.by));
+
+        let expected = scaled.solve(10_000, 1e-14).unwrap();
+        let (x, y) = solve(&mut ctx);
+        for v in 0..5 {
+            assert!((x[v] - expected.x[v]).abs() < 1e-9 && (y[v] - expected.y[v]).abs() < 1e-9);
+        }
+        // Weak surface legs leave the cave where its own shots put it.
+        let plain = graph.solve(10_000, 1e-14).unwrap();
+        assert!(x[2] < plain.x[2]);
+
+        ctx.set_edge_classes(None).unwrap();
+        let (x, _) = solve(&mut ctx);
+        assert!((x[2] - plain.x[2]).abs() < 1e-9);
+    }
+
+    #[test]
+    fn invalid_classes_are_rejected() {
+        let (graph, classes) = network();
+        for broken in [
+            EdgeClasses {
+                class: vec![0; 5],
+                ..classes.clone()
+            },
+            EdgeClasses {
+                class: vec![0, 0, 0, 0, 1, 2],
+                ..classes.clone()
+            },
+            EdgeClasses {
+                multipliers: vec![1.0, 0.0],
+                ..classes.clone()
+            },
+            EdgeClasses {
+                multipliers: vec![1.0, f64::NAN],
+                ..classes.clone()
+            },
+        ] {
+            let err = broken.validate(graph.num_edges()).unwrap_err();
+            assert_eq!(err.code, COMPASS_ERR_INVALID_ARGUMENT);
+        }
+    }
+
+    #[test]
+    fn reports_the_residuals_per_class() {
+        let (graph, classes) = network();
+        let handle = GraphContext::into_raw(graph);
+        let options = SolveOptions {
+            tolerance: 1e-12,
+            edge_class: classes.class.as_ptr(),
+            class_multipliers: classes.multipliers.as_ptr(),
+            num_classes: 2,
+            ..SolveOptions::default()
+        };
+        let mut stats = SolveStats {
+            struct_size: size_of::<SolveStats>(),
+            ..SolveStats::default()
+        };
+        assert_eq!(graph_solve(handle, &options, &mut stats), COMPASS_OK);
+        let ctx = unsafe { &*handle };
+        assert_eq!(ctx.edge_classes(), Some(&classes));
+
+        let reports = ctx.class_reports();
+        assert_eq!(reports.len(), 2);
+        // The surface legs absorb the disagreement.
+        assert_eq!((reports[0].group, reports[0].edges), (1, 2));
+        assert!(reports[0].weighted_rms > 0.1);
+        assert!(reports[1].weighted_rms < 0.01);
+
+        let (mut class, mut edges, mut rms) = ([0; 2], [0; 2], [0.0; 2]);
+        let write = |capacity, class: &mut [c_int], edges: &mut [c_int], rms: &mut [f64]| {
+            graph_class_reports(
+                handle,
+                capacity,
+                class.as_mut_ptr(),
+                edges.as_mut_ptr(),
+                rms.as_mut_ptr(),
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+            )
+        };
+        assert_eq!(
+            write(1, &mut class, &mut edges, &mut rms),
+            COMPASS_ERR_BUFFER_TOO_SMALL
+        );
+        assert_eq!(write(2, &mut class, &mut edges, &mut rms), 2);
+        assert_eq!((class, edges), ([1, 0], [2, 4]));
+        assert_eq!(rms, [reports[0].weighted_rms, reports[1].weighted_rms]);
+
+        let bad = [0, 0, 0, 0, 1, 5];
+        let options = SolveOptions {
+            edge_class: bad.as_ptr(),
+            ..options
+        };
+        assert_eq!(
+            graph_solve(handle, &options, &mut stats),
+            COMPASS_ERR_INVALID_ARGUMENT
+        );
+        graph_free(handle);
+    }
+}
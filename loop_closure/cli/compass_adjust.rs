@@ -0,0 +1,340 @@
+//! `compass-adjust`: batch adjustment from the command line.
+//!
+//! ```text
+//! compass-adjust [OPTIONS] <INPUT> [EDGES]
+//! ```
+//!
+//! The input format follows the extension of `INPUT`: a Compass `.mak` project, a serialized
+//! `.json` graph, or a vertices `.csv` file together with its edges CSV in `EDGES`. The output
+//! format follows the extension of `--output`. Formats whose feature is disabled are rejected.
+//!
+//! The exit code is the negated library error code: 1 for a panic, 2 for invalid arguments or
+//! input, 3 for I/O errors.
+
+use graph_solver::{
+    COMPASS_ERR_INVALID_ARGUMENT, COMPASS_ERR_IO, COMPASS_ERR_PANIC, DEFAULT_AMG_COARSE_SIZE,
+    DEFAULT_SCHWARZ_BLOCK_SIZE, DEFAULT_SCHWARZ_OVERLAP, Graph, LinearSolver, Preconditioner,
+    Solution, SolveError,
+};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+const USAGE: &str = "\
+Usage: compass-adjust [OPTIONS] <INPUT> [EDGES]
+
+Inputs:
+  project.mak            Compass project (feature compass_io)
+  graph.json             Serialized graph (feature io-json)
+  vertices.csv edges.csv Vertices and edges CSV files (feature io-csv)
+
+Options:
+  -o, --output <PATH>        Write the adjusted network; format from the extension:
+                             .csv, .json, .geojson, .dxf, .plt, .3d
+      --iterations <N>       Maximum number of solver iterations [default: 60000]
+      --tolerance <T>        Solver residual tolerance [default: 1e-8]
+      --solver <KIND>        Linear solver: cg, chebyshev [default: cg]
+      --preconditioner <P>   Preconditioner: none, jacobi, amg, schwarz
+                             [default: none]
+      --robust <MODE>        Robust reweighting: none [default: none]
+      --report <N>           Number of edges listed in the residual report [default: 10]
+  -h, --help                 Print this help";
+
+/// Parsed command line.
+struct Args {
+    input: PathBuf,
+    edges: Option<PathBuf>,
+    output: Option<PathBuf>,
+    iterations: usize,
+    tolerance: f64,
+    solver: LinearSolver,
+    preconditioner: Preconditioner,
+    report: usize,
+}
+
+fn main() -> ExitCode {
+    let result = std::panic::catch_unwind(|| run(std::env::args().skip(1).collect()));
+    let err = match result {
+        Ok(Ok(())) => return ExitCode::SUCCESS,
+        Ok(Err(err)) => err,
+        Err(_) => SolveError {
+            code: COMPASS_ERR_PANIC,
+            message: "panic in the solver".to_string(),
+        },
+    };
+    eprintln!("compass-adjust: {}", err.message);
+    ExitCode::from(err.code.unsigned_abs().min(255) as u8)
+}
+
+fn run(args: Vec<String>) -> Result<(), SolveError> {
+    let Some(args) = parse_args(args)? else {
+        println!("{USAGE}");
+        return Ok(());
+    };
+
+    let graph = read_graph(&args)?;
+    let solution = graph.solve_with(
+        args.iterations,
+        args.tolerance,
+        args.solver,
+        args.preconditioner,
+    )?;
+    print_report(&graph, &solution, args.report);
+    if let Some(output) = &args.output {
+        write_solution(&graph, &solution, output)?;
+    }
+    Ok(())
+}
+
+/// Returns `None` when help was requested.
+fn parse_args(args: Vec<String>) -> Result<Option<Args>, SolveError> {
+    let mut positional = Vec::new();
+    let mut output = None;
+    let mut iterations = 60_000;
+    let mut tolerance = 1e-8;
+    let mut solver = LinearSolver::ConjugateGradient;
+    let mut preconditioner = Preconditioner::None;
+    let mut report = 10;
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| invalid(format!("{arg} requires a value")))
+        };
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "-o" | "--output" => output = Some(PathBuf::from(value()?)),
+            "--iterations" => iterations = parse_number(&arg, &value()?)?,
+            "--tolerance" => tolerance = parse_number(&arg, &value()?)?,
+            "--report" => report = parse_number(&arg, &value()?)?,
+            "--solver" => {
+                solver = match choice(&arg, &value()?, &["cg", "chebyshev"])? {
+                    0 => LinearSolver::ConjugateGradient,
+                    _ => LinearSolver::Chebyshev,
+                }
+            }
+            "--preconditioner" => {
+                let choices = ["none", "jacobi", "amg", "schwarz"];
+                preconditioner = match choice(&arg, &value()?, &choices)? {
+                    0 => Preconditioner::None,
+                    1 => Preconditioner::Jacobi,
+                    2 => Preconditioner::Amg {
+                        coarse_size: DEFAULT_AMG_COARSE_SIZE,
+                    },
+                    _ => Preconditioner::AdditiveSchwarz {
+                        block_size: DEFAULT_SCHWARZ_BLOCK_SIZE,
+                        overlap: DEFAULT_SCHWARZ_OVERLAP,
+                    },
+                }
+            }
+            "--robust" => _ = choice(&arg, &value()?, &["none"])?,
+            _ if arg.starts_with('-') => return Err(invalid(format!("unknown option {arg}"))),
+            _ => positional.push(PathBuf::from(arg)),
+        }
+    }
+
+    let mut positional = positional.into_iter();
+    let Some(input) = positional.next() else {
+        return Err(invalid(format!("missing input\n\n{USAGE}")));
+    };
+    let edges = positional.next();
+    if positional.next().is_some() {
+        return Err(invalid("too many inputs".to_string()));
+    }
+    Ok(Some(Args {
+        input,
+        edges,
+        output,
+        iterations,
+        tolerance,
+        solver,
+        preconditioner,
+        report,
+    }))
+}
+
+fn read_graph(args: &Args) -> Result<Graph, SolveError> {
+    let input = &args.input;
+    let extension = extension(input);
+    if args.edges.is_some() && extension != "csv" {
+        return Err(invalid(
+            "an edges file is only accepted with a CSV input".to_string(),
+        ));
+    }
+    match extension.as_str() {
+        #[cfg(feature = "compass_io")]
+        "mak" => Graph::from_compass_project(input).map_err(import_error),
+        #[cfg(feature = "io-json")]
+        "json" => {
+            let text = std::fs::read_to_string(input).map_err(|e| io_error(input, e))?;
+            serde_json::from_str(&text).map_err(|e| invalid(format!("{}: {}", input.display(), e)))
+        }
+        #[cfg(feature = "io-csv")]
+        "csv" => {
+            let Some(edges) = &args.edges else {
+                return Err(invalid("a CSV input needs an edges CSV file".to_string()));
+            };
+            Graph::from_csv(input, edges).map_err(import_error)
+        }
+        _ => Err(invalid(format!(
+            "{}: unsupported input format",
+            input.display()
+        ))),
+    }
+}
+
+fn write_solution(graph: &Graph, solution: &Solution, path: &Path) -> Result<(), SolveError> {
+    let io = |e| io_error(path, e);
+    match extension(path).as_str() {
+        #[cfg(feature = "io-csv")]
+        "csv" => solution.write_csv(path, &Default::default()).map_err(io),
+        #[cfg(feature = "io-json")]
+        "json" => {
+            let text = serde_json::to_string_pretty(solution)
+                .map_err(|e| invalid(format!("{}: {}", path.display(), e)))?;
+            std::fs::write(path, text).map_err(io)
+        }
+        #[cfg(feature = "io-geojson")]
+        "geojson" => solution
+            .write_geojson(graph, path, &Default::default())
+            .map_err(io),
+        #[cfg(feature = "io-dxf")]
+        "dxf" => solution
+            .write_dxf(graph, path, &Default::default())
+            .map_err(io),
+        #[cfg(feature = "compass_io")]
+        "plt" => solution.write_plt(graph, path).map_err(io),
+        #[cfg(feature = "io-survex")]
+        "3d" => solution
+            .write_survex_3d(graph, path, &Default::default())
+            .map_err(io),
+        _ => {
+            // Unused when no exporter is enabled.
+            let _ = (graph, solution, io);
+            Err(invalid(format!(
+                "{}: unsupported output format",
+                path.display()
+            )))
+        }
+    }
+}
+
+/// Prints the solve statistics, the number of independent loops, the size of the cave and
+/// the edges with the largest standardized residuals.
+fn print_report(graph: &Graph, solution: &Solution, count: usize) {
+    let n = graph.num_vertices();
+    let m = graph.num_edges();
+    println!("vertices:          {n}");
+    println!("edges:             {m}");
+    println!("free vertices:     {}", solution.stats.free_vertices);
+    println!("passive vertices:  {}", solution.stats.passive_vertices);
+    println!("free networks:     {}", solution.stats.free_networks);
+    println!("iterations:        {}", solution.stats.iterations);
+    println!("independent loops: {}", m + components(graph) - n);
+    let stats = solution.statistics(graph);
+    println!("surveyed length:   {:.2}", stats.total.surveyed_length);
+    println!(
+        "horizontal extent: {:.2}",
+        stats.total.max_horizontal_distance
+    );
+    println!("vertical range:    {:.2}", stats.total.vertical_range());
+
+    if count == 0 || m == 0 {
+        return;
+    }
+    let residuals = solution.residuals(graph);
+    let standardized = solution.standardized_residuals(graph);
+    let mut order: Vec<usize> = (0..m).collect();
+    order.sort_by(|&a, &b| standardized[b].total_cmp(&standardized[a]));
+    println!();
+    println!("largest standardized residuals:");
+    let label = |v: usize| match &graph.names {
+        Some(names) => names[v].clone(),
+        None => format!("V{v}"),
+    };
+    for &e in order.iter().take(count) {
+        let (rx, ry) = residuals[e];
+        println!(
+            "  {} -> {}: residual {:.4} ({:.4}, {:.4}), standardized {:.3}",
+            label(graph.from[e]),
+            label(graph.to[e]),
+            rx.hypot(ry),
+            rx,
+            ry,
+            standardized[e]
+        );
+    }
+}
+
+/// Number of connected components of the graph.
+fn components(graph: &Graph) -> usize {
+    let mut parent: Vec<usize> = (0..graph.num_vertices()).collect();
+    fn root(parent: &mut [usize], mut v: usize) -> usize {
+        while parent[v] != v {
+            parent[v] = parent[parent[v]];
+            v = parent[v];
+        }
+        v
+    }
+    let mut count = graph.num_vertices();
+    for e in 0..graph.num_edges() {
+        let (a, b) = (
+            root(&mut parent, graph.from[e]),
+            root(&mut parent, graph.to[e]),
+        );
+        if a != b {
+            parent[a] = b;
+            count -= 1;
+        }
+    }
+    count
+}
+
+fn extension(path: &Path) -> String {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase()
+}
+
+fn parse_number<T: std::str::FromStr>(option: &str, value: &str) -> Result<T, SolveError> {
+    value
+        .parse()
+        .map_err(|_| invalid(format!("invalid value for {option}: {value}")))
+}
+
+/// Index of `value` in `choices`.
+fn choice(option: &str, value: &str, choices: &[&str]) -> Result<usize, SolveError> {
+    choices.iter().position(|&c| c == value).ok_or_else(|| {
+        invalid(format!(
+            "unsupported value for {option}: {value} (expected one of: {})",
+            choices.join(", ")
+        ))
+    })
+}
+
+fn invalid(message: String) -> SolveError {
+    SolveError {
+        code: COMPASS_ERR_INVALID_ARGUMENT,
+        message,
+    }
+}
+
+fn io_error(path: &Path, err: std::io::Error) -> SolveError {
+    SolveError {
+        code: COMPASS_ERR_IO,
+        message: format!("{}: {}", path.display(), err),
+    }
+}
+
+#[allow(dead_code)] // Only used by the optional importers.
+fn import_error(err: graph_solver::ImportError) -> SolveError {
+    let code = match err {
+        graph_solver::ImportError::Io { .. } => COMPASS_ERR_IO,
+        graph_solver::ImportError::Parse { .. } => COMPASS_ERR_INVALID_ARGUMENT,
+    };
+    SolveError {
+        code,
+        message: err.to_string(),
+    }
+}
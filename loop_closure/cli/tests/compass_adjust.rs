@@ -0,0 +1,98 @@
+//! Drives the `compass-adjust` binary against the fixture files.
+
+use assert_cmd::Command;
+use predicates::prelude::*;
+use predicates::str::contains;
+
+fn data(name: &str) -> String {
+    format!("{}/../tests/data/{}", env!("CARGO_MANIFEST_DIR"), name)
+}
+
+fn compass_adjust() -> Command {
+    Command::cargo_bin("compass-adjust").unwrap()
+}
+
+#[test]
+fn prints_the_usage() {
+    compass_adjust()
+        .arg("--help")
+        .assert()
+        .success()
+        .stdout(contains("Usage: compass-adjust"));
+}
+
+#[test]
+fn rejects_bad_arguments_with_the_invalid_argument_code() {
+    compass_adjust()
+        .assert()
+        .code(2)
+        .stderr(contains("missing input"));
+    compass_adjust()
+        .args(["--frobnicate", "graph.csv"])
+        .assert()
+        .code(2)
+        .stderr(contains("unknown option --frobnicate"));
+    compass_adjust()
+        .args(["--tolerance", "small", "graph.csv"])
+        .assert()
+        .code(2)
+        .stderr(contains("invalid value for --tolerance: small"));
+    compass_adjust()
+        .args(["--solver", "qr", "graph.csv"])
+        .assert()
+        .code(2)
+        .stderr(contains("expected one of: cg"));
+    compass_adjust()
+        .arg("graph.xyz")
+        .assert()
+        .code(2)
+        .stderr(contains("unsupported input format"));
+}
+
+#[cfg(feature = "io-csv")]
+#[test]
+fn adjusts_the_csv_fixture() {
+    let dir = tempfile::tempdir().unwrap();
+    let output = dir.path().join("adjusted.csv");
+    compass_adjust()
+        .args([&data("loop_vertices.csv"), &data("loop_edges.csv"), "-o"])
+        .arg(&output)
+        .args(["--tolerance", "1e-12", "--report", "2"])
+        .assert()
+        .success()
+        .stdout(
+            contains("vertices:          4")
+                .and(contains("free vertices:     3"))
+                .and(contains("independent loops: 1"))
+                .and(contains("Entrance, upper"))
+                .and(contains("largest standardized residuals:")),
+        );
+
+    let text = std::fs::read_to_string(&output).unwrap();
+    let mut lines = text.lines();
+    assert_eq!(lines.next(), Some("index,x,y"));
+    assert_eq!(lines.next(), Some("0,0,0"));
+    assert_eq!(lines.count(), 3);
+}
+
+#[cfg(feature = "io-csv")]
+#[test]
+fn maps_missing_files_to_the_io_code() {
+    compass_adjust()
+        .args(["missing_vertices.csv", &data("loop_edges.csv")])
+        .assert()
+        .code(3)
+        .stderr(contains("missing_vertices.csv"));
+}
+
+#[cfg(feature = "io-csv")]
+#[test]
+fn rejects_unsupported_output_formats_before_reporting_success() {
+    let dir = tempfile::tempdir().unwrap();
+    compass_adjust()
+        .args([&data("loop_vertices.csv"), &data("loop_edges.csv"), "-o"])
+        .arg(dir.path().join("adjusted.xyz"))
+        .assert()
+        .code(2)
+        .stderr(contains("unsupported output format"));
+}
@@ -0,0 +1,1636 @@
+//! Import of Compass project (`.mak`) and survey (`.dat`) files into a [`Graph`].
+//!
+//! This is a port of the parsing rules used by the sibling Python tool (`compass_lib`):
+//! all `.dat` measurements are stored in feet and degrees with a fixed column order
+//! regardless of the `FORMAT:` string, and the `.mak` file lists the survey files plus the
+//! fixed (anchor) stations with their coordinates.
+//!
+//! The resulting graph is expressed in [`CompassImportOptions::units`], meters by default:
+//! X = easting, Y = northing, Z = elevation. Lengths are converted once, from the unit they
+//! are read in to the target unit. The graph's [`CoordinateSystem`] records that unit, and a
+//! UTM grid when the project's base location and datum name one with an EPSG code, a local
+//! grid otherwise.
+
+use crate::corrections::ShotCorrection;
+use crate::crs::{CoordinateSystem, LengthUnit, Projection, convert_length, convert_weight};
+use crate::initial_guess::GuessOptions;
+use crate::locks::DEFAULT_LOCK_WEIGHT;
+use crate::weights::{GradeSigmas, SURVEY_GRADE_SIGMAS, SurveyGrade, WeightModel};
+use crate::{
+    COMPASS_ERR_INVALID_ARGUMENT, Graph, GraphContext, ImportError, Solution, SolveError,
+    check_export, invalid_input, write_message,
+};
+use std::collections::HashMap;
+use std::ffi::{CStr, c_char};
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+pub use crate::crs::FEET_TO_METERS;
+
+/// Values >= this threshold indicate missing data for distances/measurements.
+const MISSING_VALUE_THRESHOLD: f64 = 990.0;
+
+/// Values <= this threshold indicate missing data for angles.
+const MISSING_ANGLE_THRESHOLD: f64 = -900.0;
+
+/// Instrument model used to derive edge weights from the shots.
+///
+/// Each shot gets `weight = 1 / (length_sigma² + (length * angle_sigma)²)`, i.e. the inverse
+/// of the variance of its endpoint position under independent tape and angle errors.
+///
+/// Graded surveys are weighted by their grade instead: a survey is graded when its header
+/// (typically the comment) contains `BCRA <n>` or `UIS <n>` (also written `BCRA5`,
+/// `UISv1 5-2-BC`, ...), or when [`CompassImportOptions::default_grade`] is set. A
+/// [`WeightModel`] preset overrides both.
+///
+/// Shots flagged `C` (not adjusted when closing loops) get at least
+/// [`DEFAULT_LOCK_WEIGHT`], as the edges of a locked loop, and shots flagged `X` are
+/// skipped.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CompassImportOptions {
+    /// Standard deviation of a tape reading, in meters.
+    pub length_sigma: f64,
+    /// Standard deviation of a compass/clinometer reading, in degrees.
+    pub angle_sigma_deg: f64,
+    /// Weight model replacing the instrument model and the grades when set.
+    pub weight_model: Option<WeightModel>,
+    /// Grade of the surveys whose header does not state one.
+    pub default_grade: Option<SurveyGrade>,
+    /// Standard deviations of grades 1 to 6.
+    pub grade_sigmas: [GradeSigmas; 6],
+    /// Unit of the imported coordinates and observations. The standard deviations above stay
+    /// in meters; the weights are converted, see [`convert_weight`].
+    pub units: LengthUnit,
+    /// How the initial guess of the free stations is chained along the shots.
+    pub initial_guess: GuessOptions,
+}
+
+impl Default for CompassImportOptions {
+    fn default() -> Self {
+        // 0.1 ft on the tape and 2 degrees on the angles, the usual cave survey figures.
+        CompassImportOptions {
+            length_sigma: 0.1 * FEET_TO_METERS,
+            angle_sigma_deg: 2.0,
+            weight_model: None,
+            default_grade: None,
+            grade_sigmas: SURVEY_GRADE_SIGMAS,
+            units: LengthUnit::Meters,
+            initial_guess: GuessOptions::default(),
+        }
+    }
+}
+
+/// Origin of an edge in the Compass survey data, see
+/// [`Graph::from_compass_project_with_refs`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompassShotRef {
+    /// Name of the survey (`SURVEY NAME:` header) holding the shot.
+    pub survey: String,
+    /// 1-based position of the shot among the shot lines of its survey, excluded shots
+    /// included.
+    pub shot: usize,
+    /// Whether the edge runs from the shot's `TO` station to its `FROM` station.
+    pub reversed: bool,
+}
+
+impl Graph {
+    /// Builds a graph from a Compass `.mak` project and the `.dat` files it references,
+    /// using the default instrument model.
+    pub fn from_compass_project(path: impl AsRef<Path>) -> Result<Graph, ImportError> {
+        Graph::from_compass_project_with(path, &CompassImportOptions::default())
+    }
+
+    /// Builds a graph from a Compass `.mak` project and the `.dat` files it references.
+    ///
+    /// Station names are resolved to vertex indices in order of first appearance and kept in
+    /// [`Graph::names`]. Stations listed with coordinates in the project are fixed; the
+    /// initial guess of every other station is propagated along the shots from them.
+    pub fn from_compass_project_with(
+        path: impl AsRef<Path>,
+        options: &CompassImportOptions,
+    ) -> Result<Graph, ImportError> {
+        Graph::from_compass_project_with_refs(path, options).map(|(graph, _)| graph)
+    }
+
+    /// Same as [`Graph::from_compass_project_with`], also returning the origin of each edge
+    /// for [`Solution::to_compass_corrections`].
+    pub fn from_compass_project_with_refs(
+        path: impl AsRef<Path>,
+        options: &CompassImportOptions,
+    ) -> Result<(Graph, Vec<CompassShotRef>), ImportError> {
+        let path = path.as_ref();
+        let project = parse_mak(path, &read_file(path)?)?;
+        let base_dir = path.parent().unwrap_or(Path::new("."));
+
+        let mut builder = GraphBuilder::default();
+        let mut refs = Vec::new();
+        for (name, unit, position) in &project.fixed_stations {
+            let v = builder.vertex(name);
+            let [x, y, z] = position.map(|value| convert_length(value, *unit, options.units));
+            builder.graph.fixed[v] = true;
+            builder.graph.x[v] = x;
+            builder.graph.y[v] = y;
+            builder.graph.z[v] = z;
+        }
+        for file in &project.files {
+            let dat_path = resolve_path(base_dir, file);
+            for shot in parse_dat(&dat_path, &read_file(&dat_path)?)? {
+                builder.add_shot(&shot, options);
+                refs.push(CompassShotRef {
+                    survey: shot.survey,
+                    shot: shot.number,
+                    reversed: false,
+                });
+            }
+        }
+
+        let mut graph = builder.graph;
+        graph.names = Some(builder.names);
+        graph.set_coordinate_system(CoordinateSystem {
+            projection: mak_projection(&project),
+            units: options.units,
+            ..CoordinateSystem::default()
+        });
+        graph.propagate_initial_guess(&options.initial_guess);
+        Ok((graph, refs))
+    }
+}
+
+/// Loads a Compass project and returns a context handle owning the resulting graph.
+///
+/// # Arguments
+///
+/// * `path` - NUL-terminated path to the `.mak` file.
+/// * `err_buf` - Buffer receiving a NUL-terminated error message on failure. May be null.
+/// * `err_cap` - Capacity of `err_buf` in bytes.
+///
+/// # Returns
+///
+/// * A handle to release with [`crate::graph_free`], or null on failure.
+#[unsafe(no_mangle)]
+pub extern "C" fn graph_from_compass_project(
+    path: *const c_char,
+    err_buf: *mut c_char,
+    err_cap: usize,
+) -> *mut GraphContext {
+    if path.is_null() {
+        write_message(err_buf, err_cap, "path is null");
+        return std::ptr::null_mut();
+    }
+    // Safety: The caller guarantees a valid NUL-terminated string.
+    let path = unsafe { CStr::from_ptr(path) }
+        .to_string_lossy()
+        .into_owned();
+    match std::panic::catch_unwind(|| Graph::from_compass_project(&path)) {
+        Ok(Ok(graph)) => GraphContext::into_raw(graph),
+        Ok(Err(err)) => {
+            write_message(err_buf, err_cap, &err.to_string());
+            std::ptr::null_mut()
+        }
+        Err(_) => {
+            write_message(
+                err_buf,
+                err_cap,
+                "panic while importing the Compass project",
+            );
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// Length unit of the coordinates written to a `.plt` file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PltUnits {
+    /// Decimal feet, what Compass itself writes and expects.
+    #[default]
+    Feet,
+    /// Meters, for viewers configured for metric plot files.
+    Meters,
+}
+
+/// Options for [`Solution::write_plt_with`].
+#[derive(Debug, Clone, Default)]
+pub struct PltOptions {
+    /// Unit of the written coordinates.
+    pub units: PltUnits,
+    /// Cave name written in the `S` section header.
+    pub cave_name: String,
+    /// Survey name written in the `N` survey header. Defaults to `ADJUSTED` when empty.
+    pub survey_name: String,
+}
+
+impl Solution {
+    /// Writes the adjusted network as a Compass `.plt` plot file in feet.
+    pub fn write_plt(&self, graph: &Graph, path: impl AsRef<Path>) -> std::io::Result<()> {
+        self.write_plt_with(graph, path, &PltOptions::default())
+    }
+
+    /// Writes the adjusted network as a Compass `.plt` plot file.
+    pub fn write_plt_with(
+        &self,
+        graph: &Graph,
+        path: impl AsRef<Path>,
+        options: &PltOptions,
+    ) -> std::io::Result<()> {
+        let text = self.to_plt(graph, options).map_err(invalid_input)?;
+        std::fs::write(path, text)
+    }
+
+    /// Renders the adjusted network as `.plt` text: one move/draw pair per surveyed edge,
+    /// using the adjusted X/Y and the graph Z.
+    ///
+    /// Station names come from [`Graph::names`] when the graph was imported from Compass;
+    /// otherwise synthetic `V<index>` names are used. Coordinates are converted from the unit
+    /// of the solution's coordinate system, meters when it has none, to [`PltOptions::units`].
+    ///
+    /// Returns [`COMPASS_ERR_INVALID_ARGUMENT`] when the solution, the heights or the names
+    /// do not have one value per vertex of `graph`, or an edge has an endpoint out of range.
+    pub fn to_plt(&self, graph: &Graph, options: &PltOptions) -> Result<String, SolveError> {
+        check_export(self, graph)?;
+        let units = self
+            .coordinate_system
+            .map_or(LengthUnit::Meters, |cs| cs.units);
+        let target = match options.units {
+            PltUnits::Feet => LengthUnit::Feet,
+            PltUnits::Meters => LengthUnit::Meters,
+        };
+        let scale = |value: f64| convert_length(value, units, target);
+        let name = |v: usize| match &graph.names {
+            Some(names) => names[v].clone(),
+            None => format!("V{v}"),
+        };
+        // Plot files store northing, easting, vertical.
+        let point = |v: usize| [self.y[v], self.x[v], graph.z[v]].map(scale);
+
+        let mut min = [f64::INFINITY; 3];
+        let mut max = [f64::NEG_INFINITY; 3];
+        for v in 0..graph.num_vertices() {
+            for (axis, value) in point(v).into_iter().enumerate() {
+                min[axis] = min[axis].min(value);
+                max[axis] = max[axis].max(value);
+            }
+        }
+        if graph.num_vertices() == 0 {
+            (min, max) = ([0.0; 3], [0.0; 3]);
+        }
+        let bounds = format!(
+            "{:.3} {:.3} {:.3} {:.3} {:.3} {:.3}",
+            min[0], max[0], min[1], max[1], min[2], max[2]
+        );
+        let survey_name = if options.survey_name.is_empty() {
+            "ADJUSTED"
+        } else {
+            options.survey_name.as_str()
+        };
+
+        let mut out = String::new();
+        let _ = writeln!(out, "Z {bounds}");
+        let _ = writeln!(out, "S{}", options.cave_name);
+        let _ = writeln!(out, "N{survey_name}");
+        let mut pen = None;
+        for e in 0..graph.num_edges() {
+            let (u, v) = (graph.from[e], graph.to[e]);
+            // Continue the current polyline when possible, drawing the edge backwards if needed.
+            let (start, end) = if pen == Some(v) { (v, u) } else { (u, v) };
+            for (command, vertex) in [("M", start), ("D", end)] {
+                if command == "M" && pen == Some(start) {
+                    continue;
+                }
+                let [n, east, vertical] = point(vertex);
+                let _ = writeln!(
+                    out,
+                    "{command} {n:.3} {east:.3} {vertical:.3} S{} P -9.0 -9.0 -9.0 -9.0",
+                    name(vertex)
+                );
+            }
+            pen = Some(end);
+        }
+        let _ = writeln!(out, "X {bounds}");
+        Ok(out)
+    }
+}
+
+impl Solution {
+    /// Writes the per-shot corrections report of [`Solution::to_compass_corrections`].
+    pub fn write_compass_corrections(
+        &self,
+        graph: &Graph,
+        refs: &[CompassShotRef],
+        path: impl AsRef<Path>,
+    ) -> std::io::Result<()> {
+        let text = self
+            .to_compass_corrections(graph, refs)
+            .map_err(invalid_input)?;
+        std::fs::write(path, text)
+    }
+
+    /// Renders the corrections implied by the adjustment for each shot of a graph imported
+    /// with [`Graph::from_compass_project_with_refs`], as CSV keyed by survey name and shot
+    /// number, in the units of `.dat` files (feet and degrees).
+    ///
+    /// Columns are `survey, shot, from, to`, the corrected `length, azimuth, inclination`,
+    /// and the corrections to apply to the readings: `length_correction,
+    /// azimuth_correction, inclination_correction`. Everything is expressed in the direction
+    /// of the shot as written, reversed edges included. Azimuths include the declination.
+    ///
+    /// Returns [`COMPASS_ERR_INVALID_ARGUMENT`] as [`Solution::to_plt`] does, and when
+    /// `refs` or the observations do not have one entry per edge.
+    pub fn to_compass_corrections(
+        &self,
+        graph: &Graph,
+        refs: &[CompassShotRef],
+    ) -> Result<String, SolveError> {
+        check_export(self, graph)?;
+        let m = graph.num_edges();
+        let counts = [refs.len(), graph.dx.len(), graph.dy.len(), graph.dz.len()];
+        if counts.iter().any(|&count| count != m) {
+            let [refs, dx, dy, dz] = counts;
+            return Err(invalid(format!(
+                "{refs} shot references and {dx} / {dy} / {dz} observations for {m} edges"
+            )));
+        }
+        let units = self
+            .coordinate_system
+            .map_or(LengthUnit::Meters, |cs| cs.units);
+        let feet = |length: f64| convert_length(length, units, LengthUnit::Feet);
+        let name = |v: usize| match &graph.names {
+            Some(names) => names[v].clone(),
+            None => format!("V{v}"),
+        };
+        let mut out = String::from(
+            "survey,shot,from,to,length,azimuth,inclination,\
+             length_correction,azimuth_correction,inclination_correction\n",
+        );
+        for ((e, correction), origin) in self.shot_corrections(graph).iter().enumerate().zip(refs) {
+            let (from, to, c): (_, _, ShotCorrection) = match origin.reversed {
+                false => (graph.from[e], graph.to[e], *correction),
+                true => (graph.to[e], graph.from[e], correction.reversed()),
+            };
+            let _ = writeln!(
+                out,
+                "{},{},{},{},{:.3},{:.2},{:.2},{:.3},{:.2},{:.2}",
+                origin.survey,
+                origin.shot,
+                name(from),
+                name(to),
+                feet(c.length),
+                c.azimuth_deg,
+                c.inclination_deg,
+                feet(c.length_correction),
+                c.azimuth_correction_deg,
+                c.inclination_correction_deg,
+            );
+        }
+        Ok(out)
+    }
+}
+
+/// An [`COMPASS_ERR_INVALID_ARGUMENT`] error.
+fn invalid(message: String) -> SolveError {
+    SolveError {
+        code: COMPASS_ERR_INVALID_ARGUMENT,
+        message,
+    }
+}
+
+/// Files and fixed stations listed in a `.mak` project.
+#[derive(Debug, Default)]
+struct MakProject {
+    files: Vec<String>,
+    /// Fixed station name, unit and (east, north, elevation) as written.
+    fixed_stations: Vec<(String, LengthUnit, [f64; 3])>,
+    /// UTM zone of the base location, negative in the southern hemisphere.
+    utm_zone: Option<i32>,
+    /// Datum name, e.g. `North American 1983`.
+    datum: Option<String>,
+}
+
+/// Projection named by the base location and datum of `project`. Only the datums with an
+/// EPSG code per UTM zone are recognized; the grid stays local for the others.
+fn mak_projection(project: &MakProject) -> Projection {
+    let (Some(zone), Some(datum)) = (project.utm_zone, &project.datum) else {
+        return Projection::LocalGrid;
+    };
+    let north = zone > 0;
+    let Ok(zone) = u8::try_from(zone.unsigned_abs()) else {
+        return Projection::LocalGrid;
+    };
+    let epsg = |base: u32, zones: u8| {
+        (north && (1..=zones).contains(&zone)).then(|| Projection::Epsg(base + zone as u32))
+    };
+    match datum.to_ascii_lowercase().as_str() {
+        "wgs 1984" => Projection::utm_wgs84(zone, north),
+        "north american 1983" => epsg(26900, 23),
+        "north american 1927" => epsg(26700, 22),
+        _ => None,
+    }
+    .unwrap_or_default()
+}
+
+/// One shot of a `.dat` file, already reduced to a length and a direction.
+#[derive(Debug)]
+struct DatShot {
+    from: String,
+    to: String,
+    /// Corrected length, in feet.
+    length: f64,
+    /// Corrected azimuth including declination, in degrees.
+    azimuth: f64,
+    /// Corrected inclination, in degrees.
+    inclination: f64,
+    /// Grade of the survey, when its header states one.
+    grade: Option<SurveyGrade>,
+    /// Whether the shot is flagged `C`, not to be adjusted when closing loops.
+    held: bool,
+    /// Survey name.
+    survey: String,
+    /// 1-based position among the shot lines of the survey.
+    number: usize,
+}
+
+#[derive(Default)]
+struct GraphBuilder {
+    graph: Graph,
+    names: Vec<String>,
+    index: HashMap<String, usize>,
+}
+
+impl GraphBuilder {
+    fn vertex(&mut self, name: &str) -> usize {
+        if let Some(&v) = self.index.get(name) {
+            return v;
+        }
+        let v = self.graph.add_vertex(0.0, 0.0, 0.0, false);
+        self.names.push(name.to_string());
+        self.index.insert(name.to_string(), v);
+        v
+    }
+
+    fn add_shot(&mut self, shot: &DatShot, options: &CompassImportOptions) {
+        let u = self.vertex(&shot.from);
+        let v = self.vertex(&shot.to);
+        let length = convert_length(shot.length, LengthUnit::Feet, options.units);
+        let azimuth = shot.azimuth.to_radians();
+        let inclination = shot.inclination.to_radians();
+        let horizontal = length * inclination.cos();
+        let grade = shot.grade.or(options.default_grade);
+        let model = match (options.weight_model, grade) {
+            (Some(model), _) => model,
+            (None, Some(grade)) => grade.sigmas(&options.grade_sigmas).into(),
+            (None, None) => WeightModel::Custom {
+                length_sigma: options.length_sigma,
+                azimuth_sigma_deg: options.angle_sigma_deg,
+                inclination_sigma_deg: options.angle_sigma_deg,
+            },
+        };
+        // Weight models take meters.
+        let mut weight = model.weight(convert_length(
+            shot.length,
+            LengthUnit::Feet,
+            LengthUnit::Meters,
+        ));
+        if shot.held {
+            // Held at its measurement like the edges of a locked loop: the misclosure goes
+            // to the other shots.
+            weight = weight.max(DEFAULT_LOCK_WEIGHT);
+        }
+        self.graph.add_edge(
+            u,
+            v,
+            horizontal * azimuth.sin(),
+            horizontal * azimuth.cos(),
+            length * inclination.sin(),
+            convert_weight(weight, LengthUnit::Meters, options.units),
+        );
+    }
+}
+
+fn read_file(path: &Path) -> Result<String, ImportError> {
+    let bytes = std::fs::read(path).map_err(|err| ImportError::Io {
+        path: path.to_path_buf(),
+        message: err.to_string(),
+    })?;
+    // Compass writes Windows-1252; files edited since may have been saved as UTF-8.
+    Ok(String::from_utf8(bytes).unwrap_or_else(|err| decode_windows_1252(err.as_bytes())))
+}
+
+/// Characters of the bytes 0x80 to 0x9F in Windows-1252. The five bytes it leaves undefined
+/// keep their C1 control, as in the WHATWG decoder; the other bytes are Latin-1.
+const WINDOWS_1252_HIGH: [char; 32] = [
+    '€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{8d}', 'Ž', '\u{8f}',
+    '\u{90}', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{9d}', 'ž', 'Ÿ',
+];
+
+fn decode_windows_1252(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|&b| match b {
+            0x80..=0x9f => WINDOWS_1252_HIGH[usize::from(b - 0x80)],
+            _ => char::from(b),
+        })
+        .collect()
+}
+
+/// Resolves a file name from a `.mak` project relative to the project directory. Compass
+/// projects usually come from Windows, so backslashes are accepted and, if the exact name
+/// does not exist, a case-insensitive match in the same directory is used.
+fn resolve_path(base_dir: &Path, file: &str) -> PathBuf {
+    let candidate = base_dir.join(file.replace('\\', "/"));
+    if candidate.exists() {
+        return candidate;
+    }
+    let (Some(dir), Some(name)) = (candidate.parent(), candidate.file_name()) else {
+        return candidate;
+    };
+    let wanted = name.to_string_lossy().to_lowercase();
+    std::fs::read_dir(dir)
+        .ok()
+        .and_then(|entries| {
+            entries
+                .filter_map(Result::ok)
+                .find(|entry| entry.file_name().to_string_lossy().to_lowercase() == wanted)
+                .map(|entry| entry.path())
+        })
+        .unwrap_or(candidate)
+}
+
+/// Character cursor over a `.mak` file tracking 1-based line and column numbers.
+struct MakCursor<'a> {
+    path: &'a Path,
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+    column: usize,
+}
+
+impl MakCursor<'_> {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    fn error(&self, message: impl Into<String>) -> ImportError {
+        ImportError::Parse {
+            path: self.path.to_path_buf(),
+            line: self.line,
+            column: self.column,
+            message: message.into(),
+        }
+    }
+
+    /// Skips whitespace and `/` comments, which run to the end of the line.
+    fn skip_blank(&mut self) {
+        while let Some(c) = self.peek() {
+            if c.is_whitespace() {
+                self.bump();
+            } else if c == '/' {
+                while self.peek().is_some_and(|c| c != '\n') {
+                    self.bump();
+                }
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), ImportError> {
+        self.skip_blank();
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.bump();
+                Ok(())
+            }
+            Some(c) => Err(self.error(format!("expected {expected}, got {c}"))),
+            None => Err(self.error(format!("expected {expected}, got end of file"))),
+        }
+    }
+
+    /// Reads characters up to (not including) any of `stops`, trimmed.
+    fn take_until(&mut self, stops: &[char]) -> String {
+        let mut text = String::new();
+        while let Some(c) = self.peek() {
+            if stops.contains(&c) || c == '/' {
+                break;
+            }
+            text.push(c);
+            self.bump();
+        }
+        text.trim().to_string()
+    }
+
+    fn skip_directive(&mut self) -> Result<(), ImportError> {
+        while let Some(c) = self.bump() {
+            if c == ';' {
+                return Ok(());
+            }
+        }
+        Err(self.error("missing ; at end of directive"))
+    }
+
+    fn number(&mut self, what: &str) -> Result<f64, ImportError> {
+        self.skip_blank();
+        let (line, column) = (self.line, self.column);
+        let mut text = String::new();
+        while let Some(c) = self.peek() {
+            if c.is_ascii_digit() || matches!(c, '.' | '-' | '+' | 'e' | 'E') {
+                text.push(c);
+                self.bump();
+            } else {
+                break;
+            }
+        }
+        text.parse::<f64>().map_err(|_| ImportError::Parse {
+            path: self.path.to_path_buf(),
+            line,
+            column,
+            message: format!("missing or invalid {what}"),
+        })
+    }
+}
+
+fn parse_mak(path: &Path, text: &str) -> Result<MakProject, ImportError> {
+    let mut cursor = MakCursor {
+        path,
+        chars: text.chars().collect(),
+        pos: 0,
+        line: 1,
+        column: 1,
+    };
+    let mut project = MakProject::default();
+
+    loop {
+        cursor.skip_blank();
+        let Some(c) = cursor.peek() else {
+            return Ok(project);
+        };
+        match c {
+            '#' => {
+                cursor.bump();
+                parse_mak_file_entry(&mut cursor, &mut project)?;
+            }
+            '@' => {
+                cursor.bump();
+                project.utm_zone = Some(parse_mak_base_location(&mut cursor)?);
+            }
+            '&' => {
+                cursor.bump();
+                project.datum = Some(cursor.take_until(&[';']));
+                cursor.expect(';')?;
+            }
+            // Folders, convergence, zone and flags carry no information needed by the
+            // adjustment.
+            '[' | ']' | '%' | '*' | '$' | '!' => cursor.skip_directive()?,
+            other => return Err(cursor.error(format!("unexpected character: {other}"))),
+        }
+    }
+}
+
+/// Parses `east,north,elevation,zone,convergence;` after the leading `@` and returns the UTM
+/// zone.
+fn parse_mak_base_location(cursor: &mut MakCursor) -> Result<i32, ImportError> {
+    for what in ["easting", "northing", "elevation"] {
+        cursor.number(what)?;
+        cursor.expect(',')?;
+    }
+    let zone = cursor.number("UTM zone")?;
+    if zone.fract() != 0.0 || !(-60.0..=60.0).contains(&zone) {
+        return Err(cursor.error(format!("invalid UTM zone: {zone}")));
+    }
+    cursor.expect(',')?;
+    cursor.number("convergence")?;
+    cursor.expect(';')?;
+    Ok(zone as i32)
+}
+
+/// Parses `file,station[unit,east,north,elev],station;` after the leading `#`.
+fn parse_mak_file_entry(
+    cursor: &mut MakCursor,
+    project: &mut MakProject,
+) -> Result<(), ImportError> {
+    let file = cursor.take_until(&[',', ';']);
+    if file.is_empty() {
+        return Err(cursor.error("missing file name"));
+    }
+    project.files.push(file);
+
+    loop {
+        cursor.skip_blank();
+        match cursor.bump() {
+            Some(';') => return Ok(()),
+            Some(',') => {
+                cursor.skip_blank();
+                let station = cursor.take_until(&[',', ';', '[']);
+                if station.is_empty() {
+                    return Err(cursor.error("missing station name"));
+                }
+                cursor.skip_blank();
+                if cursor.peek() != Some('[') {
+                    // Link station without a location.
+                    continue;
+                }
+                cursor.bump();
+                cursor.skip_blank();
+                let unit = match cursor.bump() {
+                    Some('F' | 'f') => LengthUnit::Feet,
+                    Some('M' | 'm') => LengthUnit::Meters,
+                    Some(other) => {
+                        return Err(cursor.error(format!("invalid length unit: {other}")));
+                    }
+                    None => return Err(cursor.error("missing length unit")),
+                };
+                cursor.expect(',')?;
+                let east = cursor.number("easting")?;
+                cursor.expect(',')?;
+                let north = cursor.number("northing")?;
+                cursor.expect(',')?;
+                let elevation = cursor.number("elevation")?;
+                cursor.expect(']')?;
+                project
+                    .fixed_stations
+                    .push((station, unit, [east, north, elevation]));
+            }
+            Some(other) => return Err(cursor.error(format!("unexpected character: {other}"))),
+            None => return Err(cursor.error("missing ; at end of file line")),
+        }
+    }
+}
+
+/// Whitespace separated tokens of a line with their 1-based column.
+fn tokens(line: &str) -> Vec<(usize, &str)> {
+    let mut out = Vec::new();
+    let mut start = None;
+    for (i, c) in line.char_indices() {
+        match (c.is_whitespace(), start) {
+            (false, None) => start = Some(i),
+            (true, Some(s)) => {
+                out.push((s + 1, &line[s..i]));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        out.push((s + 1, &line[s..]));
+    }
+    out
+}
+
+/// Survey header values needed to reduce the shots.
+#[derive(Default)]
+struct DatHeader {
+    declination: f64,
+    has_backsights: bool,
+    length_correction: f64,
+    azimuth_correction: f64,
+    inclination_correction: f64,
+    backsight_azimuth_correction: f64,
+    backsight_inclination_correction: f64,
+    grade: Option<SurveyGrade>,
+    survey: String,
+}
+
+enum DatState {
+    /// Cave name line, then header fields until the `FROM TO ...` column header.
+    Header,
+    /// Shot lines until the next form feed. Blank lines are skipped.
+    Shots,
+}
+
+fn parse_dat(path: &Path, text: &str) -> Result<Vec<DatShot>, ImportError> {
+    let error = |line: usize, column: usize, message: String| ImportError::Parse {
+        path: path.to_path_buf(),
+        line,
+        column,
+        message,
+    };
+
+    let mut shots = Vec::new();
+    let mut state = DatState::Header;
+    let mut header = DatHeader::default();
+    let mut header_tokens: Vec<(usize, usize, String)> = Vec::new();
+    let mut header_line = 0;
+    let mut shot_number = 0;
+
+    for (i, raw) in text.lines().enumerate() {
+        let line_no = i + 1;
+        // Surveys are separated by a form feed.
+        let line = match raw.find('\u{c}') {
+            Some(ff) => {
+                state = DatState::Header;
+                header_tokens.clear();
+                header_line = 0;
+                &raw[ff + 1..]
+            }
+            None => raw,
+        };
+
+        match state {
+            DatState::Header => {
+                if line.trim().is_empty() && header_line == 0 {
+                    continue;
+                }
+                header_line += 1;
+                let toks = tokens(line);
+                let is_column_header = header_line > 1
+                    && toks.len() >= 2
+                    && toks[0].1.eq_ignore_ascii_case("FROM")
+                    && toks[1].1.eq_ignore_ascii_case("TO");
+                if is_column_header {
+                    header = parse_dat_header(&header_tokens, &error)?;
+                    state = DatState::Shots;
+                    shot_number = 0;
+                } else if header_line > 1 {
+                    // The first line is the free-form cave name.
+                    header_tokens.extend(toks.iter().map(|&(c, t)| (line_no, c, t.to_string())));
+                }
+            }
+            DatState::Shots => {
+                if line.trim().is_empty() {
+                    continue;
+                }
+                shot_number += 1;
+                if let Some(mut shot) = parse_dat_shot(line, line_no, &header, &error)? {
+                    shot.number = shot_number;
+                    shots.push(shot);
+                }
+            }
+        }
+    }
+    Ok(shots)
+}
+
+fn parse_dat_header(
+    toks: &[(usize, usize, String)],
+    error: &dyn Fn(usize, usize, String) -> ImportError,
+) -> Result<DatHeader, ImportError> {
+    let mut header = DatHeader {
+        has_backsights: true,
+        ..DatHeader::default()
+    };
+    let number = |k: usize| -> Result<Option<f64>, ImportError> {
+        match toks.get(k) {
+            Some((line, column, text)) if !text.ends_with(':') => text
+                .parse::<f64>()
+                .map(Some)
+                .map_err(|_| error(*line, *column, format!("invalid number: {text}"))),
+            _ => Ok(None),
+        }
+    };
+
+    for (k, (_, _, text)) in toks.iter().enumerate() {
+        match text.to_ascii_uppercase().as_str() {
+            "DECLINATION:" => header.declination = number(k + 1)?.unwrap_or(0.0),
+            "NAME:" if header.survey.is_empty() => {
+                if let Some((_, _, name)) = toks.get(k + 1) {
+                    header.survey = name.clone();
+                }
+            }
+            "FORMAT:" => {
+                if let Some((_, _, format)) = toks.get(k + 1) {
+                    // The backsight flag sits at position 11 (12/13 chars) or 13 (15 chars).
+                    let format = format.as_bytes();
+                    header.has_backsights = match format.len() {
+                        0..=11 => false,
+                        12..=14 => format[11].eq_ignore_ascii_case(&b'B'),
+                        _ => format[13].eq_ignore_ascii_case(&b'B'),
+                    };
+                }
+            }
+            "CORRECTIONS:" => {
+                header.length_correction = number(k + 1)?.unwrap_or(0.0);
+                header.azimuth_correction = number(k + 2)?.unwrap_or(0.0);
+                header.inclination_correction = number(k + 3)?.unwrap_or(0.0);
+            }
+            "CORRECTIONS2:" => {
+                header.backsight_azimuth_correction = number(k + 1)?.unwrap_or(0.0);
+                header.backsight_inclination_correction = number(k + 2)?.unwrap_or(0.0);
+            }
+            _ => {}
+        }
+        if header.grade.is_none() {
+            header.grade = parse_grade(toks, k);
+        }
+    }
+    Ok(header)
+}
+
+/// Reads a survey grade from header token `k` when it is `BCRA` or `UIS` (optionally `UISv1`)
+/// followed by the grade number, in the same token or the next one.
+fn parse_grade(toks: &[(usize, usize, String)], k: usize) -> Option<SurveyGrade> {
+    let upper = toks[k].2.to_ascii_uppercase();
+    let rest = ["BCRA", "UISV1", "UIS"]
+        .iter()
+        .find_map(|scale| upper.strip_prefix(scale))?
+        .trim_start_matches(['-', ':', '_']);
+    let grade = match rest {
+        "" => toks.get(k + 1)?.2.chars().next()?,
+        _ => rest.chars().next()?,
+    };
+    SurveyGrade::from_number(grade.to_digit(10)?)
+}
+
+/// Parses one shot line. Returns `None` for shots excluded from all processing (`X` flag).
+///
+/// Columns are always `FROM TO LENGTH AZIMUTH INCLINATION LEFT UP DOWN RIGHT [BS_AZ BS_INC]
+/// [FLAGS] [COMMENT]`.
+fn parse_dat_shot(
+    line: &str,
+    line_no: usize,
+    header: &DatHeader,
+    error: &dyn Fn(usize, usize, String) -> ImportError,
+) -> Result<Option<DatShot>, ImportError> {
+    let toks = tokens(line);
+    if toks.len() < 5 {
+        let column = toks.last().map_or(1, |t| t.0);
+        return Err(error(
+            line_no,
+            column,
+            "shot needs at least FROM TO LENGTH AZIMUTH INCLINATION".to_string(),
+        ));
+    }
+
+    let value = |k: usize, what: &str| -> Result<Option<f64>, ImportError> {
+        let Some(&(column, text)) = toks.get(k) else {
+            return Ok(None);
+        };
+        let v = text
+            .parse::<f64>()
+            .map_err(|_| error(line_no, column, format!("invalid {what}: {text}")))?;
+        let missing = v >= MISSING_VALUE_THRESHOLD || v <= MISSING_ANGLE_THRESHOLD;
+        Ok(if missing { None } else { Some(v) })
+    };
+
+    // Shot flags: `X` excludes the shot altogether, `C` keeps it out of loop closure.
+    let flags = line.find("#|").map_or("", |start| {
+        let flags = &line[start + 2..];
+        &flags[..flags.find('#').unwrap_or(flags.len())]
+    });
+    let flagged = |flag: char| flags.chars().any(|c| c.eq_ignore_ascii_case(&flag));
+    if flagged('X') {
+        return Ok(None);
+    }
+
+    let length = value(2, "length")?
+        .ok_or_else(|| error(line_no, toks[2].0, "missing length".to_string()))?;
+    let fs_azimuth = value(3, "azimuth")?;
+    let fs_inclination = value(4, "inclination")?;
+    let (bs_azimuth, bs_inclination) =
+        if header.has_backsights && toks.len() > 10 && !toks[9].1.starts_with('#') {
+            (
+                value(9, "backsight azimuth")?,
+                value(10, "backsight inclination")?,
+            )
+        } else {
+            (None, None)
+        };
+
+    let inclinations: Vec<f64> = fs_inclination
+        .map(|i| i + header.inclination_correction)
+        .into_iter()
+        .chain(bs_inclination.map(|i| -(i + header.backsight_inclination_correction)))
+        .collect();
+    let inclination = if inclinations.is_empty() {
+        0.0
+    } else {
+        inclinations.iter().sum::<f64>() / inclinations.len() as f64
+    };
+
+    // Average the foresight and reversed backsight as unit vectors to handle the 0/360 wrap.
+    let azimuths: Vec<f64> = fs_azimuth
+        .map(|a| a + header.azimuth_correction)
+        .into_iter()
+        .chain(bs_azimuth.map(|a| a + header.backsight_azimuth_correction - 180.0))
+        .collect();
+    let azimuth = if azimuths.is_empty() {
+        if inclination.abs() < 90.0 && length > 0.0 {
+            return Err(error(line_no, toks[3].0, "missing azimuth".to_string()));
+        }
+        0.0
+    } else {
+        let (s, c) = azimuths.iter().fold((0.0, 0.0), |(s, c), a: &f64| {
+            (s + a.to_radians().sin(), c + a.to_radians().cos())
+        });
+        s.atan2(c).to_degrees()
+    };
+
+    Ok(Some(DatShot {
+        from: toks[0].1.to_string(),
+        to: toks[1].1.to_string(),
+        length: length + header.length_correction,
+        azimuth: azimuth + header.declination,
+        inclination,
+        grade: header.grade,
+        held: flagged('C'),
+        survey: header.survey.clone(),
+        number: 0,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CString;
+
+    const HEADER: &str = "SECRET CAVE\nSURVEY NAME: A\nSURVEY DATE: 7 10 1979  COMMENT:\n\
+                          SURVEY TEAM:\nD.SMITH\n";
+
+    /// Writes `cave.mak` and `cave.dat` into a fresh directory and returns it with the path of
+    /// the project.
+    fn project(mak: &str, dat: &str) -> (tempfile::TempDir, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("cave.dat"), dat).unwrap();
+        let mak_path = dir.path().join("cave.mak");
+        std::fs::write(&mak_path, mak).unwrap();
+        (dir, mak_path)
+    }
+
+    fn survey(declination: f64, shots: &str) -> String {
+        format!(
+            "{HEADER}DECLINATION: {declination:.2}  FORMAT: DDDDLUDRADLN  CORRECTIONS: 0.00 0.00 \
+             0.00\n\nFROM TO LENGTH BEARING DIP LEFT UP DOWN RIGHT\n\n{shots}\u{c}\n"
+        )
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "{actual} differs from {expected}"
+        );
+    }
+
+    /// A loop of four stations around one fixed corner, solved.
+    fn solved_square(names: bool) -> (Graph, Solution) {
+        let mut graph = Graph::default();
+        for (x, y, fixed) in [(0.0, 0.0, true), (10.1, 0.2, false), (9.8, 10.3, false)] {
+            graph.add_vertex(x, y, 1.5, fixed);
+        }
+        graph.add_vertex(-0.1, 9.9, -2.0, false);
+        for (u, v, dx, dy) in [(0, 1, 10.0, 0.0), (1, 2, 0.0, 10.0), (2, 3, -10.0, 0.0)] {
+            graph.add_edge(u, v, dx, dy, 0.0, 1.0);
+        }
+        graph.add_edge(0, 3, 0.05, 10.02, 0.0, 1.0);
+        if names {
+            graph.names = Some(["A1", "A2", "B1", "B2"].map(String::from).to_vec());
+        }
+        let solution = graph.solve(1000, 1e-12).unwrap();
+        (graph, solution)
+    }
+
+    /// Station coordinates of a parsed `.plt` file, by name.
+    type Stations = HashMap<String, [f64; 3]>;
+
+    /// Station coordinates and drawn segments of `.plt` text, as a viewer reads them.
+    fn parse_plt(text: &str) -> (Stations, Vec<(String, String)>) {
+        let (mut stations, mut segments) = (HashMap::new(), Vec::new());
+        let mut pen: Option<String> = None;
+        for line in text.lines() {
+            let toks: Vec<&str> = line.split_whitespace().collect();
+            if !matches!(toks.first(), Some(&"M" | &"D")) {
+                continue;
+            }
+            let value = |k: usize| toks[k].parse::<f64>().unwrap();
+            let name = toks[4].strip_prefix('S').unwrap().to_string();
+            stations.insert(name.clone(), [value(1), value(2), value(3)]);
+            if toks[0] == "D" {
+                segments.push((pen.take().unwrap(), name.clone()));
+            }
+            pen = Some(name);
+        }
+        (stations, segments)
+    }
+
+    fn assert_round_trip(text: &str, graph: &Graph, solution: &Solution, scale: f64) {
+        let (stations, segments) = parse_plt(text);
+        let names = graph
+            .names
+            .clone()
+            .unwrap_or_else(|| (0..graph.num_vertices()).map(|v| format!("V{v}")).collect());
+        assert_eq!(stations.len(), graph.num_vertices());
+        for (v, name) in names.iter().enumerate() {
+            let [north, east, vertical] = stations[name];
+            assert!(
+                (north - solution.y[v] * scale).abs() <= 5e-4,
+                "{name} northing"
+            );
+            assert!(
+                (east - solution.x[v] * scale).abs() <= 5e-4,
+                "{name} easting"
+            );
+            assert!(
+                (vertical - graph.z[v] * scale).abs() <= 5e-4,
+                "{name} vertical"
+            );
+        }
+        let mut drawn: Vec<(usize, usize)> = (segments.iter())
+            .map(|(a, b)| {
+                let index = |n: &String| names.iter().position(|m| m == n).unwrap();
+                (index(a).min(index(b)), index(a).max(index(b)))
+            })
+            .collect();
+        drawn.sort_unstable();
+        let mut edges: Vec<(usize, usize)> = (0..graph.num_edges())
+            .map(|e| {
+                (
+                    graph.from[e].min(graph.to[e]),
+                    graph.from[e].max(graph.to[e]),
+                )
+            })
+            .collect();
+        edges.sort_unstable();
+        assert_eq!(drawn, edges);
+    }
+
+    #[test]
+    fn imports_stations_shots_and_fixed_stations() {
+        let dat = survey(
+            0.0,
+            "A1 A2 10.00 90.00 0.00 0 0 0 0\nA2 A3 20.00 0.00 30.00 0 0 0 0\n",
+        );
+        let (_dir, path) = project("#cave.dat,A1[m,100.0,200.0,50.0];\n", &dat);
+        let graph = Graph::from_compass_project(&path).unwrap();
+
+        assert_eq!(graph.names.as_deref().unwrap(), ["A1", "A2", "A3"]);
+        assert_eq!(graph.fixed, [true, false, false]);
+        assert_eq!(graph.num_edges(), 2);
+        assert_eq!((graph.from[1], graph.to[1]), (1, 2));
+        // Due east, level.
+        assert_close(graph.dx[0], 10.0 * FEET_TO_METERS);
+        assert_close(graph.dy[0], 0.0);
+        assert_close(graph.dz[0], 0.0);
+        // Due north, 30 degrees up.
+        let length = 20.0 * FEET_TO_METERS;
+        assert_close(graph.dx[1], 0.0);
+        assert_close(graph.dy[1], length * 30f64.to_radians().cos());
+        assert_close(graph.dz[1], length * 30f64.to_radians().sin());
+        // The initial guess follows the shots from the fixed station.
+        assert_close(graph.x[2], 100.0 + 10.0 * FEET_TO_METERS);
+        assert_close(graph.y[2], 200.0 + graph.dy[1]);
+        assert_close(graph.z[2], 50.0 + graph.dz[1]);
+    }
+
+    #[test]
+    fn applies_declination_and_feet_to_fixed_stations() {
+        let dat = survey(90.0, "A1 A2 10.00 0.00 0.00 0 0 0 0\n");
+        let (_dir, path) = project("#cave.dat,A1[f,10.0,20.0,30.0];\n", &dat);
+        let graph = Graph::from_compass_project(&path).unwrap();
+
+        assert_close(graph.x[0], 10.0 * FEET_TO_METERS);
+        assert_close(graph.y[0], 20.0 * FEET_TO_METERS);
+        assert_close(graph.z[0], 30.0 * FEET_TO_METERS);
+        assert_close(graph.dx[0], 10.0 * FEET_TO_METERS);
+        assert_close(graph.dy[0], 0.0);
+    }
+
+    #[test]
+    fn imports_in_the_target_unit_and_exports_the_shot_lengths_back_in_feet() {
+        let lengths = [10.37, 123.456, 0.83];
+        let dat = survey(
+            0.0,
+            "A1 A2 10.37 31.50 -12.00 0 0 0 0\n\
+             A2 A3 123.456 187.25 4.50 0 0 0 0\n\
+             A3 A4 0.83 290.00 -80.00 0 0 0 0\n",
+        );
+        let (_dir, path) = project("#cave.dat,A1[m,100.0,200.0,300.0];\n", &dat);
+        let import = |units| {
+            let options = CompassImportOptions {
+                units,
+                ..CompassImportOptions::default()
+            };
+            Graph::from_compass_project_with_refs(&path, &options).unwrap()
+        };
+
+        let (metric, _) = import(LengthUnit::Meters);
+        for units in [
+            LengthUnit::Meters,
+            LengthUnit::Feet,
+            LengthUnit::UsSurveyFeet,
+        ] {
+            let (graph, refs) = import(units);
+            assert_eq!(graph.coordinate_system().unwrap().units, units);
+            let anchor = convert_length(100.0, LengthUnit::Meters, units);
+            assert!((graph.x[0] - anchor).abs() <= 1e-12 * anchor);
+
+            // The network has no loop, so the adjustment keeps every shot as observed.
+            let solution = graph.solve(60_000, 1e-12).unwrap();
+            for (e, c) in solution.shot_corrections(&graph).iter().enumerate() {
+                let feet = convert_length(c.length, units, LengthUnit::Feet);
+                let relative = (feet - lengths[e]).abs() / lengths[e];
+                assert!(relative < 1e-9, "{units:?} edge {e}: {feet} ft");
+                let weight = convert_weight(metric.weight[e], LengthUnit::Meters, units);
+                assert!((graph.weight[e] - weight).abs() <= 1e-12 * weight);
+            }
+            let report = solution.to_compass_corrections(&graph, &refs).unwrap();
+            assert!(report.contains(",A2,A3,123.456,"), "{report}");
+        }
+    }
+
+    #[test]
+    fn base_location_and_datum_name_the_utm_grid() {
+        let dat = survey(0.0, "A1 A2 10.00 0.00 0.00 0 0 0 0\n");
+        let system = |mak: &str| {
+            let (_dir, path) = project(mak, &dat);
+            *Graph::from_compass_project(&path)
+                .unwrap()
+                .coordinate_system()
+                .unwrap()
+        };
+        let location = "@546866.900,3561472.900,1414.100,13,-0.260;\n";
+
+        let nad83 = system(&format!("{location}&North American 1983;\n#cave.dat;\n"));
+        assert_eq!(nad83.projection, Projection::Epsg(26913));
+        assert_eq!(nad83.units, LengthUnit::Meters);
+        let wgs84 = system(&format!("{location}&WGS 1984;\n#cave.dat;\n"));
+        assert_eq!(wgs84.projection, Projection::Epsg(32613));
+        let south = "@546866.900,3561472.900,1414.100,-13,-0.260;\n&WGS 1984;\n#cave.dat;\n";
+        assert_eq!(system(south).projection, Projection::Epsg(32713));
+        let unknown = system(&format!("{location}&Tokyo;\n#cave.dat;\n"));
+        assert_eq!(unknown.projection, Projection::LocalGrid);
+        assert_eq!(system("#cave.dat;\n").projection, Projection::LocalGrid);
+
+        let (_dir, path) = project("@1,2,3,61,0;\n#cave.dat;\n", &dat);
+        let err = Graph::from_compass_project(&path).unwrap_err();
+        assert!(err.to_string().contains("invalid UTM zone: 61"), "{err}");
+    }
+
+    #[test]
+    fn averages_backsights_and_skips_excluded_shots() {
+        let dat = format!(
+            "{HEADER}DECLINATION: 0.00  FORMAT: DDDDLUDRADLBN  CORRECTIONS: 0.00 0.00 0.00\n\n\
+             FROM TO LENGTH BEARING DIP LEFT UP DOWN RIGHT AZM2 INC2\n\n\
+             A1 A2 10.00 358.00 2.00 0 0 0 0 182.00 -4.00\n\
+             A2 A3 10.00 90.00 0.00 0 0 0 0 270.00 0.00 #|X#\n\u{c}\n"
+        );
+        let (_dir, path) = project("#cave.dat;\n", &dat);
+        let graph = Graph::from_compass_project(&path).unwrap();
+
+        assert_eq!(graph.num_edges(), 1);
+        assert_eq!(graph.num_vertices(), 2);
+        // 358 and 182 - 180 average to due north across the wrap; inclinations to 3 degrees.
+        let length = 10.0 * FEET_TO_METERS;
+        assert_close(graph.dx[0], 0.0);
+        assert_close(graph.dy[0], length * 3f64.to_radians().cos());
+        assert_close(graph.dz[0], length * 3f64.to_radians().sin());
+    }
+
+    #[test]
+    fn decodes_windows_1252_station_names() {
+        let dat = survey(
+            0.0,
+            "\u{c9}1 \u{c8}1 10.00 90.00 0.00 0 0 0 0\n\
+             \u{c8}1 \u{c9}2 10.00 0.00 0.00 0 0 0 0\n",
+        );
+        // One byte per character, as Compass writes them.
+        let bytes: Vec<u8> = dat.chars().map(|c| u8::try_from(c).unwrap()).collect();
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("cave.dat"), bytes).unwrap();
+        let path = dir.path().join("cave.mak");
+        std::fs::write(&path, "#cave.dat;\n").unwrap();
+
+        let graph = Graph::from_compass_project(&path).unwrap();
+        assert_eq!(graph.names.as_deref().unwrap(), ["É1", "È1", "É2"]);
+        assert_eq!(decode_windows_1252(b"\x80 \x9c\x81"), "€ œ\u{81}");
+
+        // Files saved as UTF-8 since are read as such.
+        std::fs::write(dir.path().join("cave.dat"), &dat).unwrap();
+        let graph = Graph::from_compass_project(&path).unwrap();
+        assert_eq!(graph.names.as_deref().unwrap(), ["É1", "È1", "É2"]);
+    }
+
+    #[test]
+    fn holds_the_shots_excluded_from_closure() {
+        // A loop misclosed by 0.5 ft to the south-west, its first shot flagged `C`.
+        let dat = survey(
+            0.0,
+            "A1 A2 10.00 90.00 0.00 0 0 0 0 #|C#\n\
+             A2 A3 10.00 0.00 0.00 0 0 0 0\n\
+             A3 A1 14.50 225.00 0.00 0 0 0 0 #|PL#\n",
+        );
+        let (_dir, path) = project("#cave.dat,A1[m,0.0,0.0,0.0];\n", &dat);
+        let graph = Graph::from_compass_project(&path).unwrap();
+        assert_eq!(graph.num_edges(), 3);
+        assert_eq!(graph.weight[0], DEFAULT_LOCK_WEIGHT);
+        assert!(graph.weight[1] < 1e-3 * DEFAULT_LOCK_WEIGHT);
+
+        // The misclosure goes to the two other shots.
+        let solution = graph.solve(1000, 1e-12).unwrap();
+        let correction = |e: usize| {
+            let (u, v) = (graph.from[e], graph.to[e]);
+            (solution.x[v] - solution.x[u] - graph.dx[e])
+                .hypot(solution.y[v] - solution.y[u] - graph.dy[e])
+        };
+        assert!(correction(0) < 1e-3 * correction(1));
+        assert!(correction(1) > 0.01);
+    }
+
+    #[test]
+    fn weighs_shots_by_the_grade_of_their_survey() {
+        let shot = "A1 A2 10.00 90.00 0.00 0 0 0 0\n";
+        let weight = |comment: &str, options: &CompassImportOptions| {
+            let dat = survey(0.0, shot).replace("COMMENT:", &format!("COMMENT: {comment}"));
+            let (_dir, path) = project("#cave.dat;\n", &dat);
+            Graph::from_compass_project_with(&path, options)
+                .unwrap()
+                .weight[0]
+        };
+        let defaults = CompassImportOptions::default();
+        let length = 10.0 * FEET_TO_METERS;
+        let grade5 = weight("BCRA 5c", &defaults);
+        let grade2 = weight("UISv1 2-1-A", &defaults);
+        assert!(grade5 > grade2, "{grade5} <= {grade2}");
+        assert_close(grade5, SurveyGrade::Grade5.weight_model().weight(length));
+        assert_close(weight("bcra5", &defaults), grade5);
+
+        // Ungraded surveys keep the instrument model unless a default grade is given.
+        let instrument = WeightModel::Custom {
+            length_sigma: defaults.length_sigma,
+            azimuth_sigma_deg: defaults.angle_sigma_deg,
+            inclination_sigma_deg: defaults.angle_sigma_deg,
+        };
+        assert_close(weight("", &defaults), instrument.weight(length));
+        let graded = CompassImportOptions {
+            default_grade: Some(SurveyGrade::Grade2),
+            ..defaults
+        };
+        assert_close(weight("", &graded), grade2);
+        assert_close(weight("BCRA 5", &graded), grade5);
+
+        // A custom table and an explicit weight model are honoured, in that order.
+        let mut grade_sigmas = SURVEY_GRADE_SIGMAS;
+        grade_sigmas[4] = SURVEY_GRADE_SIGMAS[1];
+        let table = CompassImportOptions {
+            grade_sigmas,
+            ..defaults
+        };
+        assert_close(weight("BCRA 5", &table), grade2);
+        let preset = CompassImportOptions {
+            weight_model: Some(WeightModel::EqualWeights),
+            ..table
+        };
+        assert_eq!(weight("BCRA 5", &preset), 1.0);
+    }
+
+    #[test]
+    fn corrections_report_is_keyed_by_survey_and_shot_number() {
+        let dat = format!(
+            "{HEADER}DECLINATION: 0.00  FORMAT: DDDDLUDRADLN  CORRECTIONS: 0.00 0.00 0.00\n\n\
+             FROM TO LENGTH BEARING DIP LEFT UP DOWN RIGHT\n\n\
+             A1 A2 10.00 90.00 0.00 0 0 0 0\n\
+             A2 A9 10.00 0.00 0.00 0 0 0 0 #|X#\n\
+             A2 A3 10.00 0.00 0.00 0 0 0 0\n\u{c}\n"
+        );
+        let mak = "#cave.dat,A1[f,0.0,0.0,0.0],A3[f,10.5,10.0,0.0];\n";
+        let (_dir, path) = project(mak, &dat);
+        let options = CompassImportOptions::default();
+        let (graph, mut refs) = Graph::from_compass_project_with_refs(&path, &options).unwrap();
+        let survey = |shot, reversed| CompassShotRef {
+            survey: "A".into(),
+            shot,
+            reversed,
+        };
+        assert_eq!(refs, [survey(1, false), survey(3, false)]);
+
+        let solution = graph.solve(1000, 1e-12).unwrap();
+        let corrections = solution.shot_corrections(&graph);
+        let report = solution.to_compass_corrections(&graph, &refs).unwrap();
+        let rows: Vec<Vec<&str>> = report.lines().map(|l| l.split(',').collect()).collect();
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[0][..4], ["survey", "shot", "from", "to"]);
+        assert_eq!(rows[2][..4], ["A", "3", "A2", "A3"]);
+        let length = corrections[1].length / FEET_TO_METERS;
+        assert_eq!(rows[2][4], format!("{length:.3}"));
+        assert_eq!(rows[2][5], format!("{:.2}", corrections[1].azimuth_deg));
+
+        // A reversed edge is reported in the direction of the shot as written.
+        refs[1].reversed = true;
+        let report = solution.to_compass_corrections(&graph, &refs).unwrap();
+        let row: Vec<&str> = report.lines().nth(2).unwrap().split(',').collect();
+        let reversed = corrections[1].reversed();
+        assert_eq!(row[..4], ["A", "3", "A3", "A2"]);
+        assert_eq!(row[5], format!("{:.2}", reversed.azimuth_deg));
+        assert_eq!(row[8], format!("{:.2}", reversed.azimuth_correction_deg));
+    }
+
+    #[test]
+    fn resolves_windows_file_names_case_insensitively() {
+        let dat = survey(0.0, "A1 A2 10.00 90.00 0.00 0 0 0 0\n");
+        let (dir, _) = project("", &dat);
+        std::fs::create_dir(dir.path().join("data")).unwrap();
+        std::fs::rename(
+            dir.path().join("cave.dat"),
+            dir.path().join("data/Cave.DAT"),
+        )
+        .unwrap();
+        let path = dir.path().join("cave.mak");
+        std::fs::write(&path, "#data\\cave.dat;\n").unwrap();
+
+        assert_eq!(Graph::from_compass_project(&path).unwrap().num_edges(), 1);
+    }
+
+    #[test]
+    fn reports_the_position_of_malformed_shots() {
+        let dat = survey(
+            0.0,
+            "A1 A2 10.00 90.00 0.00 0 0 0 0\nA2 A3 1O.00 90.00 0.00\n",
+        );
+        let (_dir, path) = project("#cave.dat;\n", &dat);
+        match Graph::from_compass_project(&path) {
+            Err(ImportError::Parse {
+                line,
+                column,
+                message,
+                ..
+            }) => {
+                assert_eq!((line, column), (11, 7));
+                assert_eq!(message, "invalid length: 1O.00");
+            }
+            other => panic!("unexpected result: {other:?}"),
+        }
+
+        let dat = survey(0.0, "A1 A2 10.00\n");
+        let (_dir, path) = project("#cave.dat;\n", &dat);
+        let err = Graph::from_compass_project(&path).unwrap_err();
+        assert!(matches!(err, ImportError::Parse { line: 10, .. }), "{err}");
+    }
+
+    #[test]
+    fn reports_the_position_of_malformed_projects() {
+        let dat = survey(0.0, "A1 A2 10.00 90.00 0.00 0 0 0 0\n");
+        let (_dir, path) = project("#cave.dat,\n  A1[m,1.0,x,3.0];\n", &dat);
+        let err = Graph::from_compass_project(&path).unwrap_err();
+        assert!(
+            matches!(
+                err,
+                ImportError::Parse {
+                    line: 2,
+                    column: 12,
+                    ..
+                }
+            ),
+            "{err}"
+        );
+        assert!(err.to_string().contains("cave.mak:2:12"), "{err}");
+
+        let (_dir, path) = project("?cave.dat;\n", &dat);
+        let err = Graph::from_compass_project(&path).unwrap_err();
+        assert!(
+            matches!(
+                err,
+                ImportError::Parse {
+                    line: 1,
+                    column: 1,
+                    ..
+                }
+            ),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn reports_missing_files_as_io_errors() {
+        let (_dir, path) = project("#missing.dat;\n", "");
+        let err = Graph::from_compass_project(&path).unwrap_err();
+        assert!(matches!(err, ImportError::Io { .. }), "{err}");
+    }
+
+    #[test]
+    fn ffi_returns_a_handle_or_the_error_message() {
+        let dat = survey(0.0, "A1 A2 10.00 90.00 0.00 0 0 0 0\n");
+        let (dir, path) = project("#cave.dat;\n", &dat);
+        let c_path = CString::new(path.to_str().unwrap()).unwrap();
+        let handle = graph_from_compass_project(c_path.as_ptr(), std::ptr::null_mut(), 0);
+        assert!(!handle.is_null());
+        assert_eq!(crate::graph_num_edges(handle), 1);
+        crate::graph_free(handle);
+
+        let missing = CString::new(dir.path().join("none.mak").to_str().unwrap()).unwrap();
+        let mut buf = [0 as c_char; 256];
+        let handle = graph_from_compass_project(missing.as_ptr(), buf.as_mut_ptr(), buf.len());
+        assert!(handle.is_null());
+        // Safety: The library NUL-terminates the message.
+        let message = unsafe { CStr::from_ptr(buf.as_ptr()) }.to_string_lossy();
+        assert!(message.contains("none.mak"), "{message}");
+    }
+
+    #[test]
+    fn plt_round_trips_adjusted_coordinates_in_feet() {
+        let (graph, solution) = solved_square(true);
+        let options = PltOptions {
+            cave_name: "SECRET CAVE".to_string(),
+            ..PltOptions::default()
+        };
+        let text = solution.to_plt(&graph, &options).unwrap();
+
+        assert_round_trip(&text, &graph, &solution, 1.0 / FEET_TO_METERS);
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[1], "SSECRET CAVE");
+        assert_eq!(lines[2], "NADJUSTED");
+        assert!(lines[0].starts_with("Z ") && lines.last().unwrap().starts_with("X "));
+    }
+
+    #[test]
+    fn plt_writes_meters_and_synthetic_names() {
+        let (graph, solution) = solved_square(false);
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("adjusted.plt");
+        let options = PltOptions {
+            units: PltUnits::Meters,
+            survey_name: "LOOP".to_string(),
+            ..PltOptions::default()
+        };
+        solution.write_plt_with(&graph, &path, &options).unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+
+        assert_round_trip(&text, &graph, &solution, 1.0);
+        assert!(text.contains("\nNLOOP\n"));
+
+        // A graph in feet is written as it is in feet, and converted to meters.
+        let mut graph = graph;
+        graph.set_coordinate_system(CoordinateSystem {
+            units: LengthUnit::Feet,
+            ..CoordinateSystem::default()
+        });
+        let solution = graph.solve(60_000, 1e-12).unwrap();
+        let text = solution.to_plt(&graph, &options).unwrap();
+        assert_round_trip(&text, &graph, &solution, FEET_TO_METERS);
+        let text = solution.to_plt(&graph, &PltOptions::default()).unwrap();
+        assert_round_trip(&text, &graph, &solution, 1.0);
+    }
+
+    #[test]
+    fn exports_reject_arrays_of_the_wrong_length() {
+        let (graph, solution) = solved_square(true);
+        let plt = |graph: &Graph, solution: &Solution| {
+            solution.to_plt(graph, &PltOptions::default()).unwrap_err()
+        };
+        let mut short = solution.clone();
+        short.y.pop();
+        assert_eq!(
+            plt(&graph, &short).message,
+            "solution has 4 / 3 coordinates for 4 vertices"
+        );
+        let mut unnamed = graph.clone();
+        unnamed.names.as_mut().unwrap().pop();
+        assert_eq!(
+            plt(&unnamed, &solution).message,
+            "graph has 3 names for 4 vertices"
+        );
+        let mut flat = graph.clone();
+        flat.z.pop();
+        assert_eq!(
+            plt(&flat, &solution).message,
+            "graph has 3 heights for 4 vertices"
+        );
+        let mut dangling = graph.clone();
+        dangling.to[2] = 4;
+        let err = plt(&dangling, &solution);
+        assert_eq!(err.code, COMPASS_ERR_INVALID_ARGUMENT);
+        assert_eq!(err.message, "edge 2 has an endpoint out of range");
+
+        // One shot reference per edge, no more and no less.
+        let shot = |shot| CompassShotRef {
+            survey: "A".into(),
+            shot,
+            reversed: false,
+        };
+        let refs: Vec<CompassShotRef> = (1..=5).map(shot).collect();
+        let err = solution.to_compass_corrections(&graph, &refs).unwrap_err();
+        assert_eq!(
+            err.message,
+            "5 shot references and 4 / 4 / 4 observations for 4 edges"
+        );
+        assert!(solution.to_compass_corrections(&graph, &refs[..3]).is_err());
+        assert!(solution.to_compass_corrections(&graph, &refs[..4]).is_ok());
+        let dir = tempfile::tempdir().unwrap();
+        let err = (solution.write_compass_corrections(&graph, &refs, dir.path().join("c.csv")))
+            .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn plt_bounds_cover_the_adjusted_stations() {
+        let (graph, solution) = solved_square(true);
+        let text = solution.to_plt(&graph, &PltOptions::default()).unwrap();
+        let bounds: Vec<f64> = (text.lines().next().unwrap().split_whitespace().skip(1))
+            .map(|t| t.parse().unwrap())
+            .collect();
+        let scale = 1.0 / FEET_TO_METERS;
+        let north = solution.y.iter().map(|y| y * scale);
+        let (min, max) = (
+            north.clone().fold(f64::MAX, f64::min),
+            north.fold(f64::MIN, f64::max),
+        );
+        assert!((bounds[0] - min).abs() <= 5e-4 && (bounds[1] - max).abs() <= 5e-4);
+        assert!((bounds[4] + 2.0 * scale).abs() <= 5e-4);
+    }
+}
@@ -0,0 +1,1086 @@
+//! Hard linear equality constraints `C x = d` on the adjusted coordinates, for relations no
+//! edge can express: stations on a straight baseline, a centroid held in place...
+//!
+//! Column `2 v` of `C` is the X coordinate of vertex `v` and column `2 v + 1` its Y
+//! coordinate, so a constraint may tie both axes. Terms on fixed vertices are constants and
+//! move to `d`. The rows are first brought to reduced row echelon form by Gauss-Jordan
+//! elimination, which drops redundant rows and detects a row that contradicts the rows
+//! before it.
+//!
+//! Up to [`ELIMINATION_LIMIT`] independent constraints are solved by null-space elimination:
+//! the pivot coordinate of each reduced row is expressed in the remaining ones, CG solves
+//! the normal equations of the remaining coordinates, and the pivots are computed back from
+//! their rows, so the constraints hold to machine precision. Larger sets solve the KKT
+//! saddle-point system `[N Cᵀ; C 0] [x; λ] = [b; d]` by MINRES, where they hold to within
+//! the solver tolerance.
+//!
+//! The translation of a free network (see [`crate::SolveStats::free_networks`]) along an
+//! axis is left free unless a constraint fixes it; the network then keeps the centroid of
+//! its initial guess along that axis.
+
+use crate::amg::{RowBuilder, multiply, transpose};
+use crate::spmv::Spmv;
+use crate::{
+    COMPASS_ERR_CANCELLED, COMPASS_ERR_INVALID_ARGUMENT, COMPASS_ERR_PANIC, COMPASS_OK,
+    Convergence, Graph, GraphContext, NormalEquations, NullSpace, PreconditionerOp, Solution,
+    SolveError, SolveOptions, SolveStats, cancel_flag, completed, inverse_diagonal, read_options,
+    solve_cg, write_stats,
+};
+use nalgebra::DVector;
+use nalgebra_sparse::CsrMatrix;
+use std::collections::BTreeMap;
+use std::ffi::{c_double, c_int};
+use std::slice;
+
+/// Largest number of independent constraints solved by elimination rather than MINRES.
+pub const ELIMINATION_LIMIT: usize = 64;
+
+/// Relative size below which a reduced constraint coefficient or right-hand side counts as
+/// zero.
+const CONSTRAINT_TOLERANCE: f64 = 1e-10;
+
+/// Linear equality constraints `C x = d`, `C` in triplet (COO) form.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Constraints {
+    /// Right-hand side `d`, one value per row.
+    pub values: Vec<f64>,
+    /// Entries of `C` as `(row, column, coefficient)`. Entries at the same position add up.
+    pub terms: Vec<(usize, usize, f64)>,
+}
+
+impl Constraints {
+    /// Appends the row `sum(coefficient * coordinate) = value` over `(column, coefficient)`
+    /// terms and returns its index.
+    pub fn push(&mut self, terms: &[(usize, f64)], value: f64) -> usize {
+        let row = self.values.len();
+        self.values.push(value);
+        self.terms.extend(
+            terms
+                .iter()
+                .map(|&(column, coefficient)| (row, column, coefficient)),
+        );
+        row
+    }
+
+    /// Appends the two rows `x_u - x_v = 0` and `y_u - y_v = 0`, tying vertex `u` to vertex
+    /// `v`, e.g. one station surveyed under two names, see
+    /// [`Solution::find_near_duplicates`].
+    pub fn tie(&mut self, u: usize, v: usize) {
+        for axis in 0..2 {
+            self.push(&[(2 * u + axis, 1.0), (2 * v + axis, -1.0)], 0.0);
+        }
+    }
+
+    /// Vertex pairs `(u, v)`, `u < v`, tied on both axes by rows of the form
+    /// [`Constraints::tie`] writes, in ascending order. Other rows are ignored.
+    pub fn ties(&self) -> Vec<(usize, usize)> {
+        let mut rows: BTreeMap<usize, Vec<(usize, f64)>> = BTreeMap::new();
+        for &(row, column, coefficient) in &self.terms {
+            rows.entry(row).or_default().push((column, coefficient));
+        }
+        let mut axes: BTreeMap<(usize, usize), [bool; 2]> = BTreeMap::new();
+        for (row, terms) in rows {
+            let &[(a, ca), (b, cb)] = terms.as_slice() else {
+                continue;
+            };
+            if self.values.get(row) != Some(&0.0)
+                || ca == 0.0
+                || ca != -cb
+                || a % 2 != b % 2
+                || a == b
+            {
+                continue;
+            }
+            axes.entry((a.min(b) / 2, a.max(b) / 2)).or_default()[a % 2] = true;
+        }
+        axes.into_iter()
+            .filter(|(_, axes)| axes[0] && axes[1])
+            .map(|(pair, _)| pair)
+            .collect()
+    }
+}
+
+/// Error of a constrained solve.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConstraintError {
+    /// Constraint `row` contradicts the rows before it: no coordinates satisfy them all.
+    Inconsistent { row: usize },
+    /// Invalid constraint terms, or a failed solve.
+    Solve(SolveError),
+}
+
+impl std::fmt::Display for ConstraintError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConstraintError::Inconsistent { row } => {
+                write!(f, "constraint {row} contradicts the constraints before it")
+            }
+            ConstraintError::Solve(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for ConstraintError {}
+
+impl From<SolveError> for ConstraintError {
+    fn from(err: SolveError) -> Self {
+        ConstraintError::Solve(err)
+    }
+}
+
+impl ConstraintError {
+    /// FFI error code of the error.
+    pub fn code(&self) -> c_int {
+        match self {
+            ConstraintError::Inconsistent { .. } => COMPASS_ERR_INVALID_ARGUMENT,
+            ConstraintError::Solve(err) => err.code,
+        }
+    }
+}
+
+impl Graph {
+    /// Solves the horizontal adjustment subject to `constraints`, leaving the graph
+    /// untouched. CG runs with Jacobi preconditioning.
+    pub fn solve_constrained(
+        &self,
+        constraints: &Constraints,
+        iterations: usize,
+        tolerance: f64,
+    ) -> Result<Solution, ConstraintError> {
+        let system = self.normal_equations(&[])?;
+        solve(
+            self,
+            &system,
+            (&self.x, &self.y),
+            constraints,
+            iterations,
+            tolerance,
+            &|| false,
+        )
+    }
+}
+
+impl GraphContext {
+    /// [`Graph::solve_constrained`] over the enabled edges and the cached normal equations,
+    /// starting from the current [`GraphContext::coordinates`]. Stores the solution.
+    pub fn solve_constrained(
+        &mut self,
+        constraints: &Constraints,
+        iterations: usize,
+        tolerance: f64,
+    ) -> Result<&Solution, ConstraintError> {
+        solve_context(self, constraints, iterations, tolerance, &|| false)
+    }
+}
+
+fn solve_context<'a>(
+    ctx: &'a mut GraphContext,
+    constraints: &Constraints,
+    iterations: usize,
+    tolerance: f64,
+    cancelled: &(dyn Fn() -> bool + Sync),
+) -> Result<&'a Solution, ConstraintError> {
+    if ctx.system.is_none() {
+        ctx.system = Some(ctx.assemble()?);
+    }
+    let Some(system) = &ctx.system else {
+        unreachable!("assembled above");
+    };
+    let (x0, y0) = match &ctx.solution {
+        Some(solution) => (&solution.x, &solution.y),
+        None => (&ctx.graph.x, &ctx.graph.y),
+    };
+    let mut solution = solve(
+        &ctx.graph,
+        system,
+        (x0, y0),
+        constraints,
+        iterations,
+        tolerance,
+        cancelled,
+    )?;
+    solution.stats.disabled_edges = ctx.edge_enabled.iter().filter(|&&e| !e).count() as c_int;
+    ctx.record_locks(&mut solution);
+    Ok(ctx.store(solution))
+}
+
+/// Sparse constraint row: coefficient by unknown.
+type Row = BTreeMap<usize, f64>;
+
+/// Constraint rows in reduced row echelon form over the unknowns of the normal equations,
+/// X coordinates first and then Y coordinates.
+struct Reduced {
+    /// Pivot unknown of each row. Its coefficient is 1 and no other row has it.
+    pivots: Vec<usize>,
+    /// Coefficients of each row on the non-pivot unknowns.
+    rows: Vec<Row>,
+    /// Right-hand side of each row.
+    values: Vec<f64>,
+}
+
+/// Solves `system`, the normal equations of `graph`, subject to `constraints`.
+fn solve(
+    graph: &Graph,
+    system: &NormalEquations,
+    (x0, y0): (&[f64], &[f64]),
+    constraints: &Constraints,
+    iterations: usize,
+    tolerance: f64,
+    cancelled: &(dyn Fn() -> bool + Sync),
+) -> Result<Solution, ConstraintError> {
+    let n = system.size();
+    let (rows, values) = substitute(graph, system, constraints)?;
+    let reduced = reduce(&rows, &values)?;
+
+    let mut z0 = DVector::zeros(2 * n);
+    for (v, index) in system.mapping.iter().enumerate() {
+        if let Some(i) = *index {
+            z0[i] = x0[v];
+            z0[n + i] = y0[v];
+        }
+    }
+    let translations = free_translations(system, &rows);
+    let (mut z, convergence) = match reduced.pivots.len() {
+        0..=ELIMINATION_LIMIT => eliminate(
+            system,
+            &reduced,
+            &translations,
+            &z0,
+            iterations,
+            tolerance,
+            cancelled,
+        ),
+        _ => kkt(system, &reduced, &z0, iterations, tolerance, cancelled),
+    }
+    .ok_or_else(|| SolveError {
+        code: COMPASS_ERR_CANCELLED,
+        message: "solve cancelled".to_string(),
+    })?;
+
+    // Free translations keep the centroid of the initial guess; moving along them changes
+    // neither the residuals nor `C x`.
+    for (_, unknowns) in &translations {
+        let mean =
+            |z: &DVector<f64>| unknowns.iter().map(|&i| z[i]).sum::<f64>() / unknowns.len() as f64;
+        let shift = mean(&z0) - mean(&z);
+        for &i in unknowns {
+            z[i] += shift;
+        }
+    }
+
+    let mut solution = Solution {
+        x: graph.x.clone(),
+        y: graph.y.clone(),
+        stats: SolveStats {
+            free_vertices: n as c_int,
+            free_networks: (0..system.null_space.dimension())
+                .filter(|&k| translations.iter().any(|&(network, _)| network == k))
+                .count() as c_int,
+            trivial: graph.trivial(&[]).flags(),
+            ..Default::default()
+        },
+        trivial: graph.trivial(&[]),
+        coordinate_system: graph.coordinate_system,
+        warnings: system.edge_warnings.clone(),
+    };
+    for (v, index) in system.mapping.iter().enumerate() {
+        if let Some(i) = *index {
+            solution.x[v] = z[i];
+            solution.y[v] = z[n + i];
+        }
+    }
+    convergence.record(&mut solution.stats);
+    Ok(solution)
+}
+
+/// The constraint rows over the unknowns of `system`, with the terms on fixed vertices moved
+/// to the right-hand side.
+fn substitute(
+    graph: &Graph,
+    system: &NormalEquations,
+    constraints: &Constraints,
+) -> Result<(Vec<Row>, Vec<f64>), SolveError> {
+    let n = system.size();
+    let mut rows = vec![BTreeMap::new(); constraints.values.len()];
+    let mut values = constraints.values.clone();
+    for (t, &(row, column, coefficient)) in constraints.terms.iter().enumerate() {
+        let vertex = column / 2;
+        if row >= rows.len() || vertex >= graph.num_vertices() {
+            return Err(SolveError {
+                code: COMPASS_ERR_INVALID_ARGUMENT,
+                message: format!("constraint term {t} out of range"),
+            });
+        }
+        let coordinates = if column % 2 == 0 { &graph.x } else { &graph.y };
+        match system.mapping[vertex] {
+            Some(i) => *rows[row].entry(i + (column % 2) * n).or_insert(0.0) += coefficient,
+            None => values[row] -= coefficient * coordinates[vertex],
+        }
+    }
+    Ok((rows, values))
+}
+
+/// Gauss-Jordan elimination of the constraint rows, in order. A row that reduces to zero is
+/// redundant if its right-hand side does too, inconsistent otherwise.
+fn reduce(rows: &[Row], values: &[f64]) -> Result<Reduced, ConstraintError> {
+    let mut reduced = Reduced {
+        pivots: Vec::new(),
+        rows: Vec::new(),
+        values: Vec::new(),
+    };
+    // Magnitude of the coefficients and of the right-hand side of each reduced row, which
+    // sets the scale of its rounding errors.
+    let mut scales: Vec<(f64, f64)> = Vec::new();
+    for (r, (row, &value)) in rows.iter().zip(values).enumerate() {
+        let mut row = row.clone();
+        let mut value = value;
+        let mut scale = (
+            row.values().fold(0.0, |m: f64, c| m.max(c.abs())),
+            value.abs(),
+        );
+        for (k, &pivot) in reduced.pivots.iter().enumerate() {
+            let Some(factor) = row.remove(&pivot) else {
+                continue;
+            };
+            for (&column, &coefficient) in &reduced.rows[k] {
+                *row.entry(column).or_insert(0.0) -= factor * coefficient;
+            }
+            value -= factor * reduced.values[k];
+            scale.0 = scale.0.max(factor.abs() * scales[k].0);
+            scale.1 += factor.abs() * scales[k].1;
+        }
+        row.retain(|_, c| c.abs() > CONSTRAINT_TOLERANCE * scale.0);
+
+        let Some((&pivot, &coefficient)) = row
+            .iter()
+            .max_by(|a, b| a.1.abs().total_cmp(&b.1.abs()).then(b.0.cmp(a.0)))
+        else {
+            if value.abs() > CONSTRAINT_TOLERANCE * scale.1.max(f64::MIN_POSITIVE) {
+                return Err(ConstraintError::Inconsistent { row: r });
+            }
+            continue;
+        };
+        row.remove(&pivot);
+        for c in row.values_mut() {
+            *c /= coefficient;
+        }
+        value /= coefficient;
+        let scale = (scale.0 / coefficient.abs(), scale.1 / coefficient.abs());
+
+        // Keeps the earlier rows free of the new pivot.
+        let earlier = reduced.rows.iter_mut().zip(&mut reduced.values);
+        for ((other, other_value), other_scale) in earlier.zip(&mut scales) {
+            let Some(factor) = other.remove(&pivot) else {
+                continue;
+            };
+            for (&column, &c) in &row {
+                *other.entry(column).or_insert(0.0) -= factor * c;
+            }
+            *other_value -= factor * value;
+            other_scale.0 = other_scale.0.max(factor.abs() * scale.0);
+            other_scale.1 += factor.abs() * scale.1;
+            other.retain(|_, c| c.abs() > CONSTRAINT_TOLERANCE * other_scale.0);
+        }
+        reduced.pivots.push(pivot);
+        reduced.rows.push(row);
+        reduced.values.push(value);
+        scales.push(scale);
+    }
+    Ok(reduced)
+}
+
+/// The translations of the free networks along one axis that the constraints leave free,
+/// i.e. over whose unknowns every constraint row sums to zero, as the index of the network
+/// and the unknowns.
+fn free_translations(system: &NormalEquations, rows: &[Row]) -> Vec<(usize, Vec<usize>)> {
+    let n = system.size();
+    let mut network = vec![usize::MAX; n];
+    for (k, component) in system.null_space.components.iter().enumerate() {
+        for &i in component {
+            network[i] = k;
+        }
+    }
+    // Translation `2 k + axis` is fixed once a row has a non-zero sum over its unknowns.
+    let mut fixed = vec![false; 2 * system.null_space.dimension()];
+    for row in rows {
+        let mut sums: BTreeMap<usize, (f64, f64)> = BTreeMap::new();
+        for (&column, &coefficient) in row {
+            let (i, axis) = (column % n, column / n);
+            if network[i] != usize::MAX {
+                let sum = sums.entry(2 * network[i] + axis).or_insert((0.0, 0.0));
+                sum.0 += coefficient;
+                sum.1 = sum.1.max(coefficient.abs());
+            }
+        }
+        for (translation, (sum, scale)) in sums {
+            fixed[translation] |= sum.abs() > CONSTRAINT_TOLERANCE * scale;
+        }
+    }
+    let mut translations = Vec::new();
+    for (k, component) in system.null_space.components.iter().enumerate() {
+        for axis in 0..2 {
+            if !fixed[2 * k + axis] {
+                translations.push((k, component.iter().map(|&i| i + axis * n).collect()));
+            }
+        }
+    }
+    translations
+}
+
+/// Null-space elimination: with `z = T u + t` spanning the solutions of the constraints,
+/// solves `Tᵀ B T u = Tᵀ (b - B t)`, `B` holding the normal matrix once per axis.
+fn eliminate(
+    system: &NormalEquations,
+    reduced: &Reduced,
+    translations: &[(usize, Vec<usize>)],
+    z0: &DVector<f64>,
+    iterations: usize,
+    tolerance: f64,
+    cancelled: &(dyn Fn() -> bool + Sync),
+) -> Option<(DVector<f64>, Convergence)> {
+    let size = z0.len();
+    // Index of each non-pivot unknown among the reduced unknowns `u`.
+    let mut reduced_index = vec![Some(0); size];
+    for &pivot in &reduced.pivots {
+        reduced_index[pivot] = None;
+    }
+    let mut m = 0;
+    for index in reduced_index.iter_mut().flatten() {
+        *index = m;
+        m += 1;
+    }
+    let mut pivot_row = vec![usize::MAX; size];
+    for (k, &pivot) in reduced.pivots.iter().enumerate() {
+        pivot_row[pivot] = k;
+    }
+
+    let mut t = DVector::zeros(size);
+    let mut builder = RowBuilder::new(m);
+    for i in 0..size {
+        match reduced_index[i] {
+            Some(u) => builder.add(u, 1.0),
+            None => {
+                let k = pivot_row[i];
+                t[i] = reduced.values[k];
+                for (&column, &coefficient) in &reduced.rows[k] {
+                    if let Some(u) = reduced_index[column] {
+                        builder.add(u, -coefficient);
+                    }
+                }
+            }
+        }
+        builder.end_row();
+    }
+    let t_matrix = builder.build();
+    let b = both_axes(&system.matrix);
+    let a = multiply(&transpose(&t_matrix), &multiply(&b, &t_matrix));
+
+    let mut bt = DVector::zeros(size);
+    Spmv::new(&b).apply(t.as_slice(), bt.as_mut_slice());
+    let mut rhs = both_sides(system, &[]);
+    rhs -= &bt;
+    let mut rhs_u = DVector::zeros(m);
+    Spmv::new(&transpose(&t_matrix)).apply(rhs.as_slice(), rhs_u.as_mut_slice());
+    let mut u0 = DVector::zeros(m);
+    for i in 0..size {
+        if let Some(u) = reduced_index[i] {
+            u0[u] = z0[i];
+        }
+    }
+
+    // The free translations involve no pivot, so they map onto the reduced unknowns as is.
+    let null_space = NullSpace {
+        components: translations
+            .iter()
+            .map(|(_, unknowns)| unknowns.iter().filter_map(|&i| reduced_index[i]).collect())
+            .collect(),
+    };
+    let jacobi = PreconditionerOp::Jacobi(inverse_diagonal(&a));
+    let (u, convergence) = completed(solve_cg(
+        &a,
+        &rhs_u,
+        &u0,
+        Some(&jacobi),
+        &null_space,
+        iterations,
+        tolerance.into(),
+        cancelled,
+    ))?;
+
+    let mut z = DVector::zeros(size);
+    for i in 0..size {
+        if let Some(index) = reduced_index[i] {
+            z[i] = u[index];
+        }
+    }
+    for (k, &pivot) in reduced.pivots.iter().enumerate() {
+        z[pivot] = reduced.values[k]
+            - reduced.rows[k]
+                .iter()
+                .map(|(&column, &coefficient)| coefficient * z[column])
+                .sum::<f64>();
+    }
+    Some((z, convergence))
+}
+
+/// Solves the KKT system of the normal equations and the reduced constraints by MINRES,
+/// preconditioned by the block diagonal `diag(B)`, `diag(C diag(B)⁻¹ Cᵀ)`.
+fn kkt(
+    system: &NormalEquations,
+    reduced: &Reduced,
+    z0: &DVector<f64>,
+    iterations: usize,
+    tolerance: f64,
+    cancelled: &(dyn Fn() -> bool + Sync),
+) -> Option<(DVector<f64>, Convergence)> {
+    let size = z0.len();
+    let k = reduced.pivots.len();
+    let b = both_axes(&system.matrix);
+    let inv_diag = inverse_diagonal(&b);
+
+    // Row `size + r` of the KKT matrix is constraint `r`, also stored as column `size + r`.
+    let mut columns = vec![Vec::new(); size];
+    for (r, row) in reduced.rows.iter().enumerate() {
+        columns[reduced.pivots[r]].push((size + r, 1.0));
+        for (&column, &coefficient) in row {
+            columns[column].push((size + r, coefficient));
+        }
+    }
+    let mut builder = RowBuilder::new(size + k);
+    let (offsets, indices, entries) = (b.row_offsets(), b.col_indices(), b.values());
+    for i in 0..size {
+        for e in offsets[i]..offsets[i + 1] {
+            builder.add(indices[e], entries[e]);
+        }
+        for &(column, coefficient) in &columns[i] {
+            builder.add(column, coefficient);
+        }
+        builder.end_row();
+    }
+    let mut preconditioner = inv_diag.iter().copied().collect::<Vec<_>>();
+    for (r, row) in reduced.rows.iter().enumerate() {
+        let pivot = reduced.pivots[r];
+        builder.add(pivot, 1.0);
+        let mut schur = inv_diag[pivot];
+        for (&column, &coefficient) in row {
+            builder.add(column, coefficient);
+            schur += coefficient * coefficient * inv_diag[column];
+        }
+        builder.end_row();
+        preconditioner.push(if schur > 0.0 { 1.0 / schur } else { 1.0 });
+    }
+    let matrix = builder.build();
+
+    let rhs = both_sides(system, &reduced.values);
+    let x0 = DVector::from_fn(size + k, |i, _| if i < size { z0[i] } else { 0.0 });
+    let preconditioner = DVector::from_vec(preconditioner);
+    let (x, convergence) = minres(
+        &matrix,
+        &rhs,
+        &x0,
+        &preconditioner,
+        iterations,
+        tolerance,
+        cancelled,
+    )?;
+    Some((DVector::from_fn(size, |i, _| x[i]), convergence))
+}
+
+/// The right-hand sides of the X and of the Y unknowns, followed by `rest`.
+fn both_sides(system: &NormalEquations, rest: &[f64]) -> DVector<f64> {
+    let sides = system.bx.iter().chain(system.by.iter()).chain(rest);
+    DVector::from_vec(sides.copied().collect())
+}
+
+/// The block diagonal matrix holding `a` once for the X and once for the Y unknowns.
+fn both_axes(a: &CsrMatrix<f64>) -> CsrMatrix<f64> {
+    let n = a.nrows();
+    let (offsets, columns, values) = (a.row_offsets(), a.col_indices(), a.values());
+    let mut builder = RowBuilder::new(2 * n);
+    for axis in 0..2 {
+        for i in 0..n {
+            for k in offsets[i]..offsets[i + 1] {
+                builder.add(columns[k] + axis * n, values[k]);
+            }
+            builder.end_row();
+        }
+    }
+    builder.build()
+}
+
+/// Solves the symmetric, possibly indefinite system `a x = b` by MINRES (Paige and
+/// Saunders), preconditioned by the positive diagonal `inv_diag`. Stops once the
+/// preconditioned residual estimate falls below `tol`; the reported residual norm is that
+/// of the returned solution. Returns `None` if `cancelled` fired.
+fn minres(
+    a: &CsrMatrix<f64>,
+    b: &DVector<f64>,
+    x0: &DVector<f64>,
+    inv_diag: &DVector<f64>,
+    max_iter: usize,
+    tol: f64,
+    cancelled: &(dyn Fn() -> bool + Sync),
+) -> Option<(DVector<f64>, Convergence)> {
+    let len = b.len();
+    let mut x = x0.clone();
+    let mut r1 = b - a * &x;
+    let mut y = r1.component_mul(inv_diag);
+    let mut beta = r1.dot(&y).sqrt();
+    let mut convergence = Convergence::default();
+
+    let mut r2 = r1.clone();
+    let (mut old_beta, mut delta_bar, mut epsilon, mut phi_bar) = (0.0, 0.0, 0.0, beta);
+    let (mut cs, mut sn) = (-1.0, 0.0);
+    let mut w = DVector::zeros(len);
+    let mut w_prev = DVector::zeros(len);
+    let mut v = DVector::zeros(len);
+    let spmv = Spmv::new(a);
+    for iteration in 0..max_iter {
+        if phi_bar < tol || beta == 0.0 {
+            break;
+        }
+        if cancelled() {
+            return None;
+        }
+        // Lanczos step on the preconditioned operator.
+        v.copy_from(&y);
+        v.scale_mut(1.0 / beta);
+        spmv.apply(v.as_slice(), y.as_mut_slice());
+        if iteration > 0 {
+            y.axpy(-beta / old_beta, &r1, 1.0);
+        }
+        let alpha = v.dot(&y);
+        y.axpy(-alpha / beta, &r2, 1.0);
+        std::mem::swap(&mut r1, &mut r2);
+        r2.copy_from(&y);
+        y = r2.component_mul(inv_diag);
+        old_beta = beta;
+        beta = r2.dot(&y).max(0.0).sqrt();
+
+        // Givens rotation of the tridiagonal system.
+        let old_epsilon = epsilon;
+        let delta = cs * delta_bar + sn * alpha;
+        let gamma_bar = sn * delta_bar - cs * alpha;
+        epsilon = sn * beta;
+        delta_bar = -cs * beta;
+        let gamma = gamma_bar.hypot(beta).max(f64::EPSILON);
+        cs = gamma_bar / gamma;
+        sn = beta / gamma;
+        let phi = cs * phi_bar;
+        phi_bar *= sn;
+
+        // w = (v - old_epsilon * w_prev - delta * w) / gamma, keeping the last two.
+        w_prev.scale_mut(-old_epsilon);
+        w_prev.axpy(-delta, &w, 1.0);
+        w_prev += &v;
+        w_prev.scale_mut(1.0 / gamma);
+        std::mem::swap(&mut w, &mut w_prev);
+        x.axpy(phi, &w, 1.0);
+        convergence.iterations += 1;
+    }
+    convergence.residual_norm = (b - a * &x).norm();
+    // The iteration only tracks an estimate of the preconditioned residual.
+    convergence.recursive_residual_norm = convergence.residual_norm;
+    convergence.converged = phi_bar < tol || beta == 0.0;
+    Some((x, convergence))
+}
+
+/// Solves the graph behind `handle` subject to the linear constraints `C x = d` and stores
+/// the solution in the handle, see [`GraphContext::solve_constrained`].
+///
+/// `C` has `num_constraints` rows and is given as `num_terms` triplets of `rows`, `columns`
+/// and `coefficients`; column `2 v` is the X coordinate of vertex `v` and `2 v + 1` its Y
+/// coordinate. `values` holds `d`. Only `iterations`, `tolerance` and `cancel` of `options`
+/// are used, once its `preset` is expanded; `out_row` and `stats` may be null. `options` and
+/// `stats` are read and written up to their `struct_size`, as by [`crate::graph_solve`].
+///
+/// # Returns
+///
+/// * [`COMPASS_OK`] on success.
+/// * [`COMPASS_ERR_INVALID_ARGUMENT`] for invalid arguments, `options` older than the first
+///   release of the struct or naming an unknown preset included, or inconsistent constraints;
+///   in the latter case `out_row` receives the first row that contradicts the rows before
+///   it, otherwise -1.
+/// * [`COMPASS_ERR_CANCELLED`] if the solve was cancelled.
+#[unsafe(no_mangle)]
+#[allow(clippy::too_many_arguments)]
+pub extern "C" fn graph_solve_constrained(
+    handle: *mut GraphContext,
+    options: *const SolveOptions,
+    num_constraints: c_int,
+    values: *const c_double,
+    num_terms: c_int,
+    rows: *const c_int,
+    columns: *const c_int,
+    coefficients: *const c_double,
+    out_row: *mut c_int,
+    stats: *mut SolveStats,
+) -> c_int {
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        if !out_row.is_null() {
+            unsafe { *out_row = -1 };
+        }
+        let (Some(ctx), Some(options)) = (unsafe { handle.as_mut() }, read_options(options)) else {
+            return COMPASS_ERR_INVALID_ARGUMENT;
+        };
+        if num_constraints < 0 || num_terms < 0 {
+            return COMPASS_ERR_INVALID_ARGUMENT;
+        }
+        let (num_constraints, num_terms) = (num_constraints as usize, num_terms as usize);
+        if (num_constraints > 0 && values.is_null())
+            || (num_terms > 0 && (rows.is_null() || columns.is_null() || coefficients.is_null()))
+        {
+            return COMPASS_ERR_INVALID_ARGUMENT;
+        }
+        // Safety: The caller guarantees `num_constraints` values and `num_terms` triplets,
+        // and the pointers were checked above when the lengths are not zero.
+        let read = |ptr: *const c_int| match num_terms {
+            0 => &[][..],
+            len => unsafe { slice::from_raw_parts(ptr, len) },
+        };
+        let (rows, columns) = (read(rows), read(columns));
+        if rows.iter().chain(columns).any(|&i| i < 0) {
+            return COMPASS_ERR_INVALID_ARGUMENT;
+        }
+        let mut constraints = Constraints::default();
+        if num_constraints > 0 {
+            constraints.values = unsafe { slice::from_raw_parts(values, num_constraints) }.to_vec();
+        }
+        if num_terms > 0 {
+            let coefficients = unsafe { slice::from_raw_parts(coefficients, num_terms) };
+            constraints.terms = (0..num_terms)
+                .map(|t| (rows[t] as usize, columns[t] as usize, coefficients[t]))
+                .collect();
+        }
+
+        let cancelled = cancel_flag(&options);
+        let iterations = options.iterations.max(0) as usize;
+        match solve_context(ctx, &constraints, iterations, options.tolerance, &cancelled) {
+            Ok(solution) => {
+                write_stats(stats, &solution.stats);
+                COMPASS_OK
+            }
+            Err(err) => {
+                if let ConstraintError::Inconsistent { row } = err
+                    && !out_row.is_null()
+                {
+                    unsafe { *out_row = row as c_int };
+                }
+                err.code()
+            }
+        }
+    }));
+
+    result.unwrap_or_else(|_| {
+        eprintln!("Panic caught in graph_solve_constrained");
+        COMPASS_ERR_PANIC
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{COMPASS_STOP_CONVERGED, Preset, graph_free};
+
+    /// A `side` x `side` grid of 10 m shots with small misclosures, fixed at its corner.
+    fn grid(side: usize) -> Graph {
+        let noise = |k: usize| ((k * 7919) % 13) as f64 * 0.01 - 0.06;
+        let mut graph = Graph::default();
+        for i in 0..side * side {
+            let (row, col) = (i / side, i % side);
+            graph.add_vertex(10.0 * col as f64, 10.0 * row as f64, 0.0, i == 0);
+        }
+        for i in 0..side * side {
+            let (row, col) = (i / side, i % side);
+            if col + 1 < side {
+                graph.add_edge(i, i + 1, 10.0 + noise(2 * i), noise(2 * i + 1), 0.0, 1.0);
+            }
+            if row + 1 < side {
+                graph.add_edge(i, i + side, noise(3 * i), 10.0 + noise(3 * i + 1), 0.0, 1.0);
+            }
+        }
+        graph
+    }
+
+    /// `C x - d` for each row of `constraints` at `solution`.
+    fn residuals(constraints: &Constraints, solution: &Solution) -> Vec<f64> {
+        let mut residuals: Vec<f64> = constraints.values.iter().map(|d| -d).collect();
+        for &(row, column, coefficient) in &constraints.terms {
+            let coordinates = if column % 2 == 0 {
+                &solution.x
+            } else {
+                &solution.y
+            };
+            residuals[row] += coefficient * coordinates[column / 2];
+        }
+        residuals
+    }
+
+    /// Constraints holding `vertices` at their grid positions, and the grid with those
+    /// vertices fixed there instead, which has the same adjustment.
+    fn pinned(side: usize, vertices: &[usize]) -> (Graph, Constraints, Graph) {
+        let graph = grid(side);
+        let mut constraints = Constraints::default();
+        let mut fixed = graph.clone();
+        for &v in vertices {
+            let (x, y) = (10.0 * (v % side) as f64, 10.0 * (v / side) as f64);
+            constraints.push(&[(2 * v, 1.0)], x);
+            constraints.push(&[(2 * v + 1, 1.0)], y);
+            fixed.x[v] = x;
+            fixed.y[v] = y;
+            fixed.fixed[v] = true;
+        }
+        (graph, constraints, fixed)
+    }
+
+    #[test]
+    fn collinear_stations_stay_on_their_baseline() {
+        // Stations 5, 10 and 15 on the diagonal of the grid: the direction from 5 to each of
+        // the others is (1, 1), so their X and Y offsets from 5 agree.
+        let graph = grid(4);
+        let mut constraints = Constraints::default();
+        for v in [10, 15] {
+            constraints.push(
+                &[(2 * v, 1.0), (10, -1.0), (2 * v + 1, -1.0), (11, 1.0)],
+                0.0,
+            );
+        }
+        let solution = graph.solve_constrained(&constraints, 1000, 1e-12).unwrap();
+
+        for residual in residuals(&constraints, &solution) {
+            assert!(residual.abs() < 1e-12, "C x - d = {residual}");
+        }
+        let free = graph
+            .solve_with(1000, 1e-12, Default::default(), Default::default())
+            .unwrap();
+        let offset = |s: &Solution, v: usize| (s.x[v] - s.x[5], s.y[v] - s.y[5]);
+        let (dx, dy) = offset(&free, 15);
+        assert!(
+            (dx - dy).abs() > 1e-3,
+            "unconstrained stations already collinear"
+        );
+        assert!(solution.stats.iterations > 0);
+    }
+
+    #[test]
+    fn redundant_rows_are_dropped_and_contradictions_reported() {
+        let graph = grid(3);
+        let mut constraints = Constraints::default();
+        constraints.push(&[(8, 1.0)], 10.0);
+        constraints.push(&[(8, 2.0)], 20.0);
+        let solution = graph.solve_constrained(&constraints, 1000, 1e-12).unwrap();
+        assert!((solution.x[4] - 10.0).abs() < 1e-12);
+
+        constraints.push(&[(8, 1.0)], 10.5);
+        assert_eq!(
+            graph
+                .solve_constrained(&constraints, 1000, 1e-12)
+                .unwrap_err(),
+            ConstraintError::Inconsistent { row: 2 }
+        );
+
+        // Vertex 0 is fixed at the origin, so its coordinate is a constant.
+        let mut constraints = Constraints::default();
+        constraints.push(&[(0, 1.0)], 0.0);
+        constraints.push(&[(1, 1.0)], 1.0);
+        assert_eq!(
+            graph
+                .solve_constrained(&constraints, 1000, 1e-12)
+                .unwrap_err(),
+            ConstraintError::Inconsistent { row: 1 }
+        );
+
+        let mut constraints = Constraints::default();
+        constraints.push(&[(2 * 9, 1.0)], 0.0);
+        let err = graph
+            .solve_constrained(&constraints, 1000, 1e-12)
+            .unwrap_err();
+        assert_eq!(err.code(), COMPASS_ERR_INVALID_ARGUMENT);
+    }
+
+    #[test]
+    fn elimination_matches_fixing_the_vertices() {
+        let vertices: Vec<usize> = (0..ELIMINATION_LIMIT / 2).map(|k| 3 * k + 2).collect();
+        let (graph, constraints, fixed) = pinned(12, &vertices);
+        let solution = graph.solve_constrained(&constraints, 2000, 1e-12).unwrap();
+        let expected = fixed
+            .solve_with(2000, 1e-12, Default::default(), Default::default())
+            .unwrap();
+
+        for residual in residuals(&constraints, &solution) {
+            assert!(residual.abs() < 1e-12, "C x - d = {residual}");
+        }
+        for v in 0..graph.num_vertices() {
+            assert!((solution.x[v] - expected.x[v]).abs() < 1e-8, "x[{v}]");
+            assert!((solution.y[v] - expected.y[v]).abs() < 1e-8, "y[{v}]");
+        }
+    }
+
+    #[test]
+    fn kkt_matches_fixing_the_vertices() {
+        let vertices: Vec<usize> = (0..ELIMINATION_LIMIT / 2 + 5).map(|k| 3 * k + 2).collect();
+        let (graph, constraints, fixed) = pinned(12, &vertices);
+        assert!(constraints.values.len() > ELIMINATION_LIMIT);
+        let solution = graph.solve_constrained(&constraints, 5000, 1e-12).unwrap();
+        let expected = fixed
+            .solve_with(2000, 1e-12, Default::default(), Default::default())
+            .unwrap();
+
+        for residual in residuals(&constraints, &solution) {
+            assert!(residual.abs() < 1e-6, "C x - d = {residual}");
+        }
+        for v in 0..graph.num_vertices() {
+            assert!((solution.x[v] - expected.x[v]).abs() < 1e-6, "x[{v}]");
+            assert!((solution.y[v] - expected.y[v]).abs() < 1e-6, "y[{v}]");
+        }
+    }
+
+    #[test]
+    fn ffi_reports_the_contradicting_row() {
+        let handle = GraphContext::into_raw(grid(3));
+        let options = SolveOptions {
+            iterations: 1000,
+            tolerance: 1e-12,
+            ..SolveOptions::default()
+        };
+        let (rows, columns, coefficients) = ([0, 1, 2], [8, 9, 8], [1.0, 1.0, 1.0]);
+        let call = |values: &[f64], out_row: &mut c_int, stats: &mut SolveStats| {
+            graph_solve_constrained(
+                handle,
+                &options,
+                3,
+                values.as_ptr(),
+                3,
+                rows.as_ptr(),
+                columns.as_ptr(),
+                coefficients.as_ptr(),
+                out_row,
+                stats,
+            )
+        };
+        let mut stats = SolveStats {
+            struct_size: size_of::<SolveStats>(),
+            ..SolveStats::default()
+        };
+        let mut out_row = 7;
+
+        assert_eq!(
+            call(&[10.0, 10.0, 10.0], &mut out_row, &mut stats),
+            COMPASS_OK
+        );
+        assert_eq!(out_row, -1);
+        assert_eq!(stats.free_vertices, 8);
+        let ctx = unsafe { &*handle };
+        let (x, y) = ctx.coordinates();
+        assert!((x[4] - 10.0).abs() < 1e-12 && (y[4] - 10.0).abs() < 1e-12);
+
+        assert_eq!(
+            call(&[10.0, 10.0, 11.0], &mut out_row, &mut stats),
+            COMPASS_ERR_INVALID_ARGUMENT
+        );
+        assert_eq!(out_row, 2);
+
+        let negative = [-1];
+        let code = graph_solve_constrained(
+            handle,
+            &options,
+            1,
+            [0.0].as_ptr(),
+            1,
+            negative.as_ptr(),
+            columns.as_ptr(),
+            coefficients.as_ptr(),
+            &mut out_row,
+            std::ptr::null_mut(),
+        );
+        assert_eq!(code, COMPASS_ERR_INVALID_ARGUMENT);
+        assert_eq!(out_row, -1);
+        graph_free(handle);
+    }
+
+    #[test]
+    fn ffi_reads_and_writes_structs_up_to_their_size() {
+        let (values, rows, columns, coefficients) = ([10.0, 10.0], [0, 1], [8, 9], [1.0, 1.0]);
+        let call = |handle, options: &SolveOptions, stats: *mut SolveStats| {
+            graph_solve_constrained(
+                handle,
+                options,
+                2,
+                values.as_ptr(),
+                2,
+                rows.as_ptr(),
+                columns.as_ptr(),
+                coefficients.as_ptr(),
+                std::ptr::null_mut(),
+                stats,
+            )
+        };
+        let full_stats = || SolveStats {
+            struct_size: size_of::<SolveStats>(),
+            ..SolveStats::default()
+        };
+
+        // A caller of the first release knows neither the preset nor the later stats: the
+        // preset beyond its `struct_size` is ignored and the stats past it are not written.
+        let handle = GraphContext::into_raw(grid(3));
+        let old = SolveOptions {
+            struct_size: crate::SOLVE_OPTIONS_MIN_SIZE,
+            iterations: 1000,
+            tolerance: 1e-12,
+            preset: 99,
+            ..SolveOptions::default()
+        };
+        let mut stats = SolveStats {
+            struct_size: crate::SOLVE_STATS_MIN_SIZE,
+            stop_reason: -7,
+            ..SolveStats::default()
+        };
+        assert_eq!(call(handle, &old, &mut stats), COMPASS_OK);
+        assert_eq!(stats.struct_size, crate::SOLVE_STATS_MIN_SIZE);
+        assert_eq!(stats.free_vertices, 8);
+        assert_eq!(stats.stop_reason, -7);
+        graph_free(handle);
+
+        // A single iteration does not converge, the preset overrides it.
+        let single = SolveOptions {
+            iterations: 1,
+            tolerance: 1e-12,
+            ..SolveOptions::default()
+        };
+        let preset = SolveOptions {
+            preset: Preset::Strict as c_int,
+            ..single
+        };
+        for (options, converged) in [(single, false), (preset, true)] {
+            let handle = GraphContext::into_raw(grid(3));
+            let mut stats = full_stats();
+            assert_eq!(call(handle, &options, &mut stats), COMPASS_OK);
+            assert_eq!(
+                stats.stop_reason == COMPASS_STOP_CONVERGED,
+                converged,
+                "{stats:?}"
+            );
+            graph_free(handle);
+        }
+
+        let handle = GraphContext::into_raw(grid(3));
+        let mut stats = full_stats();
+        let unknown = SolveOptions {
+            preset: 99,
+            ..SolveOptions::default()
+        };
+        assert_eq!(
+            call(handle, &unknown, &mut stats),
+            COMPASS_ERR_INVALID_ARGUMENT
+        );
+        let short = SolveOptions {
+            struct_size: crate::SOLVE_OPTIONS_MIN_SIZE - 1,
+            ..SolveOptions::default()
+        };
+        assert_eq!(
+            call(handle, &short, &mut stats),
+            COMPASS_ERR_INVALID_ARGUMENT
+        );
+        graph_free(handle);
+    }
+}
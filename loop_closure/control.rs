@@ -0,0 +1,211 @@
+//! Which anchor controls each station: the fixed vertex nearest to it along the shots, for
+//! QA maps coloring the stations by the entrance or benchmark that dominates their position.
+//!
+//! Distances are path lengths, each shot counting its surveyed length `|(dx, dy, dz)|`,
+//! found by a Dijkstra search from all fixed vertices at once; the direction of a shot does
+//! not matter. Of two anchors at the same distance the lower index wins. This only reads the
+//! topology and the observations, so it needs no solve.
+//!
+//! Stations no anchor reaches belong to the free networks that
+//! [`crate::SolveStats::free_networks`] counts after a solve, which
+//! [`AnchorControl::free_networks`] counts the same way beforehand.
+
+use crate::{COMPASS_ERR_INVALID_ARGUMENT, COMPASS_OK, Graph, GraphContext};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, VecDeque};
+use std::ffi::{c_double, c_int};
+use std::slice;
+
+/// Controlling anchor of each vertex, see the [module documentation](self).
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnchorControl {
+    /// Nearest fixed vertex of each vertex, itself for a fixed vertex, `None` when no anchor
+    /// reaches it.
+    pub anchor: Vec<Option<usize>>,
+    /// Path length to that anchor, infinite when there is none.
+    pub distance: Vec<f64>,
+    /// Number of connected components with an edge but no fixed vertex.
+    pub free_networks: usize,
+}
+
+impl Graph {
+    /// Controlling anchor of each vertex over all the edges, see the
+    /// [module documentation](self).
+    pub fn anchor_control(&self) -> AnchorControl {
+        anchor_control(self, &|_| true)
+    }
+}
+
+impl GraphContext {
+    /// Controlling anchor of each vertex over the enabled edges, see
+    /// [`Graph::anchor_control`].
+    pub fn anchor_control(&self) -> AnchorControl {
+        anchor_control(&self.graph, &|e| self.is_edge_enabled(e))
+    }
+}
+
+fn anchor_control(graph: &Graph, enabled: &dyn Fn(usize) -> bool) -> AnchorControl {
+    let n = graph.num_vertices();
+    let mut neighbors = vec![Vec::new(); n];
+    for e in (0..graph.num_edges()).filter(|&e| enabled(e)) {
+        let (u, v) = (graph.from[e], graph.to[e]);
+        let length = (graph.dx[e].powi(2) + graph.dy[e].powi(2) + graph.dz[e].powi(2)).sqrt();
+        if u != v && length.is_finite() {
+            neighbors[u].push((v, length));
+            neighbors[v].push((u, length));
+        }
+    }
+
+    let mut anchor = vec![None; n];
+    let mut distance = vec![f64::INFINITY; n];
+    // Distances are never negative, so their bits order them like their values.
+    let mut heap = BinaryHeap::new();
+    for v in (0..n).filter(|&v| graph.fixed[v]) {
+        anchor[v] = Some(v);
+        distance[v] = 0.0;
+        heap.push(Reverse((0.0f64.to_bits(), v, v)));
+    }
+    while let Some(Reverse((bits, from, u))) = heap.pop() {
+        let d = f64::from_bits(bits);
+        if (d, Some(from)) != (distance[u], anchor[u]) {
+            continue;
+        }
+        for &(v, length) in &neighbors[u] {
+            let candidate = (d + length, from);
+            let better = match anchor[v] {
+                None => true,
+                Some(current) => {
+                    candidate.0 < distance[v] || (candidate.0 == distance[v] && from < current)
+                }
+            };
+            if better {
+                (distance[v], anchor[v]) = (candidate.0, Some(from));
+                heap.push(Reverse((candidate.0.to_bits(), from, v)));
+            }
+        }
+    }
+
+    // Components of the stations left over, those of their own with an edge.
+    let mut seen: Vec<bool> = anchor.iter().map(Option::is_some).collect();
+    let mut free_networks = 0;
+    for seed in 0..n {
+        if seen[seed] || neighbors[seed].is_empty() {
+            continue;
+        }
+        free_networks += 1;
+        seen[seed] = true;
+        let mut queue = VecDeque::from([seed]);
+        while let Some(u) = queue.pop_front() {
+            for &(v, _) in &neighbors[u] {
+                if !std::mem::replace(&mut seen[v], true) {
+                    queue.push_back(v);
+                }
+            }
+        }
+    }
+    AnchorControl {
+        anchor,
+        distance,
+        free_networks,
+    }
+}
+
+/// Copies the controlling anchor of each vertex of the graph behind `handle` over the enabled
+/// edges, see [`GraphContext::anchor_control`], into caller buffers of length
+/// [`crate::graph_num_vertices`]: the anchor index into `out_anchor`, -1 when no anchor reaches
+/// the vertex, and the path length into `out_distance`, infinite then. The number of free
+/// networks is written to `out_free_networks`. Any output pointer may be null to skip it.
+/// Returns [`COMPASS_ERR_INVALID_ARGUMENT`] for a null handle.
+#[unsafe(no_mangle)]
+pub extern "C" fn graph_anchor_control(
+    handle: *const GraphContext,
+    out_anchor: *mut c_int,
+    out_distance: *mut c_double,
+    out_free_networks: *mut c_int,
+) -> c_int {
+    let Some(ctx) = (unsafe { handle.as_ref() }) else {
+        return COMPASS_ERR_INVALID_ARGUMENT;
+    };
+    let control = ctx.anchor_control();
+    let n = control.anchor.len();
+    if !out_anchor.is_null() {
+        // Safety: The caller guarantees a buffer of `num_vertices` elements.
+        let out = unsafe { slice::from_raw_parts_mut(out_anchor, n) };
+        for (dst, anchor) in out.iter_mut().zip(&control.anchor) {
+            *dst = anchor.map_or(-1, |a| a as c_int);
+        }
+    }
+    if !out_distance.is_null() {
+        // Safety: The caller guarantees a buffer of `num_vertices` elements.
+        unsafe { slice::from_raw_parts_mut(out_distance, n) }.copy_from_slice(&control.distance);
+    }
+    if !out_free_networks.is_null() {
+        unsafe { *out_free_networks = control.free_networks as c_int };
+    }
+    COMPASS_OK
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph_free;
+
+    /// Anchors 0 and 4 at both ends of the chain 0-1-2-3-4 of shots 3, 4, 4 and 5 m long, a
+    /// free pair 5-6 and a lone station 7.
+    fn two_entrances() -> Graph {
+        let mut graph = Graph::default();
+        for v in 0..8 {
+            graph.add_vertex(0.0, 0.0, 0.0, v == 0 || v == 4);
+        }
+        graph.add_edge(0, 1, 3.0, 0.0, 0.0, 1.0);
+        graph.add_edge(1, 2, 0.0, 4.0, 0.0, 1.0);
+        graph.add_edge(3, 2, 0.0, 0.0, 4.0, 1.0);
+        graph.add_edge(3, 4, 3.0, 4.0, 0.0, 1.0);
+        graph.add_edge(5, 6, 1.0, 0.0, 0.0, 1.0);
+        graph
+    }
+
+    #[test]
+    fn stations_take_the_nearest_anchor_along_the_shots() {
+        let graph = two_entrances();
+        let control = graph.anchor_control();
+        let anchors = [0, 0, 0, 4, 4].map(Some);
+        assert_eq!(control.anchor[..5], anchors);
+        assert_eq!(control.anchor[5..], [None; 3]);
+        assert_eq!(control.distance[..5], [0.0, 3.0, 7.0, 5.0, 0.0]);
+        assert!(control.distance[5..].iter().all(|d| d.is_infinite()));
+        assert_eq!(control.free_networks, 1);
+        let solved = graph.solve(1000, 1e-12).unwrap();
+        assert_eq!(solved.stats.free_networks, 1);
+
+        // Station 2 lies 9 m from both anchors once the first shot is 5 m long; the lower
+        // index wins the tie.
+        let mut tied = graph.clone();
+        tied.dx[0] = 5.0;
+        assert_eq!(tied.anchor_control().anchor[2], Some(0));
+
+        // Through the handle, over the enabled edges: cutting the chain at 3-4 leaves
+        // station 3 to anchor 0.
+        let handle = GraphContext::into_raw(graph);
+        unsafe { &mut *handle }.edge_enabled = vec![true, true, true, false];
+        let (mut anchor, mut distance, mut free) = ([0; 8], [0.0; 8], -1);
+        assert_eq!(
+            graph_anchor_control(
+                handle,
+                anchor.as_mut_ptr(),
+                distance.as_mut_ptr(),
+                &mut free
+            ),
+            COMPASS_OK
+        );
+        assert_eq!(anchor, [0, 0, 0, 0, 4, -1, -1, -1]);
+        assert_eq!(distance[3], 11.0);
+        assert_eq!(free, 1);
+        let null = std::ptr::null_mut();
+        assert_eq!(
+            graph_anchor_control(std::ptr::null(), null, std::ptr::null_mut(), null),
+            COMPASS_ERR_INVALID_ARGUMENT
+        );
+        graph_free(handle);
+    }
+}
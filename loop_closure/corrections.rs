@@ -0,0 +1,223 @@
+//! Per-shot corrections implied by an adjustment, for pushing the adjusted network back into
+//! the source survey data.
+//!
+//! The corrected shot of an edge is the adjusted coordinate difference of its endpoints
+//! (`x_to - x_from`, `y_to - y_from`) with the observed `dz`, since the horizontal adjustment
+//! does not change elevations. It is also expressed as a length, an azimuth (clockwise from
+//! north) and an inclination, and compared with the observed shot.
+
+use crate::{COMPASS_ERR_INVALID_ARGUMENT, Graph, GraphContext, Solution};
+use std::ffi::{c_double, c_int};
+use std::slice;
+
+/// Corrected shot of one edge and its difference from the observed one.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct ShotCorrection {
+    /// Corrected X difference.
+    pub dx: c_double,
+    /// Corrected Y difference.
+    pub dy: c_double,
+    /// Observed Z difference.
+    pub dz: c_double,
+    /// Corrected 3D length.
+    pub length: c_double,
+    /// Corrected azimuth in degrees, clockwise from north in `[0, 360)`.
+    pub azimuth_deg: c_double,
+    /// Corrected inclination in degrees, positive upwards.
+    pub inclination_deg: c_double,
+    /// Corrected minus observed length.
+    pub length_correction: c_double,
+    /// Corrected minus observed azimuth in degrees, in `(-180, 180]`.
+    pub azimuth_correction_deg: c_double,
+    /// Corrected minus observed inclination in degrees.
+    pub inclination_correction_deg: c_double,
+}
+
+impl ShotCorrection {
+    /// Correction of the shot observing `observed` when the adjustment implies `corrected`,
+    /// both as `(dx, dy, dz)`.
+    pub fn new(observed: (f64, f64, f64), corrected: (f64, f64, f64)) -> Self {
+        let (length, azimuth, inclination) = polar(corrected);
+        let (observed_length, observed_azimuth, observed_inclination) = polar(observed);
+        let mut azimuth_correction = (azimuth - observed_azimuth).rem_euclid(360.0);
+        if azimuth_correction > 180.0 {
+            azimuth_correction -= 360.0;
+        }
+        ShotCorrection {
+            dx: corrected.0,
+            dy: corrected.1,
+            dz: corrected.2,
+            length,
+            azimuth_deg: azimuth,
+            inclination_deg: inclination,
+            length_correction: length - observed_length,
+            azimuth_correction_deg: azimuth_correction,
+            inclination_correction_deg: inclination - observed_inclination,
+        }
+    }
+
+    /// The same correction for the shot taken in the opposite direction: the vectors are
+    /// negated, the azimuth turns by 180° and the inclination changes sign.
+    pub fn reversed(&self) -> Self {
+        ShotCorrection {
+            dx: -self.dx,
+            dy: -self.dy,
+            dz: -self.dz,
+            length: self.length,
+            azimuth_deg: (self.azimuth_deg + 180.0).rem_euclid(360.0),
+            inclination_deg: -self.inclination_deg,
+            length_correction: self.length_correction,
+            azimuth_correction_deg: self.azimuth_correction_deg,
+            inclination_correction_deg: -self.inclination_correction_deg,
+        }
+    }
+}
+
+/// Length, azimuth and inclination (degrees) of `(dx, dy, dz)`.
+fn polar((dx, dy, dz): (f64, f64, f64)) -> (f64, f64, f64) {
+    let horizontal = dx.hypot(dy);
+    (
+        horizontal.hypot(dz),
+        dx.atan2(dy).to_degrees().rem_euclid(360.0),
+        dz.atan2(horizontal).to_degrees(),
+    )
+}
+
+impl Solution {
+    /// Corrected shot of each edge, in edge order and edge direction.
+    pub fn shot_corrections(&self, graph: &Graph) -> Vec<ShotCorrection> {
+        shot_corrections(&self.x, &self.y, graph)
+    }
+}
+
+fn shot_corrections(x: &[f64], y: &[f64], graph: &Graph) -> Vec<ShotCorrection> {
+    (0..graph.num_edges())
+        .map(|e| {
+            let (u, v) = (graph.from[e], graph.to[e]);
+            ShotCorrection::new(
+                (graph.dx[e], graph.dy[e], graph.dz[e]),
+                (x[v] - x[u], y[v] - y[u], graph.dz[e]),
+            )
+        })
+        .collect()
+}
+
+/// Copies the corrected shot of every edge of the graph behind `handle`, at its current
+/// coordinates, into a caller buffer of [`crate::graph_num_edges`] elements. See
+/// [`Solution::shot_corrections`]. Returns the number of edges written, or
+/// [`COMPASS_ERR_INVALID_ARGUMENT`] for a null pointer.
+#[unsafe(no_mangle)]
+pub extern "C" fn graph_shot_corrections(
+    handle: *const GraphContext,
+    out: *mut ShotCorrection,
+) -> c_int {
+    let Some(ctx) = (unsafe { handle.as_ref() }) else {
+        return COMPASS_ERR_INVALID_ARGUMENT;
+    };
+    if out.is_null() {
+        return COMPASS_ERR_INVALID_ARGUMENT;
+    }
+    let (x, y) = ctx.coordinates();
+    let corrections = shot_corrections(x, y, &ctx.graph);
+    // Safety: The caller guarantees a buffer of `num_edges` elements.
+    unsafe { slice::from_raw_parts_mut(out, corrections.len()) }.copy_from_slice(&corrections);
+    corrections.len() as c_int
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-12,
+            "{actual} differs from {expected}"
+        );
+    }
+
+    #[test]
+    fn corrections_of_a_shot_turned_and_stretched() {
+        let c = ShotCorrection::new((0.0, 10.0, 0.0), (1.0, 10.0, 0.0));
+        assert_close(c.length, 101f64.sqrt());
+        assert_close(c.length_correction, 101f64.sqrt() - 10.0);
+        assert_close(c.azimuth_deg, 0.1f64.atan().to_degrees());
+        assert_close(c.azimuth_correction_deg, c.azimuth_deg);
+        assert_eq!(
+            (c.inclination_deg, c.inclination_correction_deg),
+            (0.0, 0.0)
+        );
+
+        // Corrections across north stay small, in either direction.
+        let c = ShotCorrection::new((-0.1, 10.0, 0.0), (0.1, 10.0, 0.0));
+        assert_close(c.azimuth_correction_deg, 2.0 * 0.01f64.atan().to_degrees());
+        let c = ShotCorrection::new((0.1, 10.0, 0.0), (-0.1, 10.0, 0.0));
+        assert_close(c.azimuth_correction_deg, -2.0 * 0.01f64.atan().to_degrees());
+
+        // A sloping shot keeps its observed dz, so flattening it steepens the shot.
+        let c = ShotCorrection::new((3.0, 0.0, 4.0), (2.0, 0.0, 4.0));
+        assert_close(c.azimuth_deg, 90.0);
+        assert_close(c.inclination_deg, 2f64.atan().to_degrees());
+        assert_close(
+            c.inclination_correction_deg,
+            2f64.atan().to_degrees() - (4.0f64 / 3.0).atan().to_degrees(),
+        );
+    }
+
+    #[test]
+    fn reversed_matches_the_shot_taken_backwards() {
+        let (observed, corrected) = ((3.0, 4.0, -2.0), (3.2, 3.9, -2.0));
+        let reversed = ShotCorrection::new(observed, corrected).reversed();
+        let backwards = ShotCorrection::new((-3.0, -4.0, 2.0), (-3.2, -3.9, 2.0));
+        for (a, b) in [
+            (reversed.dx, backwards.dx),
+            (reversed.dy, backwards.dy),
+            (reversed.dz, backwards.dz),
+            (reversed.length, backwards.length),
+            (reversed.azimuth_deg, backwards.azimuth_deg),
+            (reversed.inclination_deg, backwards.inclination_deg),
+            (reversed.length_correction, backwards.length_correction),
+            (
+                reversed.azimuth_correction_deg,
+                backwards.azimuth_correction_deg,
+            ),
+            (
+                reversed.inclination_correction_deg,
+                backwards.inclination_correction_deg,
+            ),
+        ] {
+            assert_close(a, b);
+        }
+    }
+
+    #[test]
+    fn ffi_corrections_follow_the_current_coordinates() {
+        let mut graph = Graph::default();
+        graph.add_vertex(0.0, 0.0, 0.0, true);
+        graph.add_vertex(10.0, 0.0, 0.0, false);
+        graph.add_vertex(10.0, 10.0, 0.0, true);
+        graph.add_edge(0, 1, 10.0, 0.5, 1.0, 1.0);
+        graph.add_edge(1, 2, 0.5, 10.0, -1.0, 1.0);
+        let solution = graph.solve(1000, 1e-12).unwrap();
+        let expected = solution.shot_corrections(&graph);
+        assert_close(expected[0].dx, solution.x[1]);
+        assert_eq!(expected[1].dz, -1.0);
+
+        let handle = GraphContext::into_raw(graph);
+        let ctx = unsafe { &mut *handle };
+        ctx.solve(1000, 1e-12.into(), Default::default(), None, &|| false)
+            .unwrap();
+        let mut out = [ShotCorrection::default(); 2];
+        assert_eq!(graph_shot_corrections(handle, out.as_mut_ptr()), 2);
+        assert_eq!(out.to_vec(), expected);
+        assert_eq!(
+            graph_shot_corrections(std::ptr::null(), out.as_mut_ptr()),
+            COMPASS_ERR_INVALID_ARGUMENT
+        );
+        assert_eq!(
+            graph_shot_corrections(handle, std::ptr::null_mut()),
+            COMPASS_ERR_INVALID_ARGUMENT
+        );
+        crate::graph_free(handle);
+    }
+}
@@ -0,0 +1,315 @@
+//! Correlated observations within traverses.
+//!
+//! Shots of one traverse share instruments and readers, so their errors are correlated, and
+//! treating them as independent overstates the precision of the network. A traverse is a run
+//! of consecutive edges sharing a traverse id. Shots `k` and `l` of a traverse get the AR(1)
+//! correlation `ρ^|k - l|`, `ρ` the parameter of the traverse, while the variance of shot `k`
+//! stays `1 / w_k`. The weight matrix of a traverse is then `W = D R⁻¹ D`, with
+//! `D = diag(√w_k)` and `R⁻¹` the inverse of the AR(1) correlation matrix, which is
+//! tridiagonal:
+//!
+//! ```text
+//! R⁻¹ = 1 / (1 - ρ²) · tridiag(-ρ; 1, 1 + ρ², …, 1 + ρ², 1; -ρ)
+//! ```
+//!
+//! The normal matrix `AᵀWA` is accumulated per traverse: the diagonal of `W` enters as edge
+//! weights, and each pair of consecutive shots `k`, `l` adds `W_kl (a_k a_lᵀ + a_l a_kᵀ)`,
+//! `a_k` the incidence row of shot `k`. Shots of different traverses stay independent.
+//!
+//! The residual statistics of [`Solution`] still treat the shots as independent.
+
+use crate::{
+    COMPASS_ERR_INVALID_ARGUMENT, Graph, NormalEquations, NullSpace, Solution, SolveError,
+};
+use nalgebra_sparse::{CooMatrix, CsrMatrix};
+use std::collections::HashMap;
+
+/// AR(1) correlation of the shots within each traverse.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TraverseCorrelation {
+    /// Traverse id of each edge (length `num_edges`). Consecutive edges with the same id form
+    /// a traverse.
+    pub traverse_id: Vec<i32>,
+    /// Correlation `ρ` in `(-1, 1)` between consecutive shots, by traverse id. Traverses whose
+    /// id is missing are uncorrelated.
+    pub rho: HashMap<i32, f64>,
+}
+
+/// Tridiagonal weight matrix `W` of the shots of a graph.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WeightMatrix {
+    /// `W_kk` of each shot.
+    pub diagonal: Vec<f64>,
+    /// `(k, W_k,k+1)` of each shot `k` correlated with the next one of its traverse.
+    pub couplings: Vec<(usize, f64)>,
+}
+
+impl TraverseCorrelation {
+    /// The weight matrix of shots of weights `weight`.
+    pub fn weight_matrix(&self, weight: &[f64]) -> Result<WeightMatrix, SolveError> {
+        let invalid = |message: String| {
+            Err(SolveError {
+                code: COMPASS_ERR_INVALID_ARGUMENT,
+                message,
+            })
+        };
+        if self.traverse_id.len() != weight.len() {
+            return invalid(format!(
+                "expected {} traverse ids, got {}",
+                weight.len(),
+                self.traverse_id.len()
+            ));
+        }
+        if let Some((id, rho)) = self
+            .rho
+            .iter()
+            .find(|(_, rho)| rho.is_nan() || rho.abs() >= 1.0)
+        {
+            return invalid(format!(
+                "correlation {rho} of traverse {id} outside (-1, 1)"
+            ));
+        }
+
+        let ids = &self.traverse_id;
+        let mut diagonal = weight.to_vec();
+        let mut couplings = Vec::new();
+        let mut start = 0;
+        while start < ids.len() {
+            let end = start
+                + ids[start..]
+                    .iter()
+                    .take_while(|&&id| id == ids[start])
+                    .count();
+            let rho = self.rho.get(&ids[start]).copied().unwrap_or(0.0);
+            if end - start > 1 && rho != 0.0 {
+                let scale = 1.0 / (1.0 - rho * rho);
+                for (k, w) in diagonal.iter_mut().enumerate().take(end).skip(start) {
+                    let inner = k > start && k + 1 < end;
+                    *w *= scale * if inner { 1.0 + rho * rho } else { 1.0 };
+                }
+                for k in start..end - 1 {
+                    couplings.push((k, -rho * scale * (weight[k] * weight[k + 1]).sqrt()));
+                }
+            }
+            start = end;
+        }
+        Ok(WeightMatrix {
+            diagonal,
+            couplings,
+        })
+    }
+}
+
+impl Graph {
+    /// Same as [`Graph::solve`], with the shots of each traverse correlated as described by
+    /// `correlation`.
+    pub fn solve_correlated(
+        &self,
+        correlation: &TraverseCorrelation,
+        iterations: usize,
+        tolerance: f64,
+    ) -> Result<Solution, SolveError> {
+        let matrix = correlation.weight_matrix(&self.weight)?;
+        let mut weighted = self.clone();
+        weighted.weight = matrix.diagonal;
+        let mut system = weighted.normal_equations(&[])?;
+        couple(&mut system, self, &matrix.couplings);
+        weighted.solve_system(
+            &system,
+            &self.x,
+            &self.y,
+            iterations,
+            tolerance.into(),
+            Default::default(),
+            &|| false,
+        )
+    }
+}
+
+/// Adds the `couplings` of consecutive shots to `system`, the normal equations of `graph`
+/// weighted by the diagonal of the weight matrix.
+fn couple(system: &mut NormalEquations, graph: &Graph, couplings: &[(usize, f64)]) {
+    // Free entries of the incidence row of edge `e`, and its observation with the fixed
+    // endpoints moved over.
+    let row = |e: usize| {
+        let (mut lx, mut ly) = (graph.dx[e], graph.dy[e]);
+        let mut free = Vec::with_capacity(2);
+        for (v, sign) in [(graph.from[e], -1.0), (graph.to[e], 1.0)] {
+            match system.mapping[v] {
+                Some(i) => free.push((i, sign)),
+                None => {
+                    lx -= sign * graph.x[v];
+                    ly -= sign * graph.y[v];
+                }
+            }
+        }
+        (free, lx, ly)
+    };
+
+    let a = &system.matrix;
+    let (offsets, columns, values) = (a.row_offsets(), a.col_indices(), a.values());
+    let mut coo = CooMatrix::new(a.nrows(), a.ncols());
+    for i in 0..a.nrows() {
+        for e in offsets[i]..offsets[i + 1] {
+            coo.push(i, columns[e], values[e]);
+        }
+    }
+    for &(k, w) in couplings {
+        let (row_k, kx, ky) = row(k);
+        let (row_l, lx, ly) = row(k + 1);
+        for &(i, si) in &row_k {
+            for &(j, sj) in &row_l {
+                coo.push(i, j, w * si * sj);
+                coo.push(j, i, w * si * sj);
+            }
+            system.bx[i] += w * si * lx;
+            system.by[i] += w * si * ly;
+        }
+        for &(j, sj) in &row_l {
+            system.bx[j] += w * sj * kx;
+            system.by[j] += w * sj * ky;
+        }
+    }
+    system.matrix = CsrMatrix::from(&coo);
+    system.null_space = NullSpace::of(&system.matrix);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra::{DMatrix, DVector};
+
+    /// A 10-shot traverse (id 1) between the fixed stations 0 and 10, and a 3-shot traverse
+    /// (id 2) from station 3 to station 7 through station 11. Shots are 10 m east with small
+    /// misclosures and uneven weights.
+    fn traverses() -> (Graph, TraverseCorrelation) {
+        let mut graph = Graph::default();
+        for i in 0..=10 {
+            graph.add_vertex(10.0 * i as f64, 0.0, 0.0, i == 0 || i == 10);
+        }
+        graph.x[10] = 100.4;
+        graph.add_vertex(50.0, 8.0, 0.0, false);
+        for i in 0..10 {
+            let noise = ((i * 7919) % 13) as f64 * 0.01 - 0.06;
+            graph.add_edge(
+                i,
+                i + 1,
+                10.0 + noise,
+                noise / 2.0,
+                0.0,
+                1.0 + 0.1 * i as f64,
+            );
+        }
+        graph.add_edge(3, 11, 20.1, 8.0, 0.0, 0.5);
+        graph.add_edge(11, 7, 19.9, -8.1, 0.0, 0.8);
+        graph.add_edge(7, 6, -10.05, 0.02, 0.0, 2.0);
+        let correlation = TraverseCorrelation {
+            traverse_id: [vec![1; 10], vec![2; 3]].concat(),
+            rho: HashMap::from([(1, 0.6), (2, -0.3)]),
+        };
+        (graph, correlation)
+    }
+
+    /// Dense `W = D R⁻¹ D` of the shots, `R` the AR(1) correlation within each traverse.
+    fn dense_weights(graph: &Graph, correlation: &TraverseCorrelation) -> DMatrix<f64> {
+        let m = graph.num_edges();
+        let ids = &correlation.traverse_id;
+        let mut r = DMatrix::identity(m, m);
+        for k in 0..m {
+            for l in 0..m {
+                let same = (k.min(l)..=k.max(l)).all(|e| ids[e] == ids[k]);
+                if k != l && same {
+                    r[(k, l)] = correlation.rho[&ids[k]].powi(k.abs_diff(l) as i32);
+                }
+            }
+        }
+        let d = DMatrix::from_diagonal(&DVector::from_iterator(
+            m,
+            graph.weight.iter().map(|w| w.sqrt()),
+        ));
+        &d * r.try_inverse().unwrap() * &d
+    }
+
+    #[test]
+    fn weight_matrix_is_the_inverse_covariance() {
+        let (graph, correlation) = traverses();
+        let matrix = correlation.weight_matrix(&graph.weight).unwrap();
+        let dense = dense_weights(&graph, &correlation);
+        let m = graph.num_edges();
+        for k in 0..m {
+            assert!(
+                (matrix.diagonal[k] - dense[(k, k)]).abs() < 1e-12,
+                "W[{k}, {k}]"
+            );
+        }
+        assert_eq!(matrix.couplings.len(), 9 + 2);
+        for &(k, w) in &matrix.couplings {
+            assert!((w - dense[(k, k + 1)]).abs() < 1e-12, "W[{k}, {}]", k + 1);
+        }
+        for k in 0..m {
+            for l in k + 2..m {
+                assert!(dense[(k, l)].abs() < 1e-12, "W[{k}, {l}]");
+            }
+        }
+    }
+
+    #[test]
+    fn matches_a_dense_reference_adjustment() {
+        let (graph, correlation) = traverses();
+        let solution = graph.solve_correlated(&correlation, 10_000, 1e-14).unwrap();
+
+        // Dense generalised least squares over the free stations, X and Y apart.
+        let free: Vec<usize> = (0..graph.num_vertices())
+            .filter(|&v| !graph.fixed[v])
+            .collect();
+        let column = |v: usize| free.iter().position(|&f| f == v);
+        let (m, n) = (graph.num_edges(), free.len());
+        let mut a = DMatrix::zeros(m, n);
+        let (mut lx, mut ly) = (DVector::zeros(m), DVector::zeros(m));
+        for e in 0..m {
+            lx[e] = graph.dx[e];
+            ly[e] = graph.dy[e];
+            for (v, sign) in [(graph.from[e], -1.0), (graph.to[e], 1.0)] {
+                match column(v) {
+                    Some(j) => a[(e, j)] = sign,
+                    None => {
+                        lx[e] -= sign * graph.x[v];
+                        ly[e] -= sign * graph.y[v];
+                    }
+                }
+            }
+        }
+        let w = dense_weights(&graph, &correlation);
+        let normal = a.transpose() * &w * &a;
+        let cholesky = normal.cholesky().unwrap();
+        let x = cholesky.solve(&(a.transpose() * &w * lx));
+        let y = cholesky.solve(&(a.transpose() * &w * ly));
+        for (j, &v) in free.iter().enumerate() {
+            assert!((solution.x[v] - x[j]).abs() < 1e-9, "x[{v}]");
+            assert!((solution.y[v] - y[j]).abs() < 1e-9, "y[{v}]");
+        }
+
+        // Uncorrelated traverses are the plain adjustment.
+        let plain = TraverseCorrelation {
+            traverse_id: correlation.traverse_id.clone(),
+            rho: HashMap::new(),
+        };
+        let uncorrelated = graph.solve_correlated(&plain, 10_000, 1e-14).unwrap();
+        let expected = graph.solve(10_000, 1e-14).unwrap();
+        assert_eq!(uncorrelated.x, expected.x);
+        assert_eq!(uncorrelated.y, expected.y);
+    }
+
+    #[test]
+    fn rejects_invalid_correlations() {
+        let (graph, mut correlation) = traverses();
+        correlation.rho.insert(2, 1.0);
+        let err = graph.solve_correlated(&correlation, 100, 1e-8).unwrap_err();
+        assert_eq!(err.code, COMPASS_ERR_INVALID_ARGUMENT);
+
+        correlation.rho.insert(2, 0.5);
+        correlation.traverse_id.pop();
+        let err = graph.solve_correlated(&correlation, 100, 1e-8).unwrap_err();
+        assert_eq!(err.code, COMPASS_ERR_INVALID_ARGUMENT);
+    }
+}
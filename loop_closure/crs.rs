@@ -0,0 +1,310 @@
+//! Coordinate reference of a network: the grid its coordinates are expressed in, their
+//! length unit and the datum of the elevations.
+//!
+//! A [`Graph`] carries at most one [`CoordinateSystem`], which covers every length it holds:
+//! the coordinates of free and fixed vertices alike and the observed differences. There is
+//! no per-vertex unit, so a feet-based graph with fixed coordinates in meters cannot be
+//! expressed: values in another unit are converted with [`convert_length`] on the way in,
+//! and [`Graph::set_coordinate_system`] converts every length when the unit changes. The
+//! system is copied into each [`Solution`], where the exporters read it.
+//!
+//! [`convert_length`] is the only place where lengths change unit, and [`convert_weight`]
+//! the only place where weights do. Feet are the international foot unless stated otherwise:
+//! the US survey foot differs by 2 ppm, which shows at state plane coordinates.
+
+use crate::Graph;
+#[cfg(doc)]
+use crate::Solution;
+
+/// Length of an international foot in meters, exact by definition.
+pub const FEET_TO_METERS: f64 = 0.3048;
+
+/// Length of a US survey foot in meters, exactly 1200/3937 by definition.
+pub const US_SURVEY_FEET_TO_METERS: f64 = 1200.0 / 3937.0;
+
+/// Length unit of coordinates and observations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum LengthUnit {
+    #[default]
+    Meters,
+    /// International feet, see [`FEET_TO_METERS`].
+    Feet,
+    /// US survey feet, see [`US_SURVEY_FEET_TO_METERS`].
+    UsSurveyFeet,
+}
+
+impl LengthUnit {
+    /// Length of one unit in meters.
+    pub const fn meters(self) -> f64 {
+        match self {
+            LengthUnit::Meters => 1.0,
+            LengthUnit::Feet => FEET_TO_METERS,
+            LengthUnit::UsSurveyFeet => US_SURVEY_FEET_TO_METERS,
+        }
+    }
+
+    /// Lower-case name, as written in exported metadata.
+    pub const fn name(self) -> &'static str {
+        match self {
+            LengthUnit::Meters => "meters",
+            LengthUnit::Feet => "feet",
+            LengthUnit::UsSurveyFeet => "US survey feet",
+        }
+    }
+}
+
+/// Converts the length `value` from the unit `from` to the unit `to`.
+pub fn convert_length(value: f64, from: LengthUnit, to: LengthUnit) -> f64 {
+    if from == to {
+        return value;
+    }
+    value * from.meters() / to.meters()
+}
+
+/// Converts the edge weight `weight` from lengths in `from` to lengths in `to`, taking it
+/// as an inverse variance so that standardized residuals do not depend on the unit. Other
+/// weightings differ from this by a constant factor, which the adjustment ignores.
+pub fn convert_weight(weight: f64, from: LengthUnit, to: LengthUnit) -> f64 {
+    let scale = convert_length(1.0, to, from);
+    weight * scale * scale
+}
+
+/// Horizontal grid of the X (easting) and Y (northing) coordinates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum Projection {
+    /// A local grid with an arbitrary origin, e.g. a survey without georeferenced anchors.
+    #[default]
+    LocalGrid,
+    /// A projected reference system by EPSG code, e.g. 32613 for WGS 84 / UTM zone 13N. The
+    /// graph's unit must be that of the system.
+    Epsg(u32),
+}
+
+impl Projection {
+    /// WGS 84 / UTM `zone` (1 to 60) of the northern or southern hemisphere, `None` for an
+    /// invalid zone.
+    pub fn utm_wgs84(zone: u8, north: bool) -> Option<Projection> {
+        let base = if north { 32600 } else { 32700 };
+        (1..=60)
+            .contains(&zone)
+            .then(|| Projection::Epsg(base + zone as u32))
+    }
+}
+
+impl std::fmt::Display for Projection {
+    /// `EPSG:<code>`, or `local grid`.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Projection::LocalGrid => write!(f, "local grid"),
+            Projection::Epsg(code) => write!(f, "EPSG:{code}"),
+        }
+    }
+}
+
+/// Reference of the Z coordinates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum VerticalDatum {
+    /// Unknown or relative to an arbitrary station.
+    #[default]
+    Unspecified,
+    /// Heights above mean sea level (orthometric).
+    MeanSeaLevel,
+    /// Heights above the ellipsoid of the horizontal system.
+    Ellipsoid,
+}
+
+impl VerticalDatum {
+    /// Lower-case name, as written in exported metadata.
+    pub const fn name(self) -> &'static str {
+        match self {
+            VerticalDatum::Unspecified => "unspecified",
+            VerticalDatum::MeanSeaLevel => "mean sea level",
+            VerticalDatum::Ellipsoid => "ellipsoid",
+        }
+    }
+}
+
+/// What the coordinates of a [`Graph`] mean, see the [module documentation](self).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct CoordinateSystem {
+    pub projection: Projection,
+    /// Unit of every coordinate and observed difference.
+    pub units: LengthUnit,
+    pub vertical_datum: VerticalDatum,
+}
+
+impl CoordinateSystem {
+    /// Coordinate system string understood by Survex (`EPSG:<code>`), `None` for a local
+    /// grid.
+    pub fn survex_cs(&self) -> Option<String> {
+        match self.projection {
+            Projection::LocalGrid => None,
+            Projection::Epsg(_) => Some(self.projection.to_string()),
+        }
+    }
+
+    /// OGC URN of the projection, as in the `crs` member of legacy GeoJSON, `None` for a
+    /// local grid.
+    pub fn ogc_urn(&self) -> Option<String> {
+        match self.projection {
+            Projection::LocalGrid => None,
+            Projection::Epsg(code) => Some(format!("urn:ogc:def:crs:EPSG::{code}")),
+        }
+    }
+}
+
+impl Graph {
+    /// The coordinate system of the graph, if one was declared.
+    pub fn coordinate_system(&self) -> Option<&CoordinateSystem> {
+        self.coordinate_system.as_ref()
+    }
+
+    /// Declares the coordinate system of the graph.
+    ///
+    /// Without a previous declaration the values are taken to be in `system`'s unit already.
+    /// Otherwise, if the unit changes, every coordinate and observed difference is converted
+    /// to the new unit and the weights are rescaled as inverse variances, so that the
+    /// adjustment and its standardized residuals are unchanged.
+    pub fn set_coordinate_system(&mut self, system: CoordinateSystem) {
+        let current = self
+            .coordinate_system
+            .map_or(system.units, |current| current.units);
+        if current != system.units {
+            for values in [
+                &mut self.x,
+                &mut self.y,
+                &mut self.z,
+                &mut self.dx,
+                &mut self.dy,
+                &mut self.dz,
+            ] {
+                for value in values.iter_mut() {
+                    *value = convert_length(*value, current, system.units);
+                }
+            }
+            for weight in &mut self.weight {
+                *weight = convert_weight(*weight, current, system.units);
+            }
+        }
+        self.coordinate_system = Some(system);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lengths_convert_between_units() {
+        use LengthUnit::*;
+        assert_eq!(convert_length(3.5, Meters, Meters), 3.5);
+        assert_eq!(convert_length(100.0, Feet, Feet), 100.0);
+        assert!((convert_length(100.0, Feet, Meters) - 30.48).abs() < 1e-12);
+        assert!((convert_length(30.48, Meters, Feet) - 100.0).abs() < 1e-12);
+        let there_and_back = convert_length(convert_length(1234.5, Feet, Meters), Meters, Feet);
+        assert!((there_and_back - 1234.5).abs() < 1e-9);
+
+        // The survey foot is 2 ppm longer than the international foot.
+        let survey = convert_length(1e6, UsSurveyFeet, Feet);
+        assert!((survey - 1e6 - 2.000_004).abs() < 1e-6, "{survey}");
+        assert!((convert_length(3937.0, UsSurveyFeet, Meters) - 1200.0).abs() < 1e-12);
+
+        // Weights follow the square of the unit, inversely.
+        assert!((convert_weight(1.0, Meters, Feet) - 0.3048 * 0.3048).abs() < 1e-15);
+        assert!(
+            (convert_weight(convert_weight(4.0, Meters, Feet), Feet, Meters) - 4.0).abs() < 1e-12
+        );
+    }
+
+    #[test]
+    fn conversion_constants_are_the_exact_definitions() {
+        assert_eq!(FEET_TO_METERS, 0.3048);
+        assert_eq!(LengthUnit::Feet.meters(), 0.3048);
+        assert_eq!(US_SURVEY_FEET_TO_METERS, 1200.0 / 3937.0);
+        assert!((LengthUnit::UsSurveyFeet.meters() * 3937.0 - 1200.0).abs() < 1e-12);
+        assert_eq!(LengthUnit::Meters.meters(), 1.0);
+    }
+
+    #[test]
+    fn changing_the_unit_converts_the_graph() {
+        let mut graph = Graph::default();
+        graph.add_vertex(0.0, 0.0, 100.0, true);
+        graph.add_vertex(10.0, 0.0, 100.0, false);
+        graph.add_vertex(10.0, 10.0, 95.0, false);
+        graph.add_edge(0, 1, 10.0, 0.1, 0.0, 1.0);
+        graph.add_edge(1, 2, 0.1, 10.0, -5.0, 1.0);
+        graph.add_edge(0, 2, 10.0, 10.0, -5.0, 2.0);
+        let metric = CoordinateSystem::default();
+        graph.set_coordinate_system(metric);
+        let before = graph.solve(60_000, 1e-12).unwrap();
+        assert_eq!(before.coordinate_system, Some(metric));
+
+        let imperial = CoordinateSystem {
+            units: LengthUnit::Feet,
+            ..metric
+        };
+        graph.set_coordinate_system(imperial);
+        assert_eq!(graph.coordinate_system(), Some(&imperial));
+        assert!((graph.x[1] - 10.0 / FEET_TO_METERS).abs() < 1e-9);
+        assert!((graph.z[2] - 95.0 / FEET_TO_METERS).abs() < 1e-9);
+        assert!((graph.weight[2] - 2.0 * FEET_TO_METERS * FEET_TO_METERS).abs() < 1e-12);
+
+        let after = graph.solve(60_000, 1e-12).unwrap();
+        for v in 0..3 {
+            let x = convert_length(after.x[v], LengthUnit::Feet, LengthUnit::Meters);
+            assert!((x - before.x[v]).abs() < 1e-9, "{x} vs {}", before.x[v]);
+        }
+        let standardized = |s: &crate::Solution, graph: &Graph| s.standardized_residuals(graph);
+        let metric_graph = {
+            let mut g = graph.clone();
+            g.set_coordinate_system(metric);
+            g
+        };
+        for (a, b) in standardized(&after, &graph)
+            .iter()
+            .zip(standardized(&before, &metric_graph))
+        {
+            assert!((a - b).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn utm_zones_map_to_epsg_codes() {
+        assert_eq!(
+            Projection::utm_wgs84(13, true),
+            Some(Projection::Epsg(32613))
+        );
+        assert_eq!(
+            Projection::utm_wgs84(1, false),
+            Some(Projection::Epsg(32701))
+        );
+        assert_eq!(Projection::utm_wgs84(0, true), None);
+        assert_eq!(Projection::utm_wgs84(61, true), None);
+        let system = CoordinateSystem {
+            projection: Projection::Epsg(32613),
+            ..CoordinateSystem::default()
+        };
+        assert_eq!(system.survex_cs().as_deref(), Some("EPSG:32613"));
+        assert_eq!(CoordinateSystem::default().survex_cs(), None);
+    }
+}
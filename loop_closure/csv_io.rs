@@ -0,0 +1,492 @@
+//! Plain CSV import/export, handy for quick experiments and human-editable fixtures.
+//!
+//! * Vertices file: `id, name, x, y, fixed` (fixed is `0`/`1` or `false`/`true`).
+//! * Edges file: `from, to, dx, dy, weight`, where `from`/`to` refer to vertex ids.
+//! * Solution file: `index, x, y`, where `index` is the row order of the vertices file.
+//!
+//! Ids are arbitrary unique tokens; vertices get dense indices in file order. Fields may be
+//! wrapped in double quotes (with `""` as an escaped quote) to contain the delimiter.
+
+use crate::{
+    COMPASS_ERR_INVALID_ARGUMENT, COMPASS_ERR_IO, COMPASS_ERR_PANIC, COMPASS_OK, Graph,
+    GraphContext, ImportError, Solution, write_message,
+};
+use std::collections::HashMap;
+use std::ffi::{CStr, c_char, c_int};
+use std::fmt::Write as _;
+use std::path::Path;
+
+/// Delimiter and header handling shared by the CSV reader and writer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CsvOptions {
+    /// Field delimiter.
+    pub delimiter: char,
+    /// Whether the first line is a header (skipped on read, written on write).
+    pub has_header: bool,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        CsvOptions {
+            delimiter: ',',
+            has_header: true,
+        }
+    }
+}
+
+impl Graph {
+    /// Builds a graph from a vertices CSV and an edges CSV with the default options.
+    pub fn from_csv(
+        vertices_path: impl AsRef<Path>,
+        edges_path: impl AsRef<Path>,
+    ) -> Result<Graph, ImportError> {
+        Graph::from_csv_with(vertices_path, edges_path, &CsvOptions::default())
+    }
+
+    /// Builds a graph from a vertices CSV and an edges CSV. Z coordinates and observations
+    /// are set to zero.
+    pub fn from_csv_with(
+        vertices_path: impl AsRef<Path>,
+        edges_path: impl AsRef<Path>,
+        options: &CsvOptions,
+    ) -> Result<Graph, ImportError> {
+        let mut graph = Graph::default();
+        let mut names = Vec::new();
+        let mut ids: HashMap<String, usize> = HashMap::new();
+
+        let path = vertices_path.as_ref();
+        for (line, fields) in read_records(path, options, 5)? {
+            let x = parse_number(path, line, &fields[2])?;
+            let y = parse_number(path, line, &fields[3])?;
+            let fixed = match fields[4].1.to_ascii_lowercase().as_str() {
+                "0" | "false" => false,
+                "1" | "true" => true,
+                _ => return Err(invalid_value(path, line, &fields[4])),
+            };
+            let v = graph.add_vertex(x, y, 0.0, fixed);
+            if ids.insert(fields[0].1.clone(), v).is_some() {
+                return Err(ImportError::Parse {
+                    path: path.to_path_buf(),
+                    line,
+                    column: fields[0].0,
+                    message: format!("duplicate vertex id: {}", fields[0].1),
+                });
+            }
+            names.push(fields[1].1.clone());
+        }
+
+        let path = edges_path.as_ref();
+        for (line, fields) in read_records(path, options, 5)? {
+            let vertex = |k: usize| -> Result<usize, ImportError> {
+                let (column, text) = &fields[k];
+                ids.get(text).copied().ok_or_else(|| ImportError::Parse {
+                    path: path.to_path_buf(),
+                    line,
+                    column: *column,
+                    message: format!("unknown vertex id: {text}"),
+                })
+            };
+            graph.add_edge(
+                vertex(0)?,
+                vertex(1)?,
+                parse_number(path, line, &fields[2])?,
+                parse_number(path, line, &fields[3])?,
+                0.0,
+                parse_number(path, line, &fields[4])?,
+            );
+        }
+
+        graph.names = Some(names);
+        Ok(graph)
+    }
+}
+
+impl Solution {
+    /// Renders the adjusted coordinates as `index, x, y` CSV text.
+    pub fn to_csv(&self, options: &CsvOptions) -> String {
+        let d = options.delimiter;
+        let mut out = String::new();
+        if options.has_header {
+            let _ = writeln!(out, "index{d}x{d}y");
+        }
+        for (i, (x, y)) in self.x.iter().zip(&self.y).enumerate() {
+            let _ = writeln!(out, "{i}{d}{x}{d}{y}");
+        }
+        out
+    }
+
+    /// Writes [`Solution::to_csv`] to `path`.
+    pub fn write_csv(&self, path: impl AsRef<Path>, options: &CsvOptions) -> std::io::Result<()> {
+        std::fs::write(path, self.to_csv(options))
+    }
+}
+
+/// Loads a graph from a vertices CSV and an edges CSV and returns a context handle.
+///
+/// # Arguments
+///
+/// * `vertices_path` / `edges_path` - NUL-terminated paths of the two files.
+/// * `delimiter` - Field delimiter (ASCII), e.g. `b','`.
+/// * `has_header` - 1 if the first line of each file is a header, 0 otherwise.
+/// * `err_buf` - Buffer receiving a NUL-terminated error message on failure. May be null.
+/// * `err_cap` - Capacity of `err_buf` in bytes.
+///
+/// # Returns
+///
+/// * A handle to release with [`crate::graph_free`], or null on failure.
+#[unsafe(no_mangle)]
+pub extern "C" fn graph_from_csv(
+    vertices_path: *const c_char,
+    edges_path: *const c_char,
+    delimiter: c_char,
+    has_header: c_int,
+    err_buf: *mut c_char,
+    err_cap: usize,
+) -> *mut GraphContext {
+    if vertices_path.is_null() || edges_path.is_null() {
+        write_message(err_buf, err_cap, "path is null");
+        return std::ptr::null_mut();
+    }
+    // Safety: The caller guarantees valid NUL-terminated strings.
+    let vertices_path = unsafe { CStr::from_ptr(vertices_path) }
+        .to_string_lossy()
+        .into_owned();
+    let edges_path = unsafe { CStr::from_ptr(edges_path) }
+        .to_string_lossy()
+        .into_owned();
+    let options = CsvOptions {
+        delimiter: delimiter as u8 as char,
+        has_header: has_header != 0,
+    };
+    match std::panic::catch_unwind(|| Graph::from_csv_with(&vertices_path, &edges_path, &options)) {
+        Ok(Ok(graph)) => GraphContext::into_raw(graph),
+        Ok(Err(err)) => {
+            write_message(err_buf, err_cap, &err.to_string());
+            std::ptr::null_mut()
+        }
+        Err(_) => {
+            write_message(err_buf, err_cap, "panic while importing CSV files");
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// Writes the current coordinates of the graph behind `handle` (adjusted after a solve,
+/// input before) as `index, x, y` CSV with a header line.
+#[unsafe(no_mangle)]
+pub extern "C" fn graph_write_csv(
+    handle: *const GraphContext,
+    path: *const c_char,
+    delimiter: c_char,
+    err_buf: *mut c_char,
+    err_cap: usize,
+) -> c_int {
+    let result = std::panic::catch_unwind(|| {
+        // Safety: We assume the caller guarantees a valid (or null) handle and path.
+        let Some(ctx) = (unsafe { handle.as_ref() }) else {
+            write_message(err_buf, err_cap, "handle is null");
+            return COMPASS_ERR_INVALID_ARGUMENT;
+        };
+        if path.is_null() {
+            write_message(err_buf, err_cap, "path is null");
+            return COMPASS_ERR_INVALID_ARGUMENT;
+        }
+        let path = unsafe { CStr::from_ptr(path) }
+            .to_string_lossy()
+            .into_owned();
+        let (x, y) = ctx.coordinates();
+        let solution = Solution {
+            x: x.to_vec(),
+            y: y.to_vec(),
+            ..Solution::default()
+        };
+        let options = CsvOptions {
+            delimiter: delimiter as u8 as char,
+            has_header: true,
+        };
+        match solution.write_csv(&path, &options) {
+            Ok(()) => COMPASS_OK,
+            Err(err) => {
+                write_message(err_buf, err_cap, &format!("{path}: {err}"));
+                COMPASS_ERR_IO
+            }
+        }
+    });
+
+    match result {
+        Ok(code) => code,
+        Err(_) => {
+            eprintln!("Panic caught in graph_write_csv");
+            COMPASS_ERR_PANIC
+        }
+    }
+}
+
+/// Reads the non-empty records of a CSV file as `(line, [(column, field)])`, checking that
+/// each has at least `min_fields` fields. Line and column numbers are 1-based, columns
+/// counted in characters as in the other importers.
+#[allow(clippy::type_complexity)]
+fn read_records(
+    path: &Path,
+    options: &CsvOptions,
+    min_fields: usize,
+) -> Result<Vec<(usize, Vec<(usize, String)>)>, ImportError> {
+    let text = std::fs::read_to_string(path).map_err(|err| ImportError::Io {
+        path: path.to_path_buf(),
+        message: err.to_string(),
+    })?;
+    let skip = usize::from(options.has_header);
+    let mut records = Vec::new();
+    for (i, raw) in text.lines().enumerate().skip(skip) {
+        if raw.trim().is_empty() {
+            continue;
+        }
+        let fields = split_record(raw, options.delimiter);
+        if fields.len() < min_fields {
+            return Err(ImportError::Parse {
+                path: path.to_path_buf(),
+                line: i + 1,
+                column: raw.chars().count() + 1,
+                message: format!("expected {min_fields} fields, got {}", fields.len()),
+            });
+        }
+        records.push((i + 1, fields));
+    }
+    Ok(records)
+}
+
+/// Splits one CSV line into trimmed fields with their 1-based starting column, in
+/// characters.
+fn split_record(line: &str, delimiter: char) -> Vec<(usize, String)> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut start = 1;
+    let mut quoted = false;
+    let mut chars = line.chars().enumerate().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek().is_some_and(|&(_, n)| n == '"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            c if c == delimiter && !quoted => {
+                fields.push((start, field.trim().to_string()));
+                field.clear();
+                start = i + 2;
+            }
+            c => field.push(c),
+        }
+    }
+    fields.push((start, field.trim().to_string()));
+    fields
+}
+
+fn invalid_value(path: &Path, line: usize, (column, text): &(usize, String)) -> ImportError {
+    ImportError::Parse {
+        path: path.to_path_buf(),
+        line,
+        column: *column,
+        message: format!("invalid value: {text}"),
+    }
+}
+
+fn parse_number(path: &Path, line: usize, field: &(usize, String)) -> Result<f64, ImportError> {
+    field
+        .1
+        .parse::<f64>()
+        .map_err(|_| invalid_value(path, line, field))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VERTICES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/loop_vertices.csv");
+    const EDGES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/loop_edges.csv");
+
+    type PathBufPair = (std::path::PathBuf, std::path::PathBuf);
+
+    fn files(vertices: &str, edges: &str) -> (tempfile::TempDir, PathBufPair) {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = (dir.path().join("v.csv"), dir.path().join("e.csv"));
+        std::fs::write(&paths.0, vertices).unwrap();
+        std::fs::write(&paths.1, edges).unwrap();
+        (dir, paths)
+    }
+
+    fn parse_error(vertices: &str, edges: &str, options: &CsvOptions) -> (usize, usize, String) {
+        let (_dir, (v, e)) = files(vertices, edges);
+        match Graph::from_csv_with(v, e, options) {
+            Err(ImportError::Parse {
+                line,
+                column,
+                message,
+                ..
+            }) => (line, column, message),
+            other => panic!("expected a parse error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn reads_the_fixture_and_closes_the_loop() {
+        let graph = Graph::from_csv(VERTICES, EDGES).unwrap();
+        assert_eq!(graph.num_vertices(), 4);
+        assert_eq!(graph.num_edges(), 4);
+        assert_eq!(graph.fixed, [true, false, false, false]);
+        assert_eq!(graph.names.as_ref().unwrap()[0], "Entrance, upper");
+        assert_eq!((graph.from[3], graph.to[3]), (3, 0));
+        assert_eq!(
+            (graph.dx[0], graph.dy[0], graph.weight[3]),
+            (10.02, 0.01, 0.5)
+        );
+
+        let solution = graph.solve(1000, 1e-12).unwrap();
+        assert_eq!((solution.x[0], solution.y[0]), (0.0, 0.0));
+        // Around a single loop the weighted residuals are all equal.
+        let residuals = solution.residuals(&graph);
+        let (wx, wy) = (residuals[0].0, residuals[0].1);
+        for (e, (rx, ry)) in residuals.iter().enumerate() {
+            assert!((graph.weight[e] * rx - wx).abs() < 1e-9);
+            assert!((graph.weight[e] * ry - wy).abs() < 1e-9);
+        }
+        // Misclosure 0.04 spread over a total inverse weight of 1 + 1 + 1 + 2.
+        assert!((wx + 0.04 / 5.0).abs() < 1e-9, "{wx}");
+    }
+
+    #[test]
+    fn honours_the_delimiter_and_header_options() {
+        let options = CsvOptions {
+            delimiter: ';',
+            has_header: false,
+        };
+        let (_dir, (v, e)) = files(
+            "1;\"a;\"\"b\"\"\";0;0;true\n\n2;c;1,5;0;FALSE\n",
+            "1;2;1;0;2\n",
+        );
+        let error = Graph::from_csv_with(&v, &e, &options).unwrap_err();
+        // `1,5` is not a number when the delimiter is `;`.
+        assert!(
+            error.to_string().ends_with(":3:5: invalid value: 1,5"),
+            "{error}"
+        );
+
+        std::fs::write(&v, "1;\"a;\"\"b\"\"\";0;0;true\n\n2;c;1.5;0;FALSE\n").unwrap();
+        let graph = Graph::from_csv_with(&v, &e, &options).unwrap();
+        assert_eq!(graph.names.unwrap(), ["a;\"b\"", "c"]);
+        assert_eq!(graph.x, [0.0, 1.5]);
+        assert_eq!(graph.weight, [2.0]);
+    }
+
+    #[test]
+    fn reports_the_position_of_malformed_rows() {
+        let header = "id,name,x,y,fixed\n";
+        let options = CsvOptions::default();
+        assert_eq!(
+            parse_error(&format!("{header}1,a,0,0,1\n1,b,0,0,0\n"), "", &options),
+            (3, 1, "duplicate vertex id: 1".to_string())
+        );
+        assert_eq!(
+            parse_error(&format!("{header}1,a,0,0,yes\n"), "", &options),
+            (2, 9, "invalid value: yes".to_string())
+        );
+        assert_eq!(
+            parse_error(&format!("{header}1,a,0,0\n"), "", &options),
+            (2, 8, "expected 5 fields, got 4".to_string())
+        );
+        assert_eq!(
+            parse_error(&format!("{header}1,a,0,0,1\n"), "h\n1, 9,1,0,1\n", &options),
+            (2, 3, "unknown vertex id: 9".to_string())
+        );
+        // Columns count characters, not bytes, past non-ASCII names.
+        assert_eq!(
+            parse_error(
+                &format!("{header}1,Gouffre Berger é,0,0,yes\n"),
+                "",
+                &options
+            ),
+            (2, 24, "invalid value: yes".to_string())
+        );
+        assert_eq!(
+            parse_error(&format!("{header}1,Höhle,0,0\n"), "", &options),
+            (2, 12, "expected 5 fields, got 4".to_string())
+        );
+    }
+
+    #[test]
+    fn writes_the_solution() {
+        let solution = Solution {
+            x: vec![0.0, 1.25],
+            y: vec![-2.0, 1e-9],
+            ..Solution::default()
+        };
+        assert_eq!(
+            solution.to_csv(&CsvOptions::default()),
+            "index,x,y\n0,0,-2\n1,1.25,0.000000001\n"
+        );
+        let options = CsvOptions {
+            delimiter: '\t',
+            has_header: false,
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("solution.tsv");
+        solution.write_csv(&path, &options).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "0\t0\t-2\n1\t1.25\t0.000000001\n"
+        );
+    }
+
+    #[test]
+    fn ffi_converts_a_pair_of_files() {
+        let vertices = std::ffi::CString::new(VERTICES).unwrap();
+        let edges = std::ffi::CString::new(EDGES).unwrap();
+        let mut buf = [0 as c_char; 256];
+        let handle = graph_from_csv(
+            vertices.as_ptr(),
+            edges.as_ptr(),
+            b',' as c_char,
+            1,
+            buf.as_mut_ptr(),
+            buf.len(),
+        );
+        assert!(!handle.is_null());
+        assert_eq!(crate::graph_num_edges(handle), 4);
+
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("out.csv");
+        let c_out = std::ffi::CString::new(out.to_str().unwrap()).unwrap();
+        let code = graph_write_csv(handle, c_out.as_ptr(), b',' as c_char, buf.as_mut_ptr(), 0);
+        assert_eq!(code, COMPASS_OK);
+        let text = std::fs::read_to_string(&out).unwrap();
+        assert_eq!(text.lines().nth(2), Some("1,10,0"));
+
+        let missing = dir.path().join("missing").join("out.csv");
+        let c_missing = std::ffi::CString::new(missing.to_str().unwrap()).unwrap();
+        let code = graph_write_csv(
+            handle,
+            c_missing.as_ptr(),
+            b',' as c_char,
+            buf.as_mut_ptr(),
+            buf.len(),
+        );
+        assert_eq!(code, COMPASS_ERR_IO);
+        crate::graph_free(handle);
+
+        let handle = graph_from_csv(
+            edges.as_ptr(),
+            edges.as_ptr(),
+            b',' as c_char,
+            1,
+            buf.as_mut_ptr(),
+            buf.len(),
+        );
+        assert!(handle.is_null());
+        // Safety: The library NUL-terminates the message.
+        let message = unsafe { CStr::from_ptr(buf.as_ptr()) }.to_string_lossy();
+        assert!(
+            message.ends_with("loop_edges.csv:5:18: invalid value: 0.5"),
+            "{message}"
+        );
+    }
+}
@@ -0,0 +1,471 @@
+//! Effective datum of a solve, and S-transformation of a free network to a chosen datum.
+//!
+//! A network without a fixed vertex, or one held only by a soft centroid constraint (see
+//! [`crate::centroid`]), has its position defined statistically: the minimum-norm solution of
+//! a free network keeps the centroid of its initial guess, and a soft centroid is weighed
+//! against the edges. Options that change the solution change that datum too, which shows as
+//! a shift of the whole network between runs. The effective datum makes it visible: the
+//! weighted rigid transformation, translation and rotation, that best maps the initial
+//! coordinates of the datum vertices onto the adjusted ones. The datum vertices are those of
+//! [`SolveOptions::centroid_weights`], weighted as there, or else the vertices of the free
+//! networks, equally weighted; with fixed vertices only, the effective datum is zero.
+//!
+//! An S-transformation expresses a free network in a minimal-constraint datum of the caller's
+//! choice instead, e.g. "hold station 0 and the azimuth to station 17", as a post-processing
+//! rigid transformation of the network: translated to bring the station back to its initial
+//! coordinates, then rotated about it to bring the azimuth back to its initial value. The
+//! translation leaves the residuals alone. The observed `(dx, dy)` orient the network
+//! already, so holding an azimuth rotates it against them and changes the residuals: it is
+//! meant to compare with tools that hold one.
+
+use crate::{
+    COMPASS_ERR_INVALID_ARGUMENT, Graph, GraphView, NormalEquations, Solution, SolveError,
+    SolveOptions, SolveStats,
+};
+use std::collections::VecDeque;
+
+/// Weighted rigid transformation from the initial to the adjusted coordinates of the datum
+/// vertices, see the [module documentation](self).
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct EffectiveDatum {
+    /// Shift of the weighted centroid of the datum vertices along X.
+    pub shift_x: f64,
+    /// Same as [`EffectiveDatum::shift_x`] along Y.
+    pub shift_y: f64,
+    /// Rotation about that centroid, in radians counterclockwise.
+    pub rotation: f64,
+}
+
+impl EffectiveDatum {
+    /// Best rigid transformation, in the weighted least squares sense, from the `initial`
+    /// to the `adjusted` coordinates of the vertices of positive `weight`. Zero without any.
+    pub fn fit(
+        initial: (&[f64], &[f64]),
+        adjusted: (&[f64], &[f64]),
+        weight: &dyn Fn(usize) -> f64,
+    ) -> EffectiveDatum {
+        let ((x0, y0), (x, y)) = (initial, adjusted);
+        let vertices: Vec<(usize, f64)> = (0..x0.len())
+            .map(|i| (i, weight(i)))
+            .filter(|&(_, w)| w > 0.0)
+            .collect();
+        let total: f64 = vertices.iter().map(|&(_, w)| w).sum();
+        if total <= 0.0 {
+            return EffectiveDatum::default();
+        }
+        let mean = |c: &[f64]| vertices.iter().map(|&(i, w)| w * c[i]).sum::<f64>() / total;
+        let (px, py, qx, qy) = (mean(x0), mean(y0), mean(x), mean(y));
+        let (mut dot, mut cross) = (0.0, 0.0);
+        for &(i, w) in &vertices {
+            let (ax, ay) = (x0[i] - px, y0[i] - py);
+            let (bx, by) = (x[i] - qx, y[i] - qy);
+            dot += w * (ax * bx + ay * by);
+            cross += w * (ax * by - ay * bx);
+        }
+        EffectiveDatum {
+            shift_x: qx - px,
+            shift_y: qy - py,
+            rotation: cross.atan2(dot),
+        }
+    }
+
+    /// Copies the transformation into `stats`.
+    pub(crate) fn record(self, stats: &mut SolveStats) {
+        stats.datum_shift_x = self.shift_x;
+        stats.datum_shift_y = self.shift_y;
+        stats.datum_rotation = self.rotation;
+    }
+}
+
+/// Minimal-constraint datum of a free network, see [`Solution::s_transform`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DatumDefinition {
+    /// Vertex held at its initial coordinates.
+    pub station: usize,
+    /// Vertex whose azimuth from `station` is held at its initial value, if any.
+    pub azimuth_to: Option<usize>,
+}
+
+impl DatumDefinition {
+    /// Definition of the S-transformation fields of `options`, `None` without one.
+    fn from_options(options: &SolveOptions) -> Result<Option<DatumDefinition>, String> {
+        let index = |name: &str, value: i32| {
+            usize::try_from(value).map_err(|_| format!("{name} is negative ({value})"))
+        };
+        let station = || index("datum_station", options.datum_station);
+        match options.s_transform {
+            0 => Ok(None),
+            1 => Ok(Some(DatumDefinition {
+                station: station()?,
+                azimuth_to: None,
+            })),
+            2 => Ok(Some(DatumDefinition {
+                station: station()?,
+                azimuth_to: Some(index(
+                    "datum_azimuth_station",
+                    options.datum_azimuth_station,
+                )?),
+            })),
+            code => Err(format!("unknown s_transform {code}")),
+        }
+    }
+}
+
+/// S-transformation planned before a solve: the datum and the vertices of its free network.
+pub(crate) struct STransform {
+    definition: DatumDefinition,
+    vertices: Vec<usize>,
+}
+
+impl STransform {
+    /// The S-transformation asked for by `options` on `graph`, whose coordinates are
+    /// `initial`, or why it cannot be done: a station out of range or in a network with a
+    /// fixed vertex, or an azimuth station elsewhere or at the same position.
+    pub(crate) fn from_options(
+        options: &SolveOptions,
+        graph: &GraphView,
+        initial: (&[f64], &[f64]),
+    ) -> Result<Option<STransform>, String> {
+        match DatumDefinition::from_options(options)? {
+            None => Ok(None),
+            Some(definition) => STransform::new(definition, graph, initial).map(Some),
+        }
+    }
+
+    fn new(
+        definition: DatumDefinition,
+        graph: &GraphView,
+        (x, y): (&[f64], &[f64]),
+    ) -> Result<STransform, String> {
+        let n = graph.fixed.len();
+        let DatumDefinition {
+            station,
+            azimuth_to,
+        } = definition;
+        if let Some(v) = [Some(station), azimuth_to]
+            .into_iter()
+            .flatten()
+            .find(|&v| v >= n)
+        {
+            return Err(format!("datum vertex {v} out of range"));
+        }
+        let vertices = network(graph, station);
+        if let Some(&v) = vertices.iter().find(|&&v| graph.fixed[v] != 0) {
+            return Err(format!("datum vertex {station} is anchored by vertex {v}"));
+        }
+        if let Some(other) = azimuth_to {
+            if !vertices.contains(&other) {
+                return Err(format!(
+                    "azimuth vertex {other} is not in the network of {station}"
+                ));
+            }
+            if x[other] == x[station] && y[other] == y[station] {
+                return Err(format!("azimuth vertex {other} lies on vertex {station}"));
+            }
+        }
+        Ok(STransform {
+            definition,
+            vertices,
+        })
+    }
+
+    /// Moves the network rigidly from the `adjusted` coordinates so that the datum holds
+    /// against the `initial` ones.
+    pub(crate) fn apply(&self, initial: (&[f64], &[f64]), adjusted: (&mut [f64], &mut [f64])) {
+        let ((x0, y0), (x, y)) = (initial, adjusted);
+        let h = self.definition.station;
+        let (hx, hy) = (x[h], y[h]);
+        let rotation = match self.definition.azimuth_to {
+            None => 0.0,
+            Some(a) => {
+                let before = (y0[a] - y0[h]).atan2(x0[a] - x0[h]);
+                before - (y[a] - hy).atan2(x[a] - hx)
+            }
+        };
+        let (sin, cos) = rotation.sin_cos();
+        for &v in &self.vertices {
+            let (dx, dy) = (x[v] - hx, y[v] - hy);
+            x[v] = x0[h] + (cos * dx - sin * dy);
+            y[v] = y0[h] + (sin * dx + cos * dy);
+        }
+    }
+}
+
+/// Vertices reachable from `start` over the enabled edges of nonzero weight, `start` first.
+fn network(graph: &GraphView, start: usize) -> Vec<usize> {
+    let n = graph.fixed.len();
+    let mut neighbors = vec![Vec::new(); n];
+    for e in (0..graph.from.len()).filter(|&e| graph.is_enabled(e) && graph.weight[e] != 0.0) {
+        let (u, v) = (graph.from[e] as usize, graph.to[e] as usize);
+        neighbors[u].push(v);
+        neighbors[v].push(u);
+    }
+    let mut seen = vec![false; n];
+    seen[start] = true;
+    let mut order = vec![start];
+    let mut queue = VecDeque::from([start]);
+    while let Some(u) = queue.pop_front() {
+        for &v in &neighbors[u] {
+            if !seen[v] {
+                seen[v] = true;
+                order.push(v);
+                queue.push_back(v);
+            }
+        }
+    }
+    order
+}
+
+/// Per-vertex flags of the vertices in the free networks of `system`.
+pub(crate) fn free_network_vertices(system: &NormalEquations) -> Vec<bool> {
+    let mut in_network = vec![false; system.size()];
+    for &row in system.null_space.components.iter().flatten() {
+        in_network[row] = true;
+    }
+    system
+        .mapping
+        .iter()
+        .map(|row| row.is_some_and(|row| in_network[row]))
+        .collect()
+}
+
+impl Solution {
+    /// Expresses the free network of `definition.station` in that datum, see the
+    /// [module documentation](self). The coordinates of `graph` are the initial ones.
+    /// Returns [`COMPASS_ERR_INVALID_ARGUMENT`] when the station is anchored by a fixed
+    /// vertex, or the azimuth station lies elsewhere or on it.
+    pub fn s_transform(
+        &mut self,
+        graph: &Graph,
+        definition: DatumDefinition,
+    ) -> Result<(), SolveError> {
+        let initial = (&graph.x[..], &graph.y[..]);
+        let transform = graph.with_view(&[], |view| STransform::new(definition, view, initial));
+        let transform = transform.map_err(|message| SolveError {
+            code: COMPASS_ERR_INVALID_ARGUMENT,
+            message,
+        })?;
+        transform.apply(initial, (&mut self.x, &mut self.y));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{COMPASS_OK, solve_graph_least_squares_ex};
+    use std::ffi::{c_char, c_int};
+
+    /// A loop of four stations without a fixed vertex, misclosed by (0.04, -0.03), and a
+    /// spur to station 4.
+    fn free_loop() -> Graph {
+        let mut graph = Graph::default();
+        for (x, y) in [
+            (0.0, 0.0),
+            (10.1, 0.2),
+            (9.8, 10.1),
+            (-0.2, 9.9),
+            (20.0, 0.5),
+        ] {
+            graph.add_vertex(x, y, 0.0, false);
+        }
+        for (u, v, dx, dy) in [
+            (0, 1, 10.0, 0.0),
+            (1, 2, 0.0, 10.0),
+            (2, 3, -10.0, 0.0),
+            (3, 0, 0.04, -10.03),
+            (1, 4, 10.0, 0.0),
+        ] {
+            graph.add_edge(u, v, dx, dy, 0.0, 1.0);
+        }
+        graph
+    }
+
+    #[test]
+    fn fit_recovers_a_rigid_motion() {
+        let (x0, y0) = (vec![0.0, 4.0, 1.0, -2.0], vec![0.0, 1.0, 5.0, 2.0]);
+        let (angle, (tx, ty)) = (0.3_f64, (1.5, -2.0));
+        let (sin, cos) = angle.sin_cos();
+        let x: Vec<f64> = (0..4).map(|i| cos * x0[i] - sin * y0[i] + tx).collect();
+        let y: Vec<f64> = (0..4).map(|i| sin * x0[i] + cos * y0[i] + ty).collect();
+        let datum = EffectiveDatum::fit((&x0, &y0), (&x, &y), &|_| 1.0);
+        assert!((datum.rotation - angle).abs() < 1e-12, "{datum:?}");
+        // The centroid (0.75, 2) moves by the rotation about the origin plus the translation.
+        let shift_x = cos * 0.75 - sin * 2.0 + tx - 0.75;
+        let shift_y = sin * 0.75 + cos * 2.0 + ty - 2.0;
+        assert!((datum.shift_x - shift_x).abs() < 1e-12, "{datum:?}");
+        assert!((datum.shift_y - shift_y).abs() < 1e-12, "{datum:?}");
+        assert_eq!(
+            EffectiveDatum::fit((&x0, &y0), (&x, &y), &|_| 0.0),
+            EffectiveDatum::default()
+        );
+    }
+
+    #[test]
+    fn s_transformed_solution_honors_the_datum() {
+        let graph = free_loop();
+        let solution = graph.solve(1000, 1e-12).unwrap();
+        // The free network keeps the centroid of its initial guess.
+        assert!(solution.stats.datum_shift_x.abs() < 1e-9);
+        assert!(solution.stats.datum_shift_y.abs() < 1e-9);
+
+        let mut held = solution.clone();
+        let station = DatumDefinition {
+            station: 2,
+            azimuth_to: None,
+        };
+        held.s_transform(&graph, station).unwrap();
+        assert_eq!((held.x[2], held.y[2]), (graph.x[2], graph.y[2]));
+        // A translation, which leaves the residuals alone.
+        for (a, b) in solution
+            .residuals(&graph)
+            .iter()
+            .zip(held.residuals(&graph))
+        {
+            assert!((a.0 - b.0).abs() < 1e-12 && (a.1 - b.1).abs() < 1e-12);
+        }
+
+        let mut oriented = solution.clone();
+        let azimuth = DatumDefinition {
+            station: 0,
+            azimuth_to: Some(4),
+        };
+        oriented.s_transform(&graph, azimuth).unwrap();
+        assert_eq!((oriented.x[0], oriented.y[0]), (graph.x[0], graph.y[0]));
+        let bearing = |x: &[f64], y: &[f64]| (y[4] - y[0]).atan2(x[4] - x[0]);
+        let before = bearing(&graph.x, &graph.y);
+        assert!((bearing(&oriented.x, &oriented.y) - before).abs() < 1e-14);
+        // The shape of the network is kept.
+        let distance = |s: &Solution, u: usize, v: usize| (s.x[v] - s.x[u]).hypot(s.y[v] - s.y[u]);
+        assert!((distance(&oriented, 2, 4) - distance(&solution, 2, 4)).abs() < 1e-12);
+
+        let mut anchored = graph.clone();
+        anchored.fixed[3] = true;
+        let err = solution
+            .clone()
+            .s_transform(&anchored, station)
+            .unwrap_err();
+        assert_eq!(err.message, "datum vertex 2 is anchored by vertex 3");
+    }
+
+    /// Solves `graph` through the FFI with `options`, returning the code, the coordinates,
+    /// the stats and the error message.
+    fn solve_ffi(
+        graph: &Graph,
+        options: &SolveOptions,
+    ) -> (c_int, Vec<f64>, Vec<f64>, SolveStats, String) {
+        let fixed: Vec<c_int> = graph.fixed.iter().map(|&f| f as c_int).collect();
+        let from: Vec<c_int> = graph.from.iter().map(|&v| v as c_int).collect();
+        let to: Vec<c_int> = graph.to.iter().map(|&v| v as c_int).collect();
+        let (mut x, mut y) = (graph.x.clone(), graph.y.clone());
+        let mut message = [0 as c_char; 64];
+        let mut stats = SolveStats {
+            struct_size: size_of::<SolveStats>(),
+            ..SolveStats::default()
+        };
+        let options = SolveOptions {
+            tolerance: 1e-12,
+            error_message: message.as_mut_ptr(),
+            error_capacity: message.len() as c_int,
+            ..*options
+        };
+        let code = solve_graph_least_squares_ex(
+            graph.num_vertices() as c_int,
+            x.as_mut_ptr(),
+            y.as_mut_ptr(),
+            fixed.as_ptr(),
+            graph.num_edges() as c_int,
+            from.as_ptr(),
+            to.as_ptr(),
+            graph.dx.as_ptr(),
+            graph.dy.as_ptr(),
+            graph.weight.as_ptr(),
+            &options,
+            &mut stats,
+        );
+        let message = unsafe { std::ffi::CStr::from_ptr(message.as_ptr()) };
+        (code, x, y, stats, message.to_str().unwrap().to_string())
+    }
+
+    #[test]
+    fn ffi_reports_the_datum_of_a_weakly_anchored_network() {
+        // The loop hangs from a fixed station by a weak shot that disagrees with the initial
+        // guess, and drifts towards it.
+        let mut graph = free_loop();
+        graph.add_vertex(-10.0, 0.0, 0.0, true);
+        graph.add_edge(5, 0, 10.3, 0.2, 0.0, 0.01);
+        let weights = [1.0, 1.0, 1.0, 1.0, 1.0, 0.0];
+        let options = SolveOptions {
+            centroid_weights: weights.as_ptr(),
+            ..SolveOptions::default()
+        };
+        let (code, _, _, stats, _) = solve_ffi(&graph, &options);
+        assert_eq!(code, COMPASS_OK);
+        assert!(
+            (stats.datum_shift_x - stats.centroid_shift_x).abs() < 1e-9,
+            "{stats:?}"
+        );
+        assert!(
+            (stats.datum_shift_y - stats.centroid_shift_y).abs() < 1e-9,
+            "{stats:?}"
+        );
+        assert!(stats.datum_shift_x > 0.1, "{stats:?}");
+
+        // Without a datum vertex there is nothing to report.
+        let (_, _, _, stats, _) = solve_ffi(&graph, &SolveOptions::default());
+        assert_eq!(
+            (
+                stats.datum_shift_x,
+                stats.datum_shift_y,
+                stats.datum_rotation
+            ),
+            (0.0, 0.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn ffi_applies_the_s_transformation() {
+        let graph = free_loop();
+        let options = SolveOptions {
+            s_transform: 2,
+            datum_station: 0,
+            datum_azimuth_station: 4,
+            ..SolveOptions::default()
+        };
+        let (code, x, y, stats, _) = solve_ffi(&graph, &options);
+        assert_eq!(code, COMPASS_OK);
+        assert_eq!((x[0], y[0]), (graph.x[0], graph.y[0]));
+        let (_, x_free, y_free, _, _) = solve_ffi(&graph, &SolveOptions::default());
+        let mut expected = Solution {
+            x: x_free,
+            y: y_free,
+            ..Solution::default()
+        };
+        let azimuth = DatumDefinition {
+            station: 0,
+            azimuth_to: Some(4),
+        };
+        expected.s_transform(&graph, azimuth).unwrap();
+        assert_eq!((x, y), (expected.x, expected.y));
+        // The effective datum is that of the solve, before the S-transformation.
+        assert!(stats.datum_shift_x.abs() < 1e-9 && stats.datum_shift_y.abs() < 1e-9);
+
+        for (s_transform, datum_station, datum_azimuth_station, expected) in [
+            (3, 0, 0, "unknown s_transform 3"),
+            (1, -1, 0, "datum_station is negative (-1)"),
+            (1, 9, 0, "datum vertex 9 out of range"),
+            (2, 0, 0, "azimuth vertex 0 lies on vertex 0"),
+        ] {
+            let options = SolveOptions {
+                s_transform,
+                datum_station,
+                datum_azimuth_station,
+                ..SolveOptions::default()
+            };
+            let (code, x, _, _, message) = solve_ffi(&graph, &options);
+            assert_eq!(code, COMPASS_ERR_INVALID_ARGUMENT);
+            assert_eq!(message, expected);
+            assert_eq!(x, graph.x);
+        }
+    }
+}
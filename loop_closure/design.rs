@@ -0,0 +1,329 @@
+//! Network design: how much planned observations would shrink the uncertainty of stations.
+//!
+//! With the edge weights as inverse variances, the covariance of the adjusted coordinates of
+//! a free vertex is its diagonal entry of `N⁻¹`, the inverse of the normal matrix, the same
+//! for X and Y: its error ellipse is a circle of radius `sigma = sqrt((N⁻¹)_ii)`. A planned
+//! edge of weight `w` adds its terms to `N` like any edge, whatever its observation, so
+//! [`GraphContext::simulate_added_edges`] compares the sigmas of the selected stations under
+//! the cached normal matrix and under a copy with the candidate terms added. The entries are
+//! probed column by column with Jacobi-preconditioned CG, as the exact traces of
+//! [`crate::variance`] are; no coordinates, weights or cached system change.
+//!
+//! Fixed vertices have a sigma of 0. Vertices of a free network, which no anchor holds, have
+//! an infinite sigma until a candidate ties them to one.
+
+use crate::weight_update::{add_to_cell, edge_terms};
+use crate::{
+    COMPASS_ERR_INVALID_ARGUMENT, COMPASS_ERR_PANIC, COMPASS_OK, GraphContext, NullSpace,
+    PreconditionerOp, SolveError, SolveOptions, inverse_diagonal, raw_slice, solve_cg,
+    write_column,
+};
+use nalgebra::DVector;
+use nalgebra_sparse::{CooMatrix, CsrMatrix};
+use std::ffi::{c_double, c_int};
+
+/// A planned observation between two stations.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CandidateEdge {
+    pub from: usize,
+    pub to: usize,
+    /// Weight of the observation, the inverse of its expected variance.
+    pub weight: f64,
+}
+
+/// Uncertainty of one station with and without the candidate edges.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UncertaintyDelta {
+    pub vertex: usize,
+    /// Standard deviation of each coordinate with the current edges.
+    pub sigma_before: f64,
+    /// Standard deviation of each coordinate with the candidate edges added.
+    pub sigma_after: f64,
+}
+
+impl GraphContext {
+    /// Standard deviations of the `stations` with and without the `candidates`, see the
+    /// [module documentation](self). The normal equations are assembled if not cached.
+    ///
+    /// Fails with [`COMPASS_ERR_INVALID_ARGUMENT`] for a vertex out of range, a negative or
+    /// non-finite weight, or a candidate at a free vertex without enabled edges.
+    pub fn simulate_added_edges(
+        &mut self,
+        candidates: &[CandidateEdge],
+        stations: &[usize],
+    ) -> Result<Vec<UncertaintyDelta>, SolveError> {
+        let invalid = |message: String| SolveError {
+            code: COMPASS_ERR_INVALID_ARGUMENT,
+            message,
+        };
+        let n = self.graph.num_vertices();
+        if let Some(&v) = stations.iter().find(|&&v| v >= n) {
+            return Err(invalid(format!("vertex {v} out of range")));
+        }
+        if self.system.is_none() {
+            self.system = Some(self.assemble()?);
+        }
+        let Some(system) = &self.system else {
+            unreachable!("assembled above");
+        };
+        let mut terms = Vec::new();
+        for (c, candidate) in candidates.iter().enumerate() {
+            let CandidateEdge { from, to, weight } = *candidate;
+            if from >= n || to >= n {
+                return Err(invalid(format!("candidate {c} ends out of range")));
+            }
+            if !(weight.is_finite() && weight >= 0.0) {
+                return Err(invalid(format!(
+                    "weight {weight} of candidate {c} is negative or not finite"
+                )));
+            }
+            let unknown = |v: usize| match (self.graph.fixed[v], system.mapping[v]) {
+                (false, None) => Err(invalid(format!("vertex {v} has no enabled edge"))),
+                (_, row) => Ok(row),
+            };
+            terms.extend(edge_terms((unknown(from)?, unknown(to)?), weight));
+        }
+
+        let mut matrix = system.matrix.clone();
+        let missing: Vec<(usize, usize, f64)> = terms
+            .into_iter()
+            .filter(|&(i, j, w)| !add_to_cell(&mut matrix, i, j, w))
+            .collect();
+        if !missing.is_empty() {
+            let mut coo = CooMatrix::from(&matrix);
+            for (i, j, w) in missing {
+                coo.push(i, j, w);
+            }
+            matrix = CsrMatrix::from(&coo);
+        }
+        let null_space = NullSpace::of(&matrix);
+
+        let options = SolveOptions::default();
+        let iterations = options.iterations as usize;
+        let sigmas = |a: &CsrMatrix<f64>, null_space: &NullSpace| {
+            let jacobi = PreconditionerOp::Jacobi(inverse_diagonal(a));
+            let mut free = vec![true; a.nrows()];
+            for &row in null_space.components.iter().flatten() {
+                free[row] = false;
+            }
+            stations
+                .iter()
+                .map(|&v| match system.mapping[v] {
+                    _ if self.graph.fixed[v] => 0.0,
+                    Some(row) if free[row] => {
+                        let unit = DVector::from_fn(a.nrows(), |i, _| (i == row) as u8 as f64);
+                        let zero = DVector::zeros(a.nrows());
+                        let (column, _) = solve_cg(
+                            a,
+                            &unit,
+                            &zero,
+                            Some(&jacobi),
+                            null_space,
+                            iterations,
+                            options.tolerance.into(),
+                            &|| false,
+                        );
+                        column[row].max(0.0).sqrt()
+                    }
+                    _ => f64::INFINITY,
+                })
+                .collect::<Vec<f64>>()
+        };
+        let before = sigmas(&system.matrix, &system.null_space);
+        let after = sigmas(&matrix, &null_space);
+        Ok(stations
+            .iter()
+            .zip(before.into_iter().zip(after))
+            .map(|(&vertex, (sigma_before, sigma_after))| UncertaintyDelta {
+                vertex,
+                sigma_before,
+                sigma_after,
+            })
+            .collect())
+    }
+}
+
+/// Standard deviations of `num_stations` stations of the graph behind `handle` with and
+/// without `num_candidates` planned edges, see [`GraphContext::simulate_added_edges`]. The
+/// candidates are given by the parallel arrays `from`, `to` and `weight`. `out_sigma_before`
+/// and `out_sigma_after` receive one value per station, in the order of `stations`; either
+/// may be null to skip it.
+///
+/// # Returns
+///
+/// * [`COMPASS_OK`] on success.
+/// * [`COMPASS_ERR_INVALID_ARGUMENT`] for a null handle, a negative count or an invalid
+///   candidate or station; nothing is written.
+#[unsafe(no_mangle)]
+#[allow(clippy::too_many_arguments)]
+pub extern "C" fn graph_simulate_added_edges(
+    handle: *mut GraphContext,
+    num_candidates: c_int,
+    from: *const c_int,
+    to: *const c_int,
+    weight: *const c_double,
+    num_stations: c_int,
+    stations: *const c_int,
+    out_sigma_before: *mut c_double,
+    out_sigma_after: *mut c_double,
+) -> c_int {
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        // Safety: We assume the caller guarantees a valid (or null) handle.
+        let (Some(ctx), Ok(m), Ok(k)) = (
+            unsafe { handle.as_mut() },
+            usize::try_from(num_candidates),
+            usize::try_from(num_stations),
+        ) else {
+            return COMPASS_ERR_INVALID_ARGUMENT;
+        };
+        // Safety: The caller guarantees arrays of `num_candidates` and `num_stations` elements.
+        let (from, to, weight, stations) = unsafe {
+            (
+                raw_slice(from, m),
+                raw_slice(to, m),
+                raw_slice(weight, m),
+                raw_slice(stations, k),
+            )
+        };
+        // Negative indices wrap past any vertex count, to be rejected as out of range.
+        let index = |v: c_int| usize::try_from(v).unwrap_or(usize::MAX);
+        let candidates: Vec<CandidateEdge> = (0..m)
+            .map(|c| CandidateEdge {
+                from: index(from[c]),
+                to: index(to[c]),
+                weight: weight[c],
+            })
+            .collect();
+        let stations: Vec<usize> = stations.iter().map(|&v| index(v)).collect();
+        match ctx.simulate_added_edges(&candidates, &stations) {
+            Ok(deltas) => {
+                write_column(out_sigma_before, &deltas, |d| d.sigma_before);
+                write_column(out_sigma_after, &deltas, |d| d.sigma_after);
+                COMPASS_OK
+            }
+            Err(err) => err.code,
+        }
+    }));
+
+    result.unwrap_or_else(|_| {
+        eprintln!("Panic caught in graph_simulate_added_edges");
+        COMPASS_ERR_PANIC
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Graph, graph_free};
+    use nalgebra::DMatrix;
+
+    /// Traverse 0-1-2-3 from the anchor 0, and the free pair 4-5.
+    fn network() -> Graph {
+        let mut graph = Graph::default();
+        for v in 0..6 {
+            graph.add_vertex(v as f64, 0.0, 0.0, v == 0);
+        }
+        for (u, v, w) in [(0, 1, 1.0), (1, 2, 2.0), (2, 3, 0.5), (4, 5, 1.0)] {
+            graph.add_edge(u, v, 1.0, 0.0, 0.0, w);
+        }
+        graph
+    }
+
+    /// `sqrt(diag(N⁻¹))` of the unknowns 1, 2, 3 of `graph`, by dense inversion.
+    fn direct(graph: &Graph) -> Vec<f64> {
+        let mut n = DMatrix::<f64>::zeros(3, 3);
+        for e in 0..graph.num_edges() {
+            let (u, v, w) = (graph.from[e], graph.to[e], graph.weight[e]);
+            let row = |v: usize| (1..4).contains(&v).then(|| v - 1);
+            for (a, b) in [(u, v), (v, u)] {
+                if let Some(i) = row(a) {
+                    n[(i, i)] += w;
+                    if let Some(j) = row(b) {
+                        n[(i, j)] -= w;
+                    }
+                }
+            }
+        }
+        let inverse = n.try_inverse().unwrap();
+        (0..3).map(|i| inverse[(i, i)].sqrt()).collect()
+    }
+
+    #[test]
+    fn sigmas_match_a_direct_inversion() {
+        let graph = network();
+        let handle = GraphContext::into_raw(graph.clone());
+        let ctx = unsafe { &mut *handle };
+        // A tie from the end of the traverse back to the anchor, a new cell 1-3, and one
+        // holding the free pair.
+        let candidates = [
+            CandidateEdge {
+                from: 3,
+                to: 0,
+                weight: 1.0,
+            },
+            CandidateEdge {
+                from: 1,
+                to: 3,
+                weight: 4.0,
+            },
+            CandidateEdge {
+                from: 5,
+                to: 3,
+                weight: 1.0,
+            },
+        ];
+        let deltas = ctx
+            .simulate_added_edges(&candidates, &[1, 2, 3, 0, 4])
+            .unwrap();
+
+        // The free pair hangs off 3 and leaves the sigmas of the traverse alone.
+        let mut planned = graph.clone();
+        for c in &candidates[..2] {
+            planned.add_edge(c.from, c.to, 0.0, 0.0, 0.0, c.weight);
+        }
+        let (before, after) = (direct(&graph), direct(&planned));
+        for (k, delta) in deltas[..3].iter().enumerate() {
+            assert_eq!(delta.vertex, k + 1);
+            assert!((delta.sigma_before - before[k]).abs() < 1e-6 * before[k]);
+            assert!((delta.sigma_after - after[k]).abs() < 1e-6 * after[k]);
+            assert!(delta.sigma_after < delta.sigma_before);
+        }
+        assert_eq!((deltas[3].sigma_before, deltas[3].sigma_after), (0.0, 0.0));
+        assert_eq!(deltas[4].sigma_before, f64::INFINITY);
+        assert!(deltas[4].sigma_after.is_finite());
+        // Nothing is observed for real.
+        assert_eq!(ctx.coordinates().0, graph.x);
+        assert_eq!(ctx.system.as_ref().unwrap().matrix.nnz(), 11);
+
+        let (mut sigma_before, mut sigma_after) = ([0.0; 2], [0.0; 2]);
+        let code = graph_simulate_added_edges(
+            handle,
+            1,
+            &3,
+            &0,
+            &1.0,
+            2,
+            [1, 3].as_ptr(),
+            sigma_before.as_mut_ptr(),
+            sigma_after.as_mut_ptr(),
+        );
+        assert_eq!(code, COMPASS_OK);
+        assert!((sigma_before[1] - before[2]).abs() < 1e-6 * before[2]);
+        assert!(sigma_after[1] < sigma_before[1]);
+        for (to, weight) in [(6, 1.0), (-1, 1.0), (2, -1.0)] {
+            let code = graph_simulate_added_edges(
+                handle,
+                1,
+                &3,
+                &to,
+                &weight,
+                0,
+                std::ptr::null(),
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+            );
+            assert_eq!(code, COMPASS_ERR_INVALID_ARGUMENT);
+        }
+        graph_free(handle);
+    }
+}
@@ -0,0 +1,436 @@
+//! Comparison of two adjustments of the same graph, e.g. before and after changing weights
+//! or removing a blunder.
+//!
+//! [`Solution::diff`] compares a solution with a later one: the displacement of each station
+//! and the change in the residual length of each edge, with their largest and mean values.
+//! Stations with a non-finite coordinate in either solution, such as passive vertices that
+//! could not be placed, are listed but left out of the statistics, and so are their edges.
+
+use crate::{
+    COMPASS_ERR_INVALID_ARGUMENT, COMPASS_ERR_PANIC, COMPASS_OK, Graph, Solution, SolveError,
+    check_edges, checked_ends, raw_slice, raw_slice_mut, write_message,
+};
+use std::ffi::{c_char, c_double, c_int};
+
+/// Number of stations and edges listed by [`SolutionDiff::most_moved`] and
+/// [`SolutionDiff::largest_residual_changes`] in the adjustment report.
+pub const MOST_MOVED: usize = 10;
+
+/// Largest and mean changes between two solutions, see [`Solution::diff`].
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DiffSummary {
+    /// Number of stations with finite coordinates in both solutions.
+    pub stations: c_int,
+    /// Largest displacement of a station.
+    pub max_displacement: c_double,
+    /// Station of [`DiffSummary::max_displacement`], -1 if no station was compared.
+    pub max_displacement_vertex: c_int,
+    /// Mean displacement of the compared stations.
+    pub mean_displacement: c_double,
+    /// Number of edges between two compared stations.
+    pub edges: c_int,
+    /// Largest absolute change in the residual length of an edge.
+    pub max_residual_change: c_double,
+    /// Edge of [`DiffSummary::max_residual_change`], -1 if no edge was compared.
+    pub max_residual_change_edge: c_int,
+    /// Mean absolute change in the residual length of the compared edges.
+    pub mean_residual_change: c_double,
+}
+
+impl Default for DiffSummary {
+    fn default() -> Self {
+        DiffSummary {
+            stations: 0,
+            max_displacement: 0.0,
+            max_displacement_vertex: -1,
+            mean_displacement: 0.0,
+            edges: 0,
+            max_residual_change: 0.0,
+            max_residual_change_edge: -1,
+            mean_residual_change: 0.0,
+        }
+    }
+}
+
+/// Displacement of one station from the first solution to the second.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StationDisplacement {
+    pub vertex: usize,
+    pub dx: f64,
+    pub dy: f64,
+    /// Length of `(dx, dy)`.
+    pub displacement: f64,
+}
+
+/// Residual length of one edge in both solutions.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResidualChange {
+    pub edge: usize,
+    pub before: f64,
+    pub after: f64,
+    /// `after - before`: negative when the edge fits the second solution better.
+    pub change: f64,
+}
+
+/// Result of [`Solution::diff`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SolutionDiff {
+    pub summary: DiffSummary,
+    /// One entry per vertex, in vertex order.
+    pub displacements: Vec<StationDisplacement>,
+    /// One entry per edge, in edge order.
+    pub residual_changes: Vec<ResidualChange>,
+}
+
+impl SolutionDiff {
+    /// The `n` stations that moved most, largest displacement first.
+    pub fn most_moved(&self, n: usize) -> Vec<&StationDisplacement> {
+        let mut moved: Vec<&StationDisplacement> = self
+            .displacements
+            .iter()
+            .filter(|d| d.displacement.is_finite())
+            .collect();
+        moved.sort_by(|a, b| {
+            b.displacement
+                .total_cmp(&a.displacement)
+                .then(a.vertex.cmp(&b.vertex))
+        });
+        moved.truncate(n);
+        moved
+    }
+
+    /// The `n` edges whose residual length changed most, in either direction, largest
+    /// absolute change first.
+    pub fn largest_residual_changes(&self, n: usize) -> Vec<&ResidualChange> {
+        let mut changes: Vec<&ResidualChange> = self
+            .residual_changes
+            .iter()
+            .filter(|r| r.change.is_finite())
+            .collect();
+        changes.sort_by(|a, b| {
+            b.change
+                .abs()
+                .total_cmp(&a.change.abs())
+                .then(a.edge.cmp(&b.edge))
+        });
+        changes.truncate(n);
+        changes
+    }
+}
+
+impl Solution {
+    /// Changes from this solution of `graph` to `other`, see [`SolutionDiff`]. Displacements
+    /// and residual changes are those of `other` relative to `self`.
+    ///
+    /// Fails with [`COMPASS_ERR_INVALID_ARGUMENT`] if either solution does not have one
+    /// coordinate pair per vertex of `graph`, or if an edge has an endpoint out of range.
+    pub fn diff(&self, other: &Solution, graph: &Graph) -> Result<SolutionDiff, SolveError> {
+        solution_diff(
+            (&self.x, &self.y),
+            (&other.x, &other.y),
+            (&graph.from, &graph.to),
+            (&graph.dx, &graph.dy),
+            Some(graph.num_vertices()),
+        )
+        .map_err(|message| SolveError {
+            code: COMPASS_ERR_INVALID_ARGUMENT,
+            message,
+        })
+    }
+}
+
+/// See [`Solution::diff`]. `num_vertices`, when given, is the vertex count both solutions
+/// must have.
+fn solution_diff<T: Copy + TryInto<usize>>(
+    (x, y): (&[f64], &[f64]),
+    (x_other, y_other): (&[f64], &[f64]),
+    (from, to): (&[T], &[T]),
+    (dx, dy): (&[f64], &[f64]),
+    num_vertices: Option<usize>,
+) -> Result<SolutionDiff, String> {
+    let n = num_vertices.unwrap_or(x.len());
+    for (name, x, y) in [("first", x, y), ("second", x_other, y_other)] {
+        if x.len() != n || y.len() != n {
+            return Err(format!(
+                "{name} solution has {} / {} coordinates for {n} vertices",
+                x.len(),
+                y.len()
+            ));
+        }
+    }
+    check_edges(n, from, to).map_err(|e| format!("edge {e} has an endpoint out of range"))?;
+
+    let mut summary = DiffSummary::default();
+    let mut total = 0.0;
+    let displacements: Vec<StationDisplacement> = (0..n)
+        .map(|v| {
+            let (ddx, ddy) = (x_other[v] - x[v], y_other[v] - y[v]);
+            let displacement = ddx.hypot(ddy);
+            if displacement.is_finite() {
+                summary.stations += 1;
+                total += displacement;
+                if summary.max_displacement_vertex < 0 || displacement > summary.max_displacement {
+                    summary.max_displacement = displacement;
+                    summary.max_displacement_vertex = v as c_int;
+                }
+            }
+            StationDisplacement {
+                vertex: v,
+                dx: ddx,
+                dy: ddy,
+                displacement,
+            }
+        })
+        .collect();
+    if summary.stations > 0 {
+        summary.mean_displacement = total / summary.stations as f64;
+    }
+
+    let mut total = 0.0;
+    let residual_changes: Vec<ResidualChange> = (0..from.len())
+        .map(|e| {
+            let (u, v) = checked_ends(n, from, to, e);
+            let residual = |x: &[f64], y: &[f64]| (x[v] - x[u] - dx[e]).hypot(y[v] - y[u] - dy[e]);
+            let (before, after) = (residual(x, y), residual(x_other, y_other));
+            let change = after - before;
+            if change.is_finite() {
+                summary.edges += 1;
+                total += change.abs();
+                if summary.max_residual_change_edge < 0
+                    || change.abs() > summary.max_residual_change
+                {
+                    summary.max_residual_change = change.abs();
+                    summary.max_residual_change_edge = e as c_int;
+                }
+            }
+            ResidualChange {
+                edge: e,
+                before,
+                after,
+                change,
+            }
+        })
+        .collect();
+    if summary.edges > 0 {
+        summary.mean_residual_change = total / summary.edges as f64;
+    }
+
+    Ok(SolutionDiff {
+        summary,
+        displacements,
+        residual_changes,
+    })
+}
+
+/// Compares two solutions of the same graph given as coordinate arrays, see
+/// [`Solution::diff`].
+///
+/// # Arguments
+///
+/// * `num_vertices`, `x`, `y` - Coordinates of the first solution.
+/// * `num_vertices_other`, `x_other`, `y_other` - Coordinates of the second solution.
+/// * `num_edges` ... `observed_dy` - Edges as for [`crate::solve_graph_least_squares`].
+/// * `out_summary` - Receives the [`DiffSummary`].
+/// * `out_displacement` - Receives the displacement of each vertex, `num_vertices` elements,
+///   or null.
+/// * `out_residual_change` - Receives the residual length change of each edge, `num_edges`
+///   elements, or null.
+/// * `err_buf` - Buffer receiving a NUL-terminated error message on failure. May be null.
+/// * `err_cap` - Capacity of `err_buf` in bytes.
+///
+/// # Returns
+///
+/// * [`COMPASS_OK`] on success.
+/// * [`COMPASS_ERR_INVALID_ARGUMENT`] for a negative count, a null pointer, vertex counts that
+///   differ or an edge whose endpoint is out of range; the outputs are left untouched.
+#[unsafe(no_mangle)]
+#[allow(clippy::too_many_arguments)]
+pub extern "C" fn compute_solution_diff(
+    num_vertices: c_int,
+    x: *const c_double,
+    y: *const c_double,
+    num_vertices_other: c_int,
+    x_other: *const c_double,
+    y_other: *const c_double,
+    num_edges: c_int,
+    from: *const c_int,
+    to: *const c_int,
+    observed_dx: *const c_double,
+    observed_dy: *const c_double,
+    out_summary: *mut DiffSummary,
+    out_displacement: *mut c_double,
+    out_residual_change: *mut c_double,
+    err_buf: *mut c_char,
+    err_cap: usize,
+) -> c_int {
+    let result = std::panic::catch_unwind(|| {
+        let (Ok(n_verts), Ok(n_other), Ok(n_edges)) = (
+            usize::try_from(num_vertices),
+            usize::try_from(num_vertices_other),
+            usize::try_from(num_edges),
+        ) else {
+            write_message(err_buf, err_cap, "negative count");
+            return COMPASS_ERR_INVALID_ARGUMENT;
+        };
+        if n_verts != n_other {
+            let message = format!("solutions have {n_verts} and {n_other} vertices");
+            write_message(err_buf, err_cap, &message);
+            return COMPASS_ERR_INVALID_ARGUMENT;
+        }
+        let edges = [observed_dx, observed_dy];
+        if out_summary.is_null()
+            || (n_verts > 0 && [x, y, x_other, y_other].iter().any(|p| p.is_null()))
+            || (n_edges > 0
+                && (from.is_null() || to.is_null() || edges.iter().any(|p| p.is_null())))
+        {
+            write_message(err_buf, err_cap, "null pointer");
+            return COMPASS_ERR_INVALID_ARGUMENT;
+        }
+        // Safety: The caller guarantees arrays of `num_vertices` / `num_edges` elements, and
+        // the pointers were checked above when the lengths are not zero.
+        let [x, y, x_other, y_other] =
+            [x, y, x_other, y_other].map(|p| unsafe { raw_slice(p, n_verts) });
+        let (from, to) = unsafe { (raw_slice(from, n_edges), raw_slice(to, n_edges)) };
+        let [dx, dy] = edges.map(|p| unsafe { raw_slice(p, n_edges) });
+        let diff = match solution_diff((x, y), (x_other, y_other), (from, to), (dx, dy), None) {
+            Ok(diff) => diff,
+            Err(message) => {
+                write_message(err_buf, err_cap, &message);
+                return COMPASS_ERR_INVALID_ARGUMENT;
+            }
+        };
+        unsafe { *out_summary = diff.summary };
+        if !out_displacement.is_null() {
+            let out = unsafe { raw_slice_mut(out_displacement, n_verts) };
+            for (out, d) in out.iter_mut().zip(&diff.displacements) {
+                *out = d.displacement;
+            }
+        }
+        if !out_residual_change.is_null() {
+            let out = unsafe { raw_slice_mut(out_residual_change, n_edges) };
+            for (out, r) in out.iter_mut().zip(&diff.residual_changes) {
+                *out = r.change;
+            }
+        }
+        COMPASS_OK
+    });
+
+    result.unwrap_or_else(|_| {
+        eprintln!("Panic caught in compute_solution_diff");
+        COMPASS_ERR_PANIC
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CStr;
+
+    /// A triangle with a misclosure on its closing edge, and the same triangle with that
+    /// edge disabled through a negligible weight.
+    fn triangle(closing_weight: f64) -> Graph {
+        let mut graph = Graph::default();
+        for (x, y, fixed) in [(0.0, 0.0, true), (1.2, 0.1, false), (0.9, 1.1, false)] {
+            graph.add_vertex(x, y, 0.0, fixed);
+        }
+        graph.add_edge(0, 1, 1.0, 0.0, 0.0, 1.0);
+        graph.add_edge(1, 2, 0.0, 1.0, 0.0, 1.0);
+        graph.add_edge(0, 2, 1.1, 0.9, 0.0, closing_weight);
+        graph
+    }
+
+    #[test]
+    fn diff_reports_displacements_and_residual_changes() {
+        let graph = triangle(1.0);
+        let before = graph.solve(60_000, 1e-12).unwrap();
+        let after = triangle(1e-9).solve(60_000, 1e-12).unwrap();
+        let diff = before.diff(&after, &graph).unwrap();
+
+        // Without the closing edge the traverse is taken as observed.
+        let summary = diff.summary;
+        assert_eq!((summary.stations, summary.edges), (3, 3));
+        assert_eq!(diff.displacements[0].displacement, 0.0);
+        let moved = diff.most_moved(1)[0];
+        assert_eq!(moved.vertex as c_int, summary.max_displacement_vertex);
+        assert!((after.x[2] - 1.0).abs() < 1e-6 && (after.y[2] - 1.0).abs() < 1e-6);
+        assert!((moved.dx - (after.x[2] - before.x[2])).abs() < 1e-12);
+        let mean = diff
+            .displacements
+            .iter()
+            .map(|d| d.displacement)
+            .sum::<f64>()
+            / 3.0;
+        assert!((summary.mean_displacement - mean).abs() < 1e-12);
+
+        // The closing edge takes the whole misclosure, the others none of it.
+        let largest = diff.largest_residual_changes(3);
+        assert_eq!(largest[0].edge, 2);
+        assert_eq!(summary.max_residual_change_edge, 2);
+        assert!((largest[0].after - 0.1f64.hypot(0.1)).abs() < 1e-6);
+        assert!(diff.residual_changes[0].after < 1e-6);
+        assert!(diff.residual_changes[0].change < 0.0);
+
+        // Mismatched solutions are rejected.
+        let mut short = after.clone();
+        short.x.pop();
+        let err = before.diff(&short, &graph).unwrap_err();
+        assert_eq!(err.code, COMPASS_ERR_INVALID_ARGUMENT);
+        assert_eq!(
+            err.message,
+            "second solution has 2 / 3 coordinates for 3 vertices"
+        );
+    }
+
+    #[test]
+    fn ffi_matches_the_safe_api_and_rejects_mismatched_counts() {
+        let graph = triangle(1.0);
+        let before = graph.solve(60_000, 1e-12).unwrap();
+        let after = triangle(1e-9).solve(60_000, 1e-12).unwrap();
+        let expected = before.diff(&after, &graph).unwrap();
+        let from: Vec<c_int> = graph.from.iter().map(|&v| v as c_int).collect();
+        let to: Vec<c_int> = graph.to.iter().map(|&v| v as c_int).collect();
+        let mut summary = DiffSummary::default();
+        let mut displacement = [0.0; 3];
+        let mut change = [0.0; 3];
+        let mut err = [0 as c_char; 64];
+        let mut diff = |n_other: c_int, summary: &mut DiffSummary, err: &mut [c_char]| {
+            compute_solution_diff(
+                3,
+                before.x.as_ptr(),
+                before.y.as_ptr(),
+                n_other,
+                after.x.as_ptr(),
+                after.y.as_ptr(),
+                3,
+                from.as_ptr(),
+                to.as_ptr(),
+                graph.dx.as_ptr(),
+                graph.dy.as_ptr(),
+                summary,
+                displacement.as_mut_ptr(),
+                change.as_mut_ptr(),
+                err.as_mut_ptr(),
+                err.len(),
+            )
+        };
+
+        assert_eq!(diff(3, &mut summary, &mut err), COMPASS_OK);
+        assert_eq!(summary, expected.summary);
+        assert_eq!(
+            diff(2, &mut summary, &mut err),
+            COMPASS_ERR_INVALID_ARGUMENT
+        );
+        let message = unsafe { CStr::from_ptr(err.as_ptr()) };
+        assert_eq!(message.to_str().unwrap(), "solutions have 3 and 2 vertices");
+        assert_eq!(summary, expected.summary);
+        let displacements: Vec<f64> = expected
+            .displacements
+            .iter()
+            .map(|d| d.displacement)
+            .collect();
+        assert_eq!(displacement.to_vec(), displacements);
+        let changes: Vec<f64> = expected.residual_changes.iter().map(|r| r.change).collect();
+        assert_eq!(change.to_vec(), changes);
+    }
+}
@@ -0,0 +1,192 @@
+//! Double-double arithmetic for the dot products and the step recurrence of Conjugate
+//! Gradient, see [`crate::ExtendedPrecision`].
+//!
+//! A value is held as the unevaluated sum `hi + lo` of two f64 with `|lo|` at most half an
+//! ulp of `hi`, about 106 bits of mantissa. Dot products are accumulated by the compensated
+//! scheme of Ogita, Rump and Oishi (error-free products by fused multiply-add, error-free
+//! sums by Knuth's TwoSum), so that sums of large terms of opposite signs keep their small
+//! difference instead of cancelling to rounding noise. The vectors themselves and the
+//! matrix-vector product of each iteration stay f64; only the true residual `b - Ax`, whose
+//! terms cancel to the size of the corrections when the coordinates are large, is summed by
+//! the same scheme, see [`residual`].
+
+use nalgebra::DVector;
+use nalgebra_sparse::CsrMatrix;
+
+/// The sum `hi + lo` of two f64.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub(crate) struct DoubleDouble {
+    hi: f64,
+    lo: f64,
+}
+
+/// `a + b` and its rounding error, exactly.
+fn two_sum(a: f64, b: f64) -> (f64, f64) {
+    let s = a + b;
+    let v = s - a;
+    (s, (a - (s - v)) + (b - v))
+}
+
+/// `a * b` and its rounding error, exactly.
+fn two_prod(a: f64, b: f64) -> (f64, f64) {
+    let p = a * b;
+    (p, a.mul_add(b, -p))
+}
+
+/// `hi + lo` renormalized, for `|hi| >= |lo|`.
+fn quick_two_sum(hi: f64, lo: f64) -> DoubleDouble {
+    let s = hi + lo;
+    DoubleDouble {
+        hi: s,
+        lo: lo - (s - hi),
+    }
+}
+
+impl DoubleDouble {
+    /// The value rounded to f64.
+    pub(crate) fn value(self) -> f64 {
+        self.hi + self.lo
+    }
+
+    /// `self - other`.
+    fn sub(self, other: DoubleDouble) -> DoubleDouble {
+        let (s, e) = two_sum(self.hi, -other.hi);
+        let (t, f) = two_sum(self.lo, -other.lo);
+        let hi_lo = quick_two_sum(s, e + t);
+        quick_two_sum(hi_lo.hi, hi_lo.lo + f)
+    }
+
+    /// `self * b` for an f64 `b`.
+    fn scale(self, b: f64) -> DoubleDouble {
+        let (p, e) = two_prod(self.hi, b);
+        quick_two_sum(p, e + self.lo * b)
+    }
+
+    /// `self / other`, by a long division of two f64 digits.
+    pub(crate) fn div(self, other: DoubleDouble) -> DoubleDouble {
+        let q1 = self.hi / other.hi;
+        let r = self.sub(other.scale(q1));
+        let q2 = r.hi / other.hi;
+        quick_two_sum(q1, q2)
+    }
+}
+
+impl From<f64> for DoubleDouble {
+    fn from(value: f64) -> Self {
+        DoubleDouble { hi: value, lo: 0.0 }
+    }
+}
+
+/// `uᵀv`, with the error of each product and each sum carried along.
+pub(crate) fn dot(u: &[f64], v: &[f64]) -> DoubleDouble {
+    let (mut sum, mut error) = (0.0, 0.0);
+    for (&a, &b) in u.iter().zip(v) {
+        let (p, e) = two_prod(a, b);
+        let (s, f) = two_sum(sum, p);
+        sum = s;
+        error += e + f;
+    }
+    quick_two_sum(sum, error)
+}
+
+/// `b - A (origin + x)`, each row summed with the error of each product and each sum
+/// carried along, then rounded to f64.
+///
+/// At UTM-like coordinates the terms of a row are some 1e14 for weights of 1e8, so that an
+/// f64 residual is rounding noise below about 0.1 whatever the iterate; summed this way it is
+/// accurate to the size of the residual itself.
+pub(crate) fn residual(
+    a: &CsrMatrix<f64>,
+    b: &DVector<f64>,
+    origin: &DVector<f64>,
+    x: &DVector<f64>,
+) -> DVector<f64> {
+    DVector::from_fn(b.len(), |i, _| {
+        let row = a.row(i);
+        let (mut sum, mut error) = (b[i], 0.0);
+        for (&j, &value) in row.col_indices().iter().zip(row.values()) {
+            for v in [origin[j], x[j]] {
+                let (p, e) = two_prod(-value, v);
+                let (s, f) = two_sum(sum, p);
+                sum = s;
+                error += e + f;
+            }
+        }
+        quick_two_sum(sum, error).value()
+    })
+}
+
+/// Precision of the dot products and the step recurrence of Conjugate Gradient.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) enum Accumulation {
+    /// f64 throughout, as the vectors.
+    #[default]
+    Double,
+    /// Double-double.
+    Extended,
+}
+
+impl Accumulation {
+    /// `uᵀv`, exactly as `u.dot(v)` in double precision.
+    pub(crate) fn dot(self, u: &DVector<f64>, v: &DVector<f64>) -> DoubleDouble {
+        match self {
+            Accumulation::Double => u.dot(v).into(),
+            Accumulation::Extended => dot(u.as_slice(), v.as_slice()),
+        }
+    }
+
+    /// `a / b` rounded to f64, exactly as `a / b` in double precision.
+    pub(crate) fn ratio(self, a: DoubleDouble, b: DoubleDouble) -> f64 {
+        match self {
+            Accumulation::Double => a.hi / b.hi,
+            Accumulation::Extended => a.div(b).value(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cancelling_terms_keep_their_difference() {
+        // 1e17 + 1 - 1e17 vanishes in f64: the 1 is below half an ulp of 1e17.
+        let (u, v) = ([1e17, 1.0, -1e17, 0.5], [1.0, 1.0, 1.0, 3.0]);
+        let plain: f64 = u.iter().zip(&v).map(|(a, b)| a * b).sum();
+        assert_eq!(plain, 1.5);
+        assert_eq!(dot(&u, &v).value(), 2.5);
+
+        // Products whose rounding errors cancel the leading terms.
+        let a = 1.0 + f64::EPSILON;
+        let product = dot(&[a, -1.0], &[a, 1.0 + 2.0 * f64::EPSILON]);
+        assert_eq!(product.value(), f64::EPSILON * f64::EPSILON);
+
+        let third = DoubleDouble::from(1.0).div(DoubleDouble::from(3.0));
+        assert_eq!(third.value(), 1.0 / 3.0);
+        let residual = DoubleDouble::from(1.0).sub(third.scale(3.0));
+        assert!(residual.value().abs() < 1e-32);
+    }
+
+    #[test]
+    fn residuals_keep_the_corrections_of_large_coordinates() {
+        use nalgebra_sparse::CooMatrix;
+
+        // Two stations tied by a weight of 2^27, about 1e8, and 2^-33 from balance at an
+        // easting of 5e6, whose ulp is 2^-30.
+        let w = 2f64.powi(27);
+        let mut coo = CooMatrix::new(2, 2);
+        for (i, j, value) in [(0, 0, w), (0, 1, -w), (1, 0, -w), (1, 1, w)] {
+            coo.push(i, j, value);
+        }
+        let a = CsrMatrix::from(&coo);
+        let b = DVector::from_vec(vec![-2f64.powi(-5), 2f64.powi(-5)]);
+        let origin = DVector::from_vec(vec![5e6, 5e6]);
+        let x = DVector::from_vec(vec![0.0, 2f64.powi(-32) + 2f64.powi(-60)]);
+
+        let r = residual(&a, &b, &origin, &x);
+        assert_eq!(r.as_slice(), [2f64.powi(-33), -2f64.powi(-33)]);
+        // In f64 the sum of origin and correction drops the correction.
+        let plain = &b - &a * (&origin + &x);
+        assert_eq!(plain, b);
+    }
+}
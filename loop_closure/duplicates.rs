@@ -0,0 +1,336 @@
+//! Near-duplicate stations: pairs of vertices that end up within a small distance of each
+//! other after the adjustment without being connected, most often one station surveyed
+//! twice under two names. Tying such a pair with [`Constraints::tie`] closes the loop the
+//! survey data missed.
+//!
+//! The search sorts the adjusted coordinates into a grid of square cells as wide as the
+//! search radius, so that a pair within the radius lies in the same cell or in two adjacent
+//! ones: each cell is compared with itself and four of its eight neighbours, and the cost
+//! grows with the number of vertices and of close pairs rather than with their square. A
+//! radius far larger than the typical shot length puts many vertices in each cell and
+//! loses that advantage.
+
+use crate::constraints::Constraints;
+use crate::{COMPASS_ERR_BUFFER_TOO_SMALL, COMPASS_ERR_INVALID_ARGUMENT, Graph, GraphContext};
+use crate::{Solution, write_column};
+use std::ffi::{c_double, c_int};
+
+impl Solution {
+    /// Pairs of vertices `(u, v, distance)`, `u < v`, whose adjusted coordinates are at most
+    /// `radius` apart and that no edge of `graph` connects, closest first. See the
+    /// [module documentation](crate::duplicates).
+    ///
+    /// Empty for a radius that is not positive and finite. Vertices with non-finite
+    /// coordinates are left out.
+    pub fn find_near_duplicates(&self, graph: &Graph, radius: f64) -> Vec<(usize, usize, f64)> {
+        self.find_near_duplicates_untied(graph, radius, &Constraints::default())
+    }
+
+    /// [`Solution::find_near_duplicates`], also leaving out the pairs `constraints` already
+    /// ties, see [`Constraints::ties`].
+    pub fn find_near_duplicates_untied(
+        &self,
+        graph: &Graph,
+        radius: f64,
+        constraints: &Constraints,
+    ) -> Vec<(usize, usize, f64)> {
+        near_duplicates(&self.x, &self.y, graph, radius, &constraints.ties())
+    }
+}
+
+impl GraphContext {
+    /// [`Solution::find_near_duplicates`] at the current [`GraphContext::coordinates`]. Disabled
+    /// edges still connect their ends.
+    pub fn find_near_duplicates(&self, radius: f64) -> Vec<(usize, usize, f64)> {
+        let (x, y) = self.coordinates();
+        near_duplicates(x, y, &self.graph, radius, &[])
+    }
+}
+
+/// Close pairs of `(x, y)` not connected by an edge of `graph` nor listed in `ties`.
+fn near_duplicates(
+    x: &[f64],
+    y: &[f64],
+    graph: &Graph,
+    radius: f64,
+    ties: &[(usize, usize)],
+) -> Vec<(usize, usize, f64)> {
+    if !(radius.is_finite() && radius > 0.0) {
+        return Vec::new();
+    }
+    let cell = |v: usize| {
+        (
+            (x[v] / radius).floor() as i64,
+            (y[v] / radius).floor() as i64,
+        )
+    };
+    // Vertices sorted by cell, column-major, so that each cell is a contiguous run and the
+    // runs of a column are sorted by row.
+    let mut order: Vec<((i64, i64), usize)> = (0..x.len().min(y.len()))
+        .filter(|&v| x[v].is_finite() && y[v].is_finite())
+        .map(|v| (cell(v), v))
+        .collect();
+    order.sort_unstable();
+    let mut runs: Vec<((i64, i64), usize, usize)> = Vec::new();
+    for (i, &(key, _)) in order.iter().enumerate() {
+        match runs.last_mut() {
+            Some((last, _, end)) if *last == key => *end = i + 1,
+            _ => runs.push((key, i, i + 1)),
+        }
+    }
+
+    let mut pairs = Vec::new();
+    let mut compare = |u: usize, others: &[((i64, i64), usize)]| {
+        for &(_, v) in others {
+            let distance = (x[u] - x[v]).hypot(y[u] - y[v]);
+            if distance <= radius {
+                pairs.push((u.min(v), u.max(v), distance));
+            }
+        }
+    };
+    // The first run at or after the lower right neighbour of the current run only moves
+    // forward, so the neighbours are found by a sweep rather than by lookups.
+    let mut next = 0;
+    for (r, &((cx, cy), start, end)) in runs.iter().enumerate() {
+        let lower_right = (cx.saturating_add(1), cy.saturating_sub(1));
+        while next < runs.len() && runs[next].0 < lower_right {
+            next += 1;
+        }
+        let above = cy.saturating_add(1);
+        let neighbours = runs
+            .get(r + 1)
+            .filter(|run| run.0 == (cx, above))
+            .into_iter()
+            .chain(
+                runs[next..]
+                    .iter()
+                    .take(3)
+                    .filter(|run| run.0.0 == lower_right.0 && run.0.1 <= above),
+            );
+        for i in start..end {
+            let u = order[i].1;
+            compare(u, &order[i + 1..end]);
+            for &(_, first, last) in neighbours.clone() {
+                compare(u, &order[first..last]);
+            }
+        }
+    }
+    if pairs.is_empty() {
+        return pairs;
+    }
+
+    let mut connected: Vec<(usize, usize)> = graph
+        .from
+        .iter()
+        .zip(&graph.to)
+        .map(|(&a, &b)| (a.min(b), a.max(b)))
+        .chain(ties.iter().copied())
+        .collect();
+    connected.sort_unstable();
+    pairs.retain(|&(u, v, _)| connected.binary_search(&(u, v)).is_err());
+    pairs.sort_by(|a, b| a.2.total_cmp(&b.2).then((a.0, a.1).cmp(&(b.0, b.1))));
+    pairs
+}
+
+/// Returns the number of near-duplicate pairs of the graph behind `handle` within `radius`,
+/// i.e. the buffer length needed by [`graph_near_duplicates`].
+#[unsafe(no_mangle)]
+pub extern "C" fn graph_near_duplicate_count(
+    handle: *const GraphContext,
+    radius: c_double,
+) -> c_int {
+    match unsafe { handle.as_ref() } {
+        Some(ctx) => ctx.find_near_duplicates(radius).len() as c_int,
+        None => COMPASS_ERR_INVALID_ARGUMENT,
+    }
+}
+
+/// Copies the near-duplicate pairs of the graph behind `handle` within `radius` into parallel
+/// caller buffers of `capacity` elements, closest first, see
+/// [`GraphContext::find_near_duplicates`]. Any output pointer may be null to skip that
+/// column.
+///
+/// # Returns
+///
+/// * The number of pairs written.
+/// * [`COMPASS_ERR_BUFFER_TOO_SMALL`] if `capacity` is below [`graph_near_duplicate_count`];
+///   nothing is written.
+/// * [`COMPASS_ERR_INVALID_ARGUMENT`] for a null handle.
+#[unsafe(no_mangle)]
+pub extern "C" fn graph_near_duplicates(
+    handle: *const GraphContext,
+    radius: c_double,
+    capacity: c_int,
+    out_from: *mut c_int,
+    out_to: *mut c_int,
+    out_distance: *mut c_double,
+) -> c_int {
+    let Some(ctx) = (unsafe { handle.as_ref() }) else {
+        return COMPASS_ERR_INVALID_ARGUMENT;
+    };
+    let pairs = ctx.find_near_duplicates(radius);
+    if (capacity.max(0) as usize) < pairs.len() {
+        return COMPASS_ERR_BUFFER_TOO_SMALL;
+    }
+    write_column(out_from, &pairs, |p| p.0 as c_int);
+    write_column(out_to, &pairs, |p| p.1 as c_int);
+    write_column(out_distance, &pairs, |p| p.2);
+    pairs.len() as c_int
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph_free;
+    use std::collections::HashSet;
+
+    /// Brute-force reference of [`near_duplicates`].
+    fn brute_force(x: &[f64], y: &[f64], graph: &Graph, radius: f64) -> Vec<(usize, usize)> {
+        let connected: HashSet<(usize, usize)> = (0..graph.num_edges())
+            .map(|e| {
+                (
+                    graph.from[e].min(graph.to[e]),
+                    graph.from[e].max(graph.to[e]),
+                )
+            })
+            .collect();
+        let mut pairs = Vec::new();
+        for u in 0..x.len() {
+            for v in u + 1..x.len() {
+                if !connected.contains(&(u, v)) && (x[u] - x[v]).hypot(y[u] - y[v]) <= radius {
+                    pairs.push((u, v));
+                }
+            }
+        }
+        pairs
+    }
+
+    /// Two survey lines four meters apart, with station 2 of the second line surveyed 5 cm
+    /// from station 2 of the first.
+    fn two_lines() -> (Graph, Solution) {
+        let mut graph = Graph::default();
+        for i in 0..5 {
+            graph.add_vertex(10.0 * i as f64, 0.0, 0.0, i == 0);
+        }
+        for i in 0..5 {
+            graph.add_vertex(10.0 * i as f64, 4.0, 0.0, false);
+        }
+        graph.y[7] = 0.05;
+        for i in 0..4 {
+            graph.add_edge(i, i + 1, 10.0, 0.0, 0.0, 1.0);
+        }
+        for i in 5..9 {
+            graph.add_edge(i, i + 1, 10.0, graph.y[i + 1] - graph.y[i], 0.0, 1.0);
+        }
+        let solution = Solution {
+            x: graph.x.clone(),
+            y: graph.y.clone(),
+            ..Solution::default()
+        };
+        (graph, solution)
+    }
+
+    #[test]
+    fn finds_unconnected_close_pairs() {
+        let (mut graph, solution) = two_lines();
+        let pairs = solution.find_near_duplicates(&graph, 0.1);
+        assert_eq!(pairs.len(), 1);
+        assert_eq!((pairs[0].0, pairs[0].1), (2, 7));
+        assert!((pairs[0].2 - 0.05).abs() < 1e-12);
+        assert!(solution.find_near_duplicates(&graph, 0.01).is_empty());
+        assert!(solution.find_near_duplicates(&graph, 0.0).is_empty());
+        assert!(solution.find_near_duplicates(&graph, f64::NAN).is_empty());
+
+        // Connected pairs and tied pairs are not duplicates.
+        let mut ties = Constraints::default();
+        ties.tie(7, 2);
+        assert_eq!(ties.ties(), [(2, 7)]);
+        assert!(
+            solution
+                .find_near_duplicates_untied(&graph, 0.1, &ties)
+                .is_empty()
+        );
+        graph.add_edge(2, 7, 0.0, 0.05, 0.0, 1.0);
+        assert!(solution.find_near_duplicates(&graph, 0.1).is_empty());
+
+        // Every pair of neighbouring stations on a line is connected; across the lines they
+        // are not.
+        let pairs = solution.find_near_duplicates(&graph, 4.0);
+        assert_eq!(
+            pairs.iter().map(|p| (p.0, p.1)).collect::<Vec<_>>(),
+            [(0, 5), (1, 6), (3, 8), (4, 9)]
+        );
+    }
+
+    #[test]
+    fn matches_brute_force_on_scattered_points() {
+        // A deterministic scatter with clusters across cell borders and negative coordinates.
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let mut next = || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state >> 11) as f64 / (1u64 << 53) as f64
+        };
+        let mut graph = Graph::default();
+        for _ in 0..400 {
+            graph.add_vertex(next() * 40.0 - 20.0, next() * 40.0 - 20.0, 0.0, false);
+        }
+        for v in 0..399 {
+            if next() < 0.5 {
+                graph.add_edge(v, v + 1, 0.0, 0.0, 0.0, 1.0);
+            }
+        }
+        let solution = Solution {
+            x: graph.x.clone(),
+            y: graph.y.clone(),
+            ..Solution::default()
+        };
+        for radius in [0.3, 1.0, 2.5] {
+            let mut found: Vec<_> = solution
+                .find_near_duplicates(&graph, radius)
+                .iter()
+                .map(|p| (p.0, p.1))
+                .collect();
+            found.sort_unstable();
+            assert_eq!(found, brute_force(&graph.x, &graph.y, &graph, radius));
+        }
+    }
+
+    #[test]
+    fn ffi_uses_the_two_call_protocol() {
+        let (graph, _) = two_lines();
+        let handle = GraphContext::into_raw(graph);
+        let count = graph_near_duplicate_count(handle, 0.1);
+        assert_eq!(count, 1);
+        let (mut from, mut to, mut distance) = ([0; 1], [0; 1], [0.0; 1]);
+        assert_eq!(
+            graph_near_duplicates(
+                handle,
+                0.1,
+                0,
+                from.as_mut_ptr(),
+                to.as_mut_ptr(),
+                distance.as_mut_ptr()
+            ),
+            COMPASS_ERR_BUFFER_TOO_SMALL
+        );
+        assert_eq!(
+            graph_near_duplicates(
+                handle,
+                0.1,
+                count,
+                from.as_mut_ptr(),
+                to.as_mut_ptr(),
+                std::ptr::null_mut()
+            ),
+            1
+        );
+        assert_eq!((from, to), ([2], [7]));
+        assert_eq!(
+            graph_near_duplicate_count(std::ptr::null(), 0.1),
+            COMPASS_ERR_INVALID_ARGUMENT
+        );
+        graph_free(handle);
+    }
+}
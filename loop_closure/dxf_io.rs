@@ -0,0 +1,475 @@
+//! Minimal DXF R12 (AC1009) ASCII export of the adjusted line plot for CAD drafting.
+//!
+//! Each edge becomes a `LINE` entity on the adjusted-centerline layer and, optionally, a
+//! second one on the pre-adjustment layer drawn from the graph's input coordinates. Error
+//! ellipses, when given, are closed `POLYLINE`s of [`ELLIPSE_SEGMENTS`] vertices on a third
+//! layer, around the adjusted stations at their height: R12 has no `ELLIPSE` entity.
+
+use crate::crs::{LengthUnit, convert_length};
+use crate::{ErrorEllipse, Graph, Solution, SolveError, check_export, invalid_input};
+use std::fmt::Write as _;
+use std::path::Path;
+
+/// Number of vertices of the polyline drawn for an error ellipse.
+pub const ELLIPSE_SEGMENTS: usize = 48;
+
+/// Options for [`Solution::to_dxf`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct DxfOptions {
+    /// Layer of the adjusted centerline.
+    pub adjusted_layer: String,
+    /// Layer of the pre-adjustment centerline.
+    pub original_layer: String,
+    /// Whether to include the pre-adjustment centerline.
+    pub include_original: bool,
+    /// Layer of the error ellipses, present only when some are given.
+    pub ellipse_layer: String,
+    /// Error ellipses drawn around the adjusted stations, empty for none.
+    pub ellipses: Vec<ErrorEllipse>,
+    /// Exaggeration applied to the ellipse axes, which are usually small at drawing scale.
+    pub ellipse_scale: f64,
+    /// Unit of the drawing, to which the coordinates are converted from the unit of the
+    /// solution's coordinate system (meters when it has none). `None` keeps them as they are.
+    pub units: Option<LengthUnit>,
+    /// Factor applied to every coordinate after the unit conversion, e.g. for a drawing at
+    /// scale.
+    pub scale: f64,
+}
+
+impl Default for DxfOptions {
+    fn default() -> Self {
+        DxfOptions {
+            adjusted_layer: "CENTERLINE_ADJUSTED".to_string(),
+            original_layer: "CENTERLINE_ORIGINAL".to_string(),
+            include_original: true,
+            ellipse_layer: "ERROR_ELLIPSES".to_string(),
+            ellipses: Vec::new(),
+            ellipse_scale: 1.0,
+            units: None,
+            scale: 1.0,
+        }
+    }
+}
+
+impl Solution {
+    /// Renders the adjusted line plot as DXF R12 ASCII text. Ellipses of a vertex out of range
+    /// are skipped.
+    ///
+    /// Returns [`crate::COMPASS_ERR_INVALID_ARGUMENT`] when the solution or an array of
+    /// `graph` does not have one value per vertex or edge, or an edge has an endpoint out of
+    /// range.
+    pub fn to_dxf(&self, graph: &Graph, options: &DxfOptions) -> Result<String, SolveError> {
+        check_export(self, graph)?;
+        // (layer name, AutoCAD color index): adjusted in white/black, original in gray.
+        let mut layers = vec![(options.adjusted_layer.as_str(), 7)];
+        if options.include_original {
+            layers.push((options.original_layer.as_str(), 8));
+        }
+        let ellipses: Vec<&ErrorEllipse> = options
+            .ellipses
+            .iter()
+            .filter(|ellipse| ellipse.vertex < graph.num_vertices())
+            .collect();
+        if !ellipses.is_empty() {
+            // Blue, as in the SVG rendering.
+            layers.push((options.ellipse_layer.as_str(), 5));
+        }
+
+        let mut out = String::new();
+        group(&mut out, 0, "SECTION");
+        group(&mut out, 2, "HEADER");
+        group(&mut out, 9, "$ACADVER");
+        group(&mut out, 1, "AC1009");
+        group(&mut out, 0, "ENDSEC");
+
+        group(&mut out, 0, "SECTION");
+        group(&mut out, 2, "TABLES");
+        group(&mut out, 0, "TABLE");
+        group(&mut out, 2, "LAYER");
+        group(&mut out, 70, layers.len());
+        for (name, color) in &layers {
+            group(&mut out, 0, "LAYER");
+            group(&mut out, 2, name);
+            group(&mut out, 70, 0);
+            group(&mut out, 62, color);
+            group(&mut out, 6, "CONTINUOUS");
+        }
+        group(&mut out, 0, "ENDTAB");
+        group(&mut out, 0, "ENDSEC");
+
+        group(&mut out, 0, "SECTION");
+        group(&mut out, 2, "ENTITIES");
+        let source = self
+            .coordinate_system
+            .map_or(LengthUnit::Meters, |cs| cs.units);
+        let scale = match options.units {
+            Some(units) => convert_length(options.scale, source, units),
+            None => options.scale,
+        };
+        for e in 0..graph.num_edges() {
+            let (u, v) = (graph.from[e], graph.to[e]);
+            line(
+                &mut out,
+                &options.adjusted_layer,
+                [self.x[u], self.y[u], graph.z[u]].map(|c| c * scale),
+                [self.x[v], self.y[v], graph.z[v]].map(|c| c * scale),
+            );
+            if options.include_original {
+                line(
+                    &mut out,
+                    &options.original_layer,
+                    [graph.x[u], graph.y[u], graph.z[u]].map(|c| c * scale),
+                    [graph.x[v], graph.y[v], graph.z[v]].map(|c| c * scale),
+                );
+            }
+        }
+        for ellipse in ellipses {
+            let v = ellipse.vertex;
+            let center = [self.x[v], self.y[v], graph.z[v]].map(|c| c * scale);
+            polyline(
+                &mut out,
+                &options.ellipse_layer,
+                &ellipse_outline(ellipse, center, scale * options.ellipse_scale),
+            );
+        }
+        group(&mut out, 0, "ENDSEC");
+        group(&mut out, 0, "EOF");
+        Ok(out)
+    }
+
+    /// Writes [`Solution::to_dxf`] to `path`.
+    pub fn write_dxf(
+        &self,
+        graph: &Graph,
+        path: impl AsRef<Path>,
+        options: &DxfOptions,
+    ) -> std::io::Result<()> {
+        let text = self.to_dxf(graph, options).map_err(invalid_input)?;
+        std::fs::write(path, text)
+    }
+}
+
+/// Writes one DXF group: the group code line followed by the value line.
+fn group(out: &mut String, code: u16, value: impl std::fmt::Display) {
+    let _ = write!(out, "{code:>3}\n{value}\n");
+}
+
+/// Vertices of the outline of `ellipse` centered on `center`, its axes multiplied by
+/// `factor`.
+fn ellipse_outline(ellipse: &ErrorEllipse, center: [f64; 3], factor: f64) -> Vec<[f64; 3]> {
+    // Unit vectors along the major axis, at the azimuth clockwise from north, and along the
+    // minor axis, a quarter turn clockwise from it.
+    let (sin, cos) = ellipse.azimuth_deg.to_radians().sin_cos();
+    let (major, minor) = ([sin, cos], [cos, -sin]);
+    let (a, b) = (ellipse.semi_major * factor, ellipse.semi_minor * factor);
+    (0..ELLIPSE_SEGMENTS)
+        .map(|k| {
+            let t = std::f64::consts::TAU * k as f64 / ELLIPSE_SEGMENTS as f64;
+            let (s, c) = t.sin_cos();
+            [
+                center[0] + a * c * major[0] + b * s * minor[0],
+                center[1] + a * c * major[1] + b * s * minor[1],
+                center[2],
+            ]
+        })
+        .collect()
+}
+
+/// Writes a closed 3D polyline through `vertices`.
+fn polyline(out: &mut String, layer: &str, vertices: &[[f64; 3]]) {
+    group(out, 0, "POLYLINE");
+    group(out, 8, layer);
+    // Vertices follow; closed 3D polyline.
+    group(out, 66, 1);
+    for code in [10, 20, 30] {
+        group(out, code, 0.0);
+    }
+    group(out, 70, 1 | 8);
+    for vertex in vertices {
+        group(out, 0, "VERTEX");
+        group(out, 8, layer);
+        for (axis, value) in vertex.iter().enumerate() {
+            group(out, 10 + 10 * axis as u16, value);
+        }
+        group(out, 70, 32);
+    }
+    group(out, 0, "SEQEND");
+    group(out, 8, layer);
+}
+
+fn line(out: &mut String, layer: &str, start: [f64; 3], end: [f64; 3]) {
+    group(out, 0, "LINE");
+    group(out, 8, layer);
+    for (axis, value) in start.into_iter().enumerate() {
+        group(out, 10 + 10 * axis as u16, value);
+    }
+    for (axis, value) in end.into_iter().enumerate() {
+        group(out, 11 + 10 * axis as u16, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A `LINE` entity read back from the drawing.
+    #[derive(Debug, PartialEq)]
+    struct Line {
+        layer: String,
+        start: [f64; 3],
+        end: [f64; 3],
+    }
+
+    /// A closed `POLYLINE` read back from the drawing.
+    #[derive(Debug, PartialEq)]
+    struct Polyline {
+        layer: String,
+        vertices: Vec<[f64; 3]>,
+    }
+
+    /// What the tiny reader extracts from a drawing.
+    #[derive(Debug, Default)]
+    struct Drawing {
+        version: String,
+        layers: Vec<(String, i32)>,
+        lines: Vec<Line>,
+        polylines: Vec<Polyline>,
+    }
+
+    /// Reads the group pairs back, checking the section structure on the way.
+    fn read(text: &str) -> Drawing {
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len() % 2, 0, "dangling group code");
+        let groups: Vec<(u16, &str)> = lines
+            .chunks(2)
+            .map(|pair| (pair[0].trim().parse().unwrap(), pair[1]))
+            .collect();
+        assert_eq!(groups.last(), Some(&(0, "EOF")));
+
+        let mut drawing = Drawing::default();
+        let mut i = 0;
+        while i < groups.len() {
+            match groups[i] {
+                (9, "$ACADVER") => drawing.version = groups[i + 1].1.to_string(),
+                (0, "LAYER") if groups[i - 1].1 != "TABLE" => {
+                    assert_eq!(groups[i + 1].0, 2);
+                    assert_eq!(groups[i + 3].0, 62);
+                    drawing.layers.push((
+                        groups[i + 1].1.to_string(),
+                        groups[i + 3].1.parse().unwrap(),
+                    ));
+                }
+                (0, "LINE") => {
+                    let mut line = Line {
+                        layer: String::new(),
+                        start: [f64::NAN; 3],
+                        end: [f64::NAN; 3],
+                    };
+                    i += 1;
+                    while groups[i].0 != 0 {
+                        let (code, value) = groups[i];
+                        match code {
+                            8 => line.layer = value.to_string(),
+                            10 | 20 | 30 => {
+                                line.start[code as usize / 10 - 1] = value.parse().unwrap()
+                            }
+                            11 | 21 | 31 => {
+                                line.end[code as usize / 10 - 1] = value.parse().unwrap()
+                            }
+                            _ => panic!("unexpected group {code} in LINE"),
+                        }
+                        i += 1;
+                    }
+                    drawing.lines.push(line);
+                    continue;
+                }
+                (0, "POLYLINE") => {
+                    let layer = groups[i + 1].1.to_string();
+                    assert_eq!(groups[i + 1].0, 8);
+                    assert_eq!(groups[i + 2], (66, "1"));
+                    assert_eq!(groups[i + 6], (70, "9"), "closed 3D polyline");
+                    let mut vertices = Vec::new();
+                    i += 7;
+                    while groups[i] == (0, "VERTEX") {
+                        assert_eq!(groups[i + 1], (8, layer.as_str()));
+                        let mut vertex = [f64::NAN; 3];
+                        for axis in 0..3 {
+                            assert_eq!(groups[i + 2 + axis].0, 10 + 10 * axis as u16);
+                            vertex[axis] = groups[i + 2 + axis].1.parse().unwrap();
+                        }
+                        assert_eq!(groups[i + 5], (70, "32"));
+                        vertices.push(vertex);
+                        i += 6;
+                    }
+                    assert_eq!(groups[i], (0, "SEQEND"));
+                    drawing.polylines.push(Polyline { layer, vertices });
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+        drawing
+    }
+
+    fn solved_triangle() -> (Graph, Solution) {
+        let mut graph = Graph::default();
+        graph.add_vertex(0.0, 0.0, 10.0, true);
+        graph.add_vertex(10.0, 0.0, 11.0, false);
+        graph.add_vertex(10.0, 10.0, 12.0, false);
+        graph.add_edge(0, 1, 10.0, 0.0, 1.0, 1.0);
+        graph.add_edge(1, 2, 0.0, 10.0, 1.0, 1.0);
+        graph.add_edge(2, 0, -10.03, -10.02, -2.0, 1.0);
+        let solution = graph.solve(1000, 1e-12).unwrap();
+        (graph, solution)
+    }
+
+    #[test]
+    fn writes_one_adjusted_and_one_original_line_per_edge() {
+        let (graph, solution) = solved_triangle();
+        let drawing = read(&solution.to_dxf(&graph, &DxfOptions::default()).unwrap());
+        assert_eq!(drawing.version, "AC1009");
+        assert_eq!(
+            drawing.layers,
+            [
+                ("CENTERLINE_ADJUSTED".to_string(), 7),
+                ("CENTERLINE_ORIGINAL".to_string(), 8)
+            ]
+        );
+        assert_eq!(drawing.lines.len(), 2 * graph.num_edges());
+
+        for e in 0..graph.num_edges() {
+            let (u, v) = (graph.from[e], graph.to[e]);
+            assert_eq!(
+                drawing.lines[2 * e],
+                Line {
+                    layer: "CENTERLINE_ADJUSTED".to_string(),
+                    start: [solution.x[u], solution.y[u], graph.z[u]],
+                    end: [solution.x[v], solution.y[v], graph.z[v]],
+                }
+            );
+            assert_eq!(
+                drawing.lines[2 * e + 1],
+                Line {
+                    layer: "CENTERLINE_ORIGINAL".to_string(),
+                    start: [graph.x[u], graph.y[u], graph.z[u]],
+                    end: [graph.x[v], graph.y[v], graph.z[v]],
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn scales_units_and_can_omit_the_original_centerline() {
+        let (graph, solution) = solved_triangle();
+        let options = DxfOptions {
+            adjusted_layer: "SURVEY".to_string(),
+            include_original: false,
+            scale: 1.0 / 0.3048,
+            ..DxfOptions::default()
+        };
+        let drawing = read(&solution.to_dxf(&graph, &options).unwrap());
+        assert_eq!(drawing.layers, [("SURVEY".to_string(), 7)]);
+        assert_eq!(drawing.lines.len(), graph.num_edges());
+        assert!(drawing.lines.iter().all(|line| line.layer == "SURVEY"));
+
+        let last = &drawing.lines[2];
+        assert_eq!(
+            last.start,
+            [solution.x[2], solution.y[2], 12.0].map(|c| c * options.scale)
+        );
+        assert_eq!(last.end, [0.0, 0.0, 10.0 * options.scale]);
+        assert!((last.end[2] - 32.808).abs() < 1e-3);
+    }
+
+    #[test]
+    fn draws_the_error_ellipses_on_their_layer() {
+        let (graph, solution) = solved_triangle();
+        let drawing = read(&solution.to_dxf(&graph, &DxfOptions::default()).unwrap());
+        assert!(drawing.polylines.is_empty());
+        assert_eq!(drawing.layers.len(), 2);
+
+        let options = DxfOptions {
+            ellipses: vec![ErrorEllipse {
+                vertex: 2,
+                semi_major: 0.2,
+                semi_minor: 0.05,
+                azimuth_deg: 30.0,
+            }],
+            ellipse_scale: 10.0,
+            ..DxfOptions::default()
+        };
+        let drawing = read(&solution.to_dxf(&graph, &options).unwrap());
+        assert_eq!(drawing.layers[2], ("ERROR_ELLIPSES".to_string(), 5));
+        assert_eq!(drawing.lines.len(), 2 * graph.num_edges());
+        let [ellipse] = &drawing.polylines[..] else {
+            panic!("one ellipse expected, got {:?}", drawing.polylines);
+        };
+        assert_eq!(ellipse.layer, "ERROR_ELLIPSES");
+        assert_eq!(ellipse.vertices.len(), ELLIPSE_SEGMENTS);
+        // The first vertex ends the major axis, at 30 degrees east of north; a quarter of
+        // the way round is the end of the minor axis, 120 degrees east of north.
+        let (sin, cos) = 30f64.to_radians().sin_cos();
+        let center = [solution.x[2], solution.y[2], 12.0];
+        let expected = [
+            (0, [center[0] + 2.0 * sin, center[1] + 2.0 * cos]),
+            (
+                ELLIPSE_SEGMENTS / 4,
+                [center[0] + 0.5 * cos, center[1] - 0.5 * sin],
+            ),
+        ];
+        for (k, [x, y]) in expected {
+            let vertex = ellipse.vertices[k];
+            assert!((vertex[0] - x).abs() < 1e-9 && (vertex[1] - y).abs() < 1e-9);
+            assert_eq!(vertex[2], 12.0);
+        }
+    }
+
+    #[test]
+    fn skips_the_ellipses_of_a_vertex_out_of_range() {
+        let (graph, solution) = solved_triangle();
+        let options = DxfOptions {
+            ellipses: vec![ErrorEllipse {
+                vertex: 3,
+                semi_major: 0.2,
+                semi_minor: 0.05,
+                azimuth_deg: 30.0,
+            }],
+            ..DxfOptions::default()
+        };
+        let drawing = read(&solution.to_dxf(&graph, &options).unwrap());
+        assert!(drawing.polylines.is_empty());
+        assert_eq!(drawing.layers.len(), 2);
+    }
+
+    #[test]
+    fn rejects_a_solution_of_another_graph() {
+        let (mut graph, solution) = solved_triangle();
+        graph.add_vertex(0.0, 0.0, 0.0, false);
+        let err = solution.to_dxf(&graph, &DxfOptions::default()).unwrap_err();
+        assert_eq!(err.code, crate::COMPASS_ERR_INVALID_ARGUMENT);
+        assert_eq!(err.message, "solution has 3 / 3 coordinates for 4 vertices");
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("plot.dxf");
+        let err = solution
+            .write_dxf(&graph, &path, &DxfOptions::default())
+            .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn writes_the_file() {
+        let (graph, solution) = solved_triangle();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("plot.dxf");
+        solution
+            .write_dxf(&graph, &path, &DxfOptions::default())
+            .unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(
+            text,
+            solution.to_dxf(&graph, &DxfOptions::default()).unwrap()
+        );
+    }
+}
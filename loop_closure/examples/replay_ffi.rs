@@ -0,0 +1,40 @@
+//! Replays a solve captured from the C ABI, see [`graph_solver::capture`].
+//!
+//! ```text
+//! cargo run --example replay_ffi -- <CAPTURE>
+//! ```
+//!
+//! Prints the result code, the statistics and the error message of the replayed call, then
+//! the largest coordinate difference with the outputs of the captured call, if it recorded
+//! them. Exits with 1 if the capture cannot be read.
+
+use graph_solver::capture::replay_capture;
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    let Some(path) = std::env::args_os().nth(1) else {
+        eprintln!("Usage: replay_ffi <CAPTURE>");
+        return ExitCode::FAILURE;
+    };
+    let replay = match replay_capture(&path) {
+        Ok(replay) => replay,
+        Err(error) => {
+            eprintln!("{error}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    println!("code: {}", replay.outputs.code);
+    println!("stats: {:#?}", replay.stats);
+    if !replay.message.is_empty() {
+        println!("message: {}", replay.message);
+    }
+    match (&replay.recorded, replay.divergence()) {
+        (Some(recorded), Some(divergence)) => {
+            println!("recorded code: {}", recorded.code);
+            println!("largest coordinate difference: {divergence:e}");
+        }
+        _ => println!("no recorded outputs: the captured call did not return"),
+    }
+    ExitCode::SUCCESS
+}
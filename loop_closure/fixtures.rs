@@ -0,0 +1,276 @@
+//! Synthetic cave networks for benchmarks and performance regression checks.
+//!
+//! [`synthetic_cave`] grows a reproducible network from a seed: traverses of shots a few
+//! meters long, branching off earlier stations now and then, with cross-connections that
+//! close loops, and two anchors. The observations are the true shot vectors with a small
+//! noise, so every loop misses by a little, as in a real survey.
+//!
+//! The [`GOLDEN`] fixtures pin the number of Conjugate Gradient iterations a solve takes for
+//! fixed seeds and sizes; [`check_golden`] solves them again and reports those that need
+//! more. The benchmarks of `benches/` draw their networks from the same generator.
+
+use crate::spmv::Spmv;
+use crate::{DEFAULT_AMG_COARSE_SIZE, DEFAULT_SCHWARZ_BLOCK_SIZE, DEFAULT_SCHWARZ_OVERLAP};
+use crate::{Graph, LinearSolver, Preconditioner, SolveError};
+use nalgebra_sparse::CsrMatrix;
+
+pub use crate::spmv::RowSplit;
+
+/// Residual tolerance of the [`GOLDEN`] solves.
+pub const GOLDEN_TOLERANCE: f64 = 1e-8;
+
+/// Iteration limit of the [`GOLDEN`] solves.
+pub const GOLDEN_ITERATIONS: usize = 100_000;
+
+/// Default factor by which the iteration count of a [`GOLDEN`] fixture may grow before
+/// [`check_golden`] reports it.
+pub const DEFAULT_REGRESSION_FACTOR: f64 = 1.25;
+
+/// A network of the generator and the iterations its solve is expected to take.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Fixture {
+    pub name: &'static str,
+    /// Seed of [`synthetic_cave`].
+    pub seed: u64,
+    /// Number of stations.
+    pub vertices: usize,
+    pub preconditioner: Preconditioner,
+    /// Iterations of the Conjugate Gradient solve to [`GOLDEN_TOLERANCE`] when the fixture
+    /// was recorded.
+    pub iterations: usize,
+}
+
+const AMG: Preconditioner = Preconditioner::Amg {
+    coarse_size: DEFAULT_AMG_COARSE_SIZE,
+};
+const SCHWARZ: Preconditioner = Preconditioner::AdditiveSchwarz {
+    block_size: DEFAULT_SCHWARZ_BLOCK_SIZE,
+    overlap: DEFAULT_SCHWARZ_OVERLAP,
+};
+
+/// The fixtures checked by [`check_golden`].
+pub const GOLDEN: &[Fixture] = &[
+    Fixture {
+        name: "1k-none",
+        seed: 1,
+        vertices: 1_000,
+        preconditioner: Preconditioner::None,
+        iterations: 2_178,
+    },
+    Fixture {
+        name: "1k-jacobi",
+        seed: 1,
+        vertices: 1_000,
+        preconditioner: Preconditioner::Jacobi,
+        iterations: 1_279,
+    },
+    Fixture {
+        name: "10k-jacobi",
+        seed: 2,
+        vertices: 10_000,
+        preconditioner: Preconditioner::Jacobi,
+        iterations: 4_661,
+    },
+    Fixture {
+        name: "10k-amg",
+        seed: 2,
+        vertices: 10_000,
+        preconditioner: AMG,
+        iterations: 36,
+    },
+    Fixture {
+        name: "10k-schwarz",
+        seed: 2,
+        vertices: 10_000,
+        preconditioner: SCHWARZ,
+        iterations: 718,
+    },
+];
+
+/// A [`GOLDEN`] fixture whose solve took more than the allowed iterations.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Regression {
+    pub fixture: Fixture,
+    /// Iterations the solve takes now.
+    pub iterations: usize,
+}
+
+type Point = (f64, f64, f64);
+
+/// SplitMix64, enough for reproducible networks without a dependency.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `[0, 1)`.
+    fn uniform(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Uniform in `0..n`.
+    fn below(&mut self, n: usize) -> usize {
+        (self.uniform() * n as f64) as usize
+    }
+}
+
+/// A reproducible cave network of `vertices` stations grown from `seed`, see the module
+/// documentation. Station 0 and the last station are fixed at their true coordinates; the
+/// others start at the origin. Shots are weighted by their inverse squared length.
+pub fn synthetic_cave(seed: u64, vertices: usize) -> Graph {
+    let mut rng = Rng(seed);
+    let mut graph = Graph::default();
+    let mut truth: Vec<Point> = Vec::with_capacity(vertices);
+    let mut heading = 0.0f64;
+    // Adds the shot `from -> to`, observed with a noise of 1% of its length.
+    let observe = |graph: &mut Graph, rng: &mut Rng, from: usize, to: usize, truth: &[Point]| {
+        let (dx, dy, dz) = (
+            truth[to].0 - truth[from].0,
+            truth[to].1 - truth[from].1,
+            truth[to].2 - truth[from].2,
+        );
+        let length = (dx * dx + dy * dy + dz * dz).sqrt().max(0.1);
+        let mut noise = || 0.01 * length * (rng.uniform() - 0.5);
+        let (nx, ny, nz) = (noise(), noise(), noise());
+        let weight = 1.0 / (length * length);
+        graph.add_edge(from, to, dx + nx, dy + ny, dz + nz, weight);
+    };
+
+    for i in 0..vertices {
+        let parent = match i {
+            0 => None,
+            // Most shots continue the traverse, some branch off an earlier station.
+            _ if rng.uniform() < 0.95 => Some(i - 1),
+            _ => Some(rng.below(i)),
+        };
+        let position = match parent {
+            None => (0.0, 0.0, 0.0),
+            Some(p) => {
+                heading += (rng.uniform() - 0.5) * 0.8;
+                let length = 2.0 + 8.0 * rng.uniform();
+                let (x, y, z) = truth[p];
+                let dip = (rng.uniform() - 0.5) * 0.4;
+                (
+                    x + length * heading.cos(),
+                    y + length * heading.sin(),
+                    z + length * dip,
+                )
+            }
+        };
+        truth.push(position);
+        graph.add_vertex(0.0, 0.0, 0.0, false);
+        if let Some(p) = parent {
+            observe(&mut graph, &mut rng, p, i, &truth);
+        }
+        // A cross-connection to one of the recent stations closes a loop.
+        if i > 50 && rng.uniform() < 0.05 {
+            let other = i - 10 - rng.below(40);
+            observe(&mut graph, &mut rng, other, i, &truth);
+        }
+    }
+    for v in [0, vertices.saturating_sub(1)] {
+        if v < vertices {
+            graph.fixed[v] = true;
+            (graph.x[v], graph.y[v], graph.z[v]) = truth[v];
+        }
+    }
+    graph
+}
+
+/// Iterations of the Conjugate Gradient solve of `fixture` to [`GOLDEN_TOLERANCE`].
+pub fn measure_iterations(fixture: &Fixture) -> Result<usize, SolveError> {
+    let graph = synthetic_cave(fixture.seed, fixture.vertices);
+    let solution = graph.solve_with(
+        GOLDEN_ITERATIONS,
+        GOLDEN_TOLERANCE,
+        LinearSolver::ConjugateGradient,
+        fixture.preconditioner,
+    )?;
+    Ok(solution.stats.iterations as usize)
+}
+
+/// Solves the [`GOLDEN`] fixtures and returns those that took more than `factor` times
+/// their recorded iterations.
+pub fn check_golden(factor: f64) -> Result<Vec<Regression>, SolveError> {
+    let mut regressions = Vec::new();
+    for fixture in GOLDEN {
+        let iterations = measure_iterations(fixture)?;
+        if iterations as f64 > factor * fixture.iterations as f64 {
+            regressions.push(Regression {
+                fixture: *fixture,
+                iterations,
+            });
+        }
+    }
+    Ok(regressions)
+}
+
+/// Normal matrix of a graph, for the benchmarks of the product `A p` that CG computes every
+/// iteration.
+pub struct NormalProduct {
+    matrix: CsrMatrix<f64>,
+    x: Vec<f64>,
+    y: Vec<f64>,
+}
+
+impl NormalProduct {
+    /// The normal matrix of `graph` over all its edges.
+    pub fn new(graph: &Graph) -> Result<NormalProduct, SolveError> {
+        let matrix = graph.normal_equations(&[])?.matrix;
+        let n = matrix.nrows();
+        Ok(NormalProduct {
+            matrix,
+            x: (0..n).map(|i| (i as f64 * 0.37).sin()).collect(),
+            y: vec![0.0; n],
+        })
+    }
+
+    /// Computes `products` products with the rows split by `split` across `threads` threads,
+    /// spawned once as for a solve.
+    pub fn run(&mut self, split: RowSplit, threads: usize, products: usize) {
+        let spmv = Spmv::with_threads(&self.matrix, split, threads);
+        for _ in 0..products {
+            spmv.apply(&self.x, &mut self.y);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn synthetic_caves_are_reproducible() {
+        let graph = synthetic_cave(7, 500);
+        let again = synthetic_cave(7, 500);
+        assert_eq!(graph.x.len(), 500);
+        assert_eq!(graph.from, again.from);
+        assert_eq!(graph.to, again.to);
+        assert_eq!(graph.dx, again.dx);
+        assert_eq!(graph.weight, again.weight);
+        assert_ne!(synthetic_cave(8, 500).dx, graph.dx);
+        // Anchored at both ends, with loops closed by cross-connections.
+        assert!(graph.fixed[0] && graph.fixed[499]);
+        assert_eq!(graph.fixed.iter().filter(|&&f| f).count(), 2);
+        assert!(graph.from.len() > 499);
+    }
+
+    #[test]
+    fn small_golden_fixtures_match_their_record() {
+        for fixture in GOLDEN.iter().filter(|f| f.vertices <= 1_000) {
+            let iterations = measure_iterations(fixture).unwrap();
+            assert!(
+                iterations as f64 <= DEFAULT_REGRESSION_FACTOR * fixture.iterations as f64,
+                "{}: {iterations} iterations, recorded {}",
+                fixture.name,
+                fixture.iterations
+            );
+        }
+    }
+}
@@ -0,0 +1,9 @@
+//! Feeds arbitrary CSV, Compass, JSON and protobuf input to the importers, see
+//! [`graph_solver::fuzzing::check_import`].
+
+#![no_main]
+
+use graph_solver::fuzzing::{ImportCase, check_import};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|case: ImportCase| check_import(&case));
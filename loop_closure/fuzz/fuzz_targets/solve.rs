@@ -0,0 +1,9 @@
+//! Solves arbitrary small graphs through the safe API and the C entry point, see
+//! [`graph_solver::fuzzing::check_solve`].
+
+#![no_main]
+
+use graph_solver::fuzzing::{FuzzCase, check_solve};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|case: FuzzCase| check_solve(&case));
@@ -0,0 +1,19 @@
+//! Writes the seed corpora of the fuzz targets to `corpus/solve` and `corpus/importers`, or
+//! under the directory given as argument.
+//!
+//! ```text
+//! cargo run --bin seed_corpus
+//! cargo fuzz run solve corpus/solve
+//! ```
+
+use graph_solver::fuzzing::write_seed_corpus;
+
+/// Networks written per corpus.
+const SEEDS: u64 = 32;
+
+fn main() -> std::io::Result<()> {
+    let dir = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "corpus".to_string());
+    write_seed_corpus(&dir, SEEDS)
+}
@@ -0,0 +1,626 @@
+//! Structured inputs for fuzzing the solver and the importers with cargo-fuzz.
+//!
+//! [`FuzzCase`] and [`ImportCase`] implement [`Arbitrary`], decoding any byte string into a
+//! solve or an import, and [`check_solve`] / [`check_import`] run them and panic when an
+//! invariant breaks. The targets of `fuzz/` are thin wrappers around these.
+//!
+//! The decoding is our own rather than derived, so that [`FuzzCase::to_bytes`] and
+//! [`ImportCase::to_bytes`] can encode the seed corpora of [`write_seed_corpus`]. Graphs are
+//! kept small and their edges within range, which the safe API assumes; numbers are mostly
+//! survey-like values, with an escape to arbitrary bit patterns, NaN and infinities
+//! included.
+
+use crate::{
+    COMPASS_ERR_INTERNAL, COMPASS_ERR_PANIC, COMPASS_OK, Graph, LinearSolver, Preconditioner,
+    SolveError, SolveOptions, SolveStats, solve_graph_least_squares_ex,
+};
+use arbitrary::{Arbitrary, Result, Unstructured};
+use std::ffi::{c_char, c_int};
+
+/// Largest number of vertices of a decoded [`Graph`].
+pub const MAX_FUZZ_VERTICES: usize = 64;
+
+/// Largest number of edges of a decoded [`Graph`].
+pub const MAX_FUZZ_EDGES: usize = 128;
+
+/// Tag of a number stored as its raw bits rather than in millimetres.
+const RAW: u8 = 0xff;
+
+/// `N` bytes of `u`, zero-filled once the input is exhausted.
+fn take<const N: usize>(u: &mut Unstructured) -> Result<[u8; N]> {
+    let mut buf = [0; N];
+    u.fill_buffer(&mut buf)?;
+    Ok(buf)
+}
+
+fn byte(u: &mut Unstructured) -> Result<u8> {
+    Ok(take::<1>(u)?[0])
+}
+
+fn flag(u: &mut Unstructured) -> Result<bool> {
+    Ok(byte(u)? & 1 == 1)
+}
+
+/// A coordinate or an observation: a whole number of millimetres, or any `f64` after the
+/// [`RAW`] tag.
+fn number(u: &mut Unstructured) -> Result<f64> {
+    match byte(u)? {
+        RAW => Ok(f64::from_le_bytes(take(u)?)),
+        _ => Ok(f64::from(i32::from_le_bytes(take(u)?)) / 1000.0),
+    }
+}
+
+/// Inverse of [`number`], to the millimetre.
+fn put_number(out: &mut Vec<u8>, value: f64) {
+    let millimetres = (value * 1000.0).round();
+    if millimetres.abs() <= f64::from(i32::MAX) {
+        out.push(0);
+        out.extend_from_slice(&(millimetres as i32).to_le_bytes());
+    } else {
+        out.push(RAW);
+        out.extend_from_slice(&value.to_le_bytes());
+    }
+}
+
+impl<'a> Arbitrary<'a> for Graph {
+    /// Up to [`MAX_FUZZ_VERTICES`] vertices and [`MAX_FUZZ_EDGES`] edges, whose endpoints are
+    /// always in range. Weights are those of shots up to a few hundred metres long, zero
+    /// included, unless raw.
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let mut graph = Graph::default();
+        let vertices = usize::from(byte(u)?) % (MAX_FUZZ_VERTICES + 1);
+        for _ in 0..vertices {
+            let (x, y) = (number(u)?, number(u)?);
+            graph.add_vertex(x, y, 0.0, flag(u)?);
+        }
+        let edges = match vertices {
+            0 => 0,
+            _ => usize::from(byte(u)?) % (MAX_FUZZ_EDGES + 1),
+        };
+        for _ in 0..edges {
+            let from = usize::from(byte(u)?) % vertices;
+            let to = usize::from(byte(u)?) % vertices;
+            let (dx, dy) = (number(u)?, number(u)?);
+            let weight = match byte(u)? {
+                RAW => f64::from_le_bytes(take(u)?),
+                _ => f64::from(u16::from_le_bytes(take(u)?)) / 1000.0,
+            };
+            graph.add_edge(from, to, dx, dy, 0.0, weight);
+        }
+        Ok(graph)
+    }
+}
+
+/// Inverse of the [`Arbitrary`] decoding of a [`Graph`], to the millimetre. The graph must
+/// fit its limits.
+fn put_graph(out: &mut Vec<u8>, graph: &Graph) {
+    out.push(graph.num_vertices() as u8);
+    for v in 0..graph.num_vertices() {
+        put_number(out, graph.x[v]);
+        put_number(out, graph.y[v]);
+        out.push(graph.fixed[v] as u8);
+    }
+    if graph.num_vertices() > 0 {
+        out.push(graph.num_edges() as u8);
+    }
+    for e in 0..graph.num_edges() {
+        out.extend_from_slice(&[graph.from[e] as u8, graph.to[e] as u8]);
+        put_number(out, graph.dx[e]);
+        put_number(out, graph.dy[e]);
+        let weight = (graph.weight[e] * 1000.0).round();
+        if (0.0..=f64::from(u16::MAX)).contains(&weight) {
+            out.push(0);
+            out.extend_from_slice(&(weight as u16).to_le_bytes());
+        } else {
+            out.push(RAW);
+            out.extend_from_slice(&graph.weight[e].to_le_bytes());
+        }
+    }
+}
+
+impl<'a> Arbitrary<'a> for LinearSolver {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(match flag(u)? {
+            false => LinearSolver::ConjugateGradient,
+            true => LinearSolver::Chebyshev,
+        })
+    }
+}
+
+impl<'a> Arbitrary<'a> for Preconditioner {
+    /// Any preconditioner, with sizes below 256 and an overlap below 4.
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(match byte(u)? % 4 {
+            0 => Preconditioner::None,
+            1 => Preconditioner::Jacobi,
+            2 => Preconditioner::Amg {
+                coarse_size: usize::from(byte(u)?),
+            },
+            _ => Preconditioner::AdditiveSchwarz {
+                block_size: usize::from(byte(u)?),
+                overlap: usize::from(byte(u)? % 4),
+            },
+        })
+    }
+}
+
+/// A solve through both the safe API and [`solve_graph_least_squares_ex`].
+#[derive(Debug, Clone)]
+pub struct FuzzCase {
+    pub graph: Graph,
+    /// Passive flag of each vertex, see [`SolveOptions::passive`]. FFI only.
+    pub passive: Vec<bool>,
+    /// Enabled flag of each edge, see [`SolveOptions::edge_enabled`]. FFI only.
+    pub edge_enabled: Vec<bool>,
+    /// Below 65536.
+    pub iterations: usize,
+    /// A power of ten from 1 down to 1e-15, or raw.
+    pub tolerance: f64,
+    pub solver: LinearSolver,
+    /// Sent to the FFI as its code, that is with the default sizes.
+    pub preconditioner: Preconditioner,
+    /// See [`SolveOptions::keep_isolated`]. FFI only.
+    pub keep_isolated: bool,
+}
+
+impl<'a> Arbitrary<'a> for FuzzCase {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let graph = Graph::arbitrary(u)?;
+        let passive = (0..graph.num_vertices())
+            .map(|_| flag(u))
+            .collect::<Result<_>>()?;
+        let edge_enabled = (0..graph.num_edges())
+            .map(|_| byte(u).map(|b| b != 0))
+            .collect::<Result<_>>()?;
+        let iterations = usize::from(u16::from_le_bytes(take(u)?));
+        let tolerance = match byte(u)? {
+            RAW => f64::from_le_bytes(take(u)?),
+            exponent => 10f64.powi(-i32::from(exponent % 16)),
+        };
+        Ok(FuzzCase {
+            graph,
+            passive,
+            edge_enabled,
+            iterations,
+            tolerance,
+            solver: LinearSolver::arbitrary(u)?,
+            preconditioner: Preconditioner::arbitrary(u)?,
+            keep_isolated: flag(u)?,
+        })
+    }
+}
+
+impl FuzzCase {
+    /// Bytes that decode back to this case, coordinates and observations rounded to the
+    /// millimetre and weights to a thousandth. The graph must fit [`MAX_FUZZ_VERTICES`] and
+    /// [`MAX_FUZZ_EDGES`]; sizes that do not fit a byte are truncated.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        put_graph(&mut out, &self.graph);
+        out.extend(self.passive.iter().map(|&p| p as u8));
+        out.extend(self.edge_enabled.iter().map(|&e| e as u8));
+        out.extend_from_slice(&(self.iterations.min(usize::from(u16::MAX)) as u16).to_le_bytes());
+        match (0..16).find(|&k| 10f64.powi(-k) == self.tolerance) {
+            Some(k) => out.push(k as u8),
+            None => {
+                out.push(RAW);
+                out.extend_from_slice(&self.tolerance.to_le_bytes());
+            }
+        }
+        out.push((self.solver == LinearSolver::Chebyshev) as u8);
+        match self.preconditioner {
+            Preconditioner::None => out.push(0),
+            Preconditioner::Jacobi => out.push(1),
+            Preconditioner::Amg { coarse_size } => out.extend_from_slice(&[2, coarse_size as u8]),
+            Preconditioner::AdditiveSchwarz {
+                block_size,
+                overlap,
+            } => out.extend_from_slice(&[3, block_size as u8, overlap as u8]),
+        }
+        out.push(self.keep_isolated as u8);
+        out
+    }
+
+    /// Whether every number of the case is finite, under which a successful solve must give
+    /// finite coordinates.
+    fn is_finite(&self) -> bool {
+        let g = &self.graph;
+        [&g.x, &g.y, &g.dx, &g.dy, &g.weight]
+            .iter()
+            .all(|values| values.iter().all(|v| v.is_finite()))
+            && self.tolerance.is_finite()
+    }
+}
+
+/// Solves `case` with [`Graph::solve_with`] and with [`solve_graph_least_squares_ex`] over
+/// buffers of its own, and panics unless: no panic reached the FFI boundary, the fixed
+/// vertices come back bit for bit, and a successful solve of finite inputs gives finite
+/// coordinates.
+pub fn check_solve(case: &FuzzCase) {
+    let graph = &case.graph;
+    let fixed_unchanged = |x: &[f64], y: &[f64]| {
+        (0..graph.num_vertices())
+            .filter(|&v| graph.fixed[v])
+            .all(|v| {
+                x[v].to_bits() == graph.x[v].to_bits() && y[v].to_bits() == graph.y[v].to_bits()
+            })
+    };
+    let all_finite = |x: &[f64], y: &[f64]| x.iter().chain(y).all(|v| v.is_finite());
+
+    match graph.solve_with(
+        case.iterations,
+        case.tolerance,
+        case.solver,
+        case.preconditioner,
+    ) {
+        Ok(solution) => {
+            let (x, y) = (&solution.x, &solution.y);
+            assert_eq!(x.len(), graph.num_vertices());
+            assert!(fixed_unchanged(x, y), "safe API moved a fixed vertex");
+            assert!(
+                !case.is_finite() || all_finite(x, y),
+                "safe API returned non-finite coordinates"
+            );
+        }
+        Err(SolveError { code, message }) => {
+            assert!(
+                code != COMPASS_ERR_PANIC && code != COMPASS_ERR_INTERNAL,
+                "safe API failed with {code}: {message}"
+            );
+        }
+    }
+
+    let flags = |values: &[bool]| values.iter().map(|&b| b as c_int).collect::<Vec<_>>();
+    let indices = |values: &[usize]| values.iter().map(|&v| v as c_int).collect::<Vec<_>>();
+    let (mut x, mut y) = (graph.x.clone(), graph.y.clone());
+    let (fixed, passive, enabled) = (
+        flags(&graph.fixed),
+        flags(&case.passive),
+        flags(&case.edge_enabled),
+    );
+    let (from, to) = (indices(&graph.from), indices(&graph.to));
+    let mut message = [0 as c_char; 256];
+    let options = SolveOptions {
+        iterations: case.iterations as c_int,
+        tolerance: case.tolerance,
+        passive: passive.as_ptr(),
+        edge_enabled: enabled.as_ptr(),
+        solver: case.solver as c_int,
+        preconditioner: match case.preconditioner {
+            Preconditioner::None => 0,
+            Preconditioner::Jacobi => 1,
+            Preconditioner::Amg { .. } => 2,
+            Preconditioner::AdditiveSchwarz { .. } => 3,
+        },
+        verify_fixed: 1,
+        error_message: message.as_mut_ptr(),
+        error_capacity: message.len() as c_int,
+        keep_isolated: case.keep_isolated as c_int,
+        ..SolveOptions::default()
+    };
+    let mut stats = SolveStats::default();
+    let code = solve_graph_least_squares_ex(
+        graph.num_vertices() as c_int,
+        x.as_mut_ptr(),
+        y.as_mut_ptr(),
+        fixed.as_ptr(),
+        graph.num_edges() as c_int,
+        from.as_ptr(),
+        to.as_ptr(),
+        graph.dx.as_ptr(),
+        graph.dy.as_ptr(),
+        graph.weight.as_ptr(),
+        &options,
+        &mut stats,
+    );
+    assert!(
+        code != COMPASS_ERR_PANIC && code != COMPASS_ERR_INTERNAL,
+        "FFI failed with {code}"
+    );
+    assert!(fixed_unchanged(&x, &y), "FFI moved a fixed vertex");
+    assert!(
+        code != COMPASS_OK || !case.is_finite() || all_finite(&x, &y),
+        "FFI returned non-finite coordinates"
+    );
+}
+
+#[cfg(all(
+    feature = "compass_io",
+    feature = "io-csv",
+    feature = "io-json",
+    feature = "io-protobuf"
+))]
+pub use importers::{ImportCase, check_import};
+
+#[cfg(all(
+    feature = "compass_io",
+    feature = "io-csv",
+    feature = "io-json",
+    feature = "io-protobuf"
+))]
+mod importers {
+    use crate::json_io::solve_json;
+    use crate::protobuf_io::solve_protobuf;
+    use crate::{COMPASS_ERR_INTERNAL, COMPASS_ERR_PANIC, Graph, ImportError, SolveError};
+    use arbitrary::{Arbitrary, Result, Unstructured};
+    use std::path::PathBuf;
+
+    /// The input of one importer. Decoded from a format byte followed by the file contents,
+    /// the two files of a format split at the first NUL byte.
+    #[derive(Debug, Clone, PartialEq)]
+    pub enum ImportCase {
+        /// Vertices and edges files of [`Graph::from_csv`].
+        Csv { vertices: String, edges: String },
+        /// A `.mak` project and the `fuzz.dat` file next to it, see
+        /// [`Graph::from_compass_project`].
+        Compass { mak: String, dat: String },
+        /// A document of [`solve_json`].
+        Json(String),
+        /// A message of [`solve_protobuf`].
+        Protobuf(Vec<u8>),
+    }
+
+    impl<'a> Arbitrary<'a> for ImportCase {
+        fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+            let format = super::byte(u)?;
+            let rest = u.bytes(u.len())?;
+            let text = |bytes: &[u8]| String::from_utf8_lossy(bytes).into_owned();
+            let (first, second) = match rest.iter().position(|&b| b == 0) {
+                Some(nul) => (&rest[..nul], &rest[nul + 1..]),
+                None => (rest, &[][..]),
+            };
+            Ok(match format % 4 {
+                0 => ImportCase::Csv {
+                    vertices: text(first),
+                    edges: text(second),
+                },
+                1 => ImportCase::Compass {
+                    mak: text(first),
+                    dat: text(second),
+                },
+                2 => ImportCase::Json(text(rest)),
+                _ => ImportCase::Protobuf(rest.to_vec()),
+            })
+        }
+    }
+
+    impl ImportCase {
+        /// Bytes that decode back to this case, provided the first file of a pair holds no
+        /// NUL.
+        pub fn to_bytes(&self) -> Vec<u8> {
+            let pair = |format: u8, first: &str, second: &str| {
+                [&[format][..], first.as_bytes(), &[0], second.as_bytes()].concat()
+            };
+            match self {
+                ImportCase::Csv { vertices, edges } => pair(0, vertices, edges),
+                ImportCase::Compass { mak, dat } => pair(1, mak, dat),
+                ImportCase::Json(text) => [&[2][..], text.as_bytes()].concat(),
+                ImportCase::Protobuf(bytes) => [&[3][..], bytes].concat(),
+            }
+        }
+    }
+
+    /// Runs the importer of `case`, the file based ones over a directory of this process,
+    /// and panics unless it returns an error without panicking or a graph whose edges are
+    /// within range.
+    pub fn check_import(case: &ImportCase) {
+        let dir = std::env::temp_dir().join(format!("graph_solver_fuzz_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("fuzz directory");
+        let write = |name: &str, contents: &str| -> PathBuf {
+            let path = dir.join(name);
+            std::fs::write(&path, contents).expect("fuzz file");
+            path
+        };
+        let check_graph = |result: std::result::Result<Graph, ImportError>| {
+            if let Ok(graph) = result {
+                let n = graph.num_vertices();
+                assert!(graph.from.iter().chain(&graph.to).all(|&v| v < n));
+                assert_eq!(graph.weight.len(), graph.num_edges());
+            }
+        };
+        let check_solve = |code: Option<i32>| {
+            assert!(
+                code != Some(COMPASS_ERR_PANIC) && code != Some(COMPASS_ERR_INTERNAL),
+                "importer failed with {code:?}"
+            );
+        };
+
+        match case {
+            ImportCase::Csv { vertices, edges } => check_graph(Graph::from_csv(
+                write("vertices.csv", vertices),
+                write("edges.csv", edges),
+            )),
+            ImportCase::Compass { mak, dat } => {
+                write("fuzz.dat", dat);
+                check_graph(Graph::from_compass_project(write("fuzz.mak", mak)));
+            }
+            ImportCase::Json(text) => {
+                check_solve(solve_json(text).err().map(|SolveError { code, .. }| code))
+            }
+            ImportCase::Protobuf(bytes) => check_solve(
+                solve_protobuf(bytes)
+                    .err()
+                    .map(|SolveError { code, .. }| code),
+            ),
+        }
+    }
+}
+
+/// Writes seed corpora drawn from [`crate::fixtures::synthetic_cave`] to `dir/solve` and
+/// `dir/importers`, `count` networks each, as files named after their seed.
+#[cfg(all(
+    feature = "fixtures",
+    feature = "compass_io",
+    feature = "io-csv",
+    feature = "io-json",
+    feature = "io-protobuf"
+))]
+pub fn write_seed_corpus(dir: impl AsRef<std::path::Path>, count: u64) -> std::io::Result<()> {
+    use crate::compass_io::FEET_TO_METERS;
+    use crate::fixtures::synthetic_cave;
+    use crate::{DEFAULT_AMG_COARSE_SIZE, DEFAULT_SCHWARZ_BLOCK_SIZE, DEFAULT_SCHWARZ_OVERLAP};
+    use std::fmt::Write as _;
+
+    let (solve_dir, import_dir) = (dir.as_ref().join("solve"), dir.as_ref().join("importers"));
+    std::fs::create_dir_all(&solve_dir)?;
+    std::fs::create_dir_all(&import_dir)?;
+    let preconditioners = [
+        Preconditioner::None,
+        Preconditioner::Jacobi,
+        Preconditioner::Amg {
+            coarse_size: DEFAULT_AMG_COARSE_SIZE,
+        },
+        Preconditioner::AdditiveSchwarz {
+            block_size: DEFAULT_SCHWARZ_BLOCK_SIZE,
+            overlap: DEFAULT_SCHWARZ_OVERLAP,
+        },
+    ];
+    for seed in 0..count {
+        let vertices = 8 + (seed as usize * 7) % (MAX_FUZZ_VERTICES - 7);
+        let graph = synthetic_cave(seed, vertices);
+        let (n, m) = (graph.num_vertices(), graph.num_edges());
+        if m > MAX_FUZZ_EDGES {
+            continue;
+        }
+        let case = FuzzCase {
+            passive: vec![false; n],
+            edge_enabled: vec![true; m],
+            iterations: 1000,
+            tolerance: 1e-8,
+            solver: LinearSolver::ConjugateGradient,
+            preconditioner: preconditioners[seed as usize % preconditioners.len()],
+            keep_isolated: false,
+            graph,
+        };
+        std::fs::write(solve_dir.join(format!("seed-{seed}")), case.to_bytes())?;
+
+        let graph = &case.graph;
+        let fixed: Vec<usize> = (0..n).filter(|&v| graph.fixed[v]).collect();
+        let (mut vertices, mut edges) = (
+            "id,name,x,y,fixed\n".to_string(),
+            "from,to,dx,dy,weight\n".to_string(),
+        );
+        let (mut json_vertices, mut json_edges) = (Vec::new(), Vec::new());
+        for v in 0..n {
+            let (x, y, f) = (graph.x[v], graph.y[v], graph.fixed[v]);
+            let _ = writeln!(vertices, "{v},S{v},{x},{y},{}", f as u8);
+            json_vertices.push(format!(r#"{{"x": {x}, "y": {y}, "fixed": {f}}}"#));
+        }
+        let mut mak = "#fuzz.dat".to_string();
+        for &v in &fixed {
+            let (x, y, z) = (graph.x[v], graph.y[v], graph.z[v]);
+            let _ = write!(mak, ",S{v}[M,{x},{y},{z}]");
+        }
+        mak.push_str(";\n");
+        let mut dat = "FUZZ CAVE\nSURVEY NAME: S\nSURVEY DATE: 1 1 2000\nSURVEY TEAM:\n\n\
+                       DECLINATION: 0.00  FORMAT: DDDDLUDRLADN  CORRECTIONS: 0.00 0.00 0.00\n\n\
+                       FROM TO LENGTH BEARING INC LEFT UP DOWN RIGHT\n\n"
+            .to_string();
+        for e in 0..m {
+            let (u, v) = (graph.from[e], graph.to[e]);
+            let (dx, dy, dz, w) = (graph.dx[e], graph.dy[e], graph.dz[e], graph.weight[e]);
+            let _ = writeln!(edges, "{u},{v},{dx},{dy},{w}");
+            json_edges.push(format!(
+                r#"{{"from": {u}, "to": {v}, "dx": {dx}, "dy": {dy}, "weight": {w}}}"#
+            ));
+            let length = (dx * dx + dy * dy + dz * dz).sqrt() / FEET_TO_METERS;
+            let bearing = dx.atan2(dy).to_degrees().rem_euclid(360.0);
+            let inclination = dz.atan2(dx.hypot(dy)).to_degrees();
+            let _ = writeln!(
+                dat,
+                "S{u} S{v} {length:.2} {bearing:.2} {inclination:.2} 0.00 0.00 0.00 0.00"
+            );
+        }
+        let json = format!(
+            r#"{{"vertices": [{}], "edges": [{}]}}"#,
+            json_vertices.join(", "),
+            json_edges.join(", ")
+        );
+        for (name, case) in [
+            ("csv", ImportCase::Csv { vertices, edges }),
+            ("compass", ImportCase::Compass { mak, dat }),
+            ("json", ImportCase::Json(json)),
+        ] {
+            std::fs::write(import_dir.join(format!("{name}-{seed}")), case.to_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn case(graph: Graph) -> FuzzCase {
+        let (n, m) = (graph.num_vertices(), graph.num_edges());
+        FuzzCase {
+            passive: vec![false; n],
+            edge_enabled: vec![true; m],
+            iterations: 1000,
+            tolerance: 1e-8,
+            solver: LinearSolver::ConjugateGradient,
+            preconditioner: Preconditioner::Jacobi,
+            keep_isolated: false,
+            graph,
+        }
+    }
+
+    fn decode(bytes: &[u8]) -> FuzzCase {
+        FuzzCase::arbitrary(&mut Unstructured::new(bytes)).unwrap()
+    }
+
+    #[test]
+    fn cases_decode_from_their_bytes() {
+        let mut graph = Graph::default();
+        graph.add_vertex(1.25, -3.5, 0.0, true);
+        graph.add_vertex(0.0, 0.0, 0.0, false);
+        graph.add_vertex(f64::NAN, 1e300, 0.0, false);
+        graph.add_edge(0, 1, 10.001, -2.0, 0.0, 0.25);
+        graph.add_edge(1, 2, 3.0, 4.0, 0.0, 1e6);
+        let mut original = case(graph);
+        original.edge_enabled[1] = false;
+        original.tolerance = 0.5;
+        original.preconditioner = Preconditioner::AdditiveSchwarz {
+            block_size: 16,
+            overlap: 2,
+        };
+        let decoded = decode(&original.to_bytes());
+        let (a, b) = (&original.graph, &decoded.graph);
+        assert_eq!((&a.from, &a.to, &a.fixed), (&b.from, &b.to, &b.fixed));
+        assert_eq!((&a.dx, &a.dy, &a.weight), (&b.dx, &b.dy, &b.weight));
+        assert_eq!(a.x[..2], b.x[..2]);
+        assert!(b.x[2].is_nan());
+        assert_eq!(b.y[2], 1e300);
+        assert_eq!(decoded.edge_enabled, original.edge_enabled);
+        assert_eq!(decoded.tolerance, 0.5);
+        assert_eq!(decoded.preconditioner, original.preconditioner);
+        // Running out of input decodes to an empty graph rather than failing.
+        assert_eq!(decode(&[]).graph.num_vertices(), 0);
+    }
+
+    #[test]
+    fn synthetic_caves_pass_the_solve_check() {
+        for seed in 0..4 {
+            let graph = crate::fixtures::synthetic_cave(seed, 40);
+            check_solve(&decode(&case(graph).to_bytes()));
+        }
+    }
+
+    #[test]
+    fn hostile_cases_pass_the_solve_check() {
+        let mut graph = Graph::default();
+        graph.add_vertex(f64::INFINITY, 0.0, 0.0, true);
+        graph.add_vertex(0.0, f64::NAN, 0.0, false);
+        graph.add_vertex(0.0, 0.0, 0.0, false);
+        graph.add_edge(0, 1, f64::MAX, 0.0, 0.0, 1.0);
+        graph.add_edge(1, 1, 1.0, 1.0, 0.0, 0.0);
+        let mut hostile = case(graph);
+        hostile.iterations = 0;
+        hostile.tolerance = f64::NAN;
+        hostile.keep_isolated = true;
+        check_solve(&hostile);
+        for bytes in [&[0xff; 64][..], &[7; 300], &[1, 2, 3]] {
+            check_solve(&decode(bytes));
+        }
+    }
+}
@@ -0,0 +1,481 @@
+//! GeoJSON export of an adjusted network: stations as `Point` features and edges as
+//! `LineString` features, with displacement and residual properties for GIS review.
+//!
+//! Coordinates stay in the local grid unless a caller-supplied affine transform is given;
+//! the real projection is left to the GIS. Numbers are written with Rust's shortest
+//! round-trip representation, so no precision is lost at UTM scale.
+//!
+//! Without a transform, the solution's coordinate system is noted in two members of the
+//! collection: the legacy `crs` member naming its EPSG code, which GIS still read, and a
+//! `coordinate_system` member with the projection, the length unit and the vertical datum.
+//!
+//! Error ellipses are not computed by the solve; stations get their axes and orientation
+//! when the caller passes them in [`ExportOptions::ellipses`].
+
+use crate::{
+    COMPASS_ERR_INVALID_ARGUMENT, COMPASS_ERR_IO, COMPASS_ERR_PANIC, COMPASS_OK, ErrorEllipse,
+    Graph, GraphContext, Solution, SolveError, check_export, invalid_input, write_message,
+};
+use std::ffi::{CStr, c_char, c_double, c_int};
+use std::fmt::Write as _;
+use std::path::Path;
+
+/// Identity affine transform.
+pub const IDENTITY_AFFINE: [f64; 6] = [1.0, 0.0, 0.0, 0.0, 1.0, 0.0];
+
+/// Options for [`Solution::to_geojson`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportOptions {
+    /// Affine transform `[a, b, c, d, e, f]` applied to the local coordinates:
+    /// `X = a*x + b*y + c`, `Y = d*x + e*y + f`. Z is written unchanged.
+    pub affine: [f64; 6],
+    /// Standardized residual above which an edge gets `"blunder": true`.
+    pub blunder_threshold: f64,
+    /// Error ellipses of the stations that have one, in the local grid. The affine
+    /// transform does not apply to them.
+    pub ellipses: Vec<ErrorEllipse>,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        ExportOptions {
+            affine: IDENTITY_AFFINE,
+            blunder_threshold: 3.0,
+            ellipses: Vec::new(),
+        }
+    }
+}
+
+impl Solution {
+    /// Renders the adjusted network as a GeoJSON `FeatureCollection`.
+    ///
+    /// Station properties: `index`, `name`, `fixed`, `displacement` (distance between the
+    /// graph coordinates and the adjusted ones) and, for the stations with an ellipse in
+    /// [`ExportOptions::ellipses`], `ellipse_semi_major`, `ellipse_semi_minor` and
+    /// `ellipse_azimuth` (degrees clockwise from north). Edge properties: `index`, `from`, `to`,
+    /// `residual_x`, `residual_y`, `residual`, `standardized_residual` and `blunder`. See the
+    /// [module documentation](self) for the coordinate system members. Ellipses of a vertex
+    /// out of range are skipped.
+    ///
+    /// Returns [`COMPASS_ERR_INVALID_ARGUMENT`] when the solution or an array of `graph` does
+    /// not have one value per vertex or edge, or an edge has an endpoint out of range.
+    pub fn to_geojson(&self, graph: &Graph, options: &ExportOptions) -> Result<String, SolveError> {
+        check_export(self, graph)?;
+        let [a, b, c, d, e, f] = options.affine;
+        let point = |v: usize| {
+            let (x, y) = (self.x[v], self.y[v]);
+            format!(
+                "[{},{},{}]",
+                number(a * x + b * y + c),
+                number(d * x + e * y + f),
+                number(graph.z[v])
+            )
+        };
+        let name = |v: usize| match &graph.names {
+            Some(names) => json_string(&names[v]),
+            None => "null".to_string(),
+        };
+
+        let mut ellipses = vec![String::new(); graph.num_vertices()];
+        for ellipse in &options.ellipses {
+            if let Some(properties) = ellipses.get_mut(ellipse.vertex) {
+                *properties = format!(
+                    r#","ellipse_semi_major":{},"ellipse_semi_minor":{},"ellipse_azimuth":{}"#,
+                    number(ellipse.semi_major),
+                    number(ellipse.semi_minor),
+                    number(ellipse.azimuth_deg)
+                );
+            }
+        }
+
+        let mut features = Vec::with_capacity(graph.num_vertices() + graph.num_edges());
+        for (v, ellipse) in ellipses.iter().enumerate() {
+            let displacement = (self.x[v] - graph.x[v]).hypot(self.y[v] - graph.y[v]);
+            features.push(format!(
+                r#"{{"type":"Feature","geometry":{{"type":"Point","coordinates":{}}},"properties":{{"kind":"station","index":{},"name":{},"fixed":{},"displacement":{}{}}}}}"#,
+                point(v),
+                v,
+                name(v),
+                graph.fixed[v],
+                number(displacement),
+                ellipse
+            ));
+        }
+
+        let residuals = self.residuals(graph);
+        let standardized = self.standardized_residuals(graph);
+        for (edge, (&(rx, ry), &sr)) in residuals.iter().zip(&standardized).enumerate() {
+            let (u, v) = (graph.from[edge], graph.to[edge]);
+            features.push(format!(
+                r#"{{"type":"Feature","geometry":{{"type":"LineString","coordinates":[{},{}]}},"properties":{{"kind":"shot","index":{},"from":{},"to":{},"residual_x":{},"residual_y":{},"residual":{},"standardized_residual":{},"blunder":{}}}}}"#,
+                point(u),
+                point(v),
+                edge,
+                u,
+                v,
+                number(rx),
+                number(ry),
+                number(rx.hypot(ry)),
+                number(sr),
+                sr > options.blunder_threshold
+            ));
+        }
+
+        let mut out = String::from(r#"{"type":"FeatureCollection","#);
+        let identity = options.affine == IDENTITY_AFFINE;
+        if let Some(system) = self.coordinate_system.filter(|_| identity) {
+            if let Some(urn) = system.ogc_urn() {
+                let _ = write!(
+                    out,
+                    r#""crs":{{"type":"name","properties":{{"name":{}}}}},"#,
+                    json_string(&urn)
+                );
+            }
+            let _ = write!(
+                out,
+                r#""coordinate_system":{{"projection":{},"units":{},"vertical_datum":{}}},"#,
+                json_string(&system.projection.to_string()),
+                json_string(system.units.name()),
+                json_string(system.vertical_datum.name())
+            );
+        }
+        out.push_str(r#""features":["#);
+        for (i, feature) in features.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            out.push_str(feature);
+        }
+        out.push_str("]}");
+        Ok(out)
+    }
+
+    /// Writes [`Solution::to_geojson`] to `path`.
+    pub fn write_geojson(
+        &self,
+        graph: &Graph,
+        path: impl AsRef<Path>,
+        options: &ExportOptions,
+    ) -> std::io::Result<()> {
+        let text = self.to_geojson(graph, options).map_err(invalid_input)?;
+        std::fs::write(path, text)
+    }
+}
+
+/// Writes the last solution of the graph behind `handle` as GeoJSON, without error
+/// ellipses: the handle holds none.
+///
+/// # Arguments
+///
+/// * `handle` - Context handle that has been solved with [`crate::graph_solve`].
+/// * `path` - NUL-terminated output path.
+/// * `affine` - Pointer to 6 affine coefficients (see [`ExportOptions::affine`]), or null for
+///   the identity.
+/// * `err_buf` - Buffer receiving a NUL-terminated error message on failure. May be null.
+/// * `err_cap` - Capacity of `err_buf` in bytes.
+#[unsafe(no_mangle)]
+pub extern "C" fn graph_write_geojson(
+    handle: *const GraphContext,
+    path: *const c_char,
+    affine: *const c_double,
+    err_buf: *mut c_char,
+    err_cap: usize,
+) -> c_int {
+    let result = std::panic::catch_unwind(|| {
+        // Safety: We assume the caller guarantees a valid (or null) handle and path.
+        let Some(ctx) = (unsafe { handle.as_ref() }) else {
+            write_message(err_buf, err_cap, "handle is null");
+            return COMPASS_ERR_INVALID_ARGUMENT;
+        };
+        if path.is_null() {
+            write_message(err_buf, err_cap, "path is null");
+            return COMPASS_ERR_INVALID_ARGUMENT;
+        }
+        let Some(solution) = ctx.solution() else {
+            write_message(err_buf, err_cap, "graph has not been solved");
+            return COMPASS_ERR_INVALID_ARGUMENT;
+        };
+        let mut options = ExportOptions::default();
+        if !affine.is_null() {
+            options
+                .affine
+                .copy_from_slice(unsafe { std::slice::from_raw_parts(affine, 6) });
+        }
+        let path = unsafe { CStr::from_ptr(path) }
+            .to_string_lossy()
+            .into_owned();
+        match solution.write_geojson(ctx.graph(), &path, &options) {
+            Ok(()) => COMPASS_OK,
+            Err(err) => {
+                write_message(err_buf, err_cap, &format!("{path}: {err}"));
+                COMPASS_ERR_IO
+            }
+        }
+    });
+
+    match result {
+        Ok(code) => code,
+        Err(_) => {
+            eprintln!("Panic caught in graph_write_geojson");
+            COMPASS_ERR_PANIC
+        }
+    }
+}
+
+/// JSON number, or `null` for NaN and infinities which JSON cannot represent.
+fn number(value: f64) -> String {
+    if value.is_finite() {
+        format!("{value}")
+    } else {
+        "null".to_string()
+    }
+}
+
+fn json_string(text: &str) -> String {
+    let mut out = String::with_capacity(text.len() + 2);
+    out.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crs::{CoordinateSystem, LengthUnit, Projection, VerticalDatum};
+    use serde_json::Value;
+    use std::ffi::CString;
+
+    /// A triangle around a fixed station at UTM scale, with a misclosure of a few centimetres.
+    fn solved_triangle() -> (Graph, Solution) {
+        let mut graph = Graph::default();
+        graph.add_vertex(500_000.123_456, 4_000_000.654_321, 100.0, true);
+        graph.add_vertex(500_010.0, 4_000_000.0, 101.0, false);
+        graph.add_vertex(500_010.0, 4_000_010.0, 102.0, false);
+        graph.add_edge(0, 1, 10.0, 0.0, 1.0, 1.0);
+        graph.add_edge(1, 2, 0.0, 10.0, 1.0, 1.0);
+        graph.add_edge(2, 0, -10.03, -10.02, -2.0, 1.0);
+        graph.names = Some(["ENT", "A\"1", "B\\2"].map(String::from).to_vec());
+        let solution = graph.solve(1000, 1e-12).unwrap();
+        (graph, solution)
+    }
+
+    fn features(json: &str) -> Vec<Value> {
+        let value: Value = serde_json::from_str(json).unwrap();
+        assert_eq!(value["type"], "FeatureCollection");
+        value["features"].as_array().unwrap().clone()
+    }
+
+    #[test]
+    fn writes_stations_and_shots_with_their_properties() {
+        let (graph, solution) = solved_triangle();
+        let features = features(
+            &solution
+                .to_geojson(&graph, &ExportOptions::default())
+                .unwrap(),
+        );
+        assert_eq!(features.len(), 6);
+
+        let station = &features[1];
+        assert_eq!(station["geometry"]["type"], "Point");
+        assert_eq!(station["properties"]["name"], "A\"1");
+        assert_eq!(station["properties"]["fixed"], false);
+        let moved = (solution.x[1] - graph.x[1]).hypot(solution.y[1] - graph.y[1]);
+        assert_eq!(station["properties"]["displacement"], moved);
+        assert_eq!(features[0]["properties"]["fixed"], true);
+
+        let residuals = solution.residuals(&graph);
+        let shot = &features[5];
+        assert_eq!(shot["geometry"]["type"], "LineString");
+        assert_eq!(shot["properties"]["from"], 2);
+        assert_eq!(shot["properties"]["to"], 0);
+        assert_eq!(shot["properties"]["residual_x"], residuals[2].0);
+        assert_eq!(shot["properties"]["residual_y"], residuals[2].1);
+        assert_eq!(shot["properties"]["blunder"], false);
+    }
+
+    #[test]
+    fn writes_the_error_ellipses_given() {
+        let (graph, solution) = solved_triangle();
+        let options = ExportOptions {
+            ellipses: vec![ErrorEllipse {
+                vertex: 2,
+                semi_major: 0.025,
+                semi_minor: 0.0125,
+                azimuth_deg: 123.5,
+            }],
+            ..ExportOptions::default()
+        };
+        let features = features(&solution.to_geojson(&graph, &options).unwrap());
+        let properties = &features[2]["properties"];
+        assert_eq!(properties["ellipse_semi_major"], 0.025);
+        assert_eq!(properties["ellipse_semi_minor"], 0.0125);
+        assert_eq!(properties["ellipse_azimuth"], 123.5);
+        let properties = features[1]["properties"].as_object().unwrap();
+        assert!(properties.keys().all(|key| !key.starts_with("ellipse")));
+
+        // An ellipse of no station is skipped.
+        let mut stray = options.clone();
+        stray.ellipses[0].vertex = 3;
+        let json = solution.to_geojson(&graph, &stray).unwrap();
+        assert!(!json.contains("ellipse"));
+    }
+
+    #[test]
+    fn rejects_a_solution_of_another_graph() {
+        let (mut graph, solution) = solved_triangle();
+        graph.add_vertex(0.0, 0.0, 0.0, false);
+        let err = solution
+            .to_geojson(&graph, &ExportOptions::default())
+            .unwrap_err();
+        assert_eq!(err.code, COMPASS_ERR_INVALID_ARGUMENT);
+        assert_eq!(err.message, "solution has 3 / 3 coordinates for 4 vertices");
+        graph.x.pop();
+        graph.fixed.pop();
+        let err = solution
+            .to_geojson(&graph, &ExportOptions::default())
+            .unwrap_err();
+        assert_eq!(err.message, "graph has 4 y coordinates for 3 vertices");
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("network.geojson");
+        let err = solution
+            .write_geojson(&graph, &path, &ExportOptions::default())
+            .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn keeps_millimetres_at_utm_scale() {
+        let (graph, solution) = solved_triangle();
+        let features = features(
+            &solution
+                .to_geojson(&graph, &ExportOptions::default())
+                .unwrap(),
+        );
+        let coordinates = &features[0]["geometry"]["coordinates"];
+        assert_eq!(coordinates[0].as_f64(), Some(500_000.123_456));
+        assert_eq!(coordinates[1].as_f64(), Some(4_000_000.654_321));
+        assert_eq!(coordinates[2].as_f64(), Some(100.0));
+        for (v, feature) in features.iter().enumerate().take(3).skip(1) {
+            let point = &feature["geometry"]["coordinates"];
+            assert_eq!(point[0].as_f64(), Some(solution.x[v]));
+            assert_eq!(point[1].as_f64(), Some(solution.y[v]));
+        }
+    }
+
+    #[test]
+    fn applies_the_affine_transform_and_flags_blunders() {
+        let (graph, solution) = solved_triangle();
+        let options = ExportOptions {
+            affine: [0.0, -1.0, 10.0, 1.0, 0.0, -20.0],
+            blunder_threshold: 0.0,
+            ..ExportOptions::default()
+        };
+        let features = features(&solution.to_geojson(&graph, &options).unwrap());
+        let point = &features[2]["geometry"]["coordinates"];
+        assert_eq!(point[0].as_f64(), Some(-solution.y[2] + 10.0));
+        assert_eq!(point[1].as_f64(), Some(solution.x[2] - 20.0));
+        assert_eq!(point[2].as_f64(), Some(102.0));
+        assert!((3..6).all(|f| features[f]["properties"]["blunder"] == true));
+    }
+
+    #[test]
+    fn notes_the_coordinate_system_without_a_transform() {
+        let (mut graph, _) = solved_triangle();
+        let value = |graph: &Graph, options: &ExportOptions| {
+            let solution = graph.solve(1000, 1e-12).unwrap();
+            serde_json::from_str::<Value>(&solution.to_geojson(graph, options).unwrap()).unwrap()
+        };
+        let undeclared = value(&graph, &ExportOptions::default());
+        assert_eq!(undeclared.get("crs"), None);
+        assert_eq!(undeclared.get("coordinate_system"), None);
+
+        graph.set_coordinate_system(CoordinateSystem {
+            projection: Projection::utm_wgs84(13, true).unwrap(),
+            units: LengthUnit::Meters,
+            vertical_datum: VerticalDatum::MeanSeaLevel,
+        });
+        let utm = value(&graph, &ExportOptions::default());
+        assert_eq!(
+            utm["crs"]["properties"]["name"],
+            "urn:ogc:def:crs:EPSG::32613"
+        );
+        let system = &utm["coordinate_system"];
+        assert_eq!(system["projection"], "EPSG:32613");
+        assert_eq!(system["units"], "meters");
+        assert_eq!(system["vertical_datum"], "mean sea level");
+        assert_eq!(utm["features"].as_array().unwrap().len(), 6);
+
+        let options = ExportOptions {
+            affine: [1.0, 0.0, -500_000.0, 0.0, 1.0, -4_000_000.0],
+            ..ExportOptions::default()
+        };
+        assert_eq!(value(&graph, &options).get("crs"), None);
+    }
+
+    #[test]
+    fn writes_null_for_non_finite_numbers_and_unnamed_stations() {
+        let (mut graph, solution) = solved_triangle();
+        graph.names = None;
+        graph.z[1] = f64::NAN;
+        let features = features(
+            &solution
+                .to_geojson(&graph, &ExportOptions::default())
+                .unwrap(),
+        );
+        assert_eq!(features[1]["geometry"]["coordinates"][2], Value::Null);
+        assert_eq!(features[1]["properties"]["name"], Value::Null);
+        assert_eq!(json_string("a\u{1}\tb"), r#""a\u0001\tb""#);
+    }
+
+    #[test]
+    fn ffi_writes_the_solved_graph_or_reports_why_not() {
+        let (graph, _) = solved_triangle();
+        let handle = GraphContext::into_raw(graph);
+        let dir = tempfile::tempdir().unwrap();
+        let path = CString::new(dir.path().join("net.geojson").to_str().unwrap()).unwrap();
+        let mut buf = [0 as c_char; 128];
+        let write = |affine: *const c_double, buf: &mut [c_char]| {
+            graph_write_geojson(handle, path.as_ptr(), affine, buf.as_mut_ptr(), buf.len())
+        };
+
+        assert_eq!(
+            write(std::ptr::null(), &mut buf),
+            COMPASS_ERR_INVALID_ARGUMENT
+        );
+        // Safety: The library NUL-terminates the message.
+        let message = unsafe { CStr::from_ptr(buf.as_ptr()) }.to_string_lossy();
+        assert_eq!(message, "graph has not been solved");
+
+        let options = crate::SolveOptions {
+            tolerance: 1e-12,
+            ..Default::default()
+        };
+        assert_eq!(
+            crate::graph_solve(handle, &options, std::ptr::null_mut()),
+            COMPASS_OK
+        );
+        let affine = [1.0, 0.0, -500_000.0, 0.0, 1.0, -4_000_000.0];
+        assert_eq!(write(affine.as_ptr(), &mut buf), COMPASS_OK);
+        let text = std::fs::read_to_string(dir.path().join("net.geojson")).unwrap();
+        let x = features(&text)[0]["geometry"]["coordinates"][0]
+            .as_f64()
+            .unwrap();
+        assert!((x - 0.123_456).abs() < 1e-9);
+        crate::graph_free(handle);
+    }
+}
@@ -1,313 +1,8388 @@
-use nalgebra::DVector;
-use nalgebra_sparse::{CooMatrix, CsrMatrix};
-use std::ffi::{c_double, c_int};
-use std::slice;
-
-/// Solves a graph Least Squares adjustment problem for 2D coordinates (X, Y).
-///
-/// This function is designed to be called from Java via FFI (Project Panama).
-/// It takes a set of vertices (some fixed, some free) and edges (constraints between vertices).
-/// It constructs a system of linear equations `Ax = b` and solves it using the Conjugate Gradient (CG) method.
-///
-/// # Arguments
-///
-/// * `num_vertices` - Total number of vertices in the graph.
-/// * `x` - Pointer to the array of X coordinates. Input: Initial guess. Output: Optimized X coordinates.
-/// * `y` - Pointer to the array of Y coordinates. Input: Initial guess. Output: Optimized Y coordinates.
-/// * `fixed` - Pointer to the array of fixed flags. 1 = Fixed (anchor), 0 = Free (to be adjusted).
-/// * `num_edges` - Total number of edges (constraints).
-/// * `from` - Pointer to the array of start vertex indices for each edge.
-/// * `to` - Pointer to the array of end vertex indices for each edge.
-/// * `observed_dx` - Pointer to the array of observed X differences (dx) for each edge.
-/// * `observed_dy` - Pointer to the array of observed Y differences (dy) for each edge.
-/// * `weight` - Pointer to the array of weights for each edge (typically 1/length or 1/variance).
-/// * `iterations` - Maximum number of iterations for the Conjugate Gradient solver.
-/// * `tolerance` - Residual tolerance for convergence of the CG solver.
-#[unsafe(no_mangle)]
-pub extern "C" fn solve_graph_least_squares(
-    num_vertices: c_int,
-    x: *mut c_double,    // In/Out: Initial guess / Result
-    y: *mut c_double,    // In/Out: Initial guess / Result
-    fixed: *const c_int, // 0 = Free, 1 = Fixed
-    num_edges: c_int,
-    from: *const c_int,
-    to: *const c_int,
-    observed_dx: *const c_double,
-    observed_dy: *const c_double,
-    weight: *const c_double,
-    iterations: c_int,
-    tolerance: c_double,
-) -> c_int {
-    let result = std::panic::catch_unwind(|| {
-        if iterations == -1 {
-            panic!("Intentional test panic triggered!");
-        }
-        let n_verts = num_vertices as usize;
-        let n_edges = num_edges as usize;
-
-        // Safety: Creating Rust slices from raw C pointers.
-        // We assume the caller (Java) guarantees valid non-null pointers and correct lengths.
-        let x_slice = unsafe { slice::from_raw_parts_mut(x, n_verts) };
-        let y_slice = unsafe { slice::from_raw_parts_mut(y, n_verts) };
-        let fixed_slice = unsafe { slice::from_raw_parts(fixed, n_verts) };
-
-        let from_slice = unsafe { slice::from_raw_parts(from, n_edges) };
-        let to_slice = unsafe { slice::from_raw_parts(to, n_edges) };
-        let dx_slice = unsafe { slice::from_raw_parts(observed_dx, n_edges) };
-        let dy_slice = unsafe { slice::from_raw_parts(observed_dy, n_edges) };
-        let w_slice = unsafe { slice::from_raw_parts(weight, n_edges) };
-
-        // 1. Mapping: Original Index -> Reduced Index
-        // Fixed vertices do not participate in the matrix as variables; they act as boundary conditions.
-        // We create a mapping where `mapping[original_index] = Some(reduced_index)` for free vertices,
-        // and `None` for fixed vertices.
-        let mut mapping = vec![None; n_verts];
-        let mut active_count = 0;
-
-        for i in 0..n_verts {
-            if fixed_slice[i] == 0 {
-                mapping[i] = Some(active_count);
-                active_count += 1;
-            }
-        }
-
-        if active_count == 0 {
-            return 0; // No free vertices to adjust, nothing to solve.
-        }
-
-        // 2. Assemble Matrix (COO format) and RHS vectors
-        // The system to solve is (A^T W A) x = A^T W l, which reduces to a symmetric positive definite system.
-        // Here we construct the Normal Equations directly.
-        let mut coo_ax = CooMatrix::new(active_count, active_count);
-
-        // Ax and Ay matrices are identical in structure and values (dependent only on weights),
-        // so we only need to construct one matrix `coo_ax`.
-
-        let mut bx = DVector::zeros(active_count);
-        let mut by = DVector::zeros(active_count);
-
-        // Initial guess vectors for the solver (mapped from input)
-        let mut x0_solver = DVector::zeros(active_count);
-        let mut y0_solver = DVector::zeros(active_count);
-
-        // Fill initial guess from input slices
-        for i in 0..n_verts {
-            if let Some(idx) = mapping[i] {
-                x0_solver[idx] = x_slice[i];
-                y0_solver[idx] = y_slice[i];
-            }
-        }
-
-        // Iterate over all edges to build the matrix and RHS vectors
-        for e in 0..n_edges {
-            let u = from_slice[e] as usize;
-            let v = to_slice[e] as usize;
-            let w = w_slice[e]; // Weight of the observation
-            let dx = dx_slice[e];
-            let dy = dy_slice[e];
-
-            // An edge between u and v provides an observation:
-            // x_v - x_u = dx
-            // y_v - y_u = dy
-            //
-            // In the normal equations (Least Squares), this contributes:
-            // A[u, u] += w, A[v, v] += w
-            // A[u, v] -= w, A[v, u] -= w
-            // RHS_u -= w * dx
-            // RHS_v += w * dx
-
-            let u_map = mapping[u];
-            let v_map = mapping[v];
-
-            match (u_map, v_map) {
-                (Some(ui), Some(vi)) => {
-                    // Case 1: Both vertices are free.
-                    // Add terms to the matrix for both u and v.
-                    coo_ax.push(ui, ui, w);
-                    coo_ax.push(vi, vi, w);
-                    coo_ax.push(ui, vi, -w);
-                    coo_ax.push(vi, ui, -w);
-
-                    // Add terms to RHS vectors
-                    bx[ui] -= w * dx;
-                    bx[vi] += w * dx;
-
-                    by[ui] -= w * dy;
-                    by[vi] += w * dy;
-                }
-                (Some(ui), None) => {
-                    // Case 2: u is free, v is fixed.
-                    // Since v is fixed, x_v and y_v are constants.
-                    // Terms involving x_v move to the RHS.
-                    // A[u, u] += w
-                    // A[u, v] * x_v (where A[u,v] is -w) becomes -(-w * x_v) = +w * x_v on the RHS.
-
-                    coo_ax.push(ui, ui, w);
-
-                    // RHS modifications from edge constraint (dx/dy)
-                    bx[ui] -= w * dx;
-                    by[ui] -= w * dy;
-
-                    // RHS modifications from the fixed neighbor v
-                    let xv = x_slice[v];
-                    let yv = y_slice[v];
-
-                    bx[ui] += w * xv;
-                    by[ui] += w * yv;
-                }
-                (None, Some(vi)) => {
-                    // Case 3: u is fixed, v is free.
-                    // Similar to Case 2, but for v.
-                    // A[v, v] += w
-                    // A[v, u] * x_u (where A[v,u] is -w) becomes -(-w * x_u) = +w * x_u on the RHS.
-
-                    coo_ax.push(vi, vi, w);
-
-                    // RHS modifications from edge constraint
-                    bx[vi] += w * dx;
-                    by[vi] += w * dy;
-
-                    // RHS modifications from the fixed neighbor u
-                    let xu = x_slice[u];
-                    let yu = y_slice[u];
-
-                    bx[vi] += w * xu;
-                    by[vi] += w * yu;
-                }
-                (None, None) => {
-                    // Case 4: Both fixed.
-                    // This is a check constraint between two anchors. It does not affect the system
-                    // of equations for the free variables, so we ignore it.
-                }
-            }
-        }
-
-        // Convert COO to CSR format for efficient multiplication in the solver
-        let csr_a = CsrMatrix::from(&coo_ax);
-
-        // 3. Solve (Conjugate Gradient)
-        // Since X and Y coordinates are independent in this formulation (no rotation/scale parameters),
-        // key optimization: we can solve for X and Y in parallel.
-        let (res_x, res_y) = std::thread::scope(|s| {
-            let handle_x = s.spawn(|| solve_cg(&csr_a, &bx, &x0_solver, iterations, tolerance));
-            let handle_y = s.spawn(|| solve_cg(&csr_a, &by, &y0_solver, iterations, tolerance));
-
-            let res_x = handle_x.join().unwrap();
-            let res_y = handle_y.join().unwrap();
-            (res_x, res_y)
-        });
-
-        // 4. Write back results to the original arrays (Java memory)
-        for i in 0..n_verts {
-            if let Some(idx) = mapping[i] {
-                x_slice[i] = res_x[idx];
-                y_slice[i] = res_y[idx];
-            }
-        }
-        0
-    });
-
-    match result {
-        Ok(code) => code,
-        Err(_) => {
-            eprintln!("Panic caught in solve_graph_least_squares");
-            -1
-        }
-    }
-}
-
-/// Solves linear system Ax = b using the Conjugate Gradient method.
-///
-/// Use this for Symmetric Positive Definite matrices (which the Normal Equations matrix always is).
-///
-/// # Arguments
-///
-/// * `a` - The matrix A (CSR format).
-/// * `b` - The RHS vector b.
-/// * `x0` - Initial guess for x.
-/// * `max_iter` - Maximum number of iterations.
-/// * `tol` - Tolerance for convergence (based on residual norm).
-///
-/// # Returns
-///
-/// * `DVector<f64>` - The solution vector x.
-fn solve_cg(
-    a: &CsrMatrix<f64>,
-    b: &DVector<f64>,
-    x0: &DVector<f64>,
-    max_iter: c_int,
-    tol: f64,
-) -> DVector<f64> {
-    let mut x = x0.clone();
-
-    // Initial residual r = b - A * x
-    // We can allow one allocation here for startup
-    let mut r = b - a * &x;
-
-    let mut p = r.clone();
-
-    // Pre-allocate workspace for A * p
-    let mut ap = DVector::zeros(x.len());
-
-    let mut rho_old = r.dot(&r);
-
-    for _ in 0..max_iter {
-        // Check convergence
-        if rho_old.sqrt() < tol {
-            break;
-        }
-
-        // ap = A * p
-        // Optimized to avoid allocation
-        spmv_csr(a, &p, &mut ap);
-
-        let p_dot_ap = p.dot(&ap);
-        if p_dot_ap.abs() < 1e-15 {
-            break;
-        } // Safety against division by zero
-
-        let alpha = rho_old / p_dot_ap; // Step size alpha
-
-        // x += alpha * p
-        x.axpy(alpha, &p, 1.0);
-
-        // r -= alpha * ap
-        r.axpy(-alpha, &ap, 1.0);
-
-        let rho_new = r.dot(&r);
-        let beta = rho_new / rho_old;
-
-        // p = r + beta * p
-        // => p = beta * p + r (in-place)
-        p.scale_mut(beta);
-        p += &r;
-
-        rho_old = rho_new;
-    }
-    x
-}
-
-/// Helper for Sparse Matrix - Vector multiplication: y = A * x
-/// avoiding per-call allocation
-fn spmv_csr(a: &CsrMatrix<f64>, x: &DVector<f64>, y: &mut DVector<f64>) {
-    // Access raw CSR structures
-    let row_offsets = a.row_offsets();
-    let col_indices = a.col_indices();
-    let values = a.values();
-
-    // y must handle the result, so we overwrite it
-    // Iterate over rows
-    for (row_idx, row_range) in row_offsets.windows(2).enumerate() {
-        let start = row_range[0];
-        let end = row_range[1];
-        let mut sum = 0.0;
-
-        for i in start..end {
-            let col_idx = col_indices[i];
-            let val = values[i];
-            // x is a DVector, indexing works
-            sum += val * x[col_idx];
-        }
-        y[row_idx] = sum;
-    }
-}
+use crate::crs::CoordinateSystem;
+use crate::double_double::Accumulation;
+use crate::limits::Limits;
+use crate::warnings::SolveWarning;
+use nalgebra::DVector;
+use nalgebra_sparse::{CooMatrix, CsrMatrix};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::ffi::{CStr, c_char, c_double, c_int, c_void};
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+use std::slice;
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Instant;
+
+/// Enters a `tracing` span for the rest of the enclosing block with the `tracing` feature,
+/// see [`trace`]. Expands to nothing otherwise, the fields not even being evaluated.
+macro_rules! profile_span {
+    ($($span:tt)*) => {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!($($span)*).entered();
+    };
+}
+
+mod amg;
+pub mod anchor_move;
+pub mod capture;
+pub mod cave_stats;
+pub mod centroid;
+pub mod classes;
+#[cfg(feature = "compass_io")]
+pub mod compass_io;
+pub mod constraints;
+pub mod control;
+pub mod corrections;
+pub mod correlation;
+pub mod crs;
+#[cfg(feature = "io-csv")]
+pub mod csv_io;
+pub mod datum;
+pub mod design;
+pub mod diff;
+mod double_double;
+pub mod duplicates;
+#[cfg(feature = "io-dxf")]
+pub mod dxf_io;
+#[cfg(any(test, feature = "fixtures"))]
+pub mod fixtures;
+#[cfg(feature = "arbitrary")]
+pub mod fuzzing;
+#[cfg(feature = "io-geojson")]
+pub mod geojson_io;
+pub mod heatmap;
+pub mod hierarchical;
+pub mod initial_guess;
+#[cfg(feature = "jni")]
+mod java;
+#[cfg(feature = "io-json")]
+pub mod json_io;
+pub mod limits;
+pub mod locks;
+#[cfg(feature = "uniffi")]
+mod mobile;
+pub mod named_graph;
+#[cfg(feature = "napi")]
+#[cfg_attr(test, allow(dead_code))] // napi-derive skips the module registration in test builds.
+mod node;
+pub mod paths;
+#[cfg(feature = "petgraph")]
+pub mod petgraph_io;
+pub mod positions;
+#[cfg(feature = "io-protobuf")]
+pub mod protobuf_io;
+#[cfg(feature = "python")]
+mod python;
+pub mod reliability;
+pub mod report;
+pub mod residual_sync;
+pub mod scale;
+mod schwarz;
+pub mod screening;
+#[cfg(feature = "serde")]
+pub mod serde_io;
+pub mod similarity;
+#[cfg(feature = "io-snapshot")]
+pub mod snapshot_io;
+pub mod sparse;
+pub mod sparsity;
+pub mod spatial;
+mod spmv;
+#[cfg(feature = "io-sqlite")]
+pub mod sqlite_io;
+#[cfg(feature = "io-survex")]
+pub mod survex_io;
+#[cfg(feature = "render-svg")]
+pub mod svg_render;
+#[cfg(any(test, feature = "test-support"))]
+pub mod test_support;
+#[cfg(feature = "tracing")]
+pub mod trace;
+pub mod transforms;
+pub mod two_stage;
+pub mod variance;
+pub mod warnings;
+#[cfg(feature = "wasm")]
+mod wasm;
+pub mod weight_update;
+pub mod weights;
+
+#[cfg(feature = "uniffi")]
+uniffi::setup_scaffolding!("compass_loop_closure");
+
+/// Success.
+pub const COMPASS_OK: c_int = 0;
+/// A panic was caught at the FFI boundary.
+pub const COMPASS_ERR_PANIC: c_int = -1;
+/// A required pointer was null or an argument was out of range.
+pub const COMPASS_ERR_INVALID_ARGUMENT: c_int = -2;
+/// Reading or writing a file failed.
+pub const COMPASS_ERR_IO: c_int = -3;
+/// A caller-provided output buffer is too small for the result.
+pub const COMPASS_ERR_BUFFER_TOO_SMALL: c_int = -4;
+/// The solve was cancelled through [`SolveOptions::cancel`]. The coordinates hold the last
+/// iterate, see [`SolveStats::result_quality`].
+pub const COMPASS_ERR_CANCELLED: c_int = -5;
+/// An internal consistency check failed, see [`SolveOptions::verify_fixed`]. This is a bug in
+/// the library; the error message names the offending vertex.
+pub const COMPASS_ERR_INTERNAL: c_int = -6;
+/// The buffers for a problem of the given size could not be allocated.
+pub const COMPASS_ERR_OUT_OF_MEMORY: c_int = -7;
+/// A free vertex has no usable edge, see [`SolveOptions::keep_isolated`]. The error message
+/// lists the isolated vertices.
+pub const COMPASS_ERR_ISOLATED_VERTEX: c_int = -8;
+/// No edge is enabled, with [`SolveOptions::error_on_trivial`].
+pub const COMPASS_ERR_NO_EDGES: c_int = -9;
+/// No vertex is free, with [`SolveOptions::error_on_trivial`].
+pub const COMPASS_ERR_NO_FREE_VERTICES: c_int = -10;
+/// A fixed flag is neither 0 nor 1. The error message names the vertex and the value.
+pub const COMPASS_ERR_INVALID_FIXED_FLAG: c_int = -11;
+/// The preconditioner returned a non-finite value, see [`sparse::Preconditioner`].
+pub const COMPASS_ERR_PRECONDITIONER: c_int = -12;
+/// The graph has more vertices or edges than this build supports, see [`limits`].
+pub const COMPASS_ERR_TOO_LARGE: c_int = -13;
+/// The control points of a [`similarity`] fit coincide, leaving its rotation undefined.
+pub const COMPASS_ERR_DEGENERATE_CONTROL: c_int = -14;
+/// Warning, not a failure: the coordinates were written, but free vertices that landed
+/// outside the bounding box were clamped onto it, see [`SolveOptions::clamp_to_bounds`].
+/// Warnings are positive.
+pub const COMPASS_WARN_CLAMPED: c_int = 1;
+
+/// [`SolveStats::result_quality`]: the coordinates were not written, as for invalid arguments.
+pub const COMPASS_RESULT_UNTOUCHED: c_int = 0;
+/// [`SolveStats::result_quality`]: the linear solver reached the tolerance.
+pub const COMPASS_RESULT_EXACT: c_int = 1;
+/// [`SolveStats::result_quality`]: the coordinates hold the best iterate of a solve that
+/// stopped short of the tolerance, out of iterations or time, or cancelled. They are
+/// consistent (passive vertices follow their parents) but not the least squares solution.
+pub const COMPASS_RESULT_APPROXIMATE: c_int = 2;
+
+/// [`SolveStats::stop_reason`]: no iterative solve ran, e.g. without free vertices.
+pub const COMPASS_STOP_NONE: c_int = 0;
+/// [`SolveStats::stop_reason`]: the residual norm reached the tolerance.
+pub const COMPASS_STOP_CONVERGED: c_int = 1;
+/// [`SolveStats::stop_reason`]: the iteration limit came first.
+pub const COMPASS_STOP_ITERATIONS: c_int = 2;
+/// [`SolveStats::stop_reason`]: Conjugate Gradient broke down, see
+/// [`SolveOptions::breakdown_tolerance`] and [`SolveStats::breakdown_iteration`].
+pub const COMPASS_STOP_BREAKDOWN: c_int = 3;
+/// [`SolveStats::stop_reason`]: the solve was cancelled.
+pub const COMPASS_STOP_CANCELLED: c_int = 4;
+/// [`SolveStats::stop_reason`]: the [`SolveOptions::time_budget`] ran out first.
+pub const COMPASS_STOP_TIME_BUDGET: c_int = 5;
+/// [`SolveStats::stop_reason`]: the initial guess already met the tolerance on both axes, so
+/// no iterative solve ran. See [`SolveStats::skipped_axes`].
+pub const COMPASS_STOP_ALREADY_CONVERGED: c_int = 6;
+
+/// [`SolveStats::trivial`] flag: no edge was enabled.
+pub const COMPASS_TRIVIAL_NO_EDGES: c_int = 1;
+/// [`SolveStats::trivial`] flag: no vertex was free, fixed, frozen and held ones aside.
+pub const COMPASS_TRIVIAL_NO_FREE_VERTICES: c_int = 2;
+
+/// Default [`SolveOptions::breakdown_tolerance`].
+pub const DEFAULT_BREAKDOWN_TOLERANCE: f64 = 1e-15;
+/// Default [`SolveOptions::true_residual_interval`].
+pub const DEFAULT_TRUE_RESIDUAL_INTERVAL: usize = 50;
+/// Default [`SolveOptions::guess_margin`].
+pub const DEFAULT_GUESS_MARGIN: f64 = 10.0;
+
+/// Optional inputs and tuning knobs for [`solve_graph_least_squares_ex`].
+///
+/// Pointer fields are optional: a null pointer means the feature is not used. They are skipped
+/// by serde and deserialize as null.
+///
+/// Fields are only ever appended to the struct. Callers zero it, set `struct_size` and then the
+/// fields they know of; the library reads that many bytes and leaves the later fields at their
+/// defaults, so a caller built against an older release keeps working.
+#[repr(C)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct SolveOptions {
+    /// `sizeof(SolveOptions)` as the caller was compiled against it.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub struct_size: usize,
+    /// Maximum number of iterations for the Conjugate Gradient solver.
+    pub iterations: c_int,
+    /// Residual tolerance for convergence of the CG solver.
+    pub tolerance: c_double,
+    /// Per-vertex passive flags (length `num_vertices`). 1 = Passive (splay/wall endpoint), 0 = Normal.
+    ///
+    /// Passive vertices are excluded from the system entirely. After the solve each one is
+    /// repositioned rigidly as `parent_adjusted + observed offset`, where the parent is the
+    /// non-passive endpoint of the first edge linking it to the network. A passive vertex
+    /// reached only through other passive vertices, e.g. along a chain of splays, follows
+    /// the first of them placed, breadth-first from the network.
+    #[cfg_attr(feature = "serde", serde(skip, default = "std::ptr::null"))]
+    pub passive: *const c_int,
+    /// Cancellation flag, polled once per CG iteration. Another thread sets it to a non-zero
+    /// value to stop the solve, which then writes back its last iterate and returns
+    /// [`COMPASS_ERR_CANCELLED`].
+    #[cfg_attr(feature = "serde", serde(skip, default = "std::ptr::null"))]
+    pub cancel: *const AtomicI32,
+    /// Per-vertex frozen flags (length `num_vertices`). 1 = Frozen, 0 = Normal.
+    ///
+    /// Frozen vertices belong to an already published region: like fixed vertices they keep
+    /// their current coordinates and act as boundary conditions, but the misclosure absorbed
+    /// by the edges linking them to adjusted vertices is reported in [`SolveStats`].
+    #[cfg_attr(feature = "serde", serde(skip, default = "std::ptr::null"))]
+    pub frozen: *const c_int,
+    /// Per-edge enabled flags (length `num_edges`). 1 = Enabled, 0 = Disabled.
+    ///
+    /// Disabled edges are left out of the adjustment and of the statistics, as if they were
+    /// not in the arrays, so shots can be toggled without rebuilding them.
+    #[cfg_attr(feature = "serde", serde(skip, default = "std::ptr::null"))]
+    pub edge_enabled: *const c_int,
+    /// Iterative solver: 0 = Conjugate Gradient, 1 = Chebyshev semi-iteration. See
+    /// [`LinearSolver`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub solver: c_int,
+    /// Preconditioner: 0 = None, 1 = Jacobi scaling, 2 = Algebraic multigrid, 3 = Additive
+    /// Schwarz, the latter two with their default sizes. See [`Preconditioner`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub preconditioner: c_int,
+    /// Per-vertex centroid weights (length `num_vertices`), 0 = not in the set.
+    ///
+    /// Holds the weighted centroid of the set at its value before the adjustment, so that a
+    /// network tied to its anchors only by weak edges does not drift between runs. Passive
+    /// vertices are left out of the set. See [`centroid`].
+    #[cfg_attr(feature = "serde", serde(skip, default = "std::ptr::null"))]
+    pub centroid_weights: *const c_double,
+    /// Weight of the centroid constraint against the edges: `INFINITY` holds the centroid
+    /// exactly, 0 only reports [`SolveStats::centroid_shift_x`] / `centroid_shift_y`.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub centroid_stiffness: c_double,
+    /// Per-edge class (length `num_edges`), an index into [`SolveOptions::class_multipliers`],
+    /// e.g. 0 = underground shot, 1 = surface GPS leg.
+    ///
+    /// The weight of each edge is multiplied by the multiplier of its class, so that kinds of
+    /// data can be weighted against each other. See [`classes`].
+    #[cfg_attr(feature = "serde", serde(skip, default = "std::ptr::null"))]
+    pub edge_class: *const c_int,
+    /// Weight multiplier of each class (length [`SolveOptions::num_classes`]), positive.
+    #[cfg_attr(feature = "serde", serde(skip, default = "std::ptr::null"))]
+    pub class_multipliers: *const c_double,
+    /// Number of classes in [`SolveOptions::class_multipliers`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub num_classes: c_int,
+    /// 1 = check in release builds too that the coordinates of the fixed vertices come back
+    /// bit for bit, as debug builds always do. A violation restores them and returns
+    /// [`COMPASS_ERR_INTERNAL`] with the vertex in [`SolveOptions::error_message`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub verify_fixed: c_int,
+    /// Caller buffer of [`SolveOptions::error_capacity`] bytes receiving a NUL-terminated
+    /// description of a [`COMPASS_ERR_INTERNAL`] or [`COMPASS_ERR_ISOLATED_VERTEX`] failure,
+    /// or of a free vertex whose edges all have zero weight, which fails with
+    /// [`COMPASS_ERR_INVALID_ARGUMENT`]. It also lists the vertices clamped with
+    /// [`COMPASS_WARN_CLAMPED`].
+    #[cfg_attr(feature = "serde", serde(skip, default = "std::ptr::null_mut"))]
+    pub error_message: *mut c_char,
+    /// Size of [`SolveOptions::error_message`] in bytes.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub error_capacity: c_int,
+    /// Conjugate Gradient stops with [`COMPASS_STOP_BREAKDOWN`] once `pᵀAp` falls to this
+    /// fraction of `|p| |Ap|`, the search direction `p` having become (nearly) conjugate to
+    /// itself. Being relative, the test does not depend on the scale of the coordinates or
+    /// the weights. 0 = [`DEFAULT_BREAKDOWN_TOLERANCE`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub breakdown_tolerance: c_double,
+    /// Conjugate Gradient iterations between two recomputations of the true residual
+    /// `b - Ax`, which the recursively updated residual drifts away from over long runs. The
+    /// solve stops when the true residual is below the tolerance; it is also checked when the
+    /// recursive one falls below it, and the iteration restarts from the true residual if
+    /// they disagree. 0 = [`DEFAULT_TRUE_RESIDUAL_INTERVAL`], negative = only those checks.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub true_residual_interval: c_int,
+    /// The initial guess of a free vertex is discarded as garbage when it is not finite or
+    /// lies farther from the bounding box of the fixed vertices than this multiple of the
+    /// extent of the network: the size of the box plus the summed lengths of the enabled
+    /// edges. Such vertices are re-seeded from their neighbors along the edges, see
+    /// [`SolveStats::reseeded_vertices`]. 0 = [`DEFAULT_GUESS_MARGIN`], negative = only
+    /// non-finite guesses are discarded.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub guess_margin: c_double,
+    /// A free vertex without a usable edge, one that is enabled and leads to another vertex
+    /// that is not passive, would leave an empty row in the normal equations. Such isolated
+    /// vertices are listed in [`SolveOptions::error_message`] with
+    /// [`COMPASS_ERR_ISOLATED_VERTEX`]; 1 = leave them at their initial coordinates instead,
+    /// counted in [`SolveStats::isolated_vertices`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub keep_isolated: c_int,
+    /// Measure of the residual compared with [`SolveOptions::tolerance`]: 0 = absolute
+    /// `|AᵀW r|` as before, 1 = relative to the initial residual, 2 = relative to `|AᵀW l|`.
+    /// See [`ConvergenceCriterion`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub convergence_criterion: c_int,
+    /// Seconds the iterative solve may run, 0 = no limit. Once they are spent it stops as if
+    /// out of iterations, with [`COMPASS_STOP_TIME_BUDGET`]. Negative or NaN budgets are
+    /// invalid.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub time_budget: c_double,
+    /// Named bundle of the tuning fields, 0 = none, the fields as set. 1 = fast preview, 2 =
+    /// standard and 3 = strict replace `iterations`, `tolerance`, `solver`,
+    /// `preconditioner`, `convergence_criterion`, `time_budget`, `breakdown_tolerance`,
+    /// `true_residual_interval` and `verify_fixed` by the values of the [`Preset`]; the
+    /// values used are reported in [`SolveStats`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub preset: c_int,
+    /// Residual tolerance of the X axis alone, 0 = [`SolveOptions::tolerance`]. The axes are
+    /// solved independently, so a noisier axis can stop earlier without changing the other.
+    /// It overrides a [`SolveOptions::preset`] too. Negative or NaN values are invalid, the
+    /// axis being named in [`SolveOptions::error_message`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub tolerance_x: c_double,
+    /// Same as [`SolveOptions::tolerance_x`] for the Y axis.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub tolerance_y: c_double,
+    /// Iteration limit of the X axis alone, 0 = [`SolveOptions::iterations`]. Like
+    /// [`SolveOptions::tolerance_x`] it overrides a preset, and negative values are invalid.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub iterations_x: c_int,
+    /// Same as [`SolveOptions::iterations_x`] for the Y axis.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub iterations_y: c_int,
+    /// Per-vertex adjustable flags (length `num_vertices`). 1 = Adjustable, 0 = Held.
+    ///
+    /// Only the free vertices flagged adjustable are unknowns of this solve, e.g. the
+    /// stations just imported; the others are held at their current coordinates as boundary
+    /// conditions, like frozen vertices but chosen per call. The edges inside the region and
+    /// at its boundary are counted apart in [`SolveStats`].
+    #[cfg_attr(feature = "serde", serde(skip, default = "std::ptr::null"))]
+    pub adjustable: *const c_int,
+    /// Displacement above which a vertex is listed in [`SolveOptions::moved_vertices`], e.g.
+    /// to invalidate the map tiles it lies in. 0 = no list; negative or NaN values are
+    /// invalid.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub moved_threshold: c_double,
+    /// Caller buffer of [`SolveOptions::moved_capacity`] vertex indices receiving the vertices
+    /// that moved farther than [`SolveOptions::moved_threshold`] from their initial
+    /// coordinates, by decreasing displacement. Their number is reported in
+    /// [`SolveStats::moved_vertices`]; when it exceeds the capacity the solve returns
+    /// [`COMPASS_ERR_BUFFER_TOO_SMALL`] without writing the coordinates, and can be repeated
+    /// with a buffer that large. May be null when the capacity is 0.
+    #[cfg_attr(feature = "serde", serde(skip, default = "std::ptr::null_mut"))]
+    pub moved_vertices: *mut c_int,
+    /// Size of [`SolveOptions::moved_vertices`] in indices.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub moved_capacity: c_int,
+    /// A solve with nothing to adjust succeeds, flagged in [`SolveStats::trivial`]; 1 = fail
+    /// with [`COMPASS_ERR_NO_EDGES`] when no edge is enabled, or else
+    /// [`COMPASS_ERR_NO_FREE_VERTICES`] when no vertex is free, for callers to whom an empty
+    /// selection is a bug. Either way the coordinates are left untouched.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub error_on_trivial: c_int,
+    /// Per-vertex fixed flags as bytes (length `num_vertices`), e.g. a Java `boolean[]`
+    /// passed without widening it to `int[]`. When non-null they replace the `fixed`
+    /// argument, which may then be null, and must be 0 or 1 like those.
+    #[cfg_attr(feature = "serde", serde(skip, default = "std::ptr::null"))]
+    pub fixed_bytes: *const u8,
+    /// S-transformation of the free network of [`SolveOptions::datum_station`] after the
+    /// solve, see [`datum`]: 0 = none, 1 = hold the station at its initial coordinates, 2 =
+    /// also hold the azimuth to [`SolveOptions::datum_azimuth_station`] at its initial value.
+    /// A station anchored by a fixed, frozen or held vertex is invalid, the reason being given
+    /// in [`SolveOptions::error_message`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub s_transform: c_int,
+    /// Vertex held by [`SolveOptions::s_transform`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub datum_station: c_int,
+    /// Vertex whose azimuth from [`SolveOptions::datum_station`] is held with
+    /// [`SolveOptions::s_transform`] 2. It must lie in the same free network.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub datum_azimuth_station: c_int,
+    /// NUL-terminated path of a capture file, see [`capture`]. When non-null, the inputs of
+    /// the call are written there before the solve and its outputs appended after it, so that
+    /// the call can be replayed with [`capture::replay_capture`]. A capture that cannot be
+    /// written fails the call with [`COMPASS_ERR_IO`] before the solve.
+    #[cfg_attr(feature = "serde", serde(skip, default = "std::ptr::null"))]
+    pub capture_path: *const c_char,
+    /// 1 = clamp the free vertices that the solve puts outside the bounding box
+    /// [`SolveOptions::bounds_min_x`]..[`SolveOptions::bounds_max_x`] by
+    /// [`SolveOptions::bounds_min_y`]..[`SolveOptions::bounds_max_y`] onto its edge, for
+    /// renderers that cannot cope with a station flung to 1e15 by a runaway adjustment. A
+    /// NaN coordinate goes to the lower bound.
+    ///
+    /// This is a safety net, not a constraint: a clamped solution no longer satisfies the
+    /// least squares equations, and its clamped stations are wrong. The solve then returns
+    /// [`COMPASS_WARN_CLAMPED`] with the clamped vertices in
+    /// [`SolveOptions::error_message`], their number in [`SolveStats::clamped_vertices`] and
+    /// the distance the farthest vertex was flung in [`SolveStats::max_displacement`], for
+    /// the user to investigate. Fixed, frozen and held vertices are never clamped. Bounds
+    /// that are NaN or not ordered are invalid.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub clamp_to_bounds: c_int,
+    /// Lower X bound of [`SolveOptions::clamp_to_bounds`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub bounds_min_x: c_double,
+    /// Lower Y bound of [`SolveOptions::clamp_to_bounds`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub bounds_min_y: c_double,
+    /// Upper X bound of [`SolveOptions::clamp_to_bounds`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub bounds_max_x: c_double,
+    /// Upper Y bound of [`SolveOptions::clamp_to_bounds`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub bounds_max_y: c_double,
+    /// Per-edge orientation flags (length `num_edges`). 1 = Reversed, 0 = Normal; any other
+    /// value is rejected with [`COMPASS_ERR_INVALID_ARGUMENT`].
+    ///
+    /// A reversed edge observes `x_from - x_to = dx` (and likewise `dy`, and `dz` in
+    /// [`two_stage::solve_graph_least_squares_2plus1`]), e.g. a backsight, so that
+    /// observations are passed in the direction they were taken instead of being negated by
+    /// the caller. The solve swaps the ends of such edges, whichever of them are fixed;
+    /// [`SolveOptions::edge_residuals`] are given in the caller's direction.
+    #[cfg_attr(feature = "serde", serde(skip, default = "std::ptr::null"))]
+    pub reversed: *const c_int,
+    /// Caller buffer of `2 * num_edges` values receiving the residual `(rx, ry)` of every
+    /// edge, enabled or not, at the coordinates written back: `rx = x_to - x_from - dx`, or
+    /// `x_from - x_to - dx` for an edge flagged in [`SolveOptions::reversed`]. Written
+    /// whenever the coordinates are.
+    #[cfg_attr(feature = "serde", serde(skip, default = "std::ptr::null_mut"))]
+    pub edge_residuals: *mut c_double,
+    /// Edge groups whose observations are corrected by an affine transform (length
+    /// [`SolveOptions::num_transforms`]), distinct ids of [`graph_set_edge_groups`], e.g. the
+    /// sources of digitized legacy data. Used by [`graph_solve`] only. See [`transforms`].
+    #[cfg_attr(feature = "serde", serde(skip, default = "std::ptr::null"))]
+    pub transform_groups: *const c_int,
+    /// 2x2 matrix of each group of [`SolveOptions::transform_groups`], four values per group
+    /// in row-major order, invertible. The other groups keep the identity.
+    #[cfg_attr(feature = "serde", serde(skip, default = "std::ptr::null"))]
+    pub transform_matrices: *const c_double,
+    /// Number of groups in [`SolveOptions::transform_groups`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub num_transforms: c_int,
+    /// Precision of the dot products and of the true residual of Conjugate Gradient: 0 = f64,
+    /// 1 = double-double once the residual stagnates, 2 = double-double throughout. See
+    /// [`ExtendedPrecision`]; [`SolveStats::extended_precision`] tells whether it engaged.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub extended_precision: c_int,
+}
+
+/// Size of the first release of [`SolveOptions`], the smallest `struct_size` accepted.
+const SOLVE_OPTIONS_MIN_SIZE: usize =
+    std::mem::offset_of!(SolveOptions, passive) + size_of::<*const c_int>();
+
+impl Default for SolveOptions {
+    fn default() -> Self {
+        SolveOptions {
+            struct_size: size_of::<SolveOptions>(),
+            iterations: 10_000,
+            tolerance: 1e-8,
+            passive: std::ptr::null(),
+            cancel: std::ptr::null(),
+            frozen: std::ptr::null(),
+            edge_enabled: std::ptr::null(),
+            solver: 0,
+            preconditioner: 0,
+            centroid_weights: std::ptr::null(),
+            centroid_stiffness: 0.0,
+            edge_class: std::ptr::null(),
+            class_multipliers: std::ptr::null(),
+            num_classes: 0,
+            verify_fixed: 0,
+            error_message: std::ptr::null_mut(),
+            error_capacity: 0,
+            breakdown_tolerance: 0.0,
+            true_residual_interval: 0,
+            guess_margin: 0.0,
+            keep_isolated: 0,
+            convergence_criterion: 0,
+            time_budget: 0.0,
+            preset: 0,
+            tolerance_x: 0.0,
+            tolerance_y: 0.0,
+            iterations_x: 0,
+            iterations_y: 0,
+            adjustable: std::ptr::null(),
+            moved_threshold: 0.0,
+            moved_vertices: std::ptr::null_mut(),
+            moved_capacity: 0,
+            error_on_trivial: 0,
+            fixed_bytes: std::ptr::null(),
+            s_transform: 0,
+            datum_station: 0,
+            datum_azimuth_station: 0,
+            capture_path: std::ptr::null(),
+            clamp_to_bounds: 0,
+            bounds_min_x: 0.0,
+            bounds_min_y: 0.0,
+            bounds_max_x: 0.0,
+            bounds_max_y: 0.0,
+            reversed: std::ptr::null(),
+            edge_residuals: std::ptr::null_mut(),
+            transform_groups: std::ptr::null(),
+            transform_matrices: std::ptr::null(),
+            num_transforms: 0,
+            extended_precision: 0,
+        }
+    }
+}
+
+impl SolveOptions {
+    /// Default options with the tuning fields of `preset`.
+    pub fn preset(preset: Preset) -> SolveOptions {
+        let mut options = SolveOptions {
+            preset: preset as c_int,
+            ..SolveOptions::default()
+        };
+        preset.apply(&mut options);
+        options
+    }
+
+    /// Sets the tolerance of both axes, clearing [`SolveOptions::tolerance_x`] and
+    /// [`SolveOptions::tolerance_y`].
+    pub fn set_tolerance(&mut self, tolerance: f64) {
+        self.tolerance = tolerance;
+        self.tolerance_x = 0.0;
+        self.tolerance_y = 0.0;
+    }
+
+    /// Sets the iteration limit of both axes, clearing [`SolveOptions::iterations_x`] and
+    /// [`SolveOptions::iterations_y`].
+    pub fn set_iterations(&mut self, iterations: c_int) {
+        self.iterations = iterations;
+        self.iterations_x = 0;
+        self.iterations_y = 0;
+    }
+
+    /// X and Y ranges of [`SolveOptions::clamp_to_bounds`], empty when a bound is NaN or the
+    /// bounds are not ordered.
+    fn bounds(&self) -> (RangeInclusive<f64>, RangeInclusive<f64>) {
+        (
+            self.bounds_min_x..=self.bounds_max_x,
+            self.bounds_min_y..=self.bounds_max_y,
+        )
+    }
+
+    /// Writes the tuning fields the solve runs with to `stats`.
+    fn report(&self, stats: &mut SolveStats) {
+        stats.preset = self.preset;
+        stats.solver = self.solver;
+        stats.preconditioner = self.preconditioner;
+        stats.iteration_limit = self.iterations;
+        stats.tolerance = self.tolerance;
+        stats.convergence_criterion = self.convergence_criterion;
+        stats.time_budget = self.time_budget;
+    }
+}
+
+/// Copies the caller's options into a full [`SolveOptions`], the fields past its
+/// `struct_size` left at their defaults and the [`SolveOptions::preset`] expanded. `None` for
+/// a null pointer, a size below the first release of the struct or an unknown preset.
+fn read_options(options: *const SolveOptions) -> Option<SolveOptions> {
+    if options.is_null() {
+        return None;
+    }
+    // Safety: `struct_size` leads every release of the struct.
+    let size = unsafe { options.cast::<usize>().read() };
+    if size < SOLVE_OPTIONS_MIN_SIZE {
+        return None;
+    }
+    let mut full = SolveOptions::default();
+    // Safety: The caller guarantees `struct_size` readable bytes, and the struct is plain data.
+    unsafe {
+        std::ptr::copy_nonoverlapping(
+            options.cast::<u8>(),
+            (&raw mut full).cast::<u8>(),
+            size.min(size_of::<SolveOptions>()),
+        );
+    }
+    full.struct_size = size_of::<SolveOptions>();
+    if full.preset != 0 {
+        Preset::from_code(full.preset)?.apply(&mut full);
+    }
+    Some(full)
+}
+
+/// Summary of a solve, filled by [`solve_graph_least_squares_ex`].
+///
+/// Like [`SolveOptions`], fields are only ever appended: the caller sets `struct_size` and the
+/// library writes the fields that fit in it.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct SolveStats {
+    /// `sizeof(SolveStats)` as the caller was compiled against it. Read, never written, by
+    /// the library.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub struct_size: usize,
+    /// Number of vertices that were unknowns of the system.
+    pub free_vertices: c_int,
+    /// Number of passive vertices excluded from the system and transformed after the solve.
+    pub passive_vertices: c_int,
+    /// Number of frozen vertices that are not also fixed.
+    pub frozen_vertices: c_int,
+    /// Number of edges linking a frozen vertex to an adjusted one.
+    pub frozen_boundary_edges: c_int,
+    /// Misclosure absorbed at the frozen boundary: the sum of `weight * |residual|^2` over
+    /// the [`SolveStats::frozen_boundary_edges`]. A growing value means the frozen region
+    /// no longer fits the new data and a full re-adjustment is overdue.
+    pub frozen_boundary_stress: c_double,
+    /// Number of edges disabled through [`SolveOptions::edge_enabled`] or
+    /// [`graph_set_edge_enabled`].
+    pub disabled_edges: c_int,
+    /// Iterations run by the linear solver, the larger count of the X and Y axes.
+    pub iterations: c_int,
+    /// Final residual norm `|b - Ax|` of the normal equations, the larger of the X and Y
+    /// axes.
+    pub residual_norm: c_double,
+    /// Number of connected components without a fixed vertex. Their adjustment is only
+    /// defined up to a translation, so each is solved as a free network: the minimum-norm
+    /// solution, which keeps the centroid of its free vertices at that of the initial guess.
+    /// 0 when every component is anchored.
+    pub free_networks: c_int,
+    /// Shift of the weighted centroid of [`SolveOptions::centroid_weights`] along X that the
+    /// adjustment would have made without the centroid constraint. 0 without the constraint.
+    pub centroid_shift_x: c_double,
+    /// Same as [`SolveStats::centroid_shift_x`] along Y.
+    pub centroid_shift_y: c_double,
+    /// Number of loops locked with [`GraphContext::lock_loop`].
+    pub locked_loops: c_int,
+    /// Largest [`locks::LockedLoopStatus::deviation`] of the locked loops after the solve: it
+    /// stays near zero while the lock weight dominates the new data.
+    pub locked_deviation: c_double,
+    /// What the coordinates hold: [`COMPASS_RESULT_UNTOUCHED`], [`COMPASS_RESULT_EXACT`] or
+    /// [`COMPASS_RESULT_APPROXIMATE`]. Running out of iterations still returns [`COMPASS_OK`],
+    /// so callers that need the least squares solution check for [`COMPASS_RESULT_EXACT`].
+    pub result_quality: c_int,
+    /// Why the linear solver stopped: one of the `COMPASS_STOP_*` constants.
+    pub stop_reason: c_int,
+    /// Iterations completed when Conjugate Gradient broke down, with
+    /// [`COMPASS_STOP_BREAKDOWN`]; 0 otherwise.
+    pub breakdown_iteration: c_int,
+    /// Final norm of the residual as updated by the iteration, the larger of the X and Y
+    /// axes. It drifts from [`SolveStats::residual_norm`] in long runs, see
+    /// [`SolveOptions::true_residual_interval`].
+    pub recursive_residual_norm: c_double,
+    /// Times the recursive residual fell below the tolerance while the true residual had
+    /// not, over both axes. Each restarted the iteration from the true residual; before
+    /// [`SolveOptions::true_residual_interval`] the solve stopped there.
+    pub residual_restarts: c_int,
+    /// Number of free vertices whose initial guess was discarded and re-seeded, see
+    /// [`SolveOptions::guess_margin`].
+    pub reseeded_vertices: c_int,
+    /// Number of free vertices left at their initial coordinates for want of a usable edge,
+    /// see [`SolveOptions::keep_isolated`].
+    pub isolated_vertices: c_int,
+    /// Code of the [`SolveOptions::convergence_criterion`] the residual was measured with;
+    /// with [`COMPASS_STOP_CONVERGED`], the criterion that stopped the iteration.
+    pub convergence_criterion: c_int,
+    /// Final residual in the measure of [`SolveStats::convergence_criterion`], the larger of
+    /// the X and Y axes: at most the tolerance once converged.
+    pub convergence_measure: c_double,
+    /// Code of the [`SolveOptions::preset`] the options were expanded from, 0 for none.
+    pub preset: c_int,
+    /// Code of the [`SolveOptions::solver`] used.
+    pub solver: c_int,
+    /// Code of the [`SolveOptions::preconditioner`] used.
+    pub preconditioner: c_int,
+    /// [`SolveOptions::iterations`] used.
+    pub iteration_limit: c_int,
+    /// [`SolveOptions::tolerance`] used.
+    pub tolerance: c_double,
+    /// [`SolveOptions::time_budget`] used.
+    pub time_budget: c_double,
+    /// Iterations run on the X axis, see [`SolveOptions::iterations_x`].
+    pub iterations_x: c_int,
+    /// Iterations run on the Y axis.
+    pub iterations_y: c_int,
+    /// Final residual norm of the X axis, see [`SolveOptions::tolerance_x`].
+    pub residual_norm_x: c_double,
+    /// Final residual norm of the Y axis.
+    pub residual_norm_y: c_double,
+    /// Axes whose initial guess already met the tolerance, so that their solve was skipped
+    /// along with the setup of the preconditioner: 1 = X, 2 = Y, 3 = both.
+    pub skipped_axes: c_int,
+    /// Number of free vertices held for want of an [`SolveOptions::adjustable`] flag, not
+    /// counting frozen ones.
+    pub held_vertices: c_int,
+    /// Number of enabled edges between two adjusted vertices, with
+    /// [`SolveOptions::adjustable`] flags.
+    pub interior_edges: c_int,
+    /// Number of edges linking a held vertex to an adjusted one.
+    pub held_boundary_edges: c_int,
+    /// Misclosure absorbed at the boundary of the adjusted region, as
+    /// [`SolveStats::frozen_boundary_stress`] over the [`SolveStats::held_boundary_edges`].
+    pub held_boundary_stress: c_double,
+    /// Number of vertices that moved farther than [`SolveOptions::moved_threshold`], the
+    /// capacity [`SolveOptions::moved_vertices`] needs.
+    pub moved_vertices: c_int,
+    /// Wall-clock milliseconds spent checking the input and options. The timings are
+    /// measured with a monotonic clock, independently of the `tracing` spans, and are 0 for
+    /// a phase that did not run, or on wasm32-unknown-unknown, which has no clock. They are
+    /// left out of the serde form, which stays reproducible.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub validate_ms: c_double,
+    /// Milliseconds spent assembling the normal equations, 0 when cached ones were reused.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub assemble_ms: c_double,
+    /// Milliseconds spent setting up the preconditioner, and the eigenvalue bounds of
+    /// Chebyshev iteration: next to nothing when it was cached, 0 when both axes were
+    /// skipped.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub precondition_ms: c_double,
+    /// Milliseconds of the solve of the X axis, measured on the thread that ran it.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub solve_x_ms: c_double,
+    /// Milliseconds of the solve of the Y axis, measured on the thread that ran it. The two
+    /// axes run in parallel, so their sum may exceed the elapsed time.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub solve_y_ms: c_double,
+    /// Milliseconds spent copying the coordinates back into the result arrays.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub writeback_ms: c_double,
+    /// Milliseconds of the whole call, at least each of the phases above.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub total_ms: c_double,
+    /// Why there was nothing to adjust: [`COMPASS_TRIVIAL_NO_EDGES`] and
+    /// [`COMPASS_TRIVIAL_NO_FREE_VERTICES`] combined, 0 for an actual adjustment. A trivial
+    /// solve runs no iteration, see [`SolveOptions::error_on_trivial`].
+    pub trivial: c_int,
+    /// Shift along X of the effective datum: the weighted rigid transformation from the
+    /// initial to the adjusted coordinates of the vertices of
+    /// [`SolveOptions::centroid_weights`], or else of the free networks, before any
+    /// [`SolveOptions::s_transform`]. 0 when there are none. See [`datum`].
+    pub datum_shift_x: c_double,
+    /// Same as [`SolveStats::datum_shift_x`] along Y.
+    pub datum_shift_y: c_double,
+    /// Rotation of the effective datum, in radians counterclockwise.
+    pub datum_rotation: c_double,
+    /// Number of free vertices clamped onto the bounding box, see
+    /// [`SolveOptions::clamp_to_bounds`].
+    pub clamped_vertices: c_int,
+    /// Largest distance of a vertex from its initial coordinates, before any clamp, so that a
+    /// runaway adjustment shows even when clamped.
+    pub max_displacement: c_double,
+    /// Number of warnings of the solve, see [`warnings`] and [`warnings::compass_get_warnings`].
+    pub warnings: c_int,
+    /// Axes whose Conjugate Gradient solve ran with double-double accumulation, see
+    /// [`SolveOptions::extended_precision`]: 1 = X, 2 = Y, 3 = both.
+    pub extended_precision: c_int,
+}
+
+/// Why a solve had nothing to adjust, see [`Solution::trivial`] and [`SolveStats::trivial`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TrivialReason {
+    /// There were free vertices and edges: an actual adjustment.
+    #[default]
+    None,
+    /// No edge was enabled, so the free vertices kept their initial coordinates.
+    NoEdges,
+    /// Every vertex was fixed, frozen or held.
+    NoFreeVertices,
+    /// Neither edges nor free vertices, e.g. an empty selection.
+    NoEdgesNorFreeVertices,
+}
+
+impl TrivialReason {
+    /// Reason of a solve with or without enabled `edges` and `free_vertices`.
+    fn of(edges: bool, free_vertices: bool) -> TrivialReason {
+        match (edges, free_vertices) {
+            (true, true) => TrivialReason::None,
+            (false, true) => TrivialReason::NoEdges,
+            (true, false) => TrivialReason::NoFreeVertices,
+            (false, false) => TrivialReason::NoEdgesNorFreeVertices,
+        }
+    }
+
+    /// Reason for the [`SolveStats::trivial`] flags.
+    pub fn from_flags(flags: c_int) -> TrivialReason {
+        TrivialReason::of(
+            flags & COMPASS_TRIVIAL_NO_EDGES == 0,
+            flags & COMPASS_TRIVIAL_NO_FREE_VERTICES == 0,
+        )
+    }
+
+    /// [`SolveStats::trivial`] flags of the reason.
+    pub fn flags(self) -> c_int {
+        match self {
+            TrivialReason::None => 0,
+            TrivialReason::NoEdges => COMPASS_TRIVIAL_NO_EDGES,
+            TrivialReason::NoFreeVertices => COMPASS_TRIVIAL_NO_FREE_VERTICES,
+            TrivialReason::NoEdgesNorFreeVertices => {
+                COMPASS_TRIVIAL_NO_EDGES | COMPASS_TRIVIAL_NO_FREE_VERTICES
+            }
+        }
+    }
+
+    /// Error code and message of the reason with [`SolveOptions::error_on_trivial`].
+    fn error(self) -> Option<(c_int, &'static str)> {
+        match self {
+            TrivialReason::None => None,
+            TrivialReason::NoEdges | TrivialReason::NoEdgesNorFreeVertices => {
+                Some((COMPASS_ERR_NO_EDGES, "no edge is enabled"))
+            }
+            TrivialReason::NoFreeVertices => {
+                Some((COMPASS_ERR_NO_FREE_VERTICES, "no vertex is free"))
+            }
+        }
+    }
+}
+
+/// Iterative method solving the normal equations, see [`SolveOptions::solver`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LinearSolver {
+    /// Conjugate Gradient.
+    #[default]
+    ConjugateGradient,
+    /// Chebyshev semi-iteration. Once the eigenvalue bounds are estimated it needs no dot
+    /// products, only matrix-vector products, which suits parallel hardware, but it takes
+    /// more iterations than CG. It needs a fixed vertex in every connected component, see
+    /// [`SolveStats::free_networks`].
+    Chebyshev,
+}
+
+impl LinearSolver {
+    /// Solver for the FFI code: 0 = ConjugateGradient, 1 = Chebyshev.
+    fn from_code(code: c_int) -> Option<LinearSolver> {
+        match code {
+            0 => Some(LinearSolver::ConjugateGradient),
+            1 => Some(LinearSolver::Chebyshev),
+            _ => None,
+        }
+    }
+}
+
+/// Size at which [`Preconditioner::Amg`] stops coarsening by default.
+pub const DEFAULT_AMG_COARSE_SIZE: usize = 64;
+
+/// Default block size of [`Preconditioner::AdditiveSchwarz`].
+pub const DEFAULT_SCHWARZ_BLOCK_SIZE: usize = 64;
+
+/// Default overlap of [`Preconditioner::AdditiveSchwarz`].
+pub const DEFAULT_SCHWARZ_OVERLAP: usize = 1;
+
+/// Preconditioner of the [`LinearSolver`], see [`SolveOptions::preconditioner`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Preconditioner {
+    #[default]
+    None,
+    /// Jacobi scaling by the inverse diagonal of the normal matrix.
+    Jacobi,
+    /// One smoothed-aggregation algebraic multigrid V-cycle, coarsening until at most
+    /// `coarse_size` unknowns remain, which are solved directly. It keeps the iteration count
+    /// of long chain-like networks nearly independent of their size, for a setup cost of a
+    /// few matrix products. Conjugate Gradient only.
+    Amg { coarse_size: usize },
+    /// Additive Schwarz: the free vertices are split into blocks of `block_size` neighbouring
+    /// stations, each extended by `overlap` layers of neighbours, whose sub-matrices are
+    /// factored directly. With no overlap it is block Jacobi. Conjugate Gradient only.
+    AdditiveSchwarz { block_size: usize, overlap: usize },
+}
+
+impl Preconditioner {
+    /// Preconditioner for the FFI code: 0 = None, 1 = Jacobi, 2 = Amg with the
+    /// [`DEFAULT_AMG_COARSE_SIZE`], 3 = AdditiveSchwarz with the
+    /// [`DEFAULT_SCHWARZ_BLOCK_SIZE`] and [`DEFAULT_SCHWARZ_OVERLAP`].
+    fn from_code(code: c_int) -> Option<Preconditioner> {
+        match code {
+            0 => Some(Preconditioner::None),
+            1 => Some(Preconditioner::Jacobi),
+            2 => Some(Preconditioner::Amg {
+                coarse_size: DEFAULT_AMG_COARSE_SIZE,
+            }),
+            3 => Some(Preconditioner::AdditiveSchwarz {
+                block_size: DEFAULT_SCHWARZ_BLOCK_SIZE,
+                overlap: DEFAULT_SCHWARZ_OVERLAP,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Size of the first release of [`SolveStats`], the smallest `struct_size` accepted.
+const SOLVE_STATS_MIN_SIZE: usize =
+    std::mem::offset_of!(SolveStats, passive_vertices) + size_of::<c_int>();
+
+/// Writes the fields of `stats` that fit in the caller's struct behind `out`, whose own
+/// `struct_size` is kept. Nothing is written through a null pointer.
+fn write_stats(out: *mut SolveStats, stats: &SolveStats) {
+    if out.is_null() {
+        return;
+    }
+    let header = size_of::<usize>();
+    // Safety: `struct_size` leads every release of the struct.
+    let size = unsafe { out.cast::<usize>().read() };
+    let len = size.min(size_of::<SolveStats>());
+    if len > header {
+        // Safety: The caller guarantees `struct_size` writable bytes.
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                (stats as *const SolveStats).cast::<u8>().add(header),
+                out.cast::<u8>().add(header),
+                len - header,
+            );
+        }
+    }
+}
+
+/// Measure of the residual compared with the tolerance, see
+/// [`SolveOptions::convergence_criterion`]. The residual is that of the normal equations,
+/// `AᵀW r = AᵀW l - AᵀW A x`, which vanishes at the least squares solution even when the
+/// observations `l` do not close.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ConvergenceCriterion {
+    /// `|AᵀW r|` itself, in the units of the weights times the coordinates.
+    #[default]
+    AbsoluteResidual,
+    /// `|AᵀW r|` relative to its value at the initial guess: a reduction factor, tighter for
+    /// a good guess.
+    RelativeResidual,
+    /// `|AᵀW r| / |AᵀW l|`, independent of the scale of the weights and of the initial guess.
+    NormalResidual,
+}
+
+impl ConvergenceCriterion {
+    /// Criterion for the FFI code: 0 = AbsoluteResidual, 1 = RelativeResidual, 2 =
+    /// NormalResidual.
+    fn from_code(code: c_int) -> Option<ConvergenceCriterion> {
+        match code {
+            0 => Some(ConvergenceCriterion::AbsoluteResidual),
+            1 => Some(ConvergenceCriterion::RelativeResidual),
+            2 => Some(ConvergenceCriterion::NormalResidual),
+            _ => None,
+        }
+    }
+
+    /// Norm the residual norm is divided by, for the right-hand side `b` and the initial
+    /// residual `r0`. A vanishing norm leaves the residual absolute.
+    fn scale(self, b: &DVector<f64>, r0: &DVector<f64>) -> f64 {
+        let norm = match self {
+            ConvergenceCriterion::AbsoluteResidual => return 1.0,
+            ConvergenceCriterion::RelativeResidual => r0.norm(),
+            ConvergenceCriterion::NormalResidual => b.norm(),
+        };
+        if norm > 0.0 { norm } else { 1.0 }
+    }
+}
+
+/// Precision of the dot products and of the step lengths of Conjugate Gradient, see
+/// [`SolveOptions::extended_precision`].
+///
+/// When two anchors nearly coincide or the weights span many orders of magnitude, the terms
+/// of the dot products `pᵀAp` and `rᵀz` can cancel to rounding noise. Double-double
+/// accumulation, about 32 significant digits, keeps their difference and that of the step
+/// lengths. At UTM-like coordinates the true residual `b - Ax` cancels in the same way, to
+/// rounding noise far above a tight absolute tolerance: once in double-double, the iteration
+/// solves for the correction from the iterate at which it switched and sums the true
+/// residual in double-double as well. The vectors and the matrix-vector product of each
+/// iteration stay f64, so that an iteration costs little more, and the condition of the
+/// matrix still bounds what the solve can reach.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ExtendedPrecision {
+    /// f64 throughout.
+    #[default]
+    Never,
+    /// f64 until the residual norm has not dropped by [`STAGNATION_DECREASE`] for
+    /// [`STAGNATION_WINDOW`] iterations; the iteration then restarts from the true residual
+    /// in double-double.
+    OnStagnation,
+    /// Double-double from the first iteration.
+    Always,
+}
+
+impl ExtendedPrecision {
+    /// Precision for the FFI code: 0 = Never, 1 = OnStagnation, 2 = Always.
+    fn from_code(code: c_int) -> Option<ExtendedPrecision> {
+        match code {
+            0 => Some(ExtendedPrecision::Never),
+            1 => Some(ExtendedPrecision::OnStagnation),
+            2 => Some(ExtendedPrecision::Always),
+            _ => None,
+        }
+    }
+}
+
+/// Conjugate Gradient iterations without progress after which
+/// [`ExtendedPrecision::OnStagnation`] engages double-double accumulation.
+pub const STAGNATION_WINDOW: usize = 50;
+
+/// Factor by which the residual norm must fall below its smallest value so far to count as
+/// progress, see [`STAGNATION_WINDOW`].
+pub const STAGNATION_DECREASE: f64 = 0.99;
+
+/// Named bundle of solver settings for callers who would rather not tune the solver, see
+/// [`SolveOptions::preset`]. All run Conjugate Gradient with the settings below, see
+/// [`ConvergenceCriterion`]; the other tuning fields keep their defaults.
+///
+/// | Preset        | Preconditioner | Criterion          | Tolerance | Iterations | Time budget |
+/// |---------------|----------------|--------------------|-----------|------------|-------------|
+/// | `FastPreview` | Jacobi         | `NormalResidual`   | 1e-4      | 200        | 0.1 s       |
+/// | `Standard`    | Jacobi         | `RelativeResidual` | 1e-8      | 10 000     | none        |
+/// | `Strict`      | AMG            | `NormalResidual`   | 1e-12     | 100 000    | none        |
+///
+/// `Strict` also checks the true residual every 10 iterations and sets
+/// [`SolveOptions::verify_fixed`]. A preset only tunes the linear solve: it neither reweights
+/// the shots robustly nor computes covariances. Run [`Graph::variance_components`] on top of
+/// it for the variance components.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Preset {
+    /// Quick approximate solve for interactive display.
+    FastPreview = 1,
+    /// Everyday adjustment.
+    Standard = 2,
+    /// Tight convergence, checked against the true residual.
+    Strict = 3,
+}
+
+impl Preset {
+    /// Preset for the FFI code: 1 = FastPreview, 2 = Standard, 3 = Strict.
+    fn from_code(code: c_int) -> Option<Preset> {
+        match code {
+            1 => Some(Preset::FastPreview),
+            2 => Some(Preset::Standard),
+            3 => Some(Preset::Strict),
+            _ => None,
+        }
+    }
+
+    /// Overwrites the tuning fields of `options` with those of the preset.
+    fn apply(self, options: &mut SolveOptions) {
+        let (iterations, tolerance, criterion, preconditioner, time_budget) = match self {
+            Preset::FastPreview => (200, 1e-4, ConvergenceCriterion::NormalResidual, 1, 0.1),
+            Preset::Standard => (10_000, 1e-8, ConvergenceCriterion::RelativeResidual, 1, 0.0),
+            Preset::Strict => (100_000, 1e-12, ConvergenceCriterion::NormalResidual, 2, 0.0),
+        };
+        let strict = self == Preset::Strict;
+        options.iterations = iterations;
+        options.tolerance = tolerance;
+        options.solver = LinearSolver::ConjugateGradient as c_int;
+        options.preconditioner = preconditioner;
+        options.convergence_criterion = criterion as c_int;
+        options.time_budget = time_budget;
+        options.breakdown_tolerance = 0.0;
+        options.true_residual_interval = if strict { 10 } else { 0 };
+        options.verify_fixed = strict as c_int;
+    }
+}
+
+/// Solves a graph Least Squares adjustment problem for 2D coordinates (X, Y).
+///
+/// This function is designed to be called from Java via FFI (Project Panama).
+/// It takes a set of vertices (some fixed, some free) and edges (constraints between vertices).
+/// It constructs a system of linear equations `Ax = b` and solves it using the Conjugate Gradient (CG) method.
+///
+/// # Arguments
+///
+/// * `num_vertices` - Total number of vertices in the graph.
+/// * `x` - Pointer to the array of X coordinates. Input: Initial guess. Output: Optimized X coordinates.
+/// * `y` - Pointer to the array of Y coordinates. Input: Initial guess. Output: Optimized Y coordinates.
+/// * `fixed` - Pointer to the array of fixed flags. 1 = Fixed (anchor), 0 = Free (to be adjusted).
+///   Any other value is rejected with [`COMPASS_ERR_INVALID_FIXED_FLAG`].
+/// * `num_edges` - Total number of edges (constraints).
+/// * `from` - Pointer to the array of start vertex indices for each edge.
+/// * `to` - Pointer to the array of end vertex indices for each edge.
+/// * `observed_dx` - Pointer to the array of observed X differences (dx) for each edge.
+/// * `observed_dy` - Pointer to the array of observed Y differences (dy) for each edge.
+/// * `weight` - Pointer to the array of weights for each edge (typically 1/length or 1/variance).
+/// * `iterations` - Maximum number of iterations for the Conjugate Gradient solver.
+/// * `tolerance` - Residual tolerance for convergence of the CG solver.
+#[unsafe(no_mangle)]
+pub extern "C" fn solve_graph_least_squares(
+    num_vertices: c_int,
+    x: *mut c_double,    // In/Out: Initial guess / Result
+    y: *mut c_double,    // In/Out: Initial guess / Result
+    fixed: *const c_int, // 0 = Free, 1 = Fixed
+    num_edges: c_int,
+    from: *const c_int,
+    to: *const c_int,
+    observed_dx: *const c_double,
+    observed_dy: *const c_double,
+    weight: *const c_double,
+    iterations: c_int,
+    tolerance: c_double,
+) -> c_int {
+    let options = SolveOptions {
+        iterations,
+        tolerance,
+        ..SolveOptions::default()
+    };
+    solve_graph_least_squares_ex(
+        num_vertices,
+        x,
+        y,
+        fixed,
+        num_edges,
+        from,
+        to,
+        observed_dx,
+        observed_dy,
+        weight,
+        &options,
+        std::ptr::null_mut(),
+    )
+}
+
+/// Same as [`solve_graph_least_squares`], with the tuning knobs and optional inputs grouped in
+/// a [`SolveOptions`] struct and a [`SolveStats`] summary filled on return.
+///
+/// # Arguments
+///
+/// * `options` - Pointer to the solve options. Must not be null.
+/// * `stats` - Pointer to the stats struct to fill. May be null if the caller does not need it.
+///
+/// All other arguments are identical to [`solve_graph_least_squares`].
+///
+/// # Returns
+///
+/// * [`COMPASS_OK`] once the coordinates are written, exact or approximate as told by
+///   [`SolveStats::result_quality`].
+/// * [`COMPASS_WARN_CLAMPED`] once they are written with free vertices clamped onto the
+///   bounding box of [`SolveOptions::clamp_to_bounds`].
+/// * [`COMPASS_ERR_CANCELLED`] when the solve was cancelled. The coordinates hold the last
+///   iterate, or are untouched if the cancel came before the solve started.
+/// * [`COMPASS_ERR_INVALID_ARGUMENT`] for a null `options`, a `struct_size` of `options` or
+///   of a non-null `stats` below the first release of the struct, a negative count, a null
+///   array of a non-empty graph, an edge referencing a missing vertex, a free vertex whose
+///   edges all have zero weight (see [`SolveOptions::error_message`]) or unsupported
+///   options; nothing is written.
+/// * [`COMPASS_ERR_ISOLATED_VERTEX`] for a free vertex without a usable edge, see
+///   [`SolveOptions::keep_isolated`]; nothing is written.
+/// * [`COMPASS_ERR_INVALID_FIXED_FLAG`] for a fixed flag, in `fixed` or
+///   [`SolveOptions::fixed_bytes`], that is neither 0 nor 1; nothing is written.
+/// * [`COMPASS_ERR_OUT_OF_MEMORY`] when the normal equations of a graph this size cannot be
+///   allocated; nothing is written.
+/// * [`COMPASS_ERR_TOO_LARGE`] for more vertices or edges than [`limits::compass_limits`]
+///   allows (see [`SolveOptions::error_message`]); nothing is written.
+/// * [`COMPASS_ERR_BUFFER_TOO_SMALL`] when more vertices moved than
+///   [`SolveOptions::moved_capacity`] holds; only `stats` is written.
+///
+/// # Concurrent readers
+///
+/// The solve works on copies of `x` / `y` and writes the free vertices back in one pass at
+/// the end, so a thread reading the arrays during the solve sees the initial guess for all
+/// but the last few milliseconds. The write-back is made of plain stores, though: a read that
+/// overlaps it may see a mix of old and new coordinates, and is a data race in the C, Java
+/// and Rust memory models alike, with no guarantee on the values read. Readers must wait for
+/// the call to return, or use a handle and [`graph_get_coordinates_snapshot`].
+#[unsafe(no_mangle)]
+pub extern "C" fn solve_graph_least_squares_ex(
+    num_vertices: c_int,
+    x: *mut c_double,    // In/Out: Initial guess / Result
+    y: *mut c_double,    // In/Out: Initial guess / Result
+    fixed: *const c_int, // 0 = Free, 1 = Fixed
+    num_edges: c_int,
+    from: *const c_int,
+    to: *const c_int,
+    observed_dx: *const c_double,
+    observed_dy: *const c_double,
+    weight: *const c_double,
+    options: *const SolveOptions,
+    stats: *mut SolveStats,
+) -> c_int {
+    let result = std::panic::catch_unwind(|| {
+        let arrays = (fixed, from, to, [observed_dx, observed_dy, weight]);
+        solve_arrays(
+            num_vertices,
+            (x, y),
+            num_edges,
+            arrays,
+            options,
+            stats,
+            None,
+        )
+    });
+
+    match result {
+        Ok(code) => code,
+        Err(_) => {
+            eprintln!("Panic caught in solve_graph_least_squares_ex");
+            COMPASS_ERR_PANIC
+        }
+    }
+}
+
+/// The edge arrays of [`solve_graph_least_squares_ex`]: `fixed`, `from`, `to` and
+/// `[observed_dx, observed_dy, weight]`.
+type EdgeArrays = (
+    *const c_int,
+    *const c_int,
+    *const c_int,
+    [*const c_double; 3],
+);
+
+/// Body of [`solve_graph_least_squares_ex`], and of
+/// [`two_stage::solve_graph_least_squares_2plus1`] with its `vertical` stage.
+fn solve_arrays(
+    num_vertices: c_int,
+    (x, y): (*mut c_double, *mut c_double),
+    num_edges: c_int,
+    (fixed, from, to, edge_arrays): EdgeArrays,
+    options: *const SolveOptions,
+    stats: *mut SolveStats,
+    vertical: Option<&mut two_stage::VerticalStage>,
+) -> c_int {
+    warnings::set_last_warnings(Vec::new());
+    let [observed_dx, observed_dy, weight] = edge_arrays;
+    let (Ok(n_verts), Ok(n_edges)) = (usize::try_from(num_vertices), usize::try_from(num_edges))
+    else {
+        return COMPASS_ERR_INVALID_ARGUMENT;
+    };
+    let Some(options) = read_options(options) else {
+        return COMPASS_ERR_INVALID_ARGUMENT;
+    };
+    if let Err(err) = Limits::CURRENT.check(n_verts, n_edges) {
+        let capacity = options.error_capacity.max(0) as usize;
+        write_message(options.error_message, capacity, &err.message);
+        return err.code;
+    }
+    // Safety: `struct_size` leads every release of the struct.
+    if !stats.is_null() && unsafe { stats.cast::<usize>().read() } < SOLVE_STATS_MIN_SIZE {
+        return COMPASS_ERR_INVALID_ARGUMENT;
+    }
+    let options = &options;
+    let no_fixed = fixed.is_null() && options.fixed_bytes.is_null();
+    if (n_verts > 0 && (x.is_null() || y.is_null() || no_fixed))
+        || (n_edges > 0
+            && (from.is_null() || to.is_null() || edge_arrays.iter().any(|p| p.is_null())))
+    {
+        return COMPASS_ERR_INVALID_ARGUMENT;
+    }
+    if options.iterations == -1 {
+        panic!("Intentional test panic triggered!");
+    }
+
+    // Safety: Creating Rust slices from raw C pointers, checked non-null above for the
+    // non-empty ones. We assume the caller (Java) guarantees correct lengths.
+    let x_slice = unsafe { raw_slice_mut(x, n_verts) };
+    let y_slice = unsafe { raw_slice_mut(y, n_verts) };
+    let widened: Vec<c_int>;
+    let fixed = match options.fixed_bytes.is_null() {
+        true => unsafe { raw_slice(fixed, n_verts) },
+        false => {
+            let bytes = unsafe { raw_slice(options.fixed_bytes, n_verts) };
+            widened = bytes.iter().map(|&f| f as c_int).collect();
+            &widened
+        }
+    };
+    if let Some(i) = fixed.iter().position(|&f| f != 0 && f != 1) {
+        let message = format!("fixed flag of vertex {i} is {}, not 0 or 1", fixed[i]);
+        let capacity = options.error_capacity.max(0) as usize;
+        write_message(options.error_message, capacity, &message);
+        return COMPASS_ERR_INVALID_FIXED_FLAG;
+    }
+    let reversed = match options.reversed.is_null() {
+        true => None,
+        false => Some(unsafe { raw_slice(options.reversed, n_edges) }),
+    };
+    if let Some(reversed) = reversed
+        && let Some(e) = reversed.iter().position(|&r| r != 0 && r != 1)
+    {
+        let message = format!("reversed flag of edge {e} is {}, not 0 or 1", reversed[e]);
+        let capacity = options.error_capacity.max(0) as usize;
+        write_message(options.error_message, capacity, &message);
+        return COMPASS_ERR_INVALID_ARGUMENT;
+    }
+    let graph = GraphView {
+        fixed,
+        from: unsafe { raw_slice(from, n_edges) },
+        to: unsafe { raw_slice(to, n_edges) },
+        dx: unsafe { raw_slice(observed_dx, n_edges) },
+        dy: unsafe { raw_slice(observed_dy, n_edges) },
+        weight: unsafe { raw_slice(weight, n_edges) },
+        enabled: if options.edge_enabled.is_null() {
+            None
+        } else {
+            Some(unsafe { slice::from_raw_parts(options.edge_enabled, n_edges) })
+        },
+    };
+    let passive = if options.passive.is_null() {
+        None
+    } else {
+        Some(unsafe { slice::from_raw_parts(options.passive, n_verts) })
+    };
+    let capture = match options.capture_path.is_null() {
+        true => None,
+        false => {
+            // Safety: The caller guarantees a NUL-terminated path.
+            let path = unsafe { CStr::from_ptr(options.capture_path) }.to_string_lossy();
+            let path = PathBuf::from(path.into_owned());
+            let xy = (&x_slice[..], &y_slice[..]);
+            if let Err(message) = capture::record_inputs(&path, options, xy, &graph) {
+                let capacity = options.error_capacity.max(0) as usize;
+                write_message(options.error_message, capacity, &message);
+                return COMPASS_ERR_IO;
+            }
+            Some(path)
+        }
+    };
+    // A reversed edge is solved with its ends swapped, which reads its observation in the
+    // usual direction.
+    let swapped: (Vec<c_int>, Vec<c_int>);
+    let graph = match reversed {
+        None => graph,
+        Some(reversed) => {
+            swapped = (0..n_edges)
+                .map(|e| match reversed[e] {
+                    1 => (graph.to[e], graph.from[e]),
+                    _ => (graph.from[e], graph.to[e]),
+                })
+                .unzip();
+            GraphView {
+                from: &swapped.0,
+                to: &swapped.1,
+                ..graph
+            }
+        }
+    };
+
+    let clock = Stopwatch::start();
+    let mut local_stats = SolveStats::default();
+    options.report(&mut local_stats);
+    let mut warnings = Vec::new();
+    let code = solve_view(
+        x_slice,
+        y_slice,
+        &graph,
+        passive,
+        options,
+        &mut local_stats,
+        vertical,
+        &mut warnings,
+    );
+    warnings::set_last_warnings(warnings);
+    local_stats.total_ms = clock.ms();
+    write_stats(stats, &local_stats);
+    if let Some(path) = capture {
+        // The call has run: a capture without its outputs still replays it.
+        let _ = capture::record_outputs(&path, code, (x_slice, y_slice));
+    }
+    code
+}
+
+/// Owned survey network for the safe Rust API.
+///
+/// Vertex and edge arrays mirror the FFI arguments of [`solve_graph_least_squares`] one-to-one,
+/// plus the vertical component, which importers and exporters carry along even though the
+/// adjustment itself is horizontal.
+#[derive(Debug, Clone, Default)]
+pub struct Graph {
+    /// X coordinates (easting). Fixed value for anchors, initial guess for free vertices.
+    pub x: Vec<f64>,
+    /// Y coordinates (northing). Fixed value for anchors, initial guess for free vertices.
+    pub y: Vec<f64>,
+    /// Z coordinates (elevation). Not adjusted by the 2D solve, see [`Graph::solve_2plus1`].
+    pub z: Vec<f64>,
+    /// Fixed flags. `true` = Fixed (anchor), `false` = Free (to be adjusted).
+    pub fixed: Vec<bool>,
+    /// Start vertex index of each edge.
+    pub from: Vec<usize>,
+    /// End vertex index of each edge.
+    pub to: Vec<usize>,
+    /// Observed X difference (`x_to - x_from`) of each edge.
+    pub dx: Vec<f64>,
+    /// Observed Y difference (`y_to - y_from`) of each edge.
+    pub dy: Vec<f64>,
+    /// Observed Z difference (`z_to - z_from`) of each edge.
+    pub dz: Vec<f64>,
+    /// Weight of each edge (typically 1/length or 1/variance).
+    pub weight: Vec<f64>,
+    /// Station name of each vertex, when the graph was built from a named source.
+    pub names: Option<Vec<String>>,
+    /// See [`Graph::coordinate_system`]. Not public, so that the unit cannot change without
+    /// converting the values.
+    coordinate_system: Option<CoordinateSystem>,
+}
+
+/// Result of [`Graph::solve`].
+#[derive(Debug, Clone, Default)]
+pub struct Solution {
+    /// Adjusted X coordinates, one per vertex.
+    pub x: Vec<f64>,
+    /// Adjusted Y coordinates, one per vertex.
+    pub y: Vec<f64>,
+    /// Summary of the solve.
+    pub stats: SolveStats,
+    /// Why there was nothing to adjust, if so. A trivial solve still succeeds.
+    pub trivial: TrivialReason,
+    /// Coordinate system of the solved graph, see [`Graph::coordinate_system`].
+    pub coordinate_system: Option<CoordinateSystem>,
+    /// Conditions that did not fail the solve but deserve attention, see [`warnings`].
+    pub warnings: Vec<SolveWarning>,
+}
+
+/// Error ellipse of one vertex, in world units, for the exporters and the SVG renderer to
+/// draw or report.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ErrorEllipse {
+    /// Vertex at the center of the ellipse.
+    pub vertex: usize,
+    /// Semi-axes, major then minor.
+    pub semi_major: f64,
+    pub semi_minor: f64,
+    /// Azimuth of the major axis in degrees, clockwise from north.
+    pub azimuth_deg: f64,
+}
+
+/// Residual statistics of one edge group (e.g. a survey trip), see
+/// [`Solution::group_reports`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct GroupReport {
+    /// Group id, as given per edge.
+    pub group: i32,
+    /// Number of edges in the group.
+    pub edges: usize,
+    /// Weighted RMS residual length: `sqrt(sum(w * |r|^2) / sum(w))`.
+    pub weighted_rms: f64,
+    /// Largest standardized residual of the group.
+    pub max_standardized: f64,
+    /// Mean squared standardized residual of the group relative to that of all edges. Values
+    /// well above 1 point at the group holding a blunder.
+    pub suspicion: f64,
+    /// Matrix correcting the observations of the group, row major, see [`transforms`]. The
+    /// identity outside of [`GraphContext::group_reports`].
+    pub transform: [f64; 4],
+}
+
+/// Misfit of the observations at given coordinates, filled by [`compute_misfit_summary`].
+/// The misfit of an edge is the coordinate difference of its ends minus its observation,
+/// i.e. its residual after the adjustment.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct MisfitSummary {
+    /// Number of edges summarized.
+    pub edges: c_int,
+    /// Weighted RMS misfit length: `sqrt(sum(w * |r|^2) / sum(w))`. 0 when the weights sum
+    /// to zero.
+    pub weighted_rms: c_double,
+    /// Same as [`MisfitSummary::weighted_rms`] with the X component of the misfit only.
+    pub weighted_rms_x: c_double,
+    /// Same as [`MisfitSummary::weighted_rms`] with the Y component of the misfit only.
+    pub weighted_rms_y: c_double,
+    /// Largest misfit length.
+    pub max_misfit: c_double,
+    /// Edge of [`MisfitSummary::max_misfit`], the first one on ties. -1 without edges.
+    pub max_edge: c_int,
+    /// Largest absolute X component of a misfit.
+    pub max_misfit_x: c_double,
+    /// Largest absolute Y component of a misfit.
+    pub max_misfit_y: c_double,
+}
+
+/// Error returned by the safe API, carrying the same code the FFI would have returned.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SolveError {
+    /// FFI error code (negative).
+    pub code: c_int,
+    /// Human readable description.
+    pub message: String,
+}
+
+impl std::fmt::Display for SolveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} (code {})", self.message, self.code)
+    }
+}
+
+impl std::error::Error for SolveError {}
+
+/// Error produced while importing survey files.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ImportError {
+    /// The file could not be read.
+    Io { path: PathBuf, message: String },
+    /// The file content is malformed. `line` and `column` are 1-based.
+    Parse {
+        path: PathBuf,
+        line: usize,
+        column: usize,
+        message: String,
+    },
+}
+
+impl std::fmt::Display for ImportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ImportError::Io { path, message } => write!(f, "{}: {}", path.display(), message),
+            ImportError::Parse {
+                path,
+                line,
+                column,
+                message,
+            } => write!(f, "{}:{}:{}: {}", path.display(), line, column, message),
+        }
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+impl Solution {
+    /// Residual `(rx, ry)` of each edge: adjusted coordinate difference minus observation.
+    pub fn residuals(&self, graph: &Graph) -> Vec<(f64, f64)> {
+        (0..graph.num_edges())
+            .map(|e| {
+                let (u, v) = (graph.from[e], graph.to[e]);
+                (
+                    self.x[v] - self.x[u] - graph.dx[e],
+                    self.y[v] - self.y[u] - graph.dy[e],
+                )
+            })
+            .collect()
+    }
+
+    /// Standardized residual of each edge: the residual length scaled by the square root of
+    /// the edge weight, i.e. in units of the observation standard deviation when the weights
+    /// are inverse variances.
+    pub fn standardized_residuals(&self, graph: &Graph) -> Vec<f64> {
+        self.residuals(graph)
+            .iter()
+            .zip(&graph.weight)
+            .map(|(&(rx, ry), &w)| rx.hypot(ry) * w.sqrt())
+            .collect()
+    }
+
+    /// Residual statistics per edge group, `group_id` holding the group of each edge. Group
+    /// ids need not be contiguous. Reports are sorted by decreasing
+    /// [`GroupReport::suspicion`], so the most suspicious group comes first.
+    pub fn group_reports(&self, graph: &Graph, group_id: &[i32]) -> Vec<GroupReport> {
+        group_reports(&self.x, &self.y, graph, group_id, &|_| true)
+    }
+
+    /// Vertices that moved farther than `threshold` from their coordinates in `graph`, with
+    /// their displacement, by decreasing displacement.
+    pub fn moved_vertices(&self, graph: &Graph, threshold: f64) -> Vec<(usize, f64)> {
+        moved_vertices((&graph.x, &graph.y), (&self.x, &self.y), threshold)
+    }
+}
+
+/// Implementation of [`Solution::moved_vertices`] from `(x0, y0)` to `(x, y)`. Vertices of
+/// equal displacement keep their order.
+fn moved_vertices(
+    (x0, y0): (&[f64], &[f64]),
+    (x, y): (&[f64], &[f64]),
+    threshold: f64,
+) -> Vec<(usize, f64)> {
+    let mut moved: Vec<(usize, f64)> = (0..x.len())
+        .map(|i| (i, (x[i] - x0[i]).hypot(y[i] - y0[i])))
+        .filter(|&(_, displacement)| displacement > threshold)
+        .collect();
+    moved.sort_by(|a, b| b.1.total_cmp(&a.1));
+    moved
+}
+
+/// Implementation of [`Solution::group_reports`] at the coordinates `x` / `y`, skipping the
+/// edges for which `is_enabled` is false.
+fn group_reports(
+    x: &[f64],
+    y: &[f64],
+    graph: &Graph,
+    group_id: &[i32],
+    is_enabled: &dyn Fn(usize) -> bool,
+) -> Vec<GroupReport> {
+    // Per group: edge count, sum of w * |r|^2, sum of w, max standardized residual.
+    let mut groups: HashMap<i32, (usize, f64, f64, f64)> = HashMap::new();
+    let (mut total_edges, mut total_square) = (0, 0.0);
+    for e in (0..graph.num_edges()).filter(|&e| is_enabled(e)) {
+        let (u, v, w) = (graph.from[e], graph.to[e], graph.weight[e]);
+        let rx = x[v] - x[u] - graph.dx[e];
+        let ry = y[v] - y[u] - graph.dy[e];
+        let square = w * (rx * rx + ry * ry);
+        let group = groups.entry(group_id[e]).or_default();
+        group.0 += 1;
+        group.1 += square;
+        group.2 += w;
+        group.3 = group.3.max(square.sqrt());
+        total_edges += 1;
+        total_square += square;
+    }
+
+    let mean_square = total_square / total_edges.max(1) as f64;
+    let mut reports: Vec<GroupReport> = groups
+        .into_iter()
+        .map(|(group, (edges, square, weight, max))| GroupReport {
+            group,
+            edges,
+            weighted_rms: if weight > 0.0 {
+                (square / weight).sqrt()
+            } else {
+                0.0
+            },
+            max_standardized: max,
+            suspicion: if mean_square > 0.0 {
+                square / edges as f64 / mean_square
+            } else {
+                0.0
+            },
+            transform: transforms::IDENTITY,
+        })
+        .collect();
+    reports.sort_by(|a, b| {
+        b.suspicion
+            .total_cmp(&a.suspicion)
+            .then(a.group.cmp(&b.group))
+    });
+    reports
+}
+
+impl Graph {
+    /// Number of vertices in the graph.
+    pub fn num_vertices(&self) -> usize {
+        self.x.len()
+    }
+
+    /// Number of edges in the graph.
+    pub fn num_edges(&self) -> usize {
+        self.from.len()
+    }
+
+    /// Adds a vertex and returns its index.
+    pub fn add_vertex(&mut self, x: f64, y: f64, z: f64, fixed: bool) -> usize {
+        self.x.push(x);
+        self.y.push(y);
+        self.z.push(z);
+        self.fixed.push(fixed);
+        self.x.len() - 1
+    }
+
+    /// Adds an edge observing `(dx, dy, dz)` from `from` to `to` and returns its index.
+    pub fn add_edge(
+        &mut self,
+        from: usize,
+        to: usize,
+        dx: f64,
+        dy: f64,
+        dz: f64,
+        weight: f64,
+    ) -> usize {
+        self.from.push(from);
+        self.to.push(to);
+        self.dx.push(dx);
+        self.dy.push(dy);
+        self.dz.push(dz);
+        self.weight.push(weight);
+        self.from.len() - 1
+    }
+
+    /// Coordinate difference `(x[to] - x[from], y[to] - y[from])` implied for each edge by
+    /// the current coordinates, the forward model of the adjustment: compared with the
+    /// observations before the solve, as [`Solution::residuals`] does after it. Fails with
+    /// [`COMPASS_ERR_INVALID_ARGUMENT`] for an edge whose endpoint is out of range.
+    pub fn edge_deltas(&self) -> Result<Vec<(f64, f64)>, SolveError> {
+        let mut dx = vec![0.0; self.num_edges()];
+        let mut dy = vec![0.0; self.num_edges()];
+        edge_deltas(&self.x, &self.y, &self.from, &self.to, &mut dx, &mut dy).map_err(|e| {
+            SolveError {
+                code: COMPASS_ERR_INVALID_ARGUMENT,
+                message: format!("edge {e} has an endpoint out of range"),
+            }
+        })?;
+        Ok(dx.into_iter().zip(dy).collect())
+    }
+
+    /// Misfit of the observations at the graph coordinates over all edges, see
+    /// [`compute_misfit_summary`]. Fails with [`COMPASS_ERR_INVALID_ARGUMENT`] for an edge
+    /// whose endpoint is out of range.
+    pub fn misfit_summary(&self) -> Result<MisfitSummary, SolveError> {
+        misfit_summary(
+            (&self.x, &self.y),
+            (&self.from, &self.to),
+            (&self.dx, &self.dy),
+            &self.weight,
+            &|_| true,
+        )
+        .map_err(|e| SolveError {
+            code: COMPASS_ERR_INVALID_ARGUMENT,
+            message: format!("edge {e} has an endpoint out of range"),
+        })
+    }
+
+    /// Removes every edge from index `len` on.
+    fn truncate_edges(&mut self, len: usize) {
+        self.from.truncate(len);
+        self.to.truncate(len);
+        self.dx.truncate(len);
+        self.dy.truncate(len);
+        self.dz.truncate(len);
+        self.weight.truncate(len);
+    }
+
+    /// Stable 64-bit hash of everything the horizontal adjustment depends on, for cache
+    /// invalidation: vertex count, fixed flags, fixed X/Y coordinates, and each edge's
+    /// endpoints, observed dx/dy and weight.
+    ///
+    /// Free initial guesses, the vertical component and station names are excluded. Vertex
+    /// order matters (indices identify vertices) but edge order does not: edges are hashed
+    /// individually and combined in sorted order. Reversing an edge (swapping endpoints and
+    /// negating the observation) changes the hash. Values are hashed as little-endian bit
+    /// patterns with FNV-1a, so the result is identical on every platform.
+    pub fn content_hash(&self) -> u64 {
+        content_hash(
+            &self.x,
+            &self.y,
+            |i| self.fixed[i],
+            (0..self.num_edges()).map(|e| {
+                (
+                    self.from[e] as u64,
+                    self.to[e] as u64,
+                    self.dx[e],
+                    self.dy[e],
+                    self.weight[e],
+                )
+            }),
+        )
+    }
+
+    /// Extracts the neighborhood of `vertices`: every vertex within `hops` edges of a seed,
+    /// and every edge between two such vertices. Returns the subgraph and, for each of its
+    /// vertices, the index of the original vertex.
+    ///
+    /// Boundary vertices, those with a neighbor outside the subgraph, become fixed at their
+    /// current coordinates, so solving the subgraph adjusts the neighborhood as if the rest
+    /// of the network were exact. Vertices keep their relative order. Seeds out of range are
+    /// ignored.
+    pub fn subgraph_around(&self, vertices: &[usize], hops: usize) -> (Graph, Vec<usize>) {
+        let n = self.num_vertices();
+        let mut neighbors = vec![Vec::new(); n];
+        for (&u, &v) in self.from.iter().zip(&self.to) {
+            neighbors[u].push(v);
+            neighbors[v].push(u);
+        }
+
+        // Breadth-first search from all seeds at once.
+        let mut distance = vec![usize::MAX; n];
+        let mut queue = std::collections::VecDeque::new();
+        for &seed in vertices.iter().filter(|&&v| v < n) {
+            distance[seed] = 0;
+            queue.push_back(seed);
+        }
+        while let Some(u) = queue.pop_front() {
+            if distance[u] == hops {
+                continue;
+            }
+            for &v in &neighbors[u] {
+                if distance[v] == usize::MAX {
+                    distance[v] = distance[u] + 1;
+                    queue.push_back(v);
+                }
+            }
+        }
+
+        let mapping: Vec<usize> = (0..n).filter(|&i| distance[i] != usize::MAX).collect();
+        let mut local = vec![usize::MAX; n];
+        let mut sub = Graph::default();
+        for &i in &mapping {
+            let boundary = neighbors[i].iter().any(|&j| distance[j] == usize::MAX);
+            local[i] = sub.add_vertex(self.x[i], self.y[i], self.z[i], self.fixed[i] || boundary);
+        }
+        for e in 0..self.num_edges() {
+            let (u, v) = (local[self.from[e]], local[self.to[e]]);
+            if u != usize::MAX && v != usize::MAX {
+                sub.add_edge(u, v, self.dx[e], self.dy[e], self.dz[e], self.weight[e]);
+            }
+        }
+        if let Some(names) = &self.names {
+            sub.names = Some(mapping.iter().map(|&i| names[i].clone()).collect());
+        }
+        (sub, mapping)
+    }
+
+    /// Solves the horizontal adjustment, leaving the graph untouched.
+    ///
+    /// The coordinates are the same bit for bit whatever the order in which the edges were
+    /// added and the number of threads: the normal equations are summed in a canonical order
+    /// of the edges, and the threads sharing the products of a large system each sum whole
+    /// rows in order.
+    pub fn solve(&self, iterations: usize, tolerance: f64) -> Result<Solution, SolveError> {
+        self.solve_with(
+            iterations,
+            tolerance,
+            LinearSolver::default(),
+            Preconditioner::default(),
+        )
+    }
+
+    /// Structure of the normal equations of this graph over all its edges, see
+    /// [`MatrixStats`].
+    pub fn matrix_stats(&self) -> Result<MatrixStats, SolveError> {
+        Ok(self.normal_equations(&[])?.matrix_stats())
+    }
+
+    /// Same as [`Graph::solve`] with a choice of linear solver and preconditioner.
+    pub fn solve_with(
+        &self,
+        iterations: usize,
+        tolerance: f64,
+        solver: LinearSolver,
+        preconditioner: Preconditioner,
+    ) -> Result<Solution, SolveError> {
+        let system = self.normal_equations(&[])?;
+        let method = (solver, preconditioner);
+        self.solve_system(
+            &system,
+            &self.x,
+            &self.y,
+            iterations,
+            tolerance.into(),
+            method,
+            &|| false,
+        )
+    }
+
+    /// Same as [`Graph::solve`], the residual being measured by `criterion` against
+    /// `tolerance`.
+    pub fn solve_until(
+        &self,
+        iterations: usize,
+        tolerance: f64,
+        criterion: ConvergenceCriterion,
+    ) -> Result<Solution, SolveError> {
+        let system = self.normal_equations(&[])?;
+        self.solve_system(
+            &system,
+            &self.x,
+            &self.y,
+            iterations,
+            Tolerance {
+                criterion,
+                ..tolerance.into()
+            },
+            Default::default(),
+            &|| false,
+        )
+    }
+
+    /// Same as [`Graph::solve`], stopping with [`COMPASS_ERR_CANCELLED`] as soon as another
+    /// thread sets `cancel`.
+    pub fn solve_cancellable(
+        &self,
+        iterations: usize,
+        tolerance: f64,
+        cancel: &AtomicBool,
+    ) -> Result<Solution, SolveError> {
+        let system = self.normal_equations(&[])?;
+        let cancelled = || cancel.load(Ordering::Relaxed);
+        let method = Default::default();
+        self.solve_system(
+            &system,
+            &self.x,
+            &self.y,
+            iterations,
+            tolerance.into(),
+            method,
+            &cancelled,
+        )
+    }
+
+    /// Same as [`Graph::solve`], with the vertices flagged in `frozen` kept at their current
+    /// coordinates like fixed ones. The misclosure absorbed at the frozen boundary is
+    /// reported in the solution stats, see [`SolveOptions::frozen`].
+    pub fn solve_frozen(
+        &self,
+        frozen: &[bool],
+        iterations: usize,
+        tolerance: f64,
+    ) -> Result<Solution, SolveError> {
+        let is_frozen = |i: usize| !self.fixed[i] && frozen.get(i).copied().unwrap_or(false);
+        let mut constrained = self.clone();
+        for (i, fixed) in constrained.fixed.iter_mut().enumerate() {
+            *fixed |= is_frozen(i);
+        }
+        let mut solution = constrained.solve(iterations, tolerance)?;
+        let (edges, stress) = frozen_boundary(
+            &solution.x,
+            &solution.y,
+            (0..self.num_edges()).map(|e| {
+                (
+                    self.from[e],
+                    self.to[e],
+                    self.dx[e],
+                    self.dy[e],
+                    self.weight[e],
+                )
+            }),
+            &is_frozen,
+            &|i| !constrained.fixed[i],
+        );
+        solution.stats.frozen_vertices =
+            (0..self.num_vertices()).filter(|&i| is_frozen(i)).count() as c_int;
+        solution.stats.frozen_boundary_edges = edges;
+        solution.stats.frozen_boundary_stress = stress;
+        Ok(solution)
+    }
+
+    /// Same as [`Graph::solve`], adjusting only the free vertices flagged in `adjustable`;
+    /// the others are held at their current coordinates like fixed ones. The edges inside
+    /// and at the boundary of the region are reported in the solution stats, see
+    /// [`SolveOptions::adjustable`].
+    pub fn solve_region(
+        &self,
+        adjustable: &[bool],
+        iterations: usize,
+        tolerance: f64,
+    ) -> Result<Solution, SolveError> {
+        let is_held = |i: usize| !self.fixed[i] && !adjustable.get(i).copied().unwrap_or(false);
+        let mut constrained = self.clone();
+        for (i, fixed) in constrained.fixed.iter_mut().enumerate() {
+            *fixed |= is_held(i);
+        }
+        let mut solution = constrained.solve(iterations, tolerance)?;
+        let edges = || {
+            (0..self.num_edges()).map(|e| {
+                (
+                    self.from[e],
+                    self.to[e],
+                    self.dx[e],
+                    self.dy[e],
+                    self.weight[e],
+                )
+            })
+        };
+        let is_active = |i: usize| !constrained.fixed[i];
+        let (boundary, stress) =
+            frozen_boundary(&solution.x, &solution.y, edges(), &is_held, &is_active);
+        solution.stats.held_vertices =
+            (0..self.num_vertices()).filter(|&i| is_held(i)).count() as c_int;
+        solution.stats.interior_edges = edges()
+            .filter(|&(u, v, ..)| is_active(u) && is_active(v))
+            .count() as c_int;
+        solution.stats.held_boundary_edges = boundary;
+        solution.stats.held_boundary_stress = stress;
+        Ok(solution)
+    }
+
+    /// Assembles the normal equations of the graph. Edges flagged `false` in `enabled` are
+    /// left out; edges past its end are enabled. Fails with [`COMPASS_ERR_TOO_LARGE`] past
+    /// the [`limits`] of the build, or [`COMPASS_ERR_OUT_OF_MEMORY`] when the system cannot
+    /// be allocated.
+    fn normal_equations(&self, enabled: &[bool]) -> Result<NormalEquations, SolveError> {
+        Limits::CURRENT.check(self.num_vertices(), self.num_edges())?;
+        self.with_view(enabled, |view| {
+            NormalEquations::assemble(&self.x, &self.y, view, &|_| false).map_err(|code| {
+                SolveError {
+                    code,
+                    message: format!(
+                        "normal equations of {} vertices and {} edges too large to allocate",
+                        self.num_vertices(),
+                        self.num_edges()
+                    ),
+                }
+            })
+        })
+    }
+
+    /// Refills `system`, the normal equations of the same edges with other enabled flags,
+    /// weights or observations, from this graph, see [`NormalEquations::refill`].
+    fn refill_normal_equations(&self, system: &mut NormalEquations, enabled: &[bool]) -> bool {
+        self.with_view(enabled, |view| {
+            system.refill(&self.x, &self.y, view, &|_| false)
+        })
+    }
+
+    /// Why a solve over the `enabled` edges would have nothing to adjust; edges past the end
+    /// of `enabled` are enabled.
+    fn trivial(&self, enabled: &[bool]) -> TrivialReason {
+        let edges = (0..self.num_edges()).any(|e| enabled.get(e).copied().unwrap_or(true));
+        TrivialReason::of(edges, self.fixed.contains(&false))
+    }
+
+    /// Calls `f` with the view of the graph over the `enabled` edges.
+    fn with_view<R>(&self, enabled: &[bool], f: impl FnOnce(&GraphView) -> R) -> R {
+        // The solver core works on the FFI representation.
+        let fixed: Vec<c_int> = self.fixed.iter().map(|&f| f as c_int).collect();
+        let from: Vec<c_int> = self.from.iter().map(|&v| v as c_int).collect();
+        let to: Vec<c_int> = self.to.iter().map(|&v| v as c_int).collect();
+        let enabled: Vec<c_int> = (0..self.num_edges())
+            .map(|e| enabled.get(e).copied().unwrap_or(true) as c_int)
+            .collect();
+        let view = GraphView {
+            fixed: &fixed,
+            from: &from,
+            to: &to,
+            dx: &self.dx,
+            dy: &self.dy,
+            weight: &self.weight,
+            enabled: Some(&enabled),
+        };
+        f(&view)
+    }
+
+    /// Solves `system`, the normal equations of this graph, starting the free vertices from
+    /// `x0` / `y0`.
+    #[allow(clippy::too_many_arguments)]
+    fn solve_system(
+        &self,
+        system: &NormalEquations,
+        x0: &[f64],
+        y0: &[f64],
+        iterations: usize,
+        tolerance: Tolerance,
+        (solver, preconditioner): (LinearSolver, Preconditioner),
+        cancelled: &(dyn Fn() -> bool + Sync),
+    ) -> Result<Solution, SolveError> {
+        let clock = Stopwatch::start();
+        if !supported(solver, preconditioner) {
+            return Err(SolveError {
+                code: COMPASS_ERR_INVALID_ARGUMENT,
+                message: "Chebyshev iteration only takes Jacobi preconditioning".to_string(),
+            });
+        }
+        if !system.accepts(solver) {
+            return Err(SolveError {
+                code: COMPASS_ERR_INVALID_ARGUMENT,
+                message: "Chebyshev iteration needs a fixed vertex in every component".to_string(),
+            });
+        }
+        let mut solution = Solution {
+            x: self.x.clone(),
+            y: self.y.clone(),
+            stats: SolveStats {
+                free_vertices: system.size() as c_int,
+                free_networks: system.null_space.dimension() as c_int,
+                isolated_vertices: (0..self.num_vertices())
+                    .filter(|&i| !self.fixed[i] && system.mapping[i].is_none())
+                    .count() as c_int,
+                trivial: self.trivial(&[]).flags(),
+                ..SolveStats::default()
+            },
+            trivial: self.trivial(&[]),
+            coordinate_system: self.coordinate_system,
+            warnings: Vec::new(),
+        };
+        for (i, idx) in system.mapping.iter().enumerate() {
+            if idx.is_some() {
+                solution.x[i] = x0[i];
+                solution.y[i] = y0[i];
+            }
+        }
+        let mut convergence = None;
+        if system.size() > 0 {
+            let (x, y) = (&mut solution.x, &mut solution.y);
+            let method = (solver, preconditioner);
+            let solved = system.solve(x, y, iterations, tolerance, method, cancelled);
+            let convergence = convergence.insert(solved);
+            if convergence.cancelled {
+                return Err(SolveError {
+                    code: COMPASS_ERR_CANCELLED,
+                    message: "solve cancelled".to_string(),
+                });
+            }
+            if let Some(iteration) = convergence.preconditioner_failure {
+                return Err(sparse::preconditioner_failure(iteration));
+            }
+            convergence.record(&mut solution.stats);
+        } else {
+            solution.stats.result_quality = COMPASS_RESULT_EXACT;
+        }
+        let is_isolated = |i: usize| !self.fixed[i] && system.mapping[i].is_none();
+        solution.warnings = warnings::solve_warnings(system, &is_isolated, convergence.as_ref());
+        solution.stats.count_warnings(&solution.warnings);
+        if system.null_space.dimension() > 0 {
+            let free = datum::free_network_vertices(system);
+            let adjusted = (&solution.x[..], &solution.y[..]);
+            let weight = |i: usize| free[i] as u8 as f64;
+            datum::EffectiveDatum::fit((x0, y0), adjusted, &weight).record(&mut solution.stats);
+        }
+        solution.stats.total_ms = clock.ms();
+        Ok(solution)
+    }
+}
+
+/// Options for [`GraphContext::preview`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PreviewOptions {
+    /// Number of sweeps over the free vertices.
+    pub sweeps: usize,
+    /// Relaxation factor in `(0, 2)`: 1 = Gauss-Seidel, above 1 = SOR.
+    pub omega: f64,
+    /// Only the vertices within this many edges of the dragged one move. `None` = all.
+    pub radius: Option<usize>,
+}
+
+impl Default for PreviewOptions {
+    fn default() -> Self {
+        PreviewOptions {
+            sweeps: 10,
+            omega: 1.0,
+            radius: None,
+        }
+    }
+}
+
+/// Opaque handle owning a [`Graph`] across FFI calls.
+///
+/// Created by one of the `graph_from_*` constructors and released with [`graph_free`].
+pub struct GraphContext {
+    graph: Graph,
+    solution: Option<Solution>,
+    /// Normal equations assembled by the first [`graph_solve`], reused by later solves along
+    /// with their preconditioner until the topology or the weights change.
+    system: Option<NormalEquations>,
+    /// Per-edge enabled flags set by [`graph_set_edge_enabled`]. Edges past its end are
+    /// enabled.
+    edge_enabled: Vec<bool>,
+    /// Per-edge group ids set by [`graph_set_edge_groups`]. Edges past its end are in group 0.
+    group_id: Vec<c_int>,
+    /// Observation transforms per edge group of the last [`graph_solve`].
+    transforms: Option<transforms::ObservationTransforms>,
+    /// Loops locked by [`GraphContext::lock_loop`], whose observations replace those of their
+    /// edges in the normal equations.
+    locked: Vec<locks::LockedLoop>,
+    /// Edge classes of the last [`graph_solve`], scaling the edge weights in the normal
+    /// equations.
+    classes: Option<classes::EdgeClasses>,
+    /// Observations of absolute positions set by [`GraphContext::set_position_observations`],
+    /// added to the normal equations.
+    positions: Vec<positions::PositionObservation>,
+    /// Coordinates read by [`graph_get_coordinates_snapshot`], possibly from another thread.
+    /// The field is never reassigned, only the buffer behind the lock changes.
+    published: Mutex<Published>,
+    /// Index of the spatial queries, built by the first query after the coordinates or the
+    /// edges change.
+    spatial_index: OnceLock<spatial::SpatialIndex>,
+    /// Residuals of the last two stored solutions, see [`residual_sync`].
+    residual_history: residual_sync::ResidualHistory,
+}
+
+/// Coordinates of a [`GraphContext`] as last published, see
+/// [`graph_get_coordinates_snapshot`].
+#[derive(Debug, Default)]
+struct Published {
+    /// Number of publications so far.
+    generation: u64,
+    x: Vec<f64>,
+    y: Vec<f64>,
+}
+
+impl GraphContext {
+    /// Wraps a graph into a heap-allocated handle for the FFI.
+    pub fn into_raw(graph: Graph) -> *mut GraphContext {
+        let ctx = GraphContext {
+            graph,
+            solution: None,
+            system: None,
+            edge_enabled: Vec::new(),
+            group_id: Vec::new(),
+            transforms: None,
+            locked: Vec::new(),
+            classes: None,
+            positions: Vec::new(),
+            published: Default::default(),
+            spatial_index: OnceLock::new(),
+            residual_history: Default::default(),
+        };
+        ctx.publish();
+        Box::into_raw(Box::new(ctx))
+    }
+
+    /// Stores `solution`, records its residuals and publishes its coordinates.
+    pub(crate) fn store(&mut self, solution: Solution) -> &Solution {
+        let mut history = std::mem::take(&mut self.residual_history);
+        history.record(&self.observed_graph(), &solution.x, &solution.y);
+        self.residual_history = history;
+        self.solution = Some(solution);
+        self.spatial_index.take();
+        self.publish();
+        let Some(solution) = &self.solution else {
+            unreachable!("stored above");
+        };
+        solution
+    }
+
+    /// Publishes the current [`GraphContext::coordinates`] for
+    /// [`graph_get_coordinates_snapshot`] under the next generation, in one pass under the
+    /// lock.
+    pub(crate) fn publish(&self) {
+        let (x, y) = self.coordinates();
+        let mut published = self.published.lock().unwrap_or_else(|e| e.into_inner());
+        published.generation += 1;
+        published.x.clear();
+        published.x.extend_from_slice(x);
+        published.y.clear();
+        published.y.extend_from_slice(y);
+    }
+
+    /// The graph owned by this handle. Its coordinates are the inputs of the solve.
+    pub fn graph(&self) -> &Graph {
+        &self.graph
+    }
+
+    /// The result of the last successful [`graph_solve`], if any.
+    pub fn solution(&self) -> Option<&Solution> {
+        self.solution.as_ref()
+    }
+
+    /// Current X/Y coordinates: the adjusted ones after a successful [`graph_solve`], the
+    /// input coordinates before.
+    pub fn coordinates(&self) -> (&[f64], &[f64]) {
+        match &self.solution {
+            Some(solution) => (&solution.x, &solution.y),
+            None => (&self.graph.x, &self.graph.y),
+        }
+    }
+
+    /// Whether `edge` takes part in the solve, see [`graph_set_edge_enabled`].
+    pub fn is_edge_enabled(&self, edge: usize) -> bool {
+        self.edge_enabled.get(edge).copied().unwrap_or(true)
+    }
+
+    /// Enables or disables `edge` for the next solves. The cached normal equations are
+    /// refilled in place, their structure being that of all the edges, or re-assembled by the
+    /// next solve when loops are locked. The next solve starts from the current coordinates.
+    pub fn set_edge_enabled(&mut self, edge: usize, enabled: bool) -> Result<(), SolveError> {
+        if edge >= self.graph.num_edges() {
+            return Err(SolveError {
+                code: COMPASS_ERR_INVALID_ARGUMENT,
+                message: format!("edge {edge} out of range"),
+            });
+        }
+        if self.is_edge_enabled(edge) != enabled {
+            self.edge_enabled.resize(self.graph.num_edges(), true);
+            self.edge_enabled[edge] = enabled;
+            self.refill();
+        }
+        Ok(())
+    }
+
+    /// Tags each edge with a group id (e.g. its survey trip) for
+    /// [`GraphContext::group_reports`] and the observation transforms. Edges added later are
+    /// in group 0. Cached normal equations are refilled when the observations are transformed.
+    pub fn set_edge_groups(&mut self, group_id: Vec<i32>) -> Result<(), SolveError> {
+        if group_id.len() != self.graph.num_edges() {
+            return Err(SolveError {
+                code: COMPASS_ERR_INVALID_ARGUMENT,
+                message: format!(
+                    "expected {} group ids, got {}",
+                    self.graph.num_edges(),
+                    group_id.len()
+                ),
+            });
+        }
+        if self.group_id != group_id {
+            self.group_id = group_id;
+            if self.transforms.is_some() {
+                self.refill();
+            }
+        }
+        Ok(())
+    }
+
+    /// Residual statistics per edge group at the current [`GraphContext::coordinates`], see
+    /// [`Solution::group_reports`], with the observations corrected by the transform of
+    /// their group, given in [`GroupReport::transform`]. Disabled edges are left out.
+    pub fn group_reports(&self) -> Vec<GroupReport> {
+        let group_id: Vec<i32> = (0..self.graph.num_edges())
+            .map(|e| self.group_id.get(e).copied().unwrap_or(0))
+            .collect();
+        let (x, y) = self.coordinates();
+        let graph = self.observed_graph();
+        let mut reports = group_reports(x, y, &graph, &group_id, &|e| self.is_edge_enabled(e));
+        if let Some(transforms) = &self.transforms {
+            for report in &mut reports {
+                report.transform = transforms.matrix_of(report.group);
+            }
+        }
+        reports
+    }
+
+    /// Approximate solve for interactive preview while `vertex` is dragged to `(x, y)`: a
+    /// few Gauss-Seidel / SOR sweeps over the cached normal equations, starting from the
+    /// current [`GraphContext::coordinates`].
+    ///
+    /// A fixed `vertex` is moved in the graph itself, as the anchor is being relocated. A
+    /// free one is pinned at `(x, y)` in the stored solution, and a later [`graph_solve`]
+    /// starts from there.
+    pub fn preview(
+        &mut self,
+        vertex: usize,
+        x: f64,
+        y: f64,
+        options: &PreviewOptions,
+    ) -> Result<&Solution, SolveError> {
+        if vertex >= self.graph.num_vertices() {
+            return Err(SolveError {
+                code: COMPASS_ERR_INVALID_ARGUMENT,
+                message: format!("vertex {vertex} out of range"),
+            });
+        }
+        if !(options.omega > 0.0 && options.omega < 2.0) {
+            return Err(SolveError {
+                code: COMPASS_ERR_INVALID_ARGUMENT,
+                message: format!("relaxation factor {} outside (0, 2)", options.omega),
+            });
+        }
+        if self.system.is_none() {
+            self.system = Some(self.assemble()?);
+        }
+        let ties = self.ties(vertex);
+        let (x0, y0) = self.coordinates();
+        let (mut xs, mut ys) = (x0.to_vec(), y0.to_vec());
+        let Some(system) = &mut self.system else {
+            unreachable!("assembled above");
+        };
+
+        // Free vertices among them, one hop away from the dragged one.
+        let neighbors: Vec<(usize, f64)> = ties
+            .iter()
+            .filter_map(|&(other, w)| system.mapping[other].map(|row| (row, w)))
+            .collect();
+        let pinned = system.mapping[vertex];
+        if self.graph.fixed[vertex] {
+            // The anchor enters the right-hand sides of its free neighbors as `w * x_fixed`.
+            let (shift_x, shift_y) = (x - self.graph.x[vertex], y - self.graph.y[vertex]);
+            for &(row, w) in &neighbors {
+                system.bx[row] += w * shift_x;
+                system.by[row] += w * shift_y;
+            }
+            self.graph.x[vertex] = x;
+            self.graph.y[vertex] = y;
+        }
+        xs[vertex] = x;
+        ys[vertex] = y;
+
+        let rows = options.radius.map(|radius| match pinned {
+            Some(row) => system.rows_near(&[row], radius),
+            None if radius == 0 => Vec::new(),
+            None => {
+                let seeds: Vec<usize> = neighbors.iter().map(|&(row, _)| row).collect();
+                system.rows_near(&seeds, radius - 1)
+            }
+        });
+        system.sweep(
+            &mut xs,
+            &mut ys,
+            rows.as_deref(),
+            pinned,
+            options.sweeps,
+            options.omega,
+        );
+        let trivial = self.graph.trivial(&self.edge_enabled);
+        let solution = Solution {
+            x: xs,
+            y: ys,
+            stats: SolveStats {
+                free_vertices: system.size() as c_int,
+                disabled_edges: self.edge_enabled.iter().filter(|&&e| !e).count() as c_int,
+                trivial: trivial.flags(),
+                ..Default::default()
+            },
+            trivial,
+            coordinate_system: self.graph.coordinate_system,
+            warnings: Vec::new(),
+        };
+        Ok(self.store(solution))
+    }
+
+    /// Vertices `vertex` is directly tied to by the enabled edges, one entry per edge, with
+    /// the weights of the normal equations: class multipliers and locked loops applied.
+    fn ties(&self, vertex: usize) -> Vec<(usize, f64)> {
+        let adjusted = self.adjusted_graph();
+        (0..self.graph.num_edges())
+            .filter(|&e| self.is_edge_enabled(e))
+            .filter_map(|e| {
+                let (u, v) = (self.graph.from[e], self.graph.to[e]);
+                match (u == vertex, v == vertex) {
+                    (true, false) => Some((v, adjusted.weight[e])),
+                    (false, true) => Some((u, adjusted.weight[e])),
+                    _ => None,
+                }
+            })
+            .collect()
+    }
+
+    /// Structure of the normal equations of the enabled edges, assembled on first use and
+    /// cached for the next solve.
+    pub fn matrix_stats(&mut self) -> Result<MatrixStats, SolveError> {
+        if self.system.is_none() {
+            self.system = Some(self.assemble()?);
+        }
+        let Some(system) = &self.system else {
+            unreachable!("assembled above");
+        };
+        Ok(system.matrix_stats())
+    }
+
+    /// The graph as adjusted: observations corrected by the transforms, edge weights scaled
+    /// by their class multipliers, and the observations of the locked loops.
+    fn adjusted_graph(&self) -> Cow<'_, Graph> {
+        let observed = self.observed_graph();
+        if self.classes.is_none() && self.locked.is_empty() {
+            return observed;
+        }
+        let mut graph = observed.into_owned();
+        if let Some(classes) = &self.classes {
+            graph.weight = classes.scale(&graph.weight);
+        }
+        locks::replace_observations(&self.locked, &mut graph);
+        Cow::Owned(graph)
+    }
+
+    /// Assembles the normal equations of the enabled edges of the adjusted graph and of the
+    /// position observations.
+    fn assemble(&self) -> Result<NormalEquations, SolveError> {
+        let mut system = self.adjusted_graph().normal_equations(&self.edge_enabled)?;
+        positions::add_to(&mut system, &self.positions);
+        Ok(system)
+    }
+
+    /// Refills the cached normal equations, if any, after a change of the edge weights or
+    /// enabled flags that leaves the topology alone. They are dropped, to be assembled by the
+    /// next solve, when the refill does not fit their pattern.
+    fn refill(&mut self) {
+        if let Some(mut system) = self.system.take()
+            && self
+                .adjusted_graph()
+                .refill_normal_equations(&mut system, &self.edge_enabled)
+        {
+            positions::add_to(&mut system, &self.positions);
+            self.system = Some(system);
+        }
+    }
+
+    /// Solves the graph, assembling the normal equations on first use, and stores the
+    /// solution. Free vertices start from the current [`GraphContext::coordinates`], so
+    /// repeated solves are warm-started, and `centroid` is held at its value there.
+    fn solve(
+        &mut self,
+        iterations: usize,
+        tolerance: Tolerance,
+        method: (LinearSolver, Preconditioner),
+        centroid: Option<&centroid::Centroid>,
+        cancelled: &(dyn Fn() -> bool + Sync),
+    ) -> Result<&Solution, SolveError> {
+        let clock = Stopwatch::start();
+        let mut assemble_ms = 0.0;
+        if self.system.is_none() {
+            self.system = Some(self.assemble()?);
+            assemble_ms = clock.ms();
+        }
+        let Some(system) = &self.system else {
+            unreachable!("assembled above");
+        };
+        let (x0, y0) = self.coordinates();
+        let n = self.graph.num_vertices();
+        let hold = centroid
+            .map(|c| {
+                centroid::prepare(
+                    system,
+                    c,
+                    n,
+                    (x0, y0),
+                    method,
+                    iterations,
+                    tolerance,
+                    cancelled,
+                )
+            })
+            .transpose()?;
+        let mut solution = self
+            .graph
+            .solve_system(system, x0, y0, iterations, tolerance, method, cancelled)?;
+        if let Some(hold) = hold {
+            hold.apply(
+                system,
+                &mut solution.x,
+                &mut solution.y,
+                &mut solution.stats,
+            );
+        }
+        solution.stats.disabled_edges = self.edge_enabled.iter().filter(|&&e| !e).count() as c_int;
+        self.record_locks(&mut solution);
+        solution.trivial = self.graph.trivial(&self.edge_enabled);
+        solution.stats.trivial = solution.trivial.flags();
+        solution.stats.assemble_ms = assemble_ms;
+        solution.stats.total_ms = clock.ms();
+        Ok(self.store(solution))
+    }
+}
+
+/// Releases a handle returned by one of the `graph_from_*` constructors. Null is ignored.
+#[unsafe(no_mangle)]
+pub extern "C" fn graph_free(handle: *mut GraphContext) {
+    if !handle.is_null() {
+        // Safety: The handle was created by `GraphContext::into_raw` and is released once.
+        drop(unsafe { Box::from_raw(handle) });
+    }
+}
+
+/// Returns the number of vertices of the graph behind `handle`, or -1 for a null handle.
+#[unsafe(no_mangle)]
+pub extern "C" fn graph_num_vertices(handle: *const GraphContext) -> c_int {
+    match unsafe { handle.as_ref() } {
+        Some(ctx) => ctx.graph.num_vertices() as c_int,
+        None => -1,
+    }
+}
+
+/// Returns the number of edges of the graph behind `handle`, or -1 for a null handle.
+#[unsafe(no_mangle)]
+pub extern "C" fn graph_num_edges(handle: *const GraphContext) -> c_int {
+    match unsafe { handle.as_ref() } {
+        Some(ctx) => ctx.graph.num_edges() as c_int,
+        None => -1,
+    }
+}
+
+/// Returns the [`Graph::content_hash`] of the graph behind `handle`, or 0 for a null handle.
+#[unsafe(no_mangle)]
+pub extern "C" fn graph_content_hash(handle: *const GraphContext) -> u64 {
+    match unsafe { handle.as_ref() } {
+        Some(ctx) => ctx.graph.content_hash(),
+        None => 0,
+    }
+}
+
+/// The `len` elements at `ptr`, which may be null when `len` is 0.
+///
+/// # Safety
+///
+/// Same as [`slice::from_raw_parts`] when `len` is not 0.
+unsafe fn raw_slice<'a, T>(ptr: *const T, len: usize) -> &'a [T] {
+    match len {
+        0 => &[],
+        len => unsafe { slice::from_raw_parts(ptr, len) },
+    }
+}
+
+/// Mutable counterpart of [`raw_slice`].
+///
+/// # Safety
+///
+/// Same as [`slice::from_raw_parts_mut`] when `len` is not 0.
+unsafe fn raw_slice_mut<'a, T>(ptr: *mut T, len: usize) -> &'a mut [T] {
+    match len {
+        0 => &mut [],
+        len => unsafe { slice::from_raw_parts_mut(ptr, len) },
+    }
+}
+
+/// A vector of `len` copies of `value`, or [`COMPASS_ERR_OUT_OF_MEMORY`] if it cannot be
+/// allocated, for buffers sized by the caller's counts.
+fn try_filled<T: Clone>(len: usize, value: T) -> Result<Vec<T>, c_int> {
+    let mut buffer = Vec::new();
+    buffer
+        .try_reserve_exact(len)
+        .map_err(|_| COMPASS_ERR_OUT_OF_MEMORY)?;
+    buffer.resize(len, value);
+    Ok(buffer)
+}
+
+/// Number of COO triplets reserved for `num_edges` edges, four each, or
+/// [`COMPASS_ERR_OUT_OF_MEMORY`] where that count does not fit a `usize`.
+fn coo_terms(num_edges: usize) -> Result<usize, c_int> {
+    num_edges.checked_mul(4).ok_or(COMPASS_ERR_OUT_OF_MEMORY)
+}
+
+/// A copy of `values` allocated as by [`try_filled`].
+fn try_copied<T: Clone>(values: &[T]) -> Result<Vec<T>, c_int> {
+    let mut buffer = Vec::new();
+    buffer
+        .try_reserve_exact(values.len())
+        .map_err(|_| COMPASS_ERR_OUT_OF_MEMORY)?;
+    buffer.extend_from_slice(values);
+    Ok(buffer)
+}
+
+/// Index of vertex `v` in a graph of `num_vertices` vertices, `None` when out of range.
+fn vertex_index<T: TryInto<usize>>(v: T, num_vertices: usize) -> Option<usize> {
+    v.try_into().ok().filter(|&v| v < num_vertices)
+}
+
+/// Checks that `solution` and the vertex arrays of `graph` have one value per vertex, that
+/// the edge arrays have one per edge, and that every edge has its endpoints among the
+/// vertices, before an exporter indexes them. The error is [`COMPASS_ERR_INVALID_ARGUMENT`].
+#[cfg(any(
+    feature = "compass_io",
+    feature = "io-dxf",
+    feature = "io-geojson",
+    feature = "io-survex"
+))]
+pub(crate) fn check_export(solution: &Solution, graph: &Graph) -> Result<(), SolveError> {
+    let invalid = |message: String| SolveError {
+        code: COMPASS_ERR_INVALID_ARGUMENT,
+        message,
+    };
+    let (n, m) = (graph.num_vertices(), graph.num_edges());
+    if solution.x.len() != n || solution.y.len() != n {
+        return Err(invalid(format!(
+            "solution has {} / {} coordinates for {n} vertices",
+            solution.x.len(),
+            solution.y.len()
+        )));
+    }
+    let mut vertex_arrays = vec![
+        ("y coordinates", graph.y.len()),
+        ("heights", graph.z.len()),
+        ("fixed flags", graph.fixed.len()),
+    ];
+    if let Some(names) = &graph.names {
+        vertex_arrays.push(("names", names.len()));
+    }
+    for (what, len) in vertex_arrays {
+        if len != n {
+            return Err(invalid(format!("graph has {len} {what} for {n} vertices")));
+        }
+    }
+    let edge_arrays = [
+        ("edge ends", graph.to.len()),
+        ("dx observations", graph.dx.len()),
+        ("dy observations", graph.dy.len()),
+        ("weights", graph.weight.len()),
+    ];
+    for (what, len) in edge_arrays {
+        if len != m {
+            return Err(invalid(format!("graph has {len} {what} for {m} edges")));
+        }
+    }
+    check_edges(n, &graph.from, &graph.to)
+        .map_err(|e| invalid(format!("edge {e} has an endpoint out of range")))
+}
+
+/// A [`SolveError`] of an export as an I/O error, for the `write_*` methods of the exporters.
+#[cfg(any(
+    feature = "compass_io",
+    feature = "io-dxf",
+    feature = "io-geojson",
+    feature = "io-survex"
+))]
+pub(crate) fn invalid_input(err: SolveError) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidInput, err)
+}
+
+/// Checks the endpoints of the edges `from` / `to` against a graph of `num_vertices`
+/// vertices, returning the first edge with an endpoint out of range.
+fn check_edges<T: Copy + TryInto<usize>>(
+    num_vertices: usize,
+    from: &[T],
+    to: &[T],
+) -> Result<(), usize> {
+    profile_span!("validate", vertices = num_vertices, edges = from.len());
+    let valid = |v: T| vertex_index(v, num_vertices).is_some();
+    match (0..from.len()).find(|&e| !valid(from[e]) || !valid(to[e])) {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+/// Endpoints of edge `e`, checked by [`check_edges`].
+fn checked_ends<T: Copy + TryInto<usize>>(
+    num_vertices: usize,
+    from: &[T],
+    to: &[T],
+    e: usize,
+) -> (usize, usize) {
+    let (Some(u), Some(v)) = (
+        vertex_index(from[e], num_vertices),
+        vertex_index(to[e], num_vertices),
+    ) else {
+        unreachable!("edges are checked first");
+    };
+    (u, v)
+}
+
+/// Writes the coordinate difference implied by `x` / `y` for each edge into `out_dx` /
+/// `out_dy`, see [`Graph::edge_deltas`]. Edges are independent, so the edge arrays may be
+/// split across threads.
+///
+/// Returns the first edge with an endpoint outside `x` before writing anything.
+fn edge_deltas<T: Copy + TryInto<usize>>(
+    x: &[f64],
+    y: &[f64],
+    from: &[T],
+    to: &[T],
+    out_dx: &mut [f64],
+    out_dy: &mut [f64],
+) -> Result<(), usize> {
+    check_edges(x.len(), from, to)?;
+    for (e, (dx, dy)) in out_dx.iter_mut().zip(out_dy.iter_mut()).enumerate() {
+        let (u, v) = checked_ends(x.len(), from, to, e);
+        *dx = x[v] - x[u];
+        *dy = y[v] - y[u];
+    }
+    Ok(())
+}
+
+/// Misfit of the observations `dx` / `dy` at the coordinates `x` / `y` over the edges for
+/// which `is_enabled` is true, see [`MisfitSummary`]. Returns the first edge with an endpoint
+/// outside `x`.
+fn misfit_summary<T: Copy + TryInto<usize>>(
+    (x, y): (&[f64], &[f64]),
+    (from, to): (&[T], &[T]),
+    (dx, dy): (&[f64], &[f64]),
+    weight: &[f64],
+    is_enabled: &dyn Fn(usize) -> bool,
+) -> Result<MisfitSummary, usize> {
+    check_edges(x.len(), from, to)?;
+    let mut summary = MisfitSummary {
+        max_edge: -1,
+        ..Default::default()
+    };
+    let (mut total, mut square_x, mut square_y) = (0.0, 0.0, 0.0);
+    for e in (0..from.len()).filter(|&e| is_enabled(e)) {
+        let (u, v) = checked_ends(x.len(), from, to, e);
+        let (rx, ry) = (x[v] - x[u] - dx[e], y[v] - y[u] - dy[e]);
+        summary.edges += 1;
+        total += weight[e];
+        square_x += weight[e] * rx * rx;
+        square_y += weight[e] * ry * ry;
+        if summary.max_edge < 0 || rx.hypot(ry) > summary.max_misfit {
+            summary.max_misfit = rx.hypot(ry);
+            summary.max_edge = e as c_int;
+        }
+        summary.max_misfit_x = summary.max_misfit_x.max(rx.abs());
+        summary.max_misfit_y = summary.max_misfit_y.max(ry.abs());
+    }
+    if total > 0.0 {
+        summary.weighted_rms = ((square_x + square_y) / total).sqrt();
+        summary.weighted_rms_x = (square_x / total).sqrt();
+        summary.weighted_rms_y = (square_y / total).sqrt();
+    }
+    Ok(summary)
+}
+
+/// Computes the coordinate difference the coordinates `x` / `y` imply for each edge, without
+/// solving, for comparison with the observations before the adjustment. See
+/// [`Graph::edge_deltas`]; [`graph_get_residuals`] covers the comparison after it.
+///
+/// # Arguments
+///
+/// * `num_vertices`, `x`, `y`, `num_edges`, `from`, `to` - As for
+///   [`solve_graph_least_squares`].
+/// * `out_dx` / `out_dy` - Receive the `num_edges` differences along X / Y.
+///
+/// # Returns
+///
+/// * [`COMPASS_OK`] on success.
+/// * [`COMPASS_ERR_INVALID_ARGUMENT`] for a negative count, a null pointer or an edge whose
+///   endpoint is out of range; nothing is written.
+#[unsafe(no_mangle)]
+#[allow(clippy::too_many_arguments)]
+pub extern "C" fn compute_edge_deltas(
+    num_vertices: c_int,
+    x: *const c_double,
+    y: *const c_double,
+    num_edges: c_int,
+    from: *const c_int,
+    to: *const c_int,
+    out_dx: *mut c_double,
+    out_dy: *mut c_double,
+) -> c_int {
+    if num_vertices < 0 || num_edges < 0 {
+        return COMPASS_ERR_INVALID_ARGUMENT;
+    }
+    let (n_verts, n_edges) = (num_vertices as usize, num_edges as usize);
+    if (n_verts > 0 && (x.is_null() || y.is_null()))
+        || (n_edges > 0 && (from.is_null() || to.is_null() || out_dx.is_null() || out_dy.is_null()))
+    {
+        return COMPASS_ERR_INVALID_ARGUMENT;
+    }
+    if n_edges == 0 {
+        return COMPASS_OK;
+    }
+    let result = std::panic::catch_unwind(|| {
+        // Safety: The caller guarantees arrays of `num_vertices` / `num_edges` elements, and
+        // the pointers were checked above.
+        let (x, y) = unsafe { (raw_slice(x, n_verts), raw_slice(y, n_verts)) };
+        let (from, to) = unsafe { (raw_slice(from, n_edges), raw_slice(to, n_edges)) };
+        let out_dx = unsafe { slice::from_raw_parts_mut(out_dx, n_edges) };
+        let out_dy = unsafe { slice::from_raw_parts_mut(out_dy, n_edges) };
+        match edge_deltas(x, y, from, to, out_dx, out_dy) {
+            Ok(()) => COMPASS_OK,
+            Err(_) => COMPASS_ERR_INVALID_ARGUMENT,
+        }
+    });
+
+    result.unwrap_or_else(|_| {
+        eprintln!("Panic caught in compute_edge_deltas");
+        COMPASS_ERR_PANIC
+    })
+}
+
+/// Fills `out_summary` with the misfit of the observations at the coordinates `x` / `y`,
+/// see [`MisfitSummary`], without solving: before the adjustment with the input coordinates,
+/// after it with the adjusted ones.
+///
+/// # Arguments
+///
+/// * `num_vertices` ... `weight` - As for [`solve_graph_least_squares`].
+/// * `edge_enabled` - Per-edge enabled flags as in [`SolveOptions::edge_enabled`], or null.
+///   Disabled edges are skipped. Edges between two fixed vertices are included, unlike in
+///   the solve.
+///
+/// # Returns
+///
+/// * [`COMPASS_OK`] on success.
+/// * [`COMPASS_ERR_INVALID_ARGUMENT`] for a negative count, a null pointer or an edge whose
+///   endpoint is out of range; `out_summary` is left untouched.
+#[unsafe(no_mangle)]
+#[allow(clippy::too_many_arguments)]
+pub extern "C" fn compute_misfit_summary(
+    num_vertices: c_int,
+    x: *const c_double,
+    y: *const c_double,
+    num_edges: c_int,
+    from: *const c_int,
+    to: *const c_int,
+    observed_dx: *const c_double,
+    observed_dy: *const c_double,
+    weight: *const c_double,
+    edge_enabled: *const c_int,
+    out_summary: *mut MisfitSummary,
+) -> c_int {
+    if num_vertices < 0 || num_edges < 0 || out_summary.is_null() {
+        return COMPASS_ERR_INVALID_ARGUMENT;
+    }
+    let (n_verts, n_edges) = (num_vertices as usize, num_edges as usize);
+    let observations = [observed_dx, observed_dy, weight];
+    if (n_verts > 0 && (x.is_null() || y.is_null()))
+        || (n_edges > 0
+            && (from.is_null() || to.is_null() || observations.iter().any(|p| p.is_null())))
+    {
+        return COMPASS_ERR_INVALID_ARGUMENT;
+    }
+    let result = std::panic::catch_unwind(|| {
+        // Safety: The caller guarantees arrays of `num_vertices` / `num_edges` elements, and
+        // the pointers were checked above when the lengths are not zero.
+        let (x, y) = unsafe { (raw_slice(x, n_verts), raw_slice(y, n_verts)) };
+        let (from, to) = unsafe { (raw_slice(from, n_edges), raw_slice(to, n_edges)) };
+        let [dx, dy, weight] = observations.map(|p| unsafe { raw_slice(p, n_edges) });
+        let enabled = match edge_enabled.is_null() {
+            true => None,
+            false => Some(unsafe { raw_slice(edge_enabled, n_edges) }),
+        };
+        let summary = misfit_summary((x, y), (from, to), (dx, dy), weight, &|e| {
+            enabled.is_none_or(|enabled| enabled[e] != 0)
+        });
+        match summary {
+            Ok(summary) => {
+                unsafe { *out_summary = summary };
+                COMPASS_OK
+            }
+            Err(_) => COMPASS_ERR_INVALID_ARGUMENT,
+        }
+    });
+
+    result.unwrap_or_else(|_| {
+        eprintln!("Panic caught in compute_misfit_summary");
+        COMPASS_ERR_PANIC
+    })
+}
+
+/// Same as [`graph_content_hash`] over the raw arrays of [`solve_graph_least_squares`], so
+/// that callers can check a cache without building a handle. The arrays may be null when
+/// their count is 0. Returns 0 for a negative count, a null array of a nonzero count or on a
+/// panic.
+#[unsafe(no_mangle)]
+pub extern "C" fn graph_content_hash_arrays(
+    num_vertices: c_int,
+    x: *const c_double,
+    y: *const c_double,
+    fixed: *const c_int,
+    num_edges: c_int,
+    from: *const c_int,
+    to: *const c_int,
+    observed_dx: *const c_double,
+    observed_dy: *const c_double,
+    weight: *const c_double,
+) -> u64 {
+    let (Ok(n_verts), Ok(n_edges)) = (usize::try_from(num_vertices), usize::try_from(num_edges))
+    else {
+        return 0;
+    };
+    let null_vertices = x.is_null() || y.is_null() || fixed.is_null();
+    let null_edges = from.is_null()
+        || to.is_null()
+        || observed_dx.is_null()
+        || observed_dy.is_null()
+        || weight.is_null();
+    if (n_verts > 0 && null_vertices) || (n_edges > 0 && null_edges) {
+        return 0;
+    }
+    let result = std::panic::catch_unwind(|| {
+        // Safety: Same contract as `solve_graph_least_squares`.
+        let x = unsafe { raw_slice(x, n_verts) };
+        let y = unsafe { raw_slice(y, n_verts) };
+        let fixed = unsafe { raw_slice(fixed, n_verts) };
+        let from = unsafe { raw_slice(from, n_edges) };
+        let to = unsafe { raw_slice(to, n_edges) };
+        let dx = unsafe { raw_slice(observed_dx, n_edges) };
+        let dy = unsafe { raw_slice(observed_dy, n_edges) };
+        let weight = unsafe { raw_slice(weight, n_edges) };
+
+        content_hash(
+            x,
+            y,
+            |i| fixed[i] != 0,
+            (0..n_edges).map(|e| (from[e] as u64, to[e] as u64, dx[e], dy[e], weight[e])),
+        )
+    });
+
+    result.unwrap_or_else(|_| {
+        eprintln!("Panic caught in graph_content_hash_arrays");
+        0
+    })
+}
+
+/// Solves the graph behind `handle` and stores the solution in the handle. The graph
+/// coordinates are left untouched so that displacements can be reported against them.
+///
+/// The normal equations are assembled on the first call and reused afterwards; later solves
+/// start from the previous solution.
+///
+/// Only `iterations`, `tolerance`, their per-axis fields, `breakdown_tolerance`, `solver`,
+/// `preconditioner`, `time_budget`, `preset`, `error_on_trivial`, `error_message`, `cancel`,
+/// the centroid, the class and the transform fields of `options` are used; `stats` may be
+/// null. The centroid is held at its value at the start of the solve, the previous solution
+/// if any. A change of the classes, their multipliers or the observation transforms since the
+/// last solve only refills the cached normal equations. A cancelled solve keeps the previous
+/// solution, if any. Returns [`COMPASS_ERR_INVALID_ARGUMENT`] if `handle` or `options` is null,
+/// or if `options` is older than the first release of the struct, names an unknown solver,
+/// preconditioner or preset, holds a negative per-axis limit, invalid classes or invalid
+/// transforms.
+#[unsafe(no_mangle)]
+pub extern "C" fn graph_solve(
+    handle: *mut GraphContext,
+    options: *const SolveOptions,
+    stats: *mut SolveStats,
+) -> c_int {
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        // Safety: We assume the caller guarantees a valid (or null) handle.
+        let Some(ctx) = (unsafe { handle.as_mut() }) else {
+            return COMPASS_ERR_INVALID_ARGUMENT;
+        };
+        let Some(options) = read_options(options) else {
+            return COMPASS_ERR_INVALID_ARGUMENT;
+        };
+        let cancelled = cancel_flag(&options);
+        let iterations = options.iterations.max(0) as usize;
+        if let Err(message) = axis_limits(&options) {
+            let capacity = options.error_capacity.max(0) as usize;
+            write_message(options.error_message, capacity, &message);
+            return COMPASS_ERR_INVALID_ARGUMENT;
+        }
+        let (Some(method), Some(tolerance)) = (method(&options), tolerance(&options)) else {
+            return COMPASS_ERR_INVALID_ARGUMENT;
+        };
+        let trivial = ctx.graph.trivial(&ctx.edge_enabled);
+        if options.error_on_trivial != 0
+            && let Some((code, message)) = trivial.error()
+        {
+            let capacity = options.error_capacity.max(0) as usize;
+            write_message(options.error_message, capacity, message);
+            return code;
+        }
+        let classes = classes::from_options(&options, ctx.graph.num_edges());
+        if let Err(err) = ctx.set_edge_classes(classes) {
+            return err.code;
+        }
+        let transforms = transforms::from_options(&options);
+        if let Err(err) = ctx.set_observation_transforms(transforms) {
+            return err.code;
+        }
+        let centroid = centroid::from_options(&options, ctx.graph.num_vertices());
+        let centroid = centroid.as_ref();
+        match ctx.solve(iterations, tolerance, method, centroid, &cancelled) {
+            Ok(solution) => {
+                let mut solved = solution.stats;
+                options.report(&mut solved);
+                write_stats(stats, &solved);
+                COMPASS_OK
+            }
+            Err(err) => err.code,
+        }
+    }));
+
+    match result {
+        Ok(code) => code,
+        Err(_) => {
+            eprintln!("Panic caught in graph_solve");
+            COMPASS_ERR_PANIC
+        }
+    }
+}
+
+/// Fills `out` with the structure of the normal equations of the graph behind `handle`, see
+/// [`GraphContext::matrix_stats`]. Returns [`COMPASS_ERR_INVALID_ARGUMENT`] for a null handle
+/// or output, and [`COMPASS_ERR_OUT_OF_MEMORY`] when the normal equations cannot be allocated.
+#[unsafe(no_mangle)]
+pub extern "C" fn graph_matrix_stats(handle: *mut GraphContext, out: *mut MatrixStats) -> c_int {
+    let (Some(ctx), false) = ((unsafe { handle.as_mut() }), out.is_null()) else {
+        return COMPASS_ERR_INVALID_ARGUMENT;
+    };
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        match ctx.matrix_stats() {
+            Ok(stats) => {
+                // Safety: The caller guarantees a writable `out`.
+                unsafe { *out = stats };
+                COMPASS_OK
+            }
+            Err(err) => err.code,
+        }
+    }));
+
+    result.unwrap_or_else(|_| {
+        eprintln!("Panic caught in graph_matrix_stats");
+        COMPASS_ERR_PANIC
+    })
+}
+
+/// Quick approximate solve of the graph behind `handle` while `vertex` is dragged to
+/// `(x, y)`, see [`GraphContext::preview`]. Read the result with [`graph_get_coordinates`].
+///
+/// # Arguments
+///
+/// * `sweeps` - Number of Gauss-Seidel / SOR sweeps.
+/// * `omega` - Relaxation factor in `(0, 2)`: 1 = Gauss-Seidel, above 1 = SOR.
+/// * `radius` - Only vertices within this many edges of `vertex` move. Negative = all.
+#[unsafe(no_mangle)]
+pub extern "C" fn graph_preview(
+    handle: *mut GraphContext,
+    vertex: c_int,
+    x: c_double,
+    y: c_double,
+    sweeps: c_int,
+    omega: c_double,
+    radius: c_int,
+) -> c_int {
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        // Safety: We assume the caller guarantees a valid (or null) handle.
+        let Some(ctx) = (unsafe { handle.as_mut() }) else {
+            return COMPASS_ERR_INVALID_ARGUMENT;
+        };
+        if vertex < 0 {
+            return COMPASS_ERR_INVALID_ARGUMENT;
+        }
+        let options = PreviewOptions {
+            sweeps: sweeps.max(0) as usize,
+            omega,
+            radius: usize::try_from(radius).ok(),
+        };
+        match ctx.preview(vertex as usize, x, y, &options) {
+            Ok(_) => COMPASS_OK,
+            Err(err) => err.code,
+        }
+    }));
+
+    match result {
+        Ok(code) => code,
+        Err(_) => {
+            eprintln!("Panic caught in graph_preview");
+            COMPASS_ERR_PANIC
+        }
+    }
+}
+
+/// Copies the coordinates of the graph behind `handle` into caller buffers of length
+/// [`graph_num_vertices`]: the adjusted X/Y after a successful [`graph_solve`], the input
+/// coordinates before. Any of the output pointers may be null to skip that axis.
+#[unsafe(no_mangle)]
+pub extern "C" fn graph_get_coordinates(
+    handle: *const GraphContext,
+    out_x: *mut c_double,
+    out_y: *mut c_double,
+    out_z: *mut c_double,
+) -> c_int {
+    let Some(ctx) = (unsafe { handle.as_ref() }) else {
+        return COMPASS_ERR_INVALID_ARGUMENT;
+    };
+    let (x, y) = ctx.coordinates();
+    for (src, dst) in [(x, out_x), (y, out_y), (&ctx.graph.z[..], out_z)] {
+        if !dst.is_null() {
+            // Safety: The caller guarantees buffers of `num_vertices` elements.
+            unsafe { slice::from_raw_parts_mut(dst, src.len()) }.copy_from_slice(src);
+        }
+    }
+    COMPASS_OK
+}
+
+/// Copies a consistent snapshot of the X/Y coordinates of the graph behind `handle`, those of
+/// [`graph_get_coordinates`], into caller buffers of length [`graph_num_vertices`], and their
+/// generation into `out_generation`. Any of the output pointers may be null to skip it.
+///
+/// Unlike the other functions taking a handle, this one may be called from another thread
+/// while a solve or a preview runs on the handle: their coordinates are published in one pass
+/// under a lock once they are complete, so a snapshot never mixes two of them. The generation
+/// grows by one with every publication, so comparing it with that of the previous snapshot
+/// tells whether the coordinates changed. Returns [`COMPASS_ERR_INVALID_ARGUMENT`] for a null
+/// handle.
+#[unsafe(no_mangle)]
+pub extern "C" fn graph_get_coordinates_snapshot(
+    handle: *const GraphContext,
+    out_x: *mut c_double,
+    out_y: *mut c_double,
+    out_generation: *mut u64,
+) -> c_int {
+    if handle.is_null() {
+        return COMPASS_ERR_INVALID_ARGUMENT;
+    }
+    // Only the published buffer is borrowed, not the context, which a solve on another thread
+    // may be mutating.
+    // Safety: The caller guarantees a valid handle; the field is never reassigned.
+    let published = unsafe { &*std::ptr::addr_of!((*handle).published) };
+    let published = published.lock().unwrap_or_else(|e| e.into_inner());
+    for (src, dst) in [(&published.x, out_x), (&published.y, out_y)] {
+        if !dst.is_null() {
+            // Safety: The caller guarantees buffers of `num_vertices` elements.
+            unsafe { slice::from_raw_parts_mut(dst, src.len()) }.copy_from_slice(src);
+        }
+    }
+    if !out_generation.is_null() {
+        unsafe { *out_generation = published.generation };
+    }
+    COMPASS_OK
+}
+
+/// Copies the residual `(rx, ry)` of every edge at the current
+/// [`GraphContext::coordinates`] into caller buffers of length [`graph_num_edges`], see
+/// [`Solution::residuals`], of the observations corrected by the transforms of the last
+/// [`graph_solve`]. Disabled edges are included, for display. Either output pointer may be
+/// null to skip that axis.
+#[unsafe(no_mangle)]
+pub extern "C" fn graph_get_residuals(
+    handle: *const GraphContext,
+    out_dx: *mut c_double,
+    out_dy: *mut c_double,
+) -> c_int {
+    let Some(ctx) = (unsafe { handle.as_ref() }) else {
+        return COMPASS_ERR_INVALID_ARGUMENT;
+    };
+    let graph = ctx.observed_graph();
+    let (x, y) = ctx.coordinates();
+    for (coords, observed, dst) in [(x, &graph.dx, out_dx), (y, &graph.dy, out_dy)] {
+        if !dst.is_null() {
+            // Safety: The caller guarantees buffers of `num_edges` elements.
+            let out = unsafe { slice::from_raw_parts_mut(dst, graph.num_edges()) };
+            for (e, r) in out.iter_mut().enumerate() {
+                *r = coords[graph.to[e]] - coords[graph.from[e]] - observed[e];
+            }
+        }
+    }
+    COMPASS_OK
+}
+
+/// Tags each edge of the graph behind `handle` with a group id, e.g. its survey trip, for
+/// [`graph_group_reports`]. `group_id` holds [`graph_num_edges`] ids, which need not be
+/// contiguous; null puts every edge back into group 0.
+#[unsafe(no_mangle)]
+pub extern "C" fn graph_set_edge_groups(
+    handle: *mut GraphContext,
+    group_id: *const c_int,
+) -> c_int {
+    let Some(ctx) = (unsafe { handle.as_mut() }) else {
+        return COMPASS_ERR_INVALID_ARGUMENT;
+    };
+    let group_id = if group_id.is_null() {
+        vec![0; ctx.graph.num_edges()]
+    } else {
+        // Safety: The caller guarantees `num_edges` group ids.
+        unsafe { slice::from_raw_parts(group_id, ctx.graph.num_edges()) }.to_vec()
+    };
+    match ctx.set_edge_groups(group_id) {
+        Ok(()) => COMPASS_OK,
+        Err(err) => err.code,
+    }
+}
+
+/// Returns the number of distinct groups among the enabled edges of the graph behind
+/// `handle`, i.e. the buffer length needed by [`graph_group_reports`].
+#[unsafe(no_mangle)]
+pub extern "C" fn graph_group_count(handle: *const GraphContext) -> c_int {
+    match unsafe { handle.as_ref() } {
+        Some(ctx) => ctx.group_reports().len() as c_int,
+        None => COMPASS_ERR_INVALID_ARGUMENT,
+    }
+}
+
+/// Copies the residual statistics per edge group of the graph behind `handle` into parallel
+/// caller buffers of `capacity` elements, most suspicious group first, see
+/// [`GraphContext::group_reports`]. Any output pointer may be null to skip that column.
+///
+/// # Returns
+///
+/// * The number of groups written.
+/// * [`COMPASS_ERR_BUFFER_TOO_SMALL`] if `capacity` is below [`graph_group_count`]; nothing is
+///   written.
+/// * [`COMPASS_ERR_INVALID_ARGUMENT`] for a null handle.
+#[unsafe(no_mangle)]
+pub extern "C" fn graph_group_reports(
+    handle: *const GraphContext,
+    capacity: c_int,
+    out_group: *mut c_int,
+    out_edges: *mut c_int,
+    out_weighted_rms: *mut c_double,
+    out_max_standardized: *mut c_double,
+    out_suspicion: *mut c_double,
+) -> c_int {
+    let Some(ctx) = (unsafe { handle.as_ref() }) else {
+        return COMPASS_ERR_INVALID_ARGUMENT;
+    };
+    let reports = ctx.group_reports();
+    if (capacity.max(0) as usize) < reports.len() {
+        return COMPASS_ERR_BUFFER_TOO_SMALL;
+    }
+    write_column(out_group, &reports, |r| r.group);
+    write_column(out_edges, &reports, |r| r.edges as c_int);
+    write_column(out_weighted_rms, &reports, |r| r.weighted_rms);
+    write_column(out_max_standardized, &reports, |r| r.max_standardized);
+    write_column(out_suspicion, &reports, |r| r.suspicion);
+    reports.len() as c_int
+}
+
+/// Writes `value(row)` for each row into the caller buffer `dst`, unless it is null.
+fn write_column<R, T>(dst: *mut T, rows: &[R], value: impl Fn(&R) -> T) {
+    if !dst.is_null() {
+        // Safety: The caller guarantees a buffer of at least `rows.len()` elements.
+        let out = unsafe { slice::from_raw_parts_mut(dst, rows.len()) };
+        for (dst, row) in out.iter_mut().zip(rows) {
+            *dst = value(row);
+        }
+    }
+}
+
+/// Enables (`enabled` != 0) or disables edge `index` of the graph behind `handle` for the
+/// next [`graph_solve`], for what-if solves without rebuilding the graph. Disabled edges
+/// contribute nothing to the adjustment; the arrays and edge indices are unchanged.
+///
+/// The cached normal equations are refilled when the flag changes; the next solve starts
+/// from the current coordinates. Returns [`COMPASS_ERR_INVALID_ARGUMENT`] for a null handle
+/// or an index out of range.
+#[unsafe(no_mangle)]
+pub extern "C" fn graph_set_edge_enabled(
+    handle: *mut GraphContext,
+    index: c_int,
+    enabled: c_int,
+) -> c_int {
+    let Some(ctx) = (unsafe { handle.as_mut() }) else {
+        return COMPASS_ERR_INVALID_ARGUMENT;
+    };
+    if index < 0 {
+        return COMPASS_ERR_INVALID_ARGUMENT;
+    }
+    match ctx.set_edge_enabled(index as usize, enabled != 0) {
+        Ok(()) => COMPASS_OK,
+        Err(err) => err.code,
+    }
+}
+
+/// Creates a handle over `num_vertices` vertices and no edges, to be filled with
+/// [`graph_add_edges_streamed`]. Arguments are those of [`solve_graph_least_squares`]; the
+/// arrays are copied. Returns null for a negative count, more vertices than
+/// [`limits::MAX_VERTICES`], or on a panic.
+#[unsafe(no_mangle)]
+pub extern "C" fn graph_from_vertices(
+    num_vertices: c_int,
+    x: *const c_double,
+    y: *const c_double,
+    fixed: *const c_int,
+) -> *mut GraphContext {
+    let Ok(n_verts) = usize::try_from(num_vertices) else {
+        return std::ptr::null_mut();
+    };
+    if n_verts > limits::MAX_VERTICES {
+        return std::ptr::null_mut();
+    }
+    let result = std::panic::catch_unwind(|| {
+        // Safety: The caller guarantees valid pointers of `num_vertices` elements.
+        let x = unsafe { slice::from_raw_parts(x, n_verts) };
+        let y = unsafe { slice::from_raw_parts(y, n_verts) };
+        let fixed = unsafe { slice::from_raw_parts(fixed, n_verts) };
+
+        let mut graph = Graph::default();
+        for i in 0..n_verts {
+            graph.add_vertex(x[i], y[i], 0.0, fixed[i] != 0);
+        }
+        GraphContext::into_raw(graph)
+    });
+
+    result.unwrap_or_else(|_| {
+        eprintln!("Panic caught in graph_from_vertices");
+        std::ptr::null_mut()
+    })
+}
+
+/// Producer of [`graph_add_edges_streamed`]: fills the first `len` elements of each output
+/// buffer with edges `start .. start + len` and returns 0, or returns non-zero to abort.
+pub type EdgeProducer = extern "C" fn(
+    user_data: *mut c_void,
+    start: c_int,
+    len: c_int,
+    out_from: *mut c_int,
+    out_to: *mut c_int,
+    out_dx: *mut c_double,
+    out_dy: *mut c_double,
+    out_weight: *mut c_double,
+) -> c_int;
+
+/// Appends `count` edges to the graph behind `handle`, pulling them from `producer` in
+/// chunks of at most `chunk_size` edges written into buffers owned by the library, so the
+/// caller never has to materialize all edges at once. `user_data` is passed through.
+///
+/// Ingestion is all-or-nothing. If the producer returns non-zero the call returns
+/// [`COMPASS_ERR_CANCELLED`]; an edge referencing a missing vertex returns
+/// [`COMPASS_ERR_INVALID_ARGUMENT`]. Either way the edges of this call are discarded and the
+/// handle stays usable. Edges past [`limits::MAX_EDGES`] are refused up front with
+/// [`COMPASS_ERR_TOO_LARGE`]. On success the cached normal equations and solution are dropped.
+#[unsafe(no_mangle)]
+pub extern "C" fn graph_add_edges_streamed(
+    handle: *mut GraphContext,
+    count: c_int,
+    producer: Option<EdgeProducer>,
+    user_data: *mut c_void,
+    chunk_size: c_int,
+) -> c_int {
+    let (Some(ctx), Some(producer)) = (unsafe { handle.as_mut() }, producer) else {
+        return COMPASS_ERR_INVALID_ARGUMENT;
+    };
+    if count < 0 || chunk_size <= 0 {
+        return COMPASS_ERR_INVALID_ARGUMENT;
+    }
+
+    let first_edge = ctx.graph.num_edges();
+    if let Err(err) = Limits::CURRENT.check(0, first_edge + count as usize) {
+        return err.code;
+    }
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        let graph = &mut ctx.graph;
+        let n_verts = graph.num_vertices();
+        let chunk = chunk_size.min(count) as usize;
+        let (mut from, mut to) = (vec![0; chunk], vec![0; chunk]);
+        let (mut dx, mut dy, mut weight) = (vec![0.0; chunk], vec![0.0; chunk], vec![0.0; chunk]);
+
+        let mut start = 0;
+        let mut code = COMPASS_OK;
+        while start < count {
+            let len = (count - start).min(chunk_size);
+            let status = producer(
+                user_data,
+                start,
+                len,
+                from.as_mut_ptr(),
+                to.as_mut_ptr(),
+                dx.as_mut_ptr(),
+                dy.as_mut_ptr(),
+                weight.as_mut_ptr(),
+            );
+            if status != 0 {
+                code = COMPASS_ERR_CANCELLED;
+                break;
+            }
+            for e in 0..len as usize {
+                let (u, v) = (from[e], to[e]);
+                if u < 0 || v < 0 || u as usize >= n_verts || v as usize >= n_verts {
+                    code = COMPASS_ERR_INVALID_ARGUMENT;
+                    break;
+                }
+                graph.add_edge(u as usize, v as usize, dx[e], dy[e], 0.0, weight[e]);
+            }
+            if code != COMPASS_OK {
+                break;
+            }
+            start += len;
+        }
+
+        code
+    }));
+
+    let code = result.unwrap_or_else(|_| {
+        eprintln!("Panic caught in graph_add_edges_streamed");
+        COMPASS_ERR_PANIC
+    });
+    if code == COMPASS_OK {
+        ctx.system = None;
+        ctx.solution = None;
+        ctx.spatial_index.take();
+        ctx.publish();
+    } else {
+        ctx.graph.truncate_edges(first_edge);
+    }
+    code
+}
+
+/// Extracts the neighborhood of `num_seeds` seed vertices of the graph behind `handle` into
+/// a new handle, see [`Graph::subgraph_around`]. Boundary vertices are fixed at the current
+/// [`GraphContext::coordinates`], i.e. the adjusted ones after a successful [`graph_solve`].
+///
+/// `out_mapping` receives the original index of each vertex of the new handle, whose count
+/// is [`graph_num_vertices`] of the result; a buffer of `graph_num_vertices(handle)`
+/// elements is always large enough. It may be null.
+///
+/// Returns null if `handle` is null, `hops` or `num_seeds` is negative, a seed is out of
+/// range, or on a panic.
+#[unsafe(no_mangle)]
+pub extern "C" fn graph_subgraph_around(
+    handle: *const GraphContext,
+    seeds: *const c_int,
+    num_seeds: c_int,
+    hops: c_int,
+    out_mapping: *mut c_int,
+) -> *mut GraphContext {
+    let Some(ctx) = (unsafe { handle.as_ref() }) else {
+        return std::ptr::null_mut();
+    };
+    let (Ok(hops), Ok(num_seeds)) = (usize::try_from(hops), usize::try_from(num_seeds)) else {
+        return std::ptr::null_mut();
+    };
+    let result = std::panic::catch_unwind(|| {
+        // Safety: The caller guarantees `num_seeds` elements at `seeds`.
+        let seeds = unsafe { raw_slice(seeds, num_seeds) };
+        let n_verts = ctx.graph.num_vertices();
+        if seeds.iter().any(|&v| v < 0 || v as usize >= n_verts) {
+            return std::ptr::null_mut();
+        }
+        let seeds: Vec<usize> = seeds.iter().map(|&v| v as usize).collect();
+        let (mut sub, mapping) = ctx.graph.subgraph_around(&seeds, hops);
+        let (x, y) = ctx.coordinates();
+        for (local, &i) in mapping.iter().enumerate() {
+            sub.x[local] = x[i];
+            sub.y[local] = y[i];
+        }
+        if !out_mapping.is_null() {
+            // Safety: The caller guarantees a buffer of at least `mapping.len()` elements.
+            let out = unsafe { slice::from_raw_parts_mut(out_mapping, mapping.len()) };
+            for (dst, &i) in out.iter_mut().zip(&mapping) {
+                *dst = i as c_int;
+            }
+        }
+        GraphContext::into_raw(sub)
+    });
+
+    result.unwrap_or_else(|_| {
+        eprintln!("Panic caught in graph_subgraph_around");
+        std::ptr::null_mut()
+    })
+}
+
+/// Copies `message` as a NUL-terminated string into a caller buffer of `cap` bytes,
+/// truncating if needed. Does nothing when the buffer is null or empty.
+pub(crate) fn write_message(buf: *mut c_char, cap: usize, message: &str) {
+    if buf.is_null() || cap == 0 {
+        return;
+    }
+    let bytes = message.as_bytes();
+    let n = bytes.len().min(cap - 1);
+    // Safety: The caller guarantees `buf` points to at least `cap` writable bytes.
+    unsafe {
+        std::ptr::copy_nonoverlapping(bytes.as_ptr(), buf as *mut u8, n);
+        *buf.add(n) = 0;
+    }
+}
+
+/// Implementation of [`Graph::content_hash`], shared with the raw-array FFI variant.
+/// `edges` yields `(from, to, dx, dy, weight)`.
+fn content_hash(
+    x: &[f64],
+    y: &[f64],
+    fixed: impl Fn(usize) -> bool,
+    edges: impl Iterator<Item = (u64, u64, f64, f64, f64)>,
+) -> u64 {
+    let mut hash = Fnv1a::new();
+    hash.write_u64(x.len() as u64);
+    for i in 0..x.len() {
+        hash.write(&[fixed(i) as u8]);
+        if fixed(i) {
+            hash.write_f64(x[i]);
+            hash.write_f64(y[i]);
+        }
+    }
+
+    let mut edge_hashes: Vec<u64> = edges
+        .map(|(from, to, dx, dy, weight)| {
+            let mut edge = Fnv1a::new();
+            edge.write_u64(from);
+            edge.write_u64(to);
+            edge.write_f64(dx);
+            edge.write_f64(dy);
+            edge.write_f64(weight);
+            edge.finish()
+        })
+        .collect();
+    edge_hashes.sort_unstable();
+    hash.write_u64(edge_hashes.len() as u64);
+    for edge in edge_hashes {
+        hash.write_u64(edge);
+    }
+    hash.finish()
+}
+
+/// 64-bit FNV-1a over explicit little-endian byte representations.
+pub(crate) struct Fnv1a(u64);
+
+impl Fnv1a {
+    pub(crate) fn new() -> Self {
+        Fnv1a(0xcbf2_9ce4_8422_2325)
+    }
+
+    pub(crate) fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.0 ^= b as u64;
+            self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+
+    pub(crate) fn write_u64(&mut self, v: u64) {
+        self.write(&v.to_le_bytes());
+    }
+
+    pub(crate) fn write_f64(&mut self, v: f64) {
+        self.write(&v.to_bits().to_le_bytes());
+    }
+
+    pub(crate) fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+/// Borrowed view over the caller's graph arrays (everything except the in/out coordinates).
+struct GraphView<'a> {
+    fixed: &'a [c_int],
+    from: &'a [c_int],
+    to: &'a [c_int],
+    dx: &'a [c_double],
+    dy: &'a [c_double],
+    weight: &'a [c_double],
+    /// Per-edge enabled flags, see [`SolveOptions::edge_enabled`]. `None` = all enabled.
+    enabled: Option<&'a [c_int]>,
+}
+
+impl GraphView<'_> {
+    /// Whether edge `e` takes part in the adjustment.
+    fn is_enabled(&self, e: usize) -> bool {
+        self.enabled.is_none_or(|enabled| enabled[e] != 0)
+    }
+
+    /// The edges in the order [`accumulate`] sums them: by end vertices, then by the bits of
+    /// their effective weight and observations, so that the floating-point sums in every cell
+    /// of the normal equations do not depend on the order in which the edges were given.
+    /// Edges equal under that key add the same terms, so their relative order is immaterial.
+    fn canonical_order(&self) -> Result<Vec<usize>, c_int> {
+        let mut order = Vec::new();
+        order
+            .try_reserve_exact(self.from.len())
+            .map_err(|_| COMPASS_ERR_OUT_OF_MEMORY)?;
+        order.extend(0..self.from.len());
+        // Survey data mostly comes in traverse order, which pattern-defeating quicksort sorts
+        // in close to linear time.
+        order.sort_unstable_by_key(|&e| {
+            let w = if self.is_enabled(e) {
+                self.weight[e]
+            } else {
+                0.0
+            };
+            let bits = [w, self.dx[e], self.dy[e]].map(f64::to_bits);
+            (self.from[e], self.to[e], bits)
+        });
+        Ok(order)
+    }
+}
+
+/// Assembles and solves the normal equations for the given graph, writing the adjusted
+/// coordinates of the free vertices back into `x_slice` / `y_slice`.
+///
+/// The slices are read once, into a local copy the solve works on, and written once at the
+/// end, skipping the fixed vertices. In debug builds, or with [`SolveOptions::verify_fixed`],
+/// the fixed coordinates of both are then checked bit for bit against a snapshot. The
+/// [`warnings`] of the solve are appended to `warnings`.
+#[allow(clippy::too_many_arguments)]
+fn solve_view(
+    x_slice: &mut [f64],
+    y_slice: &mut [f64],
+    graph: &GraphView,
+    passive: Option<&[c_int]>,
+    options: &SolveOptions,
+    stats: &mut SolveStats,
+    mut vertical: Option<&mut two_stage::VerticalStage>,
+    warnings: &mut Vec<SolveWarning>,
+) -> c_int {
+    let is_fixed = |i: usize| graph.fixed[i] != 0;
+    let verify = cfg!(debug_assertions) || options.verify_fixed != 0;
+    let anchors: Vec<(usize, f64, f64)> = match verify {
+        true => (0..x_slice.len())
+            .filter(|&i| is_fixed(i))
+            .map(|i| (i, x_slice[i], y_slice[i]))
+            .collect(),
+        false => Vec::new(),
+    };
+    let (mut x, mut y) = match (try_copied(x_slice), try_copied(y_slice)) {
+        (Ok(x), Ok(y)) => (x, y),
+        (Err(code), _) | (_, Err(code)) => return code,
+    };
+    let stage = vertical.as_deref_mut();
+    let code = adjust_view(
+        &mut x, &mut y, graph, passive, options, stats, stage, warnings,
+    );
+    if stats.result_quality == COMPASS_RESULT_UNTOUCHED {
+        return code;
+    }
+    let displacement = |i: usize| (x[i] - x_slice[i]).hypot(y[i] - y_slice[i]);
+    stats.max_displacement = (0..x.len()).map(displacement).fold(0.0, f64::max);
+    let clamped = match options.clamp_to_bounds {
+        0 => Vec::new(),
+        _ => {
+            let is_free = |i: usize| {
+                // Safety: The caller guarantees `num_vertices` frozen and adjustable flags.
+                let frozen = !options.frozen.is_null() && unsafe { *options.frozen.add(i) } != 0;
+                let held =
+                    !options.adjustable.is_null() && unsafe { *options.adjustable.add(i) } == 0;
+                !is_fixed(i) && !frozen && !held
+            };
+            clamp_to_bounds(&mut x, &mut y, options, &is_free)
+        }
+    };
+    stats.clamped_vertices = clamped.len().min(c_int::MAX as usize) as c_int;
+    warnings.extend(
+        (clamped.iter())
+            .map(|&(vertex, distance)| SolveWarning::ClampedVertex { vertex, distance }),
+    );
+    warnings::sort(warnings);
+    stats.count_warnings(warnings);
+    if options.moved_threshold > 0.0 {
+        let moved = moved_vertices((x_slice, y_slice), (&x, &y), options.moved_threshold);
+        stats.moved_vertices = moved.len().min(c_int::MAX as usize) as c_int;
+        if moved.len() > options.moved_capacity.max(0) as usize {
+            stats.result_quality = COMPASS_RESULT_UNTOUCHED;
+            return COMPASS_ERR_BUFFER_TOO_SMALL;
+        }
+        for (k, &(i, _)) in moved.iter().enumerate() {
+            // Safety: The caller guarantees `moved_capacity` writable indices.
+            unsafe { options.moved_vertices.add(k).write(i as c_int) };
+        }
+    }
+    {
+        profile_span!("write_back", vertices = x.len());
+        let clock = Stopwatch::start();
+        for i in (0..x.len()).filter(|&i| !is_fixed(i)) {
+            x_slice[i] = x[i];
+            y_slice[i] = y[i];
+        }
+        if let Some(stage) = vertical {
+            stage.write_back(&is_fixed);
+        }
+        stats.writeback_ms += clock.ms();
+    }
+
+    let same = |a: f64, b: f64| a.to_bits() == b.to_bits();
+    let moved = anchors.iter().find(|&&(i, ax, ay)| {
+        !(same(x[i], ax) && same(y[i], ay) && same(x_slice[i], ax) && same(y_slice[i], ay))
+    });
+    if let Some(&(i, ax, ay)) = moved {
+        let message = format!(
+            "fixed vertex {i} at ({ax:e}, {ay:e}) came back as ({:e}, {:e}) from the solve \
+             and ({:e}, {:e}) from the write-back",
+            x[i], y[i], x_slice[i], y_slice[i]
+        );
+        for &(i, ax, ay) in &anchors {
+            x_slice[i] = ax;
+            y_slice[i] = ay;
+        }
+        let capacity = options.error_capacity.max(0) as usize;
+        write_message(options.error_message, capacity, &message);
+        return COMPASS_ERR_INTERNAL;
+    }
+    if !options.edge_residuals.is_null() {
+        // Safety: The caller guarantees `2 * num_edges` writable values.
+        let out =
+            unsafe { slice::from_raw_parts_mut(options.edge_residuals, 2 * graph.from.len()) };
+        for (e, r) in out.chunks_exact_mut(2).enumerate() {
+            let (u, v) = (graph.from[e] as usize, graph.to[e] as usize);
+            r[0] = x_slice[v] - x_slice[u] - graph.dx[e];
+            r[1] = y_slice[v] - y_slice[u] - graph.dy[e];
+        }
+    }
+    if clamped.is_empty() || code != COMPASS_OK {
+        return code;
+    }
+    let listed: Vec<String> = clamped
+        .iter()
+        .take(20)
+        .map(|(i, _)| i.to_string())
+        .collect();
+    let more = match clamped.len() - listed.len() {
+        0 => String::new(),
+        more => format!(" and {more} more"),
+    };
+    let message = format!("clamped free vertices {}{more}", listed.join(", "));
+    write_message(
+        options.error_message,
+        options.error_capacity.max(0) as usize,
+        &message,
+    );
+    COMPASS_WARN_CLAMPED
+}
+
+/// Moves the vertices for which `is_free` holds that lie outside the bounding box of
+/// [`SolveOptions::clamp_to_bounds`] onto it. Returns them, in vertex order, with the
+/// distance each moved.
+fn clamp_to_bounds(
+    x: &mut [f64],
+    y: &mut [f64],
+    options: &SolveOptions,
+    is_free: &dyn Fn(usize) -> bool,
+) -> Vec<(usize, f64)> {
+    let (x_range, y_range) = options.bounds();
+    // `max` and `min` take the bound over a NaN.
+    let clamp = |v: f64, range: &RangeInclusive<f64>| v.max(*range.start()).min(*range.end());
+    let mut clamped = Vec::new();
+    for i in (0..x.len()).filter(|&i| is_free(i)) {
+        if !(x_range.contains(&x[i]) && y_range.contains(&y[i])) {
+            let (cx, cy) = (clamp(x[i], &x_range), clamp(y[i], &y_range));
+            clamped.push((i, (x[i] - cx).hypot(y[i] - cy)));
+            (x[i], y[i]) = (cx, cy);
+        }
+    }
+    clamped
+}
+
+/// The solve of [`solve_view`] over the local copies `x_slice` / `y_slice`.
+#[allow(clippy::too_many_arguments)]
+fn adjust_view(
+    x_slice: &mut [f64],
+    y_slice: &mut [f64],
+    graph: &GraphView,
+    passive: Option<&[c_int]>,
+    options: &SolveOptions,
+    stats: &mut SolveStats,
+    mut vertical: Option<&mut two_stage::VerticalStage>,
+    warnings: &mut Vec<SolveWarning>,
+) -> c_int {
+    let clock = Stopwatch::start();
+    if check_edges(x_slice.len(), graph.from, graph.to).is_err() {
+        return COMPASS_ERR_INVALID_ARGUMENT;
+    }
+    let threshold = options.moved_threshold;
+    if threshold < 0.0
+        || threshold.is_nan()
+        || (options.moved_vertices.is_null() && options.moved_capacity > 0)
+    {
+        return COMPASS_ERR_INVALID_ARGUMENT;
+    }
+    let (x_range, y_range) = options.bounds();
+    if options.clamp_to_bounds != 0 && (x_range.is_empty() || y_range.is_empty()) {
+        return COMPASS_ERR_INVALID_ARGUMENT;
+    }
+    // Frozen vertices are solved as fixed ones; the stats tell them apart afterwards.
+    let frozen = if options.frozen.is_null() {
+        None
+    } else {
+        // Safety: The caller guarantees `num_vertices` frozen flags.
+        Some(unsafe { slice::from_raw_parts(options.frozen, x_slice.len()) })
+    };
+    let is_frozen = |i: usize| graph.fixed[i] == 0 && frozen.is_some_and(|f| f[i] != 0);
+    // So are the vertices outside the adjustable region.
+    let adjustable = if options.adjustable.is_null() {
+        None
+    } else {
+        // Safety: The caller guarantees `num_vertices` adjustable flags.
+        Some(unsafe { slice::from_raw_parts(options.adjustable, x_slice.len()) })
+    };
+    let is_held =
+        |i: usize| graph.fixed[i] == 0 && !is_frozen(i) && adjustable.is_some_and(|a| a[i] == 0);
+    let constrained: Vec<c_int>;
+    let graph = match (frozen, adjustable) {
+        (None, None) => graph,
+        _ => {
+            constrained = (0..x_slice.len())
+                .map(|i| (graph.fixed[i] != 0 || is_frozen(i) || is_held(i)) as c_int)
+                .collect();
+            &GraphView {
+                fixed: &constrained,
+                ..*graph
+            }
+        }
+    };
+
+    // Edge weights are scaled by their class multipliers, for the statistics too.
+    let scaled: Vec<f64>;
+    let graph = match classes::from_options(options, graph.from.len()) {
+        None => graph,
+        Some(classes) => {
+            if classes.validate(graph.from.len()).is_err() {
+                return COMPASS_ERR_INVALID_ARGUMENT;
+            }
+            scaled = classes.scale(graph.weight);
+            &GraphView {
+                weight: &scaled,
+                ..*graph
+            }
+        }
+    };
+
+    // A passive vertex is a free vertex flagged as passive; fixed vertices are never passive.
+    let is_passive = |i: usize| graph.fixed[i] == 0 && passive.is_some_and(|p| p[i] != 0);
+    let capacity = options.error_capacity.max(0) as usize;
+    if let Err(message) = axis_limits(options) {
+        write_message(options.error_message, capacity, &message);
+        return COMPASS_ERR_INVALID_ARGUMENT;
+    }
+    let (Some(method), Some(tolerance)) = (method(options), tolerance(options)) else {
+        return COMPASS_ERR_INVALID_ARGUMENT;
+    };
+    let free_vertices = graph.fixed.contains(&0);
+    let edges = (0..graph.from.len()).any(|e| graph.is_enabled(e));
+    let trivial = TrivialReason::of(edges, free_vertices);
+    stats.trivial = trivial.flags();
+    if options.error_on_trivial != 0
+        && let Some((code, message)) = trivial.error()
+    {
+        write_message(options.error_message, capacity, message);
+        return code;
+    }
+    stats.validate_ms = clock.ms();
+    let margin = match options.guess_margin {
+        0.0 => DEFAULT_GUESS_MARGIN,
+        margin if margin.is_nan() => return COMPASS_ERR_INVALID_ARGUMENT,
+        margin => margin,
+    };
+    let reseeded = reseed_initial_guess(x_slice, y_slice, graph, &is_passive, margin);
+    stats.reseeded_vertices = reseeded.len() as c_int;
+    let s_transform = match datum::STransform::from_options(options, graph, (x_slice, y_slice)) {
+        Ok(s_transform) => s_transform,
+        Err(message) => {
+            write_message(options.error_message, capacity, &message);
+            return COMPASS_ERR_INVALID_ARGUMENT;
+        }
+    };
+
+    let clock = Stopwatch::start();
+    let system = match NormalEquations::assemble(x_slice, y_slice, graph, &is_passive) {
+        Ok(system) => system,
+        Err(code) => return code,
+    };
+    stats.assemble_ms = clock.ms();
+    let isolated: Vec<usize> = (0..x_slice.len())
+        .filter(|&i| graph.fixed[i] == 0 && !is_passive(i) && system.mapping[i].is_none())
+        .collect();
+    if !isolated.is_empty() && options.keep_isolated == 0 {
+        let listed: Vec<String> = isolated.iter().take(20).map(|i| i.to_string()).collect();
+        let more = match isolated.len() - listed.len() {
+            0 => String::new(),
+            more => format!(" and {more} more"),
+        };
+        let message = format!("isolated free vertices {}{more}", listed.join(", "));
+        write_message(options.error_message, capacity, &message);
+        return COMPASS_ERR_ISOLATED_VERTEX;
+    }
+    stats.isolated_vertices = isolated.len() as c_int;
+    if let Some(i) = system.empty_row() {
+        let message = format!("free vertex {i} has only edges of zero weight");
+        write_message(options.error_message, capacity, &message);
+        return COMPASS_ERR_INVALID_ARGUMENT;
+    }
+    if !system.accepts(method.0) {
+        return COMPASS_ERR_INVALID_ARGUMENT;
+    }
+    if let Some(stage) = &mut vertical {
+        options.report(&mut stage.stats);
+        if let Err(message) = stage.prepare(&system, graph, &is_passive) {
+            write_message(options.error_message, capacity, &message);
+            return COMPASS_ERR_INVALID_ARGUMENT;
+        }
+    }
+    let cancelled = cancel_flag(options);
+    let iterations = options.iterations.max(0) as usize;
+    let centroid = centroid::from_options(options, x_slice.len()).map(|mut centroid| {
+        for (i, weight) in centroid.weights.iter_mut().enumerate() {
+            if is_passive(i) {
+                *weight = 0.0;
+            }
+        }
+        centroid
+    });
+    // The extra solve of the centroid runs first, so cancelling it leaves the slices
+    // untouched.
+    let xy = (&x_slice[..], &y_slice[..]);
+    let n = x_slice.len();
+    let hold = match &centroid {
+        None => None,
+        Some(c) => {
+            match centroid::prepare(&system, c, n, xy, method, iterations, tolerance, &cancelled) {
+                Ok(hold) => Some(hold),
+                Err(err) => return err.code,
+            }
+        }
+    };
+    stats.free_vertices = system.size() as c_int;
+    stats.free_networks = system.null_space.dimension() as c_int;
+    stats.passive_vertices = (0..x_slice.len()).filter(|&i| is_passive(i)).count() as c_int;
+    stats.disabled_edges = (0..graph.from.len())
+        .filter(|&e| !graph.is_enabled(e))
+        .count() as c_int;
+
+    // The effective datum compares the adjusted coordinates with the initial ones.
+    let datum_weights = match &centroid {
+        Some(c) => Some(c.weights.clone()),
+        None if system.null_space.dimension() > 0 => Some(
+            datum::free_network_vertices(&system)
+                .into_iter()
+                .map(|free| free as u8 as f64)
+                .collect(),
+        ),
+        None => None,
+    };
+    let initial = (datum_weights.is_some() || s_transform.is_some())
+        .then(|| (x_slice.to_vec(), y_slice.to_vec()));
+
+    // With no free vertices there is nothing to solve. Passive vertices hanging off
+    // fixed stations still follow their parent. A cancelled solve leaves its last iterate,
+    // which is finished like a converged one before reporting the cancel.
+    let mut interrupted = false;
+    let mut convergence = None;
+    if system.size() > 0 {
+        let solved = system.solve(x_slice, y_slice, iterations, tolerance, method, &cancelled);
+        solved.record(stats);
+        interrupted = solved.cancelled;
+        convergence = Some(solved);
+    } else {
+        stats.result_quality = COMPASS_RESULT_EXACT;
+    }
+    let is_isolated = |i: usize| isolated.binary_search(&i).is_ok();
+    warnings.extend(warnings::solve_warnings(
+        &system,
+        &is_isolated,
+        convergence.as_ref(),
+    ));
+    warnings.extend((reseeded.into_iter()).map(|vertex| SolveWarning::ReseededVertex { vertex }));
+    if let Some(hold) = hold {
+        hold.apply(&system, x_slice, y_slice, stats);
+    }
+
+    // Move passive vertices rigidly with their (now adjusted) parent stations.
+    place_passive_vertices(x_slice, y_slice, graph, &is_passive);
+    if let Some(stage) = vertical
+        && !interrupted
+    {
+        interrupted = stage.solve(
+            graph,
+            &is_passive,
+            iterations,
+            tolerance,
+            method,
+            &cancelled,
+        );
+    }
+    if let Some((x0, y0)) = &initial {
+        if let Some(weights) = &datum_weights {
+            let adjusted = (&x_slice[..], &y_slice[..]);
+            datum::EffectiveDatum::fit((x0, y0), adjusted, &|i| weights[i]).record(stats);
+        }
+        if let Some(s_transform) = &s_transform {
+            s_transform.apply((x0, y0), (x_slice, y_slice));
+        }
+    }
+
+    let enabled_edges = || {
+        (0..graph.from.len())
+            .filter(|&e| graph.is_enabled(e))
+            .map(|e| {
+                (
+                    graph.from[e] as usize,
+                    graph.to[e] as usize,
+                    graph.dx[e],
+                    graph.dy[e],
+                    graph.weight[e],
+                )
+            })
+    };
+    let is_active = |i: usize| graph.fixed[i] == 0 && !is_passive(i);
+    if frozen.is_some() {
+        let (edges, stress) =
+            frozen_boundary(x_slice, y_slice, enabled_edges(), &is_frozen, &is_active);
+        stats.frozen_vertices = (0..x_slice.len()).filter(|&i| is_frozen(i)).count() as c_int;
+        stats.frozen_boundary_edges = edges;
+        stats.frozen_boundary_stress = stress;
+    }
+    if adjustable.is_some() {
+        let (edges, stress) =
+            frozen_boundary(x_slice, y_slice, enabled_edges(), &is_held, &is_active);
+        stats.held_vertices = (0..x_slice.len()).filter(|&i| is_held(i)).count() as c_int;
+        stats.interior_edges = enabled_edges()
+            .filter(|&(u, v, ..)| is_active(u) && is_active(v))
+            .count() as c_int;
+        stats.held_boundary_edges = edges;
+        stats.held_boundary_stress = stress;
+    }
+    if interrupted {
+        return COMPASS_ERR_CANCELLED;
+    }
+    COMPASS_OK
+}
+
+/// Counts the edges linking a frozen (or held) vertex to an active (adjusted) one and sums their
+/// weighted squared residuals at the adjusted coordinates. `edges` yields
+/// `(from, to, dx, dy, weight)`.
+fn frozen_boundary(
+    x: &[f64],
+    y: &[f64],
+    edges: impl Iterator<Item = (usize, usize, f64, f64, f64)>,
+    is_frozen: &dyn Fn(usize) -> bool,
+    is_active: &dyn Fn(usize) -> bool,
+) -> (c_int, f64) {
+    let mut count = 0;
+    let mut stress = 0.0;
+    for (u, v, dx, dy, w) in edges {
+        if (is_frozen(u) && is_active(v)) || (is_active(u) && is_frozen(v)) {
+            let rx = x[v] - x[u] - dx;
+            let ry = y[v] - y[u] - dy;
+            count += 1;
+            stress += w * (rx * rx + ry * ry);
+        }
+    }
+    (count, stress)
+}
+
+/// Structure of the normal equations matrix of a graph, filled by [`graph_matrix_stats`] to
+/// diagnose slow solves without the survey data.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct MatrixStats {
+    /// Number of rows: the free vertices.
+    pub rows: c_int,
+    /// Number of nonzero entries. Disabled edges keep their cells in the matrix, as zeros
+    /// that are not counted here or in the degrees.
+    pub nonzeros: c_int,
+    /// Mean number of off-diagonal entries per row: the free neighbors of a vertex.
+    pub mean_degree: c_double,
+    /// Largest number of off-diagonal entries in a row.
+    pub max_degree: c_int,
+    /// Smallest ratio of the diagonal entry to the summed magnitudes of the off-diagonal
+    /// ones over the rows with off-diagonal entries, infinite without any. At least 1 when
+    /// the matrix is diagonally dominant; rows tied to a fixed vertex are above 1.
+    pub dominance: c_double,
+    /// Smallest diagonal entry.
+    pub min_diagonal: c_double,
+    /// Largest diagonal entry.
+    pub max_diagonal: c_double,
+    /// Number of rows with a zero diagonal: free vertices whose edges all have zero weight,
+    /// which make the matrix singular. Free vertices without a usable edge are left out of
+    /// the matrix, see [`SolveStats::isolated_vertices`].
+    pub empty_rows: c_int,
+}
+
+/// Mapping of the vertices of `graph` to the unknowns of its normal equations, and the number
+/// of unknowns, see [`NormalEquations::mapping`].
+fn free_mapping(
+    n_verts: usize,
+    graph: &GraphView,
+    is_passive: &dyn Fn(usize) -> bool,
+) -> Result<(Vec<Option<usize>>, usize), c_int> {
+    profile_span!("mapping", vertices = n_verts);
+    let fixed_slice = graph.fixed;
+    // Fixed vertices do not participate in the matrix as variables; they act as boundary conditions.
+    // Passive vertices do not participate at all; they are transformed after the solve.
+    // We create a mapping where `mapping[original_index] = Some(reduced_index)` for free vertices,
+    // and `None` for fixed and passive vertices.
+    // Free vertices without a usable edge would leave an empty row, so they stay out too:
+    // the solve leaves them at their initial coordinates.
+    let mut usable = try_filled(n_verts, false)?;
+    for e in (0..graph.from.len()).filter(|&e| graph.is_enabled(e)) {
+        let (u, v) = (graph.from[e] as usize, graph.to[e] as usize);
+        if u != v && !is_passive(u) && !is_passive(v) {
+            usable[u] = true;
+            usable[v] = true;
+        }
+    }
+    let mut mapping = try_filled(n_verts, None)?;
+    let mut active_count = 0;
+
+    for i in 0..n_verts {
+        if fixed_slice[i] == 0 && !is_passive(i) && usable[i] {
+            mapping[i] = Some(active_count);
+            active_count += 1;
+        }
+    }
+    Ok((mapping, active_count))
+}
+
+/// Normal equations of a graph: the reduced matrix shared by X and Y and the two right-hand
+/// sides. They only depend on the graph, so [`GraphContext`] caches them across solves.
+struct NormalEquations {
+    /// `mapping[original_index] = Some(reduced_index)` for free vertices, `None` for fixed,
+    /// passive and isolated vertices, see [`SolveOptions::keep_isolated`].
+    mapping: Vec<Option<usize>>,
+    matrix: CsrMatrix<f64>,
+    bx: DVector<f64>,
+    by: DVector<f64>,
+    /// Translations of the components of `matrix` that no fixed vertex anchors.
+    null_space: NullSpace,
+    /// Preconditioner last built for `matrix`, see [`NormalEquations::preconditioner`].
+    preconditioner: Mutex<Option<(Preconditioner, Arc<PreconditionerOp>)>>,
+    /// Zero-weight and duplicate edges met at assembly, see [`warnings::edge_warnings`].
+    edge_warnings: Vec<SolveWarning>,
+}
+
+impl NormalEquations {
+    /// Builds the normal equations of `graph`. Fixed vertex coordinates are read from
+    /// `x_slice` / `y_slice`. Fails with [`COMPASS_ERR_OUT_OF_MEMORY`] when the buffers sized
+    /// by the vertex and edge counts cannot be allocated.
+    ///
+    /// Disabled edges keep their cells in the sparsity pattern with zero values, so that
+    /// toggling them only needs a [`NormalEquations::refill`]. The edges are summed in their
+    /// [canonical order](GraphView::canonical_order), so that the matrix and right-hand sides
+    /// are the same bit for bit whatever the order of the edges in `graph`.
+    fn assemble(
+        x_slice: &[f64],
+        y_slice: &[f64],
+        graph: &GraphView,
+        is_passive: &dyn Fn(usize) -> bool,
+    ) -> Result<NormalEquations, c_int> {
+        let n_verts = x_slice.len();
+
+        // 1. Mapping: Original Index -> Reduced Index
+        let (mapping, active_count) = free_mapping(n_verts, graph, is_passive)?;
+
+        // 2. Assemble Matrix (COO format) and RHS vectors
+        // The system to solve is (A^T W A) x = A^T W l, which reduces to a symmetric positive definite system.
+        // Here we construct the Normal Equations directly.
+        // Ax and Ay matrices are identical in structure and values (dependent only on weights),
+        // so we only need to construct one matrix `coo_ax`. Each edge adds at most four terms;
+        // the triplets are reserved up front so that a graph too large fails cleanly.
+        let terms = coo_terms(graph.from.len())?;
+        profile_span!(
+            "assemble",
+            unknowns = active_count,
+            edges = graph.from.len()
+        );
+        let (mut rows, mut columns) = (Vec::new(), Vec::new());
+        let mut values = Vec::new();
+        rows.try_reserve_exact(terms)
+            .and_then(|()| columns.try_reserve_exact(terms))
+            .and_then(|()| values.try_reserve_exact(terms))
+            .map_err(|_| COMPASS_ERR_OUT_OF_MEMORY)?;
+
+        let mut bx = DVector::from_vec(try_filled(active_count, 0.0)?);
+        let mut by = DVector::from_vec(try_filled(active_count, 0.0)?);
+        let order = graph.canonical_order()?;
+
+        accumulate(
+            &mapping,
+            (x_slice, y_slice),
+            graph,
+            &order,
+            is_passive,
+            &mut |i, j, w| {
+                rows.push(i);
+                columns.push(j);
+                values.push(w);
+            },
+            (&mut bx, &mut by),
+        );
+        let Ok(coo_ax) =
+            CooMatrix::try_from_triplets(active_count, active_count, rows, columns, values)
+        else {
+            unreachable!("terms are pushed at mapped vertices");
+        };
+
+        // Convert COO to CSR format for efficient multiplication in the solver
+        let matrix = {
+            profile_span!("csr", triplets = coo_ax.nnz());
+            CsrMatrix::from(&coo_ax)
+        };
+        Ok(NormalEquations {
+            mapping,
+            null_space: NullSpace::of(&matrix),
+            matrix,
+            bx,
+            by,
+            preconditioner: Mutex::new(None),
+            edge_warnings: warnings::edge_warnings(graph, &order),
+        })
+    }
+
+    /// Numeric update: refills the matrix and the right-hand sides from `graph`, the same
+    /// edges as at assembly with other enabled flags, weights or observations. The mapping and
+    /// the sparsity pattern are kept, and the preconditioner is rebuilt by the next solve.
+    ///
+    /// Returns `false`, leaving the system untouched, if `graph` needs a cell outside the
+    /// pattern, or isolates or reconnects a free vertex; the caller then assembles afresh.
+    fn refill(
+        &mut self,
+        x_slice: &[f64],
+        y_slice: &[f64],
+        graph: &GraphView,
+        is_passive: &dyn Fn(usize) -> bool,
+    ) -> bool {
+        match free_mapping(x_slice.len(), graph, is_passive) {
+            Ok((mapping, _)) if mapping == self.mapping => {}
+            _ => return false,
+        }
+        let (offsets, columns) = (self.matrix.row_offsets(), self.matrix.col_indices());
+        let mut values = vec![0.0; columns.len()];
+        let mut bx = DVector::zeros(self.size());
+        let mut by = DVector::zeros(self.size());
+        let Ok(order) = graph.canonical_order() else {
+            return false;
+        };
+        let mut missing = false;
+        accumulate(
+            &self.mapping,
+            (x_slice, y_slice),
+            graph,
+            &order,
+            is_passive,
+            &mut |i, j, w| {
+                let row = &columns[offsets[i]..offsets[i + 1]];
+                match row.binary_search(&j) {
+                    Ok(k) => values[offsets[i] + k] += w,
+                    Err(_) => missing = true,
+                }
+            },
+            (&mut bx, &mut by),
+        );
+        if missing {
+            return false;
+        }
+        self.matrix.values_mut().copy_from_slice(&values);
+        self.bx = bx;
+        self.by = by;
+        self.edge_warnings = warnings::edge_warnings(graph, &order);
+        // Disabled edges may have cut a component off its anchors, and the cached
+        // preconditioner was built from the previous values.
+        self.null_space = NullSpace::of(&self.matrix);
+        *self
+            .preconditioner
+            .get_mut()
+            .unwrap_or_else(|e| e.into_inner()) = None;
+        true
+    }
+
+    /// Number of unknowns per axis (free vertices).
+    fn size(&self) -> usize {
+        self.bx.len()
+    }
+
+    /// Structure of the matrix, in one pass over its entries.
+    fn matrix_stats(&self) -> MatrixStats {
+        let a = &self.matrix;
+        let (offsets, columns, values) = (a.row_offsets(), a.col_indices(), a.values());
+        let mut stats = MatrixStats {
+            rows: a.nrows() as c_int,
+            dominance: f64::INFINITY,
+            ..Default::default()
+        };
+        let (mut min_diagonal, mut max_diagonal) = (f64::INFINITY, f64::NEG_INFINITY);
+        for row in 0..a.nrows() {
+            let (mut diagonal, mut off_diagonal, mut degree) = (0.0, 0.0, 0);
+            // The cells of disabled edges stay in the pattern with zero values.
+            for k in (offsets[row]..offsets[row + 1]).filter(|&k| values[k] != 0.0) {
+                stats.nonzeros += 1;
+                if columns[k] == row {
+                    diagonal += values[k];
+                } else {
+                    off_diagonal += values[k].abs();
+                    degree += 1;
+                }
+            }
+            stats.max_degree = stats.max_degree.max(degree);
+            stats.mean_degree += degree as f64;
+            if degree > 0 {
+                stats.dominance = stats.dominance.min(diagonal.abs() / off_diagonal);
+            }
+            min_diagonal = min_diagonal.min(diagonal);
+            max_diagonal = max_diagonal.max(diagonal);
+            stats.empty_rows += (diagonal == 0.0) as c_int;
+        }
+        if a.nrows() > 0 {
+            stats.mean_degree /= a.nrows() as f64;
+            (stats.min_diagonal, stats.max_diagonal) = (min_diagonal, max_diagonal);
+        }
+        stats
+    }
+
+    /// The first vertex (original index) whose row has a zero diagonal, see
+    /// [`MatrixStats::empty_rows`].
+    fn empty_row(&self) -> Option<usize> {
+        let a = &self.matrix;
+        let (offsets, columns, values) = (a.row_offsets(), a.col_indices(), a.values());
+        let empty = |row: usize| {
+            (offsets[row]..offsets[row + 1])
+                .filter(|&k| columns[k] == row)
+                .map(|k| values[k])
+                .sum::<f64>()
+                == 0.0
+        };
+        self.mapping.iter().position(|row| row.is_some_and(empty))
+    }
+
+    /// Whether `solver` can solve this system. Chebyshev iteration needs a positive smallest
+    /// eigenvalue, which free networks do not have.
+    fn accepts(&self, solver: LinearSolver) -> bool {
+        solver != LinearSolver::Chebyshev || self.null_space.dimension() == 0
+    }
+
+    /// Rows (reduced indices) within `radius` couplings of the `seeds`, in breadth-first
+    /// order.
+    fn rows_near(&self, seeds: &[usize], radius: usize) -> Vec<usize> {
+        let (offsets, columns) = (self.matrix.row_offsets(), self.matrix.col_indices());
+        let mut depth = vec![usize::MAX; self.size()];
+        let mut rows = Vec::new();
+        for &seed in seeds {
+            if depth[seed] == usize::MAX {
+                depth[seed] = 0;
+                rows.push(seed);
+            }
+        }
+        let mut next = 0;
+        while let Some(&row) = rows.get(next) {
+            next += 1;
+            if depth[row] == radius {
+                continue;
+            }
+            for &column in &columns[offsets[row]..offsets[row + 1]] {
+                if depth[column] == usize::MAX {
+                    depth[column] = depth[row] + 1;
+                    rows.push(column);
+                }
+            }
+        }
+        rows
+    }
+
+    /// Runs `sweeps` Gauss-Seidel sweeps with relaxation `omega` (SOR) over `rows`, all rows
+    /// when `None`, updating the free vertices of `x_slice` / `y_slice` in place. The
+    /// `pinned` row keeps its current value.
+    fn sweep(
+        &self,
+        x_slice: &mut [f64],
+        y_slice: &mut [f64],
+        rows: Option<&[usize]>,
+        pinned: Option<usize>,
+        sweeps: usize,
+        omega: f64,
+    ) {
+        let mut x = vec![0.0; self.size()];
+        let mut y = vec![0.0; self.size()];
+        for (i, idx) in self.mapping.iter().enumerate() {
+            if let Some(idx) = *idx {
+                x[idx] = x_slice[i];
+                y[idx] = y_slice[i];
+            }
+        }
+        let all: Vec<usize>;
+        let rows = match rows {
+            Some(rows) => rows,
+            None => {
+                all = (0..self.size()).collect();
+                &all
+            }
+        };
+
+        let (offsets, columns) = (self.matrix.row_offsets(), self.matrix.col_indices());
+        let values = self.matrix.values();
+        for _ in 0..sweeps {
+            for &row in rows.iter().filter(|&&row| Some(row) != pinned) {
+                let (mut diagonal, mut sx, mut sy) = (0.0, self.bx[row], self.by[row]);
+                for k in offsets[row]..offsets[row + 1] {
+                    let column = columns[k];
+                    if column == row {
+                        diagonal += values[k];
+                    } else {
+                        sx -= values[k] * x[column];
+                        sy -= values[k] * y[column];
+                    }
+                }
+                if diagonal > 0.0 {
+                    x[row] += omega * (sx / diagonal - x[row]);
+                    y[row] += omega * (sy / diagonal - y[row]);
+                }
+            }
+        }
+
+        for (i, idx) in self.mapping.iter().enumerate() {
+            if let Some(idx) = *idx {
+                x_slice[i] = x[idx];
+                y_slice[i] = y[idx];
+            }
+        }
+    }
+
+    /// Solves for the free vertices, using their current coordinates in `x_slice` /
+    /// `y_slice` as the initial guess and writing the results back. A solve that runs out of
+    /// iterations or is cancelled writes back its last iterate, see [`Convergence`].
+    fn solve(
+        &self,
+        x_slice: &mut [f64],
+        y_slice: &mut [f64],
+        iterations: usize,
+        tolerance: Tolerance,
+        (solver, preconditioner): (LinearSolver, Preconditioner),
+        cancelled: &(dyn Fn() -> bool + Sync),
+    ) -> Convergence {
+        let mapping = &self.mapping;
+
+        // Initial guess vectors for the solver (mapped from input)
+        let mut x0_solver = DVector::zeros(self.size());
+        let mut y0_solver = DVector::zeros(self.size());
+
+        // Fill initial guess from input slices
+        for i in 0..mapping.len() {
+            if let Some(idx) = mapping[i] {
+                x0_solver[idx] = x_slice[i];
+                y0_solver[idx] = y_slice[i];
+            }
+        }
+
+        // 3. Solve (Conjugate Gradient or Chebyshev)
+        // Since X and Y coordinates are independent in this formulation (no rotation/scale parameters),
+        // key optimization: we can solve for X and Y in parallel.
+        let (bx, by) = (&self.bx, &self.by);
+        let (limits_x, limits_y) = (tolerance.axis(0, iterations), tolerance.axis(1, iterations));
+        // An axis whose initial guess already meets the tolerance is not solved, and the
+        // preconditioner is only set up if an axis is.
+        let skip_x = self.converged_at(bx, &x0_solver, &limits_x.1);
+        let skip_y = self.converged_at(by, &y0_solver, &limits_y.1);
+        let (runner, precondition_ms) = match skip_x.is_none() || skip_y.is_none() {
+            true => {
+                let clock = Stopwatch::start();
+                let runner = self.runner((solver, preconditioner), cancelled);
+                (Some(runner), clock.ms())
+            }
+            false => (None, 0.0),
+        };
+        let (b, x0) = ([bx, by], [&x0_solver, &y0_solver]);
+        let (limits, skipped) = ([limits_x, limits_y], [skip_x, skip_y]);
+        // Solves axis 0 = X or 1 = Y, on the thread that calls it, which times it.
+        let run = |axis: usize| {
+            profile_span!("solve_axis", axis, unknowns = self.size());
+            match (skipped[axis], &runner) {
+                (None, Some(run)) => {
+                    let clock = Stopwatch::start();
+                    let (x, convergence) = run(b[axis], x0[axis], limits[axis]);
+                    (x, convergence, clock.ms())
+                }
+                (skipped, _) => (x0[axis].clone(), skipped.unwrap_or_default(), 0.0),
+            }
+        };
+        // wasm32-unknown-unknown cannot spawn threads, so the axes are solved one after the other.
+        #[cfg(target_arch = "wasm32")]
+        let (res_x, res_y) = (run(0), run(1));
+        #[cfg(not(target_arch = "wasm32"))]
+        let (res_x, res_y) = std::thread::scope(|s| {
+            let handle_x = s.spawn(|| run(0));
+            let handle_y = s.spawn(|| run(1));
+
+            let res_x = handle_x.join().unwrap();
+            let res_y = handle_y.join().unwrap();
+            (res_x, res_y)
+        });
+        let ((res_x, conv_x, ms_x), (res_y, conv_y, ms_y)) = (res_x, res_y);
+
+        // 4. Write back results to the original arrays (Java memory)
+        let clock = Stopwatch::start();
+        for i in 0..mapping.len() {
+            if let Some(idx) = mapping[i] {
+                x_slice[i] = res_x[idx];
+                y_slice[i] = res_y[idx];
+            }
+        }
+        Convergence {
+            iterations: conv_x.iterations.max(conv_y.iterations),
+            residual_norm: conv_x.residual_norm.max(conv_y.residual_norm),
+            recursive_residual_norm: conv_x
+                .recursive_residual_norm
+                .max(conv_y.recursive_residual_norm),
+            converged: conv_x.converged && conv_y.converged,
+            cancelled: conv_x.cancelled || conv_y.cancelled,
+            out_of_time: conv_x.out_of_time || conv_y.out_of_time,
+            breakdown: conv_x.breakdown.into_iter().chain(conv_y.breakdown).min(),
+            preconditioner_failure: conv_x
+                .preconditioner_failure
+                .into_iter()
+                .chain(conv_y.preconditioner_failure)
+                .min(),
+            restarts: conv_x.restarts + conv_y.restarts,
+            criterion: conv_x.criterion,
+            measure: conv_x.measure.max(conv_y.measure),
+            axis_iterations: [conv_x.iterations, conv_y.iterations],
+            axis_residual_norm: [conv_x.residual_norm, conv_y.residual_norm],
+            axis_converged: [conv_x.converged, conv_y.converged],
+            skipped: [conv_x.skipped[0], conv_y.skipped[0]],
+            extended: [conv_x.extended[0], conv_y.extended[0]],
+            precondition_ms,
+            axis_ms: [ms_x, ms_y],
+            writeback_ms: clock.ms(),
+        }
+    }
+
+    /// Convergence of `x0` as the solution of `matrix * x = b` if its residual already meets
+    /// `tolerance`, in which case the solve is skipped: one matrix-vector product instead of
+    /// the setup of the preconditioner and a first iteration.
+    fn converged_at(
+        &self,
+        b: &DVector<f64>,
+        x0: &DVector<f64>,
+        tolerance: &Tolerance,
+    ) -> Option<Convergence> {
+        let mut r = b - &self.matrix * x0;
+        self.null_space.project(&mut r);
+        let (norm, scale) = (r.norm(), tolerance.criterion.scale(b, &r));
+        if norm >= tolerance.residual * scale {
+            return None;
+        }
+        let mut convergence = Convergence {
+            recursive_residual_norm: norm,
+            skipped: [true, false],
+            ..Convergence::default()
+        };
+        convergence.finish(norm, scale, tolerance);
+        Some(convergence)
+    }
+
+    /// Single right-hand side solver of this matrix: `run(b, x0, (iterations, tolerance))`
+    /// solves `matrix * x = b` from `x0` with `method`.
+    #[allow(clippy::type_complexity)]
+    fn runner<'a>(
+        &'a self,
+        (solver, preconditioner): (LinearSolver, Preconditioner),
+        cancelled: &'a (dyn Fn() -> bool + Sync),
+    ) -> impl Fn(&DVector<f64>, &DVector<f64>, (usize, Tolerance)) -> (DVector<f64>, Convergence)
+    + Sync
+    + 'a {
+        let csr_a = &self.matrix;
+        // Every right-hand side shares the matrix, hence the preconditioner and the eigenvalue
+        // bounds.
+        let op = self.preconditioner(preconditioner);
+        let bounds = match solver {
+            LinearSolver::ConjugateGradient => (0.0, 0.0),
+            LinearSolver::Chebyshev => chebyshev_bounds(csr_a, jacobi(op.as_deref())),
+        };
+        move |b: &DVector<f64>, x0: &DVector<f64>, (iterations, tolerance)| match solver {
+            LinearSolver::ConjugateGradient => solve_cg(
+                csr_a,
+                b,
+                x0,
+                op.as_deref().map(|op| op as &dyn sparse::Preconditioner),
+                &self.null_space,
+                iterations,
+                tolerance,
+                cancelled,
+            ),
+            LinearSolver::Chebyshev => solve_chebyshev(
+                csr_a,
+                b,
+                x0,
+                jacobi(op.as_deref()),
+                bounds,
+                iterations,
+                tolerance,
+                cancelled,
+            ),
+        }
+    }
+
+    /// The preconditioner of this matrix, built on first use and kept for the next solves
+    /// with the same `preconditioner`. `None` for [`Preconditioner::None`].
+    fn preconditioner(&self, preconditioner: Preconditioner) -> Option<Arc<PreconditionerOp>> {
+        let a = &self.matrix;
+        if preconditioner == Preconditioner::None {
+            return None;
+        }
+        let mut cache = self
+            .preconditioner
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        if let Some((cached, op)) = &*cache
+            && *cached == preconditioner
+        {
+            return Some(op.clone());
+        }
+        profile_span!("precondition", kind = ?preconditioner, unknowns = a.nrows());
+        let op = Arc::new(PreconditionerOp::new(a, preconditioner)?);
+        *cache = Some((preconditioner, op.clone()));
+        Some(op)
+    }
+}
+
+/// Adds the terms of the `graph` edges to the normal equations over the unknowns of `mapping`:
+/// `push(i, j, a)` for each matrix term, and the observations and fixed neighbors to `bx` /
+/// `by`. Fixed vertex coordinates are read from `x_slice` / `y_slice`. The edges are visited
+/// in `order`, their [`GraphView::canonical_order`].
+fn accumulate(
+    mapping: &[Option<usize>],
+    (x_slice, y_slice): (&[f64], &[f64]),
+    graph: &GraphView,
+    order: &[usize],
+    is_passive: &dyn Fn(usize) -> bool,
+    push: &mut dyn FnMut(usize, usize, f64),
+    (bx, by): (&mut DVector<f64>, &mut DVector<f64>),
+) {
+    let from_slice = graph.from;
+    let to_slice = graph.to;
+    let dx_slice = graph.dx;
+    let dy_slice = graph.dy;
+    let w_slice = graph.weight;
+
+    // Iterate over all edges to build the matrix and RHS vectors
+    for &e in order {
+        let u = from_slice[e] as usize;
+        let v = to_slice[e] as usize;
+        // Weight of the observation. Disabled edges keep their cells with a zero weight, so
+        // that the pattern stays that of all the edges while their terms vanish.
+        let w = if graph.is_enabled(e) { w_slice[e] } else { 0.0 };
+        let dx = dx_slice[e];
+        let dy = dy_slice[e];
+
+        // Splay/wall shots do not participate in the adjustment.
+        if is_passive(u) || is_passive(v) {
+            continue;
+        }
+
+        // An edge between u and v provides an observation:
+        // x_v - x_u = dx
+        // y_v - y_u = dy
+        //
+        // In the normal equations (Least Squares), this contributes:
+        // A[u, u] += w, A[v, v] += w
+        // A[u, v] -= w, A[v, u] -= w
+        // RHS_u -= w * dx
+        // RHS_v += w * dx
+
+        let u_map = mapping[u];
+        let v_map = mapping[v];
+
+        match (u_map, v_map) {
+            (Some(ui), Some(vi)) => {
+                // Case 1: Both vertices are free.
+                // Add terms to the matrix for both u and v.
+                push(ui, ui, w);
+                push(vi, vi, w);
+                push(ui, vi, -w);
+                push(vi, ui, -w);
+
+                // Add terms to RHS vectors
+                bx[ui] -= w * dx;
+                bx[vi] += w * dx;
+
+                by[ui] -= w * dy;
+                by[vi] += w * dy;
+            }
+            (Some(ui), None) => {
+                // Case 2: u is free, v is fixed.
+                // Since v is fixed, x_v and y_v are constants.
+                // Terms involving x_v move to the RHS.
+                // A[u, u] += w
+                // A[u, v] * x_v (where A[u,v] is -w) becomes -(-w * x_v) = +w * x_v on the RHS.
+
+                push(ui, ui, w);
+
+                // RHS modifications from edge constraint (dx/dy)
+                bx[ui] -= w * dx;
+                by[ui] -= w * dy;
+
+                // RHS modifications from the fixed neighbor v
+                let xv = x_slice[v];
+                let yv = y_slice[v];
+
+                bx[ui] += w * xv;
+                by[ui] += w * yv;
+            }
+            (None, Some(vi)) => {
+                // Case 3: u is fixed, v is free.
+                // Similar to Case 2, but for v.
+                // A[v, v] += w
+                // A[v, u] * x_u (where A[v,u] is -w) becomes -(-w * x_u) = +w * x_u on the RHS.
+
+                push(vi, vi, w);
+
+                // RHS modifications from edge constraint
+                bx[vi] += w * dx;
+                by[vi] += w * dy;
+
+                // RHS modifications from the fixed neighbor u
+                let xu = x_slice[u];
+                let yu = y_slice[u];
+
+                bx[vi] += w * xu;
+                by[vi] += w * yu;
+            }
+            (None, None) => {
+                // Case 4: Both fixed.
+                // This is a check constraint between two anchors. It does not affect the system
+                // of equations for the free variables, so we ignore it.
+            }
+        }
+    }
+}
+
+/// Inverse diagonal of a Jacobi preconditioner, the only one Chebyshev iteration takes.
+fn jacobi(op: Option<&PreconditionerOp>) -> Option<&DVector<f64>> {
+    match op {
+        Some(PreconditionerOp::Jacobi(inv_diag)) => Some(inv_diag),
+        _ => None,
+    }
+}
+
+/// Stopping criteria of an iterative solve.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Tolerance {
+    /// Residual norm `|b - Ax|`, in the measure of `criterion`, at which the solve has
+    /// converged.
+    residual: f64,
+    /// Relative breakdown threshold of Conjugate Gradient, see
+    /// [`SolveOptions::breakdown_tolerance`].
+    breakdown: f64,
+    /// Conjugate Gradient iterations between two recomputations of the true residual, 0 =
+    /// only to confirm convergence. See [`SolveOptions::true_residual_interval`].
+    true_residual_interval: usize,
+    /// Measure of the residual compared with `residual`.
+    criterion: ConvergenceCriterion,
+    /// Time at which the solve stops, see [`SolveOptions::time_budget`].
+    deadline: Option<Instant>,
+    /// Precision of the dot products of Conjugate Gradient.
+    extended: ExtendedPrecision,
+    /// Iteration limit and residual of the X and Y axes where they differ from those of the
+    /// solve, see [`SolveOptions::tolerance_x`].
+    axes: [AxisLimits; 2],
+}
+
+/// Iteration limit and residual tolerance of one axis, `None` where those of the solve apply.
+type AxisLimits = (Option<usize>, Option<f64>);
+
+impl Tolerance {
+    /// Iteration limit and stopping criteria of `axis`, 0 = X and 1 = Y, for a solve of
+    /// `iterations`.
+    fn axis(&self, axis: usize, iterations: usize) -> (usize, Tolerance) {
+        let (limit, residual) = self.axes[axis];
+        let tolerance = Tolerance {
+            residual: residual.unwrap_or(self.residual),
+            ..*self
+        };
+        (limit.unwrap_or(iterations), tolerance)
+    }
+
+    /// Whether the [`Tolerance::deadline`] has passed.
+    fn out_of_time(&self) -> bool {
+        self.deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+}
+
+impl From<f64> for Tolerance {
+    /// An absolute residual tolerance with the default breakdown threshold and true residual
+    /// interval.
+    fn from(residual: f64) -> Self {
+        Tolerance {
+            residual,
+            breakdown: DEFAULT_BREAKDOWN_TOLERANCE,
+            true_residual_interval: DEFAULT_TRUE_RESIDUAL_INTERVAL,
+            criterion: ConvergenceCriterion::default(),
+            deadline: None,
+            extended: ExtendedPrecision::Never,
+            axes: [(None, None); 2],
+        }
+    }
+}
+
+/// Iterations run and final residual norm of an iterative solve.
+#[derive(Debug, Clone, Copy, Default)]
+struct Convergence {
+    iterations: usize,
+    residual_norm: f64,
+    /// Final norm of the recursively updated residual.
+    recursive_residual_norm: f64,
+    /// The residual norm reached the tolerance.
+    converged: bool,
+    /// The solve was abandoned at the request of its `cancelled` callback.
+    cancelled: bool,
+    /// The solve ran out of its [`Tolerance::deadline`].
+    out_of_time: bool,
+    /// Iterations completed when Conjugate Gradient broke down.
+    breakdown: Option<usize>,
+    /// Iterations completed when the preconditioner returned a non-finite value.
+    preconditioner_failure: Option<usize>,
+    /// Convergence claims of the recursive residual that the true residual refuted.
+    restarts: usize,
+    /// Measure of the residual, see [`Tolerance::criterion`].
+    criterion: ConvergenceCriterion,
+    /// Final residual norm in the measure of `criterion`.
+    measure: f64,
+    /// Iterations of the X and Y axes, zero for a single right-hand side.
+    axis_iterations: [usize; 2],
+    /// Final residual norms of the X and Y axes, zero for a single right-hand side.
+    axis_residual_norm: [f64; 2],
+    /// Whether the X and Y axes reached the tolerance, `false` for a single right-hand side.
+    axis_converged: [bool; 2],
+    /// The initial guess already met the tolerance, so no iteration ran: for the X and Y
+    /// axes, or in the first entry for a single right-hand side. See
+    /// [`NormalEquations::converged_at`].
+    skipped: [bool; 2],
+    /// Conjugate Gradient switched to double-double accumulation, for the X and Y axes or in
+    /// the first entry for a single right-hand side. See [`ExtendedPrecision`].
+    extended: [bool; 2],
+    /// Milliseconds spent setting up the preconditioner.
+    precondition_ms: f64,
+    /// Milliseconds of the solves of the X and Y axes.
+    axis_ms: [f64; 2],
+    /// Milliseconds spent writing the solution back.
+    writeback_ms: f64,
+}
+
+impl Convergence {
+    /// Copies the convergence figures into `stats`.
+    fn record(self, stats: &mut SolveStats) {
+        stats.iterations = self.iterations.min(c_int::MAX as usize) as c_int;
+        stats.residual_norm = self.residual_norm;
+        stats.recursive_residual_norm = self.recursive_residual_norm;
+        stats.residual_restarts = self.restarts.min(c_int::MAX as usize) as c_int;
+        stats.result_quality = match self.converged && !self.cancelled {
+            true => COMPASS_RESULT_EXACT,
+            false => COMPASS_RESULT_APPROXIMATE,
+        };
+        stats.stop_reason = match self {
+            Convergence {
+                cancelled: true, ..
+            } => COMPASS_STOP_CANCELLED,
+            Convergence {
+                breakdown: Some(_), ..
+            } => COMPASS_STOP_BREAKDOWN,
+            Convergence {
+                skipped: [true, true],
+                ..
+            } => COMPASS_STOP_ALREADY_CONVERGED,
+            Convergence {
+                converged: true, ..
+            } => COMPASS_STOP_CONVERGED,
+            Convergence {
+                out_of_time: true, ..
+            } => COMPASS_STOP_TIME_BUDGET,
+            _ => COMPASS_STOP_ITERATIONS,
+        };
+        stats.breakdown_iteration = self.breakdown.unwrap_or(0).min(c_int::MAX as usize) as c_int;
+        stats.convergence_criterion = self.criterion as c_int;
+        stats.convergence_measure = self.measure;
+        let [iterations_x, iterations_y] = self
+            .axis_iterations
+            .map(|n| n.min(c_int::MAX as usize) as c_int);
+        (stats.iterations_x, stats.iterations_y) = (iterations_x, iterations_y);
+        [stats.residual_norm_x, stats.residual_norm_y] = self.axis_residual_norm;
+        stats.skipped_axes = self.skipped[0] as c_int | (self.skipped[1] as c_int) << 1;
+        stats.extended_precision = self.extended[0] as c_int | (self.extended[1] as c_int) << 1;
+        stats.precondition_ms = self.precondition_ms;
+        [stats.solve_x_ms, stats.solve_y_ms] = self.axis_ms;
+        stats.writeback_ms = self.writeback_ms;
+    }
+
+    /// Sets the final residual norm and whether it reached `tol`, the norm being divided by
+    /// `scale` in the measure of `tol`.
+    fn finish(&mut self, residual_norm: f64, scale: f64, tol: &Tolerance) {
+        self.residual_norm = residual_norm;
+        self.criterion = tol.criterion;
+        self.measure = residual_norm / scale;
+        self.converged = residual_norm <= tol.residual * scale;
+    }
+}
+
+/// The result of an iterative solve, `None` if it was cancelled.
+fn completed((x, convergence): (DVector<f64>, Convergence)) -> Option<(DVector<f64>, Convergence)> {
+    (!convergence.cancelled).then_some((x, convergence))
+}
+
+/// Row sum of the normal matrix, relative to the diagonal, above which a row is coupled to a
+/// fixed vertex. The rows of a free network sum to zero up to rounding.
+const ANCHOR_TOLERANCE: f64 = 1e-12;
+
+/// Null space of a normal matrix: one constant vector, a common translation, per connected
+/// component that no fixed vertex anchors.
+#[derive(Debug, Default)]
+struct NullSpace {
+    /// Rows of each free network.
+    components: Vec<Vec<usize>>,
+}
+
+impl NullSpace {
+    /// Finds the free networks of `a`. Rows without couplings (free vertices without enabled
+    /// edges) are left out: the solve never moves them. Zero cells, such as those of disabled
+    /// edges, couple nothing.
+    fn of(a: &CsrMatrix<f64>) -> NullSpace {
+        let (offsets, columns, values) = (a.row_offsets(), a.col_indices(), a.values());
+        let n = a.nrows();
+        let mut seen = vec![false; n];
+        let mut components = Vec::new();
+        for seed in 0..n {
+            let coupled = (offsets[seed]..offsets[seed + 1]).any(|k| values[k] != 0.0);
+            if seen[seed] || !coupled {
+                continue;
+            }
+            seen[seed] = true;
+            let mut rows = vec![seed];
+            let mut anchored = false;
+            let mut next = 0;
+            while let Some(&row) = rows.get(next) {
+                next += 1;
+                let (mut diagonal, mut sum) = (0.0, 0.0);
+                for k in offsets[row]..offsets[row + 1] {
+                    let column = columns[k];
+                    if column == row {
+                        diagonal += values[k];
+                    } else if !seen[column] && values[k] != 0.0 {
+                        seen[column] = true;
+                        rows.push(column);
+                    }
+                    sum += values[k];
+                }
+                anchored |= sum > ANCHOR_TOLERANCE * diagonal;
+            }
+            if !anchored {
+                components.push(rows);
+            }
+        }
+        NullSpace { components }
+    }
+
+    /// Number of free networks.
+    fn dimension(&self) -> usize {
+        self.components.len()
+    }
+
+    /// Removes the mean of `v` over each free network, projecting it onto the range of the
+    /// matrix.
+    fn project(&self, v: &mut DVector<f64>) {
+        for rows in &self.components {
+            let mean = rows.iter().map(|&i| v[i]).sum::<f64>() / rows.len() as f64;
+            for &i in rows {
+                v[i] -= mean;
+            }
+        }
+    }
+}
+
+/// Linear solver and preconditioner selected by `options`, `None` for an unknown code or
+/// an unsupported pair.
+fn method(options: &SolveOptions) -> Option<(LinearSolver, Preconditioner)> {
+    let solver = LinearSolver::from_code(options.solver)?;
+    let preconditioner = Preconditioner::from_code(options.preconditioner)?;
+    supported(solver, preconditioner).then_some((solver, preconditioner))
+}
+
+/// Stopping thresholds selected by `options`, or `None` for a negative or NaN breakdown
+/// threshold, time budget or per-axis limit, or an unknown convergence criterion. The
+/// deadline is counted from this call.
+fn tolerance(options: &SolveOptions) -> Option<Tolerance> {
+    let breakdown = match options.breakdown_tolerance {
+        0.0 => DEFAULT_BREAKDOWN_TOLERANCE,
+        breakdown if breakdown > 0.0 => breakdown,
+        _ => return None,
+    };
+    let true_residual_interval = match options.true_residual_interval {
+        0 => DEFAULT_TRUE_RESIDUAL_INTERVAL,
+        interval => interval.max(0) as usize,
+    };
+    let deadline = match options.time_budget {
+        0.0 => None,
+        budget if budget > 0.0 => deadline(budget),
+        _ => return None,
+    };
+    Some(Tolerance {
+        residual: options.tolerance,
+        breakdown,
+        true_residual_interval,
+        criterion: ConvergenceCriterion::from_code(options.convergence_criterion)?,
+        deadline,
+        extended: ExtendedPrecision::from_code(options.extended_precision)?,
+        axes: axis_limits(options).ok()?,
+    })
+}
+
+/// Iteration limits and residual tolerances set per axis by `options`, see
+/// [`SolveOptions::tolerance_x`], or a message naming the field of a negative or NaN value.
+fn axis_limits(options: &SolveOptions) -> Result<[AxisLimits; 2], String> {
+    let fields = [
+        ("x", options.iterations_x, options.tolerance_x),
+        ("y", options.iterations_y, options.tolerance_y),
+    ];
+    let mut axes = [(None, None); 2];
+    for (limits, (axis, iterations, tolerance)) in axes.iter_mut().zip(fields) {
+        if iterations < 0 {
+            return Err(format!("iterations_{axis} is negative ({iterations})"));
+        }
+        if tolerance < 0.0 || tolerance.is_nan() {
+            return Err(format!("tolerance_{axis} is negative or NaN ({tolerance})"));
+        }
+        *limits = (
+            (iterations > 0).then_some(iterations as usize),
+            (tolerance > 0.0).then_some(tolerance),
+        );
+    }
+    Ok(axes)
+}
+
+/// The time `seconds` from now, `None` when it is too far to represent.
+#[cfg(not(target_arch = "wasm32"))]
+fn deadline(seconds: f64) -> Option<Instant> {
+    let budget = std::time::Duration::try_from_secs_f64(seconds).ok()?;
+    Instant::now().checked_add(budget)
+}
+
+/// wasm32-unknown-unknown has no clock: the time budget is ignored.
+#[cfg(target_arch = "wasm32")]
+fn deadline(_seconds: f64) -> Option<Instant> {
+    None
+}
+
+/// Monotonic timer of a phase of the solve, see [`SolveStats::total_ms`]. It reads 0 on
+/// wasm32-unknown-unknown, which has no clock.
+#[derive(Debug, Clone, Copy)]
+struct Stopwatch(Option<Instant>);
+
+impl Stopwatch {
+    fn start() -> Stopwatch {
+        Stopwatch((!cfg!(target_arch = "wasm32")).then(Instant::now))
+    }
+
+    /// Milliseconds since the start.
+    fn ms(self) -> f64 {
+        self.0
+            .map_or(0.0, |start| start.elapsed().as_secs_f64() * 1e3)
+    }
+}
+
+/// Whether `solver` can run with `preconditioner`. Chebyshev iteration needs the eigenvalue
+/// bounds of the preconditioned matrix, which are only estimated for Jacobi scaling.
+fn supported(solver: LinearSolver, preconditioner: Preconditioner) -> bool {
+    solver == LinearSolver::ConjugateGradient
+        || matches!(
+            preconditioner,
+            Preconditioner::None | Preconditioner::Jacobi
+        )
+}
+
+/// Wraps the optional FFI cancellation flag of `options` into a predicate.
+fn cancel_flag(options: &SolveOptions) -> impl Fn() -> bool + Sync + '_ {
+    // Safety: The caller guarantees the flag outlives the solve.
+    let flag = unsafe { options.cancel.as_ref() };
+    move || flag.is_some_and(|flag| flag.load(Ordering::Relaxed) != 0)
+}
+
+/// Repositions every passive vertex as `parent + observed offset`, using the first edge that
+/// links it to a non-passive vertex, then the passive vertices reached only through placed
+/// ones, breadth-first. Passive vertices with no path to the network are left untouched.
+fn place_passive_vertices(
+    x_slice: &mut [f64],
+    y_slice: &mut [f64],
+    graph: &GraphView,
+    is_passive: &dyn Fn(usize) -> bool,
+) {
+    let n = x_slice.len();
+    let enabled = || (0..graph.from.len()).filter(|&e| graph.is_enabled(e));
+    let mut placed = vec![false; n];
+    let mut queue = std::collections::VecDeque::new();
+    for e in enabled() {
+        let u = graph.from[e] as usize;
+        let v = graph.to[e] as usize;
+        let (child, parent, sign) = match (is_passive(u), is_passive(v)) {
+            (false, true) => (v, u, 1.0),  // x_v = x_u + dx
+            (true, false) => (u, v, -1.0), // x_u = x_v - dx
+            _ => continue,
+        };
+        if placed[child] {
+            continue;
+        }
+        x_slice[child] = x_slice[parent] + sign * graph.dx[e];
+        y_slice[child] = y_slice[parent] + sign * graph.dy[e];
+        placed[child] = true;
+        queue.push_back(child);
+    }
+    if queue.is_empty() {
+        return;
+    }
+
+    let mut adjacency = vec![Vec::new(); n];
+    for e in enabled().filter(|&e| is_passive(graph.from[e] as usize)) {
+        if is_passive(graph.to[e] as usize) {
+            adjacency[graph.from[e] as usize].push(e);
+            adjacency[graph.to[e] as usize].push(e);
+        }
+    }
+    while let Some(v) = queue.pop_front() {
+        for &e in &adjacency[v] {
+            let (w, sign) = match graph.from[e] as usize == v {
+                true => (graph.to[e] as usize, 1.0),
+                false => (graph.from[e] as usize, -1.0),
+            };
+            if placed[w] {
+                continue;
+            }
+            x_slice[w] = x_slice[v] + sign * graph.dx[e];
+            y_slice[w] = y_slice[v] + sign * graph.dy[e];
+            placed[w] = true;
+            queue.push_back(w);
+        }
+    }
+}
+
+/// Re-seeds the free vertices whose initial guess is garbage, see
+/// [`SolveOptions::guess_margin`], by walking the enabled edges breadth-first from the other
+/// free and fixed vertices. Components left without a sound vertex start from the center of
+/// the fixed vertices, or the origin. Returns the re-seeded vertices, in vertex order.
+fn reseed_initial_guess(
+    x_slice: &mut [f64],
+    y_slice: &mut [f64],
+    graph: &GraphView,
+    is_passive: &dyn Fn(usize) -> bool,
+    margin: f64,
+) -> Vec<usize> {
+    let n = x_slice.len();
+    let is_fixed = |i: usize| graph.fixed[i] != 0;
+    let enabled = || (0..graph.from.len()).filter(|&e| graph.is_enabled(e));
+    let (mut low, mut high) = (
+        (f64::INFINITY, f64::INFINITY),
+        (f64::NEG_INFINITY, f64::NEG_INFINITY),
+    );
+    for i in (0..n).filter(|&i| is_fixed(i)) {
+        low = (low.0.min(x_slice[i]), low.1.min(y_slice[i]));
+        high = (high.0.max(x_slice[i]), high.1.max(y_slice[i]));
+    }
+    let anchored = low.0 <= high.0;
+    let length: f64 = enabled().map(|e| graph.dx[e].hypot(graph.dy[e])).sum();
+    let reach = margin * ((high.0 - low.0).max(high.1 - low.1) + length);
+    // Without fixed vertices, or with a negative margin, only non-finite guesses are garbage.
+    let sound = |i: usize| {
+        let (x, y) = (x_slice[i], y_slice[i]);
+        x.is_finite()
+            && y.is_finite()
+            && !(anchored
+                && margin >= 0.0
+                && (x < low.0 - reach
+                    || x > high.0 + reach
+                    || y < low.1 - reach
+                    || y > high.1 + reach))
+    };
+    let mut seeded: Vec<bool> = (0..n)
+        .map(|i| is_fixed(i) || is_passive(i) || sound(i))
+        .collect();
+    if seeded.iter().all(|&seeded| seeded) {
+        return Vec::new();
+    }
+
+    let mut adjacency = vec![Vec::new(); n];
+    for e in enabled() {
+        adjacency[graph.from[e] as usize].push(e);
+        adjacency[graph.to[e] as usize].push(e);
+    }
+    // Isolated vertices are left out of the solve, and so left alone here too.
+    for i in (0..n).filter(|&i| adjacency[i].is_empty()) {
+        seeded[i] = true;
+    }
+    let reseeded: Vec<usize> = (0..n).filter(|&i| !seeded[i]).collect();
+    // Passive vertices are placed after the solve, so nothing is propagated from them.
+    let mut queue: std::collections::VecDeque<usize> =
+        (0..n).filter(|&i| seeded[i] && !is_passive(i)).collect();
+    let center = match anchored {
+        true => ((low.0 + high.0) / 2.0, (low.1 + high.1) / 2.0),
+        false => (0.0, 0.0),
+    };
+    let mut next_root = 0;
+    loop {
+        while let Some(v) = queue.pop_front() {
+            for &e in &adjacency[v] {
+                let (w, sign) = match graph.from[e] as usize == v {
+                    true => (graph.to[e] as usize, 1.0),
+                    false => (graph.from[e] as usize, -1.0),
+                };
+                if seeded[w] {
+                    continue;
+                }
+                seeded[w] = true;
+                x_slice[w] = x_slice[v] + sign * graph.dx[e];
+                y_slice[w] = y_slice[v] + sign * graph.dy[e];
+                queue.push_back(w);
+            }
+        }
+        while next_root < n && seeded[next_root] {
+            next_root += 1;
+        }
+        if next_root == n {
+            break;
+        }
+        seeded[next_root] = true;
+        (x_slice[next_root], y_slice[next_root]) = center;
+        queue.push_back(next_root);
+    }
+    reseeded
+}
+
+/// Solves linear system Ax = b using the (preconditioned) Conjugate Gradient method.
+///
+/// Use this for Symmetric Positive Definite matrices (which the Normal Equations matrix is once
+/// every component is anchored). Over the free networks of `null_space` the matrix is only
+/// semi-definite: the residual and its preconditioned form are projected onto the range
+/// every iteration, so that the step never moves x along the null space. The result is the
+/// least squares solution closest to `x0`, the minimum-norm one in the gauge of `x0`.
+///
+/// # Arguments
+///
+/// * `a` - The matrix A (CSR format).
+/// * `b` - The RHS vector b.
+/// * `x0` - Initial guess for x.
+/// * `preconditioner` - The preconditioner M, or `None`. The solve stops at the first
+///   non-finite value it returns, see [`Convergence::preconditioner_failure`].
+/// * `null_space` - The free networks of A.
+/// * `max_iter` - Maximum number of iterations.
+/// * `tol` - Tolerance and criterion for convergence, and breakdown threshold.
+/// * `cancelled` - Polled once per iteration; the solve is abandoned when it returns true.
+///
+/// # Returns
+///
+/// * `(x, convergence)` - The solution vector x, or the last iterate if the solve ran out of
+///   iterations or was cancelled, see [`Convergence`].
+#[allow(clippy::too_many_arguments)]
+fn solve_cg(
+    a: &CsrMatrix<f64>,
+    b: &DVector<f64>,
+    x0: &DVector<f64>,
+    preconditioner: Option<&dyn sparse::Preconditioner>,
+    null_space: &NullSpace,
+    max_iter: usize,
+    tol: Tolerance,
+    cancelled: &(dyn Fn() -> bool + Sync),
+) -> (DVector<f64>, Convergence) {
+    let mut x = x0.clone();
+
+    // Dot products and step lengths are double-double once `accumulation` is extended. From
+    // then on `x` is the correction from `origin`, the iterate at which it was extended, so
+    // that the steps are not rounded to the ulp of large coordinates, and the true residual
+    // is summed in double-double.
+    let mut accumulation = match tol.extended {
+        ExtendedPrecision::Always => Accumulation::Extended,
+        _ => Accumulation::Double,
+    };
+    let mut origin = None;
+    if accumulation == Accumulation::Extended {
+        origin = Some(std::mem::replace(&mut x, DVector::zeros(x0.len())));
+    }
+    // The true residual `b - Ax`, projected as `r` is.
+    let true_residual = |origin: &Option<DVector<f64>>, x: &DVector<f64>| {
+        let mut r = match origin {
+            None => b - a * x,
+            Some(origin) => double_double::residual(a, b, origin, x),
+        };
+        null_space.project(&mut r);
+        r
+    };
+
+    // Initial residual r = b - A * x
+    // We can allow one allocation here for startup
+    let mut r = true_residual(&origin, &x);
+    let mut convergence = Convergence::default();
+
+    // Preconditioned residual z = M^-1 * r. Without preconditioner z is r itself and is not
+    // stored. Applying the preconditioner returns false if it wrote a non-finite value.
+    let precondition = |m: &dyn sparse::Preconditioner, r: &DVector<f64>, z: &mut DVector<f64>| {
+        m.apply(r.as_slice(), z.as_mut_slice());
+        null_space.project(z);
+        z.iter().all(|z| z.is_finite())
+    };
+    let mut z = preconditioner.map(|m| {
+        let mut z = DVector::zeros(r.len());
+        if !precondition(m, &r, &mut z) {
+            convergence.preconditioner_failure = Some(0);
+        }
+        z
+    });
+    if convergence.preconditioner_failure.is_some() {
+        return (x, convergence);
+    }
+
+    let mut p = z.clone().unwrap_or_else(|| r.clone());
+
+    // Pre-allocate workspace for A * p
+    let mut ap = DVector::zeros(x.len());
+    let spmv = spmv::Spmv::new(a);
+
+    convergence.extended[0] = accumulation == Accumulation::Extended;
+    let mut rho_old = accumulation.dot(&r, z.as_ref().unwrap_or(&r));
+    // The residual norm at which the criterion is met.
+    let scale = tol.criterion.scale(b, &r);
+    let threshold = tol.residual * scale;
+    // Iterations since `r` was last the true residual, and the norm of the true residual
+    // that confirmed convergence.
+    let mut drift = 0;
+    let mut confirmed = None;
+    // Smallest residual norm so far, and the iterations since it last dropped by
+    // `STAGNATION_DECREASE`.
+    let (mut best, mut stalled) = (f64::INFINITY, 0);
+
+    for _ in 0..max_iter {
+        let residual_norm = match z {
+            Some(_) => r.norm(),
+            None => rho_old.value().sqrt(),
+        };
+        // The recursively updated residual drifts from the true one over long runs, so the
+        // true residual decides convergence: it is checked every `true_residual_interval`
+        // iterations and whenever the recursive one falls below the tolerance. If the latter
+        // was wrong the iteration restarts from the true residual, as replacing it alone
+        // would leave the search direction inconsistent with it.
+        let claimed = residual_norm < threshold;
+        let interval = tol.true_residual_interval;
+        if drift > 0 && (claimed || (interval > 0 && drift >= interval)) {
+            let true_r = true_residual(&origin, &x);
+            drift = 0;
+            if true_r.norm() < threshold {
+                confirmed = Some(true_r.norm());
+                break;
+            }
+            if claimed {
+                convergence.restarts += 1;
+                r = true_r;
+                if let (Some(z), Some(m)) = (&mut z, preconditioner) {
+                    if !precondition(m, &r, z) {
+                        convergence.preconditioner_failure = Some(convergence.iterations);
+                        break;
+                    }
+                }
+                p.copy_from(z.as_ref().unwrap_or(&r));
+                rho_old = accumulation.dot(&r, &p);
+            }
+        } else if claimed {
+            break;
+        }
+        // A residual stuck short of the tolerance is taken for cancellation in the dot
+        // products or in the residual: the iteration restarts from the true residual in
+        // double-double.
+        if residual_norm < STAGNATION_DECREASE * best {
+            (best, stalled) = (residual_norm, 0);
+        } else {
+            stalled += 1;
+        }
+        if stalled >= STAGNATION_WINDOW
+            && tol.extended == ExtendedPrecision::OnStagnation
+            && accumulation == Accumulation::Double
+        {
+            accumulation = Accumulation::Extended;
+            convergence.extended[0] = true;
+            origin = Some(std::mem::replace(&mut x, DVector::zeros(x0.len())));
+            (r, drift, stalled) = (true_residual(&origin, &x), 0, 0);
+            if let (Some(z), Some(m)) = (&mut z, preconditioner) {
+                if !precondition(m, &r, z) {
+                    convergence.preconditioner_failure = Some(convergence.iterations);
+                    break;
+                }
+            }
+            p.copy_from(z.as_ref().unwrap_or(&r));
+            rho_old = accumulation.dot(&r, &p);
+        }
+        if cancelled() {
+            convergence.cancelled = true;
+            break;
+        }
+        if tol.out_of_time() {
+            convergence.out_of_time = true;
+            break;
+        }
+
+        // ap = A * p
+        // Optimized to avoid allocation
+        spmv.apply(p.as_slice(), ap.as_mut_slice());
+
+        // A vanishing direction means a vanishing residual: nothing is left to do. Otherwise
+        // pᵀAp is compared to |p| |Ap| rather than to a fixed epsilon, so that breakdown does
+        // not depend on the scale of the system. The matrix is positive semi-definite, so a
+        // negative pᵀAp is rounding noise and a breakdown as well.
+        let p_dot_ap = accumulation.dot(&p, &ap);
+        let p_norm = p.norm();
+        if p_norm == 0.0 {
+            break;
+        }
+        if p_dot_ap.value() <= tol.breakdown * p_norm * ap.norm() {
+            convergence.breakdown = Some(convergence.iterations);
+            break;
+        }
+
+        let alpha = accumulation.ratio(rho_old, p_dot_ap); // Step size alpha
+
+        // x += alpha * p
+        x.axpy(alpha, &p, 1.0);
+
+        // r -= alpha * ap
+        r.axpy(-alpha, &ap, 1.0);
+        null_space.project(&mut r);
+
+        if let (Some(z), Some(m)) = (&mut z, preconditioner) {
+            if !precondition(m, &r, z) {
+                convergence.preconditioner_failure = Some(convergence.iterations);
+                break;
+            }
+        }
+        let z_ref = z.as_ref().unwrap_or(&r);
+        let rho_new = accumulation.dot(&r, z_ref);
+        let beta = accumulation.ratio(rho_new, rho_old);
+
+        // p = z + beta * p
+        // => p = beta * p + z (in-place)
+        p.scale_mut(beta);
+        p += z_ref;
+
+        rho_old = rho_new;
+        convergence.iterations += 1;
+        drift += 1;
+    }
+    convergence.recursive_residual_norm = r.norm();
+    let residual_norm = match (confirmed, drift) {
+        (Some(norm), _) => norm,
+        (None, 0) => r.norm(),
+        (None, _) => true_residual(&origin, &x).norm(),
+    };
+    if let Some(origin) = origin {
+        x += origin;
+    }
+    convergence.finish(residual_norm, scale, &tol);
+    (x, convergence)
+}
+
+/// Number of Chebyshev iterations between two checks of the residual norm, the only dot
+/// products of the iteration.
+const CHEBYSHEV_CHECK_INTERVAL: usize = 10;
+
+/// Number of Lanczos steps estimating the smallest eigenvalue for Chebyshev iteration.
+const LANCZOS_STEPS: usize = 20;
+
+/// Solves linear system Ax = b by Chebyshev semi-iteration, optionally Jacobi-scaled.
+///
+/// `bounds` are `(lambda_min, lambda_max)` of the (scaled) matrix, see [`chebyshev_bounds`].
+/// Other arguments and the result are those of [`solve_cg`]. The residual norm is only
+/// checked every [`CHEBYSHEV_CHECK_INTERVAL`] iterations.
+#[allow(clippy::too_many_arguments)]
+fn solve_chebyshev(
+    a: &CsrMatrix<f64>,
+    b: &DVector<f64>,
+    x0: &DVector<f64>,
+    inv_diag: Option<&DVector<f64>>,
+    (lambda_min, lambda_max): (f64, f64),
+    max_iter: usize,
+    tol: Tolerance,
+    cancelled: &(dyn Fn() -> bool + Sync),
+) -> (DVector<f64>, Convergence) {
+    let center = (lambda_max + lambda_min) / 2.0;
+    let half_width = (lambda_max - lambda_min) / 2.0;
+
+    let mut x = x0.clone();
+    let mut r = b - a * &x;
+    let mut z = r.clone();
+    let mut p = DVector::zeros(x.len());
+    let mut ap = DVector::zeros(x.len());
+    let spmv = spmv::Spmv::new(a);
+    let mut alpha = 0.0;
+    let mut convergence = Convergence::default();
+    let scale = tol.criterion.scale(b, &r);
+
+    for i in 0..max_iter {
+        if i % CHEBYSHEV_CHECK_INTERVAL == 0 && r.norm() < tol.residual * scale {
+            break;
+        }
+        if cancelled() {
+            convergence.cancelled = true;
+            break;
+        }
+        if tol.out_of_time() {
+            convergence.out_of_time = true;
+            break;
+        }
+
+        match inv_diag {
+            Some(d) => precondition(&mut z, &r, d),
+            None => z.copy_from(&r),
+        }
+        if i == 0 {
+            p.copy_from(&z);
+            alpha = 1.0 / center;
+        } else {
+            let beta = if i == 1 {
+                0.5 * (half_width * alpha).powi(2)
+            } else {
+                (half_width * alpha / 2.0).powi(2)
+            };
+            alpha = 1.0 / (center - beta / alpha);
+            p.scale_mut(beta);
+            p += &z;
+        }
+
+        x.axpy(alpha, &p, 1.0);
+        spmv.apply(p.as_slice(), ap.as_mut_slice());
+        r.axpy(-alpha, &ap, 1.0);
+        convergence.iterations += 1;
+    }
+    convergence.recursive_residual_norm = r.norm();
+    convergence.finish((b - a * &x).norm(), scale, &tol);
+    (x, convergence)
+}
+
+/// Preconditioner of one solve, built from the normal matrix.
+enum PreconditionerOp {
+    /// Inverse diagonal of the matrix.
+    Jacobi(DVector<f64>),
+    Amg(amg::Amg),
+    Schwarz(schwarz::Schwarz),
+}
+
+impl PreconditionerOp {
+    /// Sets up `preconditioner` for `a`, `None` for [`Preconditioner::None`].
+    fn new(a: &CsrMatrix<f64>, preconditioner: Preconditioner) -> Option<PreconditionerOp> {
+        Some(match preconditioner {
+            Preconditioner::None => return None,
+            Preconditioner::Jacobi => PreconditionerOp::Jacobi(inverse_diagonal(a)),
+            Preconditioner::Amg { coarse_size } => {
+                PreconditionerOp::Amg(amg::Amg::new(a, coarse_size))
+            }
+            Preconditioner::AdditiveSchwarz {
+                block_size,
+                overlap,
+            } => PreconditionerOp::Schwarz(schwarz::Schwarz::new(a, block_size.max(1), overlap)),
+        })
+    }
+}
+
+impl sparse::Preconditioner for PreconditionerOp {
+    fn apply(&self, r: &[f64], z: &mut [f64]) {
+        match self {
+            PreconditionerOp::Jacobi(inv_diag) => {
+                for ((z, r), d) in z.iter_mut().zip(r).zip(inv_diag.iter()) {
+                    *z = r * d;
+                }
+            }
+            PreconditionerOp::Amg(amg) => amg.apply(r, z),
+            PreconditionerOp::Schwarz(schwarz) => schwarz.apply(r, z),
+        }
+    }
+}
+
+/// `z = inv_diag * r`, component-wise.
+fn precondition(z: &mut DVector<f64>, r: &DVector<f64>, inv_diag: &DVector<f64>) {
+    for ((z, r), d) in z.iter_mut().zip(r.iter()).zip(inv_diag.iter()) {
+        *z = r * d;
+    }
+}
+
+/// Inverse of the diagonal of `a`, for Jacobi scaling. Rows without a diagonal entry (free
+/// vertices without edges) get 1.
+fn inverse_diagonal(a: &CsrMatrix<f64>) -> DVector<f64> {
+    DVector::from_fn(a.nrows(), |row, _| inverse_diagonal_entry(a, row))
+}
+
+/// Entry of `row` of [`inverse_diagonal`].
+fn inverse_diagonal_entry(a: &CsrMatrix<f64>, row: usize) -> f64 {
+    let (offsets, columns, values) = (a.row_offsets(), a.col_indices(), a.values());
+    let diagonal: f64 = (offsets[row]..offsets[row + 1])
+        .filter(|&k| columns[k] == row)
+        .map(|k| values[k])
+        .sum();
+    if diagonal > 0.0 { 1.0 / diagonal } else { 1.0 }
+}
+
+/// Eigenvalue interval `(lambda_min, lambda_max)` of `a`, or of its Jacobi-scaled form
+/// `D^-1/2 A D^-1/2` (same spectrum as `D^-1 A`) when `inv_diag` is given.
+///
+/// The largest eigenvalue is bounded by the Gershgorin circles, so that no component is
+/// amplified. The smallest is the lowest Ritz value of a short Lanczos run, which lies above
+/// the true one: Chebyshev iteration still converges then, only more slowly on the lowest
+/// modes.
+fn chebyshev_bounds(a: &CsrMatrix<f64>, inv_diag: Option<&DVector<f64>>) -> (f64, f64) {
+    let (offsets, columns, values) = (a.row_offsets(), a.col_indices(), a.values());
+    let n = offsets.len() - 1;
+    let scale = DVector::from_fn(n, |i, _| inv_diag.map_or(1.0, |d| d[i].sqrt()));
+
+    let mut lambda_max: f64 = 0.0;
+    for (row, range) in offsets.windows(2).enumerate() {
+        let radius: f64 = (range[0]..range[1])
+            .map(|k| (values[k] * scale[row] * scale[columns[k]]).abs())
+            .sum();
+        lambda_max = lambda_max.max(radius);
+    }
+    if lambda_max == 0.0 {
+        return (1.0, 1.0);
+    }
+
+    // Lanczos on the scaled matrix from a fixed, non-symmetric start vector, so that the
+    // estimate is reproducible.
+    let mut v = DVector::from_fn(n, |i, _| 1.0 + (i % 7) as f64 / 7.0);
+    v.scale_mut(1.0 / v.norm());
+    let mut v_prev = DVector::zeros(n);
+    let mut w = DVector::zeros(n);
+    let (mut diagonal, mut off_diagonal) = (Vec::new(), Vec::new());
+    let mut beta = 0.0;
+    let spmv = spmv::Spmv::new(a);
+    for _ in 0..LANCZOS_STEPS.min(n) {
+        spmv.apply(v.component_mul(&scale).as_slice(), w.as_mut_slice());
+        for (w, s) in w.iter_mut().zip(scale.iter()) {
+            *w *= s;
+        }
+        let alpha = w.dot(&v);
+        w.axpy(-alpha, &v, 1.0);
+        w.axpy(-beta, &v_prev, 1.0);
+        diagonal.push(alpha);
+        beta = w.norm();
+        if beta <= 1e-12 * lambda_max {
+            break;
+        }
+        off_diagonal.push(beta);
+        v_prev.copy_from(&v);
+        v.copy_from(&w);
+        v.scale_mut(1.0 / beta);
+    }
+    off_diagonal.truncate(diagonal.len().saturating_sub(1));
+
+    let lambda_min = smallest_eigenvalue(&diagonal, &off_diagonal);
+    (lambda_min.clamp(1e-12 * lambda_max, lambda_max), lambda_max)
+}
+
+/// Smallest eigenvalue of the symmetric tridiagonal matrix with the given diagonal and
+/// off-diagonal, by bisection on its Sturm sequence.
+fn smallest_eigenvalue(diagonal: &[f64], off_diagonal: &[f64]) -> f64 {
+    // Number of eigenvalues below `x`.
+    let count_below = |x: f64| {
+        let mut count = 0;
+        let mut q = 1.0;
+        for (k, &d) in diagonal.iter().enumerate() {
+            let coupling = if k == 0 {
+                0.0
+            } else {
+                off_diagonal[k - 1].powi(2)
+            };
+            q = d - x - coupling / q;
+            if q == 0.0 {
+                q = f64::EPSILON;
+            }
+            if q < 0.0 {
+                count += 1;
+            }
+        }
+        count
+    };
+    let radius = |k: usize| {
+        let below = if k == 0 {
+            0.0
+        } else {
+            off_diagonal[k - 1].abs()
+        };
+        below + off_diagonal.get(k).map_or(0.0, |b| b.abs())
+    };
+    let mut lo = (0..diagonal.len())
+        .map(|k| diagonal[k] - radius(k))
+        .fold(f64::INFINITY, f64::min);
+    let mut hi = (0..diagonal.len())
+        .map(|k| diagonal[k] + radius(k))
+        .fold(f64::NEG_INFINITY, f64::max);
+    for _ in 0..100 {
+        let mid = (lo + hi) / 2.0;
+        if count_below(mid) >= 1 {
+            hi = mid;
+        } else {
+            lo = mid;
+        }
+    }
+    lo
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn network() -> Graph {
+        let mut graph = Graph::default();
+        graph.add_vertex(0.0, 0.0, 5.0, true);
+        graph.add_vertex(10.2, 0.1, 6.0, false);
+        graph.add_vertex(9.9, 10.3, 7.0, false);
+        graph.add_vertex(-0.2, 10.0, 8.0, true);
+        graph.add_edge(0, 1, 10.0, 0.0, 1.0, 1.0);
+        graph.add_edge(1, 2, 0.0, 10.0, 1.0, 0.5);
+        graph.add_edge(2, 3, -10.0, 0.0, 1.0, 2.0);
+        graph.add_edge(3, 0, 0.0, -10.0, -3.0, 1.0);
+        graph
+    }
+
+    /// Runs [`solve_graph_least_squares_ex`] over the arrays of `graph`, returning the code,
+    /// the coordinates and the stats.
+    fn solve_ex(graph: &Graph, options: &SolveOptions) -> (c_int, Vec<f64>, Vec<f64>, SolveStats) {
+        let fixed: Vec<c_int> = graph.fixed.iter().map(|&f| f as c_int).collect();
+        let from: Vec<c_int> = graph.from.iter().map(|&v| v as c_int).collect();
+        let to: Vec<c_int> = graph.to.iter().map(|&v| v as c_int).collect();
+        let (mut x, mut y) = (graph.x.clone(), graph.y.clone());
+        let mut stats = SolveStats {
+            struct_size: size_of::<SolveStats>(),
+            ..SolveStats::default()
+        };
+        let code = solve_graph_least_squares_ex(
+            graph.num_vertices() as c_int,
+            x.as_mut_ptr(),
+            y.as_mut_ptr(),
+            fixed.as_ptr(),
+            graph.num_edges() as c_int,
+            from.as_ptr(),
+            to.as_ptr(),
+            graph.dx.as_ptr(),
+            graph.dy.as_ptr(),
+            graph.weight.as_ptr(),
+            options,
+            &mut stats,
+        );
+        (code, x, y, stats)
+    }
+
+    #[test]
+    fn passive_vertices_follow_their_parent() {
+        let mut graph = network();
+        graph.add_vertex(0.0, 0.0, 0.0, false);
+        graph.add_vertex(0.0, 0.0, 0.0, false);
+        graph.add_edge(1, 4, 1.0, 2.0, 0.0, 1.0);
+        graph.add_edge(5, 2, 0.5, -0.5, 0.0, 1.0);
+        let passive = [0, 0, 0, 0, 1, 1];
+        let options = SolveOptions {
+            tolerance: 1e-12,
+            passive: passive.as_ptr(),
+            ..SolveOptions::default()
+        };
+        let (code, x, y, stats) = solve_ex(&graph, &options);
+        assert_eq!((code, stats.passive_vertices), (COMPASS_OK, 2));
+        // The network is solved as if the passive vertices were not there.
+        let (_, x_network, y_network, _) = solve_ex(&network(), &options);
+        assert_eq!((&x[..4], &y[..4]), (&x_network[..], &y_network[..]));
+        assert_eq!((x[4], y[4]), (x[1] + 1.0, y[1] + 2.0));
+        assert_eq!((x[5], y[5]), (x[2] - 0.5, y[2] + 0.5));
+    }
+
+    #[test]
+    fn chained_passive_vertices_are_placed_breadth_first() {
+        // A chain of splays 1 - 4 - 5 - 6, with 6 - 5 taken backwards, a vertex 7 tied to the
+        // end of the chain and, by a later edge, to 4, a passive vertex 8 without any edge and
+        // a passive pair 9 - 10 away from the network.
+        let mut graph = network();
+        for _ in 4..11 {
+            graph.add_vertex(-1.0, -2.0, 0.0, false);
+        }
+        graph.add_edge(6, 7, 0.0, 1.0, 0.0, 1.0);
+        graph.add_edge(4, 5, 0.5, 0.25, 0.0, 1.0);
+        graph.add_edge(6, 5, -2.0, 1.0, 0.0, 1.0);
+        graph.add_edge(1, 4, 1.0, 2.0, 0.0, 1.0);
+        graph.add_edge(9, 10, 3.0, 3.0, 0.0, 1.0);
+        graph.add_edge(4, 7, 9.0, 9.0, 0.0, 1.0);
+        let passive = [0, 0, 0, 0, 1, 1, 1, 1, 1, 1, 1];
+        let options = SolveOptions {
+            tolerance: 1e-12,
+            passive: passive.as_ptr(),
+            ..SolveOptions::default()
+        };
+        let (code, x, y, stats) = solve_ex(&graph, &options);
+        assert_eq!((code, stats.passive_vertices), (COMPASS_OK, 7));
+        assert_eq!((x[4], y[4]), (x[1] + 1.0, y[1] + 2.0));
+        assert_eq!((x[5], y[5]), (x[4] + 0.5, y[4] + 0.25));
+        assert_eq!((x[6], y[6]), (x[5] + 2.0, y[5] - 1.0));
+        // Closer to the network through 4 than through the chain.
+        assert_eq!((x[7], y[7]), (x[4] + 9.0, y[4] + 9.0));
+        for i in 8..11 {
+            assert_eq!((x[i], y[i]), (-1.0, -2.0), "vertex {i}");
+        }
+    }
+
+    #[test]
+    fn short_options_are_zero_extended() {
+        let graph = network();
+        let full = SolveOptions {
+            tolerance: 1e-12,
+            ..SolveOptions::default()
+        };
+        let (code, x, y, _) = solve_ex(&graph, &full);
+        assert_eq!(code, COMPASS_OK);
+
+        // A caller built against the first release only knows the fields up to `passive`;
+        // whatever follows in memory must be ignored.
+        let flag = AtomicI32::new(1);
+        let old = SolveOptions {
+            struct_size: SOLVE_OPTIONS_MIN_SIZE,
+            cancel: &flag,
+            ..full
+        };
+        let (code, old_x, old_y, _) = solve_ex(&graph, &old);
+        assert_eq!((code, old_x, old_y), (COMPASS_OK, x, y));
+
+        for struct_size in [0, SOLVE_OPTIONS_MIN_SIZE - 1] {
+            let options = SolveOptions {
+                struct_size,
+                ..SolveOptions::default()
+            };
+            assert_eq!(solve_ex(&graph, &options).0, COMPASS_ERR_INVALID_ARGUMENT);
+        }
+    }
+
+    #[test]
+    fn cancelled_solves_leave_the_coordinates_untouched() {
+        let graph = network();
+        let err = graph
+            .solve_cancellable(1000, 1e-12, &AtomicBool::new(true))
+            .unwrap_err();
+        assert_eq!(err.code, COMPASS_ERR_CANCELLED);
+        let solution = graph
+            .solve_cancellable(1000, 1e-12, &AtomicBool::new(false))
+            .unwrap();
+        assert_eq!(solution.x, graph.solve(1000, 1e-12).unwrap().x);
+
+        let flag = AtomicI32::new(1);
+        let options = SolveOptions {
+            cancel: &flag,
+            ..SolveOptions::default()
+        };
+        let (code, x, y, _) = solve_ex(&graph, &options);
+        assert_eq!(code, COMPASS_ERR_CANCELLED);
+        assert_eq!((x, y), (graph.x.clone(), graph.y.clone()));
+
+        // A handle keeps its previous, here unconverged, solution when a later solve is
+        // cancelled.
+        let handle = GraphContext::into_raw(graph);
+        let mut stats = SolveStats::default();
+        flag.store(0, Ordering::Relaxed);
+        let one_step = SolveOptions {
+            iterations: 1,
+            ..options
+        };
+        assert_eq!(graph_solve(handle, &one_step, &mut stats), COMPASS_OK);
+        let mut before = [0.0; 4];
+        graph_get_coordinates(
+            handle,
+            before.as_mut_ptr(),
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+        );
+        flag.store(1, Ordering::Relaxed);
+        assert_eq!(
+            graph_solve(handle, &options, &mut stats),
+            COMPASS_ERR_CANCELLED
+        );
+        let mut after = [0.0; 4];
+        graph_get_coordinates(
+            handle,
+            after.as_mut_ptr(),
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+        );
+        assert_eq!(before, after);
+        graph_free(handle);
+        assert_eq!(
+            graph_solve(std::ptr::null_mut(), &options, &mut stats),
+            COMPASS_ERR_INVALID_ARGUMENT
+        );
+    }
+
+    /// Source of [`produce_edges`]: the edges of `graph`, optionally aborting at the chunk
+    /// starting at `abort_at`, and the `(start, len)` of every request.
+    struct EdgeSource {
+        graph: Graph,
+        abort_at: Option<c_int>,
+        requests: Vec<(c_int, c_int)>,
+    }
+
+    extern "C" fn produce_edges(
+        user_data: *mut c_void,
+        start: c_int,
+        len: c_int,
+        out_from: *mut c_int,
+        out_to: *mut c_int,
+        out_dx: *mut c_double,
+        out_dy: *mut c_double,
+        out_weight: *mut c_double,
+    ) -> c_int {
+        let source = unsafe { &mut *(user_data as *mut EdgeSource) };
+        source.requests.push((start, len));
+        if source.abort_at == Some(start) {
+            return 1;
+        }
+        let g = &source.graph;
+        for i in 0..len as usize {
+            let e = start as usize + i;
+            unsafe {
+                *out_from.add(i) = g.from[e] as c_int;
+                *out_to.add(i) = g.to[e] as c_int;
+                *out_dx.add(i) = g.dx[e];
+                *out_dy.add(i) = g.dy[e];
+                *out_weight.add(i) = g.weight[e];
+            }
+        }
+        0
+    }
+
+    /// Creates a handle over the vertices of `graph` and no edges.
+    fn vertices_only(graph: &Graph) -> *mut GraphContext {
+        let fixed: Vec<c_int> = graph.fixed.iter().map(|&f| f as c_int).collect();
+        graph_from_vertices(
+            graph.num_vertices() as c_int,
+            graph.x.as_ptr(),
+            graph.y.as_ptr(),
+            fixed.as_ptr(),
+        )
+    }
+
+    /// Streams the edges of `source` into `handle` in chunks of `chunk_size`.
+    fn stream(handle: *mut GraphContext, source: &mut EdgeSource, chunk_size: c_int) -> c_int {
+        let count = source.graph.num_edges() as c_int;
+        let user_data = source as *mut EdgeSource as *mut c_void;
+        graph_add_edges_streamed(handle, count, Some(produce_edges), user_data, chunk_size)
+    }
+
+    /// Solves the graph behind `handle`, returning the code and the adjusted coordinates.
+    fn solve_handle(
+        handle: *mut GraphContext,
+        options: &SolveOptions,
+    ) -> (c_int, Vec<f64>, Vec<f64>) {
+        let n = graph_num_vertices(handle) as usize;
+        let code = graph_solve(handle, options, std::ptr::null_mut());
+        let (mut x, mut y) = (vec![0.0; n], vec![0.0; n]);
+        graph_get_coordinates(handle, x.as_mut_ptr(), y.as_mut_ptr(), std::ptr::null_mut());
+        (code, x, y)
+    }
+
+    #[test]
+    fn streamed_edges_solve_like_the_arrays() {
+        let graph = network();
+        let options = SolveOptions {
+            tolerance: 1e-12,
+            ..SolveOptions::default()
+        };
+        let (code, x, y, _) = solve_ex(&graph, &options);
+        assert_eq!(code, COMPASS_OK);
+
+        for chunk_size in [1, 3, 4, 100] {
+            let handle = vertices_only(&graph);
+            let mut source = EdgeSource {
+                graph: graph.clone(),
+                abort_at: None,
+                requests: Vec::new(),
+            };
+            assert_eq!(stream(handle, &mut source, chunk_size), COMPASS_OK);
+            let expected: Vec<_> = (0..4)
+                .step_by(chunk_size as usize)
+                .map(|start| (start, chunk_size.min(4 - start)))
+                .collect();
+            assert_eq!(source.requests, expected);
+            assert_eq!(graph_num_edges(handle), 4);
+            assert_eq!(graph_content_hash(handle), graph.content_hash());
+
+            let (code, handle_x, handle_y) = solve_handle(handle, &options);
+            assert_eq!(code, COMPASS_OK);
+            for i in 0..4 {
+                assert!((handle_x[i] - x[i]).abs() < 1e-9 && (handle_y[i] - y[i]).abs() < 1e-9);
+            }
+            graph_free(handle);
+        }
+    }
+
+    #[test]
+    fn failed_streams_leave_the_handle_unchanged() {
+        let graph = network();
+        let options = SolveOptions {
+            tolerance: 1e-12,
+            ..SolveOptions::default()
+        };
+        let handle = vertices_only(&graph);
+        let mut source = EdgeSource {
+            graph: graph.clone(),
+            abort_at: None,
+            requests: Vec::new(),
+        };
+        assert_eq!(stream(handle, &mut source, 2), COMPASS_OK);
+        let solved = solve_handle(handle, &options);
+        assert_eq!(solved.0, COMPASS_OK);
+
+        // The second chunk aborts after the first one was appended.
+        let mut aborting = EdgeSource {
+            graph: graph.clone(),
+            abort_at: Some(2),
+            requests: Vec::new(),
+        };
+        assert_eq!(stream(handle, &mut aborting, 2), COMPASS_ERR_CANCELLED);
+        assert_eq!(aborting.requests, [(0, 2), (2, 2)]);
+        assert_eq!(graph_num_edges(handle), 4);
+        assert_eq!(graph_content_hash(handle), graph.content_hash());
+
+        // So does a vertex index past the end, or a negative one, in the second chunk.
+        // `usize::MAX` reaches the producer as -1.
+        for bad in [4, usize::MAX] {
+            let mut out_of_range = EdgeSource {
+                graph: graph.clone(),
+                abort_at: None,
+                requests: Vec::new(),
+            };
+            out_of_range.graph.to[3] = bad;
+            assert_eq!(
+                stream(handle, &mut out_of_range, 2),
+                COMPASS_ERR_INVALID_ARGUMENT
+            );
+            assert_eq!(graph_num_edges(handle), 4);
+            assert_eq!(graph_content_hash(handle), graph.content_hash());
+        }
+
+        // The failures kept the previous solution and the handle still solves the same.
+        let mut x = vec![0.0; 4];
+        graph_get_coordinates(
+            handle,
+            x.as_mut_ptr(),
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+        );
+        assert_eq!(x, solved.1);
+        assert_eq!(solve_handle(handle, &options), solved);
+
+        let user_data = &mut source as *mut EdgeSource as *mut c_void;
+        for (count, chunk_size) in [(-1, 1), (1, 0)] {
+            let code =
+                graph_add_edges_streamed(handle, count, Some(produce_edges), user_data, chunk_size);
+            assert_eq!(code, COMPASS_ERR_INVALID_ARGUMENT);
+        }
+        assert_eq!(
+            graph_add_edges_streamed(handle, 1, None, user_data, 1),
+            COMPASS_ERR_INVALID_ARGUMENT
+        );
+        graph_free(handle);
+    }
+
+    #[test]
+    fn failures_report_what_the_coordinates_hold() {
+        let graph = grid(6);
+        let untouched = (graph.x.clone(), graph.y.clone());
+
+        // Validation errors write nothing.
+        let mut broken = graph.clone();
+        broken.to[3] = 99;
+        let (code, x, y, stats) = solve_ex(&broken, &SolveOptions::default());
+        assert_eq!(code, COMPASS_ERR_INVALID_ARGUMENT);
+        assert_eq!((x, y), untouched);
+        assert_eq!(stats.result_quality, COMPASS_RESULT_UNTOUCHED);
+
+        // A converged solve is exact.
+        let exact = SolveOptions {
+            tolerance: 1e-9,
+            ..SolveOptions::default()
+        };
+        let (code, x_exact, _, stats) = solve_ex(&graph, &exact);
+        assert_eq!(
+            (code, stats.result_quality),
+            (COMPASS_OK, COMPASS_RESULT_EXACT)
+        );
+
+        // Running out of iterations still succeeds, with the last iterate written.
+        let short = SolveOptions {
+            iterations: 2,
+            ..exact
+        };
+        let (code, x, _, stats) = solve_ex(&graph, &short);
+        assert_eq!(
+            (code, stats.result_quality),
+            (COMPASS_OK, COMPASS_RESULT_APPROXIMATE)
+        );
+        assert_eq!(stats.iterations, 2);
+        assert_ne!(x, untouched.0);
+        assert_ne!(x, x_exact);
+
+        // A cancel during the solve reports it, with the iterate reached so far written.
+        let system = graph.normal_equations(&[]).unwrap();
+        let (mut x, mut y) = untouched.clone();
+        let polls = std::sync::atomic::AtomicUsize::new(0);
+        let cancelled = || polls.fetch_add(1, Ordering::Relaxed) >= 6;
+        let method = Default::default();
+        let convergence = system.solve(&mut x, &mut y, 1000, 1e-12.into(), method, &cancelled);
+        assert!(convergence.cancelled && !convergence.converged);
+        assert!(convergence.iterations > 0);
+        assert_ne!(x, untouched.0);
+        let mut stats = SolveStats::default();
+        convergence.record(&mut stats);
+        assert_eq!(stats.result_quality, COMPASS_RESULT_APPROXIMATE);
+
+        // A cancel before the solve starts, here in the extra solve of a centroid hold,
+        // leaves the coordinates untouched.
+        let flag = AtomicI32::new(1);
+        let weights = vec![1.0; graph.num_vertices()];
+        let held = SolveOptions {
+            cancel: &flag,
+            centroid_weights: weights.as_ptr(),
+            centroid_stiffness: 1.0,
+            ..exact
+        };
+        let (code, x, y, stats) = solve_ex(&graph, &held);
+        assert_eq!(code, COMPASS_ERR_CANCELLED);
+        assert_eq!((x, y), untouched);
+        assert_eq!(stats.result_quality, COMPASS_RESULT_UNTOUCHED);
+    }
+
+    #[test]
+    fn fixed_coordinates_come_back_bit_for_bit() {
+        let mut graph = network();
+        // Values whose bits a round trip through arithmetic would likely change.
+        (graph.x[0], graph.y[0]) = (-0.0, f64::MIN_POSITIVE / 4.0);
+        (graph.x[3], graph.y[3]) = (0.1 + 0.2, 1e12 + 0.1);
+        graph.dy[3] = -10.0 - 1e12;
+        let mut message = [0x55 as c_char; 64];
+        let options = SolveOptions {
+            verify_fixed: 1,
+            error_message: message.as_mut_ptr(),
+            error_capacity: message.len() as c_int,
+            ..SolveOptions::default()
+        };
+        let (code, x, y, stats) = solve_ex(&graph, &options);
+        assert_eq!(code, COMPASS_OK);
+        // Written, though the true residual cannot reach the tolerance at 1e12.
+        assert_eq!(stats.result_quality, COMPASS_RESULT_APPROXIMATE);
+        for i in [0, 3] {
+            assert_eq!(x[i].to_bits(), graph.x[i].to_bits(), "x[{i}]");
+            assert_eq!(y[i].to_bits(), graph.y[i].to_bits(), "y[{i}]");
+        }
+        assert!(
+            message.iter().all(|&c| c == 0x55),
+            "no message without a violation"
+        );
+    }
+
+    #[test]
+    fn negative_counts_and_null_arrays_are_rejected() {
+        let graph = network();
+        let options = SolveOptions::default();
+        for (num_vertices, num_edges) in [(-1, 4), (4, -1), (c_int::MIN, 0)] {
+            let mut x = graph.x.clone();
+            let mut y = graph.y.clone();
+            let fixed: Vec<c_int> = graph.fixed.iter().map(|&f| f as c_int).collect();
+            let from: Vec<c_int> = graph.from.iter().map(|&v| v as c_int).collect();
+            let to: Vec<c_int> = graph.to.iter().map(|&v| v as c_int).collect();
+            let code = solve_graph_least_squares_ex(
+                num_vertices,
+                x.as_mut_ptr(),
+                y.as_mut_ptr(),
+                fixed.as_ptr(),
+                num_edges,
+                from.as_ptr(),
+                to.as_ptr(),
+                graph.dx.as_ptr(),
+                graph.dy.as_ptr(),
+                graph.weight.as_ptr(),
+                &options,
+                std::ptr::null_mut(),
+            );
+            assert_eq!(code, COMPASS_ERR_INVALID_ARGUMENT);
+            assert_eq!((x, y), (graph.x.clone(), graph.y.clone()));
+            let hash = graph_content_hash_arrays(
+                num_vertices,
+                graph.x.as_ptr(),
+                graph.y.as_ptr(),
+                fixed.as_ptr(),
+                num_edges,
+                from.as_ptr(),
+                to.as_ptr(),
+                graph.dx.as_ptr(),
+                graph.dy.as_ptr(),
+                graph.weight.as_ptr(),
+            );
+            assert_eq!(hash, 0);
+        }
+        assert!(
+            graph_from_vertices(-1, graph.x.as_ptr(), graph.y.as_ptr(), std::ptr::null()).is_null()
+        );
+
+        // An edge count whose 4x triplet sizing wraps in 32-bit arithmetic, with no arrays
+        // behind it: rejected before anything is read or allocated.
+        let code = solve_graph_least_squares_ex(
+            0,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+            std::ptr::null(),
+            0x4000_0001,
+            std::ptr::null(),
+            std::ptr::null(),
+            std::ptr::null(),
+            std::ptr::null(),
+            std::ptr::null(),
+            &options,
+            std::ptr::null_mut(),
+        );
+        assert_eq!(code, COMPASS_ERR_INVALID_ARGUMENT);
+        // An empty graph reads none of its null arrays.
+        let code = solve_graph_least_squares_ex(
+            0,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+            std::ptr::null(),
+            0,
+            std::ptr::null(),
+            std::ptr::null(),
+            std::ptr::null(),
+            std::ptr::null(),
+            std::ptr::null(),
+            &options,
+            std::ptr::null_mut(),
+        );
+        assert_eq!(code, COMPASS_OK);
+    }
+
+    #[test]
+    fn oversized_buffers_fail_instead_of_aborting() {
+        // 0x4000_0001 edges take 0x1_0000_0004 triplets, which 32-bit arithmetic wraps to 4.
+        match usize::BITS {
+            64 => assert_eq!(coo_terms(0x4000_0001), Ok(0x1_0000_0004)),
+            _ => assert_eq!(coo_terms(0x4000_0001), Err(COMPASS_ERR_OUT_OF_MEMORY)),
+        }
+        assert_eq!(coo_terms(usize::MAX / 2), Err(COMPASS_ERR_OUT_OF_MEMORY));
+        // More bytes than an allocation may span: refused up front rather than aborting.
+        assert_eq!(
+            try_filled(usize::MAX / 4, 0.0f64).unwrap_err(),
+            COMPASS_ERR_OUT_OF_MEMORY
+        );
+        assert_eq!(
+            try_filled(usize::MAX, Some(0usize)).unwrap_err(),
+            COMPASS_ERR_OUT_OF_MEMORY
+        );
+    }
+
+    #[test]
+    fn breakdown_is_relative_and_reported() {
+        // Two free stations on stiff and loose shots: the first search direction is the
+        // residual (1000, 1), nearly orthogonal to its image (1000, 1e6) under the diagonal
+        // matrix diag(1, 1e6), with a cosine of about 2e-3.
+        let network = |scale: f64| {
+            let mut graph = Graph::default();
+            graph.add_vertex(0.0, 0.0, 0.0, true);
+            graph.add_vertex(0.0, 0.0, 0.0, false);
+            graph.add_vertex(0.0, 0.0, 0.0, false);
+            graph.add_edge(0, 1, 1000.0, 0.0, 0.0, scale);
+            graph.add_edge(0, 2, 1e-6, 0.0, 0.0, 1e6 * scale);
+            graph
+        };
+        let options = |breakdown_tolerance| SolveOptions {
+            tolerance: 1e-12,
+            breakdown_tolerance,
+            ..SolveOptions::default()
+        };
+
+        let (code, x, _, stats) = solve_ex(&network(1.0), &options(0.0));
+        assert_eq!(code, COMPASS_OK);
+        assert_eq!(stats.stop_reason, COMPASS_STOP_CONVERGED);
+        assert_eq!(stats.breakdown_iteration, 0);
+        assert!((x[1] - 1000.0).abs() < 1e-9 && (x[2] - 1e-6).abs() < 1e-15);
+
+        // The same cosine at every scale of the weights.
+        for scale in [1e-12, 1.0, 1e12] {
+            let (code, x, _, stats) = solve_ex(&network(scale), &options(1e-2));
+            assert_eq!(code, COMPASS_OK, "scale {scale}");
+            assert_eq!(stats.stop_reason, COMPASS_STOP_BREAKDOWN, "scale {scale}");
+            assert_eq!(stats.breakdown_iteration, 0);
+            assert_eq!(stats.result_quality, COMPASS_RESULT_APPROXIMATE);
+            assert_eq!(x[1..], [0.0, 0.0], "the starting point comes back");
+        }
+
+        let limited = SolveOptions {
+            iterations: 1,
+            ..options(0.0)
+        };
+        let (_, _, _, stats) = solve_ex(&network(1.0), &limited);
+        assert_eq!(stats.stop_reason, COMPASS_STOP_ITERATIONS);
+        for invalid in [-1e-3, f64::NAN] {
+            let (code, _, _, _) = solve_ex(&network(1.0), &options(invalid));
+            assert_eq!(code, COMPASS_ERR_INVALID_ARGUMENT);
+        }
+    }
+
+    #[test]
+    fn extended_precision_engages_on_stagnation() {
+        // Two anchors a micrometre apart at UTM-like eastings, joined by shots whose weights
+        // alternate between 1e4 and 1e-4: in f64 the true residual is rounding noise far
+        // above the absolute tolerance.
+        let mut stiff = Graph::default();
+        stiff.add_vertex(5e6, 0.0, 0.0, true);
+        for i in 1..60 {
+            stiff.add_vertex(0.0, 0.0, 0.0, false);
+            let weight = if i % 2 == 0 { 1e4 } else { 1e-4 };
+            stiff.add_edge(i - 1, i, 1e-8, 0.0, 0.0, weight);
+        }
+        stiff.add_vertex(5e6 + 1e-6, 0.0, 0.0, true);
+        stiff.add_edge(59, 60, 1e-8, 0.0, 0.0, 1e4);
+        let options = |extended_precision| SolveOptions {
+            iterations: 2000,
+            tolerance: 1e-10,
+            extended_precision,
+            ..SolveOptions::default()
+        };
+
+        let (_, _, _, stats) = solve_ex(&stiff, &options(0));
+        assert_eq!(stats.stop_reason, COMPASS_STOP_ITERATIONS);
+        assert!(stats.residual_norm > 1e-6, "{stats:?}");
+        assert_eq!(stats.extended_precision, 0);
+        // The Y axis has nothing to solve, so only X switches.
+        for extended_precision in [1, 2] {
+            let (code, x, _, stats) = solve_ex(&stiff, &options(extended_precision));
+            assert_eq!(code, COMPASS_OK);
+            assert_eq!(stats.stop_reason, COMPASS_STOP_CONVERGED, "{stats:?}");
+            assert!(stats.residual_norm < 1e-10);
+            assert_eq!(stats.extended_precision, 1);
+            assert!(x.iter().all(|x| x.is_finite()));
+        }
+
+        // A solve that converges never switches and is unchanged to the last bit; in
+        // double-double throughout it reaches the same solution.
+        let graph = chain_with_loops(200);
+        let (_, x0, y0, plain) = solve_ex(&graph, &options(0));
+        let (_, x1, y1, stats) = solve_ex(&graph, &options(1));
+        assert_eq!(plain.stop_reason, COMPASS_STOP_CONVERGED);
+        assert_eq!(stats.extended_precision, 0);
+        assert_eq!((stats.iterations, &x1, &y1), (plain.iterations, &x0, &y0));
+        let (_, x2, y2, stats) = solve_ex(&graph, &options(2));
+        assert_eq!(stats.stop_reason, COMPASS_STOP_CONVERGED);
+        assert_eq!(stats.extended_precision, 3);
+        for v in 0..graph.num_vertices() {
+            assert!((x2[v] - x0[v]).abs() < 1e-6 && (y2[v] - y0[v]).abs() < 1e-6);
+        }
+
+        let (code, _, _, _) = solve_ex(&graph, &options(3));
+        assert_eq!(code, COMPASS_ERR_INVALID_ARGUMENT);
+    }
+
+    #[test]
+    fn absurd_initial_guesses_are_reseeded() {
+        let graph = chain_with_loops(200);
+        let options = SolveOptions {
+            tolerance: 1e-10,
+            ..SolveOptions::default()
+        };
+        let (_, x, y, clean) = solve_ex(&graph, &options);
+        assert_eq!(clean.reseeded_vertices, 0);
+        let expected = Solution {
+            x,
+            y,
+            ..Solution::default()
+        };
+
+        let mut garbage = graph.clone();
+        garbage.x[57] = 1e12;
+        garbage.y[58] = -1e12;
+        garbage.x[120] = f64::NAN;
+        garbage.y[121] = f64::INFINITY;
+        let (code, x, y, stats) = solve_ex(&garbage, &options);
+        assert_eq!(code, COMPASS_OK);
+        assert_eq!(stats.reseeded_vertices, 4);
+        assert_eq!(stats.result_quality, COMPASS_RESULT_EXACT);
+        assert_coordinates_close((&x, &y), &expected, 1e-8);
+
+        // A negative margin only discards the non-finite guesses.
+        let finite_only = SolveOptions {
+            guess_margin: -1.0,
+            ..options
+        };
+        let (_, _, _, stats) = solve_ex(&garbage, &finite_only);
+        assert_eq!(stats.reseeded_vertices, 2);
+        let nan = SolveOptions {
+            guess_margin: f64::NAN,
+            ..options
+        };
+        assert_eq!(solve_ex(&garbage, &nan).0, COMPASS_ERR_INVALID_ARGUMENT);
+    }
+
+    /// Message written to a 64-byte [`SolveOptions::error_message`] by a solve of `graph`.
+    fn solve_message(graph: &Graph, options: &SolveOptions) -> (c_int, String) {
+        let mut message = [0 as c_char; 64];
+        let options = SolveOptions {
+            error_message: message.as_mut_ptr(),
+            error_capacity: message.len() as c_int,
+            ..*options
+        };
+        let (code, x, y, stats) = solve_ex(graph, &options);
+        if code != COMPASS_OK {
+            assert_eq!(stats.result_quality, COMPASS_RESULT_UNTOUCHED);
+            assert_eq!((x, y), (graph.x.clone(), graph.y.clone()));
+        }
+        let message = unsafe { std::ffi::CStr::from_ptr(message.as_ptr()) };
+        (code, message.to_str().unwrap().to_string())
+    }
+
+    #[test]
+    fn matrix_stats_describe_the_normal_equations() {
+        // A path 0 - 1 - 2 - 3 anchored at 0, vertex 4 without any edge, and vertex 5 tied to
+        // 3 by a shot of zero weight.
+        let mut graph = Graph::default();
+        for i in 0..6 {
+            graph.add_vertex(10.0 * i as f64, 0.0, 0.0, i == 0);
+        }
+        for i in 0..3 {
+            graph.add_edge(i, i + 1, 10.0, 0.0, 0.0, 1.0 + i as f64);
+        }
+        graph.add_edge(3, 5, 20.0, 0.0, 0.0, 0.0);
+        let handle = GraphContext::into_raw(graph.clone());
+        let mut stats = MatrixStats::default();
+        assert_eq!(graph_matrix_stats(handle, &mut stats), COMPASS_OK);
+        // Rows 1, 2, 3, 5: diagonals 1 + 2, 2 + 3, 3 + 0 and 0. Vertex 4 is left out.
+        let expected = MatrixStats {
+            rows: 4,
+            nonzeros: 7,
+            mean_degree: 1.0,
+            max_degree: 2,
+            dominance: 1.0,
+            min_diagonal: 0.0,
+            max_diagonal: 5.0,
+            empty_rows: 1,
+        };
+        assert_eq!(stats, expected);
+
+        // Disabling edge 2 - 3 empties row 3, and leaves zero cells that do not count.
+        let ctx = unsafe { &mut *handle };
+        ctx.set_edge_enabled(2, false).unwrap();
+        assert_eq!(
+            ctx.matrix_stats().unwrap(),
+            MatrixStats {
+                nonzeros: 4,
+                mean_degree: 0.5,
+                max_degree: 1,
+                max_diagonal: 3.0,
+                empty_rows: 2,
+                ..expected
+            }
+        );
+        graph_free(handle);
+        assert_eq!(
+            graph_matrix_stats(std::ptr::null_mut(), &mut stats),
+            COMPASS_ERR_INVALID_ARGUMENT
+        );
+
+        // The main solve names the isolated vertex first, then the empty row.
+        let options = SolveOptions::default();
+        assert_eq!(
+            solve_message(&graph, &options),
+            (
+                COMPASS_ERR_ISOLATED_VERTEX,
+                "isolated free vertices 4".to_string()
+            )
+        );
+        let keep = SolveOptions {
+            keep_isolated: 1,
+            ..options
+        };
+        assert_eq!(
+            solve_message(&graph, &keep),
+            (
+                COMPASS_ERR_INVALID_ARGUMENT,
+                "free vertex 5 has only edges of zero weight".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn isolated_free_vertices_are_reported_or_kept() {
+        // Vertex 4 is referenced by no edge, vertex 5 only by the disabled edge 4.
+        let mut graph = network();
+        graph.add_vertex(50.0, 50.0, 0.0, false);
+        graph.add_vertex(60.0, 60.0, 0.0, false);
+        graph.add_edge(1, 5, 10.0, 10.0, 0.0, 1.0);
+        let enabled: [c_int; 5] = [1, 1, 1, 1, 0];
+        let options = SolveOptions {
+            tolerance: 1e-12,
+            edge_enabled: enabled.as_ptr(),
+            ..SolveOptions::default()
+        };
+        assert_eq!(
+            solve_message(&graph, &options),
+            (
+                COMPASS_ERR_ISOLATED_VERTEX,
+                "isolated free vertices 4, 5".to_string()
+            )
+        );
+
+        let expected = network().solve(10_000, 1e-12).unwrap();
+        for preconditioner in [0, 1] {
+            let keep = SolveOptions {
+                keep_isolated: 1,
+                preconditioner,
+                ..options
+            };
+            let (code, x, y, stats) = solve_ex(&graph, &keep);
+            assert_eq!(code, COMPASS_OK);
+            assert_eq!(stats.isolated_vertices, 2);
+            assert_eq!(stats.result_quality, COMPASS_RESULT_EXACT);
+            assert_eq!((&x[4..], &y[4..]), (&graph.x[4..], &graph.y[4..]));
+            assert_coordinates_close((&x[..4], &y[..4]), &expected, 1e-9);
+        }
+
+        // The context API keeps them, and reassembles when an edge isolates or reconnects one.
+        let solve = |ctx: &mut GraphContext| {
+            let solution = ctx
+                .solve(10_000, 1e-12.into(), Default::default(), None, &|| false)
+                .unwrap();
+            solution.clone()
+        };
+        let mut ctx = unsafe { *Box::from_raw(GraphContext::into_raw(graph.clone())) };
+        let connected = graph.solve(10_000, 1e-12).unwrap();
+        assert_eq!(solve(&mut ctx).stats.isolated_vertices, 1);
+        ctx.set_edge_enabled(4, false).unwrap();
+        let isolated = solve(&mut ctx);
+        assert_eq!(isolated.stats.isolated_vertices, 2);
+        assert_eq!((isolated.x[5], isolated.y[5]), (60.0, 60.0));
+        ctx.set_edge_enabled(4, true).unwrap();
+        let reconnected = solve(&mut ctx);
+        assert_eq!(reconnected.stats.isolated_vertices, 1);
+        assert_coordinates_close((&reconnected.x, &reconnected.y), &connected, 1e-9);
+    }
+
+    #[test]
+    fn snapshots_never_mix_two_publications() {
+        /// The handle, shared with the reader thread as the FFI would.
+        struct Shared(*const GraphContext);
+        unsafe impl Send for Shared {}
+        unsafe impl Sync for Shared {}
+
+        let graph = grid(12);
+        let n = graph.num_vertices();
+        let handle = GraphContext::into_raw(graph.clone());
+        let snapshot = |handle: *const GraphContext| {
+            let (mut x, mut y, mut generation) = (vec![0.0; n], vec![0.0; n], 0);
+            let code = graph_get_coordinates_snapshot(
+                handle,
+                x.as_mut_ptr(),
+                y.as_mut_ptr(),
+                &mut generation,
+            );
+            assert_eq!(code, COMPASS_OK);
+            (generation, x, y)
+        };
+        let mut published = vec![snapshot(handle)];
+        assert_eq!(published[0], (1, graph.x.clone(), graph.y.clone()));
+
+        let done = AtomicBool::new(false);
+        let shared = Shared(handle);
+        let seen = std::thread::scope(|scope| {
+            let reader = scope.spawn(|| {
+                let shared = &shared;
+                let mut seen = Vec::new();
+                while !done.load(Ordering::Acquire) {
+                    seen.push(snapshot(shared.0));
+                }
+                seen
+            });
+            // Toggling an edge moves the solution back and forth between two networks.
+            let options = SolveOptions::default();
+            for round in 0..20 {
+                let ctx = unsafe { &mut *handle };
+                ctx.set_edge_enabled(7, round % 2 == 1).unwrap();
+                assert_eq!(
+                    graph_solve(handle, &options, std::ptr::null_mut()),
+                    COMPASS_OK
+                );
+                let (x, y) = ctx.coordinates();
+                published.push((round + 2, x.to_vec(), y.to_vec()));
+            }
+            done.store(true, Ordering::Release);
+            reader.join().unwrap()
+        });
+        assert_eq!(snapshot(handle).0, 21);
+        assert!(!seen.is_empty());
+        for (generation, x, y) in seen {
+            let (_, px, py) = &published[generation as usize - 1];
+            assert!(x == *px && y == *py, "generation {generation} torn");
+        }
+        graph_free(handle);
+        assert_eq!(
+            graph_get_coordinates_snapshot(
+                std::ptr::null(),
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+                std::ptr::null_mut()
+            ),
+            COMPASS_ERR_INVALID_ARGUMENT
+        );
+    }
+
+    #[test]
+    fn content_hash_is_pinned_across_platforms() {
+        // Little-endian bit patterns through FNV-1a: this value must never change.
+        assert_eq!(network().content_hash(), 0x88f8_cc7e_7cb9_fade);
+        assert_eq!(Graph::default().content_hash(), 0x8820_1fb9_60ff_6465);
+    }
+
+    #[test]
+    fn content_hash_ignores_edge_order_but_not_edge_direction() {
+        let graph = network();
+        let mut permuted = Graph::default();
+        for i in 0..graph.num_vertices() {
+            permuted.add_vertex(graph.x[i], graph.y[i], graph.z[i], graph.fixed[i]);
+        }
+        for e in [2, 0, 3, 1] {
+            permuted.add_edge(
+                graph.from[e],
+                graph.to[e],
+                graph.dx[e],
+                graph.dy[e],
+                graph.dz[e],
+                graph.weight[e],
+            );
+        }
+        assert_eq!(permuted.content_hash(), graph.content_hash());
+
+        let mut reversed = graph.clone();
+        reversed.from[1] = 2;
+        reversed.to[1] = 1;
+        reversed.dx[1] = -reversed.dx[1];
+        reversed.dy[1] = -reversed.dy[1];
+        assert_ne!(reversed.content_hash(), graph.content_hash());
+    }
+
+    #[test]
+    fn content_hash_covers_what_the_adjustment_depends_on() {
+        let graph = network();
+        let hash = graph.content_hash();
+        let changed = |edit: fn(&mut Graph)| {
+            let mut graph = network();
+            edit(&mut graph);
+            graph.content_hash()
+        };
+
+        assert_ne!(changed(|g| g.x[0] += 1e-9), hash);
+        assert_ne!(changed(|g| g.y[3] = -g.y[3]), hash);
+        assert_ne!(changed(|g| g.fixed[1] = true), hash);
+        assert_ne!(changed(|g| g.weight[2] = 2.5), hash);
+        assert_ne!(changed(|g| g.dy[0] = 1e-12), hash);
+        assert_ne!(changed(|g| g.to[0] = 2), hash);
+        assert_ne!(
+            changed(|g| {
+                g.add_vertex(0.0, 0.0, 0.0, false);
+            }),
+            hash
+        );
+
+        assert_eq!(changed(|g| g.x[1] += 100.0), hash);
+        assert_eq!(changed(|g| g.z[0] = -1.0), hash);
+        assert_eq!(changed(|g| g.dz[3] = 0.0), hash);
+        assert_eq!(changed(|g| g.names = Some(vec!["A".to_string(); 4])), hash);
+    }
+
+    #[test]
+    fn content_hash_ffi_variants_agree() {
+        let graph = network();
+        let fixed: Vec<c_int> = graph.fixed.iter().map(|&f| f as c_int).collect();
+        let from: Vec<c_int> = graph.from.iter().map(|&v| v as c_int).collect();
+        let to: Vec<c_int> = graph.to.iter().map(|&v| v as c_int).collect();
+        let hash = graph_content_hash_arrays(
+            4,
+            graph.x.as_ptr(),
+            graph.y.as_ptr(),
+            fixed.as_ptr(),
+            4,
+            from.as_ptr(),
+            to.as_ptr(),
+            graph.dx.as_ptr(),
+            graph.dy.as_ptr(),
+            graph.weight.as_ptr(),
+        );
+        assert_eq!(hash, graph.content_hash());
+
+        // Null arrays hash as empty ones when their count is 0, and are rejected otherwise.
+        let (null_int, null_double) = (std::ptr::null::<c_int>(), std::ptr::null::<c_double>());
+        let hash_vertices_only = |num_edges| {
+            graph_content_hash_arrays(
+                4,
+                graph.x.as_ptr(),
+                graph.y.as_ptr(),
+                fixed.as_ptr(),
+                num_edges,
+                null_int,
+                null_int,
+                null_double,
+                null_double,
+                null_double,
+            )
+        };
+        let mut vertices_only = Graph::default();
+        for v in 0..4 {
+            vertices_only.add_vertex(graph.x[v], graph.y[v], graph.z[v], graph.fixed[v]);
+        }
+        assert_eq!(hash_vertices_only(0), vertices_only.content_hash());
+        assert_eq!(hash_vertices_only(1), 0);
+
+        let handle = GraphContext::into_raw(graph.clone());
+        assert_eq!(graph_content_hash(handle), graph.content_hash());
+        graph_free(handle);
+        assert_eq!(graph_content_hash(std::ptr::null()), 0);
+    }
+
+    /// `side` x `side` grid with slightly inconsistent observations, fixed at vertex 0.
+    fn grid(side: usize) -> Graph {
+        let noise = |k: usize| ((k * 7919) % 13) as f64 * 0.01 - 0.06;
+        let mut graph = Graph::default();
+        for i in 0..side * side {
+            let (row, col) = (i / side, i % side);
+            graph.add_vertex(10.0 * col as f64, 10.0 * row as f64, 0.0, i == 0);
+        }
+        for i in 0..side * side {
+            let (row, col) = (i / side, i % side);
+            if col + 1 < side {
+                graph.add_edge(i, i + 1, 10.0 + noise(2 * i), noise(2 * i + 1), 0.0, 1.0);
+            }
+            if row + 1 < side {
+                graph.add_edge(i, i + side, noise(3 * i), 10.0 + noise(3 * i + 1), 0.0, 1.0);
+            }
+        }
+        graph
+    }
+
+    #[test]
+    fn subgraph_around_collects_the_neighborhood() {
+        let mut graph = grid(5);
+        graph.names = Some((0..25).map(|i| format!("S{i}")).collect());
+        let (sub, mapping) = graph.subgraph_around(&[12, 99], 1);
+        assert_eq!(mapping, [7, 11, 12, 13, 17]);
+        assert_eq!(sub.num_edges(), 4);
+        assert_eq!(sub.fixed, [true, true, false, true, true]);
+        assert_eq!(sub.names.as_ref().unwrap()[2], "S12");
+        assert_eq!((sub.x[3], sub.y[3]), (graph.x[13], graph.y[13]));
+
+        let (sub, mapping) = graph.subgraph_around(&[0], 0);
+        assert_eq!(
+            (mapping, sub.num_edges(), sub.fixed),
+            (vec![0], 0, vec![true])
+        );
+    }
+
+    #[test]
+    fn subgraph_solve_reproduces_the_full_solve_locally() {
+        let graph = grid(6);
+        let full = graph.solve(10_000, 1e-14).unwrap();
+
+        // Start from the full adjustment, except around the suspect station.
+        let mut adjusted = graph.clone();
+        adjusted.x = full.x.clone();
+        adjusted.y = full.y.clone();
+        let (mut sub, mapping) = adjusted.subgraph_around(&[14], 2);
+        for (local, &i) in mapping.iter().enumerate() {
+            if !sub.fixed[local] {
+                sub.x[local] = graph.x[i];
+                sub.y[local] = graph.y[i];
+            }
+        }
+        assert!(sub.fixed.iter().any(|&f| !f));
+
+        // With the boundary held at the full adjustment, the interior comes out the same.
+        let local = sub.solve(10_000, 1e-14).unwrap();
+        let mut merged = (full.x.clone(), full.y.clone());
+        for (l, &i) in mapping.iter().enumerate() {
+            merged.0[i] = local.x[l];
+            merged.1[i] = local.y[l];
+        }
+        for i in 0..graph.num_vertices() {
+            assert!((merged.0[i] - full.x[i]).abs() < 1e-9, "x[{i}]");
+            assert!((merged.1[i] - full.y[i]).abs() < 1e-9, "y[{i}]");
+        }
+    }
+
+    #[test]
+    fn ffi_subgraph_fixes_the_boundary_at_the_adjusted_coordinates() {
+        let graph = grid(4);
+        let full = graph.solve(10_000, 1e-12).unwrap();
+        let handle = GraphContext::into_raw(graph);
+        let options = SolveOptions {
+            tolerance: 1e-12,
+            ..SolveOptions::default()
+        };
+        assert_eq!(
+            graph_solve(handle, &options, std::ptr::null_mut()),
+            COMPASS_OK
+        );
+
+        let mut mapping = [-1; 16];
+        let sub = graph_subgraph_around(handle, [5].as_ptr(), 1, 1, mapping.as_mut_ptr());
+        assert!(!sub.is_null());
+        assert_eq!(graph_num_vertices(sub), 5);
+        assert_eq!(mapping[..6], [1, 4, 5, 6, 9, -1]);
+        let sub_graph = unsafe { &*sub }.graph();
+        for (local, &i) in mapping[..5].iter().enumerate() {
+            assert_eq!(sub_graph.x[local], full.x[i as usize]);
+        }
+        graph_free(sub);
+
+        assert!(graph_subgraph_around(handle, [16].as_ptr(), 1, 1, mapping.as_mut_ptr()).is_null());
+        assert!(graph_subgraph_around(handle, [5].as_ptr(), 1, -1, mapping.as_mut_ptr()).is_null());
+        graph_free(handle);
+    }
+
+    #[test]
+    fn frozen_vertices_hold_and_report_the_boundary_stress() {
+        let graph = network();
+        let frozen = [false, true, false, true];
+        let solution = graph.solve_frozen(&frozen, 1000, 1e-12).unwrap();
+        assert_eq!((solution.x[1], solution.y[1]), (graph.x[1], graph.y[1]));
+
+        // Vertex 3 is fixed anyway; only edge 1 links the frozen vertex 1 to an adjusted one.
+        let stats = solution.stats;
+        assert_eq!((stats.free_vertices, stats.frozen_vertices), (1, 1));
+        assert_eq!(stats.frozen_boundary_edges, 1);
+        let (rx, ry) = solution.residuals(&graph)[1];
+        let stress = graph.weight[1] * (rx * rx + ry * ry);
+        assert!((stats.frozen_boundary_stress - stress).abs() < 1e-12);
+        assert!(stress > 0.0);
+
+        let flags: Vec<c_int> = frozen.iter().map(|&f| f as c_int).collect();
+        let options = SolveOptions {
+            tolerance: 1e-12,
+            frozen: flags.as_ptr(),
+            ..SolveOptions::default()
+        };
+        let (code, x, y, ffi_stats) = solve_ex(&graph, &options);
+        assert_eq!((code, x, y), (COMPASS_OK, solution.x, solution.y));
+        assert_eq!(ffi_stats.frozen_boundary_edges, 1);
+        assert!((ffi_stats.frozen_boundary_stress - stress).abs() < 1e-12);
+    }
+
+    /// `graph` without edge `edge`.
+    fn without_edge(graph: &Graph, edge: usize) -> Graph {
+        let mut rebuilt = graph.clone();
+        for values in [
+            &mut rebuilt.dx,
+            &mut rebuilt.dy,
+            &mut rebuilt.dz,
+            &mut rebuilt.weight,
+        ] {
+            values.remove(edge);
+        }
+        rebuilt.from.remove(edge);
+        rebuilt.to.remove(edge);
+        rebuilt
+    }
+
+    #[test]
+    fn toggling_an_edge_matches_a_graph_built_without_it() {
+        let graph = grid(4);
+        let rebuilt = without_edge(&graph, 5);
+        let expected = rebuilt.solve(10_000, 1e-14).unwrap();
+        let original = graph.solve(10_000, 1e-14).unwrap();
+
+        let ctx = unsafe { &mut *GraphContext::into_raw(graph.clone()) };
+        ctx.solve(10_000, 1e-14.into(), Default::default(), None, &|| false)
+            .unwrap();
+        ctx.set_edge_enabled(5, false).unwrap();
+        // Refilled in place: the same numbers as an assembly with the edge disabled.
+        let mut enabled = vec![true; graph.num_edges()];
+        enabled[5] = false;
+        let assembled = graph.normal_equations(&enabled).unwrap();
+        let system = ctx.system.as_ref().unwrap();
+        assert_eq!(system.matrix, assembled.matrix);
+        assert_eq!((&system.bx, &system.by), (&assembled.bx, &assembled.by));
+
+        let solution = ctx
+            .solve(10_000, 1e-14.into(), Default::default(), None, &|| false)
+            .unwrap();
+        assert_eq!(solution.stats.disabled_edges, 1);
+        for i in 0..graph.num_vertices() {
+            assert!((solution.x[i] - expected.x[i]).abs() < 1e-9, "x[{i}]");
+            assert!((solution.y[i] - expected.y[i]).abs() < 1e-9, "y[{i}]");
+        }
+
+        ctx.set_edge_enabled(5, true).unwrap();
+        let solution = ctx
+            .solve(10_000, 1e-14.into(), Default::default(), None, &|| false)
+            .unwrap();
+        assert_eq!(solution.stats.disabled_edges, 0);
+        for i in 0..graph.num_vertices() {
+            assert!((solution.x[i] - original.x[i]).abs() < 1e-9, "x[{i}]");
+        }
+
+        let handle: *mut GraphContext = ctx;
+        assert_eq!(
+            graph_set_edge_enabled(handle, 24, 0),
+            COMPASS_ERR_INVALID_ARGUMENT
+        );
+        assert_eq!(
+            graph_set_edge_enabled(handle, -1, 0),
+            COMPASS_ERR_INVALID_ARGUMENT
+        );
+        graph_free(handle);
+    }
+
+    /// Splits the edges of `graph` into those before `at` and the rest, over the same vertices.
+    fn split_edges(graph: &Graph, at: usize) -> (Graph, Graph) {
+        let mut head = graph.clone();
+        head.truncate_edges(at);
+        let mut tail = graph.clone();
+        tail.from.drain(..at);
+        tail.to.drain(..at);
+        for values in [&mut tail.dx, &mut tail.dy, &mut tail.dz, &mut tail.weight] {
+            values.drain(..at);
+        }
+        (head, tail)
+    }
+
+    #[test]
+    fn streamed_edges_are_enabled_and_keep_earlier_flags() {
+        let graph = grid(4);
+        let expected = without_edge(&graph, 5).solve(10_000, 1e-14).unwrap();
+        let (head, tail) = split_edges(&graph, 20);
+
+        let handle = vertices_only(&graph);
+        let mut sources = [head, tail].map(|edges| EdgeSource {
+            graph: edges,
+            abort_at: None,
+            requests: Vec::new(),
+        });
+        assert_eq!(stream(handle, &mut sources[0], 7), COMPASS_OK);
+        assert_eq!(graph_set_edge_enabled(handle, 5, 0), COMPASS_OK);
+        assert_eq!(
+            graph_set_edge_enabled(handle, 23, 0),
+            COMPASS_ERR_INVALID_ARGUMENT
+        );
+        assert_eq!(stream(handle, &mut sources[1], 3), COMPASS_OK);
+        assert_eq!(graph_num_edges(handle), 24);
+
+        let ctx = unsafe { &*handle };
+        assert!((0..24).all(|e| ctx.is_edge_enabled(e) == (e != 5)));
+        let options = SolveOptions {
+            tolerance: 1e-14,
+            ..SolveOptions::default()
+        };
+        let mut stats = SolveStats {
+            struct_size: size_of::<SolveStats>(),
+            ..SolveStats::default()
+        };
+        assert_eq!(graph_solve(handle, &options, &mut stats), COMPASS_OK);
+        assert_eq!(stats.disabled_edges, 1);
+        let (x, y) = ctx.coordinates();
+        for i in 0..graph.num_vertices() {
+            assert!((x[i] - expected.x[i]).abs() < 1e-9, "x[{i}]");
+            assert!((y[i] - expected.y[i]).abs() < 1e-9, "y[{i}]");
+        }
+        assert_eq!(graph_set_edge_enabled(handle, 23, 0), COMPASS_OK);
+        assert!(!ctx.is_edge_enabled(23));
+        graph_free(handle);
+    }
+
+    #[test]
+    fn streamed_edges_keep_their_weight_until_classed() {
+        let graph = grid(4);
+        let class: Vec<c_int> = (0..24).map(|e| e % 2).collect();
+        let classes = classes::EdgeClasses {
+            class: class.clone(),
+            multipliers: vec![1.0, 4.0],
+        };
+        let (head, tail) = split_edges(&graph, 20);
+        let handle = vertices_only(&graph);
+        let mut sources = [head, tail].map(|edges| EdgeSource {
+            graph: edges,
+            abort_at: None,
+            requests: Vec::new(),
+        });
+        assert_eq!(stream(handle, &mut sources[0], 7), COMPASS_OK);
+        let options = SolveOptions {
+            tolerance: 1e-14,
+            edge_class: class.as_ptr(),
+            class_multipliers: classes.multipliers.as_ptr(),
+            num_classes: 2,
+            ..SolveOptions::default()
+        };
+        assert_eq!(solve_handle(handle, &options).0, COMPASS_OK);
+
+        // The classes of the first solve cover the first 20 edges; the appended ones keep
+        // their weight until the next solve classes them.
+        assert_eq!(stream(handle, &mut sources[1], 3), COMPASS_OK);
+        let ctx = unsafe { &*handle };
+        let stale = ctx.edge_classes().unwrap();
+        assert_eq!(stale.class.len(), 20);
+        assert_eq!((stale.multiplier(19), stale.multiplier(21)), (4.0, 1.0));
+
+        let (code, x, y) = solve_handle(handle, &options);
+        assert_eq!(code, COMPASS_OK);
+        assert_eq!(ctx.edge_classes(), Some(&classes));
+        let mut scaled = graph.clone();
+        scaled.weight = classes.scale(&graph.weight);
+        let expected = scaled.solve(10_000, 1e-14).unwrap();
+        assert_coordinates_close((&x, &y), &expected, 1e-9);
+        graph_free(handle);
+    }
+
+    #[test]
+    fn edge_mask_matches_a_graph_built_without_the_edge() {
+        let graph = network();
+        let mask = [1, 0, 1, 1];
+        let options = SolveOptions {
+            tolerance: 1e-14,
+            edge_enabled: mask.as_ptr(),
+            ..SolveOptions::default()
+        };
+        let (code, x, y, stats) = solve_ex(&graph, &options);
+        assert_eq!((code, stats.disabled_edges), (COMPASS_OK, 1));
+
+        let expected = without_edge(&graph, 1).solve(10_000, 1e-14).unwrap();
+        for i in 0..graph.num_vertices() {
+            assert!((x[i] - expected.x[i]).abs() < 1e-9, "x[{i}]");
+            assert!((y[i] - expected.y[i]).abs() < 1e-9, "y[{i}]");
+        }
+    }
+
+    #[test]
+    fn group_reports_rank_the_group_holding_a_blunder_first() {
+        let mut graph = grid(4);
+        graph.dx[7] += 0.5;
+        let group_id: Vec<i32> = (0..graph.num_edges())
+            .map(|e| match e {
+                6..=8 => 7,
+                _ if e % 2 == 0 => 100,
+                _ => -2,
+            })
+            .collect();
+        let solution = graph.solve(10_000, 1e-12).unwrap();
+        let reports = solution.group_reports(&graph, &group_id);
+
+        assert_eq!(reports.iter().map(|r| r.group).collect::<Vec<_>>()[0], 7);
+        assert_eq!(reports.len(), 3);
+        assert_eq!(
+            reports.iter().map(|r| r.edges).sum::<usize>(),
+            graph.num_edges()
+        );
+        assert!(reports[0].suspicion > 1.0 && reports[2].suspicion < 1.0);
+
+        let standardized = solution.standardized_residuals(&graph);
+        let residuals = solution.residuals(&graph);
+        let blunder = &reports[0];
+        assert_eq!(blunder.edges, 3);
+        assert_eq!(
+            blunder.max_standardized,
+            standardized[6..=8].iter().copied().fold(0.0, f64::max)
+        );
+        let square: f64 = (6..=8)
+            .map(|e| graph.weight[e] * (residuals[e].0.powi(2) + residuals[e].1.powi(2)))
+            .sum();
+        assert!((blunder.weighted_rms - (square / 3.0).sqrt()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn ffi_group_reports_use_a_count_query() {
+        let graph = grid(3);
+        let handle = GraphContext::into_raw(graph.clone());
+        assert_eq!(
+            graph_solve(handle, &SolveOptions::default(), std::ptr::null_mut()),
+            COMPASS_OK
+        );
+        assert_eq!(graph_group_count(handle), 1);
+
+        let group_id: Vec<c_int> = (0..graph.num_edges() as c_int)
+            .map(|e| e % 3 * 10)
+            .collect();
+        assert_eq!(graph_set_edge_groups(handle, group_id.as_ptr()), COMPASS_OK);
+        assert_eq!(graph_group_count(handle), 3);
+
+        let (mut group, mut edges) = ([0; 3], [0; 3]);
+        let code = graph_group_reports(
+            handle,
+            2,
+            group.as_mut_ptr(),
+            edges.as_mut_ptr(),
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+        );
+        assert_eq!((code, group), (COMPASS_ERR_BUFFER_TOO_SMALL, [0; 3]));
+        let code = graph_group_reports(
+            handle,
+            3,
+            group.as_mut_ptr(),
+            edges.as_mut_ptr(),
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+        );
+        assert_eq!(code, 3);
+        group.sort();
+        assert_eq!((group, edges.iter().sum::<c_int>()), ([0, 10, 20], 12));
+
+        // Disabled edges are left out of the statistics.
+        assert_eq!(graph_set_edge_enabled(handle, 1, 0), COMPASS_OK);
+        assert_eq!(graph_set_edge_enabled(handle, 4, 0), COMPASS_OK);
+        assert_eq!(graph_set_edge_enabled(handle, 7, 0), COMPASS_OK);
+        assert_eq!(graph_set_edge_enabled(handle, 10, 0), COMPASS_OK);
+        assert_eq!(graph_group_count(handle), 2);
+        assert_eq!(graph_set_edge_groups(handle, std::ptr::null()), COMPASS_OK);
+        assert_eq!(graph_group_count(handle), 1);
+        graph_free(handle);
+    }
+
+    fn assert_coordinates_close(actual: (&[f64], &[f64]), expected: &Solution, tolerance: f64) {
+        for v in 0..expected.x.len() {
+            assert!((actual.0[v] - expected.x[v]).abs() < tolerance, "x[{v}]");
+            assert!((actual.1[v] - expected.y[v]).abs() < tolerance, "y[{v}]");
+        }
+    }
+
+    #[test]
+    fn preview_sweeps_converge_to_the_full_solve() {
+        let mut graph = grid(5);
+        graph.fixed[24] = true;
+        let handle = GraphContext::into_raw(graph.clone());
+        let ctx = unsafe { &mut *handle };
+        ctx.solve(10_000, 1e-14.into(), Default::default(), None, &|| false)
+            .unwrap();
+
+        // Dragging the second anchor moves it in the graph.
+        let mut moved = graph.clone();
+        (moved.x[24], moved.y[24]) = (41.0, 38.5);
+        let expected = moved.solve(10_000, 1e-14).unwrap();
+        for omega in [1.0, 1.5] {
+            let options = PreviewOptions {
+                sweeps: 2000,
+                omega,
+                radius: None,
+            };
+            ctx.preview(24, 41.0, 38.5, &options).unwrap();
+            assert_coordinates_close(ctx.coordinates(), &expected, 1e-9);
+            assert_eq!((ctx.graph().x[24], ctx.graph().y[24]), (41.0, 38.5));
+        }
+
+        // A dragged free vertex is held where it is dropped.
+        let mut pinned = moved.clone();
+        pinned.fixed[12] = true;
+        (pinned.x[12], pinned.y[12]) = (22.0, 19.0);
+        let expected = pinned.solve(10_000, 1e-14).unwrap();
+        let options = PreviewOptions {
+            sweeps: 2000,
+            ..PreviewOptions::default()
+        };
+        ctx.preview(12, 22.0, 19.0, &options).unwrap();
+        assert_coordinates_close(ctx.coordinates(), &expected, 1e-9);
+
+        // The full solve picks up from the preview on the same handle.
+        let expected = moved.solve(10_000, 1e-14).unwrap();
+        ctx.solve(10_000, 1e-14.into(), Default::default(), None, &|| false)
+            .unwrap();
+        assert_coordinates_close(ctx.coordinates(), &expected, 1e-9);
+        graph_free(handle);
+    }
+
+    #[test]
+    fn edge_deltas_are_the_implied_forward_model() {
+        let graph = network();
+        let deltas = graph.edge_deltas().unwrap();
+        let expected = [(10.2, 0.1), (-0.3, 10.2), (-10.1, -0.3), (0.2, -10.0)];
+        for (delta, expected) in deltas.iter().zip(expected) {
+            assert!((delta.0 - expected.0).abs() < 1e-12 && (delta.1 - expected.1).abs() < 1e-12);
+        }
+
+        let from: Vec<c_int> = graph.from.iter().map(|&v| v as c_int).collect();
+        let mut to: Vec<c_int> = graph.to.iter().map(|&v| v as c_int).collect();
+        let (mut dx, mut dy) = (vec![0.0; 4], vec![0.0; 4]);
+        let call = |to: &[c_int], dx: &mut [f64], dy: &mut [f64]| {
+            compute_edge_deltas(
+                4,
+                graph.x.as_ptr(),
+                graph.y.as_ptr(),
+                4,
+                from.as_ptr(),
+                to.as_ptr(),
+                dx.as_mut_ptr(),
+                dy.as_mut_ptr(),
+            )
+        };
+        assert_eq!(call(&to, &mut dx, &mut dy), COMPASS_OK);
+        assert_eq!(dx, deltas.iter().map(|d| d.0).collect::<Vec<_>>());
+        assert_eq!(dy, deltas.iter().map(|d| d.1).collect::<Vec<_>>());
+
+        // An out-of-range endpoint writes nothing.
+        let (mut dx, mut dy) = (vec![7.0; 4], vec![7.0; 4]);
+        to[3] = 4;
+        assert_eq!(call(&to, &mut dx, &mut dy), COMPASS_ERR_INVALID_ARGUMENT);
+        to[3] = -1;
+        assert_eq!(call(&to, &mut dx, &mut dy), COMPASS_ERR_INVALID_ARGUMENT);
+        assert_eq!((dx, dy), (vec![7.0; 4], vec![7.0; 4]));
+        let mut broken = graph.clone();
+        broken.to[1] = 9;
+        assert_eq!(
+            broken.edge_deltas().unwrap_err().code,
+            COMPASS_ERR_INVALID_ARGUMENT
+        );
+    }
+
+    #[test]
+    fn misfit_summary_matches_hand_computed_values() {
+        // Misfits (0.1, -0.2), (0, -0.3) and, between the two anchors, (-0.4, 0).
+        let mut graph = Graph::default();
+        graph.add_vertex(0.0, 0.0, 0.0, true);
+        graph.add_vertex(10.0, 0.0, 0.0, false);
+        graph.add_vertex(10.0, 5.0, 0.0, true);
+        graph.add_edge(0, 1, 9.9, 0.2, 0.0, 1.0);
+        graph.add_edge(1, 2, 0.0, 5.3, 0.0, 2.0);
+        graph.add_edge(0, 2, 10.4, 5.0, 0.0, 0.5);
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-12;
+
+        let summary = graph.misfit_summary().unwrap();
+        assert_eq!((summary.edges, summary.max_edge), (3, 2));
+        assert!(close(summary.weighted_rms, (0.31f64 / 3.5).sqrt()));
+        assert!(close(summary.weighted_rms_x, (0.09f64 / 3.5).sqrt()));
+        assert!(close(summary.weighted_rms_y, (0.22f64 / 3.5).sqrt()));
+        assert!(close(summary.max_misfit, 0.4));
+        assert!(close(summary.max_misfit_x, 0.4));
+        assert!(close(summary.max_misfit_y, 0.3));
+
+        let from: Vec<c_int> = graph.from.iter().map(|&v| v as c_int).collect();
+        let to: Vec<c_int> = graph.to.iter().map(|&v| v as c_int).collect();
+        let call = |to: &[c_int], enabled: *const c_int, out: &mut MisfitSummary| {
+            compute_misfit_summary(
+                3,
+                graph.x.as_ptr(),
+                graph.y.as_ptr(),
+                3,
+                from.as_ptr(),
+                to.as_ptr(),
+                graph.dx.as_ptr(),
+                graph.dy.as_ptr(),
+                graph.weight.as_ptr(),
+                enabled,
+                out,
+            )
+        };
+        let mut out = MisfitSummary::default();
+        assert_eq!(call(&to, std::ptr::null(), &mut out), COMPASS_OK);
+        assert_eq!(out, summary);
+
+        // Without the check between the anchors.
+        let enabled = [1, 1, 0];
+        assert_eq!(call(&to, enabled.as_ptr(), &mut out), COMPASS_OK);
+        assert_eq!((out.edges, out.max_edge), (2, 1));
+        assert!(close(out.weighted_rms, (0.23f64 / 3.0).sqrt()));
+        assert!(close(out.weighted_rms_x, (0.01f64 / 3.0).sqrt()));
+        assert!(close(out.max_misfit, 0.3));
+        assert!(close(out.max_misfit_x, 0.1));
+
+        let before = out;
+        assert_eq!(
+            call(&[1, 2, 3], std::ptr::null(), &mut out),
+            COMPASS_ERR_INVALID_ARGUMENT
+        );
+        assert_eq!(out, before);
+        let disabled = [0, 0, 0];
+        assert_eq!(call(&to, disabled.as_ptr(), &mut out), COMPASS_OK);
+        assert_eq!((out.edges, out.max_edge, out.weighted_rms), (0, -1, 0.0));
+    }
+
+    #[test]
+    fn preview_uses_the_class_multipliers() {
+        let mut graph = grid(5);
+        graph.fixed[24] = true;
+        // Shots touching the last row and column are surface legs, weighted up five times.
+        let class: Vec<c_int> = (0..graph.num_edges())
+            .map(|e| (graph.to[e] >= 20 || graph.to[e] % 5 == 4) as c_int)
+            .collect();
+        let classes = classes::EdgeClasses {
+            class,
+            multipliers: vec![1.0, 5.0],
+        };
+        let handle = GraphContext::into_raw(graph.clone());
+        let ctx = unsafe { &mut *handle };
+        ctx.set_edge_classes(Some(classes.clone())).unwrap();
+        ctx.solve(10_000, 1e-14.into(), Default::default(), None, &|| false)
+            .unwrap();
+
+        let mut moved = graph.clone();
+        moved.weight = classes.scale(&graph.weight);
+        (moved.x[24], moved.y[24]) = (41.0, 38.5);
+        let expected = moved.solve(10_000, 1e-14).unwrap();
+        let options = PreviewOptions {
+            sweeps: 3000,
+            ..PreviewOptions::default()
+        };
+        ctx.preview(24, 41.0, 38.5, &options).unwrap();
+        assert_coordinates_close(ctx.coordinates(), &expected, 1e-9);
+        graph_free(handle);
+    }
+
+    #[test]
+    fn preview_radius_limits_the_moving_vertices() {
+        let graph = grid(5);
+        let ctx = unsafe { &mut *GraphContext::into_raw(graph.clone()) };
+        let options = PreviewOptions {
+            sweeps: 3,
+            omega: 1.0,
+            radius: Some(1),
+        };
+        // Only the two free neighbors of the dragged anchor are one hop away from it.
+        ctx.preview(0, 1.0, -1.0, &options).unwrap();
+        let (x, y) = ctx.coordinates();
+        let moved: Vec<usize> = (1..25)
+            .filter(|&v| x[v] != graph.x[v] || y[v] != graph.y[v])
+            .collect();
+        assert_eq!(moved, [1, 5]);
+
+        // Around a free vertex, radius 1 reaches its four neighbors but the vertex itself is
+        // pinned.
+        let options = PreviewOptions {
+            radius: Some(1),
+            ..options
+        };
+        let before: Vec<(f64, f64)> = (0..25).map(|v| (x[v], y[v])).collect();
+        ctx.preview(12, 21.0, 19.0, &options).unwrap();
+        let (x, y) = ctx.coordinates();
+        let moved: Vec<usize> = (0..25).filter(|&v| (x[v], y[v]) != before[v]).collect();
+        assert_eq!(moved, [7, 11, 12, 13, 17]);
+        assert_eq!((x[12], y[12]), (21.0, 19.0));
+        graph_free(ctx);
+    }
+
+    #[test]
+    fn ffi_preview_rejects_bad_arguments() {
+        let handle = GraphContext::into_raw(network());
+        assert_eq!(graph_preview(handle, 1, 10.0, 0.0, 5, 1.0, -1), COMPASS_OK);
+        let (x, _) = unsafe { &*handle }.coordinates();
+        assert_eq!(x[1], 10.0);
+        for (vertex, omega) in [(-1, 1.0), (4, 1.0), (1, 0.0), (1, 2.0), (1, f64::NAN)] {
+            let code = graph_preview(handle, vertex, 0.0, 0.0, 5, omega, -1);
+            assert_eq!(code, COMPASS_ERR_INVALID_ARGUMENT);
+        }
+        let code = graph_preview(std::ptr::null_mut(), 1, 0.0, 0.0, 5, 1.0, -1);
+        assert_eq!(code, COMPASS_ERR_INVALID_ARGUMENT);
+        graph_free(handle);
+    }
+
+    /// [`grid`] with weights spread over a decade, so that Jacobi scaling matters.
+    fn weighted_grid(side: usize) -> Graph {
+        let mut graph = grid(side);
+        for (e, w) in graph.weight.iter_mut().enumerate() {
+            *w = 1.0 + (e % 5) as f64 * 2.5;
+        }
+        graph
+    }
+
+    #[test]
+    fn every_solver_and_preconditioner_reach_the_same_answer() {
+        let graph = weighted_grid(8);
+        let expected = graph.solve(10_000, 1e-14).unwrap();
+        let mut iterations = Vec::new();
+        for (solver, preconditioner) in [(0, 0), (0, 1), (1, 0), (1, 1)] {
+            let options = SolveOptions {
+                iterations: 100_000,
+                tolerance: 1e-10,
+                solver,
+                preconditioner,
+                ..SolveOptions::default()
+            };
+            let (code, x, y, stats) = solve_ex(&graph, &options);
+            assert_eq!(
+                code, COMPASS_OK,
+                "solver {solver}, preconditioner {preconditioner}"
+            );
+            assert_coordinates_close((&x, &y), &expected, 1e-7);
+            assert!(stats.residual_norm < 1e-6);
+            iterations.push(stats.iterations);
+        }
+        // Chebyshev needs no dot products but takes more iterations than CG.
+        assert!(iterations[2] > iterations[0], "{iterations:?}");
+        assert!(iterations[3] > iterations[1], "{iterations:?}");
+    }
+
+    #[test]
+    fn unknown_solver_codes_are_rejected() {
+        let graph = network();
+        for (solver, preconditioner) in [(2, 0), (0, 4), (-1, 0), (1, 2), (1, 3)] {
+            let options = SolveOptions {
+                solver,
+                preconditioner,
+                ..SolveOptions::default()
+            };
+            assert_eq!(solve_ex(&graph, &options).0, COMPASS_ERR_INVALID_ARGUMENT);
+            let handle = GraphContext::into_raw(graph.clone());
+            let code = graph_solve(handle, &options, std::ptr::null_mut());
+            assert_eq!(code, COMPASS_ERR_INVALID_ARGUMENT);
+            graph_free(handle);
+        }
+    }
+
+    #[test]
+    fn chebyshev_bounds_enclose_the_spectrum() {
+        // Path Laplacian: eigenvalues 2 - 2 cos(k pi / (n + 1)).
+        let n = 6;
+        let diagonal = vec![2.0; n];
+        let off_diagonal = vec![-1.0; n - 1];
+        let expected = 2.0 - 2.0 * (std::f64::consts::PI / (n + 1) as f64).cos();
+        assert!((smallest_eigenvalue(&diagonal, &off_diagonal) - expected).abs() < 1e-12);
+
+        let graph = weighted_grid(5);
+        let system = graph.normal_equations(&[]).unwrap();
+        let dense = nalgebra::DMatrix::from(&system.matrix);
+        let inv_diag = inverse_diagonal(&system.matrix);
+        let scaled = DVector::from_iterator(inv_diag.len(), inv_diag.iter().map(|d| d.sqrt()));
+        for (matrix, inv_diag) in [
+            (dense.clone(), None),
+            (
+                nalgebra::DMatrix::from_diagonal(&scaled)
+                    * &dense
+                    * nalgebra::DMatrix::from_diagonal(&scaled),
+                Some(&inv_diag),
+            ),
+        ] {
+            let eigenvalues = matrix.symmetric_eigenvalues();
+            let (lambda_min, lambda_max) = chebyshev_bounds(&system.matrix, inv_diag);
+            assert!(lambda_max >= eigenvalues.max() * (1.0 - 1e-12));
+            // The Ritz value lies above the true smallest eigenvalue.
+            assert!(lambda_min >= eigenvalues.min() * (1.0 - 1e-9));
+            assert!(lambda_min < lambda_max);
+        }
+    }
+
+    /// A chain of `n` stations fixed at both ends, closed by a loop shot every 40 stations:
+    /// the shape of long river passages.
+    fn chain_with_loops(n: usize) -> Graph {
+        let noise = |k: usize| ((k * 7919) % 13) as f64 * 0.01 - 0.06;
+        let mut graph = Graph::default();
+        for i in 0..n {
+            graph.add_vertex(10.0 * i as f64, 0.0, 0.0, i == 0 || i == n - 1);
+        }
+        for i in 0..n - 1 {
+            graph.add_edge(i, i + 1, 10.0 + noise(i), noise(i + 1), 0.0, 1.0);
+        }
+        for i in (0..n - 40).step_by(40) {
+            graph.add_edge(i, i + 40, 400.0 + noise(i), noise(i + 2), 0.0, 0.5);
+        }
+        graph
+    }
+
+    #[test]
+    fn amg_iterations_stay_flat_as_chains_grow() {
+        // The true residual of the 16k chain bottoms out in rounding just above 1e-9.
+        let options = SolveOptions {
+            iterations: 100_000,
+            tolerance: 1e-8,
+            preconditioner: 2,
+            ..SolveOptions::default()
+        };
+        let small = chain_with_loops(1000);
+        let (code, x, y, stats) = solve_ex(&small, &options);
+        assert_eq!(code, COMPASS_OK);
+        let jacobi = SolveOptions {
+            preconditioner: 1,
+            ..options
+        };
+        let (_, jx, jy, jacobi) = solve_ex(&small, &jacobi);
+        let expected = Solution {
+            x: jx,
+            y: jy,
+            ..Solution::default()
+        };
+        assert_coordinates_close((&x, &y), &expected, 1e-6);
+        assert!(
+            stats.iterations * 10 < jacobi.iterations,
+            "{stats:?} {jacobi:?}"
+        );
+
+        let (code, _, _, large) = solve_ex(&chain_with_loops(16_000), &options);
+        assert_eq!(code, COMPASS_OK);
+        assert!(
+            large.iterations <= stats.iterations + 5,
+            "{stats:?} {large:?}"
+        );
+    }
+
+    #[test]
+    fn true_residual_decides_convergence_on_long_chains() {
+        let graph = chain_with_loops(50_000);
+        let options = SolveOptions {
+            iterations: 1000,
+            tolerance: 1e-8,
+            preconditioner: 2,
+            ..SolveOptions::default()
+        };
+        let (code, x, y, stats) = solve_ex(&graph, &options);
+        assert_eq!(code, COMPASS_OK);
+        assert_eq!(stats.stop_reason, COMPASS_STOP_CONVERGED);
+        // The recursive residual claimed convergence while the true one was still above the
+        // tolerance: stopping there, as the solve used to, returned an inexact result.
+        assert!(stats.residual_restarts >= 1, "{stats:?}");
+
+        let system = graph.normal_equations(&[]).unwrap();
+        let free = |coordinates: &[f64]| {
+            let mut reduced = DVector::zeros(system.size());
+            for (i, row) in system.mapping.iter().enumerate() {
+                if let Some(row) = *row {
+                    reduced[row] = coordinates[i];
+                }
+            }
+            reduced
+        };
+        let residual_x = (&system.bx - &system.matrix * free(&x)).norm();
+        let residual_y = (&system.by - &system.matrix * free(&y)).norm();
+        let residual = residual_x.max(residual_y);
+        assert!(residual < options.tolerance, "{residual} {stats:?}");
+        assert!((residual - stats.residual_norm).abs() < 1e-3 * options.tolerance);
+    }
+
+    #[test]
+    fn schwarz_blocks_take_fewer_iterations_than_jacobi() {
+        let schwarz = Preconditioner::AdditiveSchwarz {
+            block_size: DEFAULT_SCHWARZ_BLOCK_SIZE,
+            overlap: DEFAULT_SCHWARZ_OVERLAP,
+        };
+        for side in [10, 30] {
+            let graph = weighted_grid(side);
+            let solve = |preconditioner| {
+                let solver = LinearSolver::ConjugateGradient;
+                graph
+                    .solve_with(100_000, 1e-10, solver, preconditioner)
+                    .unwrap()
+            };
+            let (blocks, jacobi) = (solve(schwarz), solve(Preconditioner::Jacobi));
+            assert_coordinates_close((&blocks.x, &blocks.y), &jacobi, 1e-6);
+            assert!(
+                blocks.stats.iterations * 2 < jacobi.stats.iterations,
+                "{side}: {} vs {}",
+                blocks.stats.iterations,
+                jacobi.stats.iterations
+            );
+        }
+
+        // One block holding every vertex is a direct solve.
+        let graph = weighted_grid(6);
+        let whole = Preconditioner::AdditiveSchwarz {
+            block_size: 36,
+            overlap: 0,
+        };
+        let solution = graph
+            .solve_with(100, 1e-10, LinearSolver::ConjugateGradient, whole)
+            .unwrap();
+        assert!(solution.stats.iterations <= 1);
+    }
+
+    #[test]
+    fn context_reuses_the_preconditioner_until_the_weights_change() {
+        let ctx = unsafe { &mut *GraphContext::into_raw(weighted_grid(8)) };
+        let schwarz = Preconditioner::AdditiveSchwarz {
+            block_size: 16,
+            overlap: 1,
+        };
+        let method = (LinearSolver::ConjugateGradient, schwarz);
+        ctx.solve(10_000, 1e-12.into(), method, None, &|| false)
+            .unwrap();
+        let system = ctx.system.as_ref().unwrap();
+        let first = system.preconditioner(schwarz).unwrap();
+        assert!(Arc::ptr_eq(
+            &first,
+            &system.preconditioner(schwarz).unwrap()
+        ));
+
+        // Toggling an edge refills the matrix in place and drops the stale factorization.
+        ctx.set_edge_enabled(3, false).unwrap();
+        let system = ctx.system.as_ref().unwrap();
+        assert!(system.preconditioner.lock().unwrap().is_none());
+        let solution = ctx
+            .solve(10_000, 1e-12.into(), method, None, &|| false)
+            .unwrap()
+            .clone();
+        let expected = without_edge(&weighted_grid(8), 3)
+            .solve(10_000, 1e-12)
+            .unwrap();
+        assert_coordinates_close((&solution.x, &solution.y), &expected, 1e-8);
+        graph_free(ctx);
+    }
+
+    /// Two anchor-free loops: a square (vertices 0-3) and a triangle (4-6), misclosed.
+    fn free_networks() -> Graph {
+        let mut graph = Graph::default();
+        for (x, y) in [(0.3, -0.2), (10.1, 0.4), (9.7, 10.2), (-0.1, 9.8)] {
+            graph.add_vertex(x, y, 0.0, false);
+        }
+        for (x, y) in [(50.0, 50.0), (60.2, 49.9), (55.1, 58.3)] {
+            graph.add_vertex(x, y, 0.0, false);
+        }
+        for (u, v, dx, dy) in [
+            (0, 1, 10.0, 0.1),
+            (1, 2, 0.0, 10.0),
+            (2, 3, -10.1, 0.0),
+            (3, 0, 0.0, -10.0),
+            (4, 5, 10.0, 0.0),
+            (5, 6, -5.0, 8.7),
+            (6, 4, -5.05, -8.6),
+        ] {
+            graph.add_edge(u, v, dx, dy, 0.0, 1.0);
+        }
+        graph
+    }
+
+    fn centroid(x: &[f64], vertices: std::ops::Range<usize>) -> f64 {
+        let count = vertices.len() as f64;
+        vertices.map(|v| x[v]).sum::<f64>() / count
+    }
+
+    #[test]
+    fn free_networks_keep_the_centroid_of_the_initial_guess() {
+        let graph = free_networks();
+        for preconditioner in [0, 1, 2, 3] {
+            let options = SolveOptions {
+                iterations: 1000,
+                tolerance: 1e-12,
+                preconditioner,
+                ..SolveOptions::default()
+            };
+            let (code, x, y, stats) = solve_ex(&graph, &options);
+            assert_eq!(code, COMPASS_OK, "preconditioner {preconditioner}");
+            assert_eq!(stats.free_networks, 2);
+            for vertices in [0..4, 4..7] {
+                let (before, after) = (
+                    centroid(&graph.x, vertices.clone()),
+                    centroid(&x, vertices.clone()),
+                );
+                assert!(
+                    (before - after).abs() < 1e-9,
+                    "{preconditioner}: {before} {after}"
+                );
+                let (before, after) =
+                    (centroid(&graph.y, vertices.clone()), centroid(&y, vertices));
+                assert!(
+                    (before - after).abs() < 1e-9,
+                    "{preconditioner}: {before} {after}"
+                );
+            }
+
+            // The shape is that of the anchored adjustment.
+            let mut anchored = graph.clone();
+            anchored.fixed[0] = true;
+            anchored.fixed[4] = true;
+            let expected = anchored.solve(1000, 1e-12).unwrap();
+            for (u, v) in [(0, 2), (1, 3), (4, 6), (5, 6)] {
+                let shape = expected.x[v] - expected.x[u];
+                assert!((x[v] - x[u] - shape).abs() < 1e-9);
+            }
+        }
+
+        let chebyshev = SolveOptions {
+            solver: 1,
+            ..SolveOptions::default()
+        };
+        assert_eq!(solve_ex(&graph, &chebyshev).0, COMPASS_ERR_INVALID_ARGUMENT);
+        let mut anchored = graph.clone();
+        anchored.fixed[0] = true;
+        anchored.fixed[4] = true;
+        let (code, _, _, stats) = solve_ex(&anchored, &SolveOptions::default());
+        assert_eq!((code, stats.free_networks), (COMPASS_OK, 0));
+    }
+
+    #[test]
+    fn disabling_the_tie_to_an_anchor_frees_the_network() {
+        let mut graph = free_networks();
+        graph.fixed[0] = true;
+        // The triangle hangs off the anchored square through this shot only.
+        graph.add_edge(3, 4, 50.0, 40.0, 0.0, 1.0);
+        let tie = graph.num_edges() - 1;
+
+        let handle = GraphContext::into_raw(graph.clone());
+        let ctx = unsafe { &mut *handle };
+        let mut stats = SolveStats {
+            struct_size: size_of::<SolveStats>(),
+            ..SolveStats::default()
+        };
+        let options = SolveOptions {
+            tolerance: 1e-12,
+            ..SolveOptions::default()
+        };
+        assert_eq!(graph_solve(handle, &options, &mut stats), COMPASS_OK);
+        assert_eq!(stats.free_networks, 0);
+
+        ctx.set_edge_enabled(tie, false).unwrap();
+        assert!(ctx.system.is_some());
+        assert_eq!(graph_solve(handle, &options, &mut stats), COMPASS_OK);
+        assert_eq!(stats.free_networks, 1);
+        let (x, y) = ctx.coordinates();
+        // The triangle now starts from the solution of the first solve.
+        let rebuilt = without_edge(&graph, tie);
+        let first = graph.solve(10_000, 1e-12).unwrap();
+        let expected = Graph {
+            x: first.x.clone(),
+            y: first.y.clone(),
+            ..rebuilt
+        }
+        .solve(10_000, 1e-12)
+        .unwrap();
+        assert_coordinates_close((x, y), &expected, 1e-8);
+        graph_free(handle);
+    }
+
+    #[test]
+    fn convergence_criteria_measure_the_residual() {
+        // The same grid with its weights scaled: relative criteria stop at the same iteration
+        // whatever the scale, while the absolute one stops at once for tiny weights and runs
+        // out of iterations for large ones.
+        let scaled = |factor: f64| {
+            let mut graph = grid(12);
+            graph.weight.iter_mut().for_each(|w| *w *= factor);
+            graph
+        };
+        let run = |graph: &Graph, criterion: c_int| {
+            let options = SolveOptions {
+                tolerance: 1e-8,
+                convergence_criterion: criterion,
+                ..SolveOptions::default()
+            };
+            let (code, x, _, stats) = solve_ex(graph, &options);
+            assert_eq!(code, COMPASS_OK);
+            assert_eq!(stats.convergence_criterion, criterion);
+            (stats, x)
+        };
+        for criterion in [1, 2] {
+            let (small, x_small) = run(&scaled(1e-6), criterion);
+            let (large, x_large) = run(&scaled(1e6), criterion);
+            for stats in [&small, &large] {
+                assert_eq!(stats.stop_reason, COMPASS_STOP_CONVERGED);
+                assert!(stats.convergence_measure <= 1e-8);
+            }
+            let (small, large) = (small.iterations, large.iterations);
+            assert!(small.abs_diff(large) <= 1, "{small} against {large}");
+            let gap = x_small.iter().zip(&x_large).map(|(a, b)| (a - b).abs());
+            assert!(gap.fold(0.0, f64::max) < 1e-6);
+        }
+        let (small, _) = run(&scaled(1e-6), 0);
+        let (large, _) = run(&scaled(1e6), 0);
+        assert_eq!(small.stop_reason, COMPASS_STOP_CONVERGED);
+        assert_eq!(large.stop_reason, COMPASS_STOP_ITERATIONS);
+        assert!(small.iterations < 100);
+
+        let graph = scaled(1.0);
+        let solution = graph
+            .solve_until(10_000, 1e-8, ConvergenceCriterion::NormalResidual)
+            .unwrap();
+        assert_eq!(solution.stats.convergence_criterion, 2);
+
+        let options = SolveOptions {
+            convergence_criterion: 3,
+            ..SolveOptions::default()
+        };
+        let (code, message) = solve_message(&graph, &options);
+        assert_eq!(code, COMPASS_ERR_INVALID_ARGUMENT, "{message}");
+    }
+
+    #[test]
+    fn time_budget_stops_the_solve() {
+        let graph = grid(30);
+        let options = SolveOptions {
+            iterations: 100_000,
+            tolerance: 1e-15,
+            time_budget: 1e-9,
+            ..SolveOptions::default()
+        };
+        let (code, x, _, stats) = solve_ex(&graph, &options);
+        assert_eq!(code, COMPASS_OK);
+        assert_eq!(stats.stop_reason, COMPASS_STOP_TIME_BUDGET);
+        assert_eq!(stats.result_quality, COMPASS_RESULT_APPROXIMATE);
+        assert!(stats.iterations < 100_000);
+        assert!(x.iter().all(|x| x.is_finite()));
+
+        for time_budget in [-1.0, f64::NAN] {
+            let options = SolveOptions {
+                time_budget,
+                ..SolveOptions::default()
+            };
+            assert_eq!(
+                solve_message(&graph, &options).0,
+                COMPASS_ERR_INVALID_ARGUMENT
+            );
+        }
+    }
+
+    #[test]
+    fn presets_are_pinned() {
+        // (preset, solver, preconditioner, criterion, tolerance, iterations, time budget,
+        // true residual interval, verify fixed)
+        let pinned = [
+            (Preset::FastPreview, 0, 1, 2, 1e-4, 200, 0.1, 0, 0),
+            (Preset::Standard, 0, 1, 1, 1e-8, 10_000, 0.0, 0, 0),
+            (Preset::Strict, 0, 2, 2, 1e-12, 100_000, 0.0, 10, 1),
+        ];
+        for (
+            preset,
+            solver,
+            preconditioner,
+            criterion,
+            tolerance,
+            iterations,
+            budget,
+            interval,
+            verify,
+        ) in pinned
+        {
+            let options = SolveOptions::preset(preset);
+            let expanded = (
+                options.preset,
+                options.solver,
+                options.preconditioner,
+                options.convergence_criterion,
+                options.tolerance,
+                options.iterations,
+                options.time_budget,
+                options.true_residual_interval,
+                options.verify_fixed,
+                options.breakdown_tolerance,
+            );
+            let expected = (
+                preset as c_int,
+                solver,
+                preconditioner,
+                criterion,
+                tolerance,
+                iterations,
+                budget,
+                interval,
+                verify,
+                0.0,
+            );
+            assert_eq!(expanded, expected, "{preset:?}");
+        }
+    }
+
+    #[test]
+    fn preset_code_overrides_the_tuning_fields() {
+        let graph = grid(12);
+        let options = SolveOptions {
+            iterations: 1,
+            tolerance: 1.0,
+            preconditioner: 3,
+            preset: Preset::Strict as c_int,
+            ..SolveOptions::default()
+        };
+        let (code, x, _, stats) = solve_ex(&graph, &options);
+        assert_eq!(code, COMPASS_OK);
+        assert_eq!(stats.result_quality, COMPASS_RESULT_EXACT);
+        assert_eq!(
+            (stats.preset, stats.solver, stats.preconditioner),
+            (3, 0, 2)
+        );
+        assert_eq!((stats.iteration_limit, stats.tolerance), (100_000, 1e-12));
+        assert_eq!((stats.convergence_criterion, stats.time_budget), (2, 0.0));
+        let (_, expected, _, _) = solve_ex(&graph, &SolveOptions::preset(Preset::Strict));
+        assert_eq!(x, expected);
+
+        // Without a preset the fields are reported as given.
+        let (_, _, _, stats) = solve_ex(&graph, &SolveOptions::default());
+        assert_eq!(
+            (stats.preset, stats.iteration_limit, stats.tolerance),
+            (0, 10_000, 1e-8)
+        );
+
+        let handle = GraphContext::into_raw(graph.clone());
+        let mut stats = SolveStats {
+            struct_size: size_of::<SolveStats>(),
+            ..SolveStats::default()
+        };
+        let options = SolveOptions::preset(Preset::FastPreview);
+        assert_eq!(graph_solve(handle, &options, &mut stats), COMPASS_OK);
+        assert_eq!((stats.preset, stats.time_budget), (1, 0.1));
+        graph_free(handle);
+
+        for preset in [-1, 4] {
+            let options = SolveOptions {
+                preset,
+                ..SolveOptions::default()
+            };
+            assert_eq!(
+                solve_message(&graph, &options).0,
+                COMPASS_ERR_INVALID_ARGUMENT
+            );
+        }
+    }
+
+    #[test]
+    fn per_axis_limits_stop_each_axis_on_its_own() {
+        let graph = grid(20);
+        let tight = SolveOptions {
+            tolerance: 1e-10,
+            ..SolveOptions::default()
+        };
+        let (code, x, y, both) = solve_ex(&graph, &tight);
+        assert_eq!(code, COMPASS_OK);
+        assert_eq!(both.iterations, both.iterations_x.max(both.iterations_y));
+
+        let loose_y = SolveOptions {
+            tolerance_y: 1e-2,
+            ..tight
+        };
+        let (code, x_loose, y_loose, stats) = solve_ex(&graph, &loose_y);
+        assert_eq!(code, COMPASS_OK);
+        assert_eq!(stats.stop_reason, COMPASS_STOP_CONVERGED);
+        assert!(stats.iterations_y < both.iterations_y, "{stats:?}");
+        assert_eq!(stats.iterations_x, both.iterations_x);
+        assert_eq!(x_loose, x);
+        assert_ne!(y_loose, y);
+        assert!(stats.residual_norm_y <= 1e-2 && stats.residual_norm_x <= 1e-10);
+
+        let capped = SolveOptions {
+            iterations_x: 3,
+            ..tight
+        };
+        let (_, _, y_capped, stats) = solve_ex(&graph, &capped);
+        assert_eq!(
+            (stats.iterations_x, stats.iterations_y),
+            (3, both.iterations_y)
+        );
+        assert_eq!(stats.stop_reason, COMPASS_STOP_ITERATIONS);
+        assert_eq!(y_capped, y);
+
+        // The scalar setters apply to both axes again.
+        let mut options = loose_y;
+        options.set_tolerance(1e-10);
+        assert_eq!((options.tolerance_x, options.tolerance_y), (0.0, 0.0));
+        options.iterations_y = 5;
+        options.set_iterations(10_000);
+        assert_eq!(solve_ex(&graph, &options).2, y);
+
+        for (options, axis) in [
+            (
+                SolveOptions {
+                    tolerance_y: -1.0,
+                    ..SolveOptions::default()
+                },
+                "tolerance_y",
+            ),
+            (
+                SolveOptions {
+                    tolerance_x: f64::NAN,
+                    ..SolveOptions::default()
+                },
+                "tolerance_x",
+            ),
+            (
+                SolveOptions {
+                    iterations_y: -2,
+                    ..SolveOptions::default()
+                },
+                "iterations_y",
+            ),
+        ] {
+            let (code, message) = solve_message(&graph, &options);
+            assert_eq!(code, COMPASS_ERR_INVALID_ARGUMENT);
+            assert!(message.starts_with(axis), "{message}");
+        }
+    }
+
+    #[test]
+    fn converged_initial_guess_skips_the_solve() {
+        let graph = grid(15);
+        let handle = GraphContext::into_raw(graph);
+        let mut stats = SolveStats {
+            struct_size: size_of::<SolveStats>(),
+            ..SolveStats::default()
+        };
+        let options = SolveOptions {
+            tolerance: 1e-10,
+            preconditioner: 2,
+            ..SolveOptions::default()
+        };
+        assert_eq!(graph_solve(handle, &options, &mut stats), COMPASS_OK);
+        assert!(stats.iterations > 0);
+        assert_eq!(
+            (stats.stop_reason, stats.skipped_axes),
+            (COMPASS_STOP_CONVERGED, 0)
+        );
+        let first = unsafe { &*handle }.coordinates();
+        let first = (first.0.to_vec(), first.1.to_vec());
+
+        assert_eq!(graph_solve(handle, &options, &mut stats), COMPASS_OK);
+        assert_eq!(stats.iterations, 0);
+        assert_eq!(stats.stop_reason, COMPASS_STOP_ALREADY_CONVERGED);
+        assert_eq!(stats.result_quality, COMPASS_RESULT_EXACT);
+        assert_eq!(stats.skipped_axes, 3);
+        assert!(stats.residual_norm < 1e-10);
+        let (x, y) = unsafe { &*handle }.coordinates();
+        assert_eq!((x, y), (&first.0[..], &first.1[..]));
+
+        // Only the X axis still has work to do.
+        let tighter_x = SolveOptions {
+            tolerance_x: 1e-12,
+            ..options
+        };
+        assert_eq!(graph_solve(handle, &tighter_x, &mut stats), COMPASS_OK);
+        assert_eq!((stats.skipped_axes, stats.iterations_y), (2, 0));
+        assert!(stats.iterations_x > 0);
+        assert_eq!(stats.stop_reason, COMPASS_STOP_CONVERGED);
+        graph_free(handle);
+    }
+
+    #[test]
+    fn adjustable_region_matches_extra_fixed_flags() {
+        let graph = grid(6);
+        // The lower half of the grid is adjustable; the upper half is held.
+        let adjustable: Vec<bool> = (0..36).map(|i| i < 18).collect();
+        let mut reference = graph.clone();
+        for (i, fixed) in reference.fixed.iter_mut().enumerate() {
+            *fixed |= !adjustable[i];
+        }
+        let expected = reference.solve(10_000, 1e-12).unwrap();
+
+        let solution = graph.solve_region(&adjustable, 10_000, 1e-12).unwrap();
+        assert_eq!((&solution.x, &solution.y), (&expected.x, &expected.y));
+        let stats = solution.stats;
+        assert_eq!((stats.free_vertices, stats.held_vertices), (17, 18));
+        // Rows 0 to 2 hold 15 horizontal and 12 vertical edges, two of which touch the anchor.
+        assert_eq!(stats.interior_edges, 27 - 2);
+        assert_eq!(stats.held_boundary_edges, 6);
+        let stress: f64 = solution
+            .residuals(&graph)
+            .iter()
+            .zip(graph.from.iter().zip(&graph.to))
+            .filter(|&(_, (&u, &v))| adjustable[u] != adjustable[v])
+            .map(|((rx, ry), _)| rx * rx + ry * ry)
+            .sum();
+        assert!((stats.held_boundary_stress - stress).abs() < 1e-12);
+
+        let flags: Vec<c_int> = adjustable.iter().map(|&a| a as c_int).collect();
+        let options = SolveOptions {
+            tolerance: 1e-12,
+            adjustable: flags.as_ptr(),
+            ..SolveOptions::default()
+        };
+        let (code, x, y, ffi_stats) = solve_ex(&graph, &options);
+        assert_eq!((code, x, y), (COMPASS_OK, expected.x, expected.y));
+        assert_eq!(ffi_stats.held_vertices, 18);
+        assert_eq!(ffi_stats.interior_edges, stats.interior_edges);
+        assert_eq!(ffi_stats.held_boundary_edges, 6);
+        assert!((ffi_stats.held_boundary_stress - stress).abs() < 1e-12);
+    }
+
+    #[test]
+    fn lists_the_vertices_that_moved_with_a_size_query() {
+        let mut graph = grid(5);
+        // Shift the guess of a few vertices so that they move back by a known amount.
+        let solution = graph.solve(10_000, 1e-12).unwrap();
+        graph.x.clone_from(&solution.x);
+        graph.y.clone_from(&solution.y);
+        for (i, shift) in [(7, 0.5), (12, 2.0), (18, 1.0)] {
+            graph.x[i] += shift;
+        }
+        let moved = graph
+            .solve(10_000, 1e-12)
+            .unwrap()
+            .moved_vertices(&graph, 0.1);
+        let order: Vec<usize> = moved.iter().map(|&(i, _)| i).collect();
+        assert_eq!(order, [12, 18, 7]);
+        assert!(moved.windows(2).all(|w| w[0].1 >= w[1].1));
+        assert!((moved[0].1 - 2.0).abs() < 1e-6);
+
+        // The first call only reports the size and leaves the coordinates untouched.
+        let options = SolveOptions {
+            tolerance: 1e-12,
+            moved_threshold: 0.1,
+            ..SolveOptions::default()
+        };
+        let (code, x, _, stats) = solve_ex(&graph, &options);
+        assert_eq!(code, COMPASS_ERR_BUFFER_TOO_SMALL);
+        assert_eq!(stats.moved_vertices, 3);
+        assert_eq!(stats.result_quality, COMPASS_RESULT_UNTOUCHED);
+        assert_eq!(x, graph.x);
+
+        let mut buffer = vec![-1; stats.moved_vertices as usize + 1];
+        let options = SolveOptions {
+            moved_vertices: buffer.as_mut_ptr(),
+            moved_capacity: buffer.len() as c_int,
+            ..options
+        };
+        let (code, _, _, stats) = solve_ex(&graph, &options);
+        assert_eq!((code, stats.moved_vertices), (COMPASS_OK, 3));
+        assert_eq!(buffer, [12, 18, 7, -1]);
+
+        for (moved_threshold, moved_capacity) in [(-1.0, 0), (f64::NAN, 0), (0.1, 2)] {
+            let options = SolveOptions {
+                moved_threshold,
+                moved_capacity,
+                ..SolveOptions::default()
+            };
+            assert_eq!(
+                solve_message(&graph, &options).0,
+                COMPASS_ERR_INVALID_ARGUMENT
+            );
+        }
+    }
+
+    #[test]
+    fn timings_add_up_to_at_most_the_total() {
+        let graph = grid(20);
+        let options = SolveOptions {
+            tolerance: 1e-12,
+            preconditioner: 1,
+            ..SolveOptions::default()
+        };
+        let (code, _, _, stats) = solve_ex(&graph, &options);
+        assert_eq!(code, COMPASS_OK);
+        let phases = [
+            stats.validate_ms,
+            stats.assemble_ms,
+            stats.precondition_ms,
+            stats.solve_x_ms,
+            stats.solve_y_ms,
+            stats.writeback_ms,
+        ];
+        assert!(stats.total_ms > 0.0, "{stats:?}");
+        assert!(phases.iter().all(|&ms| ms >= 0.0 && ms <= stats.total_ms));
+        // The axes run in parallel, so only one of them counts towards the total.
+        let serial = phases.iter().sum::<f64>() - stats.solve_x_ms.min(stats.solve_y_ms);
+        assert!(serial <= stats.total_ms, "{stats:?}");
+
+        // A second solve on a handle reuses the normal equations and starts from the
+        // solution, so that both axes are skipped.
+        let handle = GraphContext::into_raw(graph);
+        for assembled in [true, false] {
+            let mut stats = SolveStats {
+                struct_size: size_of::<SolveStats>(),
+                ..SolveStats::default()
+            };
+            assert_eq!(graph_solve(handle, &options, &mut stats), COMPASS_OK);
+            assert_eq!(stats.assemble_ms > 0.0, assembled, "{stats:?}");
+            assert_eq!(stats.precondition_ms > 0.0, assembled, "{stats:?}");
+            assert_eq!(stats.skipped_axes == 3, !assembled);
+            assert!(stats.total_ms >= stats.assemble_ms + stats.solve_x_ms.max(stats.solve_y_ms));
+        }
+        graph_free(handle);
+    }
+
+    #[test]
+    fn trivial_solves_are_flagged_or_rejected() {
+        use TrivialReason::*;
+        let cases = [
+            (true, true, None, COMPASS_OK, ""),
+            (
+                false,
+                true,
+                NoEdges,
+                COMPASS_ERR_NO_EDGES,
+                "no edge is enabled",
+            ),
+            (
+                true,
+                false,
+                NoFreeVertices,
+                COMPASS_ERR_NO_FREE_VERTICES,
+                "no vertex is free",
+            ),
+            (
+                false,
+                false,
+                NoEdgesNorFreeVertices,
+                COMPASS_ERR_NO_EDGES,
+                "no edge is enabled",
+            ),
+        ];
+        for (edges, free, reason, error, expected) in cases {
+            let mut graph = Graph::default();
+            graph.add_vertex(0.0, 0.0, 0.0, true);
+            graph.add_vertex(1.0, 0.0, 0.0, !free);
+            if edges {
+                graph.add_edge(0, 1, 1.5, 0.0, 0.0, 1.0);
+            }
+            let solution = graph.solve(100, 1e-12).unwrap();
+            assert_eq!(solution.trivial, reason);
+            assert_eq!(solution.stats.trivial, reason.flags());
+            assert_eq!(TrivialReason::from_flags(reason.flags()), reason);
+            assert_eq!(solution.stats.iterations == 0, reason != None);
+
+            // Isolated vertices are reported on their own, so keep them to see the flags.
+            let options = SolveOptions {
+                tolerance: 1e-12,
+                keep_isolated: 1,
+                ..SolveOptions::default()
+            };
+            let (code, x, _, stats) = solve_ex(&graph, &options);
+            assert_eq!((code, stats.trivial), (COMPASS_OK, reason.flags()));
+            assert_eq!(x == graph.x, reason != None);
+
+            let options = SolveOptions {
+                error_on_trivial: 1,
+                ..options
+            };
+            assert_eq!(
+                solve_message(&graph, &options),
+                (error, expected.to_string())
+            );
+
+            let handle = GraphContext::into_raw(graph);
+            let mut stats = SolveStats {
+                struct_size: size_of::<SolveStats>(),
+                ..SolveStats::default()
+            };
+            assert_eq!(graph_solve(handle, &options, &mut stats), error);
+            if reason == None {
+                assert_eq!(stats.trivial, 0);
+            }
+            graph_free(handle);
+        }
+    }
+
+    #[test]
+    fn fixed_flags_are_bytes_or_ints_of_zero_or_one() {
+        let graph = network();
+        let options = SolveOptions {
+            tolerance: 1e-12,
+            ..SolveOptions::default()
+        };
+        let (code, x, y, _) = solve_ex(&graph, &options);
+        assert_eq!(code, COMPASS_OK);
+
+        // The same flags as bytes, with a null `fixed` argument.
+        let bytes: Vec<u8> = graph.fixed.iter().map(|&f| f as u8).collect();
+        let from: Vec<c_int> = graph.from.iter().map(|&v| v as c_int).collect();
+        let to: Vec<c_int> = graph.to.iter().map(|&v| v as c_int).collect();
+        let (mut bx, mut by) = (graph.x.clone(), graph.y.clone());
+        let solve_bytes = |bytes: &[u8], bx: &mut [f64], by: &mut [f64]| {
+            let mut message = [0 as c_char; 64];
+            let options = SolveOptions {
+                fixed_bytes: bytes.as_ptr(),
+                error_message: message.as_mut_ptr(),
+                error_capacity: message.len() as c_int,
+                ..options
+            };
+            let code = solve_graph_least_squares_ex(
+                graph.num_vertices() as c_int,
+                bx.as_mut_ptr(),
+                by.as_mut_ptr(),
+                std::ptr::null(),
+                graph.num_edges() as c_int,
+                from.as_ptr(),
+                to.as_ptr(),
+                graph.dx.as_ptr(),
+                graph.dy.as_ptr(),
+                graph.weight.as_ptr(),
+                &options,
+                std::ptr::null_mut(),
+            );
+            let message = unsafe { std::ffi::CStr::from_ptr(message.as_ptr()) };
+            (code, message.to_str().unwrap().to_string())
+        };
+        assert_eq!(solve_bytes(&bytes, &mut bx, &mut by).0, COMPASS_OK);
+        assert_eq!((bx, by), (x, y));
+
+        // A stray value is no longer taken for an anchor, in either form.
+        let mut stray = bytes.clone();
+        stray[2] = 0xff;
+        let (mut bx, mut by) = (graph.x.clone(), graph.y.clone());
+        assert_eq!(
+            solve_bytes(&stray, &mut bx, &mut by),
+            (
+                COMPASS_ERR_INVALID_FIXED_FLAG,
+                "fixed flag of vertex 2 is 255, not 0 or 1".to_string()
+            )
+        );
+        assert_eq!((bx, by), (graph.x.clone(), graph.y.clone()));
+
+        let fixed: Vec<c_int> = graph.fixed.iter().map(|&f| f as c_int).collect();
+        for (i, value) in [(1, -1), (3, 2)] {
+            let mut ints = fixed.clone();
+            ints[i] = value;
+            let (mut ix, mut iy) = (graph.x.clone(), graph.y.clone());
+            let code = solve_graph_least_squares(
+                graph.num_vertices() as c_int,
+                ix.as_mut_ptr(),
+                iy.as_mut_ptr(),
+                ints.as_ptr(),
+                graph.num_edges() as c_int,
+                from.as_ptr(),
+                to.as_ptr(),
+                graph.dx.as_ptr(),
+                graph.dy.as_ptr(),
+                graph.weight.as_ptr(),
+                1000,
+                1e-12,
+            );
+            assert_eq!(code, COMPASS_ERR_INVALID_FIXED_FLAG);
+            assert_eq!(ix, graph.x);
+        }
+    }
+
+    #[test]
+    fn runaway_vertices_are_clamped_to_the_bounds() {
+        // A shot entered in the wrong unit flings vertex 2 to 1e15; vertex 3 is frozen
+        // outside the box, and stays there.
+        let mut graph = Graph::default();
+        for (x, fixed) in [(0.0, true), (10.0, false), (20.0, false), (-500.0, false)] {
+            graph.add_vertex(x, 0.0, 0.0, fixed);
+        }
+        graph.add_edge(0, 1, 10.0, 0.0, 0.0, 1.0);
+        graph.add_edge(1, 2, 1e15, 0.0, 0.0, 1.0);
+        graph.add_edge(0, 3, -500.0, 0.0, 0.0, 1.0);
+        let frozen = [0, 0, 0, 1];
+        let options = SolveOptions {
+            tolerance: 1e-12,
+            frozen: frozen.as_ptr(),
+            ..SolveOptions::default()
+        };
+        let (code, x, _, stats) = solve_ex(&graph, &options);
+        assert_eq!((code, stats.clamped_vertices), (COMPASS_OK, 0));
+        assert!(x[2] > 1e14);
+        assert!(stats.max_displacement > 1e14);
+
+        let mut message = [0 as c_char; 64];
+        let clamped = SolveOptions {
+            clamp_to_bounds: 1,
+            bounds_min_x: -100.0,
+            bounds_min_y: -100.0,
+            bounds_max_x: 100.0,
+            bounds_max_y: 100.0,
+            error_message: message.as_mut_ptr(),
+            error_capacity: message.len() as c_int,
+            ..options
+        };
+        let (code, x, y, stats) = solve_ex(&graph, &clamped);
+        assert_eq!((code, stats.clamped_vertices), (COMPASS_WARN_CLAMPED, 1));
+        assert_eq!(stats.result_quality, COMPASS_RESULT_EXACT);
+        assert!(stats.max_displacement > 1e14);
+        // Vertex 1 only carries the rounding of coordinates near 1e15.
+        assert!((x[1] - 10.0).abs() < 0.1, "{}", x[1]);
+        assert_eq!((x[2], y[2]), (100.0, 0.0));
+        assert_eq!(x[3], -500.0);
+        let message = unsafe { std::ffi::CStr::from_ptr(message.as_ptr()) };
+        assert_eq!(message.to_str().unwrap(), "clamped free vertices 2");
+
+        // A network inside the box is left alone.
+        graph.dx[1] = 10.0;
+        let (code, _, _, stats) = solve_ex(&graph, &clamped);
+        assert_eq!((code, stats.clamped_vertices), (COMPASS_OK, 0));
+
+        for (min_x, max_x) in [(f64::NAN, 100.0), (100.0, -100.0)] {
+            let invalid = SolveOptions {
+                bounds_min_x: min_x,
+                bounds_max_x: max_x,
+                ..clamped
+            };
+            let (code, x, _, _) = solve_ex(&graph, &invalid);
+            assert_eq!((code, x), (COMPASS_ERR_INVALID_ARGUMENT, graph.x.clone()));
+        }
+    }
+
+    #[test]
+    fn reversed_edges_match_negated_observations() {
+        // Anchors 0 and 3 and a misclosed loop through 1 and 2, with every fixity case among
+        // the edges flagged reversed: free-fixed 1-0, free-free 2-1 and fixed-fixed 3-0.
+        let mut native = Graph::default();
+        for (x, y, fixed) in [(0.0, 0.0, true), (9.0, 1.0, false), (11.0, 9.0, false)] {
+            native.add_vertex(x, y, 0.0, fixed);
+        }
+        native.add_vertex(0.0, 10.0, 0.0, true);
+        native.add_edge(1, 0, 10.0, 0.2, 0.0, 1.0);
+        native.add_edge(2, 1, -0.3, -10.0, 0.0, 2.0);
+        native.add_edge(2, 3, -10.0, 0.1, 0.0, 1.0);
+        native.add_edge(3, 0, 0.1, 10.0, 0.0, 1.0);
+        native.add_edge(0, 2, 10.2, 9.9, 0.0, 0.5);
+        let reversed = [1, 1, 0, 1, 0];
+        let mut negated = native.clone();
+        for e in (0..native.num_edges()).filter(|&e| reversed[e] != 0) {
+            (negated.dx[e], negated.dy[e]) = (-native.dx[e], -native.dy[e]);
+        }
+
+        let mut residuals = [[f64::NAN; 2]; 2].map(|_| vec![f64::NAN; 10]);
+        let options = SolveOptions {
+            tolerance: 1e-12,
+            edge_residuals: residuals[0].as_mut_ptr(),
+            ..SolveOptions::default()
+        };
+        let (code, x, y, _) = solve_ex(&negated, &options);
+        assert_eq!(code, COMPASS_OK);
+        let flagged = SolveOptions {
+            reversed: reversed.as_ptr(),
+            edge_residuals: residuals[1].as_mut_ptr(),
+            ..options
+        };
+        let (code, x_flagged, y_flagged, _) = solve_ex(&native, &flagged);
+        assert_eq!(code, COMPASS_OK);
+        for i in 0..4 {
+            assert!((x[i] - x_flagged[i]).abs() < 1e-9);
+            assert!((y[i] - y_flagged[i]).abs() < 1e-9);
+        }
+        // The residuals of the reversed edges are in the direction of their observations.
+        for e in 0..native.num_edges() {
+            let sign = [1.0, -1.0][reversed[e] as usize];
+            for k in 0..2 {
+                let (flagged, negated) = (residuals[1][2 * e + k], residuals[0][2 * e + k]);
+                assert!((flagged - sign * negated).abs() < 1e-9, "edge {e}");
+            }
+        }
+        // Between the anchors, 3-0 observes x_3 - x_0 = 0.1 and y_3 - y_0 = 10.
+        assert_eq!(residuals[1][6..8], [-0.1, 0.0]);
+
+        // Flags are 0 or 1, as the fixed flags.
+        let invalid = [1, 2, 0, 1, 0];
+        let options = SolveOptions {
+            reversed: invalid.as_ptr(),
+            ..SolveOptions::default()
+        };
+        assert_eq!(
+            solve_message(&native, &options),
+            (
+                COMPASS_ERR_INVALID_ARGUMENT,
+                "reversed flag of edge 1 is 2, not 0 or 1".to_string()
+            )
+        );
+    }
+}
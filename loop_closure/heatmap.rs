@@ -0,0 +1,221 @@
+//! Per-edge scalars in `[0, 1]` for coloring edges by standardized residual.
+//!
+//! Results depend only on the graph and the coordinates: ties and NaN are ordered with
+//! `total_cmp`, so a given input always maps to the same colors.
+
+use crate::{COMPASS_ERR_INVALID_ARGUMENT, Graph, GraphContext, Solution};
+use std::ffi::{c_double, c_int};
+use std::slice;
+
+/// Normalization of the standardized residuals in [`Solution::residual_colors`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColorScale {
+    /// `s / max(s)`.
+    Linear,
+    /// `ln(1 + s) / ln(1 + max(s))`, spreading the small residuals.
+    Log,
+    /// `s / p`, clamped to 1, where `p` is the given percentile (0 to 100) of the residuals,
+    /// so a few blunders do not wash out the rest.
+    Percentile(f64),
+}
+
+impl ColorScale {
+    /// Scale for the FFI code: 0 = Linear, 1 = Log, 2 = Percentile at `percentile`.
+    fn from_code(code: c_int, percentile: f64) -> Option<ColorScale> {
+        match code {
+            0 => Some(ColorScale::Linear),
+            1 => Some(ColorScale::Log),
+            2 if (0.0..=100.0).contains(&percentile) => Some(ColorScale::Percentile(percentile)),
+            _ => None,
+        }
+    }
+}
+
+impl Solution {
+    /// Value in `[0, 1]` of each edge for a color ramp, from its standardized residual
+    /// normalized by `scale`.
+    pub fn residual_colors(&self, graph: &Graph, scale: ColorScale) -> Vec<f32> {
+        residual_colors(&self.standardized_residuals(graph), scale, &|_| true)
+    }
+}
+
+impl GraphContext {
+    /// [`Solution::residual_colors`] at the current [`GraphContext::coordinates`]. Disabled
+    /// edges get 0 and do not take part in the normalization.
+    pub fn residual_colors(&self, scale: ColorScale) -> Vec<f32> {
+        let (x, y) = self.coordinates();
+        let solution = Solution {
+            x: x.to_vec(),
+            y: y.to_vec(),
+            ..Solution::default()
+        };
+        residual_colors(&solution.standardized_residuals(&self.graph), scale, &|e| {
+            self.is_edge_enabled(e)
+        })
+    }
+}
+
+fn residual_colors(
+    standardized: &[f64],
+    scale: ColorScale,
+    is_enabled: &dyn Fn(usize) -> bool,
+) -> Vec<f32> {
+    // NaN (e.g. from a negative weight) counts as no residual.
+    let value = |e: usize| match standardized[e] {
+        s if is_enabled(e) && s.is_finite() => s.max(0.0),
+        _ => 0.0,
+    };
+    let mut sorted: Vec<f64> = (0..standardized.len())
+        .filter(|&e| is_enabled(e))
+        .map(value)
+        .collect();
+    sorted.sort_by(f64::total_cmp);
+    let max = sorted.last().copied().unwrap_or(0.0);
+    let (transform, reference): (fn(f64) -> f64, f64) = match scale {
+        ColorScale::Linear => (|s| s, max),
+        ColorScale::Log => (f64::ln_1p, max.ln_1p()),
+        ColorScale::Percentile(p) => (|s| s, percentile(&sorted, p)),
+    };
+    (0..standardized.len())
+        .map(|e| match reference > 0.0 {
+            true => (transform(value(e)) / reference).min(1.0) as f32,
+            false => 0.0,
+        })
+        .collect()
+}
+
+/// The `p`-th percentile (0 to 100) of ascending `sorted` values, interpolating linearly
+/// between the closest ranks. 0 for an empty slice.
+pub fn percentile(sorted: &[f64], p: f64) -> f64 {
+    let Some(&last) = sorted.last() else {
+        return 0.0;
+    };
+    let rank = p.clamp(0.0, 100.0) / 100.0 * (sorted.len() - 1) as f64;
+    let below = rank.floor() as usize;
+    match sorted.get(below + 1) {
+        Some(&above) => sorted[below] + (above - sorted[below]) * (rank - below as f64),
+        None => last,
+    }
+}
+
+/// Writes the [`GraphContext::residual_colors`] of the graph behind `handle` into a caller
+/// buffer of [`crate::graph_num_edges`] elements.
+///
+/// `scale` is 0 = Linear, 1 = Log, 2 = Percentile, the latter clamping at the `percentile`
+/// (0 to 100) of the residuals. Returns the number of edges written, or
+/// [`COMPASS_ERR_INVALID_ARGUMENT`] for a null pointer or an unknown scale.
+#[unsafe(no_mangle)]
+pub extern "C" fn graph_residual_colors(
+    handle: *const GraphContext,
+    scale: c_int,
+    percentile: c_double,
+    out: *mut f32,
+) -> c_int {
+    let Some(ctx) = (unsafe { handle.as_ref() }) else {
+        return COMPASS_ERR_INVALID_ARGUMENT;
+    };
+    let Some(scale) = ColorScale::from_code(scale, percentile) else {
+        return COMPASS_ERR_INVALID_ARGUMENT;
+    };
+    if out.is_null() {
+        return COMPASS_ERR_INVALID_ARGUMENT;
+    }
+    let colors = ctx.residual_colors(scale);
+    // Safety: The caller guarantees a buffer of `num_edges` elements.
+    unsafe { slice::from_raw_parts_mut(out, colors.len()) }.copy_from_slice(&colors);
+    colors.len() as c_int
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percentiles_of_known_distributions() {
+        // Uniform 0..=100: the p-th percentile is p.
+        let uniform: Vec<f64> = (0..=100).map(f64::from).collect();
+        for p in [0.0, 12.5, 50.0, 95.0, 100.0] {
+            assert!((percentile(&uniform, p) - p).abs() < 1e-12);
+        }
+        // Interpolation between the closest ranks.
+        assert_eq!(percentile(&[1.0, 2.0, 3.0, 4.0], 50.0), 2.5);
+        assert!((percentile(&[1.0, 2.0, 3.0, 4.0], 95.0) - 3.85).abs() < 1e-12);
+        // Squares of 0..=10: the 90th percentile is 9² exactly, the 95th halfway to 10².
+        let squares: Vec<f64> = (0..=10).map(|i| f64::from(i * i)).collect();
+        assert_eq!(percentile(&squares, 90.0), 81.0);
+        assert!((percentile(&squares, 95.0) - 90.5).abs() < 1e-12);
+        // Degenerate inputs and out-of-range percentiles.
+        assert_eq!(percentile(&[], 50.0), 0.0);
+        assert_eq!(percentile(&[7.0], 30.0), 7.0);
+        assert_eq!(percentile(&uniform, 150.0), 100.0);
+    }
+
+    #[test]
+    fn scales_normalize_the_residuals() {
+        let mut standardized: Vec<f64> = (0..20).map(|i| f64::from(i) / 10.0).collect();
+        standardized.push(100.0);
+        let all = |_: usize| true;
+
+        let linear = residual_colors(&standardized, ColorScale::Linear, &all);
+        assert_eq!(linear[20], 1.0);
+        assert_eq!(linear[10], 0.01);
+        let log = residual_colors(&standardized, ColorScale::Log, &all);
+        assert_eq!(log[10], (1f64.ln_1p() / 100f64.ln_1p()) as f32);
+        assert!(log[10] > linear[10]);
+
+        // The blunder no longer washes out the rest: 1.9 is the 95th percentile.
+        let robust = residual_colors(&standardized, ColorScale::Percentile(95.0), &all);
+        assert_eq!(robust[19], 1.0);
+        assert_eq!(robust[20], 1.0);
+        assert_eq!(robust[10], (1.0 / 1.9) as f32);
+        assert!(robust.iter().all(|c| (0.0..=1.0).contains(c)));
+    }
+
+    #[test]
+    fn disabled_and_invalid_residuals_color_as_zero() {
+        let standardized = [1.0, f64::NAN, -2.0, 4.0, 2.0];
+        let colors = residual_colors(&standardized, ColorScale::Linear, &|e| e != 3);
+        assert_eq!(colors, [0.5, 0.0, 0.0, 0.0, 1.0]);
+        let zero = residual_colors(&[0.0, 0.0], ColorScale::Percentile(50.0), &|_| true);
+        assert_eq!(zero, [0.0, 0.0]);
+        assert!(residual_colors(&[], ColorScale::Log, &|_| true).is_empty());
+    }
+
+    #[test]
+    fn ffi_colors_are_deterministic() {
+        let mut graph = Graph::default();
+        for (x, y, fixed) in [(0.0, 0.0, true), (10.0, 0.0, false), (10.0, 10.0, false)] {
+            graph.add_vertex(x, y, 0.0, fixed);
+        }
+        graph.add_edge(0, 1, 10.0, 0.1, 0.0, 1.0);
+        graph.add_edge(1, 2, 0.0, 10.0, 0.0, 1.0);
+        graph.add_edge(0, 2, 10.5, 10.0, 0.0, 1.0);
+        let handle = GraphContext::into_raw(graph);
+        let ctx = unsafe { &mut *handle };
+        ctx.solve(1000, 1e-12.into(), Default::default(), None, &|| false)
+            .unwrap();
+
+        let mut first = [0f32; 3];
+        let mut second = [0f32; 3];
+        assert_eq!(
+            graph_residual_colors(handle, 2, 95.0, first.as_mut_ptr()),
+            3
+        );
+        assert_eq!(
+            graph_residual_colors(handle, 2, 95.0, second.as_mut_ptr()),
+            3
+        );
+        assert_eq!(first.map(f32::to_bits), second.map(f32::to_bits));
+        assert_eq!(
+            first.to_vec(),
+            ctx.residual_colors(ColorScale::Percentile(95.0))
+        );
+        for (scale, percentile) in [(3, 0.0), (2, 101.0), (2, f64::NAN)] {
+            let code = graph_residual_colors(handle, scale, percentile, first.as_mut_ptr());
+            assert_eq!(code, COMPASS_ERR_INVALID_ARGUMENT);
+        }
+        let code = graph_residual_colors(handle, 0, 0.0, std::ptr::null_mut());
+        assert_eq!(code, COMPASS_ERR_INVALID_ARGUMENT);
+        crate::graph_free(handle);
+    }
+}
@@ -0,0 +1,404 @@
+//! Two-level solve of merged datasets, many caves tied together by a surface network.
+//!
+//! Each vertex is given a region id by the caller, typically one per cave and one for the
+//! surface network. Free vertices with an edge to another region are the boundary vertices;
+//! the other vertices of a region form its interior, which is only coupled to the rest of
+//! the network through the boundary of its region. The solve then runs in three steps:
+//!
+//! 1. Condensation: the interior of each region, its boundary held, solves as an affine
+//!    function of the boundary coordinates. One interior solve gives the constant term, the
+//!    boundary at the origin, and one per boundary vertex tied to the interior the
+//!    coefficients of its coordinates; the latter are solved two at a time, as the X and Y
+//!    right-hand sides of one solve.
+//! 2. Skeleton: the normal equations of the boundary vertices, the interiors replaced by
+//!    their affine functions, form the Schur complement of the interiors in the normal
+//!    matrix. It is dense and factored directly, which suits up to a few thousand boundary
+//!    vertices.
+//! 3. Interiors: each region is solved again with its boundary fixed at the skeleton result.
+//!
+//! The result is that of the monolithic [`Graph::solve`] up to the tolerance of the interior
+//! solves. The regions are condensed and solved in parallel. Region ids are arbitrary; a
+//! single region solves like [`Graph::solve`], with an empty skeleton.
+
+use crate::amg::DenseCholesky;
+use crate::{
+    COMPASS_ERR_INVALID_ARGUMENT, Graph, LinearSolver, Preconditioner, SolveError, SolveStats,
+    Stopwatch, Tolerance,
+};
+use nalgebra::DVector;
+use std::collections::BTreeMap;
+
+/// Result of [`Graph::solve_hierarchical`].
+#[derive(Debug, Clone, Default)]
+pub struct HierarchicalSolution {
+    /// Adjusted X coordinates, one per vertex.
+    pub x: Vec<f64>,
+    /// Adjusted Y coordinates, one per vertex.
+    pub y: Vec<f64>,
+    /// Number of boundary vertices, the unknowns of the skeleton system.
+    pub skeleton_vertices: usize,
+    /// Time spent assembling and solving the skeleton system, in milliseconds.
+    pub skeleton_ms: f64,
+    /// One entry per region, by increasing region id.
+    pub regions: Vec<RegionStats>,
+}
+
+/// Summary of one region of a [`HierarchicalSolution`].
+#[derive(Debug, Clone, Default)]
+pub struct RegionStats {
+    /// Region id, as given per vertex.
+    pub region: i32,
+    /// Number of vertices of the region, its boundary included.
+    pub vertices: usize,
+    /// Number of its boundary vertices.
+    pub boundary_vertices: usize,
+    /// Number of interior solves of the condensation.
+    pub condensation_solves: usize,
+    /// Time spent condensing the region, in milliseconds.
+    pub condensation_ms: f64,
+    /// Summary of the final solve of the interior, its boundary fixed.
+    pub stats: SolveStats,
+}
+
+/// A region as a graph of its own, its boundary vertices fixed.
+struct Region {
+    id: i32,
+    graph: Graph,
+    /// Original index of each vertex of `graph`.
+    vertices: Vec<usize>,
+    /// Vertices of `graph` on the boundary, with their skeleton index.
+    boundary: Vec<(usize, usize)>,
+}
+
+/// Interior of a region as an affine function of its boundary coordinates.
+struct Condensed {
+    /// Coordinates of the interior with the boundary at the origin.
+    x: Vec<f64>,
+    y: Vec<f64>,
+    /// Skeleton index of each boundary vertex tied to the interior, with the coordinates of
+    /// the interior per unit of its coordinate.
+    columns: Vec<(usize, Vec<f64>)>,
+    solves: usize,
+    ms: f64,
+}
+
+impl Graph {
+    /// Solves the horizontal adjustment in two levels over the regions given by `region`, one
+    /// id per vertex, leaving the graph untouched. See the [module documentation](self);
+    /// `iterations` and `tolerance` apply to each interior solve.
+    pub fn solve_hierarchical(
+        &self,
+        region: &[i32],
+        iterations: usize,
+        tolerance: f64,
+    ) -> Result<HierarchicalSolution, SolveError> {
+        if region.len() != self.num_vertices() {
+            return Err(SolveError {
+                code: COMPASS_ERR_INVALID_ARGUMENT,
+                message: format!(
+                    "expected {} region ids, got {}",
+                    self.num_vertices(),
+                    region.len()
+                ),
+            });
+        }
+        let n = self.num_vertices();
+        let mut skeleton = vec![None; n];
+        let mut skeleton_vertices = 0;
+        for (&u, &v) in self.from.iter().zip(&self.to) {
+            if region[u] != region[v] {
+                for i in [u, v] {
+                    if !self.fixed[i] && skeleton[i].is_none() {
+                        skeleton[i] = Some(skeleton_vertices);
+                        skeleton_vertices += 1;
+                    }
+                }
+            }
+        }
+        let regions = self.regions(region, &skeleton);
+
+        let condensed = parallel_map(regions.len(), |r| {
+            condense(&regions[r], iterations, tolerance.into())
+        })
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()?;
+
+        let clock = Stopwatch::start();
+        let (bx, by) = self.skeleton_solution(&skeleton, skeleton_vertices, &regions, &condensed);
+        let skeleton_ms = clock.ms();
+
+        let (mut x, mut y) = (self.x.clone(), self.y.clone());
+        for (i, k) in skeleton.iter().enumerate() {
+            if let Some(k) = *k {
+                (x[i], y[i]) = (bx[k], by[k]);
+            }
+        }
+        let solutions = parallel_map(regions.len(), |r| {
+            let mut graph = regions[r].graph.clone();
+            for &(local, k) in &regions[r].boundary {
+                (graph.x[local], graph.y[local]) = (bx[k], by[k]);
+            }
+            graph.solve(iterations, tolerance)
+        });
+        let mut stats = Vec::with_capacity(regions.len());
+        for ((region, condensed), solution) in regions.iter().zip(&condensed).zip(solutions) {
+            let solution = solution?;
+            for (local, &i) in region.vertices.iter().enumerate() {
+                (x[i], y[i]) = (solution.x[local], solution.y[local]);
+            }
+            stats.push(RegionStats {
+                region: region.id,
+                vertices: region.vertices.len(),
+                boundary_vertices: region.boundary.len(),
+                condensation_solves: condensed.solves,
+                condensation_ms: condensed.ms,
+                stats: solution.stats,
+            });
+        }
+        Ok(HierarchicalSolution {
+            x,
+            y,
+            skeleton_vertices,
+            skeleton_ms,
+            regions: stats,
+        })
+    }
+
+    /// The regions of `region` as graphs of their own, by increasing id, the vertices with a
+    /// `skeleton` index fixed.
+    fn regions(&self, region: &[i32], skeleton: &[Option<usize>]) -> Vec<Region> {
+        let mut members: BTreeMap<i32, Vec<usize>> = BTreeMap::new();
+        for (i, &id) in region.iter().enumerate() {
+            members.entry(id).or_default().push(i);
+        }
+        let mut local = vec![usize::MAX; self.num_vertices()];
+        let mut regions: Vec<Region> = members
+            .into_iter()
+            .map(|(id, vertices)| {
+                let mut graph = Graph::default();
+                let mut boundary = Vec::new();
+                for &i in &vertices {
+                    let fixed = self.fixed[i] || skeleton[i].is_some();
+                    local[i] = graph.add_vertex(self.x[i], self.y[i], self.z[i], fixed);
+                    if let Some(k) = skeleton[i] {
+                        boundary.push((local[i], k));
+                    }
+                }
+                Region {
+                    id,
+                    graph,
+                    vertices,
+                    boundary,
+                }
+            })
+            .collect();
+        let index: BTreeMap<i32, usize> =
+            regions.iter().enumerate().map(|(r, g)| (g.id, r)).collect();
+        for e in 0..self.num_edges() {
+            let (u, v) = (self.from[e], self.to[e]);
+            if region[u] == region[v] {
+                let graph = &mut regions[index[&region[u]]].graph;
+                graph.add_edge(
+                    local[u],
+                    local[v],
+                    self.dx[e],
+                    self.dy[e],
+                    self.dz[e],
+                    self.weight[e],
+                );
+            }
+        }
+        regions
+    }
+
+    /// Solves the skeleton system over the vertices with a `skeleton` index, the interiors
+    /// replaced by their `condensed` functions. Returns the X and Y coordinates by index.
+    fn skeleton_solution(
+        &self,
+        skeleton: &[Option<usize>],
+        size: usize,
+        regions: &[Region],
+        condensed: &[Condensed],
+    ) -> (Vec<f64>, Vec<f64>) {
+        // Region and local index of each interior vertex.
+        let mut interior = vec![None; self.num_vertices()];
+        for (r, region) in regions.iter().enumerate() {
+            for (local, &i) in region.vertices.iter().enumerate() {
+                if !self.fixed[i] && skeleton[i].is_none() {
+                    interior[i] = Some((r, local));
+                }
+            }
+        }
+        let mut s = vec![0.0; size * size];
+        let (mut gx, mut gy) = (vec![0.0; size], vec![0.0; size]);
+        for e in 0..self.num_edges() {
+            let (u, v) = (self.from[e], self.to[e]);
+            let w = self.weight[e];
+            // The equation of each boundary end: w (x_end - x_other - sign * d) = 0.
+            for (end, other, sign) in [(u, v, -1.0), (v, u, 1.0)] {
+                let Some(k) = skeleton[end].filter(|_| u != v) else {
+                    continue;
+                };
+                s[k * size + k] += w;
+                gx[k] += sign * w * self.dx[e];
+                gy[k] += sign * w * self.dy[e];
+                if let Some(j) = skeleton[other] {
+                    s[k * size + j] -= w;
+                } else if let Some((r, local)) = interior[other] {
+                    let condensed = &condensed[r];
+                    gx[k] += w * condensed.x[local];
+                    gy[k] += w * condensed.y[local];
+                    for (j, column) in &condensed.columns {
+                        s[k * size + j] -= w * column[local];
+                    }
+                } else {
+                    gx[k] += w * self.x[other];
+                    gy[k] += w * self.y[other];
+                }
+            }
+        }
+        // The condensed terms are symmetric up to the tolerance of the interior solves.
+        for k in 0..size {
+            for j in 0..k {
+                let mean = (s[k * size + j] + s[j * size + k]) / 2.0;
+                (s[k * size + j], s[j * size + k]) = (mean, mean);
+            }
+        }
+        let factor = DenseCholesky::factor(size, s);
+        (factor.solve(&gx), factor.solve(&gy))
+    }
+}
+
+/// Condenses the interior of `region`, see the [module documentation](self).
+fn condense(
+    region: &Region,
+    iterations: usize,
+    tolerance: Tolerance,
+) -> Result<Condensed, SolveError> {
+    let clock = Stopwatch::start();
+    let mut graph = region.graph.clone();
+    for &(local, _) in &region.boundary {
+        (graph.x[local], graph.y[local]) = (0.0, 0.0);
+    }
+    let mut system = graph.normal_equations(&[])?;
+    let method = (LinearSolver::default(), Preconditioner::default());
+    let (mut x, mut y) = (graph.x.clone(), graph.y.clone());
+    system.solve(&mut x, &mut y, iterations, tolerance, method, &|| false);
+    let mut solves = 1;
+
+    // Right-hand side of the unit coordinate of each boundary vertex tied to the interior.
+    let mut tied: Vec<(usize, usize, DVector<f64>)> = Vec::new();
+    for &(local, k) in &region.boundary {
+        let mut b = DVector::zeros(system.size());
+        for e in 0..graph.num_edges() {
+            let (u, v) = (graph.from[e], graph.to[e]);
+            let other = match (u == local, v == local) {
+                (true, false) => v,
+                (false, true) => u,
+                _ => continue,
+            };
+            if let Some(row) = system.mapping[other] {
+                b[row] += graph.weight[e];
+            }
+        }
+        if b.iter().any(|&b| b != 0.0) {
+            tied.push((local, k, b));
+        }
+    }
+    let mut columns = Vec::with_capacity(tied.len());
+    for pair in tied.chunks(2) {
+        system.bx = pair[0].2.clone();
+        system.by = pair
+            .get(1)
+            .map_or_else(|| DVector::zeros(system.size()), |t| t.2.clone());
+        let n = graph.num_vertices();
+        let (mut cx, mut cy) = (vec![0.0; n], vec![0.0; n]);
+        system.solve(&mut cx, &mut cy, iterations, tolerance, method, &|| false);
+        solves += 1;
+        columns.push((pair[0].1, cx));
+        if let Some(second) = pair.get(1) {
+            columns.push((second.1, cy));
+        }
+    }
+    Ok(Condensed {
+        x,
+        y,
+        columns,
+        solves,
+        ms: clock.ms(),
+    })
+}
+
+/// `f(0), ..., f(count - 1)`, spread over the available cores.
+fn parallel_map<T: Send>(count: usize, f: impl Fn(usize) -> T + Sync) -> Vec<T> {
+    // wasm32-unknown-unknown cannot spawn threads, so the items are computed in order.
+    #[cfg(target_arch = "wasm32")]
+    return (0..count).map(f).collect();
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        use std::sync::Mutex;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+        let next = AtomicUsize::new(0);
+        let results: Mutex<Vec<Option<T>>> = Mutex::new((0..count).map(|_| None).collect());
+        std::thread::scope(|s| {
+            for _ in 0..threads.min(count) {
+                s.spawn(|| {
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        if i >= count {
+                            break;
+                        }
+                        let result = f(i);
+                        results.lock().unwrap_or_else(|e| e.into_inner())[i] = Some(result);
+                    }
+                });
+            }
+        });
+        let results = results.into_inner().unwrap_or_else(|e| e.into_inner());
+        results
+            .into_iter()
+            .map(|r| r.expect("computed by a thread"))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::synthetic_cave;
+
+    #[test]
+    fn two_regions_solve_like_the_whole_network() {
+        let graph = synthetic_cave(5, 200);
+        let region: Vec<i32> = (0..200).map(|i| (i >= 100) as i32).collect();
+        let hierarchical = graph.solve_hierarchical(&region, 10_000, 1e-12).unwrap();
+        let monolithic = graph.solve(10_000, 1e-12).unwrap();
+        for v in 0..200 {
+            assert!((hierarchical.x[v] - monolithic.x[v]).abs() < 1e-6);
+            assert!((hierarchical.y[v] - monolithic.y[v]).abs() < 1e-6);
+        }
+
+        assert!(hierarchical.skeleton_vertices > 0);
+        let ids: Vec<i32> = hierarchical.regions.iter().map(|r| r.region).collect();
+        assert_eq!(ids, [0, 1]);
+        for stats in &hierarchical.regions {
+            assert_eq!(stats.vertices, 100);
+            assert!(stats.boundary_vertices > 0 && stats.condensation_solves > 1);
+        }
+        let boundary: usize = hierarchical
+            .regions
+            .iter()
+            .map(|r| r.boundary_vertices)
+            .sum();
+        assert_eq!(boundary, hierarchical.skeleton_vertices);
+
+        // A single region has no skeleton.
+        let single = graph.solve_hierarchical(&[7; 200], 10_000, 1e-12).unwrap();
+        assert_eq!((single.skeleton_vertices, single.regions.len()), (0, 1));
+        assert_eq!(single.x, monolithic.x);
+
+        let err = graph.solve_hierarchical(&[0; 3], 10, 1e-8).unwrap_err();
+        assert_eq!(err.code, COMPASS_ERR_INVALID_ARGUMENT);
+    }
+}
@@ -0,0 +1,230 @@
+//! Initial guess of the free vertices, chained along the shots from the fixed stations.
+//!
+//! A single spanning tree carries a blunder on one of its edges to every vertex downstream,
+//! which then starts far from its adjusted position. [`Graph::propagate_initial_guess`]
+//! chains the observations along several spanning trees instead and keeps the per-vertex
+//! median of each coordinate: a vertex is only misplaced when most trees route it through
+//! the bad shot. The first tree is the breadth-first one from the fixed stations; the others
+//! are random minimum spanning trees grown from them, the edge priorities drawn from
+//! [`GuessOptions::seed`], so the guess is reproducible.
+//!
+//! Components without a fixed station hang from their lowest vertex in every tree, which
+//! keeps its coordinates. A graph without loops has a single spanning forest, which is then
+//! the only one chained.
+
+use crate::Graph;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+/// Default [`GuessOptions::trees`].
+pub const DEFAULT_GUESS_TREES: usize = 7;
+
+/// Default [`GuessOptions::seed`].
+pub const DEFAULT_GUESS_SEED: u64 = 0x2545_f491_4f6c_dd1d;
+
+/// Options of [`Graph::propagate_initial_guess`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GuessOptions {
+    /// Number of spanning trees, 1 for the breadth-first one alone.
+    pub trees: usize,
+    /// Seed of the edge priorities of the random trees.
+    pub seed: u64,
+}
+
+impl Default for GuessOptions {
+    fn default() -> Self {
+        GuessOptions {
+            trees: DEFAULT_GUESS_TREES,
+            seed: DEFAULT_GUESS_SEED,
+        }
+    }
+}
+
+/// Coordinates of the vertices chained along one spanning forest.
+type Chained = Vec<[f64; 3]>;
+
+impl Graph {
+    /// Sets the X, Y and Z coordinates of the free vertices to the median of their
+    /// coordinates chained along [`GuessOptions::trees`] spanning trees, see the
+    /// [module documentation](self).
+    pub fn propagate_initial_guess(&mut self, options: &GuessOptions) {
+        let n = self.num_vertices();
+        let mut incident: Vec<Vec<usize>> = vec![Vec::new(); n];
+        for e in (0..self.num_edges()).filter(|&e| self.from[e] != self.to[e]) {
+            incident[self.from[e]].push(e);
+            incident[self.to[e]].push(e);
+        }
+        let (first, tree_edges) = self.chain(&incident, None);
+        let loops = tree_edges < incident.iter().map(Vec::len).sum::<usize>() / 2;
+        let mut chained = vec![first];
+        if loops {
+            let mut state = match options.seed {
+                0 => DEFAULT_GUESS_SEED,
+                seed => seed,
+            };
+            for _ in 1..options.trees {
+                let priority: Vec<u64> = (0..self.num_edges())
+                    .map(|_| {
+                        // xorshift64
+                        state ^= state << 13;
+                        state ^= state >> 7;
+                        state ^= state << 17;
+                        state
+                    })
+                    .collect();
+                chained.push(self.chain(&incident, Some(&priority)).0);
+            }
+        }
+
+        let mut values = Vec::with_capacity(chained.len());
+        for v in (0..n).filter(|&v| !self.fixed[v]) {
+            let mut median = [0.0; 3];
+            for (axis, median) in median.iter_mut().enumerate() {
+                values.clear();
+                values.extend(chained.iter().map(|tree| tree[v][axis]));
+                values.sort_by(f64::total_cmp);
+                let mid = values.len() / 2;
+                *median = match values.len() % 2 {
+                    1 => values[mid],
+                    _ => (values[mid - 1] + values[mid]) / 2.0,
+                };
+            }
+            [self.x[v], self.y[v], self.z[v]] = median;
+        }
+    }
+
+    /// Chains the observations along a spanning forest of the `incident` edges grown from
+    /// the fixed vertices: breadth-first without `priority`, the random minimum spanning
+    /// forest of those edge priorities otherwise. Returns the coordinates and the number of
+    /// tree edges.
+    fn chain(&self, incident: &[Vec<usize>], priority: Option<&[u64]>) -> (Chained, usize) {
+        let n = self.num_vertices();
+        let mut position: Chained = (0..n).map(|v| [self.x[v], self.y[v], self.z[v]]).collect();
+        let mut placed = self.fixed.clone();
+        let mut tree_edges = 0;
+        // Edges leaving the placed vertices. Breadth first, they are taken by order of
+        // discovery of the vertex they leave, then by their order at that vertex.
+        let mut heap = BinaryHeap::new();
+        let mut discovered = 0;
+        let mut push = |heap: &mut BinaryHeap<_>, v: usize| {
+            for (k, &e) in incident[v].iter().enumerate() {
+                let key = match priority {
+                    Some(priority) => (priority[e], 0),
+                    None => (discovered, k),
+                };
+                heap.push(Reverse((key, e, v)));
+            }
+            discovered += 1;
+        };
+        for v in (0..n).filter(|&v| self.fixed[v]) {
+            push(&mut heap, v);
+        }
+        let mut next_root = 0;
+        loop {
+            while let Some(Reverse((_, e, v))) = heap.pop() {
+                let (w, sign) = match self.from[e] == v {
+                    true => (self.to[e], 1.0),
+                    false => (self.from[e], -1.0),
+                };
+                if placed[w] {
+                    continue;
+                }
+                placed[w] = true;
+                tree_edges += 1;
+                let d = [self.dx[e], self.dy[e], self.dz[e]];
+                position[w] = [0, 1, 2].map(|axis| position[v][axis] + sign * d[axis]);
+                push(&mut heap, w);
+            }
+            while next_root < n && placed[next_root] {
+                next_root += 1;
+            }
+            if next_root == n {
+                break;
+            }
+            placed[next_root] = true;
+            push(&mut heap, next_root);
+        }
+        (position, tree_edges)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A `side` x `side` grid of stations 10 m apart, anchored at its corner, the free
+    /// stations starting at the origin.
+    fn grid(side: usize) -> Graph {
+        let mut graph = Graph::default();
+        for i in 0..side * side {
+            let (x, y) = ((i % side) as f64 * 10.0, (i / side) as f64 * 10.0);
+            match i {
+                0 => graph.add_vertex(x, y, 0.0, true),
+                _ => graph.add_vertex(0.0, 0.0, 0.0, false),
+            };
+        }
+        for i in 0..side * side {
+            if i % side + 1 < side {
+                graph.add_edge(i, i + 1, 10.0, 0.0, 0.0, 1.0);
+            }
+            if i + side < side * side {
+                graph.add_edge(i, i + side, 0.0, 10.0, 0.0, 1.0);
+            }
+        }
+        graph
+    }
+
+    /// Number of vertices of `graph` farther than 1 m from their place in the grid.
+    fn misplaced(graph: &Graph, side: usize) -> usize {
+        (0..side * side)
+            .filter(|&i| {
+                let (x, y) = ((i % side) as f64 * 10.0, (i / side) as f64 * 10.0);
+                (graph.x[i] - x).hypot(graph.y[i] - y) > 1.0
+            })
+            .count()
+    }
+
+    #[test]
+    fn the_median_of_trees_resists_a_blunder() {
+        let side = 8;
+        let mut graph = grid(side);
+        // A blunder of 25 m on the shot from station 2 to station 10 below it, which the
+        // breadth-first tree takes to reach the rest of column 2.
+        let blunder = (0..graph.num_edges())
+            .find(|&e| (graph.from[e], graph.to[e]) == (2, 10))
+            .unwrap();
+        graph.dx[blunder] += 25.0;
+
+        let mut single = graph.clone();
+        single.propagate_initial_guess(&GuessOptions {
+            trees: 1,
+            ..GuessOptions::default()
+        });
+        let mut median = graph.clone();
+        median.propagate_initial_guess(&GuessOptions::default());
+        assert!(misplaced(&single, side) >= side - 1);
+        assert!(misplaced(&median, side) <= 1);
+
+        // The same seed gives the same guess.
+        let mut again = graph.clone();
+        again.propagate_initial_guess(&GuessOptions::default());
+        assert_eq!((again.x, again.y), (median.x, median.y));
+    }
+
+    #[test]
+    fn a_tree_is_chained_once() {
+        // A traverse with a blunder and a floating pair.
+        let mut graph = Graph::default();
+        graph.add_vertex(5.0, 5.0, 1.0, true);
+        for _ in 0..4 {
+            graph.add_vertex(0.0, 0.0, 0.0, false);
+        }
+        graph.add_edge(0, 1, 1.0, 0.0, 0.5, 1.0);
+        graph.add_edge(2, 1, -30.0, 2.0, 0.0, 1.0);
+        graph.add_edge(3, 4, 0.0, 1.0, 0.0, 1.0);
+        graph.propagate_initial_guess(&GuessOptions::default());
+        assert_eq!(graph.x, [5.0, 6.0, 36.0, 0.0, 0.0]);
+        assert_eq!(graph.y, [5.0, 5.0, 3.0, 0.0, 1.0]);
+        assert_eq!(graph.z, [1.0, 1.5, 1.5, 0.0, 0.0]);
+    }
+}
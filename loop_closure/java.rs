@@ -0,0 +1,316 @@
+//! JNI bindings for Java callers that cannot use Project Panama (JDK 17 and older). The Panama
+//! symbols stay the primary interface; this is a thin adapter over [`Graph::solve`]:
+//!
+//! ```java
+//! package org.openspeleo.compass;
+//!
+//! public final class NativeSolver {
+//!     public static native void solve(double[] x, double[] y, int[] fixed, int[] from,
+//!             int[] to, double[] dx, double[] dy, double[] weight, int iterations,
+//!             double tolerance);
+//! }
+//! ```
+//!
+//! Arguments mirror `solve_graph_least_squares`, with `x` and `y` overwritten by the adjusted
+//! coordinates. Arrays are accessed through `GetPrimitiveArrayCritical`, so the JVM usually
+//! does not copy them; the critical sections only cover the copies into and out of the
+//! [`Graph`], never the solve itself, so the garbage collector is not blocked while solving.
+//!
+//! Failures are thrown as `IllegalArgumentException` (invalid input) or
+//! `IllegalStateException`, with the solver message and error code.
+
+use crate::{COMPASS_ERR_INVALID_ARGUMENT, COMPASS_ERR_PANIC, Graph, SolveError};
+use jni::JNIEnv;
+use jni::objects::{JClass, JDoubleArray, JIntArray, JPrimitiveArray, ReleaseMode, TypeArray};
+use jni::sys::{jdouble, jint};
+use std::ffi::c_int;
+
+/// Failure of a JNI call: either a solver error to throw, or a JNI error.
+enum Failure {
+    Solve(SolveError),
+    Jni(jni::errors::Error),
+}
+
+impl From<SolveError> for Failure {
+    fn from(err: SolveError) -> Self {
+        Failure::Solve(err)
+    }
+}
+
+impl From<jni::errors::Error> for Failure {
+    fn from(err: jni::errors::Error) -> Self {
+        Failure::Jni(err)
+    }
+}
+
+/// `org.openspeleo.compass.NativeSolver.solve`.
+#[unsafe(no_mangle)]
+#[allow(clippy::too_many_arguments)]
+pub extern "system" fn Java_org_openspeleo_compass_NativeSolver_solve<'local>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    x: JDoubleArray<'local>,
+    y: JDoubleArray<'local>,
+    fixed: JIntArray<'local>,
+    from: JIntArray<'local>,
+    to: JIntArray<'local>,
+    dx: JDoubleArray<'local>,
+    dy: JDoubleArray<'local>,
+    weight: JDoubleArray<'local>,
+    iterations: jint,
+    tolerance: jdouble,
+) {
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        solve(
+            &mut env, &x, &y, &fixed, &from, &to, &dx, &dy, &weight, iterations, tolerance,
+        )
+    }));
+    if let Some(err) = thrown_error(result) {
+        // If throwing fails there is nothing left to report to.
+        let _ = env.throw_new(exception_class(err.code), err.to_string());
+    }
+}
+
+/// Error to throw for the outcome of a call, `None` when it succeeded or when a Java exception
+/// is already pending.
+fn thrown_error(result: std::thread::Result<Result<(), Failure>>) -> Option<SolveError> {
+    match result {
+        Ok(Ok(())) | Ok(Err(Failure::Jni(jni::errors::Error::JavaException))) => None,
+        Ok(Err(Failure::Jni(err))) => Some(SolveError {
+            code: COMPASS_ERR_INVALID_ARGUMENT,
+            message: err.to_string(),
+        }),
+        Ok(Err(Failure::Solve(err))) => Some(err),
+        Err(_) => {
+            eprintln!("Panic caught in NativeSolver.solve");
+            Some(SolveError {
+                code: COMPASS_ERR_PANIC,
+                message: "panic in the native solver".to_string(),
+            })
+        }
+    }
+}
+
+/// Java class of the exception thrown for a solver error code.
+fn exception_class(code: c_int) -> &'static str {
+    match code {
+        COMPASS_ERR_INVALID_ARGUMENT => "java/lang/IllegalArgumentException",
+        _ => "java/lang/IllegalStateException",
+    }
+}
+
+/// The arguments of `NativeSolver.solve`, copied out of the Java arrays.
+struct Arrays {
+    x: Vec<f64>,
+    y: Vec<f64>,
+    fixed: Vec<jint>,
+    from: Vec<jint>,
+    to: Vec<jint>,
+    dx: Vec<f64>,
+    dy: Vec<f64>,
+    weight: Vec<f64>,
+}
+
+#[allow(clippy::too_many_arguments)]
+fn solve(
+    env: &mut JNIEnv,
+    x: &JDoubleArray,
+    y: &JDoubleArray,
+    fixed: &JIntArray,
+    from: &JIntArray,
+    to: &JIntArray,
+    dx: &JDoubleArray,
+    dy: &JDoubleArray,
+    weight: &JDoubleArray,
+    iterations: jint,
+    tolerance: jdouble,
+) -> Result<(), Failure> {
+    let arrays = Arrays {
+        x: read(env, x)?,
+        y: read(env, y)?,
+        fixed: read(env, fixed)?,
+        from: read(env, from)?,
+        to: read(env, to)?,
+        dx: read(env, dx)?,
+        dy: read(env, dy)?,
+        weight: read(env, weight)?,
+    };
+    let (x_out, y_out) = solve_arrays(&arrays, iterations, tolerance)?;
+    write(env, x, &x_out)?;
+    write(env, y, &y_out)?;
+    Ok(())
+}
+
+/// Checks the arrays of a call and solves them, returning the adjusted coordinates to copy
+/// back into `x` and `y`, of the same lengths.
+fn solve_arrays(
+    arrays: &Arrays,
+    iterations: jint,
+    tolerance: jdouble,
+) -> Result<(Vec<f64>, Vec<f64>), SolveError> {
+    let invalid = |message: String| SolveError {
+        code: COMPASS_ERR_INVALID_ARGUMENT,
+        message,
+    };
+    let n = arrays.x.len();
+    let m = arrays.from.len();
+    if arrays.y.len() != n || arrays.fixed.len() != n {
+        return Err(invalid(
+            "x, y and fixed must have the same length".to_string(),
+        ));
+    }
+    if [
+        arrays.to.len(),
+        arrays.dx.len(),
+        arrays.dy.len(),
+        arrays.weight.len(),
+    ]
+    .iter()
+    .any(|&len| len != m)
+    {
+        return Err(invalid(
+            "from, to, dx, dy and weight must have the same length".to_string(),
+        ));
+    }
+    let (from, to) = (&arrays.from, &arrays.to);
+    if let Some(e) = (0..m).find(|&e| [from[e], to[e]].iter().any(|&v| v < 0 || v as usize >= n)) {
+        return Err(invalid(format!("edge {e} references a missing vertex")));
+    }
+
+    let mut graph = Graph::default();
+    for i in 0..n {
+        graph.add_vertex(arrays.x[i], arrays.y[i], 0.0, arrays.fixed[i] != 0);
+    }
+    for e in 0..m {
+        graph.add_edge(
+            from[e] as usize,
+            to[e] as usize,
+            arrays.dx[e],
+            arrays.dy[e],
+            0.0,
+            arrays.weight[e],
+        );
+    }
+
+    let solution = graph.solve(iterations.max(0) as usize, tolerance)?;
+    Ok((solution.x, solution.y))
+}
+
+/// Copies a Java primitive array into a `Vec`.
+fn read<T: TypeArray + Copy>(
+    env: &mut JNIEnv,
+    array: &JPrimitiveArray<T>,
+) -> jni::errors::Result<Vec<T>> {
+    // Safety: No other JNI call is made while the critical section is open.
+    let elements = unsafe { env.get_array_elements_critical(array, ReleaseMode::NoCopyBack)? };
+    Ok(elements.to_vec())
+}
+
+/// Overwrites a Java `double[]` of the same length as `values`.
+fn write(env: &mut JNIEnv, array: &JDoubleArray, values: &[f64]) -> jni::errors::Result<()> {
+    // Safety: No other JNI call is made while the critical section is open.
+    let mut elements = unsafe { env.get_array_elements_critical(array, ReleaseMode::CopyBack)? };
+    elements.copy_from_slice(values);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::COMPASS_ERR_CANCELLED;
+
+    /// A loop of four shots hanging from vertex 0, misclosed by 0.04 in X.
+    fn square() -> Arrays {
+        Arrays {
+            x: vec![0.0, 10.3, 9.8, -0.1],
+            y: vec![0.0, 0.2, 10.1, 9.7],
+            fixed: vec![1, 0, 0, 0],
+            from: vec![0, 1, 2, 3],
+            to: vec![1, 2, 3, 0],
+            dx: vec![10.0, 0.0, -10.0, 0.04],
+            dy: vec![0.0, 10.0, 0.0, -10.0],
+            weight: vec![1.0; 4],
+        }
+    }
+
+    #[test]
+    fn returns_the_coordinates_of_the_safe_api() {
+        let arrays = square();
+        let (x, y) = solve_arrays(&arrays, 1000, 1e-12).unwrap();
+        let mut graph = Graph::default();
+        for i in 0..4 {
+            graph.add_vertex(arrays.x[i], arrays.y[i], 0.0, arrays.fixed[i] != 0);
+        }
+        for e in 0..4 {
+            let (from, to) = (arrays.from[e] as usize, arrays.to[e] as usize);
+            graph.add_edge(from, to, arrays.dx[e], arrays.dy[e], 0.0, 1.0);
+        }
+        let solution = graph.solve(1000, 1e-12).unwrap();
+        // Copied back into the Java arrays as they are, vertex for vertex.
+        assert_eq!((&x, &y), (&solution.x, &solution.y));
+        assert_eq!((x[0], y[0]), (0.0, 0.0));
+        assert!((x[3] + 0.03).abs() < 1e-9, "{}", x[3]);
+
+        // A negative iteration count solves nothing.
+        let (x, y) = solve_arrays(&arrays, -5, 1e-12).unwrap();
+        assert_eq!((x, y), (arrays.x, arrays.y));
+    }
+
+    #[test]
+    fn rejects_inconsistent_arrays() {
+        let check = |arrays: Arrays, message: &str| {
+            let err = solve_arrays(&arrays, 1000, 1e-12).unwrap_err();
+            assert_eq!(err.code, COMPASS_ERR_INVALID_ARGUMENT);
+            assert_eq!(err.message, message);
+        };
+        let mut arrays = square();
+        arrays.fixed.pop();
+        check(arrays, "x, y and fixed must have the same length");
+        let mut arrays = square();
+        arrays.weight.push(1.0);
+        check(
+            arrays,
+            "from, to, dx, dy and weight must have the same length",
+        );
+        let mut arrays = square();
+        arrays.to[2] = 4;
+        check(arrays, "edge 2 references a missing vertex");
+        let mut arrays = square();
+        arrays.from[1] = -1;
+        check(arrays, "edge 1 references a missing vertex");
+    }
+
+    #[test]
+    fn maps_errors_to_java_exceptions() {
+        assert_eq!(
+            exception_class(COMPASS_ERR_INVALID_ARGUMENT),
+            "java/lang/IllegalArgumentException"
+        );
+        for code in [COMPASS_ERR_PANIC, COMPASS_ERR_CANCELLED] {
+            assert_eq!(exception_class(code), "java/lang/IllegalStateException");
+        }
+
+        // Nothing is thrown on success, nor over an exception the JVM already holds.
+        assert!(thrown_error(Ok(Ok(()))).is_none());
+        let pending = Failure::Jni(jni::errors::Error::JavaException);
+        assert!(thrown_error(Ok(Err(pending))).is_none());
+
+        // A JNI failure, such as a null array, is an invalid argument.
+        let null = Failure::Jni(jni::errors::Error::NullPtr("get_array_elements_critical"));
+        let err = thrown_error(Ok(Err(null))).unwrap();
+        assert_eq!(err.code, COMPASS_ERR_INVALID_ARGUMENT);
+        assert!(err.message.contains("get_array_elements_critical"), "{err}");
+
+        // Solver errors pass through, and the message carries the code.
+        let cancelled = SolveError {
+            code: COMPASS_ERR_CANCELLED,
+            message: "solve cancelled".to_string(),
+        };
+        let err = thrown_error(Ok(Err(cancelled.into()))).unwrap();
+        assert_eq!(err.to_string(), "solve cancelled (code -5)");
+
+        let err = thrown_error(Err(Box::new("boom"))).unwrap();
+        assert_eq!(err.code, COMPASS_ERR_PANIC);
+        assert_eq!(exception_class(err.code), "java/lang/IllegalStateException");
+    }
+}
@@ -0,0 +1,365 @@
+//! JSON solve entrypoint, used as the interchange format for scripting and bug reports.
+//!
+//! Input:
+//!
+//! ```json
+//! {
+//!   "vertices": [{"x": 0.0, "y": 0.0, "fixed": true, "name": "A1"}, {"x": 9.8, "y": 0.3}],
+//!   "edges": [{"from": 0, "to": 1, "dx": 10.0, "dy": 0.0, "weight": 1.0}],
+//!   "options": {"iterations": 60000, "tolerance": 1e-8}
+//! }
+//! ```
+//!
+//! Output:
+//!
+//! ```json
+//! {
+//!   "vertices": [{"x": 0.0, "y": 0.0}, {"x": 10.0, "y": 0.0}],
+//!   "stats": {"free_vertices": 1, "passive_vertices": 0, "frozen_vertices": 0,
+//!             "frozen_boundary_edges": 0, "frozen_boundary_stress": 0.0},
+//!   "residuals": [{"dx": 0.0, "dy": 0.0, "standardized": 0.0}]
+//! }
+//! ```
+//!
+//! Optional fields (`z`, `fixed`, `name`, `dz`, `options` and its members) may be omitted.
+//! Unknown fields are ignored so that documents written by newer libraries still load.
+
+use crate::{
+    COMPASS_ERR_BUFFER_TOO_SMALL, COMPASS_ERR_INVALID_ARGUMENT, COMPASS_ERR_PANIC, COMPASS_OK,
+    Graph, SolveError, SolveStats, write_message,
+};
+use serde::{Deserialize, Serialize};
+use std::ffi::{CStr, c_char, c_int};
+
+/// Default maximum number of CG iterations when the input omits it.
+const DEFAULT_ITERATIONS: usize = 60_000;
+/// Default CG tolerance when the input omits it.
+const DEFAULT_TOLERANCE: f64 = 1e-8;
+
+#[derive(Deserialize)]
+struct JsonInput {
+    vertices: Vec<JsonVertex>,
+    #[serde(default)]
+    edges: Vec<JsonEdge>,
+    #[serde(default)]
+    options: JsonOptions,
+}
+
+#[derive(Deserialize)]
+struct JsonVertex {
+    x: f64,
+    y: f64,
+    #[serde(default)]
+    z: f64,
+    #[serde(default)]
+    fixed: bool,
+    #[serde(default)]
+    name: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct JsonEdge {
+    from: usize,
+    to: usize,
+    dx: f64,
+    dy: f64,
+    #[serde(default)]
+    dz: f64,
+    weight: f64,
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+struct JsonOptions {
+    iterations: usize,
+    tolerance: f64,
+}
+
+impl Default for JsonOptions {
+    fn default() -> Self {
+        JsonOptions {
+            iterations: DEFAULT_ITERATIONS,
+            tolerance: DEFAULT_TOLERANCE,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct JsonOutput {
+    vertices: Vec<JsonPoint>,
+    stats: SolveStats,
+    residuals: Vec<JsonResidual>,
+}
+
+#[derive(Serialize)]
+struct JsonPoint {
+    x: f64,
+    y: f64,
+}
+
+#[derive(Serialize)]
+struct JsonResidual {
+    dx: f64,
+    dy: f64,
+    standardized: f64,
+}
+
+/// Solves the graph described by the JSON document `input` and returns the result as a JSON
+/// document (see the module documentation for both schemas).
+///
+/// Malformed input and out-of-range edge endpoints are reported as
+/// [`COMPASS_ERR_INVALID_ARGUMENT`].
+pub fn solve_json(input: &str) -> Result<String, SolveError> {
+    let invalid = |message: String| SolveError {
+        code: COMPASS_ERR_INVALID_ARGUMENT,
+        message,
+    };
+    let input: JsonInput =
+        serde_json::from_str(input).map_err(|err| invalid(format!("invalid input: {err}")))?;
+
+    let mut graph = Graph::default();
+    let mut names = Vec::with_capacity(input.vertices.len());
+    for v in &input.vertices {
+        graph.add_vertex(v.x, v.y, v.z, v.fixed);
+        names.push(v.name.clone().unwrap_or_default());
+    }
+    if input.vertices.iter().any(|v| v.name.is_some()) {
+        graph.names = Some(names);
+    }
+    for (e, edge) in input.edges.iter().enumerate() {
+        if edge.from >= graph.num_vertices() || edge.to >= graph.num_vertices() {
+            return Err(invalid(format!("edge {e} references a missing vertex")));
+        }
+        graph.add_edge(edge.from, edge.to, edge.dx, edge.dy, edge.dz, edge.weight);
+    }
+
+    let solution = graph.solve(input.options.iterations, input.options.tolerance)?;
+    let output = JsonOutput {
+        vertices: solution
+            .x
+            .iter()
+            .zip(&solution.y)
+            .map(|(&x, &y)| JsonPoint { x, y })
+            .collect(),
+        stats: solution.stats,
+        residuals: solution
+            .residuals(&graph)
+            .into_iter()
+            .zip(solution.standardized_residuals(&graph))
+            .map(|((dx, dy), standardized)| JsonResidual {
+                dx,
+                dy,
+                standardized,
+            })
+            .collect(),
+    };
+    serde_json::to_string(&output).map_err(|err| invalid(format!("invalid output: {err}")))
+}
+
+/// Solves the graph described by the NUL-terminated JSON document `input` and writes the
+/// NUL-terminated JSON result into `out`.
+///
+/// # Arguments
+///
+/// * `input` - NUL-terminated UTF-8 JSON document (see [`solve_json`]).
+/// * `out` - Buffer receiving the result on success, or the error message on failure.
+/// * `out_cap` - Capacity of `out` in bytes.
+///
+/// # Returns
+///
+/// * [`COMPASS_OK`] on success.
+/// * [`COMPASS_ERR_BUFFER_TOO_SMALL`] if the result does not fit; `out` is left untouched.
+/// * The [`SolveError`] code otherwise, with the message in `out`.
+#[unsafe(no_mangle)]
+pub extern "C" fn solve_from_json(input: *const c_char, out: *mut c_char, out_cap: usize) -> c_int {
+    let result = std::panic::catch_unwind(|| {
+        if input.is_null() || out.is_null() {
+            return COMPASS_ERR_INVALID_ARGUMENT;
+        }
+        // Safety: The caller guarantees a valid NUL-terminated string.
+        let input = match unsafe { CStr::from_ptr(input) }.to_str() {
+            Ok(input) => input,
+            Err(err) => {
+                write_message(out, out_cap, &format!("input is not UTF-8: {err}"));
+                return COMPASS_ERR_INVALID_ARGUMENT;
+            }
+        };
+        match solve_json(input) {
+            Ok(json) if json.len() < out_cap => {
+                write_message(out, out_cap, &json);
+                COMPASS_OK
+            }
+            Ok(_) => COMPASS_ERR_BUFFER_TOO_SMALL,
+            Err(err) => {
+                write_message(out, out_cap, &err.message);
+                err.code
+            }
+        }
+    });
+
+    match result {
+        Ok(code) => code,
+        Err(_) => {
+            eprintln!("Panic caught in solve_from_json");
+            COMPASS_ERR_PANIC
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{Value, json};
+    use std::ffi::CString;
+
+    fn triangle() -> Value {
+        json!({
+            "vertices": [
+                {"x": 0.0, "y": 0.0, "z": 5.0, "fixed": true, "name": "A1"},
+                {"x": 9.8, "y": 0.3},
+                {"x": 10.0, "y": 10.0, "fixed": false}
+            ],
+            "edges": [
+                {"from": 0, "to": 1, "dx": 10.0, "dy": 0.0, "weight": 1.0},
+                {"from": 1, "to": 2, "dx": 0.0, "dy": 10.0, "dz": 1.0, "weight": 1.0},
+                {"from": 2, "to": 0, "dx": -10.03, "dy": -9.98, "weight": 2.0}
+            ],
+            "options": {"iterations": 1000, "tolerance": 1e-12}
+        })
+    }
+
+    fn solve_value(input: &Value) -> Value {
+        serde_json::from_str(&solve_json(&input.to_string()).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn output_matches_the_library_solve() {
+        let output = solve_value(&triangle());
+
+        let mut graph = Graph::default();
+        graph.add_vertex(0.0, 0.0, 5.0, true);
+        graph.add_vertex(9.8, 0.3, 0.0, false);
+        graph.add_vertex(10.0, 10.0, 0.0, false);
+        graph.add_edge(0, 1, 10.0, 0.0, 0.0, 1.0);
+        graph.add_edge(1, 2, 0.0, 10.0, 1.0, 1.0);
+        graph.add_edge(2, 0, -10.03, -9.98, 0.0, 2.0);
+        let solution = graph.solve(1000, 1e-12).unwrap();
+
+        let vertices = output["vertices"].as_array().unwrap();
+        assert_eq!(vertices.len(), 3);
+        for (v, vertex) in vertices.iter().enumerate() {
+            assert_eq!(vertex["x"].as_f64(), Some(solution.x[v]));
+            assert_eq!(vertex["y"].as_f64(), Some(solution.y[v]));
+        }
+        assert_eq!(
+            output["stats"],
+            serde_json::to_value(solution.stats).unwrap()
+        );
+        assert_eq!(output["stats"]["free_vertices"], 2);
+
+        let residuals = output["residuals"].as_array().unwrap();
+        let standardized = solution.standardized_residuals(&graph);
+        for (e, (dx, dy)) in solution.residuals(&graph).into_iter().enumerate() {
+            assert_eq!(residuals[e]["dx"].as_f64(), Some(dx));
+            assert_eq!(residuals[e]["dy"].as_f64(), Some(dy));
+            assert_eq!(residuals[e]["standardized"].as_f64(), Some(standardized[e]));
+        }
+    }
+
+    #[test]
+    fn schema_round_trips_through_the_output() {
+        // Feeding the adjusted coordinates back as the initial guess reproduces them.
+        let input = triangle();
+        let output = solve_value(&input);
+        let mut again = input.clone();
+        for (vertex, adjusted) in again["vertices"]
+            .as_array_mut()
+            .unwrap()
+            .iter_mut()
+            .zip(output["vertices"].as_array().unwrap())
+        {
+            vertex["x"] = adjusted["x"].clone();
+            vertex["y"] = adjusted["y"].clone();
+        }
+        let output_again = solve_value(&again);
+        for (a, b) in output["vertices"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .zip(output_again["vertices"].as_array().unwrap())
+        {
+            assert!((a["x"].as_f64().unwrap() - b["x"].as_f64().unwrap()).abs() < 1e-9);
+            assert!((a["y"].as_f64().unwrap() - b["y"].as_f64().unwrap()).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn ignores_unknown_fields_and_defaults_optional_ones() {
+        let mut input = triangle();
+        input["format_version"] = json!(7);
+        input["vertices"][1]["sigma"] = json!(0.01);
+        input["edges"][0]["surveyed_on"] = json!("2024-05-01");
+        input["options"]["preset"] = json!("publication");
+        assert_eq!(solve_value(&input), solve_value(&triangle()));
+
+        let minimal = json!({"vertices": [{"x": 1.5, "y": -2.0}]});
+        let output = solve_value(&minimal);
+        assert_eq!(output["vertices"], json!([{"x": 1.5, "y": -2.0}]));
+        assert_eq!(output["residuals"], json!([]));
+    }
+
+    #[test]
+    fn rejects_malformed_documents() {
+        for input in [
+            "not json".to_string(),
+            json!({"edges": []}).to_string(),
+            json!({"vertices": [{"x": "0", "y": 0}]}).to_string(),
+        ] {
+            let err = solve_json(&input).unwrap_err();
+            assert_eq!(err.code, COMPASS_ERR_INVALID_ARGUMENT);
+            assert!(
+                err.message.starts_with("invalid input: "),
+                "{}",
+                err.message
+            );
+        }
+
+        let mut input = triangle();
+        input["edges"][2]["to"] = json!(3);
+        let err = solve_json(&input.to_string()).unwrap_err();
+        assert_eq!(err.code, COMPASS_ERR_INVALID_ARGUMENT);
+        assert_eq!(err.message, "edge 2 references a missing vertex");
+    }
+
+    #[test]
+    fn ffi_writes_the_result_or_reports_a_short_buffer() {
+        let input = CString::new(triangle().to_string()).unwrap();
+        let expected = solve_json(&triangle().to_string()).unwrap();
+
+        let mut out = vec![0 as c_char; expected.len() + 1];
+        assert_eq!(
+            solve_from_json(input.as_ptr(), out.as_mut_ptr(), out.len()),
+            COMPASS_OK
+        );
+        // Safety: The library NUL-terminates the result.
+        let written = unsafe { CStr::from_ptr(out.as_ptr()) }.to_str().unwrap();
+        assert_eq!(written, expected);
+
+        let mut short = vec![0x7f as c_char; expected.len()];
+        assert_eq!(
+            solve_from_json(input.as_ptr(), short.as_mut_ptr(), short.len()),
+            COMPASS_ERR_BUFFER_TOO_SMALL
+        );
+        assert!(short.iter().all(|&c| c == 0x7f));
+
+        let bad = CString::new("{").unwrap();
+        assert_eq!(
+            solve_from_json(bad.as_ptr(), out.as_mut_ptr(), out.len()),
+            COMPASS_ERR_INVALID_ARGUMENT
+        );
+        assert_eq!(
+            solve_from_json(std::ptr::null(), out.as_mut_ptr(), out.len()),
+            COMPASS_ERR_INVALID_ARGUMENT
+        );
+    }
+}
@@ -0,0 +1,181 @@
+//! Largest graphs this build accepts, for callers that size their inputs per deployment
+//! target (wasm, 32-bit mobile, desktop) instead of hard-coding it.
+//!
+//! Counts cross the FFI as `c_int`, so no build goes past `c_int::MAX` vertices or edges. On
+//! 32-bit targets the address space is the tighter bound: a solve needs about
+//! [`VERTEX_BYTES`] per vertex and [`EDGE_BYTES`] per edge, the graph, the normal equations and
+//! the iteration vectors together, and no allocation may exceed `isize::MAX` bytes. Larger
+//! inputs fail with [`COMPASS_ERR_TOO_LARGE`] before anything is allocated, rather than with
+//! [`crate::COMPASS_ERR_OUT_OF_MEMORY`] or an abort midway.
+
+use crate::{COMPASS_ERR_INVALID_ARGUMENT, COMPASS_ERR_TOO_LARGE, COMPASS_OK, SolveError};
+use std::ffi::c_int;
+
+/// Approximate memory of a solve per vertex, in bytes.
+pub const VERTEX_BYTES: u64 = 256;
+/// Approximate memory of a solve per edge, in bytes.
+pub const EDGE_BYTES: u64 = 128;
+/// [`Limits::max_vertices`] of this build.
+pub const MAX_VERTICES: usize = Limits::CURRENT.max_vertices as usize;
+/// [`Limits::max_edges`] of this build.
+pub const MAX_EDGES: usize = Limits::CURRENT.max_edges as usize;
+
+/// Limits of a build, see the [module documentation](self).
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// `sizeof(Limits)` as the caller was compiled against it. Read, never written, by the
+    /// library.
+    pub struct_size: usize,
+    pub max_vertices: c_int,
+    pub max_edges: c_int,
+    /// Width of a pointer in bits, 32 on wasm32 and 32-bit mobile targets.
+    pub pointer_width: c_int,
+    /// Width of the vertex and edge counts of the FFI in bits.
+    pub count_width: c_int,
+}
+
+impl Limits {
+    /// Limits of this build.
+    pub const CURRENT: Limits = Limits::for_pointer_width(usize::BITS);
+
+    /// Limits of a build for a target with pointers of `bits` bits, e.g. 32 to size inputs
+    /// for wasm from a desktop build.
+    pub const fn for_pointer_width(bits: u32) -> Limits {
+        let address_space = match bits {
+            64.. => i64::MAX as u64,
+            _ => (1 << (bits - 1)) - 1,
+        };
+        Limits {
+            struct_size: size_of::<Limits>(),
+            max_vertices: count_bound(address_space, VERTEX_BYTES),
+            max_edges: count_bound(address_space, EDGE_BYTES),
+            pointer_width: bits as c_int,
+            count_width: c_int::BITS as c_int,
+        }
+    }
+
+    /// Fails with [`COMPASS_ERR_TOO_LARGE`] when a graph of `vertices` vertices and `edges`
+    /// edges exceeds these limits.
+    pub fn check(&self, vertices: usize, edges: usize) -> Result<(), SolveError> {
+        let exceeded = |count: usize, max: c_int, what: &str| SolveError {
+            code: COMPASS_ERR_TOO_LARGE,
+            message: format!("{count} {what} exceed the limit of {max} of this build"),
+        };
+        if vertices > self.max_vertices as usize {
+            return Err(exceeded(vertices, self.max_vertices, "vertices"));
+        }
+        if edges > self.max_edges as usize {
+            return Err(exceeded(edges, self.max_edges, "edges"));
+        }
+        Ok(())
+    }
+}
+
+/// Number of items of `bytes` bytes that fit in `address_space` bytes, at most `c_int::MAX`.
+const fn count_bound(address_space: u64, bytes: u64) -> c_int {
+    let count = address_space / bytes;
+    match count < c_int::MAX as u64 {
+        true => count as c_int,
+        false => c_int::MAX,
+    }
+}
+
+/// Writes the [`Limits`] of this build to `out`, the fields past its `struct_size` being
+/// left out. Returns [`COMPASS_ERR_INVALID_ARGUMENT`] if `out` is null or its `struct_size`
+/// does not reach `max_edges`.
+#[unsafe(no_mangle)]
+pub extern "C" fn compass_limits(out: *mut Limits) -> c_int {
+    if out.is_null() {
+        return COMPASS_ERR_INVALID_ARGUMENT;
+    }
+    // Safety: `struct_size` leads every release of the struct.
+    let size = unsafe { out.cast::<usize>().read() };
+    if size < std::mem::offset_of!(Limits, pointer_width) {
+        return COMPASS_ERR_INVALID_ARGUMENT;
+    }
+    let header = size_of::<usize>();
+    let len = size.min(size_of::<Limits>());
+    // Safety: The caller guarantees `struct_size` writable bytes, and the struct is plain data.
+    unsafe {
+        std::ptr::copy_nonoverlapping(
+            (&Limits::CURRENT as *const Limits).cast::<u8>().add(header),
+            out.cast::<u8>().add(header),
+            len - header,
+        );
+    }
+    COMPASS_OK
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(target_pointer_width = "64")]
+    #[test]
+    fn counts_bound_a_64_bit_build() {
+        assert_eq!(Limits::CURRENT.max_vertices, c_int::MAX);
+        assert_eq!(Limits::CURRENT.max_edges, c_int::MAX);
+        assert_eq!(Limits::CURRENT.pointer_width, 64);
+    }
+
+    #[cfg(target_pointer_width = "32")]
+    #[test]
+    fn memory_bounds_a_32_bit_build() {
+        assert_eq!(Limits::CURRENT, Limits::for_pointer_width(32));
+    }
+
+    #[test]
+    fn a_32_bit_build_is_bounded_by_its_address_space() {
+        let limits = Limits::for_pointer_width(32);
+        assert_eq!(
+            limits.max_vertices,
+            (i32::MAX as u64 / VERTEX_BYTES) as c_int
+        );
+        assert_eq!(limits.max_edges, (i32::MAX as u64 / EDGE_BYTES) as c_int);
+        assert_eq!(limits.count_width, 32);
+        let (vertices, edges) = (limits.max_vertices as usize, limits.max_edges as usize);
+        assert!(limits.check(vertices, edges).is_ok());
+        let err = limits.check(vertices + 1, 0).unwrap_err();
+        assert_eq!(err.code, COMPASS_ERR_TOO_LARGE);
+        assert_eq!(
+            limits.check(0, edges + 1).unwrap_err().message,
+            format!(
+                "{} edges exceed the limit of {edges} of this build",
+                edges + 1
+            )
+        );
+        assert!(Limits::CURRENT.check(MAX_VERTICES, MAX_EDGES).is_ok());
+    }
+
+    #[test]
+    fn ffi_writes_the_fields_the_caller_knows() {
+        let mut limits = Limits {
+            struct_size: size_of::<Limits>(),
+            max_vertices: 0,
+            max_edges: 0,
+            pointer_width: 0,
+            count_width: 0,
+        };
+        assert_eq!(compass_limits(&mut limits), COMPASS_OK);
+        assert_eq!(limits, Limits::CURRENT);
+
+        // A caller compiled against a shorter struct only gets the counts.
+        limits = Limits {
+            struct_size: std::mem::offset_of!(Limits, pointer_width),
+            max_vertices: 0,
+            max_edges: 0,
+            pointer_width: -1,
+            count_width: -1,
+        };
+        assert_eq!(compass_limits(&mut limits), COMPASS_OK);
+        assert_eq!(limits.max_edges, Limits::CURRENT.max_edges);
+        assert_eq!((limits.pointer_width, limits.count_width), (-1, -1));
+        limits.struct_size = size_of::<usize>();
+        assert_eq!(compass_limits(&mut limits), COMPASS_ERR_INVALID_ARGUMENT);
+        assert_eq!(
+            compass_limits(std::ptr::null_mut()),
+            COMPASS_ERR_INVALID_ARGUMENT
+        );
+    }
+}
@@ -0,0 +1,381 @@
+//! Locking of accepted loops against re-distribution by later surveys.
+//!
+//! Once the closure of a loop has been reviewed, new data should not disturb it again. Locking
+//! the loop replaces the observations of its edges by their adjusted differences at the time
+//! of the lock, with a high weight: the loop keeps its adjusted shape through later solves,
+//! yet its vertices are not frozen, so it still moves rigidly with the rest of the network and
+//! the misclosure of new loops goes to the unlocked edges.
+//!
+//! Loops are identified by their closing edge, as listed by the loop misclosures of
+//! [`crate::report`] and by [`crate::screening`]. The edges of a loop are captured when it is
+//! locked, so later changes to the spanning forest leave it alone. An edge of several locked
+//! loops takes the observation of the last lock.
+
+use crate::report::SpanningForest;
+use crate::{
+    COMPASS_ERR_INVALID_ARGUMENT, COMPASS_ERR_PANIC, COMPASS_OK, Graph, GraphContext, Solution,
+    SolveError,
+};
+use std::ffi::{c_double, c_int};
+use std::slice;
+
+/// Default weight of the edges of a locked loop, well above that of shots measured to a few
+/// centimetres.
+pub const DEFAULT_LOCK_WEIGHT: f64 = 1e6;
+
+/// A loop locked at its adjusted shape.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LockedLoop {
+    /// Edge closing the loop when it was locked.
+    pub closing_edge: usize,
+    /// Edges of the loop with their adjusted `(dx, dy)` at the time of the lock, which replace
+    /// their observations.
+    pub edges: Vec<(usize, f64, f64)>,
+    /// Weight replacing that of the edges.
+    pub weight: f64,
+}
+
+/// State of a locked loop, filled by [`graph_locked_loops`].
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct LockedLoopStatus {
+    /// Edge closing the loop when it was locked.
+    pub closing_edge: c_int,
+    /// Number of edges in the loop.
+    pub edges: c_int,
+    /// Largest distance between the current difference of an edge of the loop and its locked
+    /// one: how far the loop has been distorted since it was locked.
+    pub deviation: c_double,
+}
+
+impl LockedLoop {
+    /// Distortion of the loop at coordinates `x` / `y`, see [`LockedLoopStatus::deviation`].
+    pub fn deviation(&self, graph: &Graph, x: &[f64], y: &[f64]) -> f64 {
+        self.edges
+            .iter()
+            .map(|&(e, dx, dy)| {
+                let (u, v) = (graph.from[e], graph.to[e]);
+                (x[v] - x[u] - dx).hypot(y[v] - y[u] - dy)
+            })
+            .fold(0.0, f64::max)
+    }
+}
+
+/// Replaces the observations and weights of the edges of the `locked` loops in `graph`.
+pub(crate) fn replace_observations(locked: &[LockedLoop], graph: &mut Graph) {
+    for lock in locked {
+        for &(e, dx, dy) in &lock.edges {
+            graph.dx[e] = dx;
+            graph.dy[e] = dy;
+            graph.weight[e] = lock.weight;
+        }
+    }
+}
+
+impl GraphContext {
+    /// Locks the loop closed by `closing_edge` at its adjusted shape, with edges of weight
+    /// `weight` (see [`DEFAULT_LOCK_WEIGHT`]). Locking a locked loop again captures its
+    /// current shape. The normal equations are re-assembled by the next solve.
+    ///
+    /// Fails with [`COMPASS_ERR_INVALID_ARGUMENT`] before the first solve, for a weight that
+    /// is not positive and finite, or when `closing_edge` does not close a loop over the
+    /// enabled edges.
+    pub fn lock_loop(&mut self, closing_edge: usize, weight: f64) -> Result<(), SolveError> {
+        let invalid = |message: String| {
+            Err(SolveError {
+                code: COMPASS_ERR_INVALID_ARGUMENT,
+                message,
+            })
+        };
+        let Some(solution) = &self.solution else {
+            return invalid("graph has not been solved".to_string());
+        };
+        if !(weight.is_finite() && weight > 0.0) {
+            return invalid(format!("invalid lock weight {weight}"));
+        }
+        if closing_edge >= self.graph.num_edges() || !self.is_edge_enabled(closing_edge) {
+            return invalid(format!("edge {closing_edge} is not an enabled edge"));
+        }
+        let enabled: Vec<usize> = (0..self.graph.num_edges())
+            .filter(|&e| self.is_edge_enabled(e))
+            .collect();
+        let forest = SpanningForest::new(&self.graph, &enabled);
+        if forest.tree_edge[closing_edge] {
+            return invalid(format!("edge {closing_edge} does not close a loop"));
+        }
+
+        let (x, y) = (&solution.x, &solution.y);
+        let edges = forest
+            .cycle(&self.graph, closing_edge)
+            .edges
+            .iter()
+            .map(|&(e, _)| {
+                let (u, v) = (self.graph.from[e], self.graph.to[e]);
+                (e, x[v] - x[u], y[v] - y[u])
+            })
+            .collect();
+        self.locked.retain(|lock| lock.closing_edge != closing_edge);
+        self.locked.push(LockedLoop {
+            closing_edge,
+            edges,
+            weight,
+        });
+        self.system = None;
+        Ok(())
+    }
+
+    /// Releases the loop locked by [`GraphContext::lock_loop`] at `closing_edge`. Fails with
+    /// [`COMPASS_ERR_INVALID_ARGUMENT`] when it is not locked.
+    pub fn unlock_loop(&mut self, closing_edge: usize) -> Result<(), SolveError> {
+        let count = self.locked.len();
+        self.locked.retain(|lock| lock.closing_edge != closing_edge);
+        if self.locked.len() == count {
+            return Err(SolveError {
+                code: COMPASS_ERR_INVALID_ARGUMENT,
+                message: format!("no loop locked at edge {closing_edge}"),
+            });
+        }
+        self.system = None;
+        Ok(())
+    }
+
+    /// The locked loops, in the order they were locked.
+    pub fn locked_loops(&self) -> &[LockedLoop] {
+        &self.locked
+    }
+
+    /// Status of the locked loops at the current [`GraphContext::coordinates`].
+    pub fn locked_loop_status(&self) -> Vec<LockedLoopStatus> {
+        let (x, y) = self.coordinates();
+        self.locked
+            .iter()
+            .map(|lock| LockedLoopStatus {
+                closing_edge: lock.closing_edge as c_int,
+                edges: lock.edges.len() as c_int,
+                deviation: lock.deviation(&self.graph, x, y),
+            })
+            .collect()
+    }
+
+    /// Records the locked loops of `solution` in its stats.
+    pub(crate) fn record_locks(&self, solution: &mut Solution) {
+        let (x, y) = (&solution.x, &solution.y);
+        solution.stats.locked_loops = self.locked.len() as c_int;
+        solution.stats.locked_deviation = self
+            .locked
+            .iter()
+            .map(|lock| lock.deviation(&self.graph, x, y))
+            .fold(0.0, f64::max);
+    }
+}
+
+/// Locks the loop closed by edge `closing_edge` of the graph behind `handle`, see
+/// [`GraphContext::lock_loop`]. Returns [`COMPASS_ERR_INVALID_ARGUMENT`] for a null handle,
+/// an unsolved graph, an invalid weight or an edge closing no loop.
+#[unsafe(no_mangle)]
+pub extern "C" fn graph_lock_loop(
+    handle: *mut GraphContext,
+    closing_edge: c_int,
+    weight: c_double,
+) -> c_int {
+    let Some(ctx) = (unsafe { handle.as_mut() }) else {
+        return COMPASS_ERR_INVALID_ARGUMENT;
+    };
+    if closing_edge < 0 {
+        return COMPASS_ERR_INVALID_ARGUMENT;
+    }
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        match ctx.lock_loop(closing_edge as usize, weight) {
+            Ok(()) => COMPASS_OK,
+            Err(err) => err.code,
+        }
+    }));
+
+    result.unwrap_or_else(|_| {
+        eprintln!("Panic caught in graph_lock_loop");
+        COMPASS_ERR_PANIC
+    })
+}
+
+/// Releases the loop locked at `closing_edge` in the graph behind `handle`. Returns
+/// [`COMPASS_ERR_INVALID_ARGUMENT`] for a null handle or a loop that is not locked.
+#[unsafe(no_mangle)]
+pub extern "C" fn graph_unlock_loop(handle: *mut GraphContext, closing_edge: c_int) -> c_int {
+    let Some(ctx) = (unsafe { handle.as_mut() }) else {
+        return COMPASS_ERR_INVALID_ARGUMENT;
+    };
+    if closing_edge < 0 {
+        return COMPASS_ERR_INVALID_ARGUMENT;
+    }
+    match ctx.unlock_loop(closing_edge as usize) {
+        Ok(()) => COMPASS_OK,
+        Err(err) => err.code,
+    }
+}
+
+/// Fills `out_status` with the status of the locked loops of the graph behind `handle`, see
+/// [`GraphContext::locked_loop_status`], when `capacity` is large enough.
+///
+/// # Returns
+///
+/// * The number of locked loops. They are written only if `capacity` is at least that number,
+///   so a first call with a null `out_status` and a capacity of 0 sizes the buffer.
+/// * [`COMPASS_ERR_INVALID_ARGUMENT`] for a null handle.
+#[unsafe(no_mangle)]
+pub extern "C" fn graph_locked_loops(
+    handle: *const GraphContext,
+    out_status: *mut LockedLoopStatus,
+    capacity: c_int,
+) -> c_int {
+    let Some(ctx) = (unsafe { handle.as_ref() }) else {
+        return COMPASS_ERR_INVALID_ARGUMENT;
+    };
+    let result = std::panic::catch_unwind(|| {
+        let status = ctx.locked_loop_status();
+        let count = status.len();
+        if !out_status.is_null() && capacity.max(0) as usize >= count {
+            // Safety: The caller guarantees `capacity` writable entries.
+            unsafe { slice::from_raw_parts_mut(out_status, count) }.copy_from_slice(&status);
+        }
+        count as c_int
+    });
+
+    result.unwrap_or_else(|_| {
+        eprintln!("Panic caught in graph_locked_loops");
+        COMPASS_ERR_PANIC
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{COMPASS_ERR_INVALID_ARGUMENT, graph_free};
+
+    /// A square loop 0-1-2-3 hanging from the anchor 0, closing 4 cm off, and a spur from 2
+    /// to 4. Edge 5, disabled until enabled by a test, is a later survey tying 4 to a second
+    /// anchor 5 that disagrees with the square by half a metre.
+    fn network() -> GraphContext {
+        let mut graph = Graph::default();
+        for (x, y, fixed) in [
+            (0.0, 0.0, true),
+            (10.0, 0.0, false),
+            (10.0, 10.0, false),
+            (0.0, 10.0, false),
+            (20.0, 10.0, false),
+            (30.5, 10.3, true),
+        ] {
+            graph.add_vertex(x, y, 0.0, fixed);
+        }
+        graph.add_edge(0, 1, 10.02, 0.01, 0.0, 1.0);
+        graph.add_edge(1, 2, 0.01, 10.02, 0.0, 1.0);
+        graph.add_edge(2, 3, -10.0, 0.01, 0.0, 1.0);
+        graph.add_edge(3, 0, 0.0, -10.0, 0.0, 1.0);
+        graph.add_edge(2, 4, 10.0, 0.0, 0.0, 1.0);
+        graph.add_edge(4, 5, 10.0, 0.0, 0.0, 1.0);
+        let mut ctx = *unsafe { Box::from_raw(GraphContext::into_raw(graph)) };
+        ctx.set_edge_enabled(5, false).unwrap();
+        ctx
+    }
+
+    /// The edge closing the square over the enabled edges.
+    fn closing_edge(ctx: &GraphContext) -> usize {
+        let enabled: Vec<usize> = (0..6).filter(|&e| ctx.is_edge_enabled(e)).collect();
+        let forest = SpanningForest::new(&ctx.graph, &enabled);
+        (0..4).find(|&e| !forest.tree_edge[e]).unwrap()
+    }
+
+    fn solve(ctx: &mut GraphContext) -> Solution {
+        ctx.solve(10_000, 1e-12.into(), Default::default(), None, &|| false)
+            .unwrap()
+            .clone()
+    }
+
+    #[test]
+    fn locked_loops_keep_their_shape_through_new_data() {
+        let mut ctx = network();
+        solve(&mut ctx);
+        let closing = closing_edge(&ctx);
+        ctx.lock_loop(closing, DEFAULT_LOCK_WEIGHT).unwrap();
+        assert_eq!(ctx.locked_loops()[0].edges.len(), 4);
+
+        let mut free = network();
+        solve(&mut free);
+        free.set_edge_enabled(5, true).unwrap();
+        let unlocked = solve(&mut free);
+        let distortion = ctx.locked_loops()[0].deviation(&free.graph, &unlocked.x, &unlocked.y);
+        assert!(distortion > 1e-2, "{distortion}");
+
+        ctx.set_edge_enabled(5, true).unwrap();
+        let locked = solve(&mut ctx);
+        assert_eq!(locked.stats.locked_loops, 1);
+        assert!(locked.stats.locked_deviation < 1e-5 * distortion.max(1.0));
+        // Toggling an edge keeps the locks in the cached normal equations.
+        ctx.set_edge_enabled(5, false).unwrap();
+        ctx.set_edge_enabled(5, true).unwrap();
+        let toggled = solve(&mut ctx);
+        assert!(toggled.stats.locked_deviation < 1e-5 * distortion.max(1.0));
+        // The misclosure of the new loop went to the spur.
+        let spur = |s: &Solution| s.x[4] - s.x[2];
+        assert!((spur(&locked) - 10.0).abs() > (spur(&unlocked) - 10.0).abs());
+
+        ctx.unlock_loop(closing).unwrap();
+        let released = solve(&mut ctx);
+        assert_eq!(released.stats.locked_loops, 0);
+        for v in 0..6 {
+            assert!((released.x[v] - unlocked.x[v]).abs() < 1e-8, "x[{v}]");
+        }
+    }
+
+    #[test]
+    fn locking_checks_its_arguments() {
+        let mut ctx = network();
+        let invalid = |result: Result<(), SolveError>| {
+            assert_eq!(result.unwrap_err().code, COMPASS_ERR_INVALID_ARGUMENT)
+        };
+        invalid(ctx.lock_loop(2, DEFAULT_LOCK_WEIGHT));
+        solve(&mut ctx);
+        let closing = closing_edge(&ctx);
+        invalid(ctx.lock_loop(closing, 0.0));
+        invalid(ctx.lock_loop(closing, f64::INFINITY));
+        invalid(ctx.lock_loop(4, DEFAULT_LOCK_WEIGHT));
+        invalid(ctx.lock_loop(5, DEFAULT_LOCK_WEIGHT));
+        invalid(ctx.lock_loop(6, DEFAULT_LOCK_WEIGHT));
+        invalid(ctx.unlock_loop(closing));
+    }
+
+    #[test]
+    fn ffi_locks_lists_and_unlocks() {
+        let ctx = network();
+        let closing = closing_edge(&ctx) as c_int;
+        let handle = Box::into_raw(Box::new(ctx));
+        assert_eq!(
+            graph_lock_loop(handle, closing, DEFAULT_LOCK_WEIGHT),
+            COMPASS_ERR_INVALID_ARGUMENT
+        );
+        solve(unsafe { &mut *handle });
+        assert_eq!(graph_lock_loop(handle, closing, 1e8), COMPASS_OK);
+        assert_eq!(
+            graph_lock_loop(handle, -1, 1e8),
+            COMPASS_ERR_INVALID_ARGUMENT
+        );
+
+        assert_eq!(graph_locked_loops(handle, std::ptr::null_mut(), 0), 1);
+        let mut status = [LockedLoopStatus::default()];
+        assert_eq!(graph_locked_loops(handle, status.as_mut_ptr(), 1), 1);
+        assert_eq!(status[0].closing_edge, closing);
+        assert_eq!(status[0].edges, 4);
+        assert!(status[0].deviation < 1e-12);
+
+        assert_eq!(graph_unlock_loop(handle, closing), COMPASS_OK);
+        assert_eq!(
+            graph_unlock_loop(handle, closing),
+            COMPASS_ERR_INVALID_ARGUMENT
+        );
+        assert_eq!(graph_locked_loops(handle, std::ptr::null_mut(), 0), 0);
+        assert_eq!(
+            graph_locked_loops(std::ptr::null(), std::ptr::null_mut(), 0),
+            COMPASS_ERR_INVALID_ARGUMENT
+        );
+        graph_free(handle);
+    }
+}
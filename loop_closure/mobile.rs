@@ -0,0 +1,457 @@
+//! UniFFI bindings for the iOS and Android sketching app. Kotlin and Swift sources are
+//! generated from the compiled library:
+//!
+//! ```text
+//! cargo build --release --features uniffi
+//! uniffi-bindgen generate --library <cdylib> --language kotlin --out-dir out
+//! uniffi-bindgen generate --library <cdylib> --language swift --out-dir out
+//! ```
+//!
+//! Arrays cross the boundary as little-endian byte buffers (`ByteArray` / `Data`) rather than
+//! lists, so marshalling is a single copy: `f64` for coordinates, observations and weights,
+//! `u32` for edge endpoints, one byte per vertex (non-zero = fixed) for the fixed flags.
+//!
+//! [`Graph::solve`] blocks; [`Graph::solve_async`] runs the solve on a background thread and
+//! is exposed as a `suspend fun` / `async func`. Cancelling the coroutine or task stops the
+//! solve at the next CG iteration.
+
+use crate::{COMPASS_ERR_CANCELLED, COMPASS_ERR_INVALID_ARGUMENT, COMPASS_ERR_PANIC};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+/// Solver tuning knobs.
+#[derive(Debug, Clone, Copy, uniffi::Record)]
+pub struct Options {
+    /// Maximum number of CG iterations.
+    pub iterations: u32,
+    /// CG residual tolerance.
+    pub tolerance: f64,
+}
+
+/// Options matching the defaults of the other bindings: 60000 iterations, 1e-8 tolerance.
+#[uniffi::export]
+pub fn default_options() -> Options {
+    Options {
+        iterations: 60_000,
+        tolerance: 1e-8,
+    }
+}
+
+/// Result of a solve.
+#[derive(Debug, Clone, uniffi::Record)]
+pub struct Solution {
+    /// Adjusted X coordinates, little-endian `f64`.
+    pub x: Vec<u8>,
+    /// Adjusted Y coordinates, little-endian `f64`.
+    pub y: Vec<u8>,
+    /// Number of vertices that were unknowns of the system.
+    pub free_vertices: u32,
+    /// Number of passive vertices (always 0 here).
+    pub passive_vertices: u32,
+}
+
+impl From<crate::Solution> for Solution {
+    fn from(solution: crate::Solution) -> Self {
+        Solution {
+            x: to_bytes(&solution.x),
+            y: to_bytes(&solution.y),
+            free_vertices: solution.stats.free_vertices as u32,
+            passive_vertices: solution.stats.passive_vertices as u32,
+        }
+    }
+}
+
+/// Error thrown by the bindings.
+#[derive(Debug, uniffi::Error)]
+pub enum SolveError {
+    /// The input buffers are malformed or inconsistent.
+    InvalidArgument { message: String },
+    /// The solve was cancelled.
+    Cancelled { message: String },
+    /// Any other failure, with the FFI error code.
+    Failed { code: i32, message: String },
+}
+
+impl std::fmt::Display for SolveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SolveError::InvalidArgument { message } | SolveError::Cancelled { message } => {
+                f.write_str(message)
+            }
+            SolveError::Failed { code, message } => write!(f, "{message} (code {code})"),
+        }
+    }
+}
+
+impl std::error::Error for SolveError {}
+
+impl From<crate::SolveError> for SolveError {
+    fn from(err: crate::SolveError) -> Self {
+        match err.code {
+            COMPASS_ERR_INVALID_ARGUMENT => SolveError::InvalidArgument {
+                message: err.message,
+            },
+            COMPASS_ERR_CANCELLED => SolveError::Cancelled {
+                message: err.message,
+            },
+            code => SolveError::Failed {
+                code,
+                message: err.message,
+            },
+        }
+    }
+}
+
+/// Survey network, immutable once built.
+#[derive(uniffi::Object)]
+pub struct Graph {
+    inner: Arc<crate::Graph>,
+}
+
+#[uniffi::export]
+impl Graph {
+    /// Builds a graph from packed buffers (see the module documentation). Arguments mirror
+    /// `solve_graph_least_squares`.
+    #[uniffi::constructor]
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        x: Vec<u8>,
+        y: Vec<u8>,
+        fixed: Vec<u8>,
+        from: Vec<u8>,
+        to: Vec<u8>,
+        dx: Vec<u8>,
+        dy: Vec<u8>,
+        weight: Vec<u8>,
+    ) -> Result<Arc<Self>, SolveError> {
+        let (x, y) = (f64s("x", &x)?, f64s("y", &y)?);
+        let (from, to) = (u32s("from", &from)?, u32s("to", &to)?);
+        let (dx, dy, weight) = (f64s("dx", &dx)?, f64s("dy", &dy)?, f64s("weight", &weight)?);
+
+        let n = x.len();
+        let m = from.len();
+        if y.len() != n || fixed.len() != n {
+            return Err(invalid(
+                "x, y and fixed must have the same length".to_string(),
+            ));
+        }
+        if [to.len(), dx.len(), dy.len(), weight.len()]
+            .iter()
+            .any(|&len| len != m)
+        {
+            return Err(invalid(
+                "from, to, dx, dy and weight must have the same length".to_string(),
+            ));
+        }
+        if let Some(e) = (0..m).find(|&e| from[e] as usize >= n || to[e] as usize >= n) {
+            return Err(invalid(format!("edge {e} references a missing vertex")));
+        }
+
+        let mut graph = crate::Graph::default();
+        for i in 0..n {
+            graph.add_vertex(x[i], y[i], 0.0, fixed[i] != 0);
+        }
+        for e in 0..m {
+            graph.add_edge(
+                from[e] as usize,
+                to[e] as usize,
+                dx[e],
+                dy[e],
+                0.0,
+                weight[e],
+            );
+        }
+        Ok(Arc::new(Graph {
+            inner: Arc::new(graph),
+        }))
+    }
+
+    /// Number of vertices.
+    pub fn num_vertices(&self) -> u32 {
+        self.inner.num_vertices() as u32
+    }
+
+    /// Number of edges.
+    pub fn num_edges(&self) -> u32 {
+        self.inner.num_edges() as u32
+    }
+
+    /// Solves on the calling thread. Do not call from the UI thread.
+    pub fn solve(&self, options: Options) -> Result<Solution, SolveError> {
+        let solution = self
+            .inner
+            .solve(options.iterations as usize, options.tolerance)?;
+        Ok(solution.into())
+    }
+
+    /// Solves on a background thread. Dropping the future, which is what cancelling the
+    /// Kotlin coroutine or Swift task does, cancels the solve.
+    pub async fn solve_async(&self, options: Options) -> Result<Solution, SolveError> {
+        SolveJob::spawn(Arc::clone(&self.inner), options).await
+    }
+}
+
+/// Shared state between a [`SolveJob`] and its worker thread.
+#[derive(Default)]
+struct JobState {
+    result: Option<Result<Solution, SolveError>>,
+    waker: Option<Waker>,
+}
+
+/// Future of a solve running on its own thread.
+struct SolveJob {
+    state: Arc<Mutex<JobState>>,
+    cancel: Arc<AtomicBool>,
+}
+
+impl SolveJob {
+    fn spawn(graph: Arc<crate::Graph>, options: Options) -> SolveJob {
+        let state = Arc::new(Mutex::new(JobState::default()));
+        let cancel = Arc::new(AtomicBool::new(false));
+        let job = SolveJob {
+            state: Arc::clone(&state),
+            cancel: Arc::clone(&cancel),
+        };
+        std::thread::spawn(move || {
+            let result = std::panic::catch_unwind(|| {
+                graph.solve_cancellable(options.iterations as usize, options.tolerance, &cancel)
+            });
+            let result = match result {
+                Ok(result) => result.map(Solution::from).map_err(SolveError::from),
+                Err(_) => {
+                    eprintln!("Panic caught in Graph.solveAsync");
+                    Err(SolveError::Failed {
+                        code: COMPASS_ERR_PANIC,
+                        message: "panic in the native solver".to_string(),
+                    })
+                }
+            };
+            let mut state = state.lock().unwrap_or_else(|e| e.into_inner());
+            state.result = Some(result);
+            if let Some(waker) = state.waker.take() {
+                waker.wake();
+            }
+        });
+        job
+    }
+}
+
+impl Future for SolveJob {
+    type Output = Result<Solution, SolveError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        match state.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl Drop for SolveJob {
+    fn drop(&mut self) {
+        // Stops the worker if the future is dropped before completion; a no-op otherwise.
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+}
+
+fn invalid(message: String) -> SolveError {
+    SolveError::InvalidArgument { message }
+}
+
+fn f64s(name: &str, bytes: &[u8]) -> Result<Vec<f64>, SolveError> {
+    if bytes.len() % 8 != 0 {
+        return Err(invalid(format!(
+            "{name} is not a whole number of f64 values"
+        )));
+    }
+    Ok(bytes
+        .chunks_exact(8)
+        .map(|b| f64::from_le_bytes(b.try_into().unwrap()))
+        .collect())
+}
+
+fn u32s(name: &str, bytes: &[u8]) -> Result<Vec<u32>, SolveError> {
+    if bytes.len() % 4 != 0 {
+        return Err(invalid(format!(
+            "{name} is not a whole number of u32 values"
+        )));
+    }
+    Ok(bytes
+        .chunks_exact(4)
+        .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+        .collect())
+}
+
+fn to_bytes(values: &[f64]) -> Vec<u8> {
+    values.iter().flat_map(|v| v.to_le_bytes()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::task::Wake;
+    use uniffi::{Lift, Lower, RustBuffer, RustCallStatus, RustCallStatusCode};
+
+    type Tag = crate::UniFfiTag;
+
+    /// The loop of the other bindings' tests, packed as the generated code packs it.
+    fn buffers() -> [Vec<u8>; 8] {
+        let u32s = |values: &[u32]| values.iter().flat_map(|v| v.to_le_bytes()).collect();
+        [
+            to_bytes(&[0.0, 10.3, 9.8, -0.1]),
+            to_bytes(&[0.0, 0.2, 10.1, 9.7]),
+            vec![1, 0, 0, 0],
+            u32s(&[0, 1, 2, 3]),
+            u32s(&[1, 2, 3, 0]),
+            to_bytes(&[10.0, 0.0, -10.0, 0.04]),
+            to_bytes(&[0.0, 10.0, 0.0, -10.0]),
+            to_bytes(&[1.0; 4]),
+        ]
+    }
+
+    fn graph() -> Arc<Graph> {
+        let [x, y, fixed, from, to, dx, dy, weight] = buffers();
+        Graph::new(x, y, fixed, from, to, dx, dy, weight).unwrap()
+    }
+
+    fn options() -> Options {
+        Options {
+            tolerance: 1e-12,
+            ..default_options()
+        }
+    }
+
+    /// Minimal executor: parks the thread until the worker wakes it.
+    fn block_on<F: Future>(future: F) -> F::Output {
+        struct Unpark(std::thread::Thread);
+        impl Wake for Unpark {
+            fn wake(self: Arc<Self>) {
+                self.0.unpark();
+            }
+        }
+        let waker = Waker::from(Arc::new(Unpark(std::thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        let mut future = std::pin::pin!(future);
+        loop {
+            match future.as_mut().poll(&mut cx) {
+                Poll::Ready(output) => return output,
+                Poll::Pending => std::thread::park(),
+            }
+        }
+    }
+
+    #[test]
+    fn solves_packed_buffers_like_the_safe_api() {
+        let graph = graph();
+        assert_eq!((graph.num_vertices(), graph.num_edges()), (4, 4));
+        let solution = graph.solve(options()).unwrap();
+        let expected = graph.inner.solve(1000, 1e-12).unwrap();
+        assert_eq!(f64s("x", &solution.x).unwrap(), expected.x);
+        assert_eq!(f64s("y", &solution.y).unwrap(), expected.y);
+        assert_eq!((solution.free_vertices, solution.passive_vertices), (3, 0));
+
+        let solution = block_on(graph.solve_async(options())).unwrap();
+        assert_eq!(f64s("x", &solution.x).unwrap(), expected.x);
+    }
+
+    #[test]
+    fn rejects_malformed_buffers() {
+        let message = |edit: fn(&mut [Vec<u8>; 8])| {
+            let mut buffers = buffers();
+            edit(&mut buffers);
+            let [x, y, fixed, from, to, dx, dy, weight] = buffers;
+            match Graph::new(x, y, fixed, from, to, dx, dy, weight) {
+                Err(SolveError::InvalidArgument { message }) => message,
+                other => panic!("expected InvalidArgument, got {:?}", other.err()),
+            }
+        };
+        assert_eq!(
+            message(|b| b[0].pop().map(drop).unwrap()),
+            "x is not a whole number of f64 values"
+        );
+        assert_eq!(
+            message(|b| b[4].truncate(3)),
+            "to is not a whole number of u32 values"
+        );
+        assert_eq!(
+            message(|b| b[2].push(0)),
+            "x, y and fixed must have the same length"
+        );
+        assert_eq!(
+            message(|b| b[7].truncate(8)),
+            "from, to, dx, dy and weight must have the same length"
+        );
+        assert_eq!(
+            message(|b| b[3][8] = 9),
+            "edge 2 references a missing vertex"
+        );
+    }
+
+    #[test]
+    fn dropping_the_future_cancels_the_solve() {
+        let job = SolveJob::spawn(Arc::clone(&graph().inner), options());
+        let cancel = Arc::clone(&job.cancel);
+        drop(job);
+        assert!(cancel.load(Ordering::Relaxed));
+
+        let err = crate::SolveError {
+            code: COMPASS_ERR_CANCELLED,
+            message: "solve cancelled".to_string(),
+        };
+        assert!(matches!(
+            SolveError::from(err),
+            SolveError::Cancelled { message } if message == "solve cancelled"
+        ));
+    }
+
+    #[test]
+    fn scaffolding_round_trips_records_and_objects() {
+        let mut status = RustCallStatus::default();
+        let buf = uniffi_graph_solver_fn_func_default_options(&mut status);
+        assert!(matches!(status.code, RustCallStatusCode::Success));
+        let defaults = <Options as Lift<Tag>>::try_lift(buf).unwrap();
+        assert_eq!((defaults.iterations, defaults.tolerance), (60_000, 1e-8));
+
+        let lower = |bytes: Vec<u8>| <Vec<u8> as Lower<Tag>>::lower(bytes);
+        let [x, y, fixed, from, to, dx, dy, weight] = buffers().map(lower);
+        let mut status = RustCallStatus::default();
+        let handle = uniffi_graph_solver_fn_constructor_graph_new(
+            x,
+            y,
+            fixed,
+            from,
+            to,
+            dx,
+            dy,
+            weight,
+            &mut status,
+        );
+        assert!(matches!(status.code, RustCallStatusCode::Success));
+
+        // Generated foreign code clones the handle for every method call; the call consumes it.
+        let mut status = RustCallStatus::default();
+        let clone = unsafe { uniffi_graph_solver_fn_clone_graph(handle, &mut status) };
+        let num_edges = uniffi_graph_solver_fn_method_graph_num_edges(clone, &mut status);
+        assert_eq!(num_edges, 4);
+
+        let options = <Options as Lower<Tag>>::lower(options());
+        let mut status = RustCallStatus::default();
+        let clone = unsafe { uniffi_graph_solver_fn_clone_graph(handle, &mut status) };
+        let buf: RustBuffer =
+            uniffi_graph_solver_fn_method_graph_solve(clone, options, &mut status);
+        assert!(matches!(status.code, RustCallStatusCode::Success));
+        let solution = <Solution as Lift<Tag>>::try_lift(buf).unwrap();
+        assert_eq!(solution.free_vertices, 3);
+
+        let mut status = RustCallStatus::default();
+        unsafe { uniffi_graph_solver_fn_free_graph(handle, &mut status) };
+        assert!(matches!(status.code, RustCallStatusCode::Success));
+    }
+}
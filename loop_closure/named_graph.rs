@@ -0,0 +1,564 @@
+//! Station-name based layer over [`Graph`], for callers that identify stations by name
+//! rather than by dense index.
+//!
+//! [`NamedGraph`] interns station names and assigns vertex indices in order of first
+//! appearance. How names differing only in case are treated is set by [`DuplicatePolicy`];
+//! whether shots may introduce stations on the fly is set by [`UnknownStationPolicy`].
+
+use crate::{
+    COMPASS_ERR_INVALID_ARGUMENT, COMPASS_ERR_PANIC, COMPASS_OK, Graph, GraphContext, SolveError,
+    SolveStats, write_message,
+};
+use std::collections::HashMap;
+use std::ffi::{CStr, c_char, c_double, c_int};
+use std::slice;
+
+/// Treatment of station names that differ only in case, e.g. `A1` and `a1`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DuplicatePolicy {
+    /// They are distinct stations.
+    #[default]
+    CaseSensitive,
+    /// They are the same station, spelled as it was first added.
+    Merge,
+    /// Using a name that differs only in case from a known one is an error.
+    Reject,
+}
+
+/// Treatment of shots referencing a station that was not added.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UnknownStationPolicy {
+    /// The station is created as a free vertex with a zero initial guess.
+    #[default]
+    Create,
+    /// The shot is rejected.
+    Error,
+}
+
+/// Options of a [`NamedGraph`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct NamedGraphOptions {
+    /// Treatment of names differing only in case.
+    pub duplicates: DuplicatePolicy,
+    /// Treatment of shots referencing unknown stations.
+    pub unknown_stations: UnknownStationPolicy,
+}
+
+/// [`Graph`] addressed by station names.
+#[derive(Debug, Clone)]
+pub struct NamedGraph {
+    /// Underlying graph; `names` is always `Some`.
+    graph: Graph,
+    /// Vertex index by lookup key (see [`NamedGraph::key`]).
+    index: HashMap<String, usize>,
+    options: NamedGraphOptions,
+}
+
+/// Adjusted station of a [`NamedSolution`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct NamedStation {
+    /// Station name.
+    pub name: String,
+    /// Adjusted X coordinate.
+    pub x: f64,
+    /// Adjusted Y coordinate.
+    pub y: f64,
+}
+
+/// Residual of one shot of a [`NamedSolution`], see [`crate::Solution::residuals`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct NamedResidual {
+    /// Name of the `from` station.
+    pub from: String,
+    /// Name of the `to` station.
+    pub to: String,
+    /// X residual.
+    pub dx: f64,
+    /// Y residual.
+    pub dy: f64,
+    /// Standardized residual, see [`crate::Solution::standardized_residuals`].
+    pub standardized: f64,
+}
+
+/// Result of [`NamedGraph::solve`], keyed by station name.
+#[derive(Debug, Clone)]
+pub struct NamedSolution {
+    /// Adjusted stations, in vertex order.
+    pub stations: Vec<NamedStation>,
+    /// Residuals, in shot order.
+    pub residuals: Vec<NamedResidual>,
+    /// Summary of the solve.
+    pub stats: SolveStats,
+    index: HashMap<String, usize>,
+    duplicates: DuplicatePolicy,
+}
+
+impl NamedSolution {
+    /// The adjusted station called `name`, matched per the graph's [`DuplicatePolicy`].
+    pub fn station(&self, name: &str) -> Option<&NamedStation> {
+        let i = *self.index.get(&lookup_key(self.duplicates, name))?;
+        let station = &self.stations[i];
+        match self.duplicates {
+            DuplicatePolicy::Reject if station.name != name => None,
+            _ => Some(station),
+        }
+    }
+}
+
+impl NamedGraph {
+    /// Creates an empty graph.
+    pub fn new(options: NamedGraphOptions) -> Self {
+        NamedGraph {
+            graph: Graph {
+                names: Some(Vec::new()),
+                ..Default::default()
+            },
+            index: HashMap::new(),
+            options,
+        }
+    }
+
+    /// The underlying graph, with [`Graph::names`] filled.
+    pub fn graph(&self) -> &Graph {
+        &self.graph
+    }
+
+    /// Converts into the underlying graph, with [`Graph::names`] filled.
+    pub fn into_graph(self) -> Graph {
+        self.graph
+    }
+
+    /// Vertex index of station `name`, if known.
+    pub fn index_of(&self, name: &str) -> Result<Option<usize>, SolveError> {
+        let Some(&i) = self.index.get(&self.key(name)) else {
+            return Ok(None);
+        };
+        if self.options.duplicates == DuplicatePolicy::Reject && self.names()[i] != name {
+            return Err(invalid(format!(
+                "station {name} differs only in case from {}",
+                self.names()[i]
+            )));
+        }
+        Ok(Some(i))
+    }
+
+    /// Adds a station and returns its vertex index. Adding a known station is an error.
+    pub fn add_station(
+        &mut self,
+        name: &str,
+        x: f64,
+        y: f64,
+        z: f64,
+        fixed: bool,
+    ) -> Result<usize, SolveError> {
+        if let Some(i) = self.index_of(name)? {
+            return Err(invalid(format!(
+                "duplicate station {name} (already added as {})",
+                self.names()[i]
+            )));
+        }
+        Ok(self.insert(name, x, y, z, fixed))
+    }
+
+    /// Adds a shot observing `(dx, dy, dz)` from station `from` to station `to` and returns
+    /// its edge index. Unknown stations are handled per [`UnknownStationPolicy`].
+    pub fn add_shot(
+        &mut self,
+        from: &str,
+        to: &str,
+        dx: f64,
+        dy: f64,
+        dz: f64,
+        weight: f64,
+    ) -> Result<usize, SolveError> {
+        let from = self.resolve(from)?;
+        let to = self.resolve(to)?;
+        Ok(self.graph.add_edge(from, to, dx, dy, dz, weight))
+    }
+
+    /// Solves the horizontal adjustment.
+    pub fn solve(&self, iterations: usize, tolerance: f64) -> Result<NamedSolution, SolveError> {
+        let solution = self.graph.solve(iterations, tolerance)?;
+        let names = self.names();
+        let stations = names
+            .iter()
+            .zip(solution.x.iter().zip(&solution.y))
+            .map(|(name, (&x, &y))| NamedStation {
+                name: name.clone(),
+                x,
+                y,
+            })
+            .collect();
+        let residuals = solution
+            .residuals(&self.graph)
+            .into_iter()
+            .zip(solution.standardized_residuals(&self.graph))
+            .enumerate()
+            .map(|(e, ((dx, dy), standardized))| NamedResidual {
+                from: names[self.graph.from[e]].clone(),
+                to: names[self.graph.to[e]].clone(),
+                dx,
+                dy,
+                standardized,
+            })
+            .collect();
+        Ok(NamedSolution {
+            stations,
+            residuals,
+            stats: solution.stats,
+            index: self.index.clone(),
+            duplicates: self.options.duplicates,
+        })
+    }
+
+    /// Index of station `name`, creating it if the policy allows.
+    fn resolve(&mut self, name: &str) -> Result<usize, SolveError> {
+        match (self.index_of(name)?, self.options.unknown_stations) {
+            (Some(i), _) => Ok(i),
+            (None, UnknownStationPolicy::Create) => Ok(self.insert(name, 0.0, 0.0, 0.0, false)),
+            (None, UnknownStationPolicy::Error) => {
+                Err(invalid(format!("shot references unknown station {name}")))
+            }
+        }
+    }
+
+    fn insert(&mut self, name: &str, x: f64, y: f64, z: f64, fixed: bool) -> usize {
+        let i = self.graph.add_vertex(x, y, z, fixed);
+        let names = self.graph.names.get_or_insert_with(Vec::new);
+        names.push(name.to_string());
+        self.index.insert(self.key(name), i);
+        i
+    }
+
+    fn names(&self) -> &[String] {
+        self.graph.names.as_deref().unwrap_or_default()
+    }
+
+    fn key(&self, name: &str) -> String {
+        lookup_key(self.options.duplicates, name)
+    }
+}
+
+/// Key of `name` in the name index: the name itself when case matters, its lowercase form
+/// otherwise.
+fn lookup_key(duplicates: DuplicatePolicy, name: &str) -> String {
+    match duplicates {
+        DuplicatePolicy::CaseSensitive => name.to_string(),
+        DuplicatePolicy::Merge | DuplicatePolicy::Reject => name.to_lowercase(),
+    }
+}
+
+fn invalid(message: String) -> SolveError {
+    SolveError {
+        code: COMPASS_ERR_INVALID_ARGUMENT,
+        message,
+    }
+}
+
+/// Builds a graph from station names and returns a context handle. Station `i` of the
+/// handle is the `i`-th vertex; stations created by shots follow in order of appearance.
+///
+/// # Arguments
+///
+/// * `num_vertices` - Number of stations.
+/// * `names` - Array of `num_vertices` NUL-terminated station names.
+/// * `x`, `y`, `fixed` - As in [`crate::solve_graph_least_squares`].
+/// * `num_edges` - Number of shots.
+/// * `from_names` / `to_names` - Arrays of `num_edges` NUL-terminated station names.
+/// * `observed_dx`, `observed_dy`, `weight` - As in [`crate::solve_graph_least_squares`].
+/// * `duplicate_policy` - 0 = case-sensitive, 1 = merge, 2 = reject (see [`DuplicatePolicy`]).
+/// * `create_unknown` - 1 to create stations referenced only by shots, 0 to fail.
+/// * `err_buf` - Buffer receiving a NUL-terminated error message on failure. May be null.
+/// * `err_cap` - Capacity of `err_buf` in bytes.
+///
+/// # Returns
+///
+/// * A handle to release with [`crate::graph_free`], or null on failure.
+#[unsafe(no_mangle)]
+pub extern "C" fn graph_from_named(
+    num_vertices: c_int,
+    names: *const *const c_char,
+    x: *const c_double,
+    y: *const c_double,
+    fixed: *const c_int,
+    num_edges: c_int,
+    from_names: *const *const c_char,
+    to_names: *const *const c_char,
+    observed_dx: *const c_double,
+    observed_dy: *const c_double,
+    weight: *const c_double,
+    duplicate_policy: c_int,
+    create_unknown: c_int,
+    err_buf: *mut c_char,
+    err_cap: usize,
+) -> *mut GraphContext {
+    let duplicates = match duplicate_policy {
+        0 => DuplicatePolicy::CaseSensitive,
+        1 => DuplicatePolicy::Merge,
+        2 => DuplicatePolicy::Reject,
+        _ => {
+            write_message(err_buf, err_cap, "invalid duplicate policy");
+            return std::ptr::null_mut();
+        }
+    };
+    let options = NamedGraphOptions {
+        duplicates,
+        unknown_stations: match create_unknown {
+            0 => UnknownStationPolicy::Error,
+            _ => UnknownStationPolicy::Create,
+        },
+    };
+
+    let (Ok(n_verts), Ok(n_edges)) = (usize::try_from(num_vertices), usize::try_from(num_edges))
+    else {
+        write_message(err_buf, err_cap, "negative station or shot count");
+        return std::ptr::null_mut();
+    };
+    let result = std::panic::catch_unwind(|| -> Result<Graph, SolveError> {
+        // Safety: The caller guarantees valid arrays of the given lengths, and valid
+        // NUL-terminated strings in the name arrays.
+        let name = |names: *const *const c_char, i: usize| {
+            let ptr = unsafe { *names.add(i) };
+            if ptr.is_null() {
+                return Err(invalid(format!("station name {i} is null")));
+            }
+            Ok(unsafe { CStr::from_ptr(ptr) }.to_string_lossy())
+        };
+        let x = unsafe { slice::from_raw_parts(x, n_verts) };
+        let y = unsafe { slice::from_raw_parts(y, n_verts) };
+        let fixed = unsafe { slice::from_raw_parts(fixed, n_verts) };
+        let dx = unsafe { slice::from_raw_parts(observed_dx, n_edges) };
+        let dy = unsafe { slice::from_raw_parts(observed_dy, n_edges) };
+        let weight = unsafe { slice::from_raw_parts(weight, n_edges) };
+
+        let mut graph = NamedGraph::new(options);
+        for i in 0..n_verts {
+            graph.add_station(&name(names, i)?, x[i], y[i], 0.0, fixed[i] != 0)?;
+        }
+        for e in 0..n_edges {
+            let (from, to) = (name(from_names, e)?, name(to_names, e)?);
+            graph.add_shot(&from, &to, dx[e], dy[e], 0.0, weight[e])?;
+        }
+        Ok(graph.into_graph())
+    });
+
+    match result {
+        Ok(Ok(graph)) => GraphContext::into_raw(graph),
+        Ok(Err(err)) => {
+            write_message(err_buf, err_cap, &err.message);
+            std::ptr::null_mut()
+        }
+        Err(_) => {
+            write_message(err_buf, err_cap, "panic while building the named graph");
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// Returns the vertex index of the station called `name` (exact match) in the graph behind
+/// `handle`, or [`COMPASS_ERR_INVALID_ARGUMENT`] if the handle has no such station.
+#[unsafe(no_mangle)]
+pub extern "C" fn graph_vertex_index(handle: *const GraphContext, name: *const c_char) -> c_int {
+    let result = std::panic::catch_unwind(|| {
+        // Safety: We assume the caller guarantees a valid (or null) handle and name.
+        let Some(ctx) = (unsafe { handle.as_ref() }) else {
+            return COMPASS_ERR_INVALID_ARGUMENT;
+        };
+        if name.is_null() {
+            return COMPASS_ERR_INVALID_ARGUMENT;
+        }
+        let name = unsafe { CStr::from_ptr(name) }.to_string_lossy();
+        ctx.graph()
+            .names
+            .as_ref()
+            .and_then(|names| names.iter().position(|n| *n == name))
+            .map_or(COMPASS_ERR_INVALID_ARGUMENT, |i| i as c_int)
+    });
+
+    result.unwrap_or_else(|_| {
+        eprintln!("Panic caught in graph_vertex_index");
+        COMPASS_ERR_PANIC
+    })
+}
+
+/// Copies the name of vertex `index` of the graph behind `handle` as a NUL-terminated string
+/// into `buf` (truncated to `cap` bytes). Unnamed graphs yield `V<index>`, as in the exports.
+#[unsafe(no_mangle)]
+pub extern "C" fn graph_vertex_name(
+    handle: *const GraphContext,
+    index: c_int,
+    buf: *mut c_char,
+    cap: usize,
+) -> c_int {
+    // Safety: We assume the caller guarantees a valid (or null) handle.
+    let Some(ctx) = (unsafe { handle.as_ref() }) else {
+        return COMPASS_ERR_INVALID_ARGUMENT;
+    };
+    let graph = ctx.graph();
+    if index < 0 || index as usize >= graph.num_vertices() {
+        return COMPASS_ERR_INVALID_ARGUMENT;
+    }
+    let i = index as usize;
+    match &graph.names {
+        Some(names) => write_message(buf, cap, &names[i]),
+        None => write_message(buf, cap, &format!("V{i}")),
+    }
+    COMPASS_OK
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CString;
+
+    fn options(
+        duplicates: DuplicatePolicy,
+        unknown_stations: UnknownStationPolicy,
+    ) -> NamedGraphOptions {
+        NamedGraphOptions {
+            duplicates,
+            unknown_stations,
+        }
+    }
+
+    fn triangle(options: NamedGraphOptions) -> NamedGraph {
+        let mut graph = NamedGraph::new(options);
+        graph.add_station("A1", 0.0, 0.0, 0.0, true).unwrap();
+        graph.add_station("A2", 9.8, 0.3, 0.0, false).unwrap();
+        graph.add_shot("A1", "A2", 10.0, 0.0, 0.0, 1.0).unwrap();
+        graph.add_shot("A2", "A3", 0.0, 10.0, 0.0, 1.0).unwrap();
+        graph.add_shot("A3", "A1", -10.03, -9.98, 0.0, 2.0).unwrap();
+        graph
+    }
+
+    #[test]
+    fn solution_is_keyed_by_name() {
+        let named = triangle(NamedGraphOptions::default());
+        let solution = named.solve(1000, 1e-12).unwrap();
+        let expected = named.graph().solve(1000, 1e-12).unwrap();
+
+        assert_eq!(named.index_of("A3").unwrap(), Some(2));
+        let a3 = solution.station("A3").unwrap();
+        assert_eq!((a3.x, a3.y), (expected.x[2], expected.y[2]));
+        assert!(solution.station("a3").is_none());
+
+        let residual = &solution.residuals[2];
+        assert_eq!((residual.from.as_str(), residual.to.as_str()), ("A3", "A1"));
+        assert_eq!(
+            (residual.dx, residual.dy),
+            expected.residuals(named.graph())[2]
+        );
+    }
+
+    #[test]
+    fn applies_the_duplicate_policy() {
+        let mut sensitive = triangle(NamedGraphOptions::default());
+        assert_eq!(
+            sensitive.add_station("a1", 0.0, 0.0, 0.0, false).unwrap(),
+            3
+        );
+
+        let mut merged = triangle(options(
+            DuplicatePolicy::Merge,
+            UnknownStationPolicy::Create,
+        ));
+        assert_eq!(
+            merged.add_shot("a3", "A2", 0.0, -10.0, 0.0, 1.0).unwrap(),
+            3
+        );
+        assert_eq!(merged.graph().num_vertices(), 3);
+        let err = merged.add_station("a1", 0.0, 0.0, 0.0, false).unwrap_err();
+        assert_eq!(err.message, "duplicate station a1 (already added as A1)");
+        let solution = merged.solve(1000, 1e-12).unwrap();
+        assert_eq!(solution.station("a2").unwrap().name, "A2");
+
+        let mut strict = triangle(options(
+            DuplicatePolicy::Reject,
+            UnknownStationPolicy::Create,
+        ));
+        let err = strict
+            .add_shot("A1", "a2", 10.0, 0.0, 0.0, 1.0)
+            .unwrap_err();
+        assert_eq!(err.message, "station a2 differs only in case from A2");
+        assert!(strict.solve(1000, 1e-12).unwrap().station("a2").is_none());
+    }
+
+    #[test]
+    fn applies_the_unknown_station_policy() {
+        let mut graph = NamedGraph::new(options(
+            DuplicatePolicy::CaseSensitive,
+            UnknownStationPolicy::Error,
+        ));
+        graph.add_station("A1", 0.0, 0.0, 0.0, true).unwrap();
+        let err = graph.add_shot("A1", "A2", 10.0, 0.0, 0.0, 1.0).unwrap_err();
+        assert_eq!(
+            (err.code, err.message.as_str()),
+            (
+                COMPASS_ERR_INVALID_ARGUMENT,
+                "shot references unknown station A2"
+            )
+        );
+        assert_eq!(graph.graph().num_edges(), 0);
+    }
+
+    #[test]
+    fn ffi_builds_a_handle_from_names() {
+        let names = ["A1", "A2"].map(|s| CString::new(s).unwrap());
+        let from = ["A1", "A2", "A3"].map(|s| CString::new(s).unwrap());
+        let to = ["A2", "A3", "A1"].map(|s| CString::new(s).unwrap());
+        let ptrs = |strings: &[CString]| strings.iter().map(|s| s.as_ptr()).collect::<Vec<_>>();
+        let mut err = [0 as c_char; 64];
+        let (err_buf, err_cap) = (err.as_mut_ptr(), err.len());
+        let build = |create_unknown| {
+            graph_from_named(
+                2,
+                ptrs(&names).as_ptr(),
+                [0.0, 9.8].as_ptr(),
+                [0.0, 0.3].as_ptr(),
+                [1, 0].as_ptr(),
+                3,
+                ptrs(&from).as_ptr(),
+                ptrs(&to).as_ptr(),
+                [10.0, 0.0, -10.03].as_ptr(),
+                [0.0, 10.0, -9.98].as_ptr(),
+                [1.0, 1.0, 2.0].as_ptr(),
+                0,
+                create_unknown,
+                err_buf,
+                err_cap,
+            )
+        };
+
+        assert!(build(0).is_null());
+        let message = unsafe { CStr::from_ptr(err_buf) };
+        assert_eq!(
+            message.to_str().unwrap(),
+            "shot references unknown station A3"
+        );
+
+        let handle = build(1);
+        assert!(!handle.is_null());
+        let a3 = CString::new("A3").unwrap();
+        assert_eq!(graph_vertex_index(handle, a3.as_ptr()), 2);
+        let a4 = CString::new("A4").unwrap();
+        assert_eq!(
+            graph_vertex_index(handle, a4.as_ptr()),
+            COMPASS_ERR_INVALID_ARGUMENT
+        );
+
+        let mut buf = [0 as c_char; 8];
+        assert_eq!(
+            graph_vertex_name(handle, 1, buf.as_mut_ptr(), buf.len()),
+            COMPASS_OK
+        );
+        assert_eq!(
+            unsafe { CStr::from_ptr(buf.as_ptr()) }.to_str().unwrap(),
+            "A2"
+        );
+        assert_eq!(
+            graph_vertex_name(handle, 3, buf.as_mut_ptr(), buf.len()),
+            COMPASS_ERR_INVALID_ARGUMENT
+        );
+        crate::graph_free(handle);
+    }
+}
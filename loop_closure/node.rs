@@ -0,0 +1,201 @@
+//! Node.js bindings (napi-rs) for the Electron backend of the map editor.
+//!
+//! `solveGraph` runs on a libuv worker thread and returns a Promise. The typed arrays are
+//! borrowed zero-copy for the duration of the solve (only the output coordinates are
+//! allocated), so callers must not mutate them until the Promise settles. TypeScript
+//! definitions are generated by `napi build` from the attributes below.
+
+use crate::{COMPASS_ERR_CANCELLED, COMPASS_OK, GraphView, SolveOptions, SolveStats, solve_view};
+use napi::bindgen_prelude::*;
+use napi::{Env, JsFunction, JsObject};
+use napi_derive::napi;
+use std::ffi::c_int;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicI32, Ordering};
+
+/// Optional tuning knobs of `solveGraph`.
+#[napi(object)]
+pub struct SolveGraphOptions {
+    /// Maximum number of CG iterations. Defaults to 60000.
+    pub iterations: Option<u32>,
+    /// CG residual tolerance. Defaults to 1e-8.
+    pub tolerance: Option<f64>,
+}
+
+/// Resolved value of `solveGraph`.
+#[napi(object)]
+pub struct SolveGraphResult {
+    /// Adjusted X coordinates.
+    pub x: Float64Array,
+    /// Adjusted Y coordinates.
+    pub y: Float64Array,
+    /// Number of vertices that were unknowns of the system.
+    pub free_vertices: i32,
+    /// Number of passive vertices (always 0 here).
+    pub passive_vertices: i32,
+}
+
+/// Background solve of one `solveGraph` call.
+pub struct SolveTask {
+    x: Float64Array,
+    y: Float64Array,
+    fixed: Int32Array,
+    from: Int32Array,
+    to: Int32Array,
+    dx: Float64Array,
+    dy: Float64Array,
+    weight: Float64Array,
+    iterations: c_int,
+    tolerance: f64,
+    /// Set by the AbortSignal listener, polled by the CG loop.
+    cancel: Arc<AtomicI32>,
+}
+
+impl Task for SolveTask {
+    type Output = (Vec<f64>, Vec<f64>, SolveStats);
+    type JsValue = SolveGraphResult;
+
+    fn compute(&mut self) -> Result<Self::Output> {
+        if self.cancel.load(Ordering::Relaxed) != 0 {
+            return Err(Error::new(Status::Cancelled, "solve aborted"));
+        }
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let mut x = self.x.to_vec();
+            let mut y = self.y.to_vec();
+            let graph = GraphView {
+                fixed: &self.fixed,
+                from: &self.from,
+                to: &self.to,
+                dx: &self.dx,
+                dy: &self.dy,
+                weight: &self.weight,
+                enabled: None,
+            };
+            let options = SolveOptions {
+                iterations: self.iterations,
+                tolerance: self.tolerance,
+                cancel: Arc::as_ptr(&self.cancel),
+                ..SolveOptions::default()
+            };
+            let mut stats = SolveStats::default();
+            let code = solve_view(
+                &mut x,
+                &mut y,
+                &graph,
+                None,
+                &options,
+                &mut stats,
+                None,
+                &mut Vec::new(),
+            );
+            (code, x, y, stats)
+        }));
+
+        match result {
+            Ok((COMPASS_OK, x, y, stats)) => Ok((x, y, stats)),
+            Ok((COMPASS_ERR_CANCELLED, ..)) => Err(Error::new(Status::Cancelled, "solve aborted")),
+            Ok((code, ..)) => Err(Error::new(
+                Status::GenericFailure,
+                format!("solve failed (code {code})"),
+            )),
+            Err(_) => Err(Error::new(
+                Status::GenericFailure,
+                "panic caught in solveGraph",
+            )),
+        }
+    }
+
+    fn resolve(&mut self, _env: Env, (x, y, stats): Self::Output) -> Result<Self::JsValue> {
+        Ok(SolveGraphResult {
+            x: x.into(),
+            y: y.into(),
+            free_vertices: stats.free_vertices,
+            passive_vertices: stats.passive_vertices,
+        })
+    }
+}
+
+/// Solves the horizontal adjustment on a worker thread. Arguments mirror
+/// `solve_graph_least_squares`; the inputs are not modified and the adjusted coordinates are
+/// returned as new arrays. Aborting `signal` rejects the Promise with a cancellation error.
+#[napi(
+    js_name = "solveGraph",
+    ts_args_type = "x: Float64Array, y: Float64Array, fixed: Int32Array, from: Int32Array, to: Int32Array, dx: Float64Array, dy: Float64Array, weight: Float64Array, options?: SolveGraphOptions, signal?: AbortSignal",
+    ts_return_type = "Promise<SolveGraphResult>"
+)]
+#[allow(clippy::too_many_arguments)]
+pub fn solve_graph(
+    env: Env,
+    x: Float64Array,
+    y: Float64Array,
+    fixed: Int32Array,
+    from: Int32Array,
+    to: Int32Array,
+    dx: Float64Array,
+    dy: Float64Array,
+    weight: Float64Array,
+    options: Option<SolveGraphOptions>,
+    signal: Option<JsObject>,
+) -> Result<AsyncTask<SolveTask>> {
+    let n = x.len();
+    let m = from.len();
+    if y.len() != n || fixed.len() != n {
+        return Err(Error::new(
+            Status::InvalidArg,
+            "x, y and fixed must have the same length",
+        ));
+    }
+    if [to.len(), dx.len(), dy.len(), weight.len()]
+        .iter()
+        .any(|&len| len != m)
+    {
+        return Err(Error::new(
+            Status::InvalidArg,
+            "from, to, dx, dy and weight must have the same length",
+        ));
+    }
+    if let Some(e) = (0..m).find(|&e| [from[e], to[e]].iter().any(|&v| v < 0 || v as usize >= n)) {
+        return Err(Error::new(
+            Status::InvalidArg,
+            format!("edge {e} references a missing vertex"),
+        ));
+    }
+
+    let cancel = Arc::new(AtomicI32::new(0));
+    if let Some(signal) = signal {
+        if signal.get_named_property::<bool>("aborted")? {
+            cancel.store(1, Ordering::Relaxed);
+        }
+        let flag = Arc::clone(&cancel);
+        let listener = env.create_function_from_closure("onAbort", move |ctx| {
+            flag.store(1, Ordering::Relaxed);
+            ctx.env.get_undefined()
+        })?;
+        let add_event_listener: JsFunction = signal.get_named_property("addEventListener")?;
+        add_event_listener.call(
+            Some(&signal),
+            &[
+                env.create_string("abort")?.into_unknown(),
+                listener.into_unknown(),
+            ],
+        )?;
+    }
+
+    let options = options.unwrap_or(SolveGraphOptions {
+        iterations: None,
+        tolerance: None,
+    });
+    Ok(AsyncTask::new(SolveTask {
+        x,
+        y,
+        fixed,
+        from,
+        to,
+        dx,
+        dy,
+        weight,
+        iterations: options.iterations.unwrap_or(60_000).min(c_int::MAX as u32) as c_int,
+        tolerance: options.tolerance.unwrap_or(1e-8),
+        cancel,
+    }))
+}
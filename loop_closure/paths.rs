@@ -0,0 +1,349 @@
+//! Where the discrepancy between two stations accumulates: the shortest path between them,
+//! by surveyed length, with the observed differences of its shots set against the coordinate
+//! differences of their ends.
+//!
+//! When two fixed points disagree with the survey between them, the running discrepancy along
+//! the traverse, coordinate minus observed differences summed from the start, shows which
+//! shots carry the error: it jumps at a blunder and drifts along a biased instrument. At the
+//! input coordinates of a network placed by chaining its shots, the whole misclosure shows up
+//! where the traverse reaches the second anchor; at adjusted coordinates, how the adjustment
+//! spread it.
+//!
+//! The adjustment report lists this breakdown for each shot between two anchors whose
+//! residual fails the check, see [`crate::report::CHECK_THRESHOLD`].
+
+use crate::{
+    COMPASS_ERR_BUFFER_TOO_SMALL, COMPASS_ERR_INVALID_ARGUMENT, COMPASS_OK, Graph, GraphContext,
+    Solution, SolveError,
+};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::ffi::{c_double, c_int};
+use std::slice;
+
+/// One shot of a [`PathBreakdown`], oriented along the path.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PathSegment {
+    pub edge: usize,
+    /// Ends of the shot, in the direction of the path.
+    pub from: usize,
+    pub to: usize,
+    /// Observed difference along the path.
+    pub observed_x: f64,
+    pub observed_y: f64,
+    /// Coordinate difference `to - from`.
+    pub coordinate_x: f64,
+    pub coordinate_y: f64,
+    /// Coordinate minus observed differences summed from the start of the path through this
+    /// shot.
+    pub discrepancy_x: f64,
+    pub discrepancy_y: f64,
+}
+
+/// Shortest path between two stations, see the [module documentation](self).
+#[derive(Debug, Clone, PartialEq)]
+pub struct PathBreakdown {
+    /// Stations of the path, from the start to the end.
+    pub vertices: Vec<usize>,
+    pub segments: Vec<PathSegment>,
+    /// Sum of the 3D shot lengths.
+    pub length: f64,
+    /// Sum of the observed differences along the path.
+    pub observed_x: f64,
+    pub observed_y: f64,
+    /// Coordinate difference between the ends.
+    pub coordinate_x: f64,
+    pub coordinate_y: f64,
+}
+
+impl PathBreakdown {
+    /// Coordinate minus observed difference between the ends, the discrepancy of the last
+    /// segment.
+    pub fn discrepancy(&self) -> (f64, f64) {
+        (
+            self.coordinate_x - self.observed_x,
+            self.coordinate_y - self.observed_y,
+        )
+    }
+}
+
+/// Breakdown of the shortest path from `from` to `to` over the edges for which `usable`
+/// holds, at the coordinates `x` / `y`; `None` when no such path joins them. A path from a
+/// vertex to itself has no segment.
+pub(crate) fn path_breakdown(
+    graph: &Graph,
+    (x, y): (&[f64], &[f64]),
+    (from, to): (usize, usize),
+    usable: &dyn Fn(usize) -> bool,
+) -> Option<PathBreakdown> {
+    let n = graph.num_vertices();
+    let length =
+        |e: usize| (graph.dx[e].powi(2) + graph.dy[e].powi(2) + graph.dz[e].powi(2)).sqrt();
+    let mut neighbors = vec![Vec::new(); n];
+    for e in (0..graph.num_edges()).filter(|&e| usable(e) && length(e).is_finite()) {
+        let (u, v) = (graph.from[e], graph.to[e]);
+        if u != v {
+            neighbors[u].push((v, e));
+            neighbors[v].push((u, e));
+        }
+    }
+
+    // Dijkstra from `from`, stopping at `to`. Lengths are never negative, so their bits
+    // order them like their values.
+    let mut distance = vec![f64::INFINITY; n];
+    let mut previous: Vec<Option<(usize, usize)>> = vec![None; n];
+    let mut heap = BinaryHeap::from([Reverse((0.0f64.to_bits(), from))]);
+    distance[from] = 0.0;
+    while let Some(Reverse((bits, u))) = heap.pop() {
+        let d = f64::from_bits(bits);
+        if u == to {
+            break;
+        }
+        if d > distance[u] {
+            continue;
+        }
+        for &(v, e) in &neighbors[u] {
+            let candidate = d + length(e);
+            if candidate < distance[v] {
+                distance[v] = candidate;
+                previous[v] = Some((u, e));
+                heap.push(Reverse((candidate.to_bits(), v)));
+            }
+        }
+    }
+    if distance[to].is_infinite() {
+        return None;
+    }
+
+    let mut vertices = vec![to];
+    let mut edges = Vec::new();
+    while let Some((u, e)) = previous[*vertices.last().unwrap_or(&from)] {
+        vertices.push(u);
+        edges.push(e);
+    }
+    vertices.reverse();
+    edges.reverse();
+
+    let mut breakdown = PathBreakdown {
+        vertices,
+        segments: Vec::with_capacity(edges.len()),
+        length: distance[to],
+        observed_x: 0.0,
+        observed_y: 0.0,
+        coordinate_x: x[to] - x[from],
+        coordinate_y: y[to] - y[from],
+    };
+    let (mut discrepancy_x, mut discrepancy_y) = (0.0, 0.0);
+    for (k, &e) in edges.iter().enumerate() {
+        let (u, v) = (breakdown.vertices[k], breakdown.vertices[k + 1]);
+        let sign = if graph.from[e] == u { 1.0 } else { -1.0 };
+        let (observed_x, observed_y) = (sign * graph.dx[e], sign * graph.dy[e]);
+        let (coordinate_x, coordinate_y) = (x[v] - x[u], y[v] - y[u]);
+        discrepancy_x += coordinate_x - observed_x;
+        discrepancy_y += coordinate_y - observed_y;
+        breakdown.observed_x += observed_x;
+        breakdown.observed_y += observed_y;
+        breakdown.segments.push(PathSegment {
+            edge: e,
+            from: u,
+            to: v,
+            observed_x,
+            observed_y,
+            coordinate_x,
+            coordinate_y,
+            discrepancy_x,
+            discrepancy_y,
+        });
+    }
+    Some(breakdown)
+}
+
+/// Checks the ends of a path in a graph of `num_vertices` vertices.
+fn check_ends(from: usize, to: usize, num_vertices: usize) -> Result<(), SolveError> {
+    match from < num_vertices && to < num_vertices {
+        true => Ok(()),
+        false => Err(SolveError {
+            code: COMPASS_ERR_INVALID_ARGUMENT,
+            message: format!("vertex {} out of range", from.max(to)),
+        }),
+    }
+}
+
+fn not_connected(from: usize, to: usize) -> SolveError {
+    SolveError {
+        code: COMPASS_ERR_INVALID_ARGUMENT,
+        message: format!("no path between vertices {from} and {to}"),
+    }
+}
+
+impl Solution {
+    /// Breakdown of the shortest path from `from` to `to` over all the edges of `graph`, at
+    /// the adjusted coordinates. Fails with [`COMPASS_ERR_INVALID_ARGUMENT`] for a vertex out
+    /// of range or when no path joins them.
+    pub fn path_breakdown(
+        &self,
+        graph: &Graph,
+        from: usize,
+        to: usize,
+    ) -> Result<PathBreakdown, SolveError> {
+        check_ends(from, to, graph.num_vertices())?;
+        path_breakdown(graph, (&self.x, &self.y), (from, to), &|_| true)
+            .ok_or_else(|| not_connected(from, to))
+    }
+}
+
+impl GraphContext {
+    /// Breakdown of the shortest path from `from` to `to` over the enabled edges, at the
+    /// current [`GraphContext::coordinates`], see [`Solution::path_breakdown`].
+    pub fn path_breakdown(&self, from: usize, to: usize) -> Result<PathBreakdown, SolveError> {
+        check_ends(from, to, self.graph.num_vertices())?;
+        let usable = |e| self.is_edge_enabled(e);
+        path_breakdown(&self.graph, self.coordinates(), (from, to), &usable)
+            .ok_or_else(|| not_connected(from, to))
+    }
+}
+
+/// Copies the shortest path from `from` to `to` over the enabled edges of the graph behind
+/// `handle`, see [`GraphContext::path_breakdown`], into caller buffers of `cap` entries: its
+/// stations into `out_vertices`, the edge reaching each station into `out_edges` (-1 for the
+/// start), and the running discrepancy at each station into `out_discrepancy`, `2 * cap`
+/// values, x then y for each station (0 at the start). The number of stations is written to
+/// `out_count` either way, so that a first call with `cap` 0 sizes the buffers. Any output
+/// pointer may be null to skip it.
+///
+/// # Returns
+///
+/// * [`COMPASS_OK`].
+/// * [`COMPASS_ERR_BUFFER_TOO_SMALL`] if `cap` is below the number of stations; nothing is
+///   written to the buffers.
+/// * [`COMPASS_ERR_INVALID_ARGUMENT`] for a null handle, a vertex out of range, or when no
+///   path joins the vertices; `out_count` is left untouched.
+#[allow(clippy::too_many_arguments)]
+#[unsafe(no_mangle)]
+pub extern "C" fn graph_path_breakdown(
+    handle: *const GraphContext,
+    from: c_int,
+    to: c_int,
+    out_vertices: *mut c_int,
+    out_edges: *mut c_int,
+    out_discrepancy: *mut c_double,
+    cap: c_int,
+    out_count: *mut c_int,
+) -> c_int {
+    let Some(ctx) = (unsafe { handle.as_ref() }) else {
+        return COMPASS_ERR_INVALID_ARGUMENT;
+    };
+    let (Ok(from), Ok(to)) = (usize::try_from(from), usize::try_from(to)) else {
+        return COMPASS_ERR_INVALID_ARGUMENT;
+    };
+    let path = match ctx.path_breakdown(from, to) {
+        Ok(path) => path,
+        Err(err) => return err.code,
+    };
+    let count = path.vertices.len();
+    if !out_count.is_null() {
+        unsafe { *out_count = count as c_int };
+    }
+    if (cap.max(0) as usize) < count {
+        return COMPASS_ERR_BUFFER_TOO_SMALL;
+    }
+    if !out_vertices.is_null() {
+        // Safety: The caller guarantees a buffer of `cap` elements.
+        let out = unsafe { slice::from_raw_parts_mut(out_vertices, count) };
+        for (dst, &v) in out.iter_mut().zip(&path.vertices) {
+            *dst = v as c_int;
+        }
+    }
+    if !out_edges.is_null() {
+        // Safety: The caller guarantees a buffer of `cap` elements.
+        let out = unsafe { slice::from_raw_parts_mut(out_edges, count) };
+        out[0] = -1;
+        for (dst, segment) in out[1..].iter_mut().zip(&path.segments) {
+            *dst = segment.edge as c_int;
+        }
+    }
+    if !out_discrepancy.is_null() {
+        // Safety: The caller guarantees a buffer of `2 * cap` elements.
+        let out = unsafe { slice::from_raw_parts_mut(out_discrepancy, 2 * count) };
+        out[..2].fill(0.0);
+        for (dst, segment) in out[2..].chunks_exact_mut(2).zip(&path.segments) {
+            dst.copy_from_slice(&[segment.discrepancy_x, segment.discrepancy_y]);
+        }
+    }
+    COMPASS_OK
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph_free;
+
+    /// Anchors 0 at (0, 0) and 3 at (30, 0) joined by the traverse 0-1-2-3 of shots
+    /// (10, 0), (10, 0) and (9.5, 0), and by a longer detour 0-4-3 of (15, 10), (15, -10).
+    /// The traverse stations are placed by chaining the shots from station 0.
+    fn blundered_traverse() -> Graph {
+        let mut graph = Graph::default();
+        for (x, y, fixed) in [
+            (0.0, 0.0, true),
+            (10.0, 0.0, false),
+            (20.0, 0.0, false),
+            (30.0, 0.0, true),
+            (15.0, 10.0, false),
+        ] {
+            graph.add_vertex(x, y, 0.0, fixed);
+        }
+        graph.add_edge(0, 1, 10.0, 0.0, 0.0, 1.0);
+        graph.add_edge(2, 1, -10.0, 0.0, 0.0, 1.0);
+        graph.add_edge(2, 3, 9.5, 0.0, 0.0, 1.0);
+        graph.add_edge(0, 4, 15.0, 10.0, 0.0, 1.0);
+        graph.add_edge(4, 3, 15.0, -10.0, 0.0, 1.0);
+        graph
+    }
+
+    #[test]
+    fn discrepancy_accumulates_at_the_blunder() {
+        let graph = blundered_traverse();
+        let placed = Solution {
+            x: graph.x.clone(),
+            y: graph.y.clone(),
+            ..Solution::default()
+        };
+        let path = placed.path_breakdown(&graph, 0, 3).unwrap();
+        assert_eq!(path.vertices, [0, 1, 2, 3]);
+        assert_eq!(path.length, 29.5);
+        // The reversed shot 2-1 counts along the path.
+        assert_eq!(path.segments[1].observed_x, 10.0);
+        let running: Vec<f64> = path.segments.iter().map(|s| s.discrepancy_x).collect();
+        assert_eq!(running, [0.0, 0.0, 0.5]);
+        assert_eq!(path.discrepancy(), (0.5, 0.0));
+
+        let reversed = placed.path_breakdown(&graph, 3, 0).unwrap();
+        assert_eq!(reversed.vertices, [3, 2, 1, 0]);
+        assert_eq!(reversed.discrepancy(), (-0.5, 0.0));
+        let err = placed.path_breakdown(&graph, 0, 5).unwrap_err();
+        assert_eq!(err.code, COMPASS_ERR_INVALID_ARGUMENT);
+
+        // Disabling the blunder routes the path through the detour.
+        let handle = GraphContext::into_raw(graph);
+        unsafe { &mut *handle }.edge_enabled = vec![true, true, false];
+        let mut count = -1;
+        let (mut vertices, mut edges, mut discrepancy) = ([-1; 3], [-2; 3], [f64::NAN; 6]);
+        let (v, e, d) = (
+            vertices.as_mut_ptr(),
+            edges.as_mut_ptr(),
+            discrepancy.as_mut_ptr(),
+        );
+        let call =
+            move |cap, count: &mut c_int| graph_path_breakdown(handle, 0, 3, v, e, d, cap, count);
+        assert_eq!(call(0, &mut count), COMPASS_ERR_BUFFER_TOO_SMALL);
+        assert_eq!(count, 3);
+        assert_eq!(call(3, &mut count), COMPASS_OK);
+        assert_eq!((vertices, edges), ([0, 4, 3], [-1, 3, 4]));
+        assert_eq!(discrepancy, [0.0; 6]);
+
+        // Cut off from station 3.
+        unsafe { &mut *handle }.edge_enabled = vec![true, true, false, true, false];
+        assert_eq!(call(3, &mut count), COMPASS_ERR_INVALID_ARGUMENT);
+        graph_free(handle);
+    }
+}
@@ -0,0 +1,234 @@
+//! Conversions between [`Graph`] and `petgraph::Graph`.
+//!
+//! Index stability: a `petgraph::Graph` keeps node and edge indices contiguous as long as
+//! nothing is removed, and both conversions preserve them one-to-one. Vertex `i` of the
+//! [`Graph`] is `NodeIndex::new(i)` and edge `e` is `EdgeIndex::new(e)`, so the output of
+//! [`Solution::residuals`] can be mapped straight back onto the petgraph edges. Removing
+//! nodes or edges from the petgraph between the conversions invalidates this mapping.
+
+use crate::{Graph, Solution};
+use petgraph::graph::{EdgeIndex, NodeIndex};
+use petgraph::visit::EdgeRef;
+
+/// Node weight of [`Solution::to_petgraph`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct AdjustedStation {
+    /// Adjusted X coordinate.
+    pub x: f64,
+    /// Adjusted Y coordinate.
+    pub y: f64,
+    /// Z coordinate, carried over from the input graph.
+    pub z: f64,
+    /// Whether the vertex was fixed.
+    pub fixed: bool,
+    /// Station name, when the input graph has names.
+    pub name: Option<String>,
+}
+
+/// Edge weight of [`Solution::to_petgraph`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AdjustedShot {
+    /// Observed X difference.
+    pub dx: f64,
+    /// Observed Y difference.
+    pub dy: f64,
+    /// Weight of the observation.
+    pub weight: f64,
+    /// Residual `(rx, ry)`, see [`Solution::residuals`].
+    pub residual: (f64, f64),
+}
+
+impl Graph {
+    /// Builds a graph from a `petgraph::Graph`, keeping node and edge indices (see the module
+    /// documentation).
+    ///
+    /// `station` extracts `(x, y, fixed)` from a node weight and `shot` extracts
+    /// `(dx, dy, weight)` from an edge weight, with the observation oriented from the edge
+    /// source to its target. Z coordinates and observations are set to zero.
+    pub fn from_petgraph<N, E>(
+        graph: &petgraph::Graph<N, E>,
+        station: impl Fn(&N) -> (f64, f64, bool),
+        shot: impl Fn(&E) -> (f64, f64, f64),
+    ) -> Graph {
+        let mut out = Graph::default();
+        for node in graph.node_indices() {
+            let (x, y, fixed) = station(&graph[node]);
+            out.add_vertex(x, y, 0.0, fixed);
+        }
+        for edge in graph.edge_references() {
+            let (dx, dy, weight) = shot(edge.weight());
+            out.add_edge(
+                edge.source().index(),
+                edge.target().index(),
+                dx,
+                dy,
+                0.0,
+                weight,
+            );
+        }
+        out
+    }
+}
+
+impl Solution {
+    /// Builds a `petgraph::Graph` of `graph` carrying the adjusted coordinates in its node
+    /// weights and the residuals in its edge weights. Node `i` and edge `e` correspond to
+    /// vertex `i` and edge `e` of `graph`.
+    pub fn to_petgraph(&self, graph: &Graph) -> petgraph::Graph<AdjustedStation, AdjustedShot> {
+        let mut out = petgraph::Graph::with_capacity(graph.num_vertices(), graph.num_edges());
+        for i in 0..graph.num_vertices() {
+            out.add_node(AdjustedStation {
+                x: self.x[i],
+                y: self.y[i],
+                z: graph.z[i],
+                fixed: graph.fixed[i],
+                name: graph.names.as_ref().map(|names| names[i].clone()),
+            });
+        }
+        for (e, residual) in self.residuals(graph).into_iter().enumerate() {
+            out.add_edge(
+                NodeIndex::new(graph.from[e]),
+                NodeIndex::new(graph.to[e]),
+                AdjustedShot {
+                    dx: graph.dx[e],
+                    dy: graph.dy[e],
+                    weight: graph.weight[e],
+                    residual,
+                },
+            );
+        }
+        out
+    }
+
+    /// Residuals keyed by petgraph edge index, for graphs built with
+    /// [`Graph::from_petgraph`].
+    pub fn petgraph_residuals(&self, graph: &Graph) -> Vec<(EdgeIndex, (f64, f64))> {
+        self.residuals(graph)
+            .into_iter()
+            .enumerate()
+            .map(|(e, residual)| (EdgeIndex::new(e), residual))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Station {
+        x: f64,
+        y: f64,
+        anchor: bool,
+    }
+
+    struct Shot {
+        dx: f64,
+        dy: f64,
+        sigma: f64,
+    }
+
+    /// A loop whose edges are added out of node order, one of them pointing backwards.
+    fn survey() -> petgraph::Graph<Station, Shot> {
+        let mut survey = petgraph::Graph::new();
+        let station = |x, y, anchor| Station { x, y, anchor };
+        let a = survey.add_node(station(0.0, 0.0, true));
+        let b = survey.add_node(station(10.3, 0.1, false));
+        let c = survey.add_node(station(9.8, 9.9, false));
+        let d = survey.add_node(station(0.2, 10.1, false));
+        let shot = |dx, dy, sigma| Shot { dx, dy, sigma };
+        survey.add_edge(c, d, shot(-10.0, 0.0, 0.5));
+        survey.add_edge(a, b, shot(10.0, 0.0, 1.0));
+        survey.add_edge(a, d, shot(0.04, 10.02, 2.0));
+        survey.add_edge(b, c, shot(0.0, 10.0, 1.0));
+        survey
+    }
+
+    fn convert(survey: &petgraph::Graph<Station, Shot>) -> Graph {
+        Graph::from_petgraph(
+            survey,
+            |s| (s.x, s.y, s.anchor),
+            |s| (s.dx, s.dy, 1.0 / (s.sigma * s.sigma)),
+        )
+    }
+
+    #[test]
+    fn keeps_node_and_edge_indices() {
+        let survey = survey();
+        let graph = convert(&survey);
+        assert_eq!(graph.num_vertices(), survey.node_count());
+        for node in survey.node_indices() {
+            let i = node.index();
+            assert_eq!((graph.x[i], graph.y[i]), (survey[node].x, survey[node].y));
+            assert_eq!(graph.fixed[i], survey[node].anchor);
+        }
+        for edge in survey.edge_indices() {
+            let (source, target) = survey.edge_endpoints(edge).unwrap();
+            let e = edge.index();
+            assert_eq!(
+                (graph.from[e], graph.to[e]),
+                (source.index(), target.index())
+            );
+            assert_eq!(graph.dx[e], survey[edge].dx);
+            assert_eq!(
+                graph.weight[e],
+                1.0 / (survey[edge].sigma * survey[edge].sigma)
+            );
+        }
+        assert!(graph.z.iter().chain(&graph.dz).all(|&v| v == 0.0));
+    }
+
+    #[test]
+    fn maps_residuals_back_onto_petgraph_edges() {
+        let survey = survey();
+        let graph = convert(&survey);
+        let solution = graph.solve(1000, 1e-12).unwrap();
+
+        for (edge, (rx, ry)) in solution.petgraph_residuals(&graph) {
+            let (source, target) = survey.edge_endpoints(edge).unwrap();
+            let (s, t) = (source.index(), target.index());
+            let shot = &survey[edge];
+            assert_eq!(rx, solution.x[t] - solution.x[s] - shot.dx);
+            assert_eq!(ry, solution.y[t] - solution.y[s] - shot.dy);
+        }
+    }
+
+    #[test]
+    fn exports_adjusted_coordinates_and_residuals() {
+        let mut graph = convert(&survey());
+        graph.names = Some(["A", "B", "C", "D"].map(String::from).to_vec());
+        let solution = graph.solve(1000, 1e-12).unwrap();
+        let adjusted = solution.to_petgraph(&graph);
+
+        assert_eq!(adjusted.node_count(), 4);
+        assert_eq!(
+            adjusted[NodeIndex::new(2)],
+            AdjustedStation {
+                x: solution.x[2],
+                y: solution.y[2],
+                z: 0.0,
+                fixed: false,
+                name: Some("C".to_string()),
+            }
+        );
+        let residuals = solution.residuals(&graph);
+        for edge in adjusted.edge_indices() {
+            let e = edge.index();
+            let (source, target) = adjusted.edge_endpoints(edge).unwrap();
+            assert_eq!(
+                (source.index(), target.index()),
+                (graph.from[e], graph.to[e])
+            );
+            assert_eq!(adjusted[edge].residual, residuals[e]);
+            assert_eq!(adjusted[edge].weight, graph.weight[e]);
+        }
+
+        // Round trip back through `from_petgraph` reproduces the adjusted network.
+        let again = Graph::from_petgraph(
+            &adjusted,
+            |s| (s.x, s.y, s.fixed),
+            |s| (s.dx, s.dy, s.weight),
+        );
+        assert_eq!((&again.from, &again.to), (&graph.from, &graph.to));
+        assert_eq!((&again.x, &again.y), (&solution.x, &solution.y));
+    }
+}
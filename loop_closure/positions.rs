@@ -0,0 +1,389 @@
+//! Observations of the absolute position of a station, such as in-cave GPS or radio-location
+//! fixes: unlike an anchor, a position observation has an uncertainty and is weighed against
+//! the shots.
+//!
+//! An observation `(vertex, x, y, weight)` adds `weight * ((x_v - x)² + (y_v - y)²)` to the
+//! objective, i.e. `weight` to the diagonal of the normal equations of the vertex and
+//! `weight * x` / `weight * y` to the right-hand sides: an edge to a fixed station at the
+//! observed position. A vertex may be observed several times, and with nothing else pulling
+//! it settles at the weighted mean of its observations. A network held by position
+//! observations only is no longer free, see [`crate::SolveStats::free_networks`].
+//!
+//! Observations of fixed vertices are ignored by the solve, as are those of free vertices
+//! without an enabled edge, which stay where they are; their residuals are still reported.
+
+use crate::{
+    COMPASS_ERR_BUFFER_TOO_SMALL, COMPASS_ERR_INVALID_ARGUMENT, COMPASS_OK, Graph, GraphContext,
+    NormalEquations, NullSpace, Solution, SolveError,
+};
+use std::ffi::{c_double, c_int};
+use std::slice;
+
+/// Tag of the residual of an edge in [`graph_observation_residuals`].
+pub const COMPASS_OBSERVATION_EDGE: c_int = 0;
+/// Tag of the residual of a [`PositionObservation`] in [`graph_observation_residuals`].
+pub const COMPASS_OBSERVATION_POSITION: c_int = 1;
+
+/// Observed absolute position of a vertex, see the [module documentation](self).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PositionObservation {
+    pub vertex: usize,
+    /// Observed X coordinate.
+    pub x: f64,
+    /// Observed Y coordinate.
+    pub y: f64,
+    /// Weight of the observation against the shots, typically 1/variance. 0 leaves it out.
+    pub weight: f64,
+}
+
+/// Kind of observation a residual belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObservationKind {
+    /// An edge, by edge index.
+    Edge,
+    /// A position observation, by index in the observations.
+    Position,
+}
+
+impl ObservationKind {
+    /// Tag for the FFI code, [`COMPASS_OBSERVATION_EDGE`] or [`COMPASS_OBSERVATION_POSITION`].
+    pub fn code(self) -> c_int {
+        match self {
+            ObservationKind::Edge => COMPASS_OBSERVATION_EDGE,
+            ObservationKind::Position => COMPASS_OBSERVATION_POSITION,
+        }
+    }
+}
+
+/// Residual of one observation, adjusted minus observed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ObservationResidual {
+    pub kind: ObservationKind,
+    /// Edge index or index of the position observation.
+    pub index: usize,
+    pub rx: f64,
+    pub ry: f64,
+}
+
+/// Checks `observations` against a graph of `num_vertices` vertices.
+fn validate(observations: &[PositionObservation], num_vertices: usize) -> Result<(), SolveError> {
+    let invalid = |k: usize| SolveError {
+        code: COMPASS_ERR_INVALID_ARGUMENT,
+        message: format!("invalid position observation {k}"),
+    };
+    for (k, o) in observations.iter().enumerate() {
+        let valid = o.vertex < num_vertices
+            && o.x.is_finite()
+            && o.y.is_finite()
+            && o.weight.is_finite()
+            && o.weight >= 0.0;
+        if !valid {
+            return Err(invalid(k));
+        }
+    }
+    Ok(())
+}
+
+/// Adds `observations` to `system`, the normal equations of the graph they observe. Their
+/// vertices have a diagonal cell whenever they have a row, see [`crate::free_mapping`].
+pub(crate) fn add_to(system: &mut NormalEquations, observations: &[PositionObservation]) {
+    let mut changed = false;
+    for o in observations.iter().filter(|o| o.weight > 0.0) {
+        let Some(i) = system.mapping[o.vertex] else {
+            continue;
+        };
+        let (offsets, columns) = (system.matrix.row_offsets(), system.matrix.col_indices());
+        let Ok(k) = columns[offsets[i]..offsets[i + 1]].binary_search(&i) else {
+            unreachable!("rows have a diagonal cell");
+        };
+        let k = offsets[i] + k;
+        system.matrix.values_mut()[k] += o.weight;
+        system.bx[i] += o.weight * o.x;
+        system.by[i] += o.weight * o.y;
+        changed = true;
+    }
+    if changed {
+        // The observations anchor the free networks they touch, and the cached
+        // preconditioner was built from the previous values.
+        system.null_space = NullSpace::of(&system.matrix);
+        *system
+            .preconditioner
+            .get_mut()
+            .unwrap_or_else(|e| e.into_inner()) = None;
+    }
+}
+
+impl Graph {
+    /// Same as [`Graph::solve`], weighing the `observations` of absolute positions against
+    /// the edges. Fails with [`COMPASS_ERR_INVALID_ARGUMENT`] for an observation of a vertex
+    /// out of range, at a non-finite position, or with a negative or non-finite weight.
+    pub fn solve_positions(
+        &self,
+        observations: &[PositionObservation],
+        iterations: usize,
+        tolerance: f64,
+    ) -> Result<Solution, SolveError> {
+        validate(observations, self.num_vertices())?;
+        let mut system = self.normal_equations(&[])?;
+        add_to(&mut system, observations);
+        self.solve_system(
+            &system,
+            &self.x,
+            &self.y,
+            iterations,
+            tolerance.into(),
+            Default::default(),
+            &|| false,
+        )
+    }
+}
+
+impl Solution {
+    /// Residual `(rx, ry)` of each of `observations`, adjusted minus observed position.
+    pub fn position_residuals(&self, observations: &[PositionObservation]) -> Vec<(f64, f64)> {
+        observations
+            .iter()
+            .map(|o| (self.x[o.vertex] - o.x, self.y[o.vertex] - o.y))
+            .collect()
+    }
+}
+
+impl GraphContext {
+    /// Replaces the position observations weighed by the next solves, see
+    /// [`Graph::solve_positions`]. The normal equations are refilled.
+    pub fn set_position_observations(
+        &mut self,
+        observations: Vec<PositionObservation>,
+    ) -> Result<(), SolveError> {
+        validate(&observations, self.graph.num_vertices())?;
+        if self.positions != observations {
+            self.positions = observations;
+            self.refill();
+        }
+        Ok(())
+    }
+
+    /// Position observations set by [`GraphContext::set_position_observations`].
+    pub fn position_observations(&self) -> &[PositionObservation] {
+        &self.positions
+    }
+
+    /// Residuals at the current [`GraphContext::coordinates`] of every edge, disabled ones
+    /// included, then of every position observation.
+    pub fn observation_residuals(&self) -> Vec<ObservationResidual> {
+        let (x, y) = self.coordinates();
+        let graph = &self.graph;
+        let edges = (0..graph.num_edges()).map(|e| {
+            let (u, v) = (graph.from[e], graph.to[e]);
+            ObservationResidual {
+                kind: ObservationKind::Edge,
+                index: e,
+                rx: x[v] - x[u] - graph.dx[e],
+                ry: y[v] - y[u] - graph.dy[e],
+            }
+        });
+        let positions = self
+            .positions
+            .iter()
+            .enumerate()
+            .map(|(k, o)| ObservationResidual {
+                kind: ObservationKind::Position,
+                index: k,
+                rx: x[o.vertex] - o.x,
+                ry: y[o.vertex] - o.y,
+            });
+        edges.chain(positions).collect()
+    }
+}
+
+/// Sets the position observations of the graph behind `handle` from `count` entries of the
+/// parallel arrays `vertices`, `x`, `y` and `weights`, replacing any previous ones; `count` 0
+/// clears them. See [`GraphContext::set_position_observations`].
+///
+/// Returns [`COMPASS_ERR_INVALID_ARGUMENT`] for a null handle, a negative `count`, a null
+/// array with a positive `count`, or an invalid observation, leaving the previous ones.
+#[unsafe(no_mangle)]
+pub extern "C" fn graph_set_position_observations(
+    handle: *mut GraphContext,
+    count: c_int,
+    vertices: *const c_int,
+    x: *const c_double,
+    y: *const c_double,
+    weights: *const c_double,
+) -> c_int {
+    let Some(ctx) = (unsafe { handle.as_mut() }) else {
+        return COMPASS_ERR_INVALID_ARGUMENT;
+    };
+    let Ok(count) = usize::try_from(count) else {
+        return COMPASS_ERR_INVALID_ARGUMENT;
+    };
+    let observations = if count == 0 {
+        Vec::new()
+    } else {
+        if vertices.is_null() || x.is_null() || y.is_null() || weights.is_null() {
+            return COMPASS_ERR_INVALID_ARGUMENT;
+        }
+        // Safety: The caller guarantees `count` elements in each array.
+        let (vertices, x, y, weights) = unsafe {
+            (
+                slice::from_raw_parts(vertices, count),
+                slice::from_raw_parts(x, count),
+                slice::from_raw_parts(y, count),
+                slice::from_raw_parts(weights, count),
+            )
+        };
+        let mut observations = Vec::with_capacity(count);
+        for k in 0..count {
+            let Ok(vertex) = usize::try_from(vertices[k]) else {
+                return COMPASS_ERR_INVALID_ARGUMENT;
+            };
+            observations.push(PositionObservation {
+                vertex,
+                x: x[k],
+                y: y[k],
+                weight: weights[k],
+            });
+        }
+        observations
+    };
+    match ctx.set_position_observations(observations) {
+        Ok(()) => COMPASS_OK,
+        Err(err) => err.code,
+    }
+}
+
+/// Copies the residuals of the graph behind `handle`, see
+/// [`GraphContext::observation_residuals`], into caller buffers of `cap` entries: the tag of
+/// each residual ([`COMPASS_OBSERVATION_EDGE`] or [`COMPASS_OBSERVATION_POSITION`]) into
+/// `out_kind`, the index of its edge or position observation into `out_index`, and the
+/// residual into `out_rx` / `out_ry`. The number of residuals, [`crate::graph_num_edges`] plus
+/// the number of position observations, is written to `out_count` either way, so that a first
+/// call with `cap` 0 sizes the buffers. Any output pointer may be null to skip it.
+///
+/// # Returns
+///
+/// * [`COMPASS_OK`].
+/// * [`COMPASS_ERR_BUFFER_TOO_SMALL`] if `cap` is below the number of residuals; nothing is
+///   written to the buffers.
+/// * [`COMPASS_ERR_INVALID_ARGUMENT`] for a null handle.
+#[unsafe(no_mangle)]
+pub extern "C" fn graph_observation_residuals(
+    handle: *const GraphContext,
+    out_kind: *mut c_int,
+    out_index: *mut c_int,
+    out_rx: *mut c_double,
+    out_ry: *mut c_double,
+    cap: c_int,
+    out_count: *mut c_int,
+) -> c_int {
+    let Some(ctx) = (unsafe { handle.as_ref() }) else {
+        return COMPASS_ERR_INVALID_ARGUMENT;
+    };
+    let residuals = ctx.observation_residuals();
+    if !out_count.is_null() {
+        unsafe { *out_count = residuals.len() as c_int };
+    }
+    if (cap.max(0) as usize) < residuals.len() {
+        return COMPASS_ERR_BUFFER_TOO_SMALL;
+    }
+    for (k, r) in residuals.iter().enumerate() {
+        // Safety: The caller guarantees buffers of `cap` elements.
+        unsafe {
+            if !out_kind.is_null() {
+                *out_kind.add(k) = r.kind.code();
+            }
+            if !out_index.is_null() {
+                *out_index.add(k) = r.index as c_int;
+            }
+            if !out_rx.is_null() {
+                *out_rx.add(k) = r.rx;
+            }
+            if !out_ry.is_null() {
+                *out_ry.add(k) = r.ry;
+            }
+        }
+    }
+    COMPASS_OK
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{SolveOptions, SolveStats, graph_free, graph_solve};
+
+    /// A free shot 0-1 of (5, 0), with no anchor.
+    fn free_shot() -> Graph {
+        let mut graph = Graph::default();
+        graph.add_vertex(1.0, 1.0, 0.0, false);
+        graph.add_vertex(6.0, 1.0, 0.0, false);
+        graph.add_edge(0, 1, 5.0, 0.0, 0.0, 1.0);
+        graph
+    }
+
+    #[test]
+    fn conflicting_fixes_settle_at_their_weighted_mean() {
+        let fixes = [
+            PositionObservation {
+                vertex: 0,
+                x: 0.0,
+                y: 0.0,
+                weight: 1.0,
+            },
+            PositionObservation {
+                vertex: 0,
+                x: 10.0,
+                y: 20.0,
+                weight: 3.0,
+            },
+        ];
+        let graph = free_shot();
+        let solution = graph.solve_positions(&fixes, 1000, 1e-14).unwrap();
+        assert_eq!(solution.stats.free_networks, 0);
+        assert!((solution.x[0] - 7.5).abs() < 1e-9 && (solution.y[0] - 15.0).abs() < 1e-9);
+        // The shot has no redundancy, so it takes no residual.
+        assert!((solution.x[1] - 12.5).abs() < 1e-9 && (solution.y[1] - 15.0).abs() < 1e-9);
+        let residuals = solution.position_residuals(&fixes);
+        assert!((residuals[0].0 - 7.5).abs() < 1e-9 && (residuals[1].1 + 5.0).abs() < 1e-9);
+
+        // The same through the handle, with the residuals tagged after those of the edges.
+        let handle = GraphContext::into_raw(graph);
+        let vertices = [0, 0];
+        let (x, y, w) = ([0.0, 10.0], [0.0, 20.0], [1.0, 3.0]);
+        let set = |count, w: &[f64]| {
+            let (v, x, y) = (vertices.as_ptr(), x.as_ptr(), y.as_ptr());
+            graph_set_position_observations(handle, count, v, x, y, w.as_ptr())
+        };
+        assert_eq!(set(2, &[1.0, -3.0]), COMPASS_ERR_INVALID_ARGUMENT);
+        assert_eq!(set(2, &w), COMPASS_OK);
+        let options = SolveOptions {
+            tolerance: 1e-14,
+            ..SolveOptions::default()
+        };
+        let mut stats = SolveStats {
+            struct_size: size_of::<SolveStats>(),
+            ..SolveStats::default()
+        };
+        assert_eq!(graph_solve(handle, &options, &mut stats), COMPASS_OK);
+        let ctx = unsafe { &*handle };
+        assert_eq!(ctx.coordinates().0, &solution.x[..]);
+
+        let mut count = -1;
+        let (mut kind, mut index) = ([-1; 3], [-1; 3]);
+        let (mut rx, mut ry) = ([0.0; 3], [0.0; 3]);
+        let mut residuals = |cap| {
+            let (k, i) = (kind.as_mut_ptr(), index.as_mut_ptr());
+            let (x, y) = (rx.as_mut_ptr(), ry.as_mut_ptr());
+            graph_observation_residuals(handle, k, i, x, y, cap, &mut count)
+        };
+        assert_eq!(residuals(2), COMPASS_ERR_BUFFER_TOO_SMALL);
+        assert_eq!(residuals(3), COMPASS_OK);
+        assert_eq!(count, 3);
+        let position = COMPASS_OBSERVATION_POSITION;
+        assert_eq!(kind, [COMPASS_OBSERVATION_EDGE, position, position]);
+        assert_eq!(index, [0, 0, 1]);
+        assert!(rx[0].abs() < 1e-9 && ry[0].abs() < 1e-9);
+        let weighted = (rx[1] + 3.0 * rx[2], ry[1] + 3.0 * ry[2]);
+        assert!(weighted.0.abs() < 1e-9 && weighted.1.abs() < 1e-9);
+        graph_free(handle);
+    }
+}
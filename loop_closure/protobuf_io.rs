@@ -0,0 +1,542 @@
+//! Protocol Buffers solve entrypoint for services that prefer a single versioned message over
+//! a dozen pointers. The schema, with its evolution rules, is `solve.proto` next to this file;
+//! the message types below are its hand-maintained prost mapping and must be kept in sync.
+//!
+//! [`solve_from_protobuf`] follows a two-call pattern: a first call with a null or too small
+//! output buffer reports the response size through `out_len`, a second call with a buffer of
+//! that size fetches it.
+
+use crate::{
+    COMPASS_ERR_BUFFER_TOO_SMALL, COMPASS_ERR_INVALID_ARGUMENT, COMPASS_ERR_PANIC, COMPASS_OK,
+    Graph, SolveError,
+};
+use prost::Message;
+use std::ffi::c_int;
+use std::slice;
+
+/// Default maximum number of CG iterations when the request omits it.
+const DEFAULT_ITERATIONS: u32 = 60_000;
+/// Default CG tolerance when the request omits it.
+const DEFAULT_TOLERANCE: f64 = 1e-8;
+
+/// `compass.loop_closure.v1.SolveRequest`.
+#[derive(Clone, PartialEq, Message)]
+pub struct SolveRequest {
+    #[prost(message, optional, tag = "1")]
+    pub graph: Option<GraphData>,
+    #[prost(message, optional, tag = "2")]
+    pub options: Option<Options>,
+}
+
+/// `compass.loop_closure.v1.GraphData`.
+#[derive(Clone, PartialEq, Message)]
+pub struct GraphData {
+    #[prost(double, repeated, tag = "1")]
+    pub x: Vec<f64>,
+    #[prost(double, repeated, tag = "2")]
+    pub y: Vec<f64>,
+    #[prost(double, repeated, tag = "3")]
+    pub z: Vec<f64>,
+    #[prost(bool, repeated, tag = "4")]
+    pub fixed: Vec<bool>,
+    #[prost(uint32, repeated, tag = "5")]
+    pub from: Vec<u32>,
+    #[prost(uint32, repeated, tag = "6")]
+    pub to: Vec<u32>,
+    #[prost(double, repeated, tag = "7")]
+    pub dx: Vec<f64>,
+    #[prost(double, repeated, tag = "8")]
+    pub dy: Vec<f64>,
+    #[prost(double, repeated, tag = "9")]
+    pub dz: Vec<f64>,
+    #[prost(double, repeated, tag = "10")]
+    pub weight: Vec<f64>,
+    #[prost(string, repeated, tag = "11")]
+    pub names: Vec<String>,
+}
+
+/// `compass.loop_closure.v1.Options`.
+#[derive(Clone, PartialEq, Message)]
+pub struct Options {
+    #[prost(uint32, optional, tag = "1")]
+    pub iterations: Option<u32>,
+    #[prost(double, optional, tag = "2")]
+    pub tolerance: Option<f64>,
+}
+
+/// `compass.loop_closure.v1.SolveResponse`.
+#[derive(Clone, PartialEq, Message)]
+pub struct SolveResponse {
+    #[prost(double, repeated, tag = "1")]
+    pub x: Vec<f64>,
+    #[prost(double, repeated, tag = "2")]
+    pub y: Vec<f64>,
+    #[prost(message, optional, tag = "3")]
+    pub stats: Option<Stats>,
+    #[prost(double, repeated, tag = "4")]
+    pub residual_dx: Vec<f64>,
+    #[prost(double, repeated, tag = "5")]
+    pub residual_dy: Vec<f64>,
+    #[prost(double, repeated, tag = "6")]
+    pub standardized_residual: Vec<f64>,
+}
+
+/// `compass.loop_closure.v1.Stats`.
+#[derive(Clone, PartialEq, Message)]
+pub struct Stats {
+    #[prost(int32, tag = "1")]
+    pub free_vertices: i32,
+    #[prost(int32, tag = "2")]
+    pub passive_vertices: i32,
+}
+
+/// Solves the graph of an encoded `SolveRequest` and returns the encoded `SolveResponse`.
+///
+/// Malformed messages, inconsistent array lengths and out-of-range edge endpoints are
+/// reported as [`COMPASS_ERR_INVALID_ARGUMENT`].
+pub fn solve_protobuf(input: &[u8]) -> Result<Vec<u8>, SolveError> {
+    let invalid = |message: String| SolveError {
+        code: COMPASS_ERR_INVALID_ARGUMENT,
+        message,
+    };
+    let request =
+        SolveRequest::decode(input).map_err(|err| invalid(format!("invalid request: {err}")))?;
+    let data = request.graph.unwrap_or_default();
+    let options = request.options.unwrap_or_default();
+
+    let n = data.x.len();
+    let m = data.from.len();
+    let optional = |len: usize, expected: usize| len == 0 || len == expected;
+    if data.y.len() != n
+        || data.fixed.len() != n
+        || !optional(data.z.len(), n)
+        || !optional(data.names.len(), n)
+    {
+        return Err(invalid(
+            "vertex arrays have inconsistent lengths".to_string(),
+        ));
+    }
+    let edge_lengths = [
+        data.to.len(),
+        data.dx.len(),
+        data.dy.len(),
+        data.weight.len(),
+    ];
+    if edge_lengths.iter().any(|&len| len != m) || !optional(data.dz.len(), m) {
+        return Err(invalid("edge arrays have inconsistent lengths".to_string()));
+    }
+    if let Some(e) = (0..m).find(|&e| data.from[e] as usize >= n || data.to[e] as usize >= n) {
+        return Err(invalid(format!("edge {e} references a missing vertex")));
+    }
+
+    let mut graph = Graph::default();
+    for i in 0..n {
+        let z = data.z.get(i).copied().unwrap_or(0.0);
+        graph.add_vertex(data.x[i], data.y[i], z, data.fixed[i]);
+    }
+    for e in 0..m {
+        let dz = data.dz.get(e).copied().unwrap_or(0.0);
+        graph.add_edge(
+            data.from[e] as usize,
+            data.to[e] as usize,
+            data.dx[e],
+            data.dy[e],
+            dz,
+            data.weight[e],
+        );
+    }
+    if !data.names.is_empty() {
+        graph.names = Some(data.names);
+    }
+
+    let solution = graph.solve(
+        options.iterations.unwrap_or(DEFAULT_ITERATIONS) as usize,
+        options.tolerance.unwrap_or(DEFAULT_TOLERANCE),
+    )?;
+    let (residual_dx, residual_dy) = solution.residuals(&graph).into_iter().unzip();
+    let response = SolveResponse {
+        standardized_residual: solution.standardized_residuals(&graph),
+        x: solution.x,
+        y: solution.y,
+        stats: Some(Stats {
+            free_vertices: solution.stats.free_vertices,
+            passive_vertices: solution.stats.passive_vertices,
+        }),
+        residual_dx,
+        residual_dy,
+    };
+    Ok(response.encode_to_vec())
+}
+
+/// Solves the graph of the encoded `SolveRequest` in `buf` and writes the encoded
+/// `SolveResponse` into `out_buf`.
+///
+/// # Arguments
+///
+/// * `buf` / `len` - The encoded request.
+/// * `out_buf` - Buffer receiving the response on success, or the UTF-8 error message
+///   (truncated to `out_cap`, not NUL-terminated) on failure. May be null when `out_cap` is 0.
+/// * `out_cap` - Capacity of `out_buf` in bytes.
+/// * `out_len` - Receives the response size on success and on
+///   [`COMPASS_ERR_BUFFER_TOO_SMALL`], or the message length on failure. May be null.
+///
+/// # Returns
+///
+/// * [`COMPASS_OK`] on success.
+/// * [`COMPASS_ERR_BUFFER_TOO_SMALL`] if the response does not fit; `out_buf` is left
+///   untouched and the call can be repeated with a buffer of `*out_len` bytes.
+/// * The [`SolveError`] code otherwise.
+#[unsafe(no_mangle)]
+pub extern "C" fn solve_from_protobuf(
+    buf: *const u8,
+    len: usize,
+    out_buf: *mut u8,
+    out_cap: usize,
+    out_len: *mut usize,
+) -> c_int {
+    let result = std::panic::catch_unwind(|| {
+        if buf.is_null() && len > 0 || out_buf.is_null() && out_cap > 0 {
+            return COMPASS_ERR_INVALID_ARGUMENT;
+        }
+        let input = match len {
+            0 => &[][..],
+            // Safety: The caller guarantees `len` readable bytes at `buf`.
+            _ => unsafe { slice::from_raw_parts(buf, len) },
+        };
+        let (code, bytes) = match solve_protobuf(input) {
+            Ok(response) if response.len() > out_cap => {
+                set_len(out_len, response.len());
+                return COMPASS_ERR_BUFFER_TOO_SMALL;
+            }
+            Ok(response) => (COMPASS_OK, response),
+            Err(err) => {
+                let mut message = err.message.into_bytes();
+                message.truncate(out_cap);
+                (err.code, message)
+            }
+        };
+        if !bytes.is_empty() {
+            // Safety: The caller guarantees `out_cap` writable bytes at `out_buf`.
+            unsafe { std::ptr::copy_nonoverlapping(bytes.as_ptr(), out_buf, bytes.len()) };
+        }
+        set_len(out_len, bytes.len());
+        code
+    });
+
+    match result {
+        Ok(code) => code,
+        Err(_) => {
+            eprintln!("Panic caught in solve_from_protobuf");
+            COMPASS_ERR_PANIC
+        }
+    }
+}
+
+fn set_len(out_len: *mut usize, len: usize) {
+    if !out_len.is_null() {
+        // Safety: The caller guarantees a valid pointer when non-null.
+        unsafe { *out_len = len };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn triangle() -> SolveRequest {
+        SolveRequest {
+            graph: Some(GraphData {
+                x: vec![0.0, 9.8, 10.0],
+                y: vec![0.0, 0.3, 10.0],
+                z: vec![5.0, 0.0, 0.0],
+                fixed: vec![true, false, false],
+                from: vec![0, 1, 2],
+                to: vec![1, 2, 0],
+                dx: vec![10.0, 0.0, -10.03],
+                dy: vec![0.0, 10.0, -9.98],
+                dz: vec![],
+                weight: vec![1.0, 1.0, 2.0],
+                names: vec!["A1".to_string(), "A2".to_string(), "A3".to_string()],
+            }),
+            options: Some(Options {
+                iterations: Some(1000),
+                tolerance: Some(1e-12),
+            }),
+        }
+    }
+
+    fn solve(request: &SolveRequest) -> Result<SolveResponse, SolveError> {
+        let output = solve_protobuf(&request.encode_to_vec())?;
+        Ok(SolveResponse::decode(&output[..]).unwrap())
+    }
+
+    #[test]
+    fn response_matches_the_library_solve() {
+        let response = solve(&triangle()).unwrap();
+
+        let mut graph = Graph::default();
+        graph.add_vertex(0.0, 0.0, 5.0, true);
+        graph.add_vertex(9.8, 0.3, 0.0, false);
+        graph.add_vertex(10.0, 10.0, 0.0, false);
+        graph.add_edge(0, 1, 10.0, 0.0, 0.0, 1.0);
+        graph.add_edge(1, 2, 0.0, 10.0, 0.0, 1.0);
+        graph.add_edge(2, 0, -10.03, -9.98, 0.0, 2.0);
+        let solution = graph.solve(1000, 1e-12).unwrap();
+        let (residual_dx, residual_dy): (Vec<f64>, Vec<f64>) =
+            solution.residuals(&graph).into_iter().unzip();
+
+        assert_eq!(response.x, solution.x);
+        assert_eq!(response.y, solution.y);
+        assert_eq!(response.residual_dx, residual_dx);
+        assert_eq!(response.residual_dy, residual_dy);
+        assert_eq!(
+            response.standardized_residual,
+            solution.standardized_residuals(&graph)
+        );
+        assert_eq!(
+            response.stats,
+            Some(Stats {
+                free_vertices: 2,
+                passive_vertices: 0,
+            })
+        );
+    }
+
+    #[test]
+    fn request_round_trips_through_the_wire_format() {
+        let request = triangle();
+        let bytes = request.encode_to_vec();
+        assert_eq!(SolveRequest::decode(&bytes[..]).unwrap(), request);
+    }
+
+    #[test]
+    fn unknown_fields_are_ignored_and_absent_options_use_the_defaults() {
+        let mut request = triangle();
+        request.options = None;
+        let mut bytes = request.encode_to_vec();
+        // Field 14 (varint) and field 15 (length-delimited) from a newer client.
+        bytes.extend_from_slice(&[14 << 3, 42, 15 << 3 | 2, 0x81, 0x01]);
+        bytes.extend(std::iter::repeat_n(7, 0x81));
+        let newer = solve_protobuf(&bytes).unwrap();
+
+        request.options = Some(Options {
+            iterations: Some(DEFAULT_ITERATIONS),
+            tolerance: Some(DEFAULT_TOLERANCE),
+        });
+        assert_eq!(newer, solve_protobuf(&request.encode_to_vec()).unwrap());
+    }
+
+    #[test]
+    fn rejects_invalid_requests() {
+        let message = |edit: fn(&mut GraphData)| {
+            let mut request = triangle();
+            edit(request.graph.as_mut().unwrap());
+            let err = solve(&request).unwrap_err();
+            assert_eq!(err.code, COMPASS_ERR_INVALID_ARGUMENT);
+            err.message
+        };
+        assert_eq!(
+            message(|g| g.fixed.truncate(2)),
+            "vertex arrays have inconsistent lengths"
+        );
+        assert_eq!(
+            message(|g| g.names.truncate(1)),
+            "vertex arrays have inconsistent lengths"
+        );
+        assert_eq!(
+            message(|g| g.dz = vec![0.0]),
+            "edge arrays have inconsistent lengths"
+        );
+        assert_eq!(
+            message(|g| g.to[1] = 3),
+            "edge 1 references a missing vertex"
+        );
+
+        let err = solve_protobuf(&[0x0a, 0x05, 0x01]).unwrap_err();
+        assert_eq!(err.code, COMPASS_ERR_INVALID_ARGUMENT);
+        assert!(err.message.starts_with("invalid request: "));
+    }
+
+    #[test]
+    fn ffi_uses_the_two_call_pattern() {
+        let request = triangle().encode_to_vec();
+        let expected = solve_protobuf(&request).unwrap();
+
+        let mut len = 0;
+        let code = solve_from_protobuf(
+            request.as_ptr(),
+            request.len(),
+            std::ptr::null_mut(),
+            0,
+            &mut len,
+        );
+        assert_eq!((code, len), (COMPASS_ERR_BUFFER_TOO_SMALL, expected.len()));
+
+        let mut out = vec![0u8; len];
+        let code = solve_from_protobuf(
+            request.as_ptr(),
+            request.len(),
+            out.as_mut_ptr(),
+            out.len(),
+            &mut len,
+        );
+        assert_eq!(code, COMPASS_OK);
+        assert_eq!(out, expected);
+
+        let mut out = [0u8; 8];
+        let code = solve_from_protobuf(
+            [0x0a, 0x05].as_ptr(),
+            2,
+            out.as_mut_ptr(),
+            out.len(),
+            &mut len,
+        );
+        assert_eq!((code, len), (COMPASS_ERR_INVALID_ARGUMENT, 8));
+        assert_eq!(&out, b"invalid ");
+
+        let code = solve_from_protobuf(std::ptr::null(), 1, std::ptr::null_mut(), 0, &mut len);
+        assert_eq!(code, COMPASS_ERR_INVALID_ARGUMENT);
+    }
+
+    /// Field numbers declared in `solve.proto`, keyed by message and field name.
+    fn schema() -> HashMap<(String, String), u32> {
+        let mut fields = HashMap::new();
+        let mut message = String::new();
+        for line in include_str!("solve.proto").lines().map(str::trim) {
+            if let Some(rest) = line.strip_prefix("message ") {
+                message = rest.trim_end_matches(" {").to_string();
+            } else if line == "}" {
+                message.clear();
+            } else if let Some((declaration, number)) = line
+                .split_once(" = ")
+                .filter(|_| !message.is_empty() && !line.starts_with("//"))
+            {
+                let name = declaration.split_whitespace().last().unwrap();
+                let number = number.trim_end_matches(';').parse().unwrap();
+                fields.insert((message.clone(), name.to_string()), number);
+            }
+        }
+        fields
+    }
+
+    /// Field number of the first key of an encoded message with a single field set.
+    fn tag(bytes: Vec<u8>) -> u32 {
+        u32::from(bytes[0] >> 3)
+    }
+
+    #[test]
+    fn prost_mapping_matches_the_schema() {
+        let graph = |edit: fn(&mut GraphData)| {
+            let mut data = GraphData::default();
+            edit(&mut data);
+            tag(data.encode_to_vec())
+        };
+        let response = |edit: fn(&mut SolveResponse)| {
+            let mut data = SolveResponse::default();
+            edit(&mut data);
+            tag(data.encode_to_vec())
+        };
+        let some_options = Some(Options::default());
+        let encoded = [
+            (
+                "SolveRequest",
+                "graph",
+                tag(SolveRequest {
+                    graph: Some(GraphData::default()),
+                    options: None,
+                }
+                .encode_to_vec()),
+            ),
+            (
+                "SolveRequest",
+                "options",
+                tag(SolveRequest {
+                    graph: None,
+                    options: some_options,
+                }
+                .encode_to_vec()),
+            ),
+            ("GraphData", "x", graph(|g| g.x = vec![1.0])),
+            ("GraphData", "y", graph(|g| g.y = vec![1.0])),
+            ("GraphData", "z", graph(|g| g.z = vec![1.0])),
+            ("GraphData", "fixed", graph(|g| g.fixed = vec![true])),
+            ("GraphData", "from", graph(|g| g.from = vec![1])),
+            ("GraphData", "to", graph(|g| g.to = vec![1])),
+            ("GraphData", "dx", graph(|g| g.dx = vec![1.0])),
+            ("GraphData", "dy", graph(|g| g.dy = vec![1.0])),
+            ("GraphData", "dz", graph(|g| g.dz = vec![1.0])),
+            ("GraphData", "weight", graph(|g| g.weight = vec![1.0])),
+            (
+                "GraphData",
+                "names",
+                graph(|g| g.names = vec![String::new()]),
+            ),
+            (
+                "Options",
+                "iterations",
+                tag(Options {
+                    iterations: Some(0),
+                    tolerance: None,
+                }
+                .encode_to_vec()),
+            ),
+            (
+                "Options",
+                "tolerance",
+                tag(Options {
+                    iterations: None,
+                    tolerance: Some(0.0),
+                }
+                .encode_to_vec()),
+            ),
+            ("SolveResponse", "x", response(|r| r.x = vec![1.0])),
+            ("SolveResponse", "y", response(|r| r.y = vec![1.0])),
+            (
+                "SolveResponse",
+                "stats",
+                response(|r| r.stats = Some(Stats::default())),
+            ),
+            (
+                "SolveResponse",
+                "residual_dx",
+                response(|r| r.residual_dx = vec![1.0]),
+            ),
+            (
+                "SolveResponse",
+                "residual_dy",
+                response(|r| r.residual_dy = vec![1.0]),
+            ),
+            (
+                "SolveResponse",
+                "standardized_residual",
+                response(|r| r.standardized_residual = vec![1.0]),
+            ),
+            (
+                "Stats",
+                "free_vertices",
+                tag(Stats {
+                    free_vertices: 1,
+                    passive_vertices: 0,
+                }
+                .encode_to_vec()),
+            ),
+            (
+                "Stats",
+                "passive_vertices",
+                tag(Stats {
+                    free_vertices: 0,
+                    passive_vertices: 1,
+                }
+                .encode_to_vec()),
+            ),
+        ];
+
+        let schema = schema();
+        assert_eq!(schema.len(), encoded.len());
+        for (message, field, number) in encoded {
+            let key = (message.to_string(), field.to_string());
+            assert_eq!(schema.get(&key), Some(&number), "{message}.{field}");
+        }
+    }
+}
@@ -0,0 +1,167 @@
+//! Python bindings over the safe API (PyO3 + numpy), built with maturin:
+//!
+//! ```text
+//! maturin develop --features python
+//! ```
+//!
+//! ```python
+//! import compass_loop_closure
+//!
+//! edges = np.column_stack([from_idx, to_idx, dx, dy])
+//! x, y, stats = compass_loop_closure.solve(x, y, fixed, edges, weights, iterations=60000)
+//! print(compass_loop_closure.report(x, y, fixed, edges, weights))
+//! ```
+
+use crate::report::ReportFormat;
+use crate::{Graph, SolveError};
+use numpy::{IntoPyArray, PyArray1, PyReadonlyArray1, PyReadonlyArray2};
+use pyo3::create_exception;
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+
+create_exception!(
+    compass_loop_closure,
+    SolverError,
+    PyRuntimeError,
+    "Raised when the solve fails. `args` is `(message, code)` with the FFI error code."
+);
+
+impl From<SolveError> for PyErr {
+    fn from(err: SolveError) -> PyErr {
+        SolverError::new_err((err.message, err.code))
+    }
+}
+
+/// Solves the horizontal adjustment.
+///
+/// * `x`, `y` - Float arrays of length `n`: fixed values and initial guesses.
+/// * `fixed` - Boolean array of length `n`.
+/// * `edges` - Float array of shape `(m, 4)` with columns `from, to, dx, dy`.
+/// * `weights` - Float array of length `m`.
+///
+/// Returns `(x, y, stats)` with the adjusted coordinates and a dict of solve statistics.
+/// The GIL is released while solving.
+#[pyfunction]
+#[pyo3(signature = (x, y, fixed, edges, weights, *, iterations = 60_000, tolerance = 1e-8))]
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn solve<'py>(
+    py: Python<'py>,
+    x: PyReadonlyArray1<'py, f64>,
+    y: PyReadonlyArray1<'py, f64>,
+    fixed: PyReadonlyArray1<'py, bool>,
+    edges: PyReadonlyArray2<'py, f64>,
+    weights: PyReadonlyArray1<'py, f64>,
+    iterations: usize,
+    tolerance: f64,
+) -> PyResult<(
+    Bound<'py, PyArray1<f64>>,
+    Bound<'py, PyArray1<f64>>,
+    Bound<'py, PyDict>,
+)> {
+    let graph = build_graph(&x, &y, &fixed, &edges, &weights)?;
+    let solution = py.allow_threads(|| graph.solve(iterations, tolerance))?;
+    let stats = PyDict::new(py);
+    stats.set_item("free_vertices", solution.stats.free_vertices)?;
+    stats.set_item("passive_vertices", solution.stats.passive_vertices)?;
+    Ok((
+        solution.x.into_pyarray(py),
+        solution.y.into_pyarray(py),
+        stats,
+    ))
+}
+
+/// Solves the horizontal adjustment like `solve` and returns its adjustment report as a
+/// string, `format` being `"text"` or `"json"`.
+#[pyfunction]
+#[pyo3(signature = (
+    x, y, fixed, edges, weights, *, format = "text", iterations = 60_000, tolerance = 1e-8
+))]
+#[allow(clippy::too_many_arguments)]
+fn report<'py>(
+    py: Python<'py>,
+    x: PyReadonlyArray1<'py, f64>,
+    y: PyReadonlyArray1<'py, f64>,
+    fixed: PyReadonlyArray1<'py, bool>,
+    edges: PyReadonlyArray2<'py, f64>,
+    weights: PyReadonlyArray1<'py, f64>,
+    format: &str,
+    iterations: usize,
+    tolerance: f64,
+) -> PyResult<String> {
+    let format = match format {
+        "text" => ReportFormat::Text,
+        "json" => ReportFormat::Json,
+        _ => {
+            return Err(PyValueError::new_err(format!(
+                "unknown report format {format:?}, expected \"text\" or \"json\""
+            )));
+        }
+    };
+    let graph = build_graph(&x, &y, &fixed, &edges, &weights)?;
+    let solution = py.allow_threads(|| graph.solve(iterations, tolerance))?;
+    Ok(solution.report(&graph, format))
+}
+
+/// Validates the numpy arguments of `solve` and builds the graph.
+fn build_graph(
+    x: &PyReadonlyArray1<'_, f64>,
+    y: &PyReadonlyArray1<'_, f64>,
+    fixed: &PyReadonlyArray1<'_, bool>,
+    edges: &PyReadonlyArray2<'_, f64>,
+    weights: &PyReadonlyArray1<'_, f64>,
+) -> PyResult<Graph> {
+    let (x, y, fixed) = (x.as_array(), y.as_array(), fixed.as_array());
+    let (edges, weights) = (edges.as_array(), weights.as_array());
+    let n = x.len();
+    if y.len() != n || fixed.len() != n {
+        return Err(PyValueError::new_err(
+            "x, y and fixed must have the same length",
+        ));
+    }
+    if edges.ncols() != 4 {
+        return Err(PyValueError::new_err(
+            "edges must have shape (m, 4): from, to, dx, dy",
+        ));
+    }
+    if weights.len() != edges.nrows() {
+        return Err(PyValueError::new_err(
+            "weights must have one entry per edge",
+        ));
+    }
+
+    let mut graph = Graph::default();
+    for i in 0..n {
+        graph.add_vertex(x[i], y[i], 0.0, fixed[i]);
+    }
+    let vertex = |e: usize, column: usize| {
+        let v = edges[[e, column]];
+        if v.fract() == 0.0 && v >= 0.0 && v < n as f64 {
+            Ok(v as usize)
+        } else {
+            Err(PyValueError::new_err(format!(
+                "edge {e} references an invalid vertex: {v}"
+            )))
+        }
+    };
+    for e in 0..edges.nrows() {
+        graph.add_edge(
+            vertex(e, 0)?,
+            vertex(e, 1)?,
+            edges[[e, 2]],
+            edges[[e, 3]],
+            0.0,
+            weights[e],
+        );
+    }
+    Ok(graph)
+}
+
+#[pymodule]
+#[pyo3(name = "compass_loop_closure")]
+fn python_module(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(solve, m)?)?;
+    m.add_function(wrap_pyfunction!(report, m)?)?;
+    m.add("SolverError", m.py().get_type::<SolverError>())?;
+    Ok(())
+}
@@ -0,0 +1,336 @@
+//! Reliability of single observations: redundancy numbers and the standard deviations of
+//! the adjusted observations.
+//!
+//! The adjusted observation of an edge is the coordinate difference `a·x` of its ends, with
+//! cofactor `q = aᵀ N⁻¹ a` over the unknowns of its free ends. Its a-posteriori standard
+//! deviation is `σ₀ √q` per axis, `σ₀²` being the variance factor of that axis from the
+//! residuals, and its redundancy number `1 - w q` is the share of an error in the
+//! observation that shows in its residual: near 0 the observation is uncontrolled, near 1
+//! fully checked by the others.
+//!
+//! Each edge costs one solve `N z = a`, with the Jacobi-preconditioned Conjugate Gradient of
+//! the variance components, so the edges are requested explicitly to keep the cost bounded
+//! on large networks. Set [`AdjustmentReport::reliability`] to list them in the report.
+//!
+//! [`AdjustmentReport::reliability`]: crate::report::AdjustmentReport::reliability
+
+use crate::{
+    COMPASS_ERR_INVALID_ARGUMENT, COMPASS_ERR_PANIC, COMPASS_OK, Graph, GraphContext,
+    NormalEquations, PreconditionerOp, Solution, SolveError, inverse_diagonal, solve_cg,
+    write_column,
+};
+use nalgebra::DVector;
+use std::ffi::{c_double, c_int};
+use std::slice;
+
+/// Options of [`Solution::edge_reliability`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReliabilityOptions {
+    /// Maximum number of solver iterations per edge.
+    pub iterations: usize,
+    /// Solver residual tolerance.
+    pub tolerance: f64,
+}
+
+impl Default for ReliabilityOptions {
+    fn default() -> Self {
+        ReliabilityOptions {
+            iterations: 60_000,
+            tolerance: 1e-10,
+        }
+    }
+}
+
+/// Reliability of the adjusted observation of one edge.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EdgeReliability {
+    pub edge: usize,
+    /// Redundancy number `1 - w q`, the same on both axes. NaN for a disabled edge.
+    pub redundancy: f64,
+    /// A-posteriori standard deviation of the adjusted `dx`.
+    pub sigma_dx: f64,
+    /// A-posteriori standard deviation of the adjusted `dy`.
+    pub sigma_dy: f64,
+}
+
+impl Solution {
+    /// Reliability of the adjusted observations of `edges`, this being the solution of
+    /// `graph` over all its edges. See the [module documentation](self).
+    pub fn edge_reliability(
+        &self,
+        graph: &Graph,
+        edges: &[usize],
+        options: &ReliabilityOptions,
+    ) -> Result<Vec<EdgeReliability>, SolveError> {
+        let system = graph.normal_equations(&[])?;
+        reliability(&system, graph, &[], (&self.x, &self.y), edges, options)
+    }
+}
+
+impl GraphContext {
+    /// [`Solution::edge_reliability`] at the current [`GraphContext::coordinates`], over the
+    /// enabled edges of the adjusted graph and the position observations.
+    pub fn edge_reliability(
+        &self,
+        edges: &[usize],
+        options: &ReliabilityOptions,
+    ) -> Result<Vec<EdgeReliability>, SolveError> {
+        let system = self.assemble()?;
+        let graph = self.adjusted_graph();
+        let enabled: Vec<bool> = (0..graph.num_edges())
+            .map(|e| self.is_edge_enabled(e))
+            .collect();
+        reliability(
+            &system,
+            &graph,
+            &enabled,
+            self.coordinates(),
+            edges,
+            options,
+        )
+    }
+}
+
+fn reliability(
+    system: &NormalEquations,
+    graph: &Graph,
+    enabled: &[bool],
+    (x, y): (&[f64], &[f64]),
+    edges: &[usize],
+    options: &ReliabilityOptions,
+) -> Result<Vec<EdgeReliability>, SolveError> {
+    if let Some(&e) = edges.iter().find(|&&e| e >= graph.num_edges()) {
+        return Err(SolveError {
+            code: COMPASS_ERR_INVALID_ARGUMENT,
+            message: format!("edge {e} out of range"),
+        });
+    }
+    let is_enabled = |e: usize| enabled.get(e).copied().unwrap_or(true);
+    // Variance factor of each axis: weighted square sum of the residuals over the
+    // observations less the unknowns, 1 without redundancy.
+    let (mut square_x, mut square_y, mut observations) = (0.0, 0.0, 0usize);
+    for e in (0..graph.num_edges()).filter(|&e| is_enabled(e)) {
+        let (u, v, w) = (graph.from[e], graph.to[e], graph.weight[e]);
+        square_x += w * (x[v] - x[u] - graph.dx[e]).powi(2);
+        square_y += w * (y[v] - y[u] - graph.dy[e]).powi(2);
+        observations += 1;
+    }
+    let n = system.size();
+    let redundancy = observations.saturating_sub(n - system.null_space.dimension());
+    let (variance_x, variance_y) = match redundancy {
+        0 => (1.0, 1.0),
+        r => (square_x / r as f64, square_y / r as f64),
+    };
+
+    let jacobi = PreconditionerOp::Jacobi(inverse_diagonal(&system.matrix));
+    let zero = DVector::zeros(n);
+    let mut rows = Vec::with_capacity(edges.len());
+    for &e in edges {
+        let ends = [(graph.to[e], 1.0), (graph.from[e], -1.0)]
+            .map(|(v, sign)| system.mapping[v].map(|i| (i, sign)));
+        let mut a = DVector::zeros(n);
+        for (i, sign) in ends.iter().flatten() {
+            a[*i] += sign;
+        }
+        let cofactor = match a.amax() {
+            0.0 => 0.0,
+            _ => {
+                let (z, _) = solve_cg(
+                    &system.matrix,
+                    &a,
+                    &zero,
+                    Some(&jacobi),
+                    &system.null_space,
+                    options.iterations,
+                    options.tolerance.into(),
+                    &|| false,
+                );
+                a.dot(&z).max(0.0)
+            }
+        };
+        rows.push(EdgeReliability {
+            edge: e,
+            redundancy: match is_enabled(e) {
+                true => 1.0 - graph.weight[e] * cofactor,
+                false => f64::NAN,
+            },
+            sigma_dx: (variance_x * cofactor).sqrt(),
+            sigma_dy: (variance_y * cofactor).sqrt(),
+        });
+    }
+    Ok(rows)
+}
+
+/// Computes the reliability of the `num_edges` edges listed in `edges` of the graph behind
+/// `handle`, see [`GraphContext::edge_reliability`], into parallel caller buffers of
+/// `num_edges` values with the [`ReliabilityOptions`] defaults. Any output pointer may be
+/// null to skip that column.
+///
+/// # Returns
+///
+/// * [`COMPASS_OK`] on success.
+/// * [`COMPASS_ERR_INVALID_ARGUMENT`] for a null handle, a negative count, a null `edges`
+///   with a positive count or an edge out of range; nothing is written.
+/// * The error of a failed assembly otherwise.
+#[unsafe(no_mangle)]
+pub extern "C" fn graph_edge_reliability(
+    handle: *const GraphContext,
+    num_edges: c_int,
+    edges: *const c_int,
+    out_redundancy: *mut c_double,
+    out_sigma_dx: *mut c_double,
+    out_sigma_dy: *mut c_double,
+) -> c_int {
+    let result = std::panic::catch_unwind(|| {
+        // Safety: We assume the caller guarantees a valid (or null) handle.
+        let (Some(ctx), Ok(count)) = (unsafe { handle.as_ref() }, usize::try_from(num_edges))
+        else {
+            return COMPASS_ERR_INVALID_ARGUMENT;
+        };
+        if edges.is_null() && count > 0 {
+            return COMPASS_ERR_INVALID_ARGUMENT;
+        }
+        let edges = match count {
+            0 => &[][..],
+            // Safety: The caller guarantees `num_edges` indices.
+            _ => unsafe { slice::from_raw_parts(edges, count) },
+        };
+        let Ok(edges) = edges
+            .iter()
+            .map(|&e| usize::try_from(e))
+            .collect::<Result<Vec<usize>, _>>()
+        else {
+            return COMPASS_ERR_INVALID_ARGUMENT;
+        };
+        let rows = match ctx.edge_reliability(&edges, &ReliabilityOptions::default()) {
+            Ok(rows) => rows,
+            Err(err) => return err.code,
+        };
+        write_column(out_redundancy, &rows, |r| r.redundancy);
+        write_column(out_sigma_dx, &rows, |r| r.sigma_dx);
+        write_column(out_sigma_dy, &rows, |r| r.sigma_dy);
+        COMPASS_OK
+    });
+
+    result.unwrap_or_else(|_| {
+        eprintln!("Panic caught in graph_edge_reliability");
+        COMPASS_ERR_PANIC
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::ReportFormat;
+    use nalgebra::DMatrix;
+
+    /// Anchors 0 and 4, a loop 1-2-3 between them, a spur 3-5 and a shot between the
+    /// anchors, all observations slightly off.
+    fn network() -> Graph {
+        let mut graph = Graph::default();
+        for v in 0..6 {
+            graph.add_vertex(10.0 * v as f64, 0.0, 0.0, v == 0 || v == 4);
+        }
+        for (u, v, dx, dy, w) in [
+            (0, 1, 10.1, 0.2, 1.0),
+            (1, 2, 9.8, -0.1, 2.0),
+            (2, 3, 10.2, 0.3, 1.0),
+            (3, 1, -20.1, 0.05, 0.5),
+            (3, 4, 9.9, -0.3, 4.0),
+            (3, 5, 10.0, 1.0, 1.0),
+            (4, 0, -40.05, 0.1, 0.25),
+        ] {
+            graph.add_edge(u, v, dx, dy, 0.0, w);
+        }
+        graph
+    }
+
+    #[test]
+    fn matches_a_dense_reference() {
+        let graph = network();
+        let solution = graph.solve(10_000, 1e-12).unwrap();
+        let edges: Vec<usize> = (0..graph.num_edges()).collect();
+        let rows = solution
+            .edge_reliability(&graph, &edges, &ReliabilityOptions::default())
+            .unwrap();
+
+        // Unknowns 1, 2, 3 and 5 in vertex order.
+        let unknown = |v: usize| [None, Some(0), Some(1), Some(2), None, Some(3)][v];
+        let design = DMatrix::from_fn(graph.num_edges(), 4, |e, i| {
+            match (unknown(graph.to[e]), unknown(graph.from[e])) {
+                (Some(j), _) if j == i => 1.0,
+                (_, Some(j)) if j == i => -1.0,
+                _ => 0.0,
+            }
+        });
+        let weight = DMatrix::from_diagonal(&DVector::from_vec(graph.weight.clone()));
+        let cofactor = (design.transpose() * &weight * &design)
+            .try_inverse()
+            .unwrap();
+        let q = &design * cofactor * design.transpose();
+        let residuals = solution.residuals(&graph);
+        let squares = residuals
+            .iter()
+            .zip(&graph.weight)
+            .fold((0.0, 0.0), |s, (r, w)| {
+                (s.0 + w * r.0 * r.0, s.1 + w * r.1 * r.1)
+            });
+        // 7 observations per axis, 4 unknowns.
+        let (variance_x, variance_y) = (squares.0 / 3.0, squares.1 / 3.0);
+        for row in &rows {
+            let (e, q) = (row.edge, q[(row.edge, row.edge)]);
+            assert!((row.redundancy - (1.0 - graph.weight[e] * q)).abs() < 1e-9);
+            assert!((row.sigma_dx - (variance_x * q).sqrt()).abs() < 1e-9);
+            assert!((row.sigma_dy - (variance_y * q).sqrt()).abs() < 1e-9);
+        }
+        // The redundancy numbers add up to the redundancy, the spur having none, and the
+        // shot between the anchors is not adjusted.
+        let total: f64 = rows.iter().map(|r| r.redundancy).sum();
+        assert!((total - 3.0).abs() < 1e-9, "{total}");
+        assert!(rows[5].redundancy.abs() < 1e-9);
+        assert_eq!((rows[6].redundancy, rows[6].sigma_dx), (1.0, 0.0));
+
+        // The report lists them.
+        let mut report = solution.adjustment_report(&graph);
+        report.reliability = Some(rows[..2].to_vec());
+        let text = report.render(&graph, ReportFormat::Text);
+        assert!(text.contains("\nReliability\n"), "{text}");
+        assert!(text.contains("  V0 -> V1 "), "{text}");
+        let json = report.render(&graph, ReportFormat::Json);
+        let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+        let listed = json["reliability"].as_array().unwrap();
+        assert_eq!(listed.len(), 2);
+        assert_eq!(listed[1]["edge"], 1);
+        assert_eq!(listed[1]["to"], "V2");
+        let sigma = listed[1]["sigma_dx"].as_f64().unwrap();
+        assert!((sigma - rows[1].sigma_dx).abs() < 1e-12);
+    }
+
+    #[test]
+    fn ffi_skips_disabled_edges_in_the_redundancy() {
+        let handle = GraphContext::into_raw(network());
+        let ctx = unsafe { &mut *handle };
+        ctx.set_edge_enabled(6, false).unwrap();
+        let edges = [6, 0];
+        let (mut redundancy, mut sigma) = ([0.0; 2], [0.0; 2]);
+        let code = graph_edge_reliability(
+            handle,
+            2,
+            edges.as_ptr(),
+            redundancy.as_mut_ptr(),
+            sigma.as_mut_ptr(),
+            std::ptr::null_mut(),
+        );
+        assert_eq!(code, COMPASS_OK);
+        assert!(redundancy[0].is_nan() && sigma[0] == 0.0);
+        assert!(redundancy[1] > 0.0 && redundancy[1] < 1.0 && sigma[1] > 0.0);
+
+        let out_of_range = [7];
+        let null = std::ptr::null_mut();
+        let code = graph_edge_reliability(handle, 1, out_of_range.as_ptr(), null, null, null);
+        assert_eq!(code, COMPASS_ERR_INVALID_ARGUMENT);
+        crate::graph_free(handle);
+        let code = graph_edge_reliability(std::ptr::null(), 0, std::ptr::null(), null, null, null);
+        assert_eq!(code, COMPASS_ERR_INVALID_ARGUMENT);
+    }
+}
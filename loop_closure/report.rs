@@ -0,0 +1,1369 @@
+//! Adjustment report for publishing a survey: network summary, anchors, loop misclosures,
+//! failed check shots, chi-square test, largest residuals, rejected observations and solver
+//! diagnostics, rendered
+//! as text or JSON. Given a baseline solution, it also lists the changes from it, see
+//! [`Solution::diff`], and given the reliability of chosen edges, see
+//! [`crate::reliability`], their redundancy numbers and adjusted standard deviations.
+//!
+//! The layout is versioned by [`REPORT_VERSION`]: downstream tools may parse either format,
+//! so any change to a field, a heading or a column bumps it.
+//!
+//! Loops are the fundamental cycles of a spanning forest of the enabled edges in which all
+//! anchors hang from a common root at their fixed coordinates, so traverses between two
+//! anchors count as loops. Their misclosure is that of the observations: the adjusted network
+//! closes every loop exactly, so the table gives the largest standardized residual along each
+//! loop to show where the misclosure went.
+//!
+//! A shot between two anchors checks their coordinates against each other. When its
+//! standardized residual exceeds [`CHECK_THRESHOLD`], the report breaks down the shortest
+//! other path between the anchors, see [`crate::paths`], to show which shots disagree.
+
+use crate::cave_stats::cave_stats;
+use crate::diff::{DiffSummary, MOST_MOVED, ResidualChange, StationDisplacement};
+use crate::paths::{PathBreakdown, path_breakdown};
+use crate::reliability::EdgeReliability;
+use crate::{
+    COMPASS_ERR_INVALID_ARGUMENT, COMPASS_ERR_IO, COMPASS_ERR_PANIC, COMPASS_OK, Graph,
+    GraphContext, Solution, SolveError, SolveStats, write_message,
+};
+use std::collections::VecDeque;
+use std::ffi::{CStr, c_char, c_double, c_int};
+use std::fmt::Write as _;
+use std::path::Path;
+
+/// Version of the report layout.
+pub const REPORT_VERSION: u32 = 5;
+/// Number of edges listed in [`AdjustmentReport::worst_residuals`].
+pub const WORST_RESIDUALS: usize = 10;
+/// Standardized residual above which a shot between two anchors fails the check, see
+/// [`AdjustmentReport::failed_checks`].
+pub const CHECK_THRESHOLD: f64 = 3.0;
+/// 97.5% quantile of the standard normal distribution, for the two-sided 95% chi-square test.
+const NORMAL_QUANTILE_975: f64 = 1.959_963_984_540_054;
+
+/// Output format of [`Solution::report`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    /// Fixed-width text for reading and printing.
+    Text,
+    /// JSON object for other tools.
+    Json,
+}
+
+impl ReportFormat {
+    /// Format for the FFI code: 0 = Text, 1 = Json.
+    fn from_code(code: c_int) -> Option<ReportFormat> {
+        match code {
+            0 => Some(ReportFormat::Text),
+            1 => Some(ReportFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+/// Everything [`Solution::report`] renders.
+#[derive(Debug, Clone)]
+pub struct AdjustmentReport {
+    /// [`REPORT_VERSION`] of the layout.
+    pub version: u32,
+    pub network: NetworkSummary,
+    /// Fixed vertices, in vertex order.
+    pub anchors: Vec<Anchor>,
+    /// One entry per independent loop, by decreasing [`LoopMisclosure::ppm`].
+    pub loops: Vec<LoopMisclosure>,
+    /// Enabled edges between two fixed vertices whose standardized residual exceeds
+    /// [`CHECK_THRESHOLD`], largest first.
+    pub failed_checks: Vec<CheckShot>,
+    /// `None` when the network has no redundant observation to test.
+    pub chi_square: Option<ChiSquareTest>,
+    /// The [`WORST_RESIDUALS`] enabled edges with the largest standardized residuals,
+    /// largest first.
+    pub worst_residuals: Vec<EdgeResidual>,
+    /// Disabled edges, with their residuals against the adjusted network.
+    pub rejected: Vec<EdgeResidual>,
+    /// Diagnostics of the solve.
+    pub stats: SolveStats,
+    /// Changes from a baseline solution, see [`Solution::adjustment_report_against`].
+    pub baseline: Option<BaselineComparison>,
+    /// Reliability of the edges requested with [`Solution::edge_reliability`] or
+    /// [`GraphContext::edge_reliability`], in their order. `None` when not computed.
+    pub reliability: Option<Vec<EdgeReliability>>,
+}
+
+/// Size of the network, see [`crate::cave_stats::CaveExtent`].
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct NetworkSummary {
+    pub stations: usize,
+    /// Enabled edges.
+    pub edges: usize,
+    pub anchors: usize,
+    pub components: usize,
+    pub loops: usize,
+    pub surveyed_length: f64,
+    pub horizontal_extent: f64,
+    pub vertical_range: f64,
+}
+
+/// A fixed vertex.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Anchor {
+    pub vertex: usize,
+    pub x: f64,
+    pub y: f64,
+}
+
+/// Misclosure of one independent loop.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoopMisclosure {
+    /// Edge closing the loop over the spanning forest.
+    pub closing_edge: usize,
+    /// Number of edges in the loop.
+    pub edges: usize,
+    /// Whether the loop is a traverse between two anchors.
+    pub through_anchors: bool,
+    /// Sum of the 3D shot lengths of the loop.
+    pub length: f64,
+    /// Misclosure of the observations along the loop, in the direction of the closing edge.
+    pub misclosure_x: f64,
+    pub misclosure_y: f64,
+    /// Length of the misclosure vector.
+    pub misclosure: f64,
+    /// [`LoopMisclosure::misclosure`] in parts per million of [`LoopMisclosure::length`].
+    pub ppm: f64,
+    /// Largest standardized residual of the loop's edges after the adjustment.
+    pub max_standardized: f64,
+}
+
+/// A shot between two anchors failing the check.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CheckShot {
+    pub residual: EdgeResidual,
+    /// Shortest path between the anchors over the other enabled edges, at the reported
+    /// coordinates; `None` when the shot is their only connection.
+    pub path: Option<PathBreakdown>,
+}
+
+/// Two-sided 95% test of the weighted sum of squared residuals against its chi-square
+/// distribution, meaningful when the weights are inverse variances.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChiSquareTest {
+    /// Sum of `w * |r|^2` over the enabled edges.
+    pub value: f64,
+    /// Two observations per enabled edge minus two unknowns per adjusted vertex.
+    pub degrees_of_freedom: usize,
+    /// `value / degrees_of_freedom`, close to 1 when the weights fit the data.
+    pub variance_factor: f64,
+    /// Acceptance interval (Wilson-Hilferty approximation of the quantiles).
+    pub lower: f64,
+    pub upper: f64,
+    pub passed: bool,
+}
+
+/// Changes of the reported solution from a baseline, see [`Solution::diff`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct BaselineComparison {
+    pub summary: DiffSummary,
+    /// The [`MOST_MOVED`] stations that moved most, largest displacement first.
+    pub most_moved: Vec<StationDisplacement>,
+    /// The [`MOST_MOVED`] edges whose residual length changed most, largest change first.
+    pub residual_changes: Vec<ResidualChange>,
+}
+
+/// Residual of one edge.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EdgeResidual {
+    pub edge: usize,
+    pub rx: f64,
+    pub ry: f64,
+    /// See [`Solution::standardized_residuals`].
+    pub standardized: f64,
+}
+
+impl Solution {
+    /// Adjustment report of this solution of `graph`, see [`AdjustmentReport`].
+    pub fn adjustment_report(&self, graph: &Graph) -> AdjustmentReport {
+        adjustment_report(&self.x, &self.y, graph, self.stats, &|_| true)
+    }
+
+    /// Renders [`Solution::adjustment_report`].
+    pub fn report(&self, graph: &Graph, format: ReportFormat) -> String {
+        self.adjustment_report(graph).render(graph, format)
+    }
+
+    /// [`Solution::adjustment_report`] with the changes from `baseline`, an earlier solution
+    /// of `graph`. Fails as [`Solution::diff`] does.
+    pub fn adjustment_report_against(
+        &self,
+        graph: &Graph,
+        baseline: &Solution,
+    ) -> Result<AdjustmentReport, SolveError> {
+        let diff = baseline.diff(self, graph)?;
+        let mut report = self.adjustment_report(graph);
+        report.baseline = Some(BaselineComparison {
+            summary: diff.summary,
+            most_moved: diff.most_moved(MOST_MOVED).into_iter().cloned().collect(),
+            residual_changes: diff
+                .largest_residual_changes(MOST_MOVED)
+                .into_iter()
+                .cloned()
+                .collect(),
+        });
+        Ok(report)
+    }
+
+    /// Renders [`Solution::adjustment_report_against`].
+    pub fn report_against(
+        &self,
+        graph: &Graph,
+        baseline: &Solution,
+        format: ReportFormat,
+    ) -> Result<String, SolveError> {
+        Ok(self
+            .adjustment_report_against(graph, baseline)?
+            .render(graph, format))
+    }
+
+    /// Writes [`Solution::report`] to `path`.
+    pub fn write_report(
+        &self,
+        graph: &Graph,
+        path: impl AsRef<Path>,
+        format: ReportFormat,
+    ) -> std::io::Result<()> {
+        std::fs::write(path, self.report(graph, format))
+    }
+}
+
+impl GraphContext {
+    /// [`Solution::adjustment_report`] at the current [`GraphContext::coordinates`], listing
+    /// the disabled edges as rejected observations.
+    pub fn adjustment_report(&self) -> AdjustmentReport {
+        let (x, y) = self.coordinates();
+        let stats = self.solution().map(|s| s.stats).unwrap_or_default();
+        adjustment_report(x, y, &self.graph, stats, &|e| self.is_edge_enabled(e))
+    }
+}
+
+fn adjustment_report(
+    x: &[f64],
+    y: &[f64],
+    graph: &Graph,
+    stats: SolveStats,
+    is_enabled: &dyn Fn(usize) -> bool,
+) -> AdjustmentReport {
+    let residual = |e: usize| {
+        let (u, v) = (graph.from[e], graph.to[e]);
+        let rx = x[v] - x[u] - graph.dx[e];
+        let ry = y[v] - y[u] - graph.dy[e];
+        EdgeResidual {
+            edge: e,
+            rx,
+            ry,
+            standardized: rx.hypot(ry) * graph.weight[e].sqrt(),
+        }
+    };
+    let (enabled, disabled): (Vec<usize>, Vec<usize>) =
+        (0..graph.num_edges()).partition(|&e| is_enabled(e));
+    let residuals: Vec<EdgeResidual> = enabled.iter().map(|&e| residual(e)).collect();
+
+    let loops = loop_misclosures(graph, &enabled, &|e| residual(e).standardized);
+    let cave = cave_stats(x, y, graph, &|_| false, is_enabled);
+    let anchors: Vec<Anchor> = (0..graph.num_vertices())
+        .filter(|&v| graph.fixed[v])
+        .map(|v| Anchor {
+            vertex: v,
+            x: graph.x[v],
+            y: graph.y[v],
+        })
+        .collect();
+
+    let mut adjusted = vec![false; graph.num_vertices()];
+    for &e in &enabled {
+        adjusted[graph.from[e]] = !graph.fixed[graph.from[e]];
+        adjusted[graph.to[e]] = !graph.fixed[graph.to[e]];
+    }
+    let unknowns = 2 * adjusted.iter().filter(|&&a| a).count();
+    let value: f64 = residuals
+        .iter()
+        .map(|r| graph.weight[r.edge] * (r.rx * r.rx + r.ry * r.ry))
+        .sum();
+    let chi_square = (2 * enabled.len())
+        .checked_sub(unknowns)
+        .filter(|&dof| dof > 0)
+        .map(|dof| {
+            let lower = chi_square_quantile(dof, -NORMAL_QUANTILE_975);
+            let upper = chi_square_quantile(dof, NORMAL_QUANTILE_975);
+            ChiSquareTest {
+                value,
+                degrees_of_freedom: dof,
+                variance_factor: value / dof as f64,
+                lower,
+                upper,
+                passed: (lower..=upper).contains(&value),
+            }
+        });
+
+    let mut failed_checks: Vec<CheckShot> = residuals
+        .iter()
+        .filter(|r| {
+            let (u, v) = (graph.from[r.edge], graph.to[r.edge]);
+            graph.fixed[u] && graph.fixed[v] && r.standardized > CHECK_THRESHOLD
+        })
+        .map(|r| {
+            let ends = (graph.from[r.edge], graph.to[r.edge]);
+            let others = |e| e != r.edge && is_enabled(e);
+            CheckShot {
+                residual: r.clone(),
+                path: path_breakdown(graph, (x, y), ends, &others),
+            }
+        })
+        .collect();
+    failed_checks.sort_by(|a, b| {
+        (b.residual.standardized)
+            .total_cmp(&a.residual.standardized)
+            .then(a.residual.edge.cmp(&b.residual.edge))
+    });
+
+    let mut worst = residuals;
+    worst.sort_by(|a, b| {
+        b.standardized
+            .total_cmp(&a.standardized)
+            .then(a.edge.cmp(&b.edge))
+    });
+    worst.truncate(WORST_RESIDUALS);
+
+    AdjustmentReport {
+        version: REPORT_VERSION,
+        network: NetworkSummary {
+            stations: graph.num_vertices(),
+            edges: enabled.len(),
+            anchors: anchors.len(),
+            components: cave.components.len(),
+            loops: loops.len(),
+            surveyed_length: cave.total.surveyed_length,
+            horizontal_extent: cave.total.max_horizontal_distance,
+            vertical_range: cave.total.vertical_range(),
+        },
+        anchors,
+        loops,
+        failed_checks,
+        chi_square,
+        worst_residuals: worst,
+        rejected: disabled.into_iter().map(residual).collect(),
+        stats,
+        baseline: None,
+        reliability: None,
+    }
+}
+
+/// Quantile of the chi-square distribution with `dof` degrees of freedom at the standard
+/// normal quantile `z` (Wilson-Hilferty).
+fn chi_square_quantile(dof: usize, z: f64) -> f64 {
+    let k = dof as f64;
+    let a = 2.0 / (9.0 * k);
+    k * (1.0 - a + z * a.sqrt()).powi(3).max(0.0)
+}
+
+/// Misclosure of the fundamental cycles of the `enabled` edges, see the module
+/// documentation.
+fn loop_misclosures(
+    graph: &Graph,
+    enabled: &[usize],
+    standardized: &dyn Fn(usize) -> f64,
+) -> Vec<LoopMisclosure> {
+    let forest = SpanningForest::new(graph, enabled);
+    let length =
+        |e: usize| (graph.dx[e].powi(2) + graph.dy[e].powi(2) + graph.dz[e].powi(2)).sqrt();
+    let mut loops: Vec<LoopMisclosure> = forest
+        .closing_edges(enabled)
+        .map(|e| {
+            let cycle = forest.cycle(graph, e);
+            let (misclosure_x, misclosure_y) = cycle.misclosure;
+            let misclosure = misclosure_x.hypot(misclosure_y);
+            let loop_length: f64 = cycle.edges.iter().map(|&(e, _)| length(e)).sum();
+            LoopMisclosure {
+                closing_edge: e,
+                edges: cycle.edges.len(),
+                through_anchors: cycle.through_anchors,
+                length: loop_length,
+                misclosure_x,
+                misclosure_y,
+                misclosure,
+                ppm: ppm(misclosure, loop_length),
+                max_standardized: cycle
+                    .edges
+                    .iter()
+                    .map(|&(e, _)| standardized(e))
+                    .fold(0.0, f64::max),
+            }
+        })
+        .collect();
+    loops.sort_by(|a, b| {
+        b.ppm
+            .total_cmp(&a.ppm)
+            .then(a.closing_edge.cmp(&b.closing_edge))
+    });
+    loops
+}
+
+/// `misclosure` in parts per million of `length`, 0 for a loop of zero length.
+pub(crate) fn ppm(misclosure: f64, length: f64) -> f64 {
+    if length > 0.0 {
+        misclosure / length * 1e6
+    } else {
+        0.0
+    }
+}
+
+/// Breadth-first spanning forest of the enabled edges in which the anchors hang from a
+/// common root, see the module documentation.
+pub(crate) struct SpanningForest {
+    /// The common root of the anchors, vertex `num_vertices`.
+    pub(crate) root: usize,
+    /// Parent vertex and tree edge of each vertex (no edge for the anchors), `None` for the
+    /// root.
+    pub(crate) parent: Vec<Option<(usize, Option<usize>)>>,
+    pub(crate) depth: Vec<usize>,
+    /// Coordinates obtained by chaining observations from the root.
+    pub(crate) position: Vec<(f64, f64)>,
+    pub(crate) tree_edge: Vec<bool>,
+    /// Vertices in breadth-first order, the root first: parents come before children.
+    pub(crate) order: Vec<usize>,
+}
+
+/// A fundamental cycle: an edge closing a loop over the spanning forest, and the tree path
+/// between its ends.
+pub(crate) struct Cycle {
+    /// Edges of the cycle with their sign in the misclosure sum, the closing edge first.
+    pub(crate) edges: Vec<(usize, f64)>,
+    /// Whether the cycle is a traverse between two anchors.
+    pub(crate) through_anchors: bool,
+    /// Misclosure of the observations along the cycle, in the direction of the closing edge.
+    pub(crate) misclosure: (f64, f64),
+}
+
+impl SpanningForest {
+    pub(crate) fn new(graph: &Graph, enabled: &[usize]) -> SpanningForest {
+        let n = graph.num_vertices();
+        // Incident edges of each vertex, in compressed rows.
+        let mut offsets = vec![0; n + 1];
+        for &e in enabled {
+            offsets[graph.from[e] + 1] += 1;
+            if graph.to[e] != graph.from[e] {
+                offsets[graph.to[e] + 1] += 1;
+            }
+        }
+        for v in 0..n {
+            offsets[v + 1] += offsets[v];
+        }
+        let mut incident = vec![0; offsets[n]];
+        let mut next = offsets.clone();
+        for &e in enabled {
+            incident[next[graph.from[e]]] = e;
+            next[graph.from[e]] += 1;
+            if graph.to[e] != graph.from[e] {
+                incident[next[graph.to[e]]] = e;
+                next[graph.to[e]] += 1;
+            }
+        }
+
+        let root = n;
+        let mut forest = SpanningForest {
+            root,
+            parent: vec![None; n + 1],
+            depth: vec![0; n + 1],
+            position: vec![(0.0, 0.0); n + 1],
+            tree_edge: vec![false; graph.num_edges()],
+            order: vec![root],
+        };
+        let mut visited = vec![false; n + 1];
+        let mut queue = VecDeque::new();
+        visited[root] = true;
+        for v in (0..n).filter(|&v| graph.fixed[v]) {
+            visited[v] = true;
+            forest.parent[v] = Some((root, None));
+            forest.depth[v] = 1;
+            forest.position[v] = (graph.x[v], graph.y[v]);
+            queue.push_back(v);
+        }
+        for start in 0..n {
+            if !visited[start] {
+                visited[start] = true;
+                queue.push_back(start);
+            }
+            while let Some(u) = queue.pop_front() {
+                forest.order.push(u);
+                for &e in &incident[offsets[u]..offsets[u + 1]] {
+                    let (v, sign) = match graph.from[e] == u {
+                        true => (graph.to[e], 1.0),
+                        false => (graph.from[e], -1.0),
+                    };
+                    if visited[v] {
+                        continue;
+                    }
+                    visited[v] = true;
+                    forest.tree_edge[e] = true;
+                    forest.parent[v] = Some((u, Some(e)));
+                    forest.depth[v] = forest.depth[u] + 1;
+                    forest.position[v] = (
+                        forest.position[u].0 + sign * graph.dx[e],
+                        forest.position[u].1 + sign * graph.dy[e],
+                    );
+                    queue.push_back(v);
+                }
+            }
+        }
+        forest
+    }
+
+    /// The `enabled` edges closing a fundamental cycle.
+    pub(crate) fn closing_edges<'a>(
+        &'a self,
+        enabled: &'a [usize],
+    ) -> impl Iterator<Item = usize> + 'a {
+        enabled.iter().copied().filter(|&e| !self.tree_edge[e])
+    }
+
+    /// Misclosure of the cycle closed by `e`, see [`Cycle::misclosure`].
+    pub(crate) fn misclosure(&self, graph: &Graph, e: usize) -> (f64, f64) {
+        let (from, to) = (self.position[graph.from[e]], self.position[graph.to[e]]);
+        (from.0 + graph.dx[e] - to.0, from.1 + graph.dy[e] - to.1)
+    }
+
+    /// The cycle closed by the non-tree edge `e`.
+    pub(crate) fn cycle(&self, graph: &Graph, e: usize) -> Cycle {
+        // A tree edge pointing away from the root counts positively on the path down to
+        // `from` and negatively on the path down to `to`.
+        let (mut a, mut b) = (graph.from[e], graph.to[e]);
+        let mut edges = vec![(e, 1.0)];
+        while a != b {
+            let (deeper, side) = match self.depth[a] >= self.depth[b] {
+                true => (&mut a, 1.0),
+                false => (&mut b, -1.0),
+            };
+            let Some((up, tree)) = self.parent[*deeper] else {
+                unreachable!("both ends are in the same tree");
+            };
+            if let Some(t) = tree {
+                let down = if graph.to[t] == *deeper { 1.0 } else { -1.0 };
+                edges.push((t, side * down));
+            }
+            *deeper = up;
+        }
+        Cycle {
+            edges,
+            through_anchors: a == self.root,
+            misclosure: self.misclosure(graph, e),
+        }
+    }
+}
+
+impl AdjustmentReport {
+    /// Renders the report, naming vertices after `graph`'s stations when it has names.
+    pub fn render(&self, graph: &Graph, format: ReportFormat) -> String {
+        match format {
+            ReportFormat::Text => self.to_text(graph),
+            ReportFormat::Json => self.to_json(graph),
+        }
+    }
+
+    fn to_text(&self, graph: &Graph) -> String {
+        let label = |v: usize| station(graph, v);
+        let shot = |e: usize| format!("{} -> {}", label(graph.from[e]), label(graph.to[e]));
+        let network = &self.network;
+        let mut out = String::new();
+        let _ = writeln!(out, "Adjustment report (format {})", self.version);
+
+        let _ = writeln!(out, "\nNetwork");
+        let _ = writeln!(out, "  stations:           {}", network.stations);
+        let _ = writeln!(out, "  edges:              {}", network.edges);
+        let _ = writeln!(out, "  anchors:            {}", network.anchors);
+        let _ = writeln!(out, "  components:         {}", network.components);
+        let _ = writeln!(out, "  independent loops:  {}", network.loops);
+        let _ = writeln!(out, "  surveyed length:    {:.3}", network.surveyed_length);
+        let _ = writeln!(
+            out,
+            "  horizontal extent:  {:.3}",
+            network.horizontal_extent
+        );
+        let _ = writeln!(out, "  vertical range:     {:.3}", network.vertical_range);
+
+        let _ = writeln!(out, "\nAnchors");
+        if self.anchors.is_empty() {
+            let _ = writeln!(out, "  (none)");
+        }
+        for anchor in &self.anchors {
+            let _ = writeln!(
+                out,
+                "  {:<24} {:>14.3} {:>14.3}",
+                label(anchor.vertex),
+                anchor.x,
+                anchor.y
+            );
+        }
+
+        let _ = writeln!(out, "\nLoop misclosures");
+        if self.loops.is_empty() {
+            let _ = writeln!(out, "  (none)");
+        } else {
+            let _ = writeln!(
+                out,
+                "  {:<32} {:>6} {:>12} {:>12} {:>10} {:>10}",
+                "closing shot", "edges", "length", "misclosure", "ppm", "max std"
+            );
+        }
+        for l in &self.loops {
+            let anchors = if l.through_anchors { " *" } else { "" };
+            let _ = writeln!(
+                out,
+                "  {:<32} {:>6} {:>12.3} {:>12.4} {:>10.0} {:>10.3}{anchors}",
+                shot(l.closing_edge),
+                l.edges,
+                l.length,
+                l.misclosure,
+                l.ppm,
+                l.max_standardized
+            );
+        }
+        if self.loops.iter().any(|l| l.through_anchors) {
+            let _ = writeln!(out, "  * traverse between anchors");
+        }
+
+        let _ = writeln!(out, "\nFailed check shots between anchors");
+        if self.failed_checks.is_empty() {
+            let _ = writeln!(out, "  (none)");
+        } else {
+            let _ = writeln!(
+                out,
+                "  {:<32} {:>12} {:>12} {:>12}",
+                "shot", "rx", "ry", "standardized"
+            );
+        }
+        for check in &self.failed_checks {
+            let r = &check.residual;
+            let _ = writeln!(
+                out,
+                "  {:<32} {:>12.4} {:>12.4} {:>12.3}",
+                shot(r.edge),
+                r.rx,
+                r.ry,
+                r.standardized
+            );
+            let Some(path) = &check.path else {
+                let _ = writeln!(out, "    no other path between the anchors");
+                continue;
+            };
+            let _ = writeln!(
+                out,
+                "    {:<30} {:>12} {:>12} {:>12} {:>12}",
+                "path", "observed x", "observed y", "running dx", "running dy"
+            );
+            for s in &path.segments {
+                let _ = writeln!(
+                    out,
+                    "    {:<30} {:>12.4} {:>12.4} {:>12.4} {:>12.4}",
+                    format!("{} -> {}", label(s.from), label(s.to)),
+                    s.observed_x,
+                    s.observed_y,
+                    s.discrepancy_x,
+                    s.discrepancy_y
+                );
+            }
+        }
+
+        let _ = writeln!(out, "\nChi-square test (95%)");
+        match &self.chi_square {
+            None => {
+                let _ = writeln!(out, "  not tested: no redundant observations");
+            }
+            Some(test) => {
+                let _ = writeln!(out, "  value:              {:.3}", test.value);
+                let _ = writeln!(out, "  degrees of freedom: {}", test.degrees_of_freedom);
+                let _ = writeln!(out, "  variance factor:    {:.3}", test.variance_factor);
+                let _ = writeln!(
+                    out,
+                    "  acceptance:         [{:.3}, {:.3}]",
+                    test.lower, test.upper
+                );
+                let verdict = if test.passed { "passed" } else { "failed" };
+                let _ = writeln!(out, "  result:             {verdict}");
+            }
+        }
+
+        for (title, residuals) in [
+            ("Largest standardized residuals", &self.worst_residuals),
+            ("Rejected observations", &self.rejected),
+        ] {
+            let _ = writeln!(out, "\n{title}");
+            if residuals.is_empty() {
+                let _ = writeln!(out, "  (none)");
+                continue;
+            }
+            let _ = writeln!(
+                out,
+                "  {:<32} {:>12} {:>12} {:>12}",
+                "shot", "rx", "ry", "standardized"
+            );
+            for r in residuals {
+                let _ = writeln!(
+                    out,
+                    "  {:<32} {:>12.4} {:>12.4} {:>12.3}",
+                    shot(r.edge),
+                    r.rx,
+                    r.ry,
+                    r.standardized
+                );
+            }
+        }
+
+        if let Some(baseline) = &self.baseline {
+            let summary = &baseline.summary;
+            let _ = writeln!(out, "\nChanges from baseline");
+            let _ = writeln!(out, "  stations compared:    {}", summary.stations);
+            let _ = writeln!(
+                out,
+                "  max displacement:     {:.4}",
+                summary.max_displacement
+            );
+            let _ = writeln!(
+                out,
+                "  mean displacement:    {:.4}",
+                summary.mean_displacement
+            );
+            let _ = writeln!(out, "  edges compared:       {}", summary.edges);
+            let _ = writeln!(
+                out,
+                "  max residual change:  {:.4}",
+                summary.max_residual_change
+            );
+            let _ = writeln!(
+                out,
+                "  mean residual change: {:.4}",
+                summary.mean_residual_change
+            );
+            if !baseline.most_moved.is_empty() {
+                let _ = writeln!(
+                    out,
+                    "  {:<32} {:>12} {:>12} {:>12}",
+                    "station", "dx", "dy", "displacement"
+                );
+            }
+            for d in &baseline.most_moved {
+                let _ = writeln!(
+                    out,
+                    "  {:<32} {:>12.4} {:>12.4} {:>12.4}",
+                    label(d.vertex),
+                    d.dx,
+                    d.dy,
+                    d.displacement
+                );
+            }
+            if !baseline.residual_changes.is_empty() {
+                let _ = writeln!(
+                    out,
+                    "  {:<32} {:>12} {:>12} {:>12}",
+                    "shot", "before", "after", "change"
+                );
+            }
+            for r in &baseline.residual_changes {
+                let _ = writeln!(
+                    out,
+                    "  {:<32} {:>12.4} {:>12.4} {:>12.4}",
+                    shot(r.edge),
+                    r.before,
+                    r.after,
+                    r.change
+                );
+            }
+        }
+
+        if let Some(reliability) = &self.reliability {
+            let _ = writeln!(out, "\nReliability");
+            if reliability.is_empty() {
+                let _ = writeln!(out, "  (none)");
+            } else {
+                let _ = writeln!(
+                    out,
+                    "  {:<32} {:>12} {:>12} {:>12}",
+                    "shot", "redundancy", "sigma dx", "sigma dy"
+                );
+            }
+            for r in reliability {
+                let _ = writeln!(
+                    out,
+                    "  {:<32} {:>12.3} {:>12.4} {:>12.4}",
+                    shot(r.edge),
+                    r.redundancy,
+                    r.sigma_dx,
+                    r.sigma_dy
+                );
+            }
+        }
+
+        let stats = &self.stats;
+        let _ = writeln!(out, "\nSolver");
+        let _ = writeln!(out, "  free vertices:          {}", stats.free_vertices);
+        let _ = writeln!(out, "  passive vertices:       {}", stats.passive_vertices);
+        let _ = writeln!(out, "  frozen vertices:        {}", stats.frozen_vertices);
+        let _ = writeln!(
+            out,
+            "  frozen boundary edges:  {}",
+            stats.frozen_boundary_edges
+        );
+        let _ = writeln!(
+            out,
+            "  frozen boundary stress: {:.3}",
+            stats.frozen_boundary_stress
+        );
+        let _ = writeln!(out, "  disabled edges:         {}", stats.disabled_edges);
+        let _ = writeln!(
+            out,
+            "  datum shift:            {:.4} {:.4}",
+            stats.datum_shift_x, stats.datum_shift_y
+        );
+        let _ = writeln!(out, "  datum rotation:         {:.6}", stats.datum_rotation);
+        out
+    }
+
+    fn to_json(&self, graph: &Graph) -> String {
+        let label = |v: usize| json_string(&station(graph, v));
+        let residuals = |residuals: &[EdgeResidual]| {
+            let rows: Vec<String> = residuals
+                .iter()
+                .map(|r| {
+                    format!(
+                        "{{\"edge\": {}, \"from\": {}, \"to\": {}, \"rx\": {}, \"ry\": {}, \
+                         \"standardized\": {}}}",
+                        r.edge,
+                        label(graph.from[r.edge]),
+                        label(graph.to[r.edge]),
+                        number(r.rx),
+                        number(r.ry),
+                        number(r.standardized)
+                    )
+                })
+                .collect();
+            array(&rows)
+        };
+        let network = &self.network;
+        let anchors: Vec<String> = self
+            .anchors
+            .iter()
+            .map(|a| {
+                format!(
+                    "{{\"vertex\": {}, \"station\": {}, \"x\": {}, \"y\": {}}}",
+                    a.vertex,
+                    label(a.vertex),
+                    number(a.x),
+                    number(a.y)
+                )
+            })
+            .collect();
+        let loops: Vec<String> = self
+            .loops
+            .iter()
+            .map(|l| {
+                format!(
+                    "{{\"closing_edge\": {}, \"from\": {}, \"to\": {}, \"edges\": {}, \
+                     \"through_anchors\": {}, \"length\": {}, \"misclosure_x\": {}, \
+                     \"misclosure_y\": {}, \"misclosure\": {}, \"ppm\": {}, \
+                     \"max_standardized\": {}}}",
+                    l.closing_edge,
+                    label(graph.from[l.closing_edge]),
+                    label(graph.to[l.closing_edge]),
+                    l.edges,
+                    l.through_anchors,
+                    number(l.length),
+                    number(l.misclosure_x),
+                    number(l.misclosure_y),
+                    number(l.misclosure),
+                    number(l.ppm),
+                    number(l.max_standardized)
+                )
+            })
+            .collect();
+        let failed_checks: Vec<String> = self
+            .failed_checks
+            .iter()
+            .map(|check| {
+                let r = &check.residual;
+                let path = match &check.path {
+                    None => "null".to_string(),
+                    Some(path) => {
+                        let segments: Vec<String> = path
+                            .segments
+                            .iter()
+                            .map(|s| {
+                                format!(
+                                    "{{\"edge\": {}, \"from\": {}, \"to\": {}, \
+                                     \"observed_x\": {}, \"observed_y\": {}, \
+                                     \"discrepancy_x\": {}, \"discrepancy_y\": {}}}",
+                                    s.edge,
+                                    label(s.from),
+                                    label(s.to),
+                                    number(s.observed_x),
+                                    number(s.observed_y),
+                                    number(s.discrepancy_x),
+                                    number(s.discrepancy_y)
+                                )
+                            })
+                            .collect();
+                        format!(
+                            "{{\"length\": {}, \"segments\": [{}]}}",
+                            number(path.length),
+                            segments.join(", ")
+                        )
+                    }
+                };
+                format!(
+                    "{{\"edge\": {}, \"from\": {}, \"to\": {}, \"rx\": {}, \"ry\": {}, \
+                     \"standardized\": {}, \"path\": {}}}",
+                    r.edge,
+                    label(graph.from[r.edge]),
+                    label(graph.to[r.edge]),
+                    number(r.rx),
+                    number(r.ry),
+                    number(r.standardized),
+                    path
+                )
+            })
+            .collect();
+        let chi_square = match &self.chi_square {
+            None => "null".to_string(),
+            Some(test) => format!(
+                "{{\"value\": {}, \"degrees_of_freedom\": {}, \"variance_factor\": {}, \
+                 \"lower\": {}, \"upper\": {}, \"passed\": {}}}",
+                number(test.value),
+                test.degrees_of_freedom,
+                number(test.variance_factor),
+                number(test.lower),
+                number(test.upper),
+                test.passed
+            ),
+        };
+        let baseline = match &self.baseline {
+            None => "null".to_string(),
+            Some(baseline) => {
+                let summary = &baseline.summary;
+                let moved: Vec<String> = baseline
+                    .most_moved
+                    .iter()
+                    .map(|d| {
+                        format!(
+                            "{{\"vertex\": {}, \"station\": {}, \"dx\": {}, \"dy\": {}, \
+                             \"displacement\": {}}}",
+                            d.vertex,
+                            label(d.vertex),
+                            number(d.dx),
+                            number(d.dy),
+                            number(d.displacement)
+                        )
+                    })
+                    .collect();
+                let changes: Vec<String> = baseline
+                    .residual_changes
+                    .iter()
+                    .map(|r| {
+                        format!(
+                            "{{\"edge\": {}, \"from\": {}, \"to\": {}, \"before\": {}, \
+                             \"after\": {}, \"change\": {}}}",
+                            r.edge,
+                            label(graph.from[r.edge]),
+                            label(graph.to[r.edge]),
+                            number(r.before),
+                            number(r.after),
+                            number(r.change)
+                        )
+                    })
+                    .collect();
+                format!(
+                    "{{\"stations\": {}, \"max_displacement\": {}, \"mean_displacement\": {}, \
+                     \"edges\": {}, \"max_residual_change\": {}, \"mean_residual_change\": {}, \
+                     \"most_moved\": {}, \"residual_changes\": {}}}",
+                    summary.stations,
+                    number(summary.max_displacement),
+                    number(summary.mean_displacement),
+                    summary.edges,
+                    number(summary.max_residual_change),
+                    number(summary.mean_residual_change),
+                    array(&moved),
+                    array(&changes)
+                )
+            }
+        };
+        let reliability = match &self.reliability {
+            None => "null".to_string(),
+            Some(reliability) => {
+                let rows: Vec<String> = reliability
+                    .iter()
+                    .map(|r| {
+                        format!(
+                            "{{\"edge\": {}, \"from\": {}, \"to\": {}, \"redundancy\": {}, \
+                             \"sigma_dx\": {}, \"sigma_dy\": {}}}",
+                            r.edge,
+                            label(graph.from[r.edge]),
+                            label(graph.to[r.edge]),
+                            number(r.redundancy),
+                            number(r.sigma_dx),
+                            number(r.sigma_dy)
+                        )
+                    })
+                    .collect();
+                array(&rows)
+            }
+        };
+        let stats = &self.stats;
+
+        let mut out = String::from("{\n");
+        let _ = writeln!(out, "  \"version\": {},", self.version);
+        let _ = writeln!(
+            out,
+            "  \"network\": {{\"stations\": {}, \"edges\": {}, \"anchors\": {}, \
+             \"components\": {}, \"loops\": {}, \"surveyed_length\": {}, \
+             \"horizontal_extent\": {}, \"vertical_range\": {}}},",
+            network.stations,
+            network.edges,
+            network.anchors,
+            network.components,
+            network.loops,
+            number(network.surveyed_length),
+            number(network.horizontal_extent),
+            number(network.vertical_range)
+        );
+        let _ = writeln!(out, "  \"anchors\": {},", array(&anchors));
+        let _ = writeln!(out, "  \"loops\": {},", array(&loops));
+        let _ = writeln!(out, "  \"failed_checks\": {},", array(&failed_checks));
+        let _ = writeln!(out, "  \"chi_square\": {chi_square},");
+        let _ = writeln!(
+            out,
+            "  \"worst_residuals\": {},",
+            residuals(&self.worst_residuals)
+        );
+        let _ = writeln!(out, "  \"rejected\": {},", residuals(&self.rejected));
+        let _ = writeln!(out, "  \"baseline\": {baseline},");
+        let _ = writeln!(out, "  \"reliability\": {reliability},");
+        let _ = writeln!(
+            out,
+            "  \"solver\": {{\"free_vertices\": {}, \"passive_vertices\": {}, \
+             \"frozen_vertices\": {}, \"frozen_boundary_edges\": {}, \
+             \"frozen_boundary_stress\": {}, \"disabled_edges\": {}, \
+             \"datum_shift_x\": {}, \"datum_shift_y\": {}, \"datum_rotation\": {}}}",
+            stats.free_vertices,
+            stats.passive_vertices,
+            stats.frozen_vertices,
+            stats.frozen_boundary_edges,
+            number(stats.frozen_boundary_stress),
+            stats.disabled_edges,
+            number(stats.datum_shift_x),
+            number(stats.datum_shift_y),
+            number(stats.datum_rotation)
+        );
+        out.push_str("}\n");
+        out
+    }
+}
+
+fn station(graph: &Graph, v: usize) -> String {
+    match &graph.names {
+        Some(names) => names[v].clone(),
+        None => format!("V{v}"),
+    }
+}
+
+/// JSON array with one element per line.
+fn array(rows: &[String]) -> String {
+    if rows.is_empty() {
+        return "[]".to_string();
+    }
+    format!("[\n    {}\n  ]", rows.join(",\n    "))
+}
+
+/// JSON number, or `null` for NaN and infinities which JSON cannot represent.
+fn number(value: c_double) -> String {
+    if value.is_finite() {
+        format!("{value}")
+    } else {
+        "null".to_string()
+    }
+}
+
+fn json_string(text: &str) -> String {
+    let mut out = String::with_capacity(text.len() + 2);
+    out.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Writes the adjustment report of the graph behind `handle` to a file, see
+/// [`GraphContext::adjustment_report`].
+///
+/// # Arguments
+///
+/// * `handle` - Context handle that has been solved with [`crate::graph_solve`].
+/// * `path` - NUL-terminated output path.
+/// * `format` - 0 = Text, 1 = Json.
+/// * `err_buf` - Buffer receiving a NUL-terminated error message on failure. May be null.
+/// * `err_cap` - Capacity of `err_buf` in bytes.
+#[unsafe(no_mangle)]
+pub extern "C" fn graph_write_report(
+    handle: *const GraphContext,
+    path: *const c_char,
+    format: c_int,
+    err_buf: *mut c_char,
+    err_cap: usize,
+) -> c_int {
+    let result = std::panic::catch_unwind(|| {
+        // Safety: We assume the caller guarantees a valid (or null) handle and path.
+        let Some(ctx) = (unsafe { handle.as_ref() }) else {
+            write_message(err_buf, err_cap, "handle is null");
+            return COMPASS_ERR_INVALID_ARGUMENT;
+        };
+        if path.is_null() {
+            write_message(err_buf, err_cap, "path is null");
+            return COMPASS_ERR_INVALID_ARGUMENT;
+        }
+        let Some(format) = ReportFormat::from_code(format) else {
+            write_message(err_buf, err_cap, &format!("unknown report format {format}"));
+            return COMPASS_ERR_INVALID_ARGUMENT;
+        };
+        if ctx.solution().is_none() {
+            write_message(err_buf, err_cap, "graph has not been solved");
+            return COMPASS_ERR_INVALID_ARGUMENT;
+        }
+        let path = unsafe { CStr::from_ptr(path) }
+            .to_string_lossy()
+            .into_owned();
+        let report = ctx.adjustment_report().render(ctx.graph(), format);
+        match std::fs::write(&path, report) {
+            Ok(()) => COMPASS_OK,
+            Err(err) => {
+                write_message(err_buf, err_cap, &format!("{path}: {err}"));
+                COMPASS_ERR_IO
+            }
+        }
+    });
+
+    match result {
+        Ok(code) => code,
+        Err(_) => {
+            eprintln!("Panic caught in graph_write_report");
+            COMPASS_ERR_PANIC
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CString;
+
+    const GOLDEN: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/../tests/artifacts/triangle_report.txt"
+    );
+
+    /// The triangle of the Python binding tests: one anchor, a misclosure of (0.05, -0.05)
+    /// and unequal weights.
+    fn misclosed_triangle() -> Graph {
+        let mut graph = Graph::default();
+        for (x, y, fixed) in [(0.0, 0.0, true), (1.2, 0.1, false), (0.9, 1.1, false)] {
+            graph.add_vertex(x, y, 0.0, fixed);
+        }
+        graph.add_edge(0, 1, 1.0, 0.0, 0.0, 1.0);
+        graph.add_edge(1, 2, 0.0, 1.0, 0.0, 2.0);
+        graph.add_edge(0, 2, 1.05, 0.95, 0.0, 4.0);
+        graph
+    }
+
+    /// Compares two reports token by token, numbers within `tolerance`.
+    fn assert_text_matches(actual: &str, expected: &str, tolerance: f64) {
+        let (actual, expected): (Vec<_>, Vec<_>) =
+            (actual.lines().collect(), expected.lines().collect());
+        assert_eq!(actual.len(), expected.len());
+        for (actual, expected) in actual.iter().zip(expected) {
+            let a: Vec<&str> = actual.split_whitespace().collect();
+            let e: Vec<&str> = expected.split_whitespace().collect();
+            assert_eq!(a.len(), e.len(), "{actual}");
+            for (a, e) in a.iter().zip(e) {
+                let number = |t: &str| t.trim_matches(['[', ']', ',']).parse::<f64>().ok();
+                match (number(a), number(e)) {
+                    (Some(a), Some(e)) => {
+                        assert!((a - e).abs() <= tolerance * e.abs().max(1.0), "{actual}")
+                    }
+                    _ => assert_eq!(*a, e, "{actual}"),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn text_matches_the_golden_file() {
+        let graph = misclosed_triangle();
+        let solution = graph.solve(60_000, 1e-8).unwrap();
+        let golden = std::fs::read_to_string(GOLDEN).unwrap();
+        assert_text_matches(&solution.report(&graph, ReportFormat::Text), &golden, 1e-3);
+    }
+
+    #[test]
+    fn json_has_the_report_structure() {
+        let graph = misclosed_triangle();
+        let solution = graph.solve(60_000, 1e-8).unwrap();
+        let json = solution.report(&graph, ReportFormat::Json);
+        let report: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(report["version"], REPORT_VERSION);
+        assert_eq!(report["network"]["loops"], 1);
+        let loops = report["loops"].as_array().unwrap();
+        assert_eq!(loops.len(), 1);
+        assert_eq!(loops[0]["edges"], 3);
+        let misclosure = loops[0]["misclosure"].as_f64().unwrap();
+        assert!((misclosure - 0.05f64.hypot(0.05)).abs() < 1e-9);
+        assert_eq!(report["chi_square"]["degrees_of_freedom"], 2);
+        let worst: Vec<_> = report["worst_residuals"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|r| r["edge"].as_u64().unwrap())
+            .collect();
+        assert_eq!(worst, [0, 1, 2]);
+        assert_eq!(report["rejected"], serde_json::json!([]));
+    }
+
+    #[test]
+    fn failed_check_shots_break_down_the_path_between_the_anchors() {
+        // Anchors 0 and 3, 30 m apart, joined by the traverse 0-1-2-3 whose last shot is
+        // 0.5 m short, and by a heavy check shot 0.5 m long.
+        let mut graph = Graph::default();
+        for x in [0.0, 10.0, 20.0, 30.0] {
+            graph.add_vertex(x, 0.0, 0.0, x == 0.0 || x == 30.0);
+        }
+        graph.add_edge(0, 1, 10.0, 0.0, 0.0, 1.0);
+        graph.add_edge(1, 2, 10.0, 0.0, 0.0, 1.0);
+        graph.add_edge(2, 3, 9.5, 0.0, 0.0, 1.0);
+        graph.add_edge(0, 3, 30.5, 0.0, 0.0, 100.0);
+        let solution = graph.solve(60_000, 1e-12).unwrap();
+        let report = solution.adjustment_report(&graph);
+        let [check] = &report.failed_checks[..] else {
+            panic!("{:?}", report.failed_checks);
+        };
+        assert_eq!(check.residual.edge, 3);
+        assert!((check.residual.standardized - 5.0).abs() < 1e-9);
+        let path = check.path.as_ref().unwrap();
+        assert_eq!(path.vertices, [0, 1, 2, 3]);
+        let (dx, dy) = path.discrepancy();
+        assert!((dx - 0.5).abs() < 1e-9 && dy.abs() < 1e-9);
+
+        let text = solution.report(&graph, ReportFormat::Text);
+        assert!(text.contains("\n  V0 -> V3 "), "{text}");
+        assert!(text.contains("\n    V2 -> V3 "), "{text}");
+        let json = solution.report(&graph, ReportFormat::Json);
+        let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+        let segments = json["failed_checks"][0]["path"]["segments"]
+            .as_array()
+            .unwrap();
+        assert_eq!(segments.len(), 3);
+
+        // Without the traverse, the check shot is the anchors' only connection.
+        let handle = GraphContext::into_raw(graph);
+        unsafe { &mut *handle }.edge_enabled = vec![true, false, true, true];
+        let report = unsafe { &*handle }.adjustment_report();
+        assert_eq!(report.failed_checks[0].path, None);
+        crate::graph_free(handle);
+    }
+
+    #[test]
+    fn baseline_section_lists_the_changes() {
+        let graph = misclosed_triangle();
+        let solution = graph.solve(60_000, 1e-8).unwrap();
+        let baseline = Solution {
+            x: graph.x.clone(),
+            y: graph.y.clone(),
+            ..Solution::default()
+        };
+        let json = solution.report(&graph, ReportFormat::Json);
+        let report: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(report["baseline"], serde_json::Value::Null);
+        assert!(
+            !solution
+                .report(&graph, ReportFormat::Text)
+                .contains("baseline")
+        );
+
+        let json = solution
+            .report_against(&graph, &baseline, ReportFormat::Json)
+            .unwrap();
+        let report: serde_json::Value = serde_json::from_str(&json).unwrap();
+        let changes = &report["baseline"];
+        assert_eq!(changes["stations"], 3);
+        let moved = changes["most_moved"].as_array().unwrap();
+        assert_eq!(moved.len(), 3);
+        assert_eq!(moved[2]["station"], "V0");
+        assert_eq!(moved[2]["displacement"], 0.0);
+        let text = solution
+            .report_against(&graph, &baseline, ReportFormat::Text)
+            .unwrap();
+        assert!(text.contains("\nChanges from baseline\n"), "{text}");
+
+        let err = solution
+            .report_against(&graph, &Solution::default(), ReportFormat::Text)
+            .unwrap_err();
+        assert_eq!(err.code, COMPASS_ERR_INVALID_ARGUMENT);
+    }
+
+    #[test]
+    fn ffi_writes_the_report_of_a_solved_context() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("report.txt");
+        let c_path = CString::new(path.to_str().unwrap()).unwrap();
+        let mut err = [0 as c_char; 128];
+        let write = |handle, format, err: &mut [c_char]| {
+            graph_write_report(handle, c_path.as_ptr(), format, err.as_mut_ptr(), err.len())
+        };
+        let message = |err: &[c_char]| {
+            unsafe { CStr::from_ptr(err.as_ptr()) }
+                .to_str()
+                .unwrap()
+                .to_owned()
+        };
+
+        let handle = GraphContext::into_raw(misclosed_triangle());
+        assert_eq!(write(handle, 0, &mut err), COMPASS_ERR_INVALID_ARGUMENT);
+        assert_eq!(message(&err), "graph has not been solved");
+        let ctx = unsafe { &mut *handle };
+        ctx.solve(60_000, 1e-8.into(), Default::default(), None, &|| false)
+            .unwrap();
+        assert_eq!(write(handle, 2, &mut err), COMPASS_ERR_INVALID_ARGUMENT);
+        assert_eq!(message(&err), "unknown report format 2");
+
+        // A rejected edge is listed as such.
+        ctx.set_edge_enabled(2, false).unwrap();
+        ctx.solve(60_000, 1e-8.into(), Default::default(), None, &|| false)
+            .unwrap();
+        assert_eq!(write(handle, 1, &mut err), COMPASS_OK);
+        let written = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(
+            written,
+            ctx.adjustment_report()
+                .render(ctx.graph(), ReportFormat::Json)
+        );
+        let report: serde_json::Value = serde_json::from_str(&written).unwrap();
+        assert_eq!(report["rejected"].as_array().unwrap().len(), 1);
+
+        let missing = CString::new(dir.path().join("no/such/dir").to_str().unwrap()).unwrap();
+        let code = graph_write_report(handle, missing.as_ptr(), 0, err.as_mut_ptr(), err.len());
+        assert_eq!(code, COMPASS_ERR_IO);
+        crate::graph_free(handle);
+    }
+}
@@ -0,0 +1,270 @@
+//! Incremental residual download: the edges whose residual changed since the previous
+//! solve, so that a server syncing residuals to its clients after each adjustment sends
+//! the few edges a change of the data moved rather than all of them.
+//!
+//! A [`GraphContext`] keeps the residuals of its last two stored solutions in two buffers
+//! that swap roles with each solve, so that no memory is allocated once they reached the
+//! number of edges. Every solve, preview or constrained solve stored on the handle counts,
+//! and advances the [generation](GraphContext::residual_generation) by one: a client that
+//! last synced generation `g` can apply the changes of generation `g + 1` only, and needs a
+//! full download with [`crate::graph_get_residuals`] otherwise.
+
+use crate::{
+    COMPASS_ERR_BUFFER_TOO_SMALL, COMPASS_ERR_INVALID_ARGUMENT, COMPASS_OK, Graph, GraphContext,
+};
+use std::ffi::{c_double, c_int};
+use std::slice;
+
+/// Residuals of the last two solutions stored on a [`GraphContext`].
+#[derive(Debug, Default)]
+pub(crate) struct ResidualHistory {
+    /// Number of solutions recorded so far.
+    generation: u64,
+    /// Residual `(rx, ry)` of each edge at the solution before the last one, empty before
+    /// the second solution.
+    previous: Vec<(f64, f64)>,
+    /// Residual of each edge at the last solution.
+    current: Vec<(f64, f64)>,
+}
+
+impl ResidualHistory {
+    /// Records the residuals of `graph` at `(x, y)` as those of the next generation.
+    pub(crate) fn record(&mut self, graph: &Graph, x: &[f64], y: &[f64]) {
+        std::mem::swap(&mut self.previous, &mut self.current);
+        self.current.clear();
+        self.current.extend((0..graph.num_edges()).map(|e| {
+            let (u, v) = (graph.from[e], graph.to[e]);
+            (x[v] - x[u] - graph.dx[e], y[v] - y[u] - graph.dy[e])
+        }));
+        self.generation += 1;
+    }
+}
+
+impl GraphContext {
+    /// Number of solutions stored on the handle so far, see the module documentation.
+    pub fn residual_generation(&self) -> u64 {
+        self.residual_history.generation
+    }
+
+    /// Edges `(e, rx, ry)` whose residual moved by more than `epsilon` between the previous
+    /// solution and the last one, in ascending order, with their residual at the last one.
+    /// Every edge is reported after the first solution, and edges added since the previous
+    /// one always are; a non-finite residual always counts as changed.
+    pub fn changed_residuals(&self, epsilon: f64) -> Vec<(usize, f64, f64)> {
+        let ResidualHistory {
+            previous, current, ..
+        } = &self.residual_history;
+        current
+            .iter()
+            .enumerate()
+            .filter(|&(e, &(rx, ry))| {
+                previous.get(e).is_none_or(|&(px, py)| {
+                    let change = (rx - px).hypot(ry - py);
+                    change.is_nan() || change > epsilon
+                })
+            })
+            .map(|(e, &(rx, ry))| (e, rx, ry))
+            .collect()
+    }
+}
+
+/// Copies the edges of the graph behind `handle` whose residual changed by more than
+/// `epsilon` since the previous solve into `out_indices` and their residual `(rx, ry)` at
+/// the last solve into `out_values`, see [`GraphContext::changed_residuals`]. `out_indices`
+/// holds `cap` edge indices and `out_values` `2 * cap` values, `rx` then `ry` for each edge.
+/// The number of edges is written to `out_count` either way, so that a first call with `cap`
+/// 0 sizes the buffers. Any output pointer may be null to skip it.
+///
+/// Compare [`graph_residual_generation`] with that of the last sync to tell whether these
+/// changes follow it.
+///
+/// # Returns
+///
+/// * [`COMPASS_OK`].
+/// * [`COMPASS_ERR_BUFFER_TOO_SMALL`] if `cap` is below the number of edges; nothing is
+///   written to the buffers.
+/// * [`COMPASS_ERR_INVALID_ARGUMENT`] for a null handle, or a negative or NaN `epsilon`.
+#[unsafe(no_mangle)]
+pub extern "C" fn graph_changed_residuals(
+    handle: *const GraphContext,
+    epsilon: c_double,
+    out_indices: *mut c_int,
+    out_values: *mut c_double,
+    cap: c_int,
+    out_count: *mut c_int,
+) -> c_int {
+    let Some(ctx) = (unsafe { handle.as_ref() }) else {
+        return COMPASS_ERR_INVALID_ARGUMENT;
+    };
+    if epsilon.is_nan() || epsilon < 0.0 {
+        return COMPASS_ERR_INVALID_ARGUMENT;
+    }
+    let changed = ctx.changed_residuals(epsilon);
+    if !out_count.is_null() {
+        unsafe { *out_count = changed.len() as c_int };
+    }
+    if (cap.max(0) as usize) < changed.len() {
+        return COMPASS_ERR_BUFFER_TOO_SMALL;
+    }
+    if !out_indices.is_null() {
+        // Safety: The caller guarantees a buffer of `cap` elements.
+        let out = unsafe { slice::from_raw_parts_mut(out_indices, changed.len()) };
+        for (dst, &(e, _, _)) in out.iter_mut().zip(&changed) {
+            *dst = e as c_int;
+        }
+    }
+    if !out_values.is_null() {
+        // Safety: The caller guarantees a buffer of `2 * cap` elements.
+        let out = unsafe { slice::from_raw_parts_mut(out_values, 2 * changed.len()) };
+        for (dst, &(_, rx, ry)) in out.chunks_exact_mut(2).zip(&changed) {
+            dst.copy_from_slice(&[rx, ry]);
+        }
+    }
+    COMPASS_OK
+}
+
+/// Writes the number of solutions stored on the handle so far into `out_generation`, see
+/// [`GraphContext::residual_generation`]. Returns [`COMPASS_ERR_INVALID_ARGUMENT`] for a
+/// null handle or output pointer.
+#[unsafe(no_mangle)]
+pub extern "C" fn graph_residual_generation(
+    handle: *const GraphContext,
+    out_generation: *mut u64,
+) -> c_int {
+    let Some(ctx) = (unsafe { handle.as_ref() }) else {
+        return COMPASS_ERR_INVALID_ARGUMENT;
+    };
+    if out_generation.is_null() {
+        return COMPASS_ERR_INVALID_ARGUMENT;
+    }
+    unsafe { *out_generation = ctx.residual_generation() };
+    COMPASS_OK
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{SolveOptions, SolveStats, graph_free, graph_solve};
+
+    /// Two squares with a misclosure each, 0-1-2-3 hanging from the fixed station 0 and
+    /// 4-5-6-7 joined to it by the single shot 2-4. Returns the graph and the edges of the
+    /// first square.
+    fn two_loops() -> (Graph, Vec<usize>) {
+        let mut graph = Graph::default();
+        graph.add_vertex(0.0, 0.0, 0.0, true);
+        for _ in 1..8 {
+            graph.add_vertex(0.0, 0.0, 0.0, false);
+        }
+        let mut first = Vec::new();
+        for (u, v, dx, dy) in [
+            (0, 1, 10.0, 0.0),
+            (1, 2, 0.0, 10.1),
+            (2, 3, -10.0, 0.0),
+            (3, 0, 0.0, -10.0),
+        ] {
+            first.push(graph.add_edge(u, v, dx, dy, 0.0, 1.0));
+        }
+        graph.add_edge(2, 4, 20.0, 0.0, 0.0, 1.0);
+        for (u, v, dx, dy) in [
+            (4, 5, 10.0, 0.0),
+            (5, 6, 0.0, 10.0),
+            (6, 7, -10.2, 0.0),
+            (7, 4, 0.0, -10.0),
+        ] {
+            graph.add_edge(u, v, dx, dy, 0.0, 1.0);
+        }
+        (graph, first)
+    }
+
+    fn solve(handle: *mut GraphContext) {
+        let options = SolveOptions {
+            tolerance: 1e-14,
+            ..SolveOptions::default()
+        };
+        let mut stats = SolveStats {
+            struct_size: size_of::<SolveStats>(),
+            ..SolveStats::default()
+        };
+        assert_eq!(graph_solve(handle, &options, &mut stats), COMPASS_OK);
+    }
+
+    #[test]
+    fn reports_only_the_edges_a_weight_change_moved() {
+        let (graph, first) = two_loops();
+        let edges = graph.num_edges();
+        let handle = GraphContext::into_raw(graph);
+        let generation = |handle| {
+            let mut generation = u64::MAX;
+            assert_eq!(
+                graph_residual_generation(handle, &mut generation),
+                COMPASS_OK
+            );
+            generation
+        };
+        assert_eq!(generation(handle), 0);
+
+        // Everything is new after the first solve.
+        solve(handle);
+        assert_eq!(generation(handle), 1);
+        let ctx = unsafe { &mut *handle };
+        assert_eq!(ctx.changed_residuals(1e-9).len(), edges);
+
+        // Down-weighting a shot of the first loop moves its misclosure around that loop
+        // only: the second loop translates as a whole and the joining shot has no residual.
+        ctx.recompute_weights(|e, w| if e == first[1] { 0.25 * w } else { w })
+            .unwrap();
+        solve(handle);
+        assert_eq!(generation(handle), 2);
+        let ctx = unsafe { &*handle };
+        let changed = ctx.changed_residuals(1e-9);
+        assert_eq!(changed.iter().map(|c| c.0).collect::<Vec<_>>(), first);
+
+        let mut count = -1;
+        let (mut indices, mut values) = ([-1; 4], [0.0; 8]);
+        assert_eq!(
+            graph_changed_residuals(
+                handle,
+                1e-9,
+                indices.as_mut_ptr(),
+                values.as_mut_ptr(),
+                0,
+                &mut count
+            ),
+            COMPASS_ERR_BUFFER_TOO_SMALL
+        );
+        assert_eq!((count, indices), (4, [-1; 4]));
+        assert_eq!(
+            graph_changed_residuals(
+                handle,
+                1e-9,
+                indices.as_mut_ptr(),
+                values.as_mut_ptr(),
+                count,
+                &mut count
+            ),
+            COMPASS_OK
+        );
+        assert_eq!(indices.map(|e| e as usize).to_vec(), first);
+        let residuals = ctx.solution().unwrap().residuals(ctx.graph());
+        for (i, &e) in first.iter().enumerate() {
+            assert_eq!((values[2 * i], values[2 * i + 1]), residuals[e]);
+        }
+
+        // A solve that changes nothing reports nothing.
+        solve(handle);
+        assert_eq!(generation(handle), 3);
+        assert!(unsafe { &*handle }.changed_residuals(1e-9).is_empty());
+        assert_eq!(
+            graph_changed_residuals(
+                handle,
+                f64::NAN,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+                0,
+                std::ptr::null_mut()
+            ),
+            COMPASS_ERR_INVALID_ARGUMENT
+        );
+        graph_free(handle);
+    }
+}
@@ -0,0 +1,493 @@
+//! Estimation of scale factors of the observations, e.g. of a tape later found to be
+//! mis-calibrated by an unknown constant factor.
+//!
+//! Each group `k` of observations gets an unknown multiplier `s_k`, the edges observing
+//! `x_to - x_from = s_k (dx, dy)`. The scale couples the X and Y axes, which are otherwise
+//! solved apart, so it is estimated by Gauss-Newton iterations around the usual linear
+//! solve instead of as one more unknown of the normal equations. For fixed scales the
+//! coordinates follow from one adjustment, and their derivative by `s_k` from one more,
+//! `N x_k = Aᵀ W d_k` with the observations `d_k` of the group alone and the fixed
+//! vertices at the origin. The residual derivatives `J_k = A x_k - d_k` then give the step
+//! `(Jᵀ W J) Δs = -Jᵀ W r`.
+//!
+//! The residuals are linear in the scales, so the first step lands on the estimate up to
+//! the solver tolerance and the next one confirms it. `σ₀² (Jᵀ W J)⁻¹` is the covariance of
+//! the scales, `σ₀²` being the variance factor of the adjustment from its residuals.
+//!
+//! Loops close whatever the scale: only the distances between fixed vertices measure it, so
+//! a group whose scale they do not determine fails the estimation.
+
+use crate::{
+    COMPASS_ERR_BUFFER_TOO_SMALL, COMPASS_ERR_INVALID_ARGUMENT, COMPASS_ERR_PANIC, Graph,
+    GraphContext, Solution, SolveError, write_column,
+};
+use nalgebra::{DMatrix, DVector};
+use std::ffi::{c_double, c_int};
+
+/// Options of [`Graph::estimate_scale`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScaleOptions {
+    /// Maximum number of solver iterations of each adjustment.
+    pub iterations: usize,
+    /// Solver residual tolerance.
+    pub tolerance: f64,
+    /// Maximum number of Gauss-Newton rounds.
+    pub max_rounds: usize,
+    /// The estimation has converged once no scale moves by more than this in a round.
+    pub convergence: f64,
+}
+
+impl Default for ScaleOptions {
+    fn default() -> Self {
+        ScaleOptions {
+            iterations: 60_000,
+            tolerance: 1e-8,
+            max_rounds: 10,
+            convergence: 1e-9,
+        }
+    }
+}
+
+/// Estimated scale factor of one group.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScaleFactor {
+    /// Group id, 0 for a single global scale.
+    pub group: i32,
+    /// Number of enabled edges in the group.
+    pub edges: usize,
+    /// Multiplier of the observations of the group.
+    pub scale: f64,
+    /// Approximate standard error of the scale.
+    pub std_error: f64,
+}
+
+/// Result of [`Graph::estimate_scale`].
+#[derive(Debug, Clone)]
+pub struct ScaleEstimate {
+    /// One entry per group, by increasing group id.
+    pub factors: Vec<ScaleFactor>,
+    /// Number of Gauss-Newton rounds run.
+    pub rounds: usize,
+    /// Whether the scales converged within [`ScaleOptions::max_rounds`].
+    pub converged: bool,
+    /// A-posteriori variance factor `σ₀²` of the adjustment with the estimated scales.
+    pub variance_factor: f64,
+    /// Adjustment with the observations scaled by the last estimate.
+    pub solution: Solution,
+}
+
+impl Graph {
+    /// Estimates one scale factor of the observations per group of `group_id`, which holds
+    /// the group of each edge, or a single global one without groups, jointly with the
+    /// coordinates. The graph is left untouched. See the [module documentation](self).
+    pub fn estimate_scale(
+        &self,
+        group_id: Option<&[i32]>,
+        options: &ScaleOptions,
+    ) -> Result<ScaleEstimate, SolveError> {
+        estimate(self, &[], group_id, options)
+    }
+}
+
+impl GraphContext {
+    /// [`Graph::estimate_scale`] over the enabled edges of the adjusted graph, one scale per
+    /// group of [`GraphContext::set_edge_groups`] if `per_group`, a global one otherwise.
+    pub fn estimate_scale(
+        &self,
+        per_group: bool,
+        options: &ScaleOptions,
+    ) -> Result<ScaleEstimate, SolveError> {
+        let group_id: Vec<i32> = (0..self.graph.num_edges())
+            .map(|e| self.group_id.get(e).copied().unwrap_or(0))
+            .collect();
+        let group_id = per_group.then_some(&group_id[..]);
+        estimate(
+            &self.adjusted_graph(),
+            &self.edge_enabled,
+            group_id,
+            options,
+        )
+    }
+}
+
+fn estimate(
+    graph: &Graph,
+    enabled: &[bool],
+    group_id: Option<&[i32]>,
+    options: &ScaleOptions,
+) -> Result<ScaleEstimate, SolveError> {
+    let invalid = |message: String| SolveError {
+        code: COMPASS_ERR_INVALID_ARGUMENT,
+        message,
+    };
+    let edges: Vec<usize> = (0..graph.num_edges())
+        .filter(|&e| enabled.get(e).copied().unwrap_or(true))
+        .collect();
+    let group_id = match group_id {
+        Some(group_id) if group_id.len() != graph.num_edges() => {
+            return Err(invalid(format!(
+                "{} group ids for {} edges",
+                group_id.len(),
+                graph.num_edges()
+            )));
+        }
+        Some(group_id) => group_id.to_vec(),
+        None => vec![0; graph.num_edges()],
+    };
+    let mut ids: Vec<i32> = edges.iter().map(|&e| group_id[e]).collect();
+    ids.sort_unstable();
+    ids.dedup();
+    let group_of: Vec<usize> = (0..graph.num_edges())
+        .map(|e| ids.binary_search(&group_id[e]).unwrap_or(0))
+        .collect();
+    let k = ids.len();
+
+    // Derivative of the coordinates by each scale: the adjustment of the observations of
+    // the group alone, the fixed vertices at the origin.
+    let system = graph.normal_equations(enabled)?;
+    let mut derivatives = Vec::with_capacity(k);
+    for g in 0..k {
+        let mut part = graph.clone();
+        part.x.fill(0.0);
+        part.y.fill(0.0);
+        for e in (0..graph.num_edges()).filter(|&e| group_of[e] != g) {
+            (part.dx[e], part.dy[e]) = (0.0, 0.0);
+        }
+        let system = part.normal_equations(enabled)?;
+        let solved = part.solve_system(
+            &system,
+            &part.x,
+            &part.y,
+            options.iterations,
+            options.tolerance.into(),
+            Default::default(),
+            &|| false,
+        )?;
+        derivatives.push((solved.x, solved.y));
+    }
+    // Residual derivatives, per edge and axis.
+    let jacobian = |e: usize, g: usize| {
+        let (u, v) = (graph.from[e], graph.to[e]);
+        let (x, y) = &derivatives[g];
+        let own = (group_of[e] == g) as u8 as f64;
+        (
+            x[v] - x[u] - own * graph.dx[e],
+            y[v] - y[u] - own * graph.dy[e],
+        )
+    };
+    let mut normal = DMatrix::<f64>::zeros(k, k);
+    // Weighted square sum of the observations of each group, against which a vanishing
+    // derivative is measured.
+    let mut size = vec![0.0; k];
+    for &e in &edges {
+        let w = graph.weight[e];
+        size[group_of[e]] += w * (graph.dx[e].powi(2) + graph.dy[e].powi(2));
+        for a in 0..k {
+            let ja = jacobian(e, a);
+            for b in 0..k {
+                let jb = jacobian(e, b);
+                normal[(a, b)] += w * (ja.0 * jb.0 + ja.1 * jb.1);
+            }
+        }
+    }
+    // A component whose fixed vertices all sit at one position can be scaled about it: the
+    // residuals of noisy data would then shrink with the scale down to 0.
+    let spread = anchor_spread(graph, &edges);
+    let anchored = |g: usize| {
+        edges
+            .iter()
+            .any(|&e| group_of[e] == g && spread[graph.from[e]])
+    };
+    let undetermined = (0..k).find(|&g| !anchored(g) || normal[(g, g)] <= 1e-9 * size[g]);
+    let cholesky = match undetermined {
+        None => normal.cholesky(),
+        Some(_) => None,
+    };
+    let Some(cholesky) = cholesky else {
+        let group = ids[undetermined.unwrap_or(0)];
+        return Err(invalid(format!(
+            "the fixed vertices do not determine the scale of group {group}"
+        )));
+    };
+
+    let mut scale = vec![1.0; k];
+    let mut scaled = graph.clone();
+    let (mut x0, mut y0) = (graph.x.clone(), graph.y.clone());
+    let mut rounds = 0;
+    let mut converged = false;
+    let mut solution = Solution::default();
+    let mut square = 0.0;
+    while rounds < options.max_rounds && !converged {
+        rounds += 1;
+        for &e in &edges {
+            let s = scale[group_of[e]];
+            (scaled.dx[e], scaled.dy[e]) = (s * graph.dx[e], s * graph.dy[e]);
+        }
+        let system = scaled.normal_equations(enabled)?;
+        solution = scaled.solve_system(
+            &system,
+            &x0,
+            &y0,
+            options.iterations,
+            options.tolerance.into(),
+            Default::default(),
+            &|| false,
+        )?;
+        (x0, y0) = (solution.x.clone(), solution.y.clone());
+
+        let mut gradient = DVector::<f64>::zeros(k);
+        square = 0.0;
+        for &e in &edges {
+            let (u, v, w) = (scaled.from[e], scaled.to[e], scaled.weight[e]);
+            let rx = solution.x[v] - solution.x[u] - scaled.dx[e];
+            let ry = solution.y[v] - solution.y[u] - scaled.dy[e];
+            square += w * (rx * rx + ry * ry);
+            for (g, gradient) in gradient.iter_mut().enumerate() {
+                let j = jacobian(e, g);
+                *gradient += w * (j.0 * rx + j.1 * ry);
+            }
+        }
+        let step = cholesky.solve(&-gradient);
+        converged = step.amax() <= options.convergence;
+        for (s, step) in scale.iter_mut().zip(step.iter()) {
+            *s += step;
+        }
+    }
+
+    // Observations (edge axes) less unknowns: the free coordinates of both axes, but for
+    // the datum of each free network, and the scales.
+    let unknowns = 2 * (system.size() - system.null_space.dimension()) + k;
+    let redundancy = (2 * edges.len()).saturating_sub(unknowns);
+    let variance_factor = match redundancy {
+        0 => 0.0,
+        r => square / r as f64,
+    };
+    let covariance = cholesky.inverse();
+    let factors = (0..k)
+        .map(|g| ScaleFactor {
+            group: ids[g],
+            edges: edges.iter().filter(|&&e| group_of[e] == g).count(),
+            scale: scale[g],
+            std_error: (variance_factor * covariance[(g, g)]).sqrt(),
+        })
+        .collect();
+    Ok(ScaleEstimate {
+        factors,
+        rounds,
+        converged,
+        variance_factor,
+        solution,
+    })
+}
+
+/// Whether the component of each vertex along `edges` holds fixed vertices at two distinct
+/// positions.
+fn anchor_spread(graph: &Graph, edges: &[usize]) -> Vec<bool> {
+    let n = graph.num_vertices();
+    let mut neighbors = vec![Vec::new(); n];
+    for &e in edges {
+        neighbors[graph.from[e]].push(graph.to[e]);
+        neighbors[graph.to[e]].push(graph.from[e]);
+    }
+    let mut spread = vec![false; n];
+    let mut seen = vec![false; n];
+    for root in 0..n {
+        if seen[root] {
+            continue;
+        }
+        seen[root] = true;
+        let (mut component, mut anchor, mut spreads) = (vec![root], None, false);
+        let mut k = 0;
+        while let Some(&v) = component.get(k) {
+            k += 1;
+            if graph.fixed[v] {
+                let position = (graph.x[v], graph.y[v]);
+                spreads |= *anchor.get_or_insert(position) != position;
+            }
+            for &w in &neighbors[v] {
+                if !seen[w] {
+                    seen[w] = true;
+                    component.push(w);
+                }
+            }
+        }
+        for v in component {
+            spread[v] = spreads;
+        }
+    }
+    spread
+}
+
+/// Estimates the scale factors of the observations of the graph behind `handle`, see
+/// [`GraphContext::estimate_scale`], and copies them into parallel caller buffers of
+/// `capacity` elements by increasing group id. `per_group` != 0 estimates one scale per
+/// edge group instead of a global one. Any output pointer may be null to skip that column.
+/// The other options are the [`ScaleOptions`] defaults.
+///
+/// # Returns
+///
+/// * The number of scales written.
+/// * [`COMPASS_ERR_BUFFER_TOO_SMALL`] if `capacity` is below the number of scales, 1 or
+///   [`crate::graph_group_count`] per group; nothing is written.
+/// * [`COMPASS_ERR_INVALID_ARGUMENT`] for a null handle or a scale the fixed vertices do not
+///   determine, or the error of a failed solve.
+#[unsafe(no_mangle)]
+#[allow(clippy::too_many_arguments)]
+pub extern "C" fn graph_estimate_scale(
+    handle: *const GraphContext,
+    per_group: c_int,
+    iterations: c_int,
+    tolerance: c_double,
+    capacity: c_int,
+    out_group: *mut c_int,
+    out_scale: *mut c_double,
+    out_std_error: *mut c_double,
+) -> c_int {
+    let Some(ctx) = (unsafe { handle.as_ref() }) else {
+        return COMPASS_ERR_INVALID_ARGUMENT;
+    };
+    let result = std::panic::catch_unwind(|| {
+        let options = ScaleOptions {
+            iterations: iterations.max(0) as usize,
+            tolerance,
+            ..Default::default()
+        };
+        let estimate = match ctx.estimate_scale(per_group != 0, &options) {
+            Ok(estimate) => estimate,
+            Err(err) => return err.code,
+        };
+        let factors = &estimate.factors;
+        if (capacity.max(0) as usize) < factors.len() {
+            return COMPASS_ERR_BUFFER_TOO_SMALL;
+        }
+        write_column(out_group, factors, |f| f.group);
+        write_column(out_scale, factors, |f| f.scale);
+        write_column(out_std_error, factors, |f| f.std_error);
+        factors.len() as c_int
+    });
+
+    result.unwrap_or_else(|_| {
+        eprintln!("Panic caught in graph_estimate_scale");
+        COMPASS_ERR_PANIC
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A `side` x `side` grid of stations 10 m apart, fixed at its four corners, observed
+    /// with 5 mm of noise per axis by a tape reading `1 / scale` of the distances. Shots
+    /// along X are in group 0, shots along Y in group 1.
+    fn taped_grid(side: usize, scale: [f64; 2]) -> (Graph, Vec<i32>) {
+        // Standard normal deviates (xorshift64 and Box-Muller).
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let mut uniform = || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state >> 11) as f64 / (1u64 << 53) as f64
+        };
+        let mut normal = || {
+            let (u, v) = (1.0 - uniform(), uniform());
+            0.005 * (-2.0 * u.ln()).sqrt() * (std::f64::consts::TAU * v).cos()
+        };
+        let mut graph = Graph::default();
+        let corners = [0, side - 1, side * (side - 1), side * side - 1];
+        for i in 0..side * side {
+            let (x, y) = (10.0 * (i % side) as f64, 10.0 * (i / side) as f64);
+            graph.add_vertex(x, y, 0.0, corners.contains(&i));
+        }
+        let mut groups = Vec::new();
+        let weight = 1.0 / 0.005f64.powi(2);
+        for i in 0..side * side {
+            if i % side + 1 < side {
+                let (dx, dy) = (10.0 / scale[0] + normal(), normal());
+                graph.add_edge(i, i + 1, dx, dy, 0.0, weight);
+                groups.push(0);
+            }
+            if i + side < side * side {
+                let (dx, dy) = (normal(), 10.0 / scale[1] + normal());
+                graph.add_edge(i, i + side, dx, dy, 0.0, weight);
+                groups.push(1);
+            }
+        }
+        (graph, groups)
+    }
+
+    /// The weights being large, the solver tolerance is loose in absolute terms.
+    const OPTIONS: ScaleOptions = ScaleOptions {
+        iterations: 60_000,
+        tolerance: 1e-4,
+        max_rounds: 10,
+        convergence: 1e-9,
+    };
+
+    #[test]
+    fn recovers_a_global_scale() {
+        let (graph, _) = taped_grid(10, [1.003, 1.003]);
+        let estimate = graph.estimate_scale(None, &OPTIONS).unwrap();
+        assert!(estimate.converged && estimate.rounds <= 3, "{estimate:?}");
+        let [factor] = &estimate.factors[..] else {
+            panic!("{:?}", estimate.factors);
+        };
+        assert_eq!((factor.group, factor.edges), (0, 180));
+        assert!(
+            factor.std_error > 0.0 && factor.std_error < 1e-4,
+            "{factor:?}"
+        );
+        assert!(
+            (factor.scale - 1.003).abs() < 4.0 * factor.std_error,
+            "{factor:?}"
+        );
+        // The noise is that of the weights.
+        assert!((estimate.variance_factor - 1.0).abs() < 0.3);
+        // The scaled adjustment puts the stations back on the grid.
+        for i in 0..100 {
+            let (x, y) = (10.0 * (i % 10) as f64, 10.0 * (i / 10) as f64);
+            let (dx, dy) = (estimate.solution.x[i] - x, estimate.solution.y[i] - y);
+            assert!(dx.hypot(dy) < 0.02, "{i}: {dx} {dy}");
+        }
+
+        // A single anchor leaves the scale free.
+        let mut anchored = graph.clone();
+        anchored.fixed[1..].fill(false);
+        let err = anchored.estimate_scale(None, &OPTIONS).unwrap_err();
+        assert_eq!(err.code, COMPASS_ERR_INVALID_ARGUMENT);
+    }
+
+    #[test]
+    fn ffi_writes_a_scale_per_group() {
+        let (graph, groups) = taped_grid(10, [1.003, 1.0]);
+        let handle = GraphContext::into_raw(graph);
+        unsafe { &mut *handle }.set_edge_groups(groups).unwrap();
+        let (mut group, mut scale, mut std_error) = ([-1; 2], [0.0; 2], [0.0; 2]);
+        let call = |capacity, group: *mut c_int, scale: *mut c_double, error: *mut c_double| {
+            graph_estimate_scale(handle, 1, 60_000, 1e-4, capacity, group, scale, error)
+        };
+        let null = std::ptr::null_mut();
+        assert_eq!(
+            call(1, group.as_mut_ptr(), null, null),
+            COMPASS_ERR_BUFFER_TOO_SMALL
+        );
+        assert_eq!(group, [-1; 2]);
+        let code = call(
+            2,
+            group.as_mut_ptr(),
+            scale.as_mut_ptr(),
+            std_error.as_mut_ptr(),
+        );
+        assert_eq!((code, group), (2, [0, 1]));
+        for (g, expected) in [1.003, 1.0].into_iter().enumerate() {
+            assert!(std_error[g] > 0.0 && std_error[g] < 1e-4);
+            assert!(
+                (scale[g] - expected).abs() < 4.0 * std_error[g],
+                "{scale:?}"
+            );
+        }
+        crate::graph_free(handle);
+        let null_handle = std::ptr::null();
+        let code = graph_estimate_scale(null_handle, 0, 0, 0.0, 0, group.as_mut_ptr(), null, null);
+        assert_eq!(code, COMPASS_ERR_INVALID_ARGUMENT);
+    }
+}
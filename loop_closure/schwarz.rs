@@ -0,0 +1,221 @@
+//! Additive Schwarz preconditioner, the [`crate::Preconditioner::AdditiveSchwarz`] of CG.
+//!
+//! The free vertices are split into blocks of neighbouring stations by region growing over
+//! the graph of the normal matrix. Each block is extended by a few layers of its neighbours
+//! (the overlap) and the principal sub-matrix of the extended block is factored densely.
+//! The preconditioner sums the block solves of the residual, `sum R_i^T A_i^-1 R_i`, which
+//! is symmetric positive definite; without overlap it is block Jacobi.
+
+use crate::amg::DenseCholesky;
+use nalgebra_sparse::CsrMatrix;
+
+/// Factored blocks of a normal matrix.
+pub(crate) struct Schwarz {
+    blocks: Vec<Block>,
+}
+
+struct Block {
+    /// Rows of the block, the overlap included.
+    rows: Vec<usize>,
+    factor: DenseCholesky,
+}
+
+impl Schwarz {
+    /// Splits the rows of `a` into blocks of `block_size` rows, each extended by `overlap`
+    /// layers of neighbours, and factors them.
+    pub(crate) fn new(a: &CsrMatrix<f64>, block_size: usize, overlap: usize) -> Schwarz {
+        let n = a.nrows();
+        let (offsets, columns) = (a.row_offsets(), a.col_indices());
+        let neighbours = |i: usize| {
+            columns[offsets[i]..offsets[i + 1]]
+                .iter()
+                .copied()
+                .filter(move |&j| j != i)
+        };
+
+        let mut assigned = vec![false; n];
+        // Block that last took each row, to test membership without clearing.
+        let mut member = vec![usize::MAX; n];
+        let mut position = vec![usize::MAX; n];
+        let mut blocks = Vec::new();
+        for seed in 0..n {
+            if assigned[seed] {
+                continue;
+            }
+            let b = blocks.len();
+            let mut rows = vec![seed];
+            assigned[seed] = true;
+            member[seed] = b;
+
+            // Region growing, breadth first over the rows no block owns yet.
+            let mut next = 0;
+            while next < rows.len() && rows.len() < block_size {
+                for j in neighbours(rows[next]) {
+                    if !assigned[j] && rows.len() < block_size {
+                        assigned[j] = true;
+                        member[j] = b;
+                        rows.push(j);
+                    }
+                }
+                next += 1;
+            }
+
+            let mut layer = 0..rows.len();
+            for _ in 0..overlap {
+                let end = rows.len();
+                for k in layer {
+                    for j in neighbours(rows[k]) {
+                        if member[j] != b {
+                            member[j] = b;
+                            rows.push(j);
+                        }
+                    }
+                }
+                layer = end..rows.len();
+            }
+
+            let factor = factor(a, &rows, &mut position);
+            blocks.push(Block { rows, factor });
+        }
+        Schwarz { blocks }
+    }
+
+    /// Factors again the blocks holding any of `rows`, after the values of `a` changed in
+    /// those rows and columns only, as a change of edge weight does. The blocks depend on the
+    /// pattern alone and are kept, so the result is the preconditioner [`Schwarz::new`] builds
+    /// from the new values.
+    pub(crate) fn refactor(&mut self, a: &CsrMatrix<f64>, rows: &[usize]) {
+        let mut position = vec![usize::MAX; a.nrows()];
+        for block in &mut self.blocks {
+            if block.rows.iter().any(|i| rows.contains(i)) {
+                block.factor = factor(a, &block.rows, &mut position);
+            }
+        }
+    }
+
+    /// `z = M^-1 r`, the sum of the block solves.
+    pub(crate) fn apply(&self, r: &[f64], z: &mut [f64]) {
+        for z in z.iter_mut() {
+            *z = 0.0;
+        }
+        for block in &self.blocks {
+            let local: Vec<f64> = block.rows.iter().map(|&i| r[i]).collect();
+            for (&i, x) in block.rows.iter().zip(block.factor.solve(&local)) {
+                z[i] += x;
+            }
+        }
+    }
+}
+
+/// Dense factor of the principal sub-matrix of `a` over `rows`. `position` maps the rows of
+/// `a` to those of the block, `usize::MAX` outside; it is left so.
+fn factor(a: &CsrMatrix<f64>, rows: &[usize], position: &mut [usize]) -> DenseCholesky {
+    let (offsets, columns, values) = (a.row_offsets(), a.col_indices(), a.values());
+    let m = rows.len();
+    for (k, &i) in rows.iter().enumerate() {
+        position[i] = k;
+    }
+    let mut dense = vec![0.0; m * m];
+    for (k, &i) in rows.iter().enumerate() {
+        for e in offsets[i]..offsets[i + 1] {
+            if position[columns[e]] != usize::MAX {
+                dense[k * m + position[columns[e]]] += values[e];
+            }
+        }
+    }
+    for &i in rows {
+        position[i] = usize::MAX;
+    }
+    DenseCholesky::factor(m, dense)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra::DVector;
+    use nalgebra_sparse::CooMatrix;
+
+    /// Normal matrix of a `side` x `side` grid of free stations tied to the ground along
+    /// one edge.
+    fn grid(side: usize) -> CsrMatrix<f64> {
+        let n = side * side;
+        let mut coo = CooMatrix::new(n, n);
+        let mut couple = |i: usize, j: usize, w: f64| {
+            coo.push(i, i, w);
+            coo.push(j, j, w);
+            coo.push(i, j, -w);
+            coo.push(j, i, -w);
+        };
+        for i in 0..n {
+            if i % side + 1 < side {
+                couple(i, i + 1, 1.0 + (i % 4) as f64);
+            }
+            if i + side < n {
+                couple(i, i + side, 2.0);
+            }
+        }
+        for i in 0..side {
+            coo.push(i, i, 1.0);
+        }
+        CsrMatrix::from(&coo)
+    }
+
+    #[test]
+    fn blocks_partition_the_rows_and_overlap_grows_them() {
+        let a = grid(12);
+        let disjoint = Schwarz::new(&a, 16, 0);
+        let mut owners = vec![0; 144];
+        for block in &disjoint.blocks {
+            assert!(block.rows.len() <= 16);
+            for &i in &block.rows {
+                owners[i] += 1;
+            }
+        }
+        assert!(owners.iter().all(|&o| o == 1));
+
+        let overlapping = Schwarz::new(&a, 16, 1);
+        assert_eq!(overlapping.blocks.len(), disjoint.blocks.len());
+        for (wide, narrow) in overlapping.blocks.iter().zip(&disjoint.blocks) {
+            assert_eq!(wide.rows[..narrow.rows.len()], narrow.rows);
+            assert!(wide.rows.len() > narrow.rows.len());
+        }
+    }
+
+    #[test]
+    fn application_is_symmetric_and_exact_for_one_block() {
+        let a = grid(8);
+        let u = DVector::from_fn(64, |i, _| ((i * 37) % 11) as f64 - 5.0);
+        let v = DVector::from_fn(64, |i, _| (i as f64 * 0.2).sin());
+        let schwarz = Schwarz::new(&a, 10, 2);
+        let (mut mu, mut mv) = (DVector::zeros(64), DVector::zeros(64));
+        schwarz.apply(u.as_slice(), mu.as_mut_slice());
+        schwarz.apply(v.as_slice(), mv.as_mut_slice());
+        assert!((mu.dot(&v) - u.dot(&mv)).abs() < 1e-10 * mu.norm() * v.norm());
+        assert!(mu.dot(&u) > 0.0);
+
+        let whole = Schwarz::new(&a, 64, 0);
+        whole.apply(v.as_slice(), mv.as_mut_slice());
+        let exact = nalgebra::DMatrix::from(&a).cholesky().unwrap().solve(&v);
+        assert!((mv - exact).norm() < 1e-10);
+    }
+
+    #[test]
+    fn refactoring_matches_a_fresh_factorization() {
+        let mut a = grid(10);
+        let mut schwarz = Schwarz::new(&a, 12, 1);
+        // Coupling of rows 44 and 45 raised by 3.
+        for (i, j, w) in [(44, 44, 3.0), (45, 45, 3.0), (44, 45, -3.0), (45, 44, -3.0)] {
+            match a.get_entry_mut(i, j) {
+                Some(nalgebra_sparse::SparseEntryMut::NonZero(value)) => *value += w,
+                _ => unreachable!("cell of the grid"),
+            }
+        }
+        schwarz.refactor(&a, &[44, 45]);
+        let fresh = Schwarz::new(&a, 12, 1);
+        let r = DVector::from_fn(100, |i, _| (i as f64 * 0.3).cos());
+        let (mut z, mut expected) = (DVector::zeros(100), DVector::zeros(100));
+        schwarz.apply(r.as_slice(), z.as_mut_slice());
+        fresh.apply(r.as_slice(), expected.as_mut_slice());
+        assert_eq!(z, expected);
+    }
+}
@@ -0,0 +1,419 @@
+//! Loop misclosure screening before the adjustment, a first line of defence against typos.
+//!
+//! Every fundamental loop (see [`crate::report`]) is closed with the raw observations and its
+//! misclosure expressed in parts per million of its length; loops above a threshold are
+//! flagged. Nothing is solved, so the screening is cheap enough to run whenever a file is
+//! opened.
+//!
+//! The suspect edge of a flagged loop comes from a small analysis of the loops through each
+//! of its edges. Correcting the observation of edge `e` by the misclosure of the flagged loop
+//! closes that loop, and shifts the misclosure of every other loop through `e` by the same
+//! vector. A typo shows as the edge whose correction also brings the other loops closer to
+//! closure: the edges are ranked by the decrease of the summed misclosure of their loops,
+//! the loops under the threshold counting as closed.
+//!
+//! Only the flagged loops are walked. The length of every loop and the number of loops
+//! through every tree edge follow from the lowest common ancestors of the closing edges'
+//! ends, found in one pass over the spanning forest, so the screening runs in near-linear
+//! time.
+
+use crate::report::{SpanningForest, ppm};
+use crate::{COMPASS_ERR_INVALID_ARGUMENT, COMPASS_ERR_PANIC, Graph, GraphContext};
+use std::collections::HashMap;
+use std::ffi::{c_double, c_int};
+use std::slice;
+
+/// A loop whose misclosure exceeds the screening threshold.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct LoopWarning {
+    /// Edge closing the loop over the spanning forest.
+    pub closing_edge: c_int,
+    /// Number of edges in the loop.
+    pub edges: c_int,
+    /// 1 when the loop is a traverse between two anchors.
+    pub through_anchors: c_int,
+    /// Sum of the 3D shot lengths of the loop.
+    pub length: c_double,
+    /// Misclosure of the observations along the loop, in the direction of the closing edge.
+    pub misclosure_x: c_double,
+    pub misclosure_y: c_double,
+    /// Length of the misclosure vector.
+    pub misclosure: c_double,
+    /// [`LoopWarning::misclosure`] in parts per million of [`LoopWarning::length`].
+    pub ppm: c_double,
+    /// The most likely culprit: the edge of the loop whose correction most reduces the
+    /// misclosure of the loops through it, see the module documentation.
+    pub worst_edge: c_int,
+    /// Decrease of the summed misclosure of the loops through
+    /// [`LoopWarning::worst_edge`] when it is corrected to close this loop.
+    pub improvement: c_double,
+}
+
+impl Graph {
+    /// Flags the fundamental loops whose misclosure exceeds `threshold_ppm` parts per million
+    /// of their length, by decreasing [`LoopWarning::ppm`]. Nothing is solved.
+    pub fn screen_loops(&self, threshold_ppm: f64) -> Vec<LoopWarning> {
+        let enabled: Vec<usize> = (0..self.num_edges()).collect();
+        screen_loops(self, &enabled, threshold_ppm)
+    }
+}
+
+impl GraphContext {
+    /// [`Graph::screen_loops`] over the enabled edges.
+    pub fn screen_loops(&self, threshold_ppm: f64) -> Vec<LoopWarning> {
+        let enabled: Vec<usize> = (0..self.graph.num_edges())
+            .filter(|&e| self.is_edge_enabled(e))
+            .collect();
+        screen_loops(&self.graph, &enabled, threshold_ppm)
+    }
+}
+
+fn screen_loops(graph: &Graph, enabled: &[usize], threshold_ppm: f64) -> Vec<LoopWarning> {
+    let forest = SpanningForest::new(graph, enabled);
+    let length =
+        |e: usize| (graph.dx[e].powi(2) + graph.dy[e].powi(2) + graph.dz[e].powi(2)).sqrt();
+    // Length of the tree path from the root of its tree to each vertex.
+    let mut distance = vec![0.0; forest.order.len()];
+    for &v in &forest.order {
+        if let Some((u, Some(e))) = forest.parent[v] {
+            distance[v] = distance[u] + length(e);
+        }
+    }
+
+    let closing: Vec<usize> = forest.closing_edges(enabled).collect();
+    let ancestors = common_ancestors(graph, &forest, &closing);
+    // Loops through the tree edge above each vertex: the closing edges with exactly one end
+    // below it.
+    let mut crossing = vec![0i64; forest.order.len()];
+    for (&e, &a) in closing.iter().zip(&ancestors) {
+        crossing[graph.from[e]] += 1;
+        crossing[graph.to[e]] += 1;
+        crossing[a] -= 2;
+    }
+    for &v in forest.order.iter().rev() {
+        if let Some((u, _)) = forest.parent[v] {
+            crossing[u] += crossing[v];
+        }
+    }
+
+    struct Flagged {
+        closing_edge: usize,
+        length: f64,
+        misclosure: (f64, f64),
+        edges: Vec<(usize, f64)>,
+        through_anchors: bool,
+    }
+    let flagged: Vec<Flagged> = closing
+        .iter()
+        .zip(&ancestors)
+        .filter_map(|(&e, &a)| {
+            let (from, to) = (graph.from[e], graph.to[e]);
+            let length = distance[from] + distance[to] - 2.0 * distance[a] + length(e);
+            let misclosure = forest.misclosure(graph, e);
+            (ppm(misclosure.0.hypot(misclosure.1), length) > threshold_ppm).then(|| {
+                let cycle = forest.cycle(graph, e);
+                Flagged {
+                    closing_edge: e,
+                    length,
+                    misclosure,
+                    edges: cycle.edges,
+                    through_anchors: cycle.through_anchors,
+                }
+            })
+        })
+        .collect();
+
+    // Flagged loops through each of their edges, with the sign of the edge in them.
+    let mut through: HashMap<usize, Vec<(usize, f64)>> = HashMap::new();
+    for (k, loop_) in flagged.iter().enumerate() {
+        for &(e, sign) in &loop_.edges {
+            through.entry(e).or_default().push((k, sign));
+        }
+    }
+    // Number of loops through an edge: its own for a closing edge.
+    let loops = |e: usize| match forest.tree_edge[e] {
+        true => {
+            let below = match forest.parent[graph.to[e]] {
+                Some((_, Some(t))) if t == e => graph.to[e],
+                _ => graph.from[e],
+            };
+            crossing[below] as usize
+        }
+        false => 1,
+    };
+
+    let norm = |(x, y): (f64, f64)| x.hypot(y);
+    let mut warnings: Vec<LoopWarning> = flagged
+        .iter()
+        .map(|loop_| {
+            let (mx, my) = loop_.misclosure;
+            let misclosure = norm(loop_.misclosure);
+            // Correcting edge `e`, of sign `s` here, by `-s m` closes this loop and moves the
+            // misclosure of a loop where it has sign `t` by `-t s m`. The loops under the
+            // threshold count as closed, so each gets `|m|` of misclosure.
+            let improvement = |e: usize, s: f64| -> f64 {
+                let flagged_through = &through[&e];
+                let closed = loops(e).saturating_sub(flagged_through.len());
+                flagged_through
+                    .iter()
+                    .map(|&(k, t)| {
+                        let (kx, ky) = flagged[k].misclosure;
+                        norm((kx, ky)) - norm((kx - t * s * mx, ky - t * s * my))
+                    })
+                    .sum::<f64>()
+                    - closed as f64 * misclosure
+            };
+            let (worst_edge, improvement) = loop_
+                .edges
+                .iter()
+                .map(|&(e, s)| (e, improvement(e, s)))
+                .fold(
+                    (loop_.closing_edge, f64::NEG_INFINITY),
+                    |best, (e, score)| match score > best.1 || (score == best.1 && e < best.0) {
+                        true => (e, score),
+                        false => best,
+                    },
+                );
+            LoopWarning {
+                closing_edge: loop_.closing_edge as c_int,
+                edges: loop_.edges.len() as c_int,
+                through_anchors: loop_.through_anchors as c_int,
+                length: loop_.length,
+                misclosure_x: mx,
+                misclosure_y: my,
+                misclosure,
+                ppm: ppm(misclosure, loop_.length),
+                worst_edge: worst_edge as c_int,
+                improvement,
+            }
+        })
+        .collect();
+    warnings.sort_by(|a, b| {
+        b.ppm
+            .total_cmp(&a.ppm)
+            .then(a.closing_edge.cmp(&b.closing_edge))
+    });
+    warnings
+}
+
+/// Lowest common ancestor in `forest` of the ends of each of the `closing` edges, by
+/// Tarjan's offline algorithm.
+fn common_ancestors(graph: &Graph, forest: &SpanningForest, closing: &[usize]) -> Vec<usize> {
+    let n = forest.order.len();
+    // Breadth-first order lists the children of each vertex together: `order[children[v]]`.
+    let mut children = vec![0..0; n];
+    for (i, &v) in forest.order.iter().enumerate() {
+        if let Some((u, _)) = forest.parent[v] {
+            if children[u].is_empty() {
+                children[u] = i..i;
+            }
+            children[u].end = i + 1;
+        }
+    }
+    // Closing edges at each vertex, in compressed rows.
+    let mut query_offsets = vec![0; n + 1];
+    for &e in closing {
+        query_offsets[graph.from[e] + 1] += 1;
+        query_offsets[graph.to[e] + 1] += 1;
+    }
+    for v in 0..n {
+        query_offsets[v + 1] += query_offsets[v];
+    }
+    let mut queries = vec![0; query_offsets[n]];
+    let mut next = query_offsets.clone();
+    for (q, &e) in closing.iter().enumerate() {
+        for v in [graph.from[e], graph.to[e]] {
+            queries[next[v]] = q;
+            next[v] += 1;
+        }
+    }
+
+    let mut link: Vec<usize> = (0..n).collect();
+    let mut ancestor: Vec<usize> = (0..n).collect();
+    let mut finished = vec![false; n];
+    let mut lca = vec![usize::MAX; closing.len()];
+    let find = |link: &mut [usize], mut v: usize| {
+        while link[v] != v {
+            link[v] = link[link[v]];
+            v = link[v];
+        }
+        v
+    };
+    for &top in forest.order.iter().filter(|&&v| forest.parent[v].is_none()) {
+        let mut stack = vec![(top, children[top].clone())];
+        while let Some((v, pending)) = stack.last_mut() {
+            let v = *v;
+            if let Some(i) = pending.next() {
+                let child = forest.order[i];
+                stack.push((child, children[child].clone()));
+                continue;
+            }
+            finished[v] = true;
+            for &q in &queries[query_offsets[v]..query_offsets[v + 1]] {
+                let e = closing[q];
+                let other = if graph.from[e] == v {
+                    graph.to[e]
+                } else {
+                    graph.from[e]
+                };
+                if finished[other] && lca[q] == usize::MAX {
+                    lca[q] = ancestor[find(&mut link, other)];
+                }
+            }
+            stack.pop();
+            if let Some(&(parent, _)) = stack.last() {
+                let (a, b) = (find(&mut link, parent), find(&mut link, v));
+                link[b] = a;
+                ancestor[a] = parent;
+            }
+        }
+    }
+    lca
+}
+
+/// Screens the loops of the graph behind `handle` over its enabled edges, see
+/// [`GraphContext::screen_loops`], and fills `out_warnings` when `capacity` is large enough.
+///
+/// # Returns
+///
+/// * The number of flagged loops. They are written only if `capacity` is at least that
+///   number, so a first call with a null `out_warnings` and a capacity of 0 sizes the buffer.
+/// * [`COMPASS_ERR_INVALID_ARGUMENT`] for a null handle or a NaN threshold.
+#[unsafe(no_mangle)]
+pub extern "C" fn graph_screen_loops(
+    handle: *const GraphContext,
+    threshold_ppm: c_double,
+    out_warnings: *mut LoopWarning,
+    capacity: c_int,
+) -> c_int {
+    let Some(ctx) = (unsafe { handle.as_ref() }) else {
+        return COMPASS_ERR_INVALID_ARGUMENT;
+    };
+    if threshold_ppm.is_nan() {
+        return COMPASS_ERR_INVALID_ARGUMENT;
+    }
+    let result = std::panic::catch_unwind(|| {
+        let warnings = ctx.screen_loops(threshold_ppm);
+        let count = warnings.len();
+        if !out_warnings.is_null() && capacity.max(0) as usize >= count {
+            // Safety: The caller guarantees `capacity` writable warnings.
+            unsafe { slice::from_raw_parts_mut(out_warnings, count) }.copy_from_slice(&warnings);
+        }
+        count as c_int
+    });
+
+    result.unwrap_or_else(|_| {
+        eprintln!("Panic caught in graph_screen_loops");
+        COMPASS_ERR_PANIC
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph_free;
+
+    /// A `side` x `side` grid of 10 m shots, closing to within a few millimetres, fixed at
+    /// its corner.
+    fn grid(side: usize) -> Graph {
+        let noise = |k: usize| ((k * 7919) % 13) as f64 * 0.0005 - 0.003;
+        let mut graph = Graph::default();
+        for i in 0..side * side {
+            let (row, col) = (i / side, i % side);
+            graph.add_vertex(10.0 * col as f64, 10.0 * row as f64, 0.0, i == 0);
+        }
+        for i in 0..side * side {
+            let (row, col) = (i / side, i % side);
+            if col + 1 < side {
+                graph.add_edge(i, i + 1, 10.0 + noise(2 * i), noise(2 * i + 1), 0.0, 1.0);
+            }
+            if row + 1 < side {
+                graph.add_edge(i, i + side, noise(3 * i), 10.0 + noise(3 * i + 1), 0.0, 1.0);
+            }
+        }
+        graph
+    }
+
+    #[test]
+    fn clean_networks_raise_no_warning() {
+        assert!(grid(6).screen_loops(1000.0).is_empty());
+        // Every square misses by well over a part per million.
+        assert_eq!(grid(6).screen_loops(0.0).len(), 25);
+    }
+
+    #[test]
+    fn a_typo_is_the_worst_edge_of_every_loop_it_breaks() {
+        for typo in [0, 17, 30, 45] {
+            let mut graph = grid(6);
+            graph.dx[typo] += 1.0;
+            let warnings = graph.screen_loops(1000.0);
+            assert!(!warnings.is_empty(), "edge {typo}");
+            for warning in &warnings {
+                assert_eq!(warning.worst_edge, typo as c_int, "{warning:?}");
+                assert!(warning.improvement > 0.0);
+                assert!((warning.misclosure - 1.0).abs() < 0.02, "{warning:?}");
+                assert!((warning.ppm - 1e6 * warning.misclosure / warning.length).abs() < 1e-6);
+                // The loop length sums the observed shots, the typo included.
+                let excess = warning.length - 10.0 * warning.edges as f64;
+                assert!(excess > 0.04 && excess < 1.01, "{warning:?}");
+                assert_eq!(warning.through_anchors, 0);
+            }
+            assert!(warnings.windows(2).all(|w| w[0].ppm >= w[1].ppm));
+        }
+    }
+
+    #[test]
+    fn traverses_between_anchors_are_loops() {
+        // A straight 3-shot traverse between two anchors 30.5 m apart.
+        let mut graph = Graph::default();
+        for i in 0..4 {
+            graph.add_vertex(10.0 * i as f64, 0.0, 0.0, i == 0 || i == 3);
+        }
+        graph.x[3] = 30.5;
+        for i in 0..3 {
+            graph.add_edge(i, i + 1, 10.0, 0.0, 0.0, 1.0);
+        }
+        let warnings = graph.screen_loops(1000.0);
+        assert_eq!(warnings.len(), 1);
+        let warning = warnings[0];
+        assert_eq!(warning.through_anchors, 1);
+        assert_eq!(warning.edges, 3);
+        assert!((warning.misclosure - 0.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn ffi_sizes_and_fills_the_buffer_over_the_enabled_edges() {
+        let mut graph = grid(4);
+        graph.dy[5] += 2.0;
+        let expected = graph.screen_loops(1000.0);
+        let handle = GraphContext::into_raw(graph);
+
+        let count = graph_screen_loops(handle, 1000.0, std::ptr::null_mut(), 0);
+        assert_eq!(count as usize, expected.len());
+        let mut buffer = vec![LoopWarning::default(); count as usize];
+        let short = graph_screen_loops(handle, 1000.0, buffer.as_mut_ptr(), count - 1);
+        assert_eq!(short, count);
+        assert_eq!(buffer[0], LoopWarning::default());
+        assert_eq!(
+            graph_screen_loops(handle, 1000.0, buffer.as_mut_ptr(), count),
+            count
+        );
+        assert_eq!(buffer, expected);
+
+        unsafe { &mut *handle }.set_edge_enabled(5, false).unwrap();
+        assert_eq!(
+            graph_screen_loops(handle, 1000.0, std::ptr::null_mut(), 0),
+            0
+        );
+        assert_eq!(
+            graph_screen_loops(handle, f64::NAN, std::ptr::null_mut(), 0),
+            COMPASS_ERR_INVALID_ARGUMENT
+        );
+        assert_eq!(
+            graph_screen_loops(std::ptr::null(), 1000.0, std::ptr::null_mut(), 0),
+            COMPASS_ERR_INVALID_ARGUMENT
+        );
+        graph_free(handle);
+    }
+}
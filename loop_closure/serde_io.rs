@@ -0,0 +1,391 @@
+//! Serde support for the safe API types, so that graphs and solutions can be cached to disk in
+//! any serde format (JSON, bincode, ...).
+//!
+//! [`Graph`] and [`Solution`] are written with a leading `format_version` field, currently
+//! [`FORMAT_VERSION`]. Documents without it (version 0) predate the vertical component and
+//! station names; they are upgraded on load by zero-filling `z` and `dz`. Version 2 adds the
+//! `coordinate_system` field, null when none is declared; in self-describing formats such as
+//! JSON, version 1 documents load without one. Documents from a newer library are rejected.
+//!
+//! Non-finite floats are not representable in JSON, so serializing a graph or solution that
+//! contains NaN or infinities fails with an error naming the offending array instead of
+//! producing an invalid or lossy document.
+
+use crate::crs::CoordinateSystem;
+use crate::{Graph, Solution, SolveStats, TrivialReason};
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de, ser};
+
+/// Version written in the `format_version` field of serialized graphs and solutions.
+pub const FORMAT_VERSION: u32 = 2;
+
+#[derive(Serialize)]
+struct GraphRef<'a> {
+    format_version: u32,
+    x: &'a [f64],
+    y: &'a [f64],
+    z: &'a [f64],
+    fixed: &'a [bool],
+    from: &'a [usize],
+    to: &'a [usize],
+    dx: &'a [f64],
+    dy: &'a [f64],
+    dz: &'a [f64],
+    weight: &'a [f64],
+    names: Option<&'a [String]>,
+    coordinate_system: Option<&'a CoordinateSystem>,
+}
+
+#[derive(Deserialize)]
+struct GraphDoc {
+    #[serde(default)]
+    format_version: u32,
+    x: Vec<f64>,
+    y: Vec<f64>,
+    #[serde(default)]
+    z: Vec<f64>,
+    fixed: Vec<bool>,
+    from: Vec<usize>,
+    to: Vec<usize>,
+    dx: Vec<f64>,
+    dy: Vec<f64>,
+    #[serde(default)]
+    dz: Vec<f64>,
+    weight: Vec<f64>,
+    #[serde(default)]
+    names: Option<Vec<String>>,
+    #[serde(default)]
+    coordinate_system: Option<CoordinateSystem>,
+}
+
+#[derive(Serialize)]
+struct SolutionRef<'a> {
+    format_version: u32,
+    x: &'a [f64],
+    y: &'a [f64],
+    stats: &'a SolveStats,
+    coordinate_system: Option<&'a CoordinateSystem>,
+}
+
+#[derive(Deserialize)]
+struct SolutionDoc {
+    #[serde(default)]
+    format_version: u32,
+    x: Vec<f64>,
+    y: Vec<f64>,
+    #[serde(default)]
+    stats: SolveStats,
+    #[serde(default)]
+    coordinate_system: Option<CoordinateSystem>,
+}
+
+impl Serialize for Graph {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        for (name, values) in [
+            ("x", &self.x),
+            ("y", &self.y),
+            ("z", &self.z),
+            ("dx", &self.dx),
+            ("dy", &self.dy),
+            ("dz", &self.dz),
+            ("weight", &self.weight),
+        ] {
+            ensure_finite(name, values)?;
+        }
+        GraphRef {
+            format_version: FORMAT_VERSION,
+            x: &self.x,
+            y: &self.y,
+            z: &self.z,
+            fixed: &self.fixed,
+            from: &self.from,
+            to: &self.to,
+            dx: &self.dx,
+            dy: &self.dy,
+            dz: &self.dz,
+            weight: &self.weight,
+            names: self.names.as_deref(),
+            coordinate_system: self.coordinate_system.as_ref(),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Graph {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut doc = GraphDoc::deserialize(deserializer)?;
+        check_version(doc.format_version)?;
+        if doc.format_version == 0 {
+            doc.z.resize(doc.x.len(), 0.0);
+            doc.dz.resize(doc.from.len(), 0.0);
+        }
+
+        let n = doc.x.len();
+        let m = doc.from.len();
+        let vertex_lengths = [doc.y.len(), doc.z.len(), doc.fixed.len()];
+        let edge_lengths = [
+            doc.to.len(),
+            doc.dx.len(),
+            doc.dy.len(),
+            doc.dz.len(),
+            doc.weight.len(),
+        ];
+        if vertex_lengths.iter().any(|&len| len != n) || edge_lengths.iter().any(|&len| len != m) {
+            return Err(de::Error::custom("graph arrays have inconsistent lengths"));
+        }
+        if doc.names.as_ref().is_some_and(|names| names.len() != n) {
+            return Err(de::Error::custom(
+                "graph names do not match the vertex count",
+            ));
+        }
+        if let Some(e) = (0..m).find(|&e| doc.from[e] >= n || doc.to[e] >= n) {
+            return Err(de::Error::custom(format!(
+                "edge {e} references a missing vertex"
+            )));
+        }
+
+        Ok(Graph {
+            x: doc.x,
+            y: doc.y,
+            z: doc.z,
+            fixed: doc.fixed,
+            from: doc.from,
+            to: doc.to,
+            dx: doc.dx,
+            dy: doc.dy,
+            dz: doc.dz,
+            weight: doc.weight,
+            names: doc.names,
+            coordinate_system: doc.coordinate_system,
+        })
+    }
+}
+
+impl Serialize for Solution {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        ensure_finite("x", &self.x)?;
+        ensure_finite("y", &self.y)?;
+        SolutionRef {
+            format_version: FORMAT_VERSION,
+            x: &self.x,
+            y: &self.y,
+            stats: &self.stats,
+            coordinate_system: self.coordinate_system.as_ref(),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Solution {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let doc = SolutionDoc::deserialize(deserializer)?;
+        check_version(doc.format_version)?;
+        if doc.x.len() != doc.y.len() {
+            return Err(de::Error::custom(
+                "solution arrays have inconsistent lengths",
+            ));
+        }
+        Ok(Solution {
+            x: doc.x,
+            y: doc.y,
+            trivial: TrivialReason::from_flags(doc.stats.trivial),
+            stats: doc.stats,
+            coordinate_system: doc.coordinate_system,
+            warnings: Vec::new(),
+        })
+    }
+}
+
+fn ensure_finite<E: ser::Error>(name: &str, values: &[f64]) -> Result<(), E> {
+    match values.iter().position(|v| !v.is_finite()) {
+        Some(i) => Err(E::custom(format!(
+            "{name}[{i}] is not finite ({})",
+            values[i]
+        ))),
+        None => Ok(()),
+    }
+}
+
+fn check_version<E: de::Error>(version: u32) -> Result<(), E> {
+    if version > FORMAT_VERSION {
+        return Err(E::custom(format!(
+            "unsupported format_version {version} (this library reads up to {FORMAT_VERSION})"
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SolveOptions;
+    use serde_json::json;
+
+    fn graph() -> Graph {
+        let mut graph = Graph::default();
+        graph.add_vertex(0.0, 0.0, 100.0, true);
+        graph.add_vertex(10.1, 0.2, 98.5, false);
+        graph.add_vertex(0.1, 9.7, 97.0, false);
+        graph.add_edge(0, 1, 10.0, 0.0, -1.5, 1.0);
+        graph.add_edge(1, 2, -10.0, 10.0, -1.5, 0.5);
+        graph.add_edge(2, 0, 0.0, -10.0, 3.0, 2.0);
+        graph.names = Some(["A1", "A2", "B1"].map(String::from).to_vec());
+        graph.set_coordinate_system(crate::crs::CoordinateSystem {
+            projection: crate::crs::Projection::Epsg(32613),
+            ..Default::default()
+        });
+        graph
+    }
+
+    fn assert_same_graph(a: &Graph, b: &Graph) {
+        assert_eq!((&a.x, &a.y, &a.z, &a.fixed), (&b.x, &b.y, &b.z, &b.fixed));
+        assert_eq!((&a.from, &a.to), (&b.from, &b.to));
+        assert_eq!(
+            (&a.dx, &a.dy, &a.dz, &a.weight),
+            (&b.dx, &b.dy, &b.dz, &b.weight)
+        );
+        assert_eq!(a.names, b.names);
+        assert_eq!(a.coordinate_system(), b.coordinate_system());
+    }
+
+    fn assert_same_solution(a: &Solution, b: &Solution) {
+        assert_eq!((&a.x, &a.y), (&b.x, &b.y));
+        assert_eq!(a.stats.free_vertices, b.stats.free_vertices);
+        assert_eq!(a.stats.passive_vertices, b.stats.passive_vertices);
+        assert_eq!(a.coordinate_system, b.coordinate_system);
+    }
+
+    #[test]
+    fn round_trips_through_json() {
+        let graph = graph();
+        let solution = graph.solve(1000, 1e-12).unwrap();
+
+        let text = serde_json::to_string(&graph).unwrap();
+        assert!(text.starts_with(r#"{"format_version":2,"#), "{text}");
+        assert_same_graph(&serde_json::from_str(&text).unwrap(), &graph);
+
+        let text = serde_json::to_string(&solution).unwrap();
+        assert_same_solution(&serde_json::from_str(&text).unwrap(), &solution);
+    }
+
+    #[test]
+    fn round_trips_through_bincode() {
+        let graph = graph();
+        let solution = graph.solve(1000, 1e-12).unwrap();
+
+        let bytes = bincode::serialize(&graph).unwrap();
+        assert_same_graph(&bincode::deserialize(&bytes).unwrap(), &graph);
+
+        let bytes = bincode::serialize(&solution).unwrap();
+        assert_same_solution(&bincode::deserialize(&bytes).unwrap(), &solution);
+    }
+
+    #[test]
+    fn upgrades_unversioned_graphs() {
+        let old = json!({
+            "x": [0.0, 3.0], "y": [0.0, 4.0], "fixed": [true, false],
+            "from": [0], "to": [1], "dx": [3.0], "dy": [4.0], "weight": [1.0]
+        });
+        let graph: Graph = serde_json::from_value(old).unwrap();
+        assert_eq!(graph.z, [0.0, 0.0]);
+        assert_eq!(graph.dz, [0.0]);
+        assert_eq!(graph.names, None);
+
+        let old = json!({"x": [1.0], "y": [2.0]});
+        let solution: Solution = serde_json::from_value(old).unwrap();
+        assert_eq!((solution.x, solution.y), (vec![1.0], vec![2.0]));
+        assert_eq!(solution.stats.free_vertices, 0);
+    }
+
+    #[test]
+    fn rejects_newer_and_inconsistent_documents() {
+        let mut doc = serde_json::to_value(graph()).unwrap();
+        doc["format_version"] = json!(FORMAT_VERSION + 1);
+        let err = serde_json::from_value::<Graph>(doc.clone()).unwrap_err();
+        assert!(
+            err.to_string().starts_with("unsupported format_version 3"),
+            "{err}"
+        );
+
+        doc["format_version"] = json!(FORMAT_VERSION);
+        doc["to"] = json!([1, 2, 3]);
+        let err = serde_json::from_value::<Graph>(doc.clone()).unwrap_err();
+        assert_eq!(err.to_string(), "edge 2 references a missing vertex");
+
+        doc["to"] = json!([1, 2]);
+        let err = serde_json::from_value::<Graph>(doc.clone()).unwrap_err();
+        assert_eq!(err.to_string(), "graph arrays have inconsistent lengths");
+
+        doc["to"] = json!([1, 2, 0]);
+        doc["names"] = json!(["A1"]);
+        let err = serde_json::from_value::<Graph>(doc).unwrap_err();
+        assert_eq!(err.to_string(), "graph names do not match the vertex count");
+    }
+
+    #[test]
+    fn refuses_to_serialize_non_finite_values() {
+        let mut graph = graph();
+        graph.weight[1] = f64::INFINITY;
+        let err = serde_json::to_string(&graph).unwrap_err();
+        assert_eq!(err.to_string(), "weight[1] is not finite (inf)");
+
+        let solution = Solution {
+            x: vec![0.0, 1.0],
+            y: vec![0.0, f64::NAN],
+            ..Solution::default()
+        };
+        let err = bincode::serialize(&solution).unwrap_err();
+        assert_eq!(err.to_string(), "y[1] is not finite (NaN)");
+    }
+
+    #[test]
+    fn options_skip_the_header_and_pointers() {
+        let options = SolveOptions {
+            iterations: 250,
+            ..SolveOptions::default()
+        };
+        let value = serde_json::to_value(&options).unwrap();
+        assert_eq!(
+            value,
+            json!({
+                "iterations": 250,
+                "tolerance": 1e-8,
+                "solver": 0,
+                "preconditioner": 0,
+                "centroid_stiffness": 0.0,
+                "num_classes": 0,
+                "verify_fixed": 0,
+                "error_capacity": 0,
+                "breakdown_tolerance": 0.0,
+                "true_residual_interval": 0,
+                "guess_margin": 0.0,
+                "keep_isolated": 0,
+                "convergence_criterion": 0,
+                "time_budget": 0.0,
+                "preset": 0,
+                "tolerance_x": 0.0,
+                "tolerance_y": 0.0,
+                "iterations_x": 0,
+                "iterations_y": 0,
+                "moved_threshold": 0.0,
+                "moved_capacity": 0,
+                "error_on_trivial": 0,
+                "s_transform": 0,
+                "datum_station": 0,
+                "datum_azimuth_station": 0,
+                "clamp_to_bounds": 0,
+                "bounds_min_x": 0.0,
+                "bounds_min_y": 0.0,
+                "bounds_max_x": 0.0,
+                "bounds_max_y": 0.0,
+                "num_transforms": 0,
+                "extended_precision": 0
+            })
+        );
+
+        let options: SolveOptions = serde_json::from_value(json!({"tolerance": 1e-3})).unwrap();
+        assert_eq!(options.struct_size, size_of::<SolveOptions>());
+        assert_eq!(options.iterations, SolveOptions::default().iterations);
+        assert_eq!(options.tolerance, 1e-3);
+        assert!(options.passive.is_null());
+    }
+}
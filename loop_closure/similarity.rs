@@ -0,0 +1,567 @@
+//! Post-fit of an adjusted network to external control: the 2D similarity transformation,
+//! translation, rotation and optionally scale, that best maps adjusted stations onto their
+//! known coordinates in another frame, e.g. a national grid.
+//!
+//! The fit is the weighted least squares one over the control points: the centroids are
+//! matched, then the rotation (and scale) follow in closed form from the coordinates about
+//! them. Two control points determine a similarity exactly; more are fitted, leaving
+//! residuals that show how well the network agrees with the control. Without scale the
+//! fit is rigid, which keeps the lengths of the adjustment.
+//!
+//! The transformation only re-expresses the result: the adjustment, its residuals along the
+//! shots and the fixed vertices of the graph are left alone. Control points that all
+//! coincide, in the network or in the target frame, leave the rotation undefined and fail
+//! with [`COMPASS_ERR_DEGENERATE_CONTROL`].
+
+use crate::{
+    COMPASS_ERR_DEGENERATE_CONTROL, COMPASS_ERR_INVALID_ARGUMENT, COMPASS_ERR_PANIC, COMPASS_OK,
+    GraphContext, Solution, SolveError, write_column,
+};
+use std::ffi::{c_double, c_int};
+use std::slice;
+
+/// Spread of the control points, relative to the magnitude of their coordinates, below
+/// which they are taken for coincident.
+const COINCIDENT: f64 = 1e-12;
+
+/// A station with its known coordinates in the target frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ControlPoint {
+    pub vertex: usize,
+    /// Target X coordinate.
+    pub x: f64,
+    /// Target Y coordinate.
+    pub y: f64,
+    /// Weight in the fit, positive.
+    pub weight: f64,
+}
+
+/// Similarity transformation `(x, y) -> s R(θ) (x, y) + (shift_x, shift_y)`, with the
+/// control points it was fitted to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Similarity2 {
+    /// Scale factor `s`, 1 for a rigid fit.
+    pub scale: f64,
+    /// Rotation `θ`, in radians counterclockwise.
+    pub rotation: f64,
+    /// Translation along X, applied after the rotation and scale.
+    pub shift_x: f64,
+    /// Same as [`Similarity2::shift_x`] along Y.
+    pub shift_y: f64,
+    /// The control points of the fit.
+    pub control: Vec<ControlPoint>,
+}
+
+impl Similarity2 {
+    /// Image of the point `(x, y)`.
+    pub fn apply(&self, x: f64, y: f64) -> (f64, f64) {
+        let (sin, cos) = self.rotation.sin_cos();
+        let (a, b) = (self.scale * cos, self.scale * sin);
+        (a * x - b * y + self.shift_x, b * x + a * y + self.shift_y)
+    }
+}
+
+/// Misfit of one control point: its target coordinates minus the image of its adjusted
+/// ones.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ControlResidual {
+    pub vertex: usize,
+    pub dx: f64,
+    pub dy: f64,
+}
+
+/// Coordinates of a solution expressed in the frame of a [`Similarity2`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransformedSolution {
+    /// Transformed X coordinates, one per vertex.
+    pub x: Vec<f64>,
+    /// Transformed Y coordinates, one per vertex.
+    pub y: Vec<f64>,
+    /// Residual of each control point of the transformation, in its order.
+    pub residuals: Vec<ControlResidual>,
+}
+
+impl Solution {
+    /// Best similarity transformation from the adjusted coordinates of the `control`
+    /// vertices to their target coordinates `(vertex, target_x, target_y)`, equally
+    /// weighted; `allow_scale` false fits a rigid one. See the
+    /// [module documentation](self).
+    ///
+    /// Fails with [`COMPASS_ERR_INVALID_ARGUMENT`] for fewer than two points, a vertex out of
+    /// range or a non-finite target, and with [`COMPASS_ERR_DEGENERATE_CONTROL`] when the
+    /// points coincide.
+    pub fn fit_similarity(
+        &self,
+        control: &[(usize, f64, f64)],
+        allow_scale: bool,
+    ) -> Result<Similarity2, SolveError> {
+        let control: Vec<ControlPoint> = control
+            .iter()
+            .map(|&(vertex, x, y)| ControlPoint {
+                vertex,
+                x,
+                y,
+                weight: 1.0,
+            })
+            .collect();
+        fit((&self.x, &self.y), control, allow_scale)
+    }
+
+    /// Same as [`Solution::fit_similarity`] with a weight per control point. Non-positive
+    /// or non-finite weights are invalid.
+    pub fn fit_similarity_weighted(
+        &self,
+        control: &[ControlPoint],
+        allow_scale: bool,
+    ) -> Result<Similarity2, SolveError> {
+        fit((&self.x, &self.y), control.to_vec(), allow_scale)
+    }
+
+    /// The coordinates transformed by `transform`, with the residuals of its control points.
+    pub fn apply_transform(&self, transform: &Similarity2) -> TransformedSolution {
+        apply((&self.x, &self.y), transform)
+    }
+}
+
+fn apply((x, y): (&[f64], &[f64]), transform: &Similarity2) -> TransformedSolution {
+    let (x, y): (Vec<f64>, Vec<f64>) = x
+        .iter()
+        .zip(y)
+        .map(|(&x, &y)| transform.apply(x, y))
+        .unzip();
+    let residuals = residuals((&x, &y), &transform.control, &|x, y| (x, y));
+    TransformedSolution { x, y, residuals }
+}
+
+/// Target minus the image by `map` of the coordinates of each control point.
+fn residuals(
+    (x, y): (&[f64], &[f64]),
+    control: &[ControlPoint],
+    map: &dyn Fn(f64, f64) -> (f64, f64),
+) -> Vec<ControlResidual> {
+    control
+        .iter()
+        .map(|point| {
+            let (px, py) = map(x[point.vertex], y[point.vertex]);
+            ControlResidual {
+                vertex: point.vertex,
+                dx: point.x - px,
+                dy: point.y - py,
+            }
+        })
+        .collect()
+}
+
+fn fit(
+    (x, y): (&[f64], &[f64]),
+    control: Vec<ControlPoint>,
+    allow_scale: bool,
+) -> Result<Similarity2, SolveError> {
+    let error = |code, message: String| Err(SolveError { code, message });
+    if control.len() < 2 {
+        return error(
+            COMPASS_ERR_INVALID_ARGUMENT,
+            format!("{} control points, at least 2 needed", control.len()),
+        );
+    }
+    for point in &control {
+        let source = x.get(point.vertex).zip(y.get(point.vertex));
+        let Some((&px, &py)) = source else {
+            return error(
+                COMPASS_ERR_INVALID_ARGUMENT,
+                format!("control vertex {} out of range", point.vertex),
+            );
+        };
+        if !(point.x.is_finite() && point.y.is_finite() && px.is_finite() && py.is_finite()) {
+            return error(
+                COMPASS_ERR_INVALID_ARGUMENT,
+                format!("control point at vertex {} is not finite", point.vertex),
+            );
+        }
+        if !(point.weight > 0.0 && point.weight.is_finite()) {
+            return error(
+                COMPASS_ERR_INVALID_ARGUMENT,
+                format!(
+                    "control point at vertex {} has weight {}",
+                    point.vertex, point.weight
+                ),
+            );
+        }
+    }
+
+    let total: f64 = control.iter().map(|p| p.weight).sum();
+    let mean = |c: &dyn Fn(&ControlPoint) -> f64| {
+        control.iter().map(|p| p.weight * c(p)).sum::<f64>() / total
+    };
+    let (px, py) = (mean(&|p| x[p.vertex]), mean(&|p| y[p.vertex]));
+    let (qx, qy) = (mean(&|p| p.x), mean(&|p| p.y));
+    let (mut source, mut target, mut dot, mut cross) = (0.0, 0.0, 0.0, 0.0);
+    let (mut source_size, mut target_size) = (0.0f64, 0.0f64);
+    for point in &control {
+        let (ax, ay) = (x[point.vertex] - px, y[point.vertex] - py);
+        let (bx, by) = (point.x - qx, point.y - qy);
+        source += point.weight * (ax * ax + ay * ay);
+        target += point.weight * (bx * bx + by * by);
+        dot += point.weight * (ax * bx + ay * by);
+        cross += point.weight * (ax * by - ay * bx);
+        source_size = source_size.max(x[point.vertex].abs().max(y[point.vertex].abs()));
+        target_size = target_size.max(point.x.abs().max(point.y.abs()));
+    }
+    // Root mean square distances to the centroids against the size of the coordinates.
+    let coincident = |spread: f64, size: f64| (spread / total).sqrt() <= COINCIDENT * size.max(1.0);
+    if coincident(source, source_size) {
+        return error(
+            COMPASS_ERR_DEGENERATE_CONTROL,
+            "the adjusted control stations coincide".to_string(),
+        );
+    }
+    if coincident(target, target_size) {
+        return error(
+            COMPASS_ERR_DEGENERATE_CONTROL,
+            "the target control coordinates coincide".to_string(),
+        );
+    }
+
+    let rotation = cross.atan2(dot);
+    let scale = match allow_scale {
+        true => dot.hypot(cross) / source,
+        false => 1.0,
+    };
+    let (sin, cos) = rotation.sin_cos();
+    let (a, b) = (scale * cos, scale * sin);
+    Ok(Similarity2 {
+        scale,
+        rotation,
+        shift_x: qx - (a * px - b * py),
+        shift_y: qy - (b * px + a * py),
+        control,
+    })
+}
+
+/// Fits the similarity transformation from the current coordinates of the graph behind
+/// `handle` (see [`GraphContext::coordinates`]) to the `num_points` control points
+/// `(vertices[i], target_x[i], target_y[i])`, see [`Solution::fit_similarity`]. `weights`
+/// may be null for equal weights; `allow_scale` != 0 fits the scale too.
+///
+/// `out_transform` receives `[scale, rotation, shift_x, shift_y]`, the input of
+/// [`graph_apply_similarity`]; `out_residual_x` and `out_residual_y`, of `num_points` values
+/// each, the residual of each control point. Any output pointer may be null to skip it.
+///
+/// # Returns
+///
+/// * [`COMPASS_OK`] on success.
+/// * [`COMPASS_ERR_INVALID_ARGUMENT`] for a null handle, fewer than two points, a null input
+///   array, a vertex out of range, a non-finite target or a non-positive weight.
+/// * [`COMPASS_ERR_DEGENERATE_CONTROL`] when the control points coincide.
+#[unsafe(no_mangle)]
+#[allow(clippy::too_many_arguments)]
+pub extern "C" fn graph_fit_similarity(
+    handle: *const GraphContext,
+    num_points: c_int,
+    vertices: *const c_int,
+    target_x: *const c_double,
+    target_y: *const c_double,
+    weights: *const c_double,
+    allow_scale: c_int,
+    out_transform: *mut c_double,
+    out_residual_x: *mut c_double,
+    out_residual_y: *mut c_double,
+) -> c_int {
+    let result = std::panic::catch_unwind(|| {
+        // Safety: We assume the caller guarantees a valid (or null) handle.
+        let (Some(ctx), Ok(count)) = (unsafe { handle.as_ref() }, usize::try_from(num_points))
+        else {
+            return COMPASS_ERR_INVALID_ARGUMENT;
+        };
+        if count < 2 || vertices.is_null() || target_x.is_null() || target_y.is_null() {
+            return COMPASS_ERR_INVALID_ARGUMENT;
+        }
+        // Safety: The caller guarantees `num_points` values in each non-null array.
+        let (vertices, target_x, target_y) = unsafe {
+            (
+                slice::from_raw_parts(vertices, count),
+                slice::from_raw_parts(target_x, count),
+                slice::from_raw_parts(target_y, count),
+            )
+        };
+        let weights = match weights.is_null() {
+            true => None,
+            false => Some(unsafe { slice::from_raw_parts(weights, count) }),
+        };
+        let mut control = Vec::with_capacity(count);
+        for i in 0..count {
+            let Ok(vertex) = usize::try_from(vertices[i]) else {
+                return COMPASS_ERR_INVALID_ARGUMENT;
+            };
+            control.push(ControlPoint {
+                vertex,
+                x: target_x[i],
+                y: target_y[i],
+                weight: weights.map_or(1.0, |w| w[i]),
+            });
+        }
+        let coordinates = ctx.coordinates();
+        let transform = match fit(coordinates, control, allow_scale != 0) {
+            Ok(transform) => transform,
+            Err(err) => return err.code,
+        };
+        if !out_transform.is_null() {
+            // Safety: The caller guarantees a buffer of 4 values.
+            let out = unsafe { slice::from_raw_parts_mut(out_transform, 4) };
+            out.copy_from_slice(&[
+                transform.scale,
+                transform.rotation,
+                transform.shift_x,
+                transform.shift_y,
+            ]);
+        }
+        let residuals = residuals(coordinates, &transform.control, &|x, y| {
+            transform.apply(x, y)
+        });
+        write_column(out_residual_x, &residuals, |r| r.dx);
+        write_column(out_residual_y, &residuals, |r| r.dy);
+        COMPASS_OK
+    });
+
+    result.unwrap_or_else(|_| {
+        eprintln!("Panic caught in graph_fit_similarity");
+        COMPASS_ERR_PANIC
+    })
+}
+
+/// Writes the current coordinates of the graph behind `handle` transformed by `transform`,
+/// `[scale, rotation, shift_x, shift_y]` as [`graph_fit_similarity`] returns it, into
+/// `out_x` and `out_y` of `num_vertices` values each. The graph itself is left alone.
+///
+/// # Returns
+///
+/// * [`COMPASS_OK`] on success.
+/// * [`COMPASS_ERR_INVALID_ARGUMENT`] for a null pointer or a non-finite transform.
+#[unsafe(no_mangle)]
+pub extern "C" fn graph_apply_similarity(
+    handle: *const GraphContext,
+    transform: *const c_double,
+    out_x: *mut c_double,
+    out_y: *mut c_double,
+) -> c_int {
+    let result = std::panic::catch_unwind(|| {
+        // Safety: We assume the caller guarantees a valid (or null) handle.
+        let Some(ctx) = (unsafe { handle.as_ref() }) else {
+            return COMPASS_ERR_INVALID_ARGUMENT;
+        };
+        if transform.is_null() || out_x.is_null() || out_y.is_null() {
+            return COMPASS_ERR_INVALID_ARGUMENT;
+        }
+        // Safety: The caller guarantees 4 values.
+        let values = unsafe { slice::from_raw_parts(transform, 4) };
+        if !values.iter().all(|v| v.is_finite()) {
+            return COMPASS_ERR_INVALID_ARGUMENT;
+        }
+        let transform = Similarity2 {
+            scale: values[0],
+            rotation: values[1],
+            shift_x: values[2],
+            shift_y: values[3],
+            control: Vec::new(),
+        };
+        let transformed = apply(ctx.coordinates(), &transform);
+        let n = transformed.x.len();
+        // Safety: The caller guarantees buffers of `num_vertices` values.
+        unsafe {
+            slice::from_raw_parts_mut(out_x, n).copy_from_slice(&transformed.x);
+            slice::from_raw_parts_mut(out_y, n).copy_from_slice(&transformed.y);
+        }
+        COMPASS_OK
+    });
+
+    result.unwrap_or_else(|_| {
+        eprintln!("Panic caught in graph_apply_similarity");
+        COMPASS_ERR_PANIC
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Graph;
+
+    /// A square loop of 10 m shots, anchored at station 0, closing with a few centimetres of
+    /// misclosure, and a spur to station 4.
+    fn solved() -> Solution {
+        let mut graph = Graph::default();
+        for v in 0..5 {
+            graph.add_vertex(0.0, 0.0, 0.0, v == 0);
+        }
+        for (u, v, dx, dy) in [
+            (0, 1, 10.0, 0.02),
+            (1, 2, 0.0, 10.0),
+            (2, 3, -10.03, 0.0),
+            (3, 0, 0.0, -10.0),
+            (2, 4, 5.0, 5.0),
+        ] {
+            graph.add_edge(u, v, dx, dy, 0.0, 1.0);
+        }
+        graph.solve(1000, 1e-12).unwrap()
+    }
+
+    #[test]
+    fn recovers_a_similarity_and_reports_the_misfit() {
+        let solution = solved();
+        let truth = Similarity2 {
+            scale: 0.9996,
+            rotation: 0.4,
+            shift_x: 500_000.0,
+            shift_y: 4_200_000.0,
+            control: Vec::new(),
+        };
+        let target = |v: usize| truth.apply(solution.x[v], solution.y[v]);
+        let control: Vec<(usize, f64, f64)> = [0, 2, 3, 4]
+            .into_iter()
+            .map(|v| (v, target(v).0, target(v).1))
+            .collect();
+
+        // Two points are matched exactly, more fitted; exact control leaves no misfit.
+        for points in [&control[..2], &control[..]] {
+            let fitted = solution.fit_similarity(points, true).unwrap();
+            // Grid coordinates of millions of metres carry about 1e-9 m of rounding.
+            assert!((fitted.scale - truth.scale).abs() < 1e-9, "{fitted:?}");
+            assert!(
+                (fitted.rotation - truth.rotation).abs() < 1e-9,
+                "{fitted:?}"
+            );
+            let transformed = solution.apply_transform(&fitted);
+            for v in 0..5 {
+                let (x, y) = target(v);
+                assert!((transformed.x[v] - x).abs() < 1e-6);
+                assert!((transformed.y[v] - y).abs() < 1e-6);
+            }
+            assert_eq!(transformed.residuals.len(), points.len());
+            assert!(
+                transformed
+                    .residuals
+                    .iter()
+                    .all(|r| r.dx.hypot(r.dy) < 1e-6)
+            );
+        }
+
+        // A rigid fit keeps the lengths and leaves the scale difference in the residuals,
+        // symmetric about the centroid.
+        let rigid = solution.fit_similarity(&control, false).unwrap();
+        assert_eq!(rigid.scale, 1.0);
+        assert!((rigid.rotation - truth.rotation).abs() < 1e-9);
+        let residuals = solution.apply_transform(&rigid).residuals;
+        let (sum_x, sum_y) = residuals
+            .iter()
+            .fold((0.0, 0.0), |(x, y), r| (x + r.dx, y + r.dy));
+        assert!(sum_x.abs() < 1e-6 && sum_y.abs() < 1e-6);
+        assert!(residuals.iter().any(|r| r.dx.hypot(r.dy) > 1e-3));
+
+        // A heavy weight pulls the fit onto its point.
+        let mut weighted: Vec<ControlPoint> = control
+            .iter()
+            .map(|&(vertex, x, y)| ControlPoint {
+                vertex,
+                x,
+                y,
+                weight: 1.0,
+            })
+            .collect();
+        weighted[1].x += 0.1;
+        weighted[1].weight = 1e6;
+        let fitted = solution.fit_similarity_weighted(&weighted, true).unwrap();
+        let misfit = solution.apply_transform(&fitted).residuals;
+        assert!(misfit[1].dx.abs() < 1e-6 && misfit[0].dx.hypot(misfit[0].dy) > 1e-2);
+    }
+
+    #[test]
+    fn degenerate_control_is_rejected() {
+        let solution = solved();
+        let code = |control: &[(usize, f64, f64)]| {
+            solution
+                .fit_similarity(control, true)
+                .map(|_| ())
+                .map_err(|err| err.code)
+        };
+        assert_eq!(code(&[(0, 1.0, 2.0)]), Err(COMPASS_ERR_INVALID_ARGUMENT));
+        assert_eq!(
+            code(&[(0, 1.0, 2.0), (9, 3.0, 4.0)]),
+            Err(COMPASS_ERR_INVALID_ARGUMENT)
+        );
+        assert_eq!(
+            code(&[(2, 1.0, 2.0), (2, 3.0, 4.0)]),
+            Err(COMPASS_ERR_DEGENERATE_CONTROL)
+        );
+        assert_eq!(
+            code(&[(0, 4e6, 5e6), (2, 4e6, 5e6), (3, 4e6, 5e6)]),
+            Err(COMPASS_ERR_DEGENERATE_CONTROL)
+        );
+        assert_eq!(code(&[(0, 0.0, 0.0), (2, 1.0, 1.0)]), Ok(()));
+    }
+
+    #[test]
+    fn ffi_fits_and_applies() {
+        let solution = solved();
+        let mut graph = Graph::default();
+        for v in 0..5 {
+            graph.add_vertex(solution.x[v], solution.y[v], 0.0, true);
+        }
+        let handle = GraphContext::into_raw(graph);
+        let (vertices, tx, ty) = ([0, 2, 4], [10.0, 10.0, 5.0], [0.0, -10.0, -15.0]);
+        let mut transform = [0.0; 4];
+        let (mut rx, mut ry) = ([f64::NAN; 3], [f64::NAN; 3]);
+        let code = graph_fit_similarity(
+            handle,
+            3,
+            vertices.as_ptr(),
+            tx.as_ptr(),
+            ty.as_ptr(),
+            std::ptr::null(),
+            0,
+            transform.as_mut_ptr(),
+            rx.as_mut_ptr(),
+            ry.as_mut_ptr(),
+        );
+        assert_eq!(code, COMPASS_OK);
+        let control: Vec<_> = (0..3)
+            .map(|i| (vertices[i] as usize, tx[i], ty[i]))
+            .collect();
+        let expected = solution.fit_similarity(&control, false).unwrap();
+        assert_eq!(
+            transform,
+            [
+                expected.scale,
+                expected.rotation,
+                expected.shift_x,
+                expected.shift_y
+            ]
+        );
+        let transformed = solution.apply_transform(&expected);
+        for (i, r) in transformed.residuals.iter().enumerate() {
+            assert_eq!((rx[i], ry[i]), (r.dx, r.dy));
+        }
+
+        let (mut x, mut y) = ([0.0; 5], [0.0; 5]);
+        let code =
+            graph_apply_similarity(handle, transform.as_ptr(), x.as_mut_ptr(), y.as_mut_ptr());
+        assert_eq!(code, COMPASS_OK);
+        assert_eq!((&x[..], &y[..]), (&transformed.x[..], &transformed.y[..]));
+
+        let same = [3.0; 3];
+        let code = graph_fit_similarity(
+            handle,
+            3,
+            vertices.as_ptr(),
+            same.as_ptr(),
+            same.as_ptr(),
+            std::ptr::null(),
+            1,
+            transform.as_mut_ptr(),
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+        );
+        assert_eq!(code, COMPASS_ERR_DEGENERATE_CONTROL);
+        let null = graph_apply_similarity(handle, std::ptr::null(), x.as_mut_ptr(), y.as_mut_ptr());
+        assert_eq!(null, COMPASS_ERR_INVALID_ARGUMENT);
+        crate::graph_free(handle);
+    }
+}
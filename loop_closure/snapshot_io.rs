@@ -0,0 +1,679 @@
+//! Binary snapshots of a [`GraphContext`], so that large projects re-opened daily can skip
+//! assembling the normal equations.
+//!
+//! Layout (all integers and floats little-endian):
+//!
+//! | Field            | Type     | Notes                                         |
+//! |------------------|----------|-----------------------------------------------|
+//! | magic            | 8 bytes  | `LCSNAPSH`                                    |
+//! | version          | u32      | [`SNAPSHOT_VERSION`]                          |
+//! | graph hash       | u64      | [`Graph::content_hash`] of the embedded graph |
+//! | payload length   | u64      |                                               |
+//! | payload checksum | u64      | FNV-1a 64 of the payload                      |
+//! | payload          |          | graph, current coordinates, normal equations  |
+//!
+//! The payload stores the graph arrays, the last solution (if any) and the assembled system:
+//! the vertex mapping, the CSR matrix and both right-hand sides. Snapshots written by a newer
+//! library are rejected rather than guessed at.
+
+use crate::{
+    COMPASS_ERR_INVALID_ARGUMENT, COMPASS_ERR_IO, COMPASS_ERR_PANIC, COMPASS_OK, Fnv1a, Graph,
+    GraphContext, NormalEquations, NullSpace, Solution, SolveError, SolveStats, TrivialReason,
+    warnings, write_message,
+};
+use nalgebra::DVector;
+use nalgebra_sparse::CsrMatrix;
+use std::ffi::{CStr, c_char, c_int};
+use std::path::Path;
+
+/// Format version written in snapshot headers.
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+const MAGIC: &[u8; 8] = b"LCSNAPSH";
+const HEADER_LEN: usize = 8 + 4 + 8 + 8 + 8;
+
+impl GraphContext {
+    /// Writes a snapshot of the graph, its current coordinates and, once solved, its normal
+    /// equations to `path`.
+    pub fn save_snapshot(&self, path: impl AsRef<Path>) -> Result<(), SolveError> {
+        let path = path.as_ref();
+        std::fs::write(path, self.to_snapshot()).map_err(|err| SolveError {
+            code: COMPASS_ERR_IO,
+            message: format!("{}: {err}", path.display()),
+        })
+    }
+
+    /// Loads a snapshot written by [`GraphContext::save_snapshot`].
+    ///
+    /// When `expected_hash` is given, the snapshot must have been taken from a graph with
+    /// that [`Graph::content_hash`]; a stale snapshot fails with [`COMPASS_ERR_INVALID_ARGUMENT`].
+    /// Unreadable, corrupted or newer-version files fail with [`COMPASS_ERR_IO`].
+    pub fn load_snapshot(
+        path: impl AsRef<Path>,
+        expected_hash: Option<u64>,
+    ) -> Result<GraphContext, SolveError> {
+        let path = path.as_ref();
+        let data = std::fs::read(path).map_err(|err| SolveError {
+            code: COMPASS_ERR_IO,
+            message: format!("{}: {err}", path.display()),
+        })?;
+        GraphContext::from_snapshot(&data, expected_hash).map_err(|err| SolveError {
+            message: format!("{}: {}", path.display(), err.message),
+            ..err
+        })
+    }
+
+    /// Encodes the context as a snapshot.
+    pub fn to_snapshot(&self) -> Vec<u8> {
+        let graph = &self.graph;
+        let mut out = Writer::default();
+        out.put_u64(graph.num_vertices() as u64);
+        out.put_u64(graph.num_edges() as u64);
+        for values in [&graph.x, &graph.y, &graph.z] {
+            out.put_f64s(values);
+        }
+        out.bytes.extend(graph.fixed.iter().map(|&f| f as u8));
+        for indices in [&graph.from, &graph.to] {
+            out.put_usizes(indices);
+        }
+        for values in [&graph.dx, &graph.dy, &graph.dz, &graph.weight] {
+            out.put_f64s(values);
+        }
+        out.put_bool(graph.names.is_some());
+        for name in graph.names.iter().flatten() {
+            out.put_u64(name.len() as u64);
+            out.bytes.extend_from_slice(name.as_bytes());
+        }
+
+        out.put_bool(self.solution.is_some());
+        if let Some(solution) = &self.solution {
+            out.put_f64s(&solution.x);
+            out.put_f64s(&solution.y);
+            out.put_i32(solution.stats.free_vertices);
+            out.put_i32(solution.stats.passive_vertices);
+        }
+
+        // Normal equations assembled with disabled edges do not match the stored graph, whose
+        // edges are all enabled once loaded.
+        let system = self
+            .system
+            .as_ref()
+            .filter(|_| self.edge_enabled.iter().all(|&e| e));
+        out.put_bool(system.is_some());
+        if let Some(system) = system {
+            for idx in &system.mapping {
+                out.put_u64(idx.map_or(u64::MAX, |i| i as u64));
+            }
+            out.put_u64(system.size() as u64);
+            out.put_u64(system.matrix.nnz() as u64);
+            out.put_usizes(system.matrix.row_offsets());
+            out.put_usizes(system.matrix.col_indices());
+            out.put_f64s(system.matrix.values());
+            out.put_f64s(system.bx.as_slice());
+            out.put_f64s(system.by.as_slice());
+        }
+
+        let payload = out.bytes;
+        let mut checksum = Fnv1a::new();
+        checksum.write(&payload);
+        let mut snapshot = Vec::with_capacity(HEADER_LEN + payload.len());
+        snapshot.extend_from_slice(MAGIC);
+        snapshot.extend_from_slice(&SNAPSHOT_VERSION.to_le_bytes());
+        snapshot.extend_from_slice(&graph.content_hash().to_le_bytes());
+        snapshot.extend_from_slice(&(payload.len() as u64).to_le_bytes());
+        snapshot.extend_from_slice(&checksum.finish().to_le_bytes());
+        snapshot.extend_from_slice(&payload);
+        snapshot
+    }
+
+    /// Decodes a snapshot produced by [`GraphContext::to_snapshot`]. See
+    /// [`GraphContext::load_snapshot`] for the error codes.
+    pub fn from_snapshot(
+        data: &[u8],
+        expected_hash: Option<u64>,
+    ) -> Result<GraphContext, SolveError> {
+        let mut header = Reader { data, pos: 0 };
+        if header.take(MAGIC.len())? != MAGIC {
+            return Err(corrupt("not a snapshot file"));
+        }
+        let version = header.u32()?;
+        if version > SNAPSHOT_VERSION {
+            return Err(SolveError {
+                code: COMPASS_ERR_IO,
+                message: format!(
+                    "snapshot version {version} is newer than supported ({SNAPSHOT_VERSION})"
+                ),
+            });
+        }
+        let hash = header.u64()?;
+        if let Some(expected) = expected_hash
+            && expected != hash
+        {
+            return Err(SolveError {
+                code: COMPASS_ERR_INVALID_ARGUMENT,
+                message: format!(
+                    "snapshot was taken from a different graph (hash {hash:016x}, expected {expected:016x})"
+                ),
+            });
+        }
+        let len = header.length()?;
+        let stored_checksum = header.u64()?;
+        let payload = header.take(len)?;
+        let mut checksum = Fnv1a::new();
+        checksum.write(payload);
+        if checksum.finish() != stored_checksum {
+            return Err(corrupt("checksum mismatch"));
+        }
+
+        let mut input = Reader {
+            data: payload,
+            pos: 0,
+        };
+        let n = input.length()?;
+        let m = input.length()?;
+        let mut graph = Graph {
+            x: input.f64s(n)?,
+            y: input.f64s(n)?,
+            z: input.f64s(n)?,
+            fixed: input.take(n)?.iter().map(|&f| f != 0).collect(),
+            ..Graph::default()
+        };
+        graph.from = input.indices(m, n)?;
+        graph.to = input.indices(m, n)?;
+        graph.dx = input.f64s(m)?;
+        graph.dy = input.f64s(m)?;
+        graph.dz = input.f64s(m)?;
+        graph.weight = input.f64s(m)?;
+        if input.bool()? {
+            let mut names = Vec::with_capacity(n);
+            for _ in 0..n {
+                let len = input.length()?;
+                let name = std::str::from_utf8(input.take(len)?)
+                    .map_err(|_| corrupt("station name is not UTF-8"))?;
+                names.push(name.to_string());
+            }
+            graph.names = Some(names);
+        }
+        if graph.content_hash() != hash {
+            return Err(corrupt("graph does not match the header hash"));
+        }
+
+        let solution = if input.bool()? {
+            Some(Solution {
+                x: input.f64s(n)?,
+                y: input.f64s(n)?,
+                stats: SolveStats {
+                    free_vertices: input.i32()?,
+                    passive_vertices: input.i32()?,
+                    ..SolveStats::default()
+                },
+                trivial: TrivialReason::default(),
+                coordinate_system: graph.coordinate_system,
+                // Not saved: the next solve reports them again.
+                warnings: Vec::new(),
+            })
+        } else {
+            None
+        };
+
+        let system = if input.bool()? {
+            let mut mapping = Vec::with_capacity(n);
+            for _ in 0..n {
+                mapping.push(match input.u64()? {
+                    u64::MAX => None,
+                    i => Some(i as usize),
+                });
+            }
+            let size = input.length()?;
+            let nnz = input.length()?;
+            let row_offsets = input.indices(size + 1, nnz + 1)?;
+            let col_indices = input.indices(nnz, size)?;
+            let values = input.f64s(nnz)?;
+            if mapping.iter().flatten().any(|&i| i >= size) {
+                return Err(corrupt("vertex mapping out of range"));
+            }
+            let matrix = CsrMatrix::try_from_csr_data(size, size, row_offsets, col_indices, values)
+                .map_err(|err| corrupt(&format!("invalid matrix: {err}")))?;
+            Some(NormalEquations {
+                mapping,
+                null_space: NullSpace::of(&matrix),
+                matrix,
+                bx: DVector::from_vec(input.f64s(size)?),
+                by: DVector::from_vec(input.f64s(size)?),
+                // Rebuilt by the first solve.
+                preconditioner: Default::default(),
+                edge_warnings: graph.with_view(&[], |view| {
+                    let order = view.canonical_order().unwrap_or_default();
+                    warnings::edge_warnings(view, &order)
+                }),
+            })
+        } else {
+            None
+        };
+
+        if input.pos != payload.len() {
+            return Err(corrupt("trailing data"));
+        }
+        let ctx = GraphContext {
+            graph,
+            solution,
+            system,
+            edge_enabled: Vec::new(),
+            group_id: Vec::new(),
+            transforms: None,
+            locked: Vec::new(),
+            classes: None,
+            positions: Vec::new(),
+            published: Default::default(),
+            spatial_index: Default::default(),
+            residual_history: Default::default(),
+        };
+        ctx.publish();
+        Ok(ctx)
+    }
+}
+
+/// Writes a snapshot of the graph behind `handle` to `path`.
+///
+/// The snapshot includes the normal equations only once the handle has been solved with
+/// [`crate::graph_solve`].
+#[unsafe(no_mangle)]
+pub extern "C" fn graph_save_snapshot(
+    handle: *const GraphContext,
+    path: *const c_char,
+    err_buf: *mut c_char,
+    err_cap: usize,
+) -> c_int {
+    let result = std::panic::catch_unwind(|| {
+        // Safety: We assume the caller guarantees a valid (or null) handle and path.
+        let Some(ctx) = (unsafe { handle.as_ref() }) else {
+            write_message(err_buf, err_cap, "handle is null");
+            return COMPASS_ERR_INVALID_ARGUMENT;
+        };
+        if path.is_null() {
+            write_message(err_buf, err_cap, "path is null");
+            return COMPASS_ERR_INVALID_ARGUMENT;
+        }
+        let path = unsafe { CStr::from_ptr(path) }
+            .to_string_lossy()
+            .into_owned();
+        match ctx.save_snapshot(&path) {
+            Ok(()) => COMPASS_OK,
+            Err(err) => {
+                write_message(err_buf, err_cap, &err.message);
+                err.code
+            }
+        }
+    });
+
+    match result {
+        Ok(code) => code,
+        Err(_) => {
+            eprintln!("Panic caught in graph_save_snapshot");
+            COMPASS_ERR_PANIC
+        }
+    }
+}
+
+/// Loads a snapshot written by [`graph_save_snapshot`] and returns a new handle.
+///
+/// # Arguments
+///
+/// * `path` - NUL-terminated path of the snapshot.
+/// * `expected_hash` - [`crate::graph_content_hash`] of the current graph, or 0 to skip the check.
+/// * `err_buf` - Buffer receiving a NUL-terminated error message on failure. May be null.
+/// * `err_cap` - Capacity of `err_buf` in bytes.
+///
+/// # Returns
+///
+/// * A handle to release with [`crate::graph_free`], or null if the file is unreadable,
+///   corrupted, from a newer library, or was taken from a different graph.
+#[unsafe(no_mangle)]
+pub extern "C" fn graph_load_snapshot(
+    path: *const c_char,
+    expected_hash: u64,
+    err_buf: *mut c_char,
+    err_cap: usize,
+) -> *mut GraphContext {
+    if path.is_null() {
+        write_message(err_buf, err_cap, "path is null");
+        return std::ptr::null_mut();
+    }
+    // Safety: The caller guarantees a valid NUL-terminated string.
+    let path = unsafe { CStr::from_ptr(path) }
+        .to_string_lossy()
+        .into_owned();
+    let expected_hash = (expected_hash != 0).then_some(expected_hash);
+    match std::panic::catch_unwind(|| GraphContext::load_snapshot(&path, expected_hash)) {
+        Ok(Ok(ctx)) => Box::into_raw(Box::new(ctx)),
+        Ok(Err(err)) => {
+            write_message(err_buf, err_cap, &err.message);
+            std::ptr::null_mut()
+        }
+        Err(_) => {
+            write_message(err_buf, err_cap, "panic while loading snapshot");
+            std::ptr::null_mut()
+        }
+    }
+}
+
+fn corrupt(message: &str) -> SolveError {
+    SolveError {
+        code: COMPASS_ERR_IO,
+        message: format!("corrupted snapshot: {message}"),
+    }
+}
+
+#[derive(Default)]
+struct Writer {
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    fn put_bool(&mut self, v: bool) {
+        self.bytes.push(v as u8);
+    }
+
+    fn put_i32(&mut self, v: i32) {
+        self.bytes.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn put_u64(&mut self, v: u64) {
+        self.bytes.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn put_usizes(&mut self, values: &[usize]) {
+        for &v in values {
+            self.put_u64(v as u64);
+        }
+    }
+
+    fn put_f64s(&mut self, values: &[f64]) {
+        for &v in values {
+            self.bytes.extend_from_slice(&v.to_le_bytes());
+        }
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], SolveError> {
+        let end = self
+            .pos
+            .checked_add(n)
+            .filter(|&end| end <= self.data.len())
+            .ok_or_else(|| corrupt("unexpected end of data"))?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], SolveError> {
+        Ok(self.take(N)?.try_into().expect("length checked by take"))
+    }
+
+    fn bool(&mut self) -> Result<bool, SolveError> {
+        Ok(self.array::<1>()?[0] != 0)
+    }
+
+    fn i32(&mut self) -> Result<i32, SolveError> {
+        Ok(i32::from_le_bytes(self.array()?))
+    }
+
+    fn u32(&mut self) -> Result<u32, SolveError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    fn u64(&mut self) -> Result<u64, SolveError> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    /// A length or count, checked against the remaining data so that a corrupted value
+    /// cannot trigger a huge allocation.
+    fn length(&mut self) -> Result<usize, SolveError> {
+        let len = self.u64()?;
+        if len > (self.data.len() - self.pos) as u64 {
+            return Err(corrupt("length out of range"));
+        }
+        Ok(len as usize)
+    }
+
+    /// `count` indices, each less than `bound`.
+    fn indices(&mut self, count: usize, bound: usize) -> Result<Vec<usize>, SolveError> {
+        let bytes = self.take(
+            count
+                .checked_mul(8)
+                .ok_or_else(|| corrupt("length out of range"))?,
+        )?;
+        bytes
+            .chunks_exact(8)
+            .map(|chunk| {
+                let v = u64::from_le_bytes(chunk.try_into().expect("chunks of 8 bytes"));
+                if v < bound as u64 {
+                    Ok(v as usize)
+                } else {
+                    Err(corrupt("index out of range"))
+                }
+            })
+            .collect()
+    }
+
+    fn f64s(&mut self, count: usize) -> Result<Vec<f64>, SolveError> {
+        let bytes = self.take(
+            count
+                .checked_mul(8)
+                .ok_or_else(|| corrupt("length out of range"))?,
+        )?;
+        Ok(bytes
+            .chunks_exact(8)
+            .map(|chunk| f64::from_le_bytes(chunk.try_into().expect("chunks of 8 bytes")))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CString;
+
+    fn context() -> GraphContext {
+        let mut graph = Graph::default();
+        graph.add_vertex(100.0, 200.0, 10.0, true);
+        graph.add_vertex(110.2, 199.9, 11.0, false);
+        graph.add_vertex(109.8, 210.1, 12.0, false);
+        graph.add_vertex(99.9, 209.7, 13.0, false);
+        graph.add_edge(0, 1, 10.0, 0.0, 1.0, 1.0);
+        graph.add_edge(1, 2, 0.0, 10.0, 1.0, 1.0);
+        graph.add_edge(2, 3, -10.0, 0.0, 1.0, 0.5);
+        graph.add_edge(3, 0, 0.05, -10.02, -3.0, 2.0);
+        graph.names = Some(["E", "A", "B", "\u{e9}"].map(String::from).to_vec());
+        GraphContext {
+            graph,
+            solution: None,
+            system: None,
+            edge_enabled: Vec::new(),
+            group_id: Vec::new(),
+            transforms: None,
+            locked: Vec::new(),
+            classes: None,
+            positions: Vec::new(),
+            published: Default::default(),
+            spatial_index: Default::default(),
+            residual_history: Default::default(),
+        }
+    }
+
+    fn assert_same(a: &GraphContext, b: &GraphContext) {
+        assert_eq!(a.graph.x, b.graph.x);
+        assert_eq!(a.graph.z, b.graph.z);
+        assert_eq!(a.graph.fixed, b.graph.fixed);
+        assert_eq!((&a.graph.from, &a.graph.to), (&b.graph.from, &b.graph.to));
+        assert_eq!(
+            (&a.graph.dz, &a.graph.weight),
+            (&b.graph.dz, &b.graph.weight)
+        );
+        assert_eq!(a.graph.names, b.graph.names);
+        assert_eq!(a.coordinates(), b.coordinates());
+        match (&a.system, &b.system) {
+            (Some(a), Some(b)) => {
+                assert_eq!(a.mapping, b.mapping);
+                assert_eq!(a.matrix, b.matrix);
+                assert_eq!((&a.bx, &a.by), (&b.bx, &b.by));
+            }
+            (None, None) => {}
+            _ => panic!("only one context has normal equations"),
+        }
+    }
+
+    #[test]
+    fn round_trips_unsolved_and_solved_contexts() {
+        let mut ctx = context();
+        let loaded = GraphContext::from_snapshot(&ctx.to_snapshot(), None).unwrap();
+        assert!(loaded.solution.is_none() && loaded.system.is_none());
+        assert_same(&loaded, &ctx);
+
+        ctx.solve(1000, 1e-12.into(), Default::default(), None, &|| false)
+            .unwrap();
+        let hash = ctx.graph.content_hash();
+        let mut loaded = GraphContext::from_snapshot(&ctx.to_snapshot(), Some(hash)).unwrap();
+        assert_same(&loaded, &ctx);
+        let stats = loaded.solution.as_ref().unwrap().stats;
+        assert_eq!((stats.free_vertices, stats.passive_vertices), (3, 0));
+
+        // The loaded system solves exactly like the original.
+        let expected = ctx
+            .solve(1000, 1e-12.into(), Default::default(), None, &|| false)
+            .unwrap()
+            .clone();
+        let solution = loaded
+            .solve(1000, 1e-12.into(), Default::default(), None, &|| false)
+            .unwrap();
+        assert_eq!((&solution.x, &solution.y), (&expected.x, &expected.y));
+    }
+
+    #[test]
+    fn saves_and_loads_files() {
+        let mut ctx = context();
+        ctx.solve(1000, 1e-12.into(), Default::default(), None, &|| false)
+            .unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cave.snapshot");
+        ctx.save_snapshot(&path).unwrap();
+        assert_same(&GraphContext::load_snapshot(&path, None).unwrap(), &ctx);
+
+        let err = GraphContext::load_snapshot(dir.path().join("none"), None)
+            .err()
+            .unwrap();
+        assert_eq!(err.code, COMPASS_ERR_IO);
+        let err = ctx
+            .save_snapshot(dir.path().join("no/such/dir"))
+            .err()
+            .unwrap();
+        assert_eq!(err.code, COMPASS_ERR_IO);
+    }
+
+    #[test]
+    fn rejects_snapshots_of_a_different_graph() {
+        let ctx = context();
+        let snapshot = ctx.to_snapshot();
+
+        let mut edited = context().graph;
+        edited.weight[2] = 0.25;
+        let err = GraphContext::from_snapshot(&snapshot, Some(edited.content_hash()))
+            .err()
+            .unwrap();
+        assert_eq!(err.code, COMPASS_ERR_INVALID_ARGUMENT);
+        assert!(err.message.contains("different graph"), "{}", err.message);
+
+        // Free initial guesses do not invalidate the snapshot.
+        let mut moved = context().graph;
+        moved.x[2] += 1.0;
+        assert!(GraphContext::from_snapshot(&snapshot, Some(moved.content_hash())).is_ok());
+    }
+
+    #[test]
+    fn rejects_newer_versions() {
+        let mut snapshot = context().to_snapshot();
+        snapshot[8..12].copy_from_slice(&(SNAPSHOT_VERSION + 1).to_le_bytes());
+        let err = GraphContext::from_snapshot(&snapshot, None).err().unwrap();
+        assert_eq!(err.code, COMPASS_ERR_IO);
+        assert_eq!(
+            err.message,
+            format!(
+                "snapshot version {} is newer than supported ({SNAPSHOT_VERSION})",
+                SNAPSHOT_VERSION + 1
+            )
+        );
+    }
+
+    #[test]
+    fn rejects_corrupted_and_truncated_data() {
+        let mut ctx = context();
+        ctx.solve(1000, 1e-12.into(), Default::default(), None, &|| false)
+            .unwrap();
+        let snapshot = ctx.to_snapshot();
+        let message = |data: &[u8]| {
+            GraphContext::from_snapshot(data, None)
+                .err()
+                .unwrap()
+                .message
+        };
+
+        assert_eq!(
+            message(b"LCSNAPSX"),
+            "corrupted snapshot: not a snapshot file"
+        );
+        let mut flipped = snapshot.clone();
+        *flipped.last_mut().unwrap() ^= 1;
+        assert_eq!(message(&flipped), "corrupted snapshot: checksum mismatch");
+        for len in [0, 7, 20, snapshot.len() - 1] {
+            assert_eq!(
+                message(&snapshot[..len]),
+                "corrupted snapshot: unexpected end of data"
+            );
+        }
+        for len in [28, HEADER_LEN] {
+            assert_eq!(
+                message(&snapshot[..len]),
+                "corrupted snapshot: length out of range"
+            );
+        }
+        let mut hash = snapshot.clone();
+        hash[12] ^= 1;
+        assert_eq!(
+            message(&hash),
+            "corrupted snapshot: graph does not match the header hash"
+        );
+    }
+
+    #[test]
+    fn ffi_saves_loads_and_checks_the_hash() {
+        let ctx = context();
+        let dir = tempfile::tempdir().unwrap();
+        let path = CString::new(dir.path().join("s.bin").to_str().unwrap()).unwrap();
+        let handle = GraphContext::into_raw(ctx.graph.clone());
+        let hash = crate::graph_content_hash(handle);
+        assert_eq!(hash, ctx.graph.content_hash());
+        assert_eq!(crate::graph_content_hash(std::ptr::null()), 0);
+
+        let mut buf = [0 as c_char; 256];
+        let code = graph_save_snapshot(handle, path.as_ptr(), buf.as_mut_ptr(), buf.len());
+        assert_eq!(code, COMPASS_OK);
+        crate::graph_free(handle);
+
+        let loaded = graph_load_snapshot(path.as_ptr(), hash, buf.as_mut_ptr(), buf.len());
+        assert!(!loaded.is_null());
+        assert_eq!(crate::graph_num_edges(loaded), 4);
+        crate::graph_free(loaded);
+
+        let stale = graph_load_snapshot(path.as_ptr(), hash ^ 1, buf.as_mut_ptr(), buf.len());
+        assert!(stale.is_null());
+        // Safety: The library NUL-terminates the message.
+        let message = unsafe { CStr::from_ptr(buf.as_ptr()) }.to_string_lossy();
+        assert!(message.contains("different graph"), "{message}");
+    }
+}
@@ -0,0 +1,409 @@
+//! Preconditioned Conjugate Gradient on a sparse symmetric positive definite matrix, with a
+//! preconditioner of the caller's, e.g. a factorization of last month's nearly identical
+//! network.
+//!
+//! [`pcg`] runs the Conjugate Gradient of the graph solves with any [`Preconditioner`]. The
+//! built-in preconditioners implement the same trait, see
+//! [`SymmetricMatrix::preconditioner`], so that both take the same path. On the C ABI,
+//! [`sparse_pcg_solve`] takes the preconditioner as a [`PreconditionerCallback`].
+//!
+//! The output of the preconditioner is checked after every application: the first
+//! non-finite value stops the solve with [`COMPASS_ERR_PRECONDITIONER`].
+
+use crate::{
+    COMPASS_ERR_INVALID_ARGUMENT, COMPASS_ERR_PANIC, COMPASS_ERR_PRECONDITIONER, COMPASS_OK,
+    NullSpace, PreconditionerOp, SolveError, SolveStats, raw_slice, raw_slice_mut, solve_cg,
+    write_message, write_stats,
+};
+use nalgebra::DVector;
+use nalgebra_sparse::CsrMatrix;
+use std::ffi::{c_char, c_double, c_int, c_void};
+
+/// Approximate inverse `M^-1` of a matrix, applied once per Conjugate Gradient iteration.
+/// `M` must be symmetric positive definite for the iteration to converge.
+pub trait Preconditioner {
+    /// Writes `z = M^-1 r`. Both slices have the size of the matrix.
+    fn apply(&self, r: &[f64], z: &mut [f64]);
+}
+
+/// Sparse symmetric positive definite matrix in compressed sparse row (CSR) form. Symmetry
+/// is not checked: both triangles must be stored.
+#[derive(Debug, Clone)]
+pub struct SymmetricMatrix {
+    matrix: CsrMatrix<f64>,
+}
+
+impl SymmetricMatrix {
+    /// The `n` x `n` matrix whose row `i` holds `values[k]` in column `col_indices[k]` for `k`
+    /// in `row_offsets[i]..row_offsets[i + 1]`. Columns need not be sorted within a row, but
+    /// must not repeat.
+    pub fn from_csr(
+        n: usize,
+        row_offsets: Vec<usize>,
+        col_indices: Vec<usize>,
+        values: Vec<f64>,
+    ) -> Result<SymmetricMatrix, SolveError> {
+        let invalid = |message: String| SolveError {
+            code: COMPASS_ERR_INVALID_ARGUMENT,
+            message,
+        };
+        if let Some(k) = values.iter().position(|v| !v.is_finite()) {
+            return Err(invalid(format!("value {k} of the matrix is not finite")));
+        }
+        let matrix = CsrMatrix::try_from_unsorted_csr_data(n, n, row_offsets, col_indices, values)
+            .map_err(|err| invalid(format!("invalid CSR matrix: {err}")))?;
+        Ok(SymmetricMatrix { matrix })
+    }
+
+    /// Number of rows and columns.
+    pub fn size(&self) -> usize {
+        self.matrix.nrows()
+    }
+
+    /// Sets up the built-in `preconditioner` for this matrix, `None` for
+    /// [`crate::Preconditioner::None`].
+    pub fn preconditioner(
+        &self,
+        preconditioner: crate::Preconditioner,
+    ) -> Option<Box<dyn Preconditioner + Send + Sync>> {
+        PreconditionerOp::new(&self.matrix, preconditioner)
+            .map(|op| Box::new(op) as Box<dyn Preconditioner + Send + Sync>)
+    }
+}
+
+/// Solves `matrix * x = b` by Conjugate Gradient preconditioned with `preconditioner`,
+/// starting from and overwriting `x`, until the residual norm `|b - Ax|` falls below
+/// `tolerance` or `iterations` run out.
+///
+/// Running out of iterations is not an error: [`SolveStats::stop_reason`] and
+/// [`SolveStats::result_quality`] tell it, and `x` holds the last iterate. `x` is left
+/// untouched on error.
+///
+/// # Errors
+///
+/// * [`COMPASS_ERR_INVALID_ARGUMENT`] if `b` or `x` does not have the size of the matrix.
+/// * [`COMPASS_ERR_PRECONDITIONER`] if the preconditioner returns a non-finite value.
+pub fn pcg(
+    matrix: &SymmetricMatrix,
+    b: &[f64],
+    x: &mut [f64],
+    preconditioner: Option<&dyn Preconditioner>,
+    iterations: usize,
+    tolerance: f64,
+) -> Result<SolveStats, SolveError> {
+    let n = matrix.size();
+    if b.len() != n || x.len() != n {
+        return Err(SolveError {
+            code: COMPASS_ERR_INVALID_ARGUMENT,
+            message: format!(
+                "matrix of size {n} with {} right-hand side values and {} unknowns",
+                b.len(),
+                x.len()
+            ),
+        });
+    }
+    let (solution, convergence) = solve_cg(
+        &matrix.matrix,
+        &DVector::from_column_slice(b),
+        &DVector::from_column_slice(x),
+        preconditioner,
+        &NullSpace::default(),
+        iterations,
+        tolerance.into(),
+        &|| false,
+    );
+    if let Some(iteration) = convergence.preconditioner_failure {
+        return Err(preconditioner_failure(iteration));
+    }
+    x.copy_from_slice(solution.as_slice());
+    let mut stats = SolveStats::default();
+    convergence.record(&mut stats);
+    Ok(stats)
+}
+
+/// Error of a solve whose preconditioner returned a non-finite value after `iteration`
+/// iterations.
+pub(crate) fn preconditioner_failure(iteration: usize) -> SolveError {
+    SolveError {
+        code: COMPASS_ERR_PRECONDITIONER,
+        message: format!("preconditioner returned a non-finite value at iteration {iteration}"),
+    }
+}
+
+/// Preconditioner of [`sparse_pcg_solve`]: writes `z = M^-1 r` into the `n` elements of
+/// `z_out` from the `n` elements of `r_in`. `user_data` is passed through.
+pub type PreconditionerCallback =
+    extern "C" fn(user_data: *mut c_void, n: c_int, r_in: *const c_double, z_out: *mut c_double);
+
+/// A [`PreconditionerCallback`] with its user data.
+struct Callback {
+    apply: PreconditionerCallback,
+    user_data: *mut c_void,
+}
+
+impl Preconditioner for Callback {
+    fn apply(&self, r: &[f64], z: &mut [f64]) {
+        (self.apply)(self.user_data, r.len() as c_int, r.as_ptr(), z.as_mut_ptr());
+    }
+}
+
+/// Solves the sparse symmetric positive definite system `A x = b` by preconditioned
+/// Conjugate Gradient, see [`pcg`].
+///
+/// # Arguments
+///
+/// * `n` - Size of the matrix.
+/// * `row_offsets` - `n + 1` offsets into `col_indices` and `values`, see
+///   [`SymmetricMatrix::from_csr`].
+/// * `col_indices`, `values` - `row_offsets[n]` column indices and values.
+/// * `b` - The `n` values of the right-hand side.
+/// * `x` - The `n` values of the initial guess, overwritten by the solution.
+/// * `iterations`, `tolerance` - Iteration limit and absolute residual norm tolerance.
+/// * `callback` - Preconditioner of the caller, or null for the built-in `preconditioner`.
+/// * `user_data` - Passed through to `callback`.
+/// * `preconditioner` - Built-in preconditioner used without a callback, as
+///   [`crate::SolveOptions::preconditioner`].
+/// * `stats` - Receives the iterations, the residual norm and the stop reason, or null.
+/// * `err_buf` - Buffer receiving a NUL-terminated error message on failure. May be null.
+/// * `err_cap` - Capacity of `err_buf` in bytes.
+///
+/// # Returns
+///
+/// * [`COMPASS_OK`] on success, also when the iterations ran out, see `stats`.
+/// * [`COMPASS_ERR_INVALID_ARGUMENT`] for a negative count, a null pointer, an invalid matrix
+///   or an unknown preconditioner; `x` is left untouched.
+/// * [`COMPASS_ERR_PRECONDITIONER`] if the preconditioner wrote a non-finite value; `x` is
+///   left untouched.
+#[unsafe(no_mangle)]
+#[allow(clippy::too_many_arguments)]
+pub extern "C" fn sparse_pcg_solve(
+    n: c_int,
+    row_offsets: *const c_int,
+    col_indices: *const c_int,
+    values: *const c_double,
+    b: *const c_double,
+    x: *mut c_double,
+    iterations: c_int,
+    tolerance: c_double,
+    callback: Option<PreconditionerCallback>,
+    user_data: *mut c_void,
+    preconditioner: c_int,
+    stats: *mut SolveStats,
+    err_buf: *mut c_char,
+    err_cap: usize,
+) -> c_int {
+    let result = std::panic::catch_unwind(|| {
+        let fail = |code: c_int, message: &str| {
+            write_message(err_buf, err_cap, message);
+            code
+        };
+        let Ok(size) = usize::try_from(n) else {
+            return fail(COMPASS_ERR_INVALID_ARGUMENT, "negative count");
+        };
+        if row_offsets.is_null() || (size > 0 && (b.is_null() || x.is_null())) {
+            return fail(COMPASS_ERR_INVALID_ARGUMENT, "null pointer");
+        }
+        // Safety: The caller guarantees `n + 1` row offsets.
+        let offsets = unsafe { raw_slice(row_offsets, size + 1) };
+        let Ok(offsets) = offsets
+            .iter()
+            .map(|&o| usize::try_from(o))
+            .collect::<Result<Vec<_>, _>>()
+        else {
+            return fail(COMPASS_ERR_INVALID_ARGUMENT, "negative row offset");
+        };
+        let nnz = offsets[size];
+        if nnz > 0 && (col_indices.is_null() || values.is_null()) {
+            return fail(COMPASS_ERR_INVALID_ARGUMENT, "null pointer");
+        }
+        // Safety: The caller guarantees `row_offsets[n]` column indices and values.
+        let (columns, values) = unsafe { (raw_slice(col_indices, nnz), raw_slice(values, nnz)) };
+        let Ok(columns) = columns
+            .iter()
+            .map(|&c| usize::try_from(c))
+            .collect::<Result<Vec<_>, _>>()
+        else {
+            return fail(COMPASS_ERR_INVALID_ARGUMENT, "negative column index");
+        };
+        let matrix = match SymmetricMatrix::from_csr(size, offsets, columns, values.to_vec()) {
+            Ok(matrix) => matrix,
+            Err(err) => return fail(err.code, &err.message),
+        };
+        let Some(builtin) = crate::Preconditioner::from_code(preconditioner) else {
+            return fail(COMPASS_ERR_INVALID_ARGUMENT, "unknown preconditioner");
+        };
+        let callback = callback.map(|apply| Callback { apply, user_data });
+        let builtin = matrix.preconditioner(builtin);
+        let preconditioner: Option<&dyn Preconditioner> = match (&callback, &builtin) {
+            (Some(callback), _) => Some(callback),
+            (None, builtin) => builtin.as_deref().map(|op| op as &dyn Preconditioner),
+        };
+        // Safety: The caller guarantees `n` values behind `b` and `x`.
+        let (b, x) = unsafe { (raw_slice(b, size), raw_slice_mut(x, size)) };
+        let iterations = iterations.max(0) as usize;
+        match pcg(&matrix, b, x, preconditioner, iterations, tolerance) {
+            Ok(solved) => {
+                write_stats(stats, &solved);
+                COMPASS_OK
+            }
+            Err(err) => fail(err.code, &err.message),
+        }
+    });
+
+    result.unwrap_or_else(|_| {
+        eprintln!("Panic caught in sparse_pcg_solve");
+        COMPASS_ERR_PANIC
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::COMPASS_STOP_CONVERGED;
+
+    /// Normal matrix of a chain of `n` stations with unit weights, anchored at both ends, in
+    /// CSR form.
+    fn chain(n: usize) -> (Vec<usize>, Vec<usize>, Vec<f64>) {
+        let (mut offsets, mut columns, mut values) = (vec![0], Vec::new(), Vec::new());
+        for i in 0..n {
+            if i > 0 {
+                columns.push(i - 1);
+                values.push(-1.0);
+            }
+            columns.push(i);
+            values.push(2.0);
+            if i + 1 < n {
+                columns.push(i + 1);
+                values.push(-1.0);
+            }
+            offsets.push(columns.len());
+        }
+        (offsets, columns, values)
+    }
+
+    /// The exact inverse of the chain matrix of size `n`:
+    /// `(A^-1)_ij = (min(i, j) + 1) (n - max(i, j)) / (n + 1)` with 0-based indices.
+    struct ExactInverse(usize);
+
+    impl Preconditioner for ExactInverse {
+        fn apply(&self, r: &[f64], z: &mut [f64]) {
+            let n = self.0;
+            for (i, z) in z.iter_mut().enumerate() {
+                *z = (0..n)
+                    .map(|j| ((i.min(j) + 1) * (n - i.max(j))) as f64 / (n + 1) as f64 * r[j])
+                    .sum();
+            }
+        }
+    }
+
+    #[test]
+    fn an_exact_preconditioner_solves_in_one_iteration() {
+        let n = 40;
+        let (offsets, columns, values) = chain(n);
+        let matrix = SymmetricMatrix::from_csr(n, offsets, columns, values).unwrap();
+        let b: Vec<f64> = (0..n).map(|i| (i as f64 * 0.37).sin()).collect();
+
+        let mut exact = vec![0.0; n];
+        let stats = pcg(&matrix, &b, &mut exact, Some(&ExactInverse(n)), 100, 1e-10).unwrap();
+        assert_eq!(stats.iterations, 1);
+        assert_eq!(stats.stop_reason, COMPASS_STOP_CONVERGED);
+
+        // The built-in preconditioners take the same path to the same solution.
+        for builtin in [
+            crate::Preconditioner::None,
+            crate::Preconditioner::Jacobi,
+            crate::Preconditioner::AdditiveSchwarz {
+                block_size: 8,
+                overlap: 1,
+            },
+        ] {
+            let op = matrix.preconditioner(builtin);
+            let mut x = vec![0.0; n];
+            let stats = pcg(
+                &matrix,
+                &b,
+                &mut x,
+                op.as_deref().map(|op| op as _),
+                1000,
+                1e-10,
+            )
+            .unwrap();
+            assert!(stats.iterations > 1, "{builtin:?}");
+            for (x, exact) in x.iter().zip(&exact) {
+                assert!((x - exact).abs() < 1e-8, "{builtin:?}");
+            }
+        }
+
+        let err = pcg(&matrix, &b[1..], &mut exact, None, 10, 1e-10).unwrap_err();
+        assert_eq!(err.code, COMPASS_ERR_INVALID_ARGUMENT);
+    }
+
+    /// Jacobi scaling of the chain matrix, whose diagonal is 2, through the C ABI; writes a
+    /// NaN when `user_data` points at a true flag.
+    extern "C" fn halve(user_data: *mut c_void, n: c_int, r_in: *const c_double, z: *mut c_double) {
+        let broken = unsafe { *user_data.cast::<bool>() };
+        let (r, z) = unsafe {
+            (
+                std::slice::from_raw_parts(r_in, n as usize),
+                std::slice::from_raw_parts_mut(z, n as usize),
+            )
+        };
+        for (z, r) in z.iter_mut().zip(r) {
+            *z = if broken { f64::NAN } else { 0.5 * r };
+        }
+    }
+
+    #[test]
+    fn ffi_callbacks_precondition_and_nan_fails_the_solve() {
+        let n = 30;
+        let (offsets, columns, values) = chain(n);
+        let to_c = |v: &[usize]| v.iter().map(|&i| i as c_int).collect::<Vec<_>>();
+        let (offsets, columns) = (to_c(&offsets), to_c(&columns));
+        let b = vec![1.0; n];
+        let mut flag = false;
+        let solve = |x: &mut [f64], flag: &mut bool, stats: &mut SolveStats| {
+            let mut err = [0 as c_char; 128];
+            let code = sparse_pcg_solve(
+                n as c_int,
+                offsets.as_ptr(),
+                columns.as_ptr(),
+                values.as_ptr(),
+                b.as_ptr(),
+                x.as_mut_ptr(),
+                1000,
+                1e-10,
+                Some(halve),
+                (flag as *mut bool).cast(),
+                0,
+                stats,
+                err.as_mut_ptr(),
+                err.len(),
+            );
+            let message = unsafe { std::ffi::CStr::from_ptr(err.as_ptr()) };
+            (code, message.to_string_lossy().into_owned())
+        };
+
+        let mut stats = SolveStats {
+            struct_size: size_of::<SolveStats>(),
+            ..SolveStats::default()
+        };
+        let mut x = vec![0.0; n];
+        let (code, _) = solve(&mut x, &mut flag, &mut stats);
+        assert_eq!(code, COMPASS_OK);
+        assert_eq!(stats.stop_reason, COMPASS_STOP_CONVERGED);
+        // x_i = (i + 1) (n - i) / 2 solves the chain with a unit load.
+        for (i, x) in x.iter().enumerate() {
+            assert!((x - ((i + 1) * (n - i)) as f64 / 2.0).abs() < 1e-8);
+        }
+
+        flag = true;
+        let mut untouched = vec![7.0; n];
+        let (code, message) = solve(&mut untouched, &mut flag, &mut stats);
+        assert_eq!(code, COMPASS_ERR_PRECONDITIONER);
+        assert_eq!(
+            message,
+            "preconditioner returned a non-finite value at iteration 0"
+        );
+        assert_eq!(untouched, vec![7.0; n]);
+    }
+}
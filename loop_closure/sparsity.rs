@@ -0,0 +1,316 @@
+//! Sparsity pattern of the normal equations, for external studies of the structure of cave
+//! networks: the CSR row offsets and column indices of the matrix, and the station behind each
+//! of its rows, computed from the topology alone without assembling any value.
+//!
+//! The pattern is exactly that of the matrix the solver assembles, explicit zeros included:
+//! disabled edges keep their cells, see [`crate::SolveOptions::edge_enabled`], so it may hold
+//! more entries than [`crate::MatrixStats::nonzeros`] counts. Rows follow the
+//! [`COMPASS_ORDERING_NATURAL`] ordering, the only one the solver uses; [`SparsityInfo`]
+//! reports it so that a future reordering cannot be mistaken for it.
+
+use crate::{
+    COMPASS_ERR_BUFFER_TOO_SMALL, COMPASS_ERR_INVALID_ARGUMENT, COMPASS_ERR_PANIC, COMPASS_OK,
+    Graph, GraphContext, GraphView, SolveError, free_mapping,
+};
+use std::ffi::c_int;
+use std::slice;
+
+/// Row ordering of the normal equations: the free stations in ascending index order, fixed,
+/// passive and isolated stations having no row.
+pub const COMPASS_ORDERING_NATURAL: c_int = 0;
+
+/// Sizes and ordering of a sparsity pattern, filled by [`graph_sparsity`].
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SparsityInfo {
+    /// Number of rows (and columns): the unknowns of each axis.
+    pub rows: c_int,
+    /// Number of stored entries, explicit zeros of disabled edges included.
+    pub nonzeros: c_int,
+    /// Row ordering, [`COMPASS_ORDERING_NATURAL`].
+    pub ordering: c_int,
+}
+
+/// Pattern of the normal equations in CSR form, and the row of each vertex, see
+/// [`GraphContext::sparsity_pattern`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SparsityPattern {
+    /// `rows + 1` offsets into `col_indices`.
+    pub row_offsets: Vec<usize>,
+    /// Column of each entry, ascending within each row.
+    pub col_indices: Vec<usize>,
+    /// Row of each vertex, `None` for the vertices without one.
+    pub mapping: Vec<Option<usize>>,
+}
+
+impl SparsityPattern {
+    /// The cells [`crate::NormalEquations::assemble`] fills: the diagonal of every free end of
+    /// an edge and the couplings of the edges between two free vertices, sorted by row then
+    /// column.
+    fn of(graph: &GraphView) -> Result<SparsityPattern, c_int> {
+        let (mapping, rows) = free_mapping(graph.fixed.len(), graph, &|_| false)?;
+        let mut cells = Vec::new();
+        for (&u, &v) in graph.from.iter().zip(graph.to) {
+            match (mapping[u as usize], mapping[v as usize]) {
+                (Some(ui), Some(vi)) => cells.extend([(ui, ui), (vi, vi), (ui, vi), (vi, ui)]),
+                (Some(i), None) | (None, Some(i)) => cells.push((i, i)),
+                (None, None) => {}
+            }
+        }
+        cells.sort_unstable();
+        cells.dedup();
+
+        let mut row_offsets = vec![0; rows + 1];
+        for &(row, _) in &cells {
+            row_offsets[row + 1] += 1;
+        }
+        for row in 0..rows {
+            row_offsets[row + 1] += row_offsets[row];
+        }
+        let col_indices = cells.into_iter().map(|(_, column)| column).collect();
+        Ok(SparsityPattern {
+            row_offsets,
+            col_indices,
+            mapping,
+        })
+    }
+
+    /// Pattern of `graph` over its `enabled` edges, see [`Graph::normal_equations`].
+    fn of_graph(graph: &Graph, enabled: &[bool]) -> Result<SparsityPattern, SolveError> {
+        graph
+            .with_view(enabled, SparsityPattern::of)
+            .map_err(|code| SolveError {
+                code,
+                message: format!(
+                    "sparsity pattern of {} vertices too large to allocate",
+                    graph.num_vertices()
+                ),
+            })
+    }
+}
+
+impl Graph {
+    /// Row offsets and column indices of the normal equations of this graph in CSR form, the
+    /// columns of each row ascending, computed from the topology without assembling the
+    /// matrix. See [`Graph::row_mapping`] for the station of each row.
+    ///
+    /// # Panics
+    ///
+    /// If the vertex flags cannot be allocated, as any `Vec` would.
+    pub fn sparsity_pattern(&self) -> (Vec<usize>, Vec<usize>) {
+        match SparsityPattern::of_graph(self, &[]) {
+            Ok(pattern) => (pattern.row_offsets, pattern.col_indices),
+            Err(err) => panic!("{}", err.message),
+        }
+    }
+
+    /// Row of each vertex in the normal equations of this graph, `None` for the fixed and
+    /// isolated vertices, in the [`COMPASS_ORDERING_NATURAL`] ordering.
+    ///
+    /// # Panics
+    ///
+    /// If the vertex flags cannot be allocated, as any `Vec` would.
+    pub fn row_mapping(&self) -> Vec<Option<usize>> {
+        match SparsityPattern::of_graph(self, &[]) {
+            Ok(pattern) => pattern.mapping,
+            Err(err) => panic!("{}", err.message),
+        }
+    }
+}
+
+impl GraphContext {
+    /// Sparsity pattern of the normal equations of the enabled edges, those the next solve
+    /// assembles, see [`Graph::sparsity_pattern`].
+    pub fn sparsity_pattern(&self) -> Result<SparsityPattern, SolveError> {
+        SparsityPattern::of_graph(&self.graph, &self.edge_enabled)
+    }
+}
+
+/// Copies the sparsity pattern of the normal equations of the graph behind `handle`, see
+/// [`GraphContext::sparsity_pattern`], into caller buffers: `row_offsets_cap` CSR offsets,
+/// `rows + 1` of them, `col_indices_cap` column indices, `nonzeros` of them, and the station of
+/// each row into `vertices_cap` entries of `out_row_vertices`, `rows` of them. Any buffer may
+/// be null to skip it. `out_info` is filled either way, so that a first call with null buffers
+/// sizes them.
+///
+/// # Returns
+///
+/// * [`COMPASS_OK`].
+/// * [`COMPASS_ERR_BUFFER_TOO_SMALL`] if a buffer is shorter than it needs; nothing is written
+///   to the buffers.
+/// * [`COMPASS_ERR_INVALID_ARGUMENT`] for a null handle or `out_info`, or a pattern too large
+///   for `c_int` indices.
+/// * [`crate::COMPASS_ERR_OUT_OF_MEMORY`] when the pattern cannot be allocated.
+#[allow(clippy::too_many_arguments)]
+#[unsafe(no_mangle)]
+pub extern "C" fn graph_sparsity(
+    handle: *const GraphContext,
+    out_info: *mut SparsityInfo,
+    out_row_offsets: *mut c_int,
+    row_offsets_cap: c_int,
+    out_col_indices: *mut c_int,
+    col_indices_cap: c_int,
+    out_row_vertices: *mut c_int,
+    vertices_cap: c_int,
+) -> c_int {
+    let (Some(ctx), false) = ((unsafe { handle.as_ref() }), out_info.is_null()) else {
+        return COMPASS_ERR_INVALID_ARGUMENT;
+    };
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        let SparsityPattern {
+            row_offsets,
+            col_indices,
+            mapping,
+        } = match ctx.sparsity_pattern() {
+            Ok(pattern) => pattern,
+            Err(err) => return err.code,
+        };
+        let rows = row_offsets.len() - 1;
+        let (Ok(rows_int), Ok(nonzeros)) =
+            (c_int::try_from(rows), c_int::try_from(col_indices.len()))
+        else {
+            return COMPASS_ERR_INVALID_ARGUMENT;
+        };
+        // Safety: The caller guarantees a writable `out_info`.
+        unsafe {
+            *out_info = SparsityInfo {
+                rows: rows_int,
+                nonzeros,
+                ordering: COMPASS_ORDERING_NATURAL,
+            }
+        };
+        let fits = |buffer: *mut c_int, cap: c_int, len: usize| {
+            buffer.is_null() || cap.max(0) as usize >= len
+        };
+        if !fits(out_row_offsets, row_offsets_cap, rows + 1)
+            || !fits(out_col_indices, col_indices_cap, col_indices.len())
+            || !fits(out_row_vertices, vertices_cap, rows)
+        {
+            return COMPASS_ERR_BUFFER_TOO_SMALL;
+        }
+        let copy = |buffer: *mut c_int, values: &mut dyn Iterator<Item = usize>, len: usize| {
+            if !buffer.is_null() {
+                // Safety: The caller guarantees a buffer of its capacity, at least `len`.
+                let out = unsafe { slice::from_raw_parts_mut(buffer, len) };
+                for (dst, value) in out.iter_mut().zip(values) {
+                    *dst = value as c_int;
+                }
+            }
+        };
+        copy(out_row_offsets, &mut row_offsets.into_iter(), rows + 1);
+        copy(
+            out_col_indices,
+            &mut col_indices.iter().copied(),
+            nonzeros as usize,
+        );
+        let mut row_vertices = (0..mapping.len()).filter(|&v| mapping[v].is_some());
+        copy(out_row_vertices, &mut row_vertices, rows);
+        COMPASS_OK
+    }));
+
+    result.unwrap_or_else(|_| {
+        eprintln!("Panic caught in graph_sparsity");
+        COMPASS_ERR_PANIC
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph_free;
+
+    /// A triangle hanging from the fixed station 0, with an isolated free station 4 and a
+    /// pendant station 3 hanging from the fixed one.
+    fn triangle() -> Graph {
+        let mut graph = Graph::default();
+        graph.add_vertex(0.0, 0.0, 0.0, true);
+        for _ in 1..5 {
+            graph.add_vertex(0.0, 0.0, 0.0, false);
+        }
+        graph.add_edge(0, 1, 10.0, 0.0, 0.0, 1.0);
+        graph.add_edge(1, 2, 0.0, 10.0, 0.0, 1.0);
+        graph.add_edge(2, 0, -10.0, -10.0, 0.0, 1.0);
+        graph.add_edge(3, 0, 5.0, 5.0, 0.0, 1.0);
+        graph
+    }
+
+    #[test]
+    fn pattern_matches_the_assembled_matrix() {
+        let graph = crate::fixtures::synthetic_cave(7, 200);
+        let system = graph.normal_equations(&[]).unwrap();
+        let (offsets, columns) = graph.sparsity_pattern();
+        assert_eq!(offsets, system.matrix.row_offsets());
+        assert_eq!(columns, system.matrix.col_indices());
+        assert_eq!(graph.row_mapping(), system.mapping);
+
+        let graph = triangle();
+        assert_eq!(
+            graph.sparsity_pattern(),
+            (vec![0, 2, 4, 5], vec![0, 1, 0, 1, 2])
+        );
+        assert_eq!(graph.row_mapping(), [None, Some(0), Some(1), Some(2), None]);
+    }
+
+    #[test]
+    fn ffi_sizes_then_fills_the_buffers() {
+        let handle = GraphContext::into_raw(triangle());
+        let mut info = SparsityInfo::default();
+        let null = std::ptr::null_mut();
+        assert_eq!(
+            graph_sparsity(handle, &mut info, null, 0, null, 0, null, 0),
+            COMPASS_OK
+        );
+        let expected = SparsityInfo {
+            rows: 3,
+            nonzeros: 5,
+            ordering: COMPASS_ORDERING_NATURAL,
+        };
+        assert_eq!(info, expected);
+
+        let (mut offsets, mut columns, mut vertices) = ([-1; 4], [-1; 5], [-1; 3]);
+        assert_eq!(
+            graph_sparsity(
+                handle,
+                &mut info,
+                offsets.as_mut_ptr(),
+                3,
+                columns.as_mut_ptr(),
+                5,
+                vertices.as_mut_ptr(),
+                3
+            ),
+            COMPASS_ERR_BUFFER_TOO_SMALL
+        );
+        assert_eq!(offsets, [-1; 4]);
+        assert_eq!(
+            graph_sparsity(
+                handle,
+                &mut info,
+                offsets.as_mut_ptr(),
+                4,
+                columns.as_mut_ptr(),
+                5,
+                vertices.as_mut_ptr(),
+                3
+            ),
+            COMPASS_OK
+        );
+        assert_eq!(
+            (offsets, columns, vertices),
+            ([0, 2, 4, 5], [0, 1, 0, 1, 2], [1, 2, 3])
+        );
+
+        // Disabling the pendant shot leaves station 3 without a row.
+        unsafe { &mut *handle }.edge_enabled = vec![true, true, true, false];
+        assert_eq!(
+            graph_sparsity(handle, &mut info, null, 0, null, 0, null, 0),
+            COMPASS_OK
+        );
+        assert_eq!((info.rows, info.nonzeros), (2, 4));
+        assert_eq!(
+            graph_sparsity(std::ptr::null(), &mut info, null, 0, null, 0, null, 0),
+            COMPASS_ERR_INVALID_ARGUMENT
+        );
+        graph_free(handle);
+    }
+}
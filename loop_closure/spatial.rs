@@ -0,0 +1,540 @@
+//! Spatial queries on the current coordinates of a [`GraphContext`]: the station nearest to a
+//! point, e.g. under a click in a viewer, and the edges meeting a bounding box, e.g. those
+//! to redraw.
+//!
+//! The queries run on a [`SpatialIndex`] built by the first query after the coordinates
+//! change, i.e. after a successful solve or new edges, and shared by the queries after it.
+//! The index is a uniform grid over the bounding box of the stations with about one station
+//! per cell: each station is filed under its cell, and each edge under every cell its
+//! segment crosses.
+//!
+//! Bounding boxes are closed: an edge that only touches the box, at an end or along a side,
+//! meets it. Stations and edges with a non-finite coordinate are never returned.
+
+use crate::{
+    COMPASS_ERR_BUFFER_TOO_SMALL, COMPASS_ERR_INVALID_ARGUMENT, COMPASS_OK, Graph, GraphContext,
+    write_column,
+};
+use std::ffi::{c_double, c_int};
+
+/// Uniform grid over the stations and edges of a graph at given coordinates, see the module
+/// documentation.
+#[derive(Debug, Clone)]
+pub struct SpatialIndex {
+    x: Vec<f64>,
+    y: Vec<f64>,
+    from: Vec<usize>,
+    to: Vec<usize>,
+    /// Lower left corner of the grid.
+    origin: (f64, f64),
+    /// Width of a square cell.
+    cell: f64,
+    /// Number of columns and rows.
+    columns: usize,
+    rows: usize,
+    /// Stations of cell `c` (column-major) in `vertices[vertex_start[c]..vertex_start[c + 1]]`.
+    vertex_start: Vec<usize>,
+    vertices: Vec<usize>,
+    /// Edges of cell `c`, as for the stations.
+    edge_start: Vec<usize>,
+    edges: Vec<usize>,
+}
+
+impl SpatialIndex {
+    /// Indexes the stations of `graph` at `(x, y)` and its edges between them.
+    pub fn new(graph: &Graph, x: &[f64], y: &[f64]) -> SpatialIndex {
+        let n = x.len().min(y.len());
+        let usable = |v: usize| v < n && x[v].is_finite() && y[v].is_finite();
+        let stations: Vec<usize> = (0..n).filter(|&v| usable(v)).collect();
+        let (mut min, mut max) = ((0.0, 0.0), (0.0, 0.0));
+        if let Some(&first) = stations.first() {
+            (min, max) = ((x[first], y[first]), (x[first], y[first]));
+        }
+        for &v in &stations {
+            min = (min.0.min(x[v]), min.1.min(y[v]));
+            max = (max.0.max(x[v]), max.1.max(y[v]));
+        }
+        let (width, height) = (max.0 - min.0, max.1 - min.1);
+        // About one station per cell, and no more cells along a side than stations.
+        let count = stations.len().max(1) as f64;
+        let cell = (width * height / count)
+            .sqrt()
+            .max(width.max(height) / count);
+        let cell = if cell > 0.0 && cell.is_finite() {
+            cell
+        } else {
+            1.0
+        };
+        let mut index = SpatialIndex {
+            x: x.to_vec(),
+            y: y.to_vec(),
+            from: graph.from.clone(),
+            to: graph.to.clone(),
+            origin: min,
+            cell,
+            columns: (width / cell) as usize + 1,
+            rows: (height / cell) as usize + 1,
+            vertex_start: Vec::new(),
+            vertices: Vec::new(),
+            edge_start: Vec::new(),
+            edges: Vec::new(),
+        };
+
+        let mut filed = Vec::with_capacity(stations.len());
+        for &v in &stations {
+            filed.push((index.cell_of(x[v], y[v]), v));
+        }
+        (index.vertex_start, index.vertices) = index.bucket(filed);
+
+        let mut filed = Vec::with_capacity(graph.num_edges());
+        for (e, (&u, &v)) in graph.from.iter().zip(&graph.to).enumerate() {
+            if usable(u) && usable(v) {
+                index.segment_cells((x[u], y[u]), (x[v], y[v]), |c| filed.push((c, e)));
+            }
+        }
+        (index.edge_start, index.edges) = index.bucket(filed);
+        index
+    }
+
+    /// The station nearest to `(x, y)` and its distance, the lowest index among equally near
+    /// ones. `None` without stations or for a non-finite point.
+    pub fn nearest(&self, x: f64, y: f64) -> Option<(usize, f64)> {
+        if self.vertices.is_empty() || !(x.is_finite() && y.is_finite()) {
+            return None;
+        }
+        let (column, row) = self.column_row(x, y);
+        let mut best: Option<(usize, f64)> = None;
+        for ring in 0..self.columns.max(self.rows) {
+            // The cells not searched yet are `ring` cells or more away from the cell of the
+            // point, or of its projection onto the grid, so at least `ring - 1` cell widths
+            // away from the point.
+            if best
+                .is_some_and(|(_, distance)| distance <= ring.saturating_sub(1) as f64 * self.cell)
+            {
+                break;
+            }
+            for (c, r) in ring_cells(column, row, ring, self.columns, self.rows) {
+                let cell = c * self.rows + r;
+                for &v in &self.vertices[self.vertex_start[cell]..self.vertex_start[cell + 1]] {
+                    let distance = (self.x[v] - x).hypot(self.y[v] - y);
+                    if best.is_none_or(|(w, d)| (distance, v) < (d, w)) {
+                        best = Some((v, distance));
+                    }
+                }
+            }
+        }
+        best
+    }
+
+    /// Edges whose segment meets the closed box `[min_x, max_x] x [min_y, max_y]`, in
+    /// ascending order. Empty for an empty or non-finite box.
+    pub fn edges_in_box(&self, min_x: f64, min_y: f64, max_x: f64, max_y: f64) -> Vec<usize> {
+        if !valid_box(min_x, min_y, max_x, max_y) {
+            return Vec::new();
+        }
+        let (c0, r0) = self.column_row(min_x, min_y);
+        let (c1, r1) = self.column_row(max_x, max_y);
+        let mut found = Vec::new();
+        for c in c0..=c1 {
+            for r in r0..=r1 {
+                let cell = c * self.rows + r;
+                for &e in &self.edges[self.edge_start[cell]..self.edge_start[cell + 1]] {
+                    let (u, v) = (self.from[e], self.to[e]);
+                    let (a, b) = ((self.x[u], self.y[u]), (self.x[v], self.y[v]));
+                    if segment_meets_box(a, b, (min_x, min_y), (max_x, max_y)) {
+                        found.push(e);
+                    }
+                }
+            }
+        }
+        found.sort_unstable();
+        found.dedup();
+        found
+    }
+
+    /// Column and row of the cell holding `(x, y)`, or nearest to it outside the grid.
+    fn column_row(&self, x: f64, y: f64) -> (usize, usize) {
+        let along = |value: f64, origin: f64, count: usize| {
+            ((value - origin) / self.cell)
+                .floor()
+                .clamp(0.0, (count - 1) as f64) as usize
+        };
+        (
+            along(x, self.origin.0, self.columns),
+            along(y, self.origin.1, self.rows),
+        )
+    }
+
+    fn cell_of(&self, x: f64, y: f64) -> usize {
+        let (column, row) = self.column_row(x, y);
+        column * self.rows + row
+    }
+
+    /// Calls `file` with every cell the segment `a`-`b` crosses: column by column, the rows
+    /// between the heights of the segment at the sides of the column.
+    fn segment_cells(&self, a: (f64, f64), b: (f64, f64), mut file: impl FnMut(usize)) {
+        let (left, right) = if a.0 <= b.0 { (a, b) } else { (b, a) };
+        let (c0, _) = self.column_row(left.0, left.1);
+        let (c1, _) = self.column_row(right.0, right.1);
+        let slope = (right.1 - left.1) / (right.0 - left.0);
+        // Rounding must not lose a cell the segment grazes.
+        let margin = 1e-9 * self.cell;
+        for c in c0..=c1 {
+            let side = |column: usize| self.origin.0 + column as f64 * self.cell;
+            let (xa, xb) = (side(c).max(left.0), side(c + 1).min(right.0));
+            let (ya, yb) = if slope.is_finite() {
+                (
+                    left.1 + slope * (xa - left.0),
+                    left.1 + slope * (xb - left.0),
+                )
+            } else {
+                (left.1, right.1)
+            };
+            let (_, r0) = self.column_row(xa, ya.min(yb) - margin);
+            let (_, r1) = self.column_row(xa, ya.max(yb) + margin);
+            for r in r0..=r1 {
+                file(c * self.rows + r);
+            }
+        }
+    }
+
+    /// Sorts `(cell, item)` pairs into the start offset of each cell and the items.
+    fn bucket(&self, mut filed: Vec<(usize, usize)>) -> (Vec<usize>, Vec<usize>) {
+        filed.sort_unstable();
+        let cells = self.columns * self.rows;
+        let mut start = vec![0; cells + 1];
+        for &(cell, _) in &filed {
+            start[cell + 1] += 1;
+        }
+        for cell in 0..cells {
+            start[cell + 1] += start[cell];
+        }
+        (start, filed.into_iter().map(|(_, item)| item).collect())
+    }
+}
+
+/// Cells of the square ring `ring` cells away from `(column, row)`, within the grid.
+fn ring_cells(
+    column: usize,
+    row: usize,
+    ring: usize,
+    columns: usize,
+    rows: usize,
+) -> impl Iterator<Item = (usize, usize)> {
+    let (c0, c1) = (
+        column.saturating_sub(ring),
+        (column + ring).min(columns - 1),
+    );
+    let (r0, r1) = (row.saturating_sub(ring), (row + ring).min(rows - 1));
+    (c0..=c1).flat_map(move |c| {
+        (r0..=r1)
+            .filter(move |&r| c.abs_diff(column) == ring || r.abs_diff(row) == ring)
+            .map(move |r| (c, r))
+    })
+}
+
+/// Whether the corners are finite and `min` is not above `max`.
+fn valid_box(min_x: f64, min_y: f64, max_x: f64, max_y: f64) -> bool {
+    [min_x, min_y, max_x, max_y].iter().all(|c| c.is_finite()) && min_x <= max_x && min_y <= max_y
+}
+
+/// Whether the segment `a`-`b` has a point in the closed box `min`-`max`, by Liang-Barsky
+/// clipping.
+fn segment_meets_box(a: (f64, f64), b: (f64, f64), min: (f64, f64), max: (f64, f64)) -> bool {
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let (mut t0, mut t1) = (0.0f64, 1.0f64);
+    for (p, q) in [
+        (-dx, a.0 - min.0),
+        (dx, max.0 - a.0),
+        (-dy, a.1 - min.1),
+        (dy, max.1 - a.1),
+    ] {
+        if p == 0.0 {
+            if q < 0.0 {
+                return false;
+            }
+        } else {
+            let t = q / p;
+            if p < 0.0 {
+                t0 = t0.max(t);
+            } else {
+                t1 = t1.min(t);
+            }
+            if t0 > t1 {
+                return false;
+            }
+        }
+    }
+    true
+}
+
+impl GraphContext {
+    /// The spatial index over the current [`GraphContext::coordinates`], built on first use.
+    pub fn spatial_index(&self) -> &SpatialIndex {
+        self.spatial_index.get_or_init(|| {
+            let (x, y) = self.coordinates();
+            SpatialIndex::new(&self.graph, x, y)
+        })
+    }
+
+    /// [`SpatialIndex::nearest`] at the current coordinates.
+    pub fn query_nearest(&self, x: f64, y: f64) -> Option<(usize, f64)> {
+        self.spatial_index().nearest(x, y)
+    }
+
+    /// [`SpatialIndex::edges_in_box`] at the current coordinates. Disabled edges are
+    /// included.
+    pub fn query_bbox(&self, min_x: f64, min_y: f64, max_x: f64, max_y: f64) -> Vec<usize> {
+        self.spatial_index()
+            .edges_in_box(min_x, min_y, max_x, max_y)
+    }
+}
+
+/// Finds the station of the graph behind `handle` nearest to `(x, y)`, see
+/// [`GraphContext::query_nearest`]. Writes its index to `out_index` and its distance to
+/// `out_dist`, or -1 and infinity without finite stations. Either pointer may be null.
+///
+/// Returns [`COMPASS_OK`], or [`COMPASS_ERR_INVALID_ARGUMENT`] for a null handle or a
+/// non-finite point.
+#[unsafe(no_mangle)]
+pub extern "C" fn graph_query_nearest(
+    handle: *const GraphContext,
+    x: c_double,
+    y: c_double,
+    out_index: *mut c_int,
+    out_dist: *mut c_double,
+) -> c_int {
+    let Some(ctx) = (unsafe { handle.as_ref() }) else {
+        return COMPASS_ERR_INVALID_ARGUMENT;
+    };
+    if !(x.is_finite() && y.is_finite()) {
+        return COMPASS_ERR_INVALID_ARGUMENT;
+    }
+    let (index, distance) = ctx
+        .query_nearest(x, y)
+        .map_or((-1, f64::INFINITY), |(v, d)| (v as c_int, d));
+    if !out_index.is_null() {
+        unsafe { *out_index = index };
+    }
+    if !out_dist.is_null() {
+        unsafe { *out_dist = distance };
+    }
+    COMPASS_OK
+}
+
+/// Copies the edges of the graph behind `handle` meeting the closed box
+/// `[min_x, max_x] x [min_y, max_y]` into `out_buf` of `cap` elements, in ascending order,
+/// see [`GraphContext::query_bbox`]. The number of edges is written to `out_count` either
+/// way, so that a first call with `cap` 0 sizes the buffer. `out_count` may be null.
+///
+/// # Returns
+///
+/// * [`COMPASS_OK`].
+/// * [`COMPASS_ERR_BUFFER_TOO_SMALL`] if `cap` is below the number of edges; nothing is
+///   written to `out_buf`.
+/// * [`COMPASS_ERR_INVALID_ARGUMENT`] for a null handle, or a box with a non-finite corner
+///   or `min` above `max`.
+#[unsafe(no_mangle)]
+#[allow(clippy::too_many_arguments)]
+pub extern "C" fn graph_query_bbox(
+    handle: *const GraphContext,
+    min_x: c_double,
+    min_y: c_double,
+    max_x: c_double,
+    max_y: c_double,
+    out_buf: *mut c_int,
+    cap: c_int,
+    out_count: *mut c_int,
+) -> c_int {
+    let Some(ctx) = (unsafe { handle.as_ref() }) else {
+        return COMPASS_ERR_INVALID_ARGUMENT;
+    };
+    if !valid_box(min_x, min_y, max_x, max_y) {
+        return COMPASS_ERR_INVALID_ARGUMENT;
+    }
+    let edges = ctx.query_bbox(min_x, min_y, max_x, max_y);
+    if !out_count.is_null() {
+        unsafe { *out_count = edges.len() as c_int };
+    }
+    if (cap.max(0) as usize) < edges.len() {
+        return COMPASS_ERR_BUFFER_TOO_SMALL;
+    }
+    write_column(out_buf, &edges, |&e| e as c_int);
+    COMPASS_OK
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{SolveOptions, SolveStats, graph_free, graph_solve};
+
+    /// A unit square 0-1-2-3 with the diagonal 0-2, and a station 4 far to the east.
+    fn square() -> Graph {
+        let mut graph = Graph::default();
+        for (x, y) in [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0), (10.0, 0.5)] {
+            graph.add_vertex(x, y, 0.0, false);
+        }
+        for (u, v) in [(0, 1), (1, 2), (2, 3), (3, 0), (0, 2)] {
+            let (dx, dy) = (graph.x[v] - graph.x[u], graph.y[v] - graph.y[u]);
+            graph.add_edge(u, v, dx, dy, 0.0, 1.0);
+        }
+        graph
+    }
+
+    fn index(graph: &Graph) -> SpatialIndex {
+        SpatialIndex::new(graph, &graph.x, &graph.y)
+    }
+
+    #[test]
+    fn bounding_boxes_are_closed() {
+        let index = index(&square());
+        // Touching the right side along the edge 1-2, and at the ends of 0-1 and 2-3.
+        assert_eq!(index.edges_in_box(1.0, 0.2, 2.0, 0.8), [1]);
+        assert_eq!(index.edges_in_box(1.0, 0.0, 2.0, 1.0), [0, 1, 2, 4]);
+        // Just outside the square.
+        assert!(index.edges_in_box(1.0 + 1e-12, 0.0, 2.0, 1.0).is_empty());
+        // Crossing the diagonal without holding an end of it.
+        assert_eq!(index.edges_in_box(0.4, 0.5, 0.6, 0.55), [4]);
+        assert!(index.edges_in_box(0.4, 0.6, 0.45, 0.65).is_empty());
+        // A degenerate box is a point or a line.
+        assert_eq!(index.edges_in_box(0.5, 0.5, 0.5, 0.5), [4]);
+        assert_eq!(index.edges_in_box(0.5, -1.0, 0.5, 2.0), [0, 2, 4]);
+        // Outside the grid, and invalid boxes.
+        assert!(index.edges_in_box(-5.0, -5.0, -4.0, -4.0).is_empty());
+        assert_eq!(index.edges_in_box(-5.0, -5.0, 50.0, 50.0), [0, 1, 2, 3, 4]);
+        assert!(index.edges_in_box(1.0, 0.0, 0.0, 1.0).is_empty());
+        assert!(index.edges_in_box(f64::NAN, 0.0, 1.0, 1.0).is_empty());
+    }
+
+    #[test]
+    fn finds_the_nearest_station() {
+        let index = index(&square());
+        assert_eq!(index.nearest(0.9, 0.1), Some((1, 0.1f64.hypot(0.1))));
+        assert_eq!(index.nearest(8.0, 0.5), Some((4, 2.0)));
+        // Equidistant from 0 and 1: the lower index wins.
+        assert_eq!(index.nearest(0.5, -3.0).map(|(v, _)| v), Some(0));
+        // Far outside the grid.
+        assert_eq!(index.nearest(-100.0, 1.0), Some((3, 100.0)));
+        assert_eq!(index.nearest(f64::NAN, 0.0), None);
+        assert_eq!(
+            SpatialIndex::new(&Graph::default(), &[], &[]).nearest(0.0, 0.0),
+            None
+        );
+    }
+
+    #[test]
+    fn matches_brute_force_on_scattered_points() {
+        let mut state = 0x9e37_79b9_7f4a_7c15_u64;
+        let mut next = || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state >> 11) as f64 / (1u64 << 53) as f64
+        };
+        let mut graph = Graph::default();
+        for _ in 0..300 {
+            graph.add_vertex(next() * 100.0, next() * 30.0, 0.0, false);
+        }
+        for _ in 0..300 {
+            let (u, v) = ((next() * 300.0) as usize, (next() * 300.0) as usize);
+            graph.add_edge(u, v, 0.0, 0.0, 0.0, 1.0);
+        }
+        let index = index(&graph);
+        for _ in 0..50 {
+            let (x, y) = (next() * 120.0 - 10.0, next() * 50.0 - 10.0);
+            let expected = (0..300)
+                .map(|v| ((graph.x[v] - x).hypot(graph.y[v] - y), v))
+                .min_by(|a, b| a.partial_cmp(b).unwrap())
+                .map(|(d, v)| (v, d));
+            assert_eq!(index.nearest(x, y), expected);
+
+            let (w, h) = (next() * 20.0, next() * 10.0);
+            let expected: Vec<usize> = (0..graph.num_edges())
+                .filter(|&e| {
+                    let (u, v) = (graph.from[e], graph.to[e]);
+                    let (a, b) = ((graph.x[u], graph.y[u]), (graph.x[v], graph.y[v]));
+                    segment_meets_box(a, b, (x, y), (x + w, y + h))
+                })
+                .collect();
+            assert_eq!(index.edges_in_box(x, y, x + w, y + h), expected);
+        }
+    }
+
+    #[test]
+    fn a_solve_rebuilds_the_index() {
+        // Station 1 starts at the origin with station 0 and is adjusted 5 m east.
+        let mut graph = Graph::default();
+        graph.add_vertex(0.0, 0.0, 0.0, true);
+        graph.add_vertex(0.0, 0.0, 0.0, false);
+        graph.add_edge(0, 1, 5.0, 0.0, 0.0, 1.0);
+        let handle = GraphContext::into_raw(graph);
+        let (mut index, mut distance) = (-1, 0.0);
+        assert_eq!(
+            graph_query_nearest(handle, 4.0, 0.0, &mut index, &mut distance),
+            COMPASS_OK
+        );
+        assert_eq!((index, distance), (0, 4.0));
+
+        let mut stats = SolveStats {
+            struct_size: size_of::<SolveStats>(),
+            ..SolveStats::default()
+        };
+        assert_eq!(
+            graph_solve(handle, &SolveOptions::default(), &mut stats),
+            COMPASS_OK
+        );
+        assert_eq!(
+            graph_query_nearest(handle, 4.0, 0.0, &mut index, std::ptr::null_mut()),
+            COMPASS_OK
+        );
+        assert_eq!(index, 1);
+
+        let (mut edges, mut count) = ([-1; 1], -1);
+        assert_eq!(
+            graph_query_bbox(
+                handle,
+                4.0,
+                -1.0,
+                6.0,
+                1.0,
+                edges.as_mut_ptr(),
+                0,
+                &mut count
+            ),
+            COMPASS_ERR_BUFFER_TOO_SMALL
+        );
+        assert_eq!((edges, count), ([-1], 1));
+        assert_eq!(
+            graph_query_bbox(
+                handle,
+                4.0,
+                -1.0,
+                6.0,
+                1.0,
+                edges.as_mut_ptr(),
+                1,
+                &mut count
+            ),
+            COMPASS_OK
+        );
+        assert_eq!(edges, [0]);
+        assert_eq!(
+            graph_query_bbox(
+                handle,
+                6.0,
+                0.0,
+                4.0,
+                1.0,
+                edges.as_mut_ptr(),
+                1,
+                &mut count
+            ),
+            COMPASS_ERR_INVALID_ARGUMENT
+        );
+        assert_eq!(
+            graph_query_nearest(handle, f64::NAN, 0.0, &mut index, &mut distance),
+            COMPASS_ERR_INVALID_ARGUMENT
+        );
+        graph_free(handle);
+    }
+}
@@ -0,0 +1,284 @@
+//! Product of the normal matrix with a vector, the `A p` of every iteration of CG and of the
+//! Chebyshev semi-iteration, split across threads by nonzeros.
+//!
+//! The rows are cut into runs of about the same number of nonzeros rather than of rows, so
+//! that a hub station, e.g. a cave entrance tied to thousands of surface shots, does not leave
+//! one thread with most of the work on top of its share of rows. Runs are cut between rows
+//! only, and every row is summed in the order of its nonzeros by one thread, so the product,
+//! and the solve, is the same to the last bit whatever the number of threads. A hub row heavier
+//! than a share is a run of its own.
+//!
+//! The threads are spawned once, when the product of a solve is set up, and wait for the
+//! vectors of each product; the calling thread computes the first run itself.
+
+use nalgebra_sparse::CsrMatrix;
+use std::ops::Range;
+use std::sync::mpsc::{Receiver, Sender, channel};
+use std::thread::JoinHandle;
+
+/// Matrices with fewer nonzeros are multiplied on the calling thread: below that, handing
+/// the vectors to other threads costs more than it saves.
+const PARALLEL_NONZEROS: usize = 1 << 18;
+
+/// How the rows are cut into runs, one per thread.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RowSplit {
+    /// Runs of as many rows, the split before hub rows were accounted for, kept for the
+    /// benchmarks to compare with.
+    #[cfg_attr(not(any(test, feature = "fixtures")), allow(dead_code))]
+    Rows,
+    /// Runs of about as many nonzeros.
+    Nonzeros,
+}
+
+/// One product of a run of rows, handed to a worker.
+///
+/// The slices are passed as raw parts: they are borrowed for the duration of
+/// [`Spmv::apply`], which waits for every worker to be done with them before it returns.
+#[derive(Clone, Copy)]
+struct Job {
+    offsets: (*const usize, usize),
+    columns: (*const usize, usize),
+    values: (*const f64, usize),
+    x: (*const f64, usize),
+    /// First output of the run, `y[rows.start]`.
+    y: *mut f64,
+    rows: (usize, usize),
+}
+
+// Safety: A job only reaches a worker during `Spmv::apply`, while the slices it points into
+// are borrowed, and the output rows of the workers do not overlap.
+unsafe impl Send for Job {}
+
+impl Job {
+    /// Computes the run of rows of the job.
+    ///
+    /// # Safety
+    ///
+    /// The pointers come from live slices of the given lengths, and no other thread accesses
+    /// the output rows until the job is done.
+    unsafe fn run(self) {
+        let (start, end) = self.rows;
+        let (offsets, columns, values, x, y) = unsafe {
+            (
+                std::slice::from_raw_parts(self.offsets.0, self.offsets.1),
+                std::slice::from_raw_parts(self.columns.0, self.columns.1),
+                std::slice::from_raw_parts(self.values.0, self.values.1),
+                std::slice::from_raw_parts(self.x.0, self.x.1),
+                std::slice::from_raw_parts_mut(self.y, end - start),
+            )
+        };
+        product_rows((offsets, columns, values), x, start..end, y);
+    }
+}
+
+/// A thread waiting for the jobs of one run.
+struct Worker {
+    jobs: Sender<Job>,
+    handle: JoinHandle<()>,
+}
+
+/// Split of the product `y = A x` of one matrix across threads, set up once per solve.
+pub(crate) struct Spmv<'a> {
+    a: &'a CsrMatrix<f64>,
+    /// Start of the run of each thread, followed by the number of rows.
+    bounds: Vec<usize>,
+    /// Threads of the runs after the first, in order.
+    workers: Vec<Worker>,
+    /// Whether each job of the workers completed without a panic.
+    done: Receiver<bool>,
+}
+
+impl<'a> Spmv<'a> {
+    /// Splits the product with `a` across the threads left to each axis of the solve, the
+    /// two axes being solved in parallel already.
+    pub(crate) fn new(a: &'a CsrMatrix<f64>) -> Spmv<'a> {
+        #[cfg(target_arch = "wasm32")]
+        let threads = 1;
+        #[cfg(not(target_arch = "wasm32"))]
+        let threads = match a.nnz() < PARALLEL_NONZEROS {
+            true => 1,
+            false => std::thread::available_parallelism().map_or(1, |n| (n.get() / 2).max(1)),
+        };
+        Spmv::with_threads(a, RowSplit::Nonzeros, threads)
+    }
+
+    /// Splits the product with `a` across `threads` threads, or as many as can be spawned.
+    pub(crate) fn with_threads(a: &'a CsrMatrix<f64>, split: RowSplit, threads: usize) -> Spmv<'a> {
+        let (done_sender, done) = channel();
+        let mut workers = Vec::new();
+        for _ in 1..threads.max(1) {
+            let (jobs, waiting) = channel::<Job>();
+            let done = done_sender.clone();
+            let spawned = std::thread::Builder::new()
+                .name("spmv".to_string())
+                .spawn(move || {
+                    while let Ok(job) = waiting.recv() {
+                        // Safety: See `Job`.
+                        let run = std::panic::catch_unwind(|| unsafe { job.run() });
+                        if done.send(run.is_ok()).is_err() {
+                            break;
+                        }
+                    }
+                });
+            match spawned {
+                Ok(handle) => workers.push(Worker { jobs, handle }),
+                Err(_) => break,
+            }
+        }
+        Spmv {
+            a,
+            bounds: bounds(a, split, workers.len() + 1),
+            workers,
+            done,
+        }
+    }
+
+    /// Overwrites `y` with `A x`.
+    pub(crate) fn apply(&self, x: &[f64], y: &mut [f64]) {
+        assert_eq!(x.len(), self.a.ncols());
+        assert_eq!(y.len(), self.a.nrows());
+        let (offsets, columns, values) =
+            (self.a.row_offsets(), self.a.col_indices(), self.a.values());
+        let output = y.as_mut_ptr();
+        for (worker, run) in self.workers.iter().zip(self.bounds.windows(2).skip(1)) {
+            let job = Job {
+                offsets: (offsets.as_ptr(), offsets.len()),
+                columns: (columns.as_ptr(), columns.len()),
+                values: (values.as_ptr(), values.len()),
+                x: (x.as_ptr(), x.len()),
+                // Safety: The run starts within `y`.
+                y: unsafe { output.add(run[0]) },
+                rows: (run[0], run[1]),
+            };
+            worker
+                .jobs
+                .send(job)
+                .expect("product threads outlive the split");
+        }
+        // The workers write into `y` until they are done: the calling thread waits for them
+        // even if its own run panics.
+        let own = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let first = 0..self.bounds[1];
+            // Safety: The first run is disjoint from those of the workers.
+            let y = unsafe { std::slice::from_raw_parts_mut(output, first.len()) };
+            product_rows((offsets, columns, values), x, first, y);
+        }));
+        let failed = (0..self.workers.len())
+            .filter(|_| !self.done.recv().unwrap_or(false))
+            .count();
+        if let Err(panic) = own {
+            std::panic::resume_unwind(panic);
+        }
+        assert_eq!(failed, 0, "matrix-vector product failed on a worker thread");
+    }
+}
+
+impl Drop for Spmv<'_> {
+    fn drop(&mut self) {
+        for worker in self.workers.drain(..) {
+            drop(worker.jobs);
+            let _ = worker.handle.join();
+        }
+    }
+}
+
+/// Start of the run of each of `threads` threads, followed by the number of rows of `a`.
+fn bounds(a: &CsrMatrix<f64>, split: RowSplit, threads: usize) -> Vec<usize> {
+    let (rows, offsets) = (a.nrows(), a.row_offsets());
+    let mut bounds = vec![0];
+    for t in 1..threads {
+        let start = match split {
+            RowSplit::Rows => rows * t / threads,
+            // The first row whose nonzeros start at or past `t` shares.
+            RowSplit::Nonzeros => offsets[..rows].partition_point(|&o| o < a.nnz() * t / threads),
+        };
+        bounds.push(start.max(bounds[t - 1]));
+    }
+    bounds.push(rows);
+    bounds
+}
+
+/// `y = A x` over `rows`, each row summed in the order of its nonzeros.
+fn product_rows(
+    (offsets, columns, values): (&[usize], &[usize], &[f64]),
+    x: &[f64],
+    rows: Range<usize>,
+    y: &mut [f64],
+) {
+    for (y, row) in y.iter_mut().zip(rows) {
+        let mut sum = 0.0;
+        for k in offsets[row]..offsets[row + 1] {
+            sum += values[k] * x[columns[k]];
+        }
+        *y = sum;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra_sparse::CooMatrix;
+
+    /// Laplacian of a `side` by `side` grid whose station 0 is also tied to every other one.
+    fn star_plus_grid(side: usize) -> CsrMatrix<f64> {
+        let n = side * side;
+        let mut coo = CooMatrix::new(n, n);
+        let mut tie = |i: usize, j: usize, w: f64| {
+            coo.push(i, j, -w);
+            coo.push(j, i, -w);
+            coo.push(i, i, w);
+            coo.push(j, j, w);
+        };
+        for i in 0..n {
+            if i % side + 1 < side {
+                tie(i, i + 1, 1.0 + (i % 5) as f64 / 7.0);
+            }
+            if i + side < n {
+                tie(i, i + side, 1.0 + (i % 3) as f64 / 11.0);
+            }
+            if i > 0 {
+                tie(0, i, 1.0 / (1.0 + i as f64));
+            }
+        }
+        CsrMatrix::from(&coo)
+    }
+
+    #[test]
+    fn products_are_the_same_bit_for_bit_on_any_number_of_threads() {
+        let a = star_plus_grid(60);
+        assert!(a.row(0).nnz() > a.nnz() / 8);
+        let x: Vec<f64> = (0..a.nrows()).map(|i| (i as f64 * 0.37).sin()).collect();
+
+        // Each row summed in order, as the product did on one thread.
+        let expected: Vec<u64> = (0..a.nrows())
+            .map(|row| {
+                let mut sum = 0.0;
+                for (&column, value) in a.row(row).col_indices().iter().zip(a.row(row).values()) {
+                    sum += value * x[column];
+                }
+                sum.to_bits()
+            })
+            .collect();
+        for split in [RowSplit::Rows, RowSplit::Nonzeros] {
+            for threads in [1, 2, 3, 7, 64] {
+                let spmv = Spmv::with_threads(&a, split, threads);
+                assert!(spmv.workers.len() < threads);
+                let mut y = vec![f64::NAN; a.nrows()];
+                // The workers take the vectors of every product.
+                for _ in 0..3 {
+                    y.fill(f64::NAN);
+                    spmv.apply(&x, &mut y);
+                    let bits: Vec<u64> = y.iter().map(|y| y.to_bits()).collect();
+                    assert!(bits == expected, "{split:?} on {threads} threads");
+                }
+            }
+        }
+
+        // The hub row is a run of its own, the other rows share the remaining threads.
+        let spmv = Spmv::with_threads(&a, RowSplit::Nonzeros, 8);
+        assert_eq!(spmv.bounds[..2], [0, 1]);
+        let by_rows = Spmv::with_threads(&a, RowSplit::Rows, 4);
+        assert_eq!(by_rows.bounds, [0, 900, 1800, 2700, 3600]);
+    }
+}
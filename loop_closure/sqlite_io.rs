@@ -0,0 +1,482 @@
+//! SQLite persistence of survey graphs and adjustment runs, so that several tools can share
+//! one survey database and its adjustment history.
+//!
+//! Schema (version [`SQLITE_SCHEMA_VERSION`], tracked in `PRAGMA user_version`):
+//!
+//! * `stations(project_id, station_index, name, x, y, z)`: one row per vertex. Indices of a
+//!   project are dense (`0..n`) and are the vertex indices of the [`Graph`]; `x`/`y` are the
+//!   initial guesses.
+//! * `shots(project_id, shot_index, from_station, to_station, dx, dy, dz, weight)`: one row
+//!   per edge, with dense indices and endpoints given as station indices.
+//! * `fixed_points(project_id, station_index, x, y)`: fixed stations and their coordinates,
+//!   which take precedence over the `stations` ones.
+//! * `adjustments(run_id, project_id, created_at, free_vertices, passive_vertices,
+//!   graph_hash)`: one row per adjustment run, `graph_hash` being [`Graph::content_hash`] once
+//!   the run is written.
+//! * `adjusted_stations(run_id, station_index, x, y)`: adjusted coordinates of a run.
+//! * `residuals(run_id, shot_index, dx, dy, standardized)`: per-shot residuals of a run.
+//!
+//! Database errors are reported as [`COMPASS_ERR_IO`], inconsistent contents as
+//! [`COMPASS_ERR_INVALID_ARGUMENT`].
+
+use crate::{COMPASS_ERR_INVALID_ARGUMENT, COMPASS_ERR_IO, Graph, Solution, SolveError};
+use rusqlite::{Connection, params};
+
+/// Schema version created by [`migrate_sqlite`].
+pub const SQLITE_SCHEMA_VERSION: i64 = 1;
+
+/// Migration scripts: entry `i` upgrades a database from version `i` to `i + 1`.
+const MIGRATIONS: [&str; SQLITE_SCHEMA_VERSION as usize] = ["
+    CREATE TABLE stations (
+        project_id    INTEGER NOT NULL,
+        station_index INTEGER NOT NULL,
+        name          TEXT,
+        x             REAL NOT NULL,
+        y             REAL NOT NULL,
+        z             REAL NOT NULL DEFAULT 0,
+        PRIMARY KEY (project_id, station_index)
+    );
+    CREATE TABLE shots (
+        project_id   INTEGER NOT NULL,
+        shot_index   INTEGER NOT NULL,
+        from_station INTEGER NOT NULL,
+        to_station   INTEGER NOT NULL,
+        dx           REAL NOT NULL,
+        dy           REAL NOT NULL,
+        dz           REAL NOT NULL DEFAULT 0,
+        weight       REAL NOT NULL,
+        PRIMARY KEY (project_id, shot_index)
+    );
+    CREATE TABLE fixed_points (
+        project_id    INTEGER NOT NULL,
+        station_index INTEGER NOT NULL,
+        x             REAL NOT NULL,
+        y             REAL NOT NULL,
+        PRIMARY KEY (project_id, station_index)
+    );
+    CREATE TABLE adjustments (
+        run_id           INTEGER PRIMARY KEY,
+        project_id       INTEGER NOT NULL,
+        created_at       TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+        free_vertices    INTEGER,
+        passive_vertices INTEGER,
+        graph_hash       INTEGER
+    );
+    CREATE TABLE adjusted_stations (
+        run_id        INTEGER NOT NULL REFERENCES adjustments (run_id),
+        station_index INTEGER NOT NULL,
+        x             REAL NOT NULL,
+        y             REAL NOT NULL,
+        PRIMARY KEY (run_id, station_index)
+    );
+    CREATE TABLE residuals (
+        run_id       INTEGER NOT NULL REFERENCES adjustments (run_id),
+        shot_index   INTEGER NOT NULL,
+        dx           REAL NOT NULL,
+        dy           REAL NOT NULL,
+        standardized REAL NOT NULL,
+        PRIMARY KEY (run_id, shot_index)
+    );
+"];
+
+/// Creates the schema in an empty database, or upgrades an older one to
+/// [`SQLITE_SCHEMA_VERSION`]. Databases created by a newer library are rejected.
+pub fn migrate_sqlite(conn: &mut Connection) -> Result<(), SolveError> {
+    let version: i64 = conn
+        .pragma_query_value(None, "user_version", |row| row.get(0))
+        .map_err(db_error)?;
+    if version > SQLITE_SCHEMA_VERSION {
+        return Err(invalid(format!(
+            "unsupported schema version {version} (this library reads up to {SQLITE_SCHEMA_VERSION})"
+        )));
+    }
+    let tx = conn.transaction().map_err(db_error)?;
+    for (from, script) in MIGRATIONS.iter().enumerate().skip(version.max(0) as usize) {
+        tx.execute_batch(script).map_err(db_error)?;
+        tx.pragma_update(None, "user_version", from as i64 + 1)
+            .map_err(db_error)?;
+    }
+    tx.commit().map_err(db_error)
+}
+
+/// Registers a new adjustment run of `project_id` and returns its id, to be filled by
+/// [`Solution::write_sqlite`].
+pub fn create_adjustment_run(conn: &Connection, project_id: i64) -> Result<i64, SolveError> {
+    conn.execute(
+        "INSERT INTO adjustments (project_id) VALUES (?1)",
+        params![project_id],
+    )
+    .map_err(db_error)?;
+    Ok(conn.last_insert_rowid())
+}
+
+impl Graph {
+    /// Builds the solver input of `project_id`. Station and shot indices must be dense.
+    pub fn from_sqlite(conn: &Connection, project_id: i64) -> Result<Graph, SolveError> {
+        let mut graph = Graph::default();
+        let mut names = Vec::new();
+
+        let mut stations = conn
+            .prepare(
+                "SELECT station_index, name, x, y, z FROM stations
+                 WHERE project_id = ?1 ORDER BY station_index",
+            )
+            .map_err(db_error)?;
+        let rows = stations
+            .query_map(params![project_id], |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, Option<String>>(1)?,
+                    row.get::<_, f64>(2)?,
+                    row.get::<_, f64>(3)?,
+                    row.get::<_, f64>(4)?,
+                ))
+            })
+            .map_err(db_error)?;
+        for row in rows {
+            let (index, name, x, y, z) = row.map_err(db_error)?;
+            if index != graph.num_vertices() as i64 {
+                return Err(invalid(format!(
+                    "station indices of project {project_id} are not dense at {index}"
+                )));
+            }
+            graph.add_vertex(x, y, z, false);
+            names.push(name);
+        }
+        if names.iter().any(Option::is_some) {
+            graph.names = Some(names.into_iter().map(Option::unwrap_or_default).collect());
+        }
+
+        let n = graph.num_vertices() as i64;
+        let mut fixed = conn
+            .prepare("SELECT station_index, x, y FROM fixed_points WHERE project_id = ?1")
+            .map_err(db_error)?;
+        let rows = fixed
+            .query_map(params![project_id], |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, f64>(1)?,
+                    row.get::<_, f64>(2)?,
+                ))
+            })
+            .map_err(db_error)?;
+        for row in rows {
+            let (index, x, y) = row.map_err(db_error)?;
+            if !(0..n).contains(&index) {
+                return Err(invalid(format!(
+                    "fixed point references missing station {index}"
+                )));
+            }
+            let i = index as usize;
+            graph.fixed[i] = true;
+            graph.x[i] = x;
+            graph.y[i] = y;
+        }
+
+        let mut shots = conn
+            .prepare(
+                "SELECT shot_index, from_station, to_station, dx, dy, dz, weight FROM shots
+                 WHERE project_id = ?1 ORDER BY shot_index",
+            )
+            .map_err(db_error)?;
+        let rows = shots
+            .query_map(params![project_id], |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, i64>(1)?,
+                    row.get::<_, i64>(2)?,
+                    row.get::<_, f64>(3)?,
+                    row.get::<_, f64>(4)?,
+                    row.get::<_, f64>(5)?,
+                    row.get::<_, f64>(6)?,
+                ))
+            })
+            .map_err(db_error)?;
+        for row in rows {
+            let (index, from, to, dx, dy, dz, weight) = row.map_err(db_error)?;
+            if index != graph.num_edges() as i64 {
+                return Err(invalid(format!(
+                    "shot indices of project {project_id} are not dense at {index}"
+                )));
+            }
+            if !(0..n).contains(&from) || !(0..n).contains(&to) {
+                return Err(invalid(format!(
+                    "shot {index} references a missing station"
+                )));
+            }
+            graph.add_edge(from as usize, to as usize, dx, dy, dz, weight);
+        }
+        Ok(graph)
+    }
+
+    /// Replaces the stations, shots and fixed points of `project_id` with this graph, in a
+    /// single transaction. Adjustment runs of the project are kept.
+    pub fn write_sqlite(&self, conn: &mut Connection, project_id: i64) -> Result<(), SolveError> {
+        let tx = conn.transaction().map_err(db_error)?;
+        for table in ["stations", "shots", "fixed_points"] {
+            tx.execute(
+                &format!("DELETE FROM {table} WHERE project_id = ?1"),
+                params![project_id],
+            )
+            .map_err(db_error)?;
+        }
+        self.insert_sqlite(&tx, project_id)?;
+        tx.commit().map_err(db_error)
+    }
+
+    /// Inserts the rows of [`Graph::write_sqlite`].
+    fn insert_sqlite(&self, conn: &Connection, project_id: i64) -> Result<(), SolveError> {
+        let mut station = conn
+            .prepare(
+                "INSERT INTO stations (project_id, station_index, name, x, y, z)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            )
+            .map_err(db_error)?;
+        let mut fixed = conn
+            .prepare(
+                "INSERT INTO fixed_points (project_id, station_index, x, y)
+                 VALUES (?1, ?2, ?3, ?4)",
+            )
+            .map_err(db_error)?;
+        for i in 0..self.num_vertices() {
+            let name = self.names.as_ref().map(|names| names[i].as_str());
+            station
+                .execute(params![
+                    project_id, i as i64, name, self.x[i], self.y[i], self.z[i]
+                ])
+                .map_err(db_error)?;
+            if self.fixed[i] {
+                fixed
+                    .execute(params![project_id, i as i64, self.x[i], self.y[i]])
+                    .map_err(db_error)?;
+            }
+        }
+
+        let mut shot = conn
+            .prepare(
+                "INSERT INTO shots (project_id, shot_index, from_station, to_station, dx, dy, dz, weight)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            )
+            .map_err(db_error)?;
+        for e in 0..self.num_edges() {
+            shot.execute(params![
+                project_id,
+                e as i64,
+                self.from[e] as i64,
+                self.to[e] as i64,
+                self.dx[e],
+                self.dy[e],
+                self.dz[e],
+                self.weight[e]
+            ])
+            .map_err(db_error)?;
+        }
+        Ok(())
+    }
+}
+
+impl Solution {
+    /// Stores this solution of `graph` as adjustment run `run_id`, created beforehand with
+    /// [`create_adjustment_run`]: the stats row, the adjusted coordinates and the per-shot
+    /// residuals, in a single transaction. Writing the same run again replaces its results.
+    pub fn write_sqlite(
+        &self,
+        graph: &Graph,
+        conn: &mut Connection,
+        run_id: i64,
+    ) -> Result<(), SolveError> {
+        let tx = conn.transaction().map_err(db_error)?;
+        let updated = tx
+            .execute(
+                "UPDATE adjustments SET free_vertices = ?2, passive_vertices = ?3, graph_hash = ?4
+                 WHERE run_id = ?1",
+                params![
+                    run_id,
+                    self.stats.free_vertices,
+                    self.stats.passive_vertices,
+                    // SQLite integers are signed; the hash is stored bit for bit.
+                    graph.content_hash() as i64
+                ],
+            )
+            .map_err(db_error)?;
+        if updated == 0 {
+            return Err(invalid(format!("adjustment run {run_id} does not exist")));
+        }
+        for table in ["adjusted_stations", "residuals"] {
+            tx.execute(
+                &format!("DELETE FROM {table} WHERE run_id = ?1"),
+                params![run_id],
+            )
+            .map_err(db_error)?;
+        }
+        self.insert_sqlite(graph, &tx, run_id)?;
+        tx.commit().map_err(db_error)
+    }
+
+    /// Inserts the rows of [`Solution::write_sqlite`] other than the stats.
+    fn insert_sqlite(
+        &self,
+        graph: &Graph,
+        conn: &Connection,
+        run_id: i64,
+    ) -> Result<(), SolveError> {
+        let mut station = conn
+            .prepare(
+                "INSERT INTO adjusted_stations (run_id, station_index, x, y)
+                 VALUES (?1, ?2, ?3, ?4)",
+            )
+            .map_err(db_error)?;
+        for (i, (x, y)) in self.x.iter().zip(&self.y).enumerate() {
+            station
+                .execute(params![run_id, i as i64, x, y])
+                .map_err(db_error)?;
+        }
+
+        let mut residual = conn
+            .prepare(
+                "INSERT INTO residuals (run_id, shot_index, dx, dy, standardized)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+            )
+            .map_err(db_error)?;
+        let standardized = self.standardized_residuals(graph);
+        for (e, (dx, dy)) in self.residuals(graph).into_iter().enumerate() {
+            residual
+                .execute(params![run_id, e as i64, dx, dy, standardized[e]])
+                .map_err(db_error)?;
+        }
+        Ok(())
+    }
+}
+
+fn db_error(err: rusqlite::Error) -> SolveError {
+    SolveError {
+        code: COMPASS_ERR_IO,
+        message: format!("sqlite: {err}"),
+    }
+}
+
+fn invalid(message: String) -> SolveError {
+    SolveError {
+        code: COMPASS_ERR_INVALID_ARGUMENT,
+        message,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn database() -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate_sqlite(&mut conn).unwrap();
+        conn
+    }
+
+    fn network() -> Graph {
+        let mut graph = Graph::default();
+        graph.add_vertex(0.0, 0.0, 5.0, true);
+        graph.add_vertex(9.8, 0.3, 0.0, false);
+        graph.add_vertex(10.0, 10.0, 0.0, false);
+        graph.add_edge(0, 1, 10.0, 0.0, 0.0, 1.0);
+        graph.add_edge(1, 2, 0.0, 10.0, 1.0, 1.0);
+        graph.add_edge(2, 0, -10.03, -9.98, 0.0, 2.0);
+        graph.names = Some(vec!["A1".into(), "A2".into(), "A3".into()]);
+        graph
+    }
+
+    #[test]
+    fn graphs_round_trip_per_project() {
+        let mut conn = database();
+        let graph = network();
+        graph.write_sqlite(&mut conn, 7).unwrap();
+        // Rewriting replaces the rows instead of duplicating them.
+        graph.write_sqlite(&mut conn, 7).unwrap();
+
+        let read = Graph::from_sqlite(&conn, 7).unwrap();
+        assert_eq!((read.x, read.y, read.z), (graph.x, graph.y, graph.z));
+        assert_eq!(read.fixed, graph.fixed);
+        assert_eq!((read.from, read.to), (graph.from, graph.to));
+        assert_eq!((read.dx, read.dy, read.dz), (graph.dx, graph.dy, graph.dz));
+        assert_eq!(read.weight, graph.weight);
+        assert_eq!(read.names, graph.names);
+
+        let empty = Graph::from_sqlite(&conn, 8).unwrap();
+        assert_eq!((empty.num_vertices(), empty.num_edges()), (0, 0));
+    }
+
+    #[test]
+    fn stores_adjustment_runs() {
+        let mut conn = database();
+        let graph = network();
+        graph.write_sqlite(&mut conn, 1).unwrap();
+        let solution = graph.solve(1000, 1e-12).unwrap();
+        let run = create_adjustment_run(&conn, 1).unwrap();
+        solution.write_sqlite(&graph, &mut conn, run).unwrap();
+        solution.write_sqlite(&graph, &mut conn, run).unwrap();
+
+        let (free, hash): (i32, i64) = conn
+            .query_row(
+                "SELECT free_vertices, graph_hash FROM adjustments WHERE run_id = ?1",
+                params![run],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(free, solution.stats.free_vertices);
+        assert_eq!(hash as u64, graph.content_hash());
+
+        let x: Vec<f64> = conn
+            .prepare("SELECT x FROM adjusted_stations WHERE run_id = ?1 ORDER BY station_index")
+            .unwrap()
+            .query_map(params![run], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(x, solution.x);
+
+        let standardized: Vec<f64> = conn
+            .prepare("SELECT standardized FROM residuals WHERE run_id = ?1 ORDER BY shot_index")
+            .unwrap()
+            .query_map(params![run], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(standardized, solution.standardized_residuals(&graph));
+
+        let err = solution
+            .write_sqlite(&graph, &mut conn, run + 1)
+            .unwrap_err();
+        assert_eq!(err.code, COMPASS_ERR_INVALID_ARGUMENT);
+    }
+
+    #[test]
+    fn migration_is_idempotent_and_rejects_newer_schemas() {
+        let mut conn = database();
+        migrate_sqlite(&mut conn).unwrap();
+        conn.pragma_update(None, "user_version", SQLITE_SCHEMA_VERSION + 1)
+            .unwrap();
+        let err = migrate_sqlite(&mut conn).unwrap_err();
+        assert_eq!(err.code, COMPASS_ERR_INVALID_ARGUMENT);
+        assert!(err.message.starts_with("unsupported schema version 2"));
+    }
+
+    #[test]
+    fn rejects_inconsistent_projects() {
+        let mut conn = database();
+        network().write_sqlite(&mut conn, 1).unwrap();
+        conn.execute("DELETE FROM stations WHERE station_index = 1", [])
+            .unwrap();
+        let err = Graph::from_sqlite(&conn, 1).err().unwrap();
+        assert_eq!(
+            (err.code, err.message.as_str()),
+            (
+                COMPASS_ERR_INVALID_ARGUMENT,
+                "station indices of project 1 are not dense at 2"
+            )
+        );
+
+        let err = Graph::from_sqlite(&Connection::open_in_memory().unwrap(), 1)
+            .err()
+            .unwrap();
+        assert_eq!(err.code, COMPASS_ERR_IO);
+    }
+}
@@ -0,0 +1,271 @@
+//! Export of an adjusted network to the Survex `.3d` (version 8) binary format, readable by
+//! Aven and `dump3d`.
+//!
+//! Only the subset of the format needed to render a centreline is written: the header with
+//! the title and coordinate system, one `MOVE` + `LINE` pair per edge and one `LABEL` per
+//! station. Coordinates are stored as little-endian `i32` centimetres, converted from the
+//! unit of the solution's coordinate system.
+
+use crate::crs::{LengthUnit, convert_length};
+use crate::{Graph, Solution, SolveError, check_export, invalid_input};
+use std::path::Path;
+
+/// `MOVE` item code, followed by three coordinates.
+const ITEM_MOVE: u8 = 0x0f;
+/// `LINE` item code (flags in the low bits), followed by a label change and three coordinates.
+const ITEM_LINE: u8 = 0x40;
+/// `LABEL` item code (flags in the low bits), followed by a label change and three coordinates.
+const ITEM_LABEL: u8 = 0x80;
+/// Station flag: underground station.
+const LABEL_UNDERGROUND: u8 = 0x02;
+/// Station flag: fixed point.
+const LABEL_FIXED: u8 = 0x10;
+
+/// Options for [`Solution::to_survex_3d`].
+#[derive(Debug, Clone, Default)]
+pub struct Survex3dOptions {
+    /// Survey title stored in the header.
+    pub title: String,
+    /// Coordinate system string (e.g. `EPSG:32613`), stored in the header when set. Defaults
+    /// to that of the solution's coordinate system, see
+    /// [`crate::crs::CoordinateSystem::survex_cs`].
+    pub coordinate_system: Option<String>,
+    /// Seconds since the Unix epoch stored as the file timestamp. Kept explicit so that
+    /// the output is reproducible.
+    pub timestamp: u64,
+}
+
+impl Solution {
+    /// Writes the adjusted network as a Survex `.3d` file.
+    pub fn write_survex_3d(
+        &self,
+        graph: &Graph,
+        path: impl AsRef<Path>,
+        options: &Survex3dOptions,
+    ) -> std::io::Result<()> {
+        let bytes = self.to_survex_3d(graph, options).map_err(invalid_input)?;
+        std::fs::write(path, bytes)
+    }
+
+    /// Encodes the adjusted network (adjusted X/Y, graph Z) as Survex `.3d` v8 bytes.
+    ///
+    /// Station labels come from [`Graph::names`] when available, `V<index>` otherwise.
+    ///
+    /// Returns [`crate::COMPASS_ERR_INVALID_ARGUMENT`] when the solution or an array of
+    /// `graph` does not have one value per vertex or edge, or an edge has an endpoint out of
+    /// range.
+    pub fn to_survex_3d(
+        &self,
+        graph: &Graph,
+        options: &Survex3dOptions,
+    ) -> Result<Vec<u8>, SolveError> {
+        check_export(self, graph)?;
+        let mut out = Vec::new();
+        out.extend_from_slice(b"Survex 3D Image File\nv8\n");
+        out.extend_from_slice(options.title.as_bytes());
+        let cs = options
+            .coordinate_system
+            .clone()
+            .or_else(|| self.coordinate_system.and_then(|system| system.survex_cs()));
+        if let Some(cs) = &cs {
+            out.push(0);
+            out.extend_from_slice(cs.as_bytes());
+        }
+        out.push(b'\n');
+        out.extend_from_slice(format!("@{}\n", options.timestamp).as_bytes());
+        // File-wide flags: plain plan data, no extended elevation.
+        out.push(0);
+
+        let mut writer = ItemWriter {
+            out,
+            label: Vec::new(),
+        };
+        let units = self
+            .coordinate_system
+            .map_or(LengthUnit::Meters, |cs| cs.units);
+        let point = |v: usize| {
+            [self.x[v], self.y[v], graph.z[v]].map(|c| convert_length(c, units, LengthUnit::Meters))
+        };
+
+        let mut pen = None;
+        for e in 0..graph.num_edges() {
+            let (u, v) = (graph.from[e], graph.to[e]);
+            if pen != Some(u) {
+                writer.out.push(ITEM_MOVE);
+                writer.coordinates(point(u));
+            }
+            writer.out.push(ITEM_LINE);
+            writer.label_change(b"");
+            writer.coordinates(point(v));
+            pen = Some(v);
+        }
+
+        for v in 0..graph.num_vertices() {
+            let name = match &graph.names {
+                Some(names) => names[v].clone(),
+                None => format!("V{v}"),
+            };
+            let mut flags = LABEL_UNDERGROUND;
+            if graph.fixed[v] {
+                flags |= LABEL_FIXED;
+            }
+            writer.out.push(ITEM_LABEL | flags);
+            writer.label_change(name.as_bytes());
+            writer.coordinates(point(v));
+        }
+        Ok(writer.out)
+    }
+}
+
+/// Item encoder tracking the current label, which v8 stores as a delta against the
+/// previous one.
+struct ItemWriter {
+    out: Vec<u8>,
+    label: Vec<u8>,
+}
+
+impl ItemWriter {
+    fn coordinates(&mut self, point: [f64; 3]) {
+        for value in point {
+            let cm = (value * 100.0).round() as i32;
+            self.out.extend_from_slice(&cm.to_le_bytes());
+        }
+    }
+
+    /// Writes the change from the current label to `label`: the number of trailing bytes
+    /// to delete, the number to add, then the added bytes.
+    fn label_change(&mut self, label: &[u8]) {
+        let common = self
+            .label
+            .iter()
+            .zip(label)
+            .take_while(|(a, b)| a == b)
+            .count();
+        let delete = self.label.len() - common;
+        let add = label.len() - common;
+        if (delete != 0 || add != 0) && delete < 15 && add < 15 {
+            self.out.push(((delete as u8) << 4) | add as u8);
+        } else {
+            self.out.push(0);
+            self.number(delete);
+            self.number(add);
+        }
+        self.out.extend_from_slice(&label[common..]);
+        self.label = label.to_vec();
+    }
+
+    /// Small counts take one byte; larger ones an escape byte and a little-endian `u32`.
+    fn number(&mut self, n: usize) {
+        if n < 0xff {
+            self.out.push(n as u8);
+        } else {
+            self.out.push(0xff);
+            self.out.extend_from_slice(&(n as u32).to_le_bytes());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Fixed station `A` and free station `B` closed exactly by one shot.
+    fn two_stations() -> (Graph, Solution) {
+        let mut graph = Graph::default();
+        graph.add_vertex(0.0, 0.0, 0.0, true);
+        graph.add_vertex(0.0, 0.0, 0.1, false);
+        graph.add_edge(0, 1, 1.5, -2.25, 0.1, 1.0);
+        graph.names = Some(vec!["A".to_string(), "B".to_string()]);
+        let solution = graph.solve(100, 1e-12).unwrap();
+        (graph, solution)
+    }
+
+    fn options() -> Survex3dOptions {
+        Survex3dOptions {
+            title: "Test cave".to_string(),
+            coordinate_system: Some("EPSG:32613".to_string()),
+            timestamp: 1_700_000_000,
+        }
+    }
+
+    fn centimetres(point: [i32; 3]) -> Vec<u8> {
+        point.iter().flat_map(|c| c.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn layout_matches_the_v8_format() {
+        let (graph, solution) = two_stations();
+        let mut expected =
+            b"Survex 3D Image File\nv8\nTest cave\0EPSG:32613\n@1700000000\n\0".to_vec();
+        // MOVE to A, then LINE to B with an unchanged (empty) label.
+        expected.push(0x0f);
+        expected.extend(centimetres([0, 0, 0]));
+        expected.extend([0x40, 0x00, 0x00, 0x00]);
+        expected.extend(centimetres([150, -225, 10]));
+        // LABEL A, underground and fixed: nothing to delete, one byte to add.
+        expected.extend([0x92, 0x01, b'A']);
+        expected.extend(centimetres([0, 0, 0]));
+        // LABEL B, underground: delete one byte, add one.
+        expected.extend([0x82, 0x11, b'B']);
+        expected.extend(centimetres([150, -225, 10]));
+
+        assert_eq!(solution.to_survex_3d(&graph, &options()).unwrap(), expected);
+    }
+
+    #[test]
+    fn rejects_a_solution_of_another_graph() {
+        let (mut graph, solution) = two_stations();
+        graph.add_vertex(0.0, 0.0, 0.0, false);
+        let err = solution.to_survex_3d(&graph, &options()).unwrap_err();
+        assert_eq!(err.code, crate::COMPASS_ERR_INVALID_ARGUMENT);
+        assert_eq!(err.message, "solution has 2 / 2 coordinates for 3 vertices");
+        let dir = tempfile::tempdir().unwrap();
+        let err =
+            (solution.write_survex_3d(&graph, dir.path().join("x.3d"), &options())).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn matches_the_golden_file() {
+        let (graph, solution) = two_stations();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("two_stations.3d");
+        solution.write_survex_3d(&graph, &path, &options()).unwrap();
+        let golden = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/two_stations.3d");
+        assert_eq!(std::fs::read(path).unwrap(), std::fs::read(golden).unwrap());
+    }
+
+    #[test]
+    fn encodes_long_label_changes_with_escaped_counts() {
+        let mut writer = ItemWriter {
+            out: Vec::new(),
+            label: Vec::new(),
+        };
+        let long = vec![b'x'; 300];
+        writer.label_change(&long);
+        writer.label_change(b"xy");
+        let mut expected = vec![0x00, 0x00, 0xff];
+        expected.extend(300u32.to_le_bytes());
+        expected.extend(long);
+        // 299 bytes deleted after the common `x`, `y` added.
+        expected.extend([0x00, 0xff]);
+        expected.extend(299u32.to_le_bytes());
+        expected.extend([0x01, b'y']);
+        assert_eq!(writer.out, expected);
+    }
+
+    #[test]
+    fn dump3d_reads_the_file_when_survex_is_installed() {
+        let (graph, solution) = two_stations();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("two_stations.3d");
+        solution.write_survex_3d(&graph, &path, &options()).unwrap();
+        let Ok(output) = std::process::Command::new("dump3d").arg(&path).output() else {
+            return;
+        };
+        let text = String::from_utf8_lossy(&output.stdout);
+        assert!(output.status.success(), "{text}");
+        assert!(text.contains("Test cave"), "{text}");
+        assert!(text.contains("[A]") && text.contains("[B]"), "{text}");
+    }
+}
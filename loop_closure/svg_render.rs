@@ -0,0 +1,347 @@
+//! SVG rendering of the network before and after adjustment, for visual checks without a
+//! GIS.
+//!
+//! Layers are `<g>` groups with stable ids, drawn bottom to top: `original` (input
+//! coordinates, gray), `adjusted` (black), `suspect` (edges whose standardized residual
+//! exceeds the threshold, red), `ellipses` and `fixed` (anchors as triangles). Each edge is
+//! one `<line>` in each layer it belongs to.
+
+pub use crate::ErrorEllipse;
+use crate::{Graph, Solution};
+use std::fmt::Write as _;
+use std::path::Path;
+
+/// Options for [`Solution::render_svg`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderOptions {
+    /// Canvas size in pixels.
+    pub width: f64,
+    pub height: f64,
+    /// Blank border kept around the drawing, in pixels.
+    pub margin: f64,
+    /// Pixels per world unit. `None` fits the bounding box of the drawn coordinates to the
+    /// canvas; otherwise the drawing is centered on it.
+    pub scale: Option<f64>,
+    /// Whether to draw the pre-adjustment network.
+    pub show_original: bool,
+    /// Whether to draw the adjusted network.
+    pub show_adjusted: bool,
+    /// Whether to highlight the suspect edges.
+    pub show_suspect: bool,
+    /// Whether to mark the fixed vertices.
+    pub show_fixed: bool,
+    /// Standardized residual above which an edge is suspect.
+    pub suspect_threshold: f64,
+    /// Error ellipses drawn at the adjusted vertices, empty for none.
+    pub ellipses: Vec<ErrorEllipse>,
+    /// Exaggeration applied to the ellipse axes, which are usually small at map scale.
+    pub ellipse_scale: f64,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        RenderOptions {
+            width: 800.0,
+            height: 600.0,
+            margin: 20.0,
+            scale: None,
+            show_original: true,
+            show_adjusted: true,
+            show_suspect: true,
+            show_fixed: true,
+            suspect_threshold: 3.0,
+            ellipses: Vec::new(),
+            ellipse_scale: 1.0,
+        }
+    }
+}
+
+impl Solution {
+    /// Renders the network as a standalone SVG document.
+    pub fn render_svg(&self, graph: &Graph, options: &RenderOptions) -> String {
+        let original = (graph.x.as_slice(), graph.y.as_slice());
+        let adjusted = (self.x.as_slice(), self.y.as_slice());
+
+        // Bounding box of every coordinate that is drawn.
+        let mut drawn = vec![adjusted];
+        if options.show_original {
+            drawn.push(original);
+        }
+        let (mut min_x, mut max_x) = (f64::INFINITY, f64::NEG_INFINITY);
+        let (mut min_y, mut max_y) = (f64::INFINITY, f64::NEG_INFINITY);
+        for (xs, ys) in &drawn {
+            for (&x, &y) in xs.iter().zip(ys.iter()) {
+                min_x = min_x.min(x);
+                max_x = max_x.max(x);
+                min_y = min_y.min(y);
+                max_y = max_y.max(y);
+            }
+        }
+        if min_x > max_x {
+            (min_x, max_x, min_y, max_y) = (0.0, 0.0, 0.0, 0.0);
+        }
+        let inner_width = (options.width - 2.0 * options.margin).max(0.0);
+        let inner_height = (options.height - 2.0 * options.margin).max(0.0);
+        let scale = options.scale.unwrap_or_else(|| {
+            let sx = inner_width / (max_x - min_x);
+            let sy = inner_height / (max_y - min_y);
+            let fit = sx.min(sy);
+            if fit.is_finite() && fit > 0.0 {
+                fit
+            } else {
+                1.0
+            }
+        });
+        // World point drawn at the center of the canvas; SVG Y grows downwards.
+        let (center_x, center_y) = ((min_x + max_x) / 2.0, (min_y + max_y) / 2.0);
+        let point = |x: f64, y: f64| {
+            (
+                options.width / 2.0 + (x - center_x) * scale,
+                options.height / 2.0 - (y - center_y) * scale,
+            )
+        };
+
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" \
+             viewBox=\"0 0 {} {}\">",
+            options.width, options.height, options.width, options.height
+        );
+        let edges = |out: &mut String, id: &str, color: &str, (xs, ys): (&[f64], &[f64])| {
+            edge_layer(
+                out,
+                id,
+                color,
+                graph,
+                (0..graph.num_edges()).collect(),
+                |v| point(xs[v], ys[v]),
+            )
+        };
+        if options.show_original {
+            edges(&mut out, "original", "gray", original);
+        }
+        if options.show_adjusted {
+            edges(&mut out, "adjusted", "black", adjusted);
+        }
+        if options.show_suspect {
+            let suspect = self
+                .standardized_residuals(graph)
+                .iter()
+                .enumerate()
+                .filter(|&(_, &s)| s > options.suspect_threshold)
+                .map(|(e, _)| e)
+                .collect();
+            edge_layer(&mut out, "suspect", "red", graph, suspect, |v| {
+                point(self.x[v], self.y[v])
+            });
+        }
+        if !options.ellipses.is_empty() {
+            let _ = writeln!(
+                out,
+                "<g id=\"ellipses\" fill=\"none\" stroke=\"blue\" stroke-width=\"1\">"
+            );
+            for ellipse in &options.ellipses {
+                let (cx, cy) = point(self.x[ellipse.vertex], self.y[ellipse.vertex]);
+                let factor = scale * options.ellipse_scale;
+                // The major axis lies along SVG X before rotation; azimuths turn clockwise
+                // from north, i.e. from -90 degrees in SVG.
+                let _ = writeln!(
+                    out,
+                    "<ellipse cx=\"{cx:.2}\" cy=\"{cy:.2}\" rx=\"{:.2}\" ry=\"{:.2}\" \
+                     transform=\"rotate({:.2} {cx:.2} {cy:.2})\"/>",
+                    ellipse.semi_major * factor,
+                    ellipse.semi_minor * factor,
+                    ellipse.azimuth_deg - 90.0
+                );
+            }
+            let _ = writeln!(out, "</g>");
+        }
+        if options.show_fixed {
+            let _ = writeln!(out, "<g id=\"fixed\" fill=\"black\">");
+            for v in (0..graph.num_vertices()).filter(|&v| graph.fixed[v]) {
+                let (px, py) = point(self.x[v], self.y[v]);
+                let _ = writeln!(
+                    out,
+                    "<polygon points=\"{:.2},{:.2} {:.2},{:.2} {:.2},{:.2}\"/>",
+                    px,
+                    py - 6.0,
+                    px - 5.0,
+                    py + 4.0,
+                    px + 5.0,
+                    py + 4.0
+                );
+            }
+            let _ = writeln!(out, "</g>");
+        }
+        out.push_str("</svg>\n");
+        out
+    }
+
+    /// Writes [`Solution::render_svg`] to `path`.
+    pub fn write_svg(
+        &self,
+        graph: &Graph,
+        path: impl AsRef<Path>,
+        options: &RenderOptions,
+    ) -> std::io::Result<()> {
+        std::fs::write(path, self.render_svg(graph, options))
+    }
+}
+
+/// Writes a `<g>` layer with one `<line>` per edge in `edges`, vertices placed by `point`.
+fn edge_layer(
+    out: &mut String,
+    id: &str,
+    color: &str,
+    graph: &Graph,
+    edges: Vec<usize>,
+    point: impl Fn(usize) -> (f64, f64),
+) {
+    let _ = writeln!(
+        out,
+        "<g id=\"{id}\" stroke=\"{color}\" stroke-width=\"1\" fill=\"none\">"
+    );
+    for e in edges {
+        let ((x1, y1), (x2, y2)) = (point(graph.from[e]), point(graph.to[e]));
+        let _ = writeln!(
+            out,
+            "<line x1=\"{x1:.2}\" y1=\"{y1:.2}\" x2=\"{x2:.2}\" y2=\"{y2:.2}\"/>"
+        );
+    }
+    let _ = writeln!(out, "</g>");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A braced square around one anchor, with a 2 m blunder on the diagonal 0 -> 2.
+    fn network() -> (Graph, Solution) {
+        let mut graph = Graph::default();
+        for (x, y, fixed) in [(0.0, 0.0, true), (10.0, 0.0, false), (10.0, 10.0, false)] {
+            graph.add_vertex(x, y, 0.0, fixed);
+        }
+        graph.add_vertex(0.0, 10.0, 0.0, false);
+        for (u, v, dx, dy) in [
+            (0, 1, 10.0, 0.01),
+            (1, 2, -0.02, 10.0),
+            (2, 3, -10.0, 0.01),
+            (3, 0, 0.01, -10.0),
+            (0, 2, 12.0, 10.0),
+            (1, 3, -10.0, 10.02),
+        ] {
+            graph.add_edge(u, v, dx, dy, 0.0, 1.0);
+        }
+        let solution = graph.solve(1000, 1e-12).unwrap();
+        (graph, solution)
+    }
+
+    /// Body of the layer with id `id`, if drawn.
+    fn layer<'a>(svg: &'a str, id: &str) -> Option<&'a str> {
+        let start = svg.find(&format!("<g id=\"{id}\""))?;
+        let end = start + svg[start..].find("</g>")?;
+        Some(&svg[start..end])
+    }
+
+    /// Values of attribute `name` in `svg`, in document order.
+    fn attributes(svg: &str, name: &str) -> Vec<f64> {
+        let key = format!(" {name}=\"");
+        svg.match_indices(&key)
+            .map(|(i, _)| {
+                let value = &svg[i + key.len()..];
+                value[..value.find('"').unwrap()].parse().unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn draws_every_layer_with_one_element_per_item() {
+        let (graph, solution) = network();
+        let options = RenderOptions {
+            suspect_threshold: 0.75,
+            ..RenderOptions::default()
+        };
+        let svg = solution.render_svg(&graph, &options);
+        assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"800\""));
+        assert!(svg.ends_with("</svg>\n"));
+        let ids: Vec<&str> = svg
+            .match_indices("<g id=\"")
+            .map(|(i, _)| svg[i + 7..].split('"').next().unwrap())
+            .collect();
+        assert_eq!(ids, ["original", "adjusted", "suspect", "fixed"]);
+        assert_eq!(layer(&svg, "original").unwrap().matches("<line").count(), 6);
+        assert_eq!(layer(&svg, "adjusted").unwrap().matches("<line").count(), 6);
+        assert_eq!(layer(&svg, "fixed").unwrap().matches("<polygon").count(), 1);
+
+        // Only the blundered diagonal stands out.
+        let suspect = solution.standardized_residuals(&graph);
+        let flagged: Vec<usize> = (0..6).filter(|&e| suspect[e] > 0.75).collect();
+        assert_eq!(flagged, [4]);
+        let lines = layer(&svg, "suspect").unwrap().matches("<line").count();
+        assert_eq!(lines, 1);
+    }
+
+    #[test]
+    fn options_select_the_layers() {
+        let (graph, solution) = network();
+        let options = RenderOptions {
+            show_original: false,
+            show_suspect: false,
+            show_fixed: false,
+            ellipses: vec![ErrorEllipse {
+                vertex: 2,
+                semi_major: 0.2,
+                semi_minor: 0.1,
+                azimuth_deg: 45.0,
+            }],
+            ellipse_scale: 10.0,
+            ..RenderOptions::default()
+        };
+        let svg = solution.render_svg(&graph, &options);
+        assert!(layer(&svg, "original").is_none());
+        assert!(layer(&svg, "suspect").is_none());
+        assert!(layer(&svg, "fixed").is_none());
+        let ellipses = layer(&svg, "ellipses").unwrap();
+        assert_eq!(ellipses.matches("<ellipse").count(), 1);
+        assert!(ellipses.contains("rotate(-45.00 "));
+        let (rx, ry) = (attributes(ellipses, "rx")[0], attributes(ellipses, "ry")[0]);
+        assert!((rx / ry - 2.0).abs() < 0.05, "{rx} {ry}");
+    }
+
+    #[test]
+    fn autoscaling_fits_the_drawing_inside_the_margin() {
+        let (graph, solution) = network();
+        let options = RenderOptions::default();
+        let svg = solution.render_svg(&graph, &options);
+        let xs: Vec<f64> = ["x1", "x2"]
+            .iter()
+            .flat_map(|a| attributes(&svg, a))
+            .collect();
+        let ys: Vec<f64> = ["y1", "y2"]
+            .iter()
+            .flat_map(|a| attributes(&svg, a))
+            .collect();
+        let span = |v: &[f64]| {
+            let min = v.iter().copied().fold(f64::INFINITY, f64::min);
+            let max = v.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+            (min, max)
+        };
+        let ((min_x, max_x), (min_y, max_y)) = (span(&xs), span(&ys));
+        // The network is about square, so it fills the height and is centered across.
+        assert!((min_y - options.margin).abs() < 0.02 && (max_y - 580.0).abs() < 0.02);
+        assert!(min_x >= options.margin && max_x <= options.width - options.margin);
+        assert!(((min_x + max_x) / 2.0 - 400.0).abs() < 0.02);
+
+        // A fixed scale keeps the center and sets the pixel size.
+        let fixed = RenderOptions {
+            scale: Some(2.0),
+            show_original: false,
+            ..options
+        };
+        let svg = solution.render_svg(&graph, &fixed);
+        let adjusted = layer(&svg, "adjusted").unwrap();
+        let first = attributes(adjusted, "x2")[0] - attributes(adjusted, "x1")[0];
+        assert!((first - 2.0 * (solution.x[1] - solution.x[0])).abs() < 0.02);
+    }
+}
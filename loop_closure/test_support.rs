@@ -0,0 +1,401 @@
+//! Invariants of the adjustment, for test suites downstream as well as ours.
+//!
+//! Each check compares a [`Solution`] with what the least squares solution must satisfy and
+//! fails with [`COMPASS_ERR_INTERNAL`], naming the offending vertex or constraint row:
+//!
+//! * [`Solution::check_hard_constraints`]: fixed vertices keep their coordinates bit for bit
+//!   and the [`Constraints`] of a constrained solve hold.
+//! * [`Solution::check_orthogonality`]: the residuals are orthogonal to the columns of the
+//!   design matrix, `AᵀW r ≈ 0` on every free vertex, the normal equations of an
+//!   unconstrained solve.
+//! * [`Solution::check_relabeled`]: numbering the vertices differently, see
+//!   [`Graph::relabeled`], moves the adjusted coordinates along with them.
+//! * [`Solution::check_translated`]: translating every vertex, see [`Graph::translated`],
+//!   translates the solution by as much.
+//! * [`Solution::check_identical`]: giving the edges in another order, see
+//!   [`Graph::reordered`], leaves the solution unchanged bit for bit.
+//!
+//! Tolerances are relative for the equations and in coordinate units for the comparison of
+//! two solutions.
+
+use crate::constraints::Constraints;
+use crate::{COMPASS_ERR_INTERNAL, COMPASS_ERR_INVALID_ARGUMENT, Graph, Solution, SolveError};
+
+fn violation(message: String) -> Result<(), SolveError> {
+    Err(SolveError {
+        code: COMPASS_ERR_INTERNAL,
+        message,
+    })
+}
+
+impl Graph {
+    /// The graph with vertex `v` renumbered `permutation[v]`, edges kept in order. Fails with
+    /// [`COMPASS_ERR_INVALID_ARGUMENT`] unless `permutation` is a permutation of the vertices.
+    pub fn relabeled(&self, permutation: &[usize]) -> Result<Graph, SolveError> {
+        let n = self.num_vertices();
+        let mut seen = vec![false; n];
+        let is_permutation = permutation.len() == n
+            && permutation
+                .iter()
+                .all(|&p| p < n && !std::mem::replace(&mut seen[p], true));
+        if !is_permutation {
+            return Err(SolveError {
+                code: COMPASS_ERR_INVALID_ARGUMENT,
+                message: format!("not a permutation of {n} vertices"),
+            });
+        }
+
+        let permute = |values: &[f64]| {
+            let mut out = vec![0.0; n];
+            for (v, &value) in values.iter().enumerate() {
+                out[permutation[v]] = value;
+            }
+            out
+        };
+        let mut fixed = vec![false; n];
+        for v in 0..n {
+            fixed[permutation[v]] = self.fixed[v];
+        }
+        let names = self.names.as_ref().map(|names| {
+            let mut out = vec![String::new(); n];
+            for (v, name) in names.iter().enumerate() {
+                out[permutation[v]] = name.clone();
+            }
+            out
+        });
+        Ok(Graph {
+            x: permute(&self.x),
+            y: permute(&self.y),
+            z: permute(&self.z),
+            fixed,
+            from: self.from.iter().map(|&v| permutation[v]).collect(),
+            to: self.to.iter().map(|&v| permutation[v]).collect(),
+            names,
+            ..self.clone()
+        })
+    }
+
+    /// The graph with edge `e` taken from edge `order[e]`, vertices kept in order. Fails with
+    /// [`COMPASS_ERR_INVALID_ARGUMENT`] unless `order` is a permutation of the edges.
+    pub fn reordered(&self, order: &[usize]) -> Result<Graph, SolveError> {
+        let m = self.num_edges();
+        let mut seen = vec![false; m];
+        let is_permutation = order.len() == m
+            && order
+                .iter()
+                .all(|&e| e < m && !std::mem::replace(&mut seen[e], true));
+        if !is_permutation {
+            return Err(SolveError {
+                code: COMPASS_ERR_INVALID_ARGUMENT,
+                message: format!("not a permutation of {m} edges"),
+            });
+        }
+
+        fn pick<T: Copy>(values: &[T], order: &[usize]) -> Vec<T> {
+            order.iter().map(|&e| values[e]).collect()
+        }
+        Ok(Graph {
+            from: pick(&self.from, order),
+            to: pick(&self.to, order),
+            dx: pick(&self.dx, order),
+            dy: pick(&self.dy, order),
+            dz: pick(&self.dz, order),
+            weight: pick(&self.weight, order),
+            ..self.clone()
+        })
+    }
+
+    /// The graph with every vertex, fixed or not, moved by `(dx, dy)`.
+    pub fn translated(&self, dx: f64, dy: f64) -> Graph {
+        Graph {
+            x: self.x.iter().map(|x| x + dx).collect(),
+            y: self.y.iter().map(|y| y + dy).collect(),
+            ..self.clone()
+        }
+    }
+}
+
+impl Solution {
+    /// Checks that the fixed vertices of `graph` kept their coordinates bit for bit, and that
+    /// each row of `constraints` holds to `tolerance` relative to the size of its terms.
+    /// Pass empty constraints after an unconstrained solve.
+    pub fn check_hard_constraints(
+        &self,
+        graph: &Graph,
+        constraints: &Constraints,
+        tolerance: f64,
+    ) -> Result<(), SolveError> {
+        let n = graph.num_vertices();
+        if self.x.len() != n || self.y.len() != n {
+            return violation(format!(
+                "{} / {} coordinates for {n} vertices",
+                self.x.len(),
+                self.y.len()
+            ));
+        }
+        let moved = (0..n).find(|&v| {
+            graph.fixed[v]
+                && (self.x[v].to_bits() != graph.x[v].to_bits()
+                    || self.y[v].to_bits() != graph.y[v].to_bits())
+        });
+        if let Some(v) = moved {
+            return violation(format!("fixed vertex {v} moved"));
+        }
+
+        let coordinate = |column: usize| match column % 2 {
+            0 => self.x[column / 2],
+            _ => self.y[column / 2],
+        };
+        let mut value = vec![0.0; constraints.values.len()];
+        let mut scale: Vec<f64> = constraints.values.iter().map(|d| d.abs()).collect();
+        for &(row, column, coefficient) in &constraints.terms {
+            let term = coefficient * coordinate(column);
+            value[row] += term;
+            scale[row] += term.abs();
+        }
+        for (row, d) in constraints.values.iter().enumerate() {
+            let error = (value[row] - d).abs();
+            if error.is_nan() || error > tolerance * scale[row].max(1.0) {
+                return violation(format!("constraint {row} off by {error}"));
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks the normal equations `AᵀW r = 0` of an unconstrained solve over all the edges
+    /// of `graph`: their residual at the free vertices must be within `tolerance` of the
+    /// larger of `AᵀW l` and `AᵀW A x`, `l` the observations and `x` the solution.
+    pub fn check_orthogonality(&self, graph: &Graph, tolerance: f64) -> Result<(), SolveError> {
+        let n = graph.num_vertices();
+        // Per vertex, `AᵀW l` and `AᵀW A x` for both axes.
+        let mut observed = vec![(0.0, 0.0); n];
+        let mut adjusted = vec![(0.0, 0.0); n];
+        for e in 0..graph.num_edges() {
+            let (u, v, w) = (graph.from[e], graph.to[e], graph.weight[e]);
+            let (lx, ly) = (w * graph.dx[e], w * graph.dy[e]);
+            let (ax, ay) = (w * (self.x[v] - self.x[u]), w * (self.y[v] - self.y[u]));
+            for (vertex, sign) in [(u, -1.0), (v, 1.0)] {
+                observed[vertex].0 += sign * lx;
+                observed[vertex].1 += sign * ly;
+                adjusted[vertex].0 += sign * ax;
+                adjusted[vertex].1 += sign * ay;
+            }
+        }
+
+        let free = || (0..n).filter(|&v| !graph.fixed[v]);
+        let norm = |values: &[(f64, f64)]| {
+            free()
+                .map(|v| values[v].0.powi(2) + values[v].1.powi(2))
+                .sum::<f64>()
+                .sqrt()
+        };
+        let gradient: Vec<(f64, f64)> = (0..n)
+            .map(|v| (adjusted[v].0 - observed[v].0, adjusted[v].1 - observed[v].1))
+            .collect();
+        let bound = tolerance * norm(&observed).max(norm(&adjusted));
+        let residual = norm(&gradient);
+        if residual.is_nan() || residual > bound {
+            let worst = free()
+                .max_by(|&a, &b| {
+                    let size = |v: usize| gradient[v].0.hypot(gradient[v].1);
+                    size(a).total_cmp(&size(b))
+                })
+                .unwrap_or(0);
+            return violation(format!(
+                "normal equations off by {residual} (bound {bound}), most at vertex {worst}"
+            ));
+        }
+        Ok(())
+    }
+
+    /// Checks that `relabeled`, the solution of the graph renumbered by `permutation` (see
+    /// [`Graph::relabeled`]), puts each vertex within `tolerance` of this solution.
+    pub fn check_relabeled(
+        &self,
+        relabeled: &Solution,
+        permutation: &[usize],
+        tolerance: f64,
+    ) -> Result<(), SolveError> {
+        self.check_moved(relabeled, tolerance, |v| (permutation[v], 0.0, 0.0))
+    }
+
+    /// Checks that `translated`, the solution of the graph moved by `(dx, dy)` (see
+    /// [`Graph::translated`]), is within `tolerance` of this solution moved by as much.
+    pub fn check_translated(
+        &self,
+        translated: &Solution,
+        dx: f64,
+        dy: f64,
+        tolerance: f64,
+    ) -> Result<(), SolveError> {
+        self.check_moved(translated, tolerance, |v| (v, dx, dy))
+    }
+
+    /// Checks that `other` has the same coordinates as this solution bit for bit, as the
+    /// solution of the same graph with its edges [reordered](Graph::reordered) must.
+    pub fn check_identical(&self, other: &Solution) -> Result<(), SolveError> {
+        if other.x.len() != self.x.len() {
+            return violation(format!(
+                "{} vertices against {}",
+                other.x.len(),
+                self.x.len()
+            ));
+        }
+        for v in 0..self.x.len() {
+            let (a, b) = ((self.x[v], self.y[v]), (other.x[v], other.y[v]));
+            if a.0.to_bits() != b.0.to_bits() || a.1.to_bits() != b.1.to_bits() {
+                return violation(format!("vertex {v} is at {b:?} instead of {a:?}"));
+            }
+        }
+        Ok(())
+    }
+
+    /// Compares `other` with this solution, vertex `v` of which `moved` sends to a vertex of
+    /// `other` with an offset.
+    fn check_moved(
+        &self,
+        other: &Solution,
+        tolerance: f64,
+        moved: impl Fn(usize) -> (usize, f64, f64),
+    ) -> Result<(), SolveError> {
+        if other.x.len() != self.x.len() {
+            return violation(format!(
+                "{} vertices against {}",
+                other.x.len(),
+                self.x.len()
+            ));
+        }
+        for v in 0..self.x.len() {
+            let (w, dx, dy) = moved(v);
+            let distance = (other.x[w] - self.x[v] - dx).hypot(other.y[w] - self.y[v] - dy);
+            if distance.is_nan() || distance > tolerance {
+                return violation(format!("vertex {v} is {distance} away as vertex {w}"));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::synthetic_cave;
+    use crate::{LinearSolver, Preconditioner};
+    use proptest::prelude::*;
+
+    const ITERATIONS: usize = 100_000;
+    const TOLERANCE: f64 = 1e-12;
+
+    /// A synthetic cave and a permutation of its vertices.
+    fn cave_and_permutation() -> impl Strategy<Value = (Graph, Vec<usize>)> {
+        (any::<u64>(), 2usize..60).prop_flat_map(|(seed, n)| {
+            let permutation = Just((0..n).collect::<Vec<_>>()).prop_shuffle();
+            (Just(synthetic_cave(seed, n)), permutation)
+        })
+    }
+
+    /// A synthetic cave and a permutation of its edges.
+    fn cave_and_edge_order() -> impl Strategy<Value = (Graph, Vec<usize>)> {
+        (any::<u64>(), 2usize..60).prop_flat_map(|(seed, n)| {
+            let graph = synthetic_cave(seed, n);
+            let order = Just((0..graph.num_edges()).collect::<Vec<_>>()).prop_shuffle();
+            (Just(graph), order)
+        })
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(24))]
+
+        #[test]
+        fn solutions_satisfy_the_normal_equations(seed: u64, n in 2usize..60) {
+            let graph = synthetic_cave(seed, n);
+            let solution = graph.solve(ITERATIONS, TOLERANCE).unwrap();
+            solution.check_hard_constraints(&graph, &Constraints::default(), 0.0).unwrap();
+            solution.check_orthogonality(&graph, 1e-9).unwrap();
+        }
+
+        #[test]
+        fn solutions_move_with_their_vertices((graph, permutation) in cave_and_permutation()) {
+            let solution = graph.solve(ITERATIONS, TOLERANCE).unwrap();
+            let relabeled = graph.relabeled(&permutation).unwrap();
+            let moved = relabeled.solve(ITERATIONS, TOLERANCE).unwrap();
+            solution.check_relabeled(&moved, &permutation, 1e-6).unwrap();
+        }
+
+        #[test]
+        fn solutions_ignore_the_order_of_the_edges((graph, order) in cave_and_edge_order()) {
+            let reordered = graph.reordered(&order).unwrap();
+            // Conjugate Gradient, and the direct solve of a multigrid that never coarsens.
+            for preconditioner in [Preconditioner::None, Preconditioner::Amg { coarse_size: 64 }] {
+                let solve = |graph: &Graph| {
+                    graph
+                        .solve_with(ITERATIONS, TOLERANCE, LinearSolver::ConjugateGradient, preconditioner)
+                        .unwrap()
+                };
+                solve(&graph).check_identical(&solve(&reordered)).unwrap();
+            }
+        }
+
+        #[test]
+        fn solutions_translate_with_the_anchors(
+            seed: u64,
+            n in 2usize..60,
+            dx in -1e4f64..1e4,
+            dy in -1e4f64..1e4,
+        ) {
+            // Far from the origin the residual cannot get as small relative to the
+            // right-hand side.
+            let graph = synthetic_cave(seed, n);
+            let solution = graph.solve(ITERATIONS, 1e-10).unwrap();
+            let translated = graph.translated(dx, dy).solve(ITERATIONS, 1e-10).unwrap();
+            solution.check_translated(&translated, dx, dy, 1e-6).unwrap();
+        }
+
+        #[test]
+        fn constrained_solutions_hold_their_constraints(
+            seed: u64,
+            n in 3usize..60,
+            pick in any::<prop::sample::Index>(),
+            offset in -10f64..10.0,
+        ) {
+            // Ties one free vertex to the x of another, offset by a few metres.
+            let graph = synthetic_cave(seed, n);
+            let (a, b) = (1 + pick.index(n - 2), 0);
+            let mut constraints = Constraints::default();
+            constraints.push(&[(2 * a, 1.0), (2 * b, -1.0)], offset);
+            let solution = graph.solve_constrained(&constraints, ITERATIONS, TOLERANCE).unwrap();
+            solution.check_hard_constraints(&graph, &constraints, 1e-9).unwrap();
+        }
+    }
+
+    #[test]
+    fn broken_solutions_are_rejected() {
+        let graph = synthetic_cave(3, 60);
+        let solution = graph.solve(ITERATIONS, TOLERANCE).unwrap();
+        let none = Constraints::default();
+
+        let mut moved = solution.clone();
+        moved.x[0] += 1e-9;
+        assert!(moved.check_hard_constraints(&graph, &none, 0.0).is_err());
+        let mut skewed = solution.clone();
+        skewed.y[30] += 0.5;
+        assert!(skewed.check_orthogonality(&graph, 1e-9).is_err());
+        assert!(solution.check_translated(&skewed, 0.0, 0.0, 1e-6).is_err());
+        assert!(solution.check_identical(&moved).is_err());
+
+        // Swapping two free vertices is not the solution of the identity relabeling.
+        let identity: Vec<usize> = (0..60).collect();
+        let mut swapped = solution.clone();
+        swapped.x.swap(10, 20);
+        swapped.y.swap(10, 20);
+        assert!(solution.check_relabeled(&swapped, &identity, 1e-6).is_err());
+        assert_eq!(
+            graph.relabeled(&[0; 60]).unwrap_err().code,
+            COMPASS_ERR_INVALID_ARGUMENT
+        );
+        assert_eq!(
+            graph.reordered(&[0; 2]).unwrap_err().code,
+            COMPASS_ERR_INVALID_ARGUMENT
+        );
+    }
+}
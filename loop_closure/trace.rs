@@ -0,0 +1,270 @@
+//! Profiling spans of the solve, and their forwarding to a host behind the C ABI.
+//!
+//! With the `tracing` feature the solve enters a `tracing` span per phase, each carrying the
+//! sizes it works on as fields:
+//!
+//! | Span           | Phase                                           | Fields                |
+//! |----------------|-------------------------------------------------|-----------------------|
+//! | `validate`     | Range check of the edge endpoints               | `vertices`, `edges`   |
+//! | `mapping`      | Numbering of the free vertices                  | `vertices`            |
+//! | `assemble`     | Normal equations in COO form                    | `unknowns`, `edges`   |
+//! | `csr`          | Conversion of the matrix to CSR                 | `triplets`            |
+//! | `precondition` | Setup of the preconditioner, when not cached    | `kind`, `unknowns`    |
+//! | `solve_axis`   | Iterative solve of one axis, on its own thread  | `axis`, `unknowns`    |
+//! | `write_back`   | Copy of the coordinates into the caller arrays  | `vertices`            |
+//!
+//! A Rust host reads them with any subscriber. A host behind the C ABI, such as a Java
+//! profiler, registers a [`TraceCallback`] with [`compass_set_trace_callback`] instead: this
+//! installs a minimal global subscriber that calls it as each span closes, with the span
+//! name, its duration in microseconds and its fields as `key=value` pairs.
+//!
+//! Without a callback the subscriber disables every span, which then costs an atomic load;
+//! without the feature the spans are not compiled at all. See `benches/trace.rs`.
+
+use crate::{COMPASS_ERR_INVALID_ARGUMENT, COMPASS_ERR_PANIC, COMPASS_OK};
+use std::collections::HashMap;
+use std::ffi::{CString, c_char, c_int, c_void};
+use std::fmt::Write;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::Instant;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::subscriber::Interest;
+use tracing::{Event, Metadata, Subscriber};
+
+/// Receives a closed span: its NUL-terminated `name` and `fields`, its duration in
+/// microseconds and the `user_data` given to [`compass_set_trace_callback`]. The strings are
+/// only valid during the call. It is called on the thread that closed the span, possibly
+/// several at once.
+pub type TraceCallback =
+    extern "C" fn(name: *const c_char, micros: u64, fields: *const c_char, user_data: *mut c_void);
+
+/// The registered callback and its user data, as an address so that it can be shared.
+static CALLBACK: Mutex<Option<(TraceCallback, usize)>> = Mutex::new(None);
+
+/// Whether a callback is registered, read by every span before anything else.
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// Whether the forwarding subscriber is the global default, decided on the first
+/// registration.
+static INSTALLED: OnceLock<bool> = OnceLock::new();
+
+/// Registers `callback`, called with `user_data` as each span of the solve closes, or
+/// unregisters the current one for a null `callback`. See the [module documentation](self).
+///
+/// The first registration installs the forwarding subscriber as the global default of the
+/// process. Returns [`COMPASS_ERR_INVALID_ARGUMENT`] if another one is installed already,
+/// e.g. by a Rust host, which then receives the spans itself.
+#[unsafe(no_mangle)]
+pub extern "C" fn compass_set_trace_callback(
+    callback: Option<TraceCallback>,
+    user_data: *mut c_void,
+) -> c_int {
+    let result = std::panic::catch_unwind(|| {
+        let Some(callback) = callback else {
+            ACTIVE.store(false, Ordering::Relaxed);
+            *CALLBACK.lock().unwrap_or_else(|e| e.into_inner()) = None;
+            return COMPASS_OK;
+        };
+        let installed = INSTALLED
+            .get_or_init(|| tracing::subscriber::set_global_default(Forwarder::default()).is_ok());
+        if !installed {
+            return COMPASS_ERR_INVALID_ARGUMENT;
+        }
+        *CALLBACK.lock().unwrap_or_else(|e| e.into_inner()) = Some((callback, user_data as usize));
+        // Callsites are re-evaluated so that spans already seen as disabled are enabled.
+        tracing::callsite::rebuild_interest_cache();
+        ACTIVE.store(true, Ordering::Relaxed);
+        COMPASS_OK
+    });
+
+    result.unwrap_or_else(|_| {
+        eprintln!("Panic caught in compass_set_trace_callback");
+        COMPASS_ERR_PANIC
+    })
+}
+
+/// Subscriber timing the spans and handing the closed ones to the [`CALLBACK`].
+#[derive(Default)]
+struct Forwarder {
+    /// Spans not closed yet, by id.
+    spans: Mutex<HashMap<u64, OpenSpan>>,
+    /// Last id handed out; ids start at 1.
+    last_id: AtomicU64,
+}
+
+/// A span between its creation and its close.
+struct OpenSpan {
+    name: &'static str,
+    /// Fields as space separated `key=value` pairs.
+    fields: String,
+    start: Instant,
+    /// Handles to the span, see [`Subscriber::clone_span`].
+    references: usize,
+}
+
+/// Appends the visited fields to a string, see [`OpenSpan::fields`].
+struct FieldWriter<'a>(&'a mut String);
+
+impl Visit for FieldWriter<'_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.record_debug(field, &format_args!("{value}"));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if !self.0.is_empty() {
+            self.0.push(' ');
+        }
+        let _ = write!(self.0, "{}={value:?}", field.name());
+    }
+}
+
+impl Forwarder {
+    /// The open spans, recovering them from a poisoned lock.
+    fn spans(&self) -> std::sync::MutexGuard<'_, HashMap<u64, OpenSpan>> {
+        self.spans.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Subscriber for Forwarder {
+    fn register_callsite(&self, _metadata: &'static Metadata<'static>) -> Interest {
+        match ACTIVE.load(Ordering::Relaxed) {
+            true => Interest::sometimes(),
+            false => Interest::never(),
+        }
+    }
+
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        metadata.is_span() && ACTIVE.load(Ordering::Relaxed)
+    }
+
+    fn new_span(&self, span: &Attributes<'_>) -> Id {
+        let mut fields = String::new();
+        span.record(&mut FieldWriter(&mut fields));
+        let id = self.last_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let open = OpenSpan {
+            name: span.metadata().name(),
+            fields,
+            start: Instant::now(),
+            references: 1,
+        };
+        self.spans().insert(id, open);
+        Id::from_u64(id)
+    }
+
+    fn record(&self, span: &Id, values: &Record<'_>) {
+        if let Some(open) = self.spans().get_mut(&span.into_u64()) {
+            values.record(&mut FieldWriter(&mut open.fields));
+        }
+    }
+
+    fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+    fn event(&self, _event: &Event<'_>) {}
+
+    fn enter(&self, _span: &Id) {}
+
+    fn exit(&self, _span: &Id) {}
+
+    fn clone_span(&self, span: &Id) -> Id {
+        if let Some(open) = self.spans().get_mut(&span.into_u64()) {
+            open.references += 1;
+        }
+        span.clone()
+    }
+
+    fn try_close(&self, span: Id) -> bool {
+        let closed = {
+            let mut spans = self.spans();
+            let Some(open) = spans.get_mut(&span.into_u64()) else {
+                return false;
+            };
+            open.references -= 1;
+            if open.references > 0 {
+                return false;
+            }
+            spans.remove(&span.into_u64())
+        };
+        if let Some(open) = closed {
+            forward(&open);
+        }
+        true
+    }
+}
+
+/// Calls the registered callback, if any, with the closed span `open`.
+fn forward(open: &OpenSpan) {
+    let Some((callback, user_data)) = *CALLBACK.lock().unwrap_or_else(|e| e.into_inner()) else {
+        return;
+    };
+    let micros = open.start.elapsed().as_micros().min(u64::MAX as u128) as u64;
+    let name = CString::new(open.name).unwrap_or_default();
+    let fields = CString::new(open.fields.replace('\0', "")).unwrap_or_default();
+    callback(
+        name.as_ptr(),
+        micros,
+        fields.as_ptr(),
+        user_data as *mut c_void,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Graph;
+    use std::ffi::CStr;
+
+    static CLOSED: Mutex<Vec<(String, String)>> = Mutex::new(Vec::new());
+
+    extern "C" fn collect(
+        name: *const c_char,
+        _micros: u64,
+        fields: *const c_char,
+        user_data: *mut c_void,
+    ) {
+        assert_eq!(user_data as usize, 42);
+        let text = |p: *const c_char| unsafe { CStr::from_ptr(p) }.to_str().unwrap().to_string();
+        CLOSED.lock().unwrap().push((text(name), text(fields)));
+    }
+
+    #[test]
+    fn forwards_the_spans_of_a_solve_to_the_callback() {
+        let mut graph = Graph::default();
+        graph.add_vertex(0.0, 0.0, 0.0, true);
+        graph.add_vertex(1.0, 0.0, 0.0, false);
+        graph.add_vertex(1.0, 1.0, 0.0, false);
+        graph.add_edge(0, 1, 1.0, 0.1, 0.0, 1.0);
+        graph.add_edge(1, 2, 0.1, 1.0, 0.0, 1.0);
+        graph.add_edge(2, 0, -1.0, -1.0, 0.0, 1.0);
+
+        let user_data = 42 as *mut c_void;
+        assert_eq!(
+            compass_set_trace_callback(Some(collect), user_data),
+            COMPASS_OK
+        );
+        graph
+            .solve_with(
+                100,
+                1e-12,
+                crate::LinearSolver::ConjugateGradient,
+                crate::Preconditioner::Jacobi,
+            )
+            .unwrap();
+        assert_eq!(compass_set_trace_callback(None, user_data), COMPASS_OK);
+        let closed = std::mem::take(&mut *CLOSED.lock().unwrap());
+        let names: Vec<&str> = closed.iter().map(|(name, _)| name.as_str()).collect();
+        for name in ["mapping", "assemble", "csr", "precondition"] {
+            assert!(names.contains(&name), "{name} in {names:?}");
+        }
+        // Tests running alongside may add their own spans.
+        let has = |name: &str, fields: &str| closed.contains(&(name.into(), fields.into()));
+        assert!(has("solve_axis", "axis=0 unknowns=2"), "{closed:?}");
+        assert!(has("solve_axis", "axis=1 unknowns=2"), "{closed:?}");
+        assert!(has("assemble", "unknowns=2 edges=3"), "{closed:?}");
+
+        // Nothing is forwarded once the callback is cleared.
+        graph.solve(100, 1e-12).unwrap();
+        assert!(CLOSED.lock().unwrap().is_empty());
+    }
+}
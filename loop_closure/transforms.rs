@@ -0,0 +1,233 @@
+//! Affine correction of the observations per edge group, e.g. the calibration of a digitizer
+//! that stretched one source of legacy data.
+//!
+//! Each group of [`GraphContext::set_edge_groups`] may have a 2x2 matrix `M`, row major,
+//! replacing the observation `(dx, dy)` of its edges by `M (dx, dy)` before the adjustment:
+//! `[scale_x, shear, 0, scale_y]` corrects scales and a shear along X. Groups without a
+//! matrix keep their observations, as with the identity. The corrected observations enter
+//! the normal equations wherever the raw ones did, between free stations and towards fixed
+//! ones alike, and the residuals of the handle are those of the corrected observations:
+//! [`crate::graph_get_residuals`], [`GraphContext::group_reports`], which echoes the matrix
+//! of each group in [`GroupReport::transform`], and the class reports.
+//!
+//! A change of the matrices or of the groups between two [`crate::graph_solve`] refills the
+//! cached normal equations in place, like a change of the edge classes.
+//!
+//! [`GroupReport::transform`]: crate::GroupReport::transform
+
+use crate::{COMPASS_ERR_INVALID_ARGUMENT, Graph, GraphContext, SolveError, SolveOptions};
+use std::borrow::Cow;
+use std::ffi::c_int;
+use std::slice;
+
+/// The identity matrix, row major.
+pub const IDENTITY: [f64; 4] = [1.0, 0.0, 0.0, 1.0];
+
+/// Correction matrix of each transformed edge group.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ObservationTransforms {
+    /// Group ids, distinct.
+    pub group: Vec<c_int>,
+    /// 2x2 matrix of each group, row major, invertible.
+    pub matrix: Vec<[f64; 4]>,
+}
+
+impl ObservationTransforms {
+    /// Matrix of `group`, the identity for a group without one.
+    pub fn matrix_of(&self, group: c_int) -> [f64; 4] {
+        self.group
+            .iter()
+            .position(|&g| g == group)
+            .map_or(IDENTITY, |k| self.matrix[k])
+    }
+
+    /// Replaces the observations of `graph` by the corrected ones, `group_id` holding the
+    /// group of each edge. Edges past its end are in group 0.
+    pub fn apply(&self, group_id: &[c_int], graph: &mut Graph) {
+        for e in 0..graph.num_edges() {
+            let m = self.matrix_of(group_id.get(e).copied().unwrap_or(0));
+            let (dx, dy) = (graph.dx[e], graph.dy[e]);
+            graph.dx[e] = m[0] * dx + m[1] * dy;
+            graph.dy[e] = m[2] * dx + m[3] * dy;
+        }
+    }
+
+    /// Checks that the groups are distinct and the matrices finite and invertible.
+    pub fn validate(&self) -> Result<(), SolveError> {
+        let invalid = |message: String| {
+            Err(SolveError {
+                code: COMPASS_ERR_INVALID_ARGUMENT,
+                message,
+            })
+        };
+        if self.group.len() != self.matrix.len() {
+            return invalid(format!(
+                "{} transformed groups for {} matrices",
+                self.group.len(),
+                self.matrix.len()
+            ));
+        }
+        for (k, (&group, m)) in self.group.iter().zip(&self.matrix).enumerate() {
+            if self.group[..k].contains(&group) {
+                return invalid(format!("group {group} transformed twice"));
+            }
+            // The determinant is compared with the size of the entries, so that a matrix
+            // scaled down uniformly is not taken for a singular one.
+            let scale = m.iter().fold(0.0, |s: f64, v| s.max(v.abs()));
+            let det = m[0] * m[3] - m[1] * m[2];
+            if !(det.is_finite() && det.abs() > 1e-12 * scale * scale) {
+                return invalid(format!("transform of group {group} is not invertible"));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The transforms of [`SolveOptions::transform_groups`], `None` when the pointer is null.
+pub(crate) fn from_options(options: &SolveOptions) -> Option<ObservationTransforms> {
+    if options.transform_groups.is_null() {
+        return None;
+    }
+    let count = options.num_transforms.max(0) as usize;
+    // Safety: The caller guarantees `num_transforms` groups and `4 * num_transforms` values.
+    let group = unsafe { slice::from_raw_parts(options.transform_groups, count) };
+    let matrix = match options.transform_matrices.is_null() {
+        true => &[][..],
+        false => unsafe { slice::from_raw_parts(options.transform_matrices, 4 * count) },
+    };
+    Some(ObservationTransforms {
+        group: group.to_vec(),
+        matrix: matrix
+            .chunks_exact(4)
+            .map(|m| [m[0], m[1], m[2], m[3]])
+            .collect(),
+    })
+}
+
+impl GraphContext {
+    /// The observation transforms of the next solves, `None` for the raw observations.
+    pub fn observation_transforms(&self) -> Option<&ObservationTransforms> {
+        self.transforms.as_ref()
+    }
+
+    /// Sets the observation transforms of the next solves, see the
+    /// [module documentation](self). Cached normal equations are refilled in place.
+    pub fn set_observation_transforms(
+        &mut self,
+        transforms: Option<ObservationTransforms>,
+    ) -> Result<(), SolveError> {
+        if let Some(transforms) = &transforms {
+            transforms.validate()?;
+        }
+        if self.transforms != transforms {
+            self.transforms = transforms;
+            self.refill();
+        }
+        Ok(())
+    }
+
+    /// Matrix of the group of `edge`, the identity without transforms.
+    pub(crate) fn transform_of(&self, edge: usize) -> [f64; 4] {
+        self.transforms.as_ref().map_or(IDENTITY, |transforms| {
+            transforms.matrix_of(self.group_id.get(edge).copied().unwrap_or(0))
+        })
+    }
+
+    /// The graph with its observations corrected by the transforms.
+    pub(crate) fn observed_graph(&self) -> Cow<'_, Graph> {
+        match &self.transforms {
+            None => Cow::Borrowed(&self.graph),
+            Some(transforms) => {
+                let mut graph = self.graph.clone();
+                transforms.apply(&self.group_id, &mut graph);
+                Cow::Owned(graph)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::synthetic_cave;
+    use crate::{COMPASS_OK, SolveStats, graph_free, graph_get_residuals, graph_solve};
+
+    /// Solves the graph behind `handle` tightly with the transforms of `group` and `matrix`.
+    fn solve(handle: *mut GraphContext, group: &[c_int], matrix: &[f64]) -> c_int {
+        let options = SolveOptions {
+            tolerance: 1e-12,
+            transform_groups: group.as_ptr(),
+            transform_matrices: matrix.as_ptr(),
+            num_transforms: group.len() as c_int,
+            ..SolveOptions::default()
+        };
+        let mut stats = SolveStats {
+            struct_size: size_of::<SolveStats>(),
+            ..SolveStats::default()
+        };
+        graph_solve(handle, &options, &mut stats)
+    }
+
+    #[test]
+    fn a_scaled_group_solves_like_prescaled_data() {
+        let graph = synthetic_cave(5, 60);
+        // Every third edge, among them edge 0 from the fixed station 0, comes from source 7.
+        let group_id: Vec<c_int> = (0..graph.num_edges()).map(|e| [7, 1, 2][e % 3]).collect();
+        assert!(graph.fixed[graph.from[0]] || graph.fixed[graph.to[0]]);
+        let mut prescaled = graph.clone();
+        for e in (0..graph.num_edges()).filter(|&e| group_id[e] == 7) {
+            prescaled.dx[e] *= 1.01;
+            prescaled.dy[e] *= 1.01;
+        }
+
+        let handle = GraphContext::into_raw(graph.clone());
+        // A first solve with the raw observations caches the normal equations.
+        assert_eq!(solve(handle, &[], &[]), COMPASS_OK);
+        unsafe { &mut *handle }
+            .set_edge_groups(group_id.clone())
+            .unwrap();
+        assert_eq!(solve(handle, &[7], &[1.01, 0.0, 0.0, 1.01]), COMPASS_OK);
+        let expected = GraphContext::into_raw(prescaled);
+        assert_eq!(solve(expected, &[], &[]), COMPASS_OK);
+        let (ctx, fresh) = unsafe { (&*handle, &*expected) };
+        let ((x, y), (x0, y0)) = (ctx.coordinates(), fresh.coordinates());
+        for v in 0..graph.num_vertices() {
+            assert!((x[v] - x0[v]).abs() < 1e-8 && (y[v] - y0[v]).abs() < 1e-8);
+        }
+
+        // The residuals are those of the corrected observations.
+        let edges = graph.num_edges();
+        let mut residuals = [(); 4].map(|_| vec![0.0; edges]);
+        let [rx, ry, rx0, ry0] = &mut residuals;
+        graph_get_residuals(handle, rx.as_mut_ptr(), ry.as_mut_ptr());
+        graph_get_residuals(expected, rx0.as_mut_ptr(), ry0.as_mut_ptr());
+        for e in 0..edges {
+            assert!((rx[e] - rx0[e]).abs() < 1e-8 && (ry[e] - ry0[e]).abs() < 1e-8);
+        }
+        let reports = ctx.group_reports();
+        for report in &reports {
+            let transform = match report.group {
+                7 => [1.01, 0.0, 0.0, 1.01],
+                _ => IDENTITY,
+            };
+            assert_eq!(report.transform, transform);
+        }
+        assert_eq!(reports.len(), 3);
+        graph_free(expected);
+
+        // A singular matrix or a group given twice is rejected.
+        assert_eq!(
+            solve(handle, &[7], &[1.0, 2.0, 0.5, 1.0]),
+            COMPASS_ERR_INVALID_ARGUMENT
+        );
+        assert_eq!(
+            solve(handle, &[1, 1], &[IDENTITY, IDENTITY].concat()),
+            COMPASS_ERR_INVALID_ARGUMENT
+        );
+        assert_eq!(
+            unsafe { &*handle }.observation_transforms().unwrap().group,
+            [7]
+        );
+        graph_free(handle);
+    }
+}
@@ -0,0 +1,603 @@
+//! Two-stage "2+1" adjustment: the horizontal network, then the heights, in one call.
+//!
+//! Horizontal and vertical errors of a cave survey have unrelated models, so the heights are
+//! adjusted on their own: the observed `dz` of each edge, weighted by its own `weight_z`, are
+//! fitted to the heights `z` exactly as `(dx, dy)` are fitted to `(x, y)`. The vertical normal
+//! equations only differ from the horizontal ones in their values, so they are refilled from
+//! the horizontal system, keeping its mapping and sparsity pattern, and solved as a single
+//! right-hand side.
+//!
+//! The vertical solve takes the fixed, frozen, held and passive vertices and the enabled
+//! edges of the horizontal one. The centroid, datum and edge class options only apply to the
+//! horizontal solve, and the per-axis limits to its X and Y axes.
+
+use crate::report::{NetworkSummary, SpanningForest, ppm};
+use crate::{
+    COMPASS_ERR_INVALID_ARGUMENT, COMPASS_ERR_PANIC, COMPASS_RESULT_EXACT,
+    COMPASS_RESULT_UNTOUCHED, Convergence, Graph, GraphView, LinearSolver, NormalEquations,
+    NullSpace, Preconditioner, SOLVE_STATS_MIN_SIZE, Solution, SolveError, SolveOptions,
+    SolveStats, Stopwatch, Tolerance, place_passive_vertices, raw_slice, raw_slice_mut,
+    solve_arrays, try_copied, write_stats,
+};
+use nalgebra::DVector;
+use std::ffi::{c_double, c_int};
+use std::sync::Mutex;
+
+/// Result of [`Graph::solve_2plus1`].
+#[derive(Debug, Clone, Default)]
+pub struct TwoStageSolution {
+    /// The horizontal adjustment, as [`Graph::solve`] returns it.
+    pub horizontal: Solution,
+    /// Adjusted Z coordinates, one per vertex.
+    pub z: Vec<f64>,
+    /// Summary of the vertical solve, its single axis reported in the X axis fields.
+    pub vertical: SolveStats,
+}
+
+/// Horizontal and vertical misclosure of one independent loop, see
+/// [`crate::report::LoopMisclosure`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct TwoStageLoop {
+    /// Edge closing the loop over the spanning forest.
+    pub closing_edge: usize,
+    /// Number of edges in the loop.
+    pub edges: usize,
+    /// Whether the loop is a traverse between two anchors.
+    pub through_anchors: bool,
+    /// Sum of the 3D shot lengths of the loop.
+    pub length: f64,
+    /// Length of the horizontal misclosure vector.
+    pub horizontal_misclosure: f64,
+    /// [`TwoStageLoop::horizontal_misclosure`] in parts per million of the length.
+    pub horizontal_ppm: f64,
+    /// Misclosure of the observed `dz` along the loop, in the direction of the closing edge.
+    pub vertical_misclosure: f64,
+    /// Magnitude of [`TwoStageLoop::vertical_misclosure`] in parts per million of the length.
+    pub vertical_ppm: f64,
+}
+
+/// Residuals of one edge after both stages.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TwoStageResidual {
+    pub edge: usize,
+    pub rx: f64,
+    pub ry: f64,
+    pub rz: f64,
+    /// Horizontal standardized residual, see [`Solution::standardized_residuals`].
+    pub standardized: f64,
+    /// `|rz| * sqrt(weight_z)`.
+    pub standardized_z: f64,
+}
+
+/// Unified report of a 2+1 adjustment, see [`TwoStageSolution::report`].
+#[derive(Debug, Clone)]
+pub struct TwoStageReport {
+    pub network: NetworkSummary,
+    /// Diagnostics of the horizontal solve.
+    pub horizontal: SolveStats,
+    /// Diagnostics of the vertical solve.
+    pub vertical: SolveStats,
+    /// One entry per independent loop, by decreasing [`TwoStageLoop::horizontal_ppm`].
+    pub loops: Vec<TwoStageLoop>,
+    /// Residuals of every edge, in edge order.
+    pub residuals: Vec<TwoStageResidual>,
+}
+
+impl Graph {
+    /// Solves the horizontal adjustment like [`Graph::solve`], then the heights from
+    /// [`Graph::dz`] weighted by `weight_z`, one per edge, on the same normal equations
+    /// structure. See the [module documentation](self).
+    pub fn solve_2plus1(
+        &self,
+        weight_z: &[f64],
+        iterations: usize,
+        tolerance: f64,
+    ) -> Result<TwoStageSolution, SolveError> {
+        let invalid = |message: String| SolveError {
+            code: COMPASS_ERR_INVALID_ARGUMENT,
+            message,
+        };
+        if weight_z.len() != self.num_edges() {
+            return Err(invalid(format!(
+                "expected {} vertical weights, got {}",
+                self.num_edges(),
+                weight_z.len()
+            )));
+        }
+        let system = self.normal_equations(&[])?;
+        let mut z = self.z.clone();
+        let mut stage =
+            VerticalStage::new(&mut z, &self.dz, weight_z).map_err(|code| SolveError {
+                code,
+                message: format!("{} heights too many to allocate", self.num_vertices()),
+            })?;
+        let method = (LinearSolver::default(), Preconditioner::default());
+        self.with_view(&[], |view| stage.prepare(&system, view, &|_| false))
+            .map_err(invalid)?;
+        let horizontal = self.solve_system(
+            &system,
+            &self.x,
+            &self.y,
+            iterations,
+            tolerance.into(),
+            method,
+            &|| false,
+        )?;
+        self.with_view(&[], |view| {
+            stage.solve(
+                view,
+                &|_| false,
+                iterations,
+                tolerance.into(),
+                method,
+                &|| false,
+            )
+        });
+        stage.write_back(&|i| self.fixed[i]);
+        let vertical = stage.stats;
+        Ok(TwoStageSolution {
+            horizontal,
+            z,
+            vertical,
+        })
+    }
+}
+
+impl TwoStageSolution {
+    /// Report of this solution of `graph` with the vertical weights `weight_z` it was solved
+    /// with: the loop misclosures of [`crate::report::AdjustmentReport`] with their vertical
+    /// counterpart, and the residuals of both stages.
+    pub fn report(&self, graph: &Graph, weight_z: &[f64]) -> TwoStageReport {
+        let horizontal = self.horizontal.adjustment_report(graph);
+        let (x, y, z) = (&self.horizontal.x, &self.horizontal.y, &self.z);
+
+        // Heights chained along the same spanning forest as the horizontal positions.
+        let enabled: Vec<usize> = (0..graph.num_edges()).collect();
+        let forest = SpanningForest::new(graph, &enabled);
+        let mut height = vec![0.0; graph.num_vertices() + 1];
+        for &v in &forest.order {
+            height[v] = match forest.parent[v] {
+                Some((_, None)) => graph.z[v],
+                Some((u, Some(e))) if graph.to[e] == v => height[u] + graph.dz[e],
+                Some((u, Some(e))) => height[u] - graph.dz[e],
+                None => 0.0,
+            };
+        }
+        let loops = horizontal
+            .loops
+            .iter()
+            .map(|l| {
+                let e = l.closing_edge;
+                let vertical_misclosure = height[graph.from[e]] + graph.dz[e] - height[graph.to[e]];
+                TwoStageLoop {
+                    closing_edge: e,
+                    edges: l.edges,
+                    through_anchors: l.through_anchors,
+                    length: l.length,
+                    horizontal_misclosure: l.misclosure,
+                    horizontal_ppm: l.ppm,
+                    vertical_misclosure,
+                    vertical_ppm: ppm(vertical_misclosure.abs(), l.length),
+                }
+            })
+            .collect();
+
+        let residuals = (0..graph.num_edges())
+            .map(|e| {
+                let (u, v) = (graph.from[e], graph.to[e]);
+                let rx = x[v] - x[u] - graph.dx[e];
+                let ry = y[v] - y[u] - graph.dy[e];
+                let rz = z[v] - z[u] - graph.dz[e];
+                TwoStageResidual {
+                    edge: e,
+                    rx,
+                    ry,
+                    rz,
+                    standardized: rx.hypot(ry) * graph.weight[e].sqrt(),
+                    standardized_z: rz.abs() * weight_z[e].sqrt(),
+                }
+            })
+            .collect();
+
+        TwoStageReport {
+            network: horizontal.network,
+            horizontal: self.horizontal.stats,
+            vertical: self.vertical,
+            loops,
+            residuals,
+        }
+    }
+}
+
+/// Vertical stage of a solve, run by the horizontal one on its normal equations.
+pub(crate) struct VerticalStage<'a> {
+    /// The caller's heights, see [`VerticalStage::write_back`].
+    heights: &'a mut [f64],
+    /// Local copy of the heights the solve works on.
+    z: Vec<f64>,
+    dz: &'a [f64],
+    weight_z: &'a [f64],
+    /// Vertical normal equations, see [`VerticalStage::prepare`].
+    system: Option<NormalEquations>,
+    pub(crate) stats: SolveStats,
+}
+
+impl<'a> VerticalStage<'a> {
+    /// Stage adjusting `heights` from the observed `dz` weighted by `weight_z`. Fails with
+    /// [`crate::COMPASS_ERR_OUT_OF_MEMORY`] when the local copy cannot be allocated.
+    pub(crate) fn new(
+        heights: &'a mut [f64],
+        dz: &'a [f64],
+        weight_z: &'a [f64],
+    ) -> Result<VerticalStage<'a>, c_int> {
+        Ok(VerticalStage {
+            z: try_copied(heights)?,
+            heights,
+            dz,
+            weight_z,
+            system: None,
+            stats: SolveStats::default(),
+        })
+    }
+
+    /// The vertical counterpart of `graph`.
+    fn view<'v>(&self, graph: &GraphView<'v>) -> GraphView<'v>
+    where
+        'a: 'v,
+    {
+        GraphView {
+            dx: self.dz,
+            dy: self.dz,
+            weight: self.weight_z,
+            ..*graph
+        }
+    }
+
+    /// Builds the vertical normal equations of `graph` from `system`, its horizontal ones,
+    /// before either is solved. Fails with the reason when a free vertex has only edges of
+    /// zero vertical weight.
+    pub(crate) fn prepare(
+        &mut self,
+        system: &NormalEquations,
+        graph: &GraphView,
+        is_passive: &dyn Fn(usize) -> bool,
+    ) -> Result<(), String> {
+        let mut vertical = NormalEquations {
+            mapping: system.mapping.clone(),
+            matrix: system.matrix.clone(),
+            bx: system.bx.clone(),
+            by: system.by.clone(),
+            null_space: NullSpace::default(),
+            preconditioner: Mutex::new(None),
+            edge_warnings: Vec::new(),
+        };
+        let view = self.view(graph);
+        if !vertical.refill(&self.z, &self.z, &view, is_passive) {
+            unreachable!("the vertical system has the edges of the horizontal one");
+        }
+        if let Some(i) = vertical.empty_row() {
+            return Err(format!(
+                "free vertex {i} has only edges of zero vertical weight"
+            ));
+        }
+        self.stats.free_vertices = vertical.size() as c_int;
+        self.stats.free_networks = vertical.null_space.dimension() as c_int;
+        self.system = Some(vertical);
+        Ok(())
+    }
+
+    /// Solves the heights prepared by [`VerticalStage::prepare`] and places the passive
+    /// vertices. Returns whether the solve was cancelled.
+    pub(crate) fn solve(
+        &mut self,
+        graph: &GraphView,
+        is_passive: &dyn Fn(usize) -> bool,
+        iterations: usize,
+        tolerance: Tolerance,
+        method: (LinearSolver, Preconditioner),
+        cancelled: &(dyn Fn() -> bool + Sync),
+    ) -> bool {
+        let Some(system) = &self.system else {
+            return false;
+        };
+        let clock = Stopwatch::start();
+        let mut interrupted = false;
+        if system.size() > 0 {
+            let convergence =
+                system.solve_heights(&mut self.z, iterations, tolerance, method, cancelled);
+            convergence.record(&mut self.stats);
+            interrupted = convergence.cancelled;
+        } else {
+            self.stats.result_quality = COMPASS_RESULT_EXACT;
+        }
+        let (view, mut unused) = (self.view(graph), self.z.clone());
+        place_passive_vertices(&mut self.z, &mut unused, &view, is_passive);
+        self.stats.total_ms = clock.ms();
+        interrupted
+    }
+
+    /// Writes the adjusted heights of the vertices that are not `is_fixed` to the caller's,
+    /// unless the vertical solve never ran.
+    pub(crate) fn write_back(&mut self, is_fixed: &dyn Fn(usize) -> bool) {
+        if self.stats.result_quality == COMPASS_RESULT_UNTOUCHED {
+            return;
+        }
+        for i in (0..self.z.len()).filter(|&i| !is_fixed(i)) {
+            self.heights[i] = self.z[i];
+        }
+    }
+}
+
+impl NormalEquations {
+    /// Solves the single right-hand side [`NormalEquations::bx`] for the free vertices, using
+    /// their current heights in `z` as the initial guess and writing the results back. The
+    /// convergence is reported in the X axis fields.
+    fn solve_heights(
+        &self,
+        z: &mut [f64],
+        iterations: usize,
+        tolerance: Tolerance,
+        method: (LinearSolver, Preconditioner),
+        cancelled: &(dyn Fn() -> bool + Sync),
+    ) -> Convergence {
+        let mut z0 = DVector::zeros(self.size());
+        for (i, idx) in self.mapping.iter().enumerate() {
+            if let Some(idx) = *idx {
+                z0[idx] = z[i];
+            }
+        }
+        let tolerance = Tolerance {
+            axes: [(None, None); 2],
+            ..tolerance
+        };
+        let (solved, mut convergence) = match self.converged_at(&self.bx, &z0, &tolerance) {
+            Some(convergence) => (z0, convergence),
+            None => {
+                let clock = Stopwatch::start();
+                let run = self.runner(method, cancelled);
+                let precondition_ms = clock.ms();
+                let clock = Stopwatch::start();
+                let (solved, mut convergence) = run(&self.bx, &z0, (iterations, tolerance));
+                convergence.precondition_ms = precondition_ms;
+                convergence.axis_ms[0] = clock.ms();
+                (solved, convergence)
+            }
+        };
+        convergence.axis_iterations[0] = convergence.iterations;
+        convergence.axis_residual_norm[0] = convergence.residual_norm;
+        for (i, idx) in self.mapping.iter().enumerate() {
+            if let Some(idx) = *idx {
+                z[i] = solved[idx];
+            }
+        }
+        convergence
+    }
+}
+
+/// Same as [`crate::solve_graph_least_squares_ex`], then adjusts the heights `z` (in/out)
+/// from `observed_dz` weighted by `weight_z`, see the [module documentation](self). The
+/// vertical solve is summarized in `vertical_stats`, its single axis in the X axis fields.
+///
+/// The vertical normal equations are built and checked before either solve, so a free vertex
+/// with only edges of zero vertical weight fails with [`COMPASS_ERR_INVALID_ARGUMENT`] and
+/// writes nothing, the reason being given in [`SolveOptions::error_message`]. The heights are
+/// written back with the horizontal coordinates, in the same cases.
+#[unsafe(no_mangle)]
+pub extern "C" fn solve_graph_least_squares_2plus1(
+    num_vertices: c_int,
+    x: *mut c_double,
+    y: *mut c_double,
+    z: *mut c_double,
+    fixed: *const c_int,
+    num_edges: c_int,
+    from: *const c_int,
+    to: *const c_int,
+    observed_dx: *const c_double,
+    observed_dy: *const c_double,
+    observed_dz: *const c_double,
+    weight: *const c_double,
+    weight_z: *const c_double,
+    options: *const SolveOptions,
+    stats: *mut SolveStats,
+    vertical_stats: *mut SolveStats,
+) -> c_int {
+    let result = std::panic::catch_unwind(|| {
+        let (Ok(n_verts), Ok(n_edges)) =
+            (usize::try_from(num_vertices), usize::try_from(num_edges))
+        else {
+            return COMPASS_ERR_INVALID_ARGUMENT;
+        };
+        // Safety: `struct_size` leads every release of the struct.
+        if !vertical_stats.is_null()
+            && unsafe { vertical_stats.cast::<usize>().read() } < SOLVE_STATS_MIN_SIZE
+        {
+            return COMPASS_ERR_INVALID_ARGUMENT;
+        }
+        if (n_verts > 0 && z.is_null())
+            || (n_edges > 0 && (observed_dz.is_null() || weight_z.is_null()))
+        {
+            return COMPASS_ERR_INVALID_ARGUMENT;
+        }
+        // Safety: Checked non-null above for the non-empty ones; the caller guarantees the
+        // lengths.
+        let heights = unsafe { raw_slice_mut(z, n_verts) };
+        let dz = unsafe { raw_slice(observed_dz, n_edges) };
+        let weight_z = unsafe { raw_slice(weight_z, n_edges) };
+        let mut stage = match VerticalStage::new(heights, dz, weight_z) {
+            Ok(stage) => stage,
+            Err(code) => return code,
+        };
+        let arrays = (fixed, from, to, [observed_dx, observed_dy, weight]);
+        let vertical = Some(&mut stage);
+        let code = solve_arrays(
+            num_vertices,
+            (x, y),
+            num_edges,
+            arrays,
+            options,
+            stats,
+            vertical,
+        );
+        write_stats(vertical_stats, &stage.stats);
+        code
+    });
+
+    result.unwrap_or_else(|_| {
+        eprintln!("Panic caught in solve_graph_least_squares_2plus1");
+        COMPASS_ERR_PANIC
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::COMPASS_OK;
+    use std::ffi::{CStr, c_char};
+
+    /// A misclosed square loop in 3D anchored at vertex 0, with a spur to vertex 4. The loop
+    /// misses by (0.04, -0.02) horizontally and 0.08 vertically.
+    fn square() -> (Graph, Vec<f64>) {
+        let mut graph = Graph::default();
+        graph.add_vertex(0.0, 0.0, 100.0, true);
+        graph.add_vertex(10.0, 0.0, 98.0, false);
+        graph.add_vertex(10.0, 10.0, 95.0, false);
+        graph.add_vertex(0.0, 10.0, 97.0, false);
+        graph.add_vertex(0.0, 15.0, 96.0, false);
+        graph.add_edge(0, 1, 10.0, 0.0, -2.0, 1.0);
+        graph.add_edge(1, 2, 0.0, 10.0, -3.0, 1.0);
+        graph.add_edge(2, 3, -10.0, 0.0, 2.0, 1.0);
+        graph.add_edge(3, 0, 0.04, -10.02, 3.08, 1.0);
+        graph.add_edge(3, 4, 0.0, 5.0, -1.0, 1.0);
+        (graph, vec![2.0, 2.0, 1.0, 1.0, 4.0])
+    }
+
+    #[test]
+    fn heights_are_adjusted_like_a_horizontal_axis() {
+        let (graph, weight_z) = square();
+        let solution = graph.solve_2plus1(&weight_z, 1000, 1e-12).unwrap();
+        let horizontal = graph.solve(1000, 1e-12).unwrap();
+        assert_eq!(solution.horizontal.x, horizontal.x);
+        assert_eq!(solution.horizontal.y, horizontal.y);
+
+        // The heights are the X axis of the graph observing dz with the vertical weights.
+        let mut flat = graph.clone();
+        flat.x = graph.z.clone();
+        flat.dx = graph.dz.clone();
+        flat.weight = weight_z.clone();
+        let expected = flat.solve(1000, 1e-12).unwrap();
+        for (z, expected) in solution.z.iter().zip(&expected.x) {
+            assert!((z - expected).abs() < 1e-9, "{z} vs {expected}");
+        }
+        assert_eq!(solution.z[0], 100.0);
+        assert_eq!(solution.vertical.result_quality, COMPASS_RESULT_EXACT);
+        assert_eq!(solution.vertical.free_vertices, 4);
+
+        // The vertical misclosure is spread against the vertical weights: 0.08 over
+        // 1/2 + 1/2 + 1 + 1.
+        let report = solution.report(&graph, &weight_z);
+        let [l] = &report.loops[..] else {
+            panic!("{:?}", report.loops);
+        };
+        assert_eq!(l.edges, 4);
+        assert!((l.vertical_misclosure.abs() - 0.08).abs() < 1e-12);
+        assert!((l.horizontal_misclosure - 0.04f64.hypot(0.02)).abs() < 1e-12);
+        assert!((l.vertical_ppm - 0.08 / l.length * 1e6).abs() < 1e-6);
+        for (r, w) in report.residuals.iter().zip(&weight_z).take(4) {
+            assert!((r.rz.abs() - 0.08 / (3.0 * w)).abs() < 1e-9, "{r:?}");
+        }
+        assert!(report.residuals[4].rz.abs() < 1e-9);
+        assert_eq!(report.horizontal.free_vertices, 4);
+        assert_eq!(report.vertical.result_quality, COMPASS_RESULT_EXACT);
+    }
+
+    fn solve_ffi(
+        graph: &Graph,
+        weight_z: &[f64],
+    ) -> (c_int, [Vec<f64>; 3], [SolveStats; 2], String) {
+        let fixed: Vec<c_int> = graph.fixed.iter().map(|&f| f as c_int).collect();
+        let from: Vec<c_int> = graph.from.iter().map(|&v| v as c_int).collect();
+        let to: Vec<c_int> = graph.to.iter().map(|&v| v as c_int).collect();
+        let (mut x, mut y, mut z) = (graph.x.clone(), graph.y.clone(), graph.z.clone());
+        let mut message = [0 as c_char; 64];
+        let mut stats = [SolveStats {
+            struct_size: size_of::<SolveStats>(),
+            ..SolveStats::default()
+        }; 2];
+        let options = SolveOptions {
+            tolerance: 1e-12,
+            error_message: message.as_mut_ptr(),
+            error_capacity: message.len() as c_int,
+            ..SolveOptions::default()
+        };
+        let [horizontal, vertical] = &mut stats;
+        let code = solve_graph_least_squares_2plus1(
+            graph.num_vertices() as c_int,
+            x.as_mut_ptr(),
+            y.as_mut_ptr(),
+            z.as_mut_ptr(),
+            fixed.as_ptr(),
+            graph.num_edges() as c_int,
+            from.as_ptr(),
+            to.as_ptr(),
+            graph.dx.as_ptr(),
+            graph.dy.as_ptr(),
+            graph.dz.as_ptr(),
+            graph.weight.as_ptr(),
+            weight_z.as_ptr(),
+            &options,
+            horizontal,
+            vertical,
+        );
+        let message = unsafe { CStr::from_ptr(message.as_ptr()) };
+        let message = message.to_str().unwrap().to_string();
+        (code, [x, y, z], stats, message)
+    }
+
+    #[test]
+    fn ffi_writes_back_all_three_axes() {
+        let (graph, weight_z) = square();
+        let expected = graph.solve_2plus1(&weight_z, 1000, 1e-12).unwrap();
+        let (code, [x, y, z], [horizontal, vertical], _) = solve_ffi(&graph, &weight_z);
+        assert_eq!(code, COMPASS_OK);
+        for (actual, expected) in
+            [x, y, z]
+                .iter()
+                .zip([&expected.horizontal.x, &expected.horizontal.y, &expected.z])
+        {
+            for (a, e) in actual.iter().zip(expected) {
+                assert!((a - e).abs() < 1e-9, "{a} vs {e}");
+            }
+        }
+        assert_eq!(horizontal.result_quality, COMPASS_RESULT_EXACT);
+        assert_eq!(vertical.result_quality, COMPASS_RESULT_EXACT);
+        assert_eq!(vertical.free_vertices, 4);
+        assert!(vertical.iterations_x > 0);
+        assert_eq!(vertical.iterations_y, 0);
+
+        // A free vertex held by zero vertical weights only is rejected before either solve.
+        let weight_z = [2.0, 2.0, 1.0, 1.0, 0.0];
+        let (code, [x, y, z], [horizontal, vertical], message) = solve_ffi(&graph, &weight_z);
+        assert_eq!(code, COMPASS_ERR_INVALID_ARGUMENT);
+        assert_eq!(
+            message,
+            "free vertex 4 has only edges of zero vertical weight"
+        );
+        assert_eq!(
+            (x, y, z),
+            (graph.x.clone(), graph.y.clone(), graph.z.clone())
+        );
+        assert_eq!(horizontal.result_quality, COMPASS_RESULT_UNTOUCHED);
+        assert_eq!(vertical.result_quality, COMPASS_RESULT_UNTOUCHED);
+        assert_eq!(
+            graph
+                .solve_2plus1(&weight_z, 1000, 1e-12)
+                .unwrap_err()
+                .message,
+            message
+        );
+        assert_eq!(
+            graph.solve_2plus1(&[1.0], 1000, 1e-12).unwrap_err().code,
+            COMPASS_ERR_INVALID_ARGUMENT
+        );
+    }
+}
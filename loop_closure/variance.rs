@@ -0,0 +1,557 @@
+//! Variance component estimation: per-group variance factors of the stated weights, to
+//! recalibrate the instrument models behind them.
+//!
+//! This is Förstner's iterative form of Helmert's estimator. An adjustment with the current
+//! weights gives, per group `k`, the weighted square sum of the residuals
+//! `Ω_k = sum(w |r|²)` and the redundancy `r_k = sum(1 - w aᵀ N⁻¹ a)` of its observations
+//! (one per axis and edge). `Ω_k / r_k` estimates the variance factor of the group, by which
+//! its weights are divided before the next round. At convergence every new estimate is 1
+//! and the accumulated factors are the corrections to the stated variances.
+//!
+//! The redundancies need the traces `tr(N⁻¹ N_k)` of the group parts `N_k` of the normal
+//! matrix. Systems of up to [`VarianceOptions::probes`] unknowns get them exactly, from unit
+//! vectors; larger ones use Hutchinson's estimator with as many Rademacher vectors drawn
+//! from a fixed seed, so results are reproducible.
+
+use crate::{
+    COMPASS_ERR_BUFFER_TOO_SMALL, COMPASS_ERR_INVALID_ARGUMENT, COMPASS_ERR_PANIC, Graph,
+    GraphContext, NormalEquations, PreconditionerOp, Solution, SolveError, inverse_diagonal,
+    solve_cg, write_column,
+};
+use nalgebra::DVector;
+use std::ffi::{c_double, c_int};
+
+/// Seed of the Rademacher probe vectors.
+const PROBE_SEED: u64 = 0x9e37_79b9_7f4a_7c15;
+
+/// How the observations are partitioned into variance components.
+#[derive(Debug, Clone, PartialEq)]
+pub enum VarianceGrouping {
+    /// One component per distinct id of the enabled edges, `group_id` holding the id of
+    /// each edge; both axes of an edge belong to its group.
+    Groups(Vec<i32>),
+    /// Two components, 0 for the X axis and 1 for the Y axis of every edge.
+    Axes,
+}
+
+/// Options of [`Graph::variance_components`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct VarianceOptions {
+    /// Maximum number of solver iterations of each adjustment.
+    pub iterations: usize,
+    /// Solver residual tolerance.
+    pub tolerance: f64,
+    /// Maximum number of estimation rounds.
+    pub max_rounds: usize,
+    /// The estimation has converged once every new variance factor is within this distance
+    /// of 1.
+    pub convergence: f64,
+    /// Components whose redundancy is below this are too poorly determined to estimate: their
+    /// factor is frozen at its current value.
+    pub min_redundancy: f64,
+    /// Number of probe vectors of the trace estimator, and size up to which the traces are
+    /// computed exactly.
+    pub probes: usize,
+}
+
+impl Default for VarianceOptions {
+    fn default() -> Self {
+        VarianceOptions {
+            iterations: 60_000,
+            tolerance: 1e-8,
+            max_rounds: 20,
+            convergence: 0.01,
+            min_redundancy: 1.0,
+            probes: 200,
+        }
+    }
+}
+
+/// Estimated variance factor of one component.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VarianceComponent {
+    /// Group id, or 0 = X and 1 = Y for [`VarianceGrouping::Axes`].
+    pub group: i32,
+    /// Number of observations (edge axes) in the component.
+    pub observations: usize,
+    /// Redundancy of the component in the last round.
+    pub redundancy: f64,
+    /// Factor by which the stated variances of the component must be multiplied, i.e. its
+    /// weights divided.
+    pub factor: f64,
+    /// Whether the factor was frozen, for lack of redundancy (see
+    /// [`VarianceOptions::min_redundancy`]) or because the residuals of the component vanish.
+    pub frozen: bool,
+}
+
+/// Result of [`Graph::variance_components`].
+#[derive(Debug, Clone)]
+pub struct VarianceEstimate {
+    /// One entry per component, by increasing group id.
+    pub components: Vec<VarianceComponent>,
+    /// Number of estimation rounds run.
+    pub rounds: usize,
+    /// Whether the factors converged within [`VarianceOptions::max_rounds`].
+    pub converged: bool,
+    /// Adjustment of the last round, with the weights rescaled by all but the last factor
+    /// update.
+    pub solution: Solution,
+}
+
+impl Graph {
+    /// Estimates the variance factor of each component of `grouping` by iterated
+    /// adjustments, leaving the graph untouched.
+    pub fn variance_components(
+        &self,
+        grouping: &VarianceGrouping,
+        options: &VarianceOptions,
+    ) -> Result<VarianceEstimate, SolveError> {
+        estimate(self, &[], grouping, options)
+    }
+}
+
+impl GraphContext {
+    /// [`Graph::variance_components`] over the enabled edges, grouped by the ids of
+    /// [`GraphContext::set_edge_groups`] or, if `by_axis`, by axis.
+    pub fn variance_components(
+        &self,
+        by_axis: bool,
+        options: &VarianceOptions,
+    ) -> Result<VarianceEstimate, SolveError> {
+        let grouping = match by_axis {
+            true => VarianceGrouping::Axes,
+            false => VarianceGrouping::Groups(
+                (0..self.graph.num_edges())
+                    .map(|e| self.group_id.get(e).copied().unwrap_or(0))
+                    .collect(),
+            ),
+        };
+        estimate(&self.graph, &self.edge_enabled, &grouping, options)
+    }
+}
+
+fn estimate(
+    graph: &Graph,
+    enabled: &[bool],
+    grouping: &VarianceGrouping,
+    options: &VarianceOptions,
+) -> Result<VarianceEstimate, SolveError> {
+    let edges: Vec<usize> = (0..graph.num_edges())
+        .filter(|&e| enabled.get(e).copied().unwrap_or(true))
+        .collect();
+    // Component ids, and the trace group of each edge: its component, or a single group
+    // spanning both axis components.
+    let (ids, group_of) = match grouping {
+        VarianceGrouping::Groups(group_id) => {
+            if group_id.len() != graph.num_edges() {
+                return Err(SolveError {
+                    code: COMPASS_ERR_INVALID_ARGUMENT,
+                    message: format!(
+                        "{} group ids for {} edges",
+                        group_id.len(),
+                        graph.num_edges()
+                    ),
+                });
+            }
+            let mut ids: Vec<i32> = edges.iter().map(|&e| group_id[e]).collect();
+            ids.sort_unstable();
+            ids.dedup();
+            let group_of = (0..graph.num_edges())
+                .map(|e| ids.binary_search(&group_id[e]).unwrap_or(0))
+                .collect();
+            (ids, group_of)
+        }
+        VarianceGrouping::Axes => (vec![0, 1], vec![0; graph.num_edges()]),
+    };
+    let by_axis = *grouping == VarianceGrouping::Axes;
+    let trace_groups = if by_axis { 1 } else { ids.len() };
+
+    let mut observations = vec![0; ids.len()];
+    for &e in &edges {
+        match by_axis {
+            true => observations.iter_mut().for_each(|o| *o += 1),
+            false => observations[group_of[e]] += 2,
+        }
+    }
+    let mut factor = vec![1.0; ids.len()];
+    let mut frozen = vec![false; ids.len()];
+    let mut redundancy = vec![0.0; ids.len()];
+    let mut scaled = graph.clone();
+    let mut rounds = 0;
+    let mut converged = false;
+    let mut solution = Solution::default();
+    while rounds < options.max_rounds && !converged {
+        rounds += 1;
+        // Scaling the weights of a whole axis does not change its adjustment, so the axis
+        // factors only enter the square sums.
+        if !by_axis {
+            for &e in &edges {
+                scaled.weight[e] = graph.weight[e] / factor[group_of[e]];
+            }
+        }
+        let system = scaled.normal_equations(enabled)?;
+        solution = scaled.solve_system(
+            &system,
+            &graph.x,
+            &graph.y,
+            options.iterations,
+            options.tolerance.into(),
+            Default::default(),
+            &|| false,
+        )?;
+        let traces = traces(&system, &scaled, &edges, &group_of, trace_groups, options);
+
+        let mut squares = vec![0.0; ids.len()];
+        for &e in &edges {
+            let (u, v, w) = (graph.from[e], graph.to[e], scaled.weight[e]);
+            let rx = solution.x[v] - solution.x[u] - graph.dx[e];
+            let ry = solution.y[v] - solution.y[u] - graph.dy[e];
+            match by_axis {
+                true => {
+                    squares[0] += w * rx * rx / factor[0];
+                    squares[1] += w * ry * ry / factor[1];
+                }
+                false => squares[group_of[e]] += w * (rx * rx + ry * ry),
+            }
+        }
+
+        converged = true;
+        for c in 0..ids.len() {
+            redundancy[c] = match by_axis {
+                true => observations[c] as f64 - traces[0],
+                false => observations[c] as f64 - 2.0 * traces[c],
+            };
+            if frozen[c] {
+                continue;
+            }
+            if redundancy[c] < options.min_redundancy {
+                frozen[c] = true;
+                continue;
+            }
+            let estimate = squares[c] / redundancy[c];
+            if !(estimate > 0.0 && estimate.is_finite()) {
+                frozen[c] = true;
+                continue;
+            }
+            factor[c] *= estimate;
+            converged &= (estimate - 1.0).abs() <= options.convergence;
+        }
+    }
+
+    let components = (0..ids.len())
+        .map(|c| VarianceComponent {
+            group: ids[c],
+            observations: observations[c],
+            redundancy: redundancy[c],
+            factor: factor[c],
+            frozen: frozen[c],
+        })
+        .collect();
+    Ok(VarianceEstimate {
+        components,
+        rounds,
+        converged,
+        solution,
+    })
+}
+
+/// `tr(N⁻¹ N_g)` for each of the `groups` edge groups, `N_g` being the part of the normal
+/// matrix assembled from the edges of group `g` (one axis).
+fn traces(
+    system: &NormalEquations,
+    graph: &Graph,
+    edges: &[usize],
+    group_of: &[usize],
+    groups: usize,
+    options: &VarianceOptions,
+) -> Vec<f64> {
+    let n = system.size();
+    let mut traces = vec![0.0; groups];
+    if n == 0 {
+        return traces;
+    }
+    let exact = n <= options.probes;
+    let probes = if exact { n } else { options.probes.max(1) };
+    let jacobi = PreconditionerOp::Jacobi(inverse_diagonal(&system.matrix));
+    let mut state = PROBE_SEED;
+    // a·s for the edge `e`, where fixed vertices have no unknown.
+    let difference = |s: &DVector<f64>, e: usize| {
+        let value = |v: usize| system.mapping[v].map_or(0.0, |i| s[i]);
+        value(graph.to[e]) - value(graph.from[e])
+    };
+    for k in 0..probes {
+        let z = match exact {
+            true => DVector::from_fn(n, |i, _| if i == k { 1.0 } else { 0.0 }),
+            false => DVector::from_fn(n, |_, _| {
+                // xorshift64
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                if state & 1 == 0 { 1.0 } else { -1.0 }
+            }),
+        };
+        let zero = DVector::zeros(n);
+        let (s, _) = solve_cg(
+            &system.matrix,
+            &z,
+            &zero,
+            Some(&jacobi),
+            &system.null_space,
+            options.iterations,
+            options.tolerance.into(),
+            &|| false,
+        );
+        for &e in edges {
+            traces[group_of[e]] += graph.weight[e] * difference(&s, e) * difference(&z, e);
+        }
+    }
+    if !exact {
+        // The traces of all groups add up to the rank of the matrix, n less one per free
+        // network; rescaling to it removes the common part of the sampling error.
+        let rank = n - system.null_space.dimension();
+        let total: f64 = traces.iter().sum();
+        for trace in &mut traces {
+            *trace *= match total > 0.0 {
+                true => rank as f64 / total,
+                false => 1.0 / probes as f64,
+            };
+        }
+    }
+    traces
+}
+
+/// Estimates the variance factors of the graph behind `handle`, see
+/// [`GraphContext::variance_components`], and copies them into parallel caller buffers of
+/// `capacity` elements by increasing group id. `by_axis` != 0 estimates one factor per axis
+/// (group 0 = X, 1 = Y) instead of one per edge group. Any output pointer may be null to
+/// skip that column; `out_frozen` receives 1 for the frozen factors. The other options are
+/// the [`VarianceOptions`] defaults.
+///
+/// # Returns
+///
+/// * The number of components written.
+/// * [`COMPASS_ERR_BUFFER_TOO_SMALL`] if `capacity` is below the number of components, 2 by
+///   axis or [`crate::graph_group_count`] otherwise; nothing is written.
+/// * [`COMPASS_ERR_INVALID_ARGUMENT`] for a null handle, or the error of a failed solve.
+#[unsafe(no_mangle)]
+#[allow(clippy::too_many_arguments)]
+pub extern "C" fn graph_variance_components(
+    handle: *const GraphContext,
+    by_axis: c_int,
+    iterations: c_int,
+    tolerance: c_double,
+    capacity: c_int,
+    out_group: *mut c_int,
+    out_factor: *mut c_double,
+    out_redundancy: *mut c_double,
+    out_frozen: *mut c_int,
+) -> c_int {
+    let Some(ctx) = (unsafe { handle.as_ref() }) else {
+        return COMPASS_ERR_INVALID_ARGUMENT;
+    };
+    let result = std::panic::catch_unwind(|| {
+        let options = VarianceOptions {
+            iterations: iterations.max(0) as usize,
+            tolerance,
+            ..Default::default()
+        };
+        let estimate = match ctx.variance_components(by_axis != 0, &options) {
+            Ok(estimate) => estimate,
+            Err(err) => return err.code,
+        };
+        let components = &estimate.components;
+        if (capacity.max(0) as usize) < components.len() {
+            return COMPASS_ERR_BUFFER_TOO_SMALL;
+        }
+        write_column(out_group, components, |c| c.group);
+        write_column(out_factor, components, |c| c.factor);
+        write_column(out_redundancy, components, |c| c.redundancy);
+        write_column(out_frozen, components, |c| c.frozen as c_int);
+        components.len() as c_int
+    });
+
+    result.unwrap_or_else(|_| {
+        eprintln!("Panic caught in graph_variance_components");
+        COMPASS_ERR_PANIC
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Standard normal deviates from a fixed seed (xorshift64 and Box-Muller).
+    fn normals(count: usize) -> Vec<f64> {
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let mut uniform = || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state >> 11) as f64 / (1u64 << 53) as f64
+        };
+        (0..count)
+            .map(|_| {
+                let (u, v) = (1.0 - uniform(), uniform());
+                (-2.0 * u.ln()).sqrt() * (std::f64::consts::TAU * v).cos()
+            })
+            .collect()
+    }
+
+    /// A `side` x `side` grid fixed at its corner, every shot stated at 5 cm. Shots along X
+    /// (group 0) carry `sigma_x` of noise on each axis, shots along Y (group 1) `sigma_y`.
+    fn noisy_grid(side: usize, sigma_x: f64, sigma_y: f64) -> (Graph, Vec<i32>) {
+        let noise = normals(4 * side * side);
+        let mut graph = Graph::default();
+        let mut groups = Vec::new();
+        for i in 0..side * side {
+            let (row, col) = (i / side, i % side);
+            graph.add_vertex(10.0 * col as f64, 10.0 * row as f64, 0.0, i == 0);
+        }
+        let weight = 1.0 / 0.05f64.powi(2);
+        for i in 0..side * side {
+            let (row, col) = (i / side, i % side);
+            let (a, b, c, d) = (
+                noise[4 * i],
+                noise[4 * i + 1],
+                noise[4 * i + 2],
+                noise[4 * i + 3],
+            );
+            if col + 1 < side {
+                graph.add_edge(i, i + 1, 10.0 + sigma_x * a, sigma_x * b, 0.0, weight);
+                groups.push(0);
+            }
+            if row + 1 < side {
+                graph.add_edge(i, i + side, sigma_y * c, 10.0 + sigma_y * d, 0.0, weight);
+                groups.push(1);
+            }
+        }
+        (graph, groups)
+    }
+
+    #[test]
+    fn recovers_the_variance_factors_of_two_groups() {
+        let (graph, groups) = noisy_grid(12, 0.05, 0.1);
+        let grouping = VarianceGrouping::Groups(groups);
+        let estimate = graph
+            .variance_components(&grouping, &VarianceOptions::default())
+            .unwrap();
+        assert!(estimate.converged);
+        let [tape, compass] = &estimate.components[..] else {
+            panic!("{:?}", estimate.components);
+        };
+        assert_eq!((tape.group, compass.group), (0, 1));
+        assert_eq!((tape.observations, compass.observations), (264, 264));
+        assert!((tape.factor - 1.0).abs() < 0.3, "{tape:?}");
+        assert!((compass.factor - 4.0).abs() < 1.2, "{compass:?}");
+        // The redundancies add up to observations minus unknowns.
+        let redundancy = tape.redundancy + compass.redundancy;
+        assert!((redundancy - (528.0 - 286.0)).abs() < 1e-6, "{redundancy}");
+
+        // Hutchinson probes estimate the same factors.
+        let sampled = VarianceOptions {
+            probes: 60,
+            ..VarianceOptions::default()
+        };
+        let sampled = graph.variance_components(&grouping, &sampled).unwrap();
+        for (exact, sampled) in estimate.components.iter().zip(&sampled.components) {
+            assert!(
+                (sampled.factor / exact.factor - 1.0).abs() < 0.15,
+                "{sampled:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn axis_components_and_frozen_groups() {
+        let (graph, _) = noisy_grid(10, 0.1, 0.1);
+        let estimate = graph
+            .variance_components(&VarianceGrouping::Axes, &VarianceOptions::default())
+            .unwrap();
+        let factors: Vec<f64> = estimate.components.iter().map(|c| c.factor).collect();
+        assert_eq!(estimate.components.len(), 2);
+        assert!(factors.iter().all(|f| (f - 4.0).abs() < 1.2), "{factors:?}");
+
+        // A spur shot has no redundancy: its group keeps its factor and is reported frozen.
+        let (mut graph, mut groups) = noisy_grid(6, 0.05, 0.05);
+        let spur = graph.add_vertex(-10.0, 0.0, 0.0, false);
+        graph.add_edge(0, spur, -10.3, 0.2, 0.0, 400.0);
+        groups.push(7);
+        let estimate = graph
+            .variance_components(
+                &VarianceGrouping::Groups(groups),
+                &VarianceOptions::default(),
+            )
+            .unwrap();
+        let spur = &estimate.components[2];
+        assert_eq!(spur.group, 7);
+        assert!(spur.frozen && spur.factor == 1.0, "{spur:?}");
+        assert!(spur.redundancy.abs() < 1e-6);
+        assert!(!estimate.components[0].frozen);
+
+        let wrong = VarianceGrouping::Groups(vec![0; 3]);
+        let err = graph
+            .variance_components(&wrong, &VarianceOptions::default())
+            .unwrap_err();
+        assert_eq!(err.code, COMPASS_ERR_INVALID_ARGUMENT);
+    }
+
+    #[test]
+    fn ffi_writes_the_factors_by_group() {
+        let (graph, groups) = noisy_grid(8, 0.05, 0.1);
+        let handle = GraphContext::into_raw(graph);
+        let ctx = unsafe { &mut *handle };
+        ctx.set_edge_groups(groups).unwrap();
+        let expected = ctx
+            .variance_components(false, &VarianceOptions::default())
+            .unwrap();
+
+        let (mut group, mut factor, mut frozen) = ([0; 2], [0.0; 2], [0; 2]);
+        let call = |capacity, group: *mut c_int, factor: *mut c_double, frozen: *mut c_int| {
+            graph_variance_components(
+                handle,
+                0,
+                60_000,
+                1e-8,
+                capacity,
+                group,
+                factor,
+                std::ptr::null_mut(),
+                frozen,
+            )
+        };
+        let null = (
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+        );
+        assert_eq!(
+            call(1, null.0, null.1, null.2),
+            COMPASS_ERR_BUFFER_TOO_SMALL
+        );
+        let count = call(
+            2,
+            group.as_mut_ptr(),
+            factor.as_mut_ptr(),
+            frozen.as_mut_ptr(),
+        );
+        assert_eq!(count, 2);
+        assert_eq!(group, [0, 1]);
+        assert_eq!(factor[1], expected.components[1].factor);
+        assert_eq!(frozen, [0, 0]);
+        assert_eq!(
+            graph_variance_components(
+                std::ptr::null(),
+                0,
+                10,
+                1e-8,
+                2,
+                null.0,
+                null.1,
+                null.1,
+                null.2
+            ),
+            COMPASS_ERR_INVALID_ARGUMENT
+        );
+        crate::graph_free(handle);
+    }
+}
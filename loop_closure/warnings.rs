@@ -0,0 +1,409 @@
+//! Conditions that do not fail a solve but must reach the user: edges that add nothing or
+//! repeat another, networks placed by the datum instead of an anchor, vertices left where
+//! they were, and coordinates that are not the least squares solution.
+//!
+//! [`Solution::warnings`] lists them, and [`SolveStats::warnings`] counts them.
+//! [`compass_get_warnings`] copies them as [`WarningRecord`]s, from a handle or from the last
+//! [`crate::solve_graph_least_squares_ex`] call of the thread, which alone reports the
+//! reseeded and clamped vertices of its options.
+//!
+//! The two axes are solved on two threads. Each hands its convergence back through its join
+//! handle, and the warnings are drawn from both once joined, so no lock is shared and the X
+//! axis always comes first. Warnings are ordered by kind, then by index.
+
+use crate::{
+    COMPASS_ERR_BUFFER_TOO_SMALL, COMPASS_ERR_INVALID_ARGUMENT, COMPASS_OK, Convergence,
+    GraphContext, GraphView, NormalEquations, Solution, SolveStats,
+};
+use std::cell::RefCell;
+use std::ffi::{c_double, c_int};
+use std::slice;
+
+/// [`SolveWarning::ZeroWeightEdge`]: index = edge.
+pub const COMPASS_WARNING_ZERO_WEIGHT_EDGE: c_int = 1;
+/// [`SolveWarning::DuplicateEdge`]: index = edge, value = the earlier edge.
+pub const COMPASS_WARNING_DUPLICATE_EDGE: c_int = 2;
+/// [`SolveWarning::FreeNetwork`]: index = lowest vertex, value = number of vertices.
+pub const COMPASS_WARNING_FREE_NETWORK: c_int = 3;
+/// [`SolveWarning::IsolatedVertex`]: index = vertex.
+pub const COMPASS_WARNING_ISOLATED_VERTEX: c_int = 4;
+/// [`SolveWarning::ReseededVertex`]: index = vertex.
+pub const COMPASS_WARNING_RESEEDED_VERTEX: c_int = 5;
+/// [`SolveWarning::NotConverged`]: index = axis, 0 = X, 1 = Y; value = residual norm.
+pub const COMPASS_WARNING_NOT_CONVERGED: c_int = 6;
+/// [`SolveWarning::ClampedVertex`]: index = vertex, value = distance moved by the clamp.
+pub const COMPASS_WARNING_CLAMPED_VERTEX: c_int = 7;
+
+/// A condition worth telling the user about, see the [module documentation](self).
+#[derive(Debug, Clone, PartialEq)]
+pub enum SolveWarning {
+    /// An enabled edge of zero weight, which adds nothing to the adjustment.
+    ZeroWeightEdge { edge: usize },
+    /// An enabled edge with the same ends, observations and weight as an earlier one, e.g.
+    /// a survey imported twice: the observation counts twice.
+    DuplicateEdge { edge: usize, earlier: usize },
+    /// A connected component without anchor, held by the datum of its initial coordinates.
+    FreeNetwork { vertex: usize, vertices: usize },
+    /// A free vertex without usable edge, left at its initial coordinates, see
+    /// [`crate::SolveOptions::keep_isolated`].
+    IsolatedVertex { vertex: usize },
+    /// A free vertex whose initial guess was discarded, see
+    /// [`crate::SolveOptions::guess_margin`].
+    ReseededVertex { vertex: usize },
+    /// An axis stopped before reaching its tolerance, so its coordinates are approximate.
+    NotConverged { axis: usize, residual_norm: f64 },
+    /// A free vertex moved onto the bounding box, see
+    /// [`crate::SolveOptions::clamp_to_bounds`].
+    ClampedVertex { vertex: usize, distance: f64 },
+}
+
+/// A [`SolveWarning`] for C callers.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct WarningRecord {
+    /// One of the `COMPASS_WARNING_*` codes.
+    pub code: c_int,
+    /// Edge, vertex or axis, as documented for the code.
+    pub index: c_int,
+    /// Value documented for the code, 0 when it has none.
+    pub value: c_double,
+}
+
+impl SolveWarning {
+    /// `COMPASS_WARNING_*` code of the warning.
+    pub fn code(&self) -> c_int {
+        match self {
+            SolveWarning::ZeroWeightEdge { .. } => COMPASS_WARNING_ZERO_WEIGHT_EDGE,
+            SolveWarning::DuplicateEdge { .. } => COMPASS_WARNING_DUPLICATE_EDGE,
+            SolveWarning::FreeNetwork { .. } => COMPASS_WARNING_FREE_NETWORK,
+            SolveWarning::IsolatedVertex { .. } => COMPASS_WARNING_ISOLATED_VERTEX,
+            SolveWarning::ReseededVertex { .. } => COMPASS_WARNING_RESEEDED_VERTEX,
+            SolveWarning::NotConverged { .. } => COMPASS_WARNING_NOT_CONVERGED,
+            SolveWarning::ClampedVertex { .. } => COMPASS_WARNING_CLAMPED_VERTEX,
+        }
+    }
+
+    /// The warning encoded for C callers.
+    pub fn record(&self) -> WarningRecord {
+        let (index, value) = match *self {
+            SolveWarning::ZeroWeightEdge { edge } => (edge, 0.0),
+            SolveWarning::DuplicateEdge { edge, earlier } => (edge, earlier as f64),
+            SolveWarning::FreeNetwork { vertex, vertices } => (vertex, vertices as f64),
+            SolveWarning::IsolatedVertex { vertex } | SolveWarning::ReseededVertex { vertex } => {
+                (vertex, 0.0)
+            }
+            SolveWarning::NotConverged {
+                axis,
+                residual_norm,
+            } => (axis, residual_norm),
+            SolveWarning::ClampedVertex { vertex, distance } => (vertex, distance),
+        };
+        WarningRecord {
+            code: self.code(),
+            index: index as c_int,
+            value,
+        }
+    }
+}
+
+/// Zero-weight and duplicate edges among the enabled edges of `graph`, given in its
+/// [canonical order](GraphView::canonical_order), under which duplicates are adjacent.
+pub(crate) fn edge_warnings(graph: &GraphView, order: &[usize]) -> Vec<SolveWarning> {
+    let key = |e: usize| {
+        let bits = [graph.weight[e], graph.dx[e], graph.dy[e]].map(f64::to_bits);
+        (graph.from[e], graph.to[e], bits)
+    };
+    let mut warnings: Vec<SolveWarning> = (0..graph.from.len())
+        .filter(|&e| graph.is_enabled(e) && graph.weight[e] == 0.0)
+        .map(|edge| SolveWarning::ZeroWeightEdge { edge })
+        .collect();
+    let weighted: Vec<usize> = (order.iter().copied())
+        .filter(|&e| graph.is_enabled(e) && graph.weight[e] != 0.0)
+        .collect();
+    for group in weighted.chunk_by(|&a, &b| key(a) == key(b)) {
+        let earlier = group.iter().copied().min().unwrap_or_default();
+        warnings.extend(
+            (group.iter().copied())
+                .filter(|&edge| edge != earlier)
+                .map(|edge| SolveWarning::DuplicateEdge { edge, earlier }),
+        );
+    }
+    warnings
+}
+
+/// Warnings of a solve of `system`: its edge warnings, its free networks, the vertices for
+/// which `is_isolated` holds and the axes that `convergence` leaves short of the tolerance,
+/// in the order of the [module documentation](self).
+pub(crate) fn solve_warnings(
+    system: &NormalEquations,
+    is_isolated: &dyn Fn(usize) -> bool,
+    convergence: Option<&Convergence>,
+) -> Vec<SolveWarning> {
+    let mut warnings = system.edge_warnings.clone();
+    let mut vertex_of_row = vec![0; system.size()];
+    for (vertex, row) in system.mapping.iter().enumerate() {
+        if let Some(row) = *row {
+            vertex_of_row[row] = vertex;
+        }
+    }
+    warnings.extend(system.null_space.components.iter().map(|rows| {
+        SolveWarning::FreeNetwork {
+            vertex: rows
+                .iter()
+                .map(|&row| vertex_of_row[row])
+                .min()
+                .unwrap_or(0),
+            vertices: rows.len(),
+        }
+    }));
+    warnings.extend(
+        (0..system.mapping.len())
+            .filter(|&v| is_isolated(v))
+            .map(|vertex| SolveWarning::IsolatedVertex { vertex }),
+    );
+    if let Some(convergence) = convergence.filter(|c| !c.cancelled) {
+        warnings.extend(
+            (0..2)
+                .filter(|&axis| !convergence.axis_converged[axis])
+                .map(|axis| SolveWarning::NotConverged {
+                    axis,
+                    residual_norm: convergence.axis_residual_norm[axis],
+                }),
+        );
+    }
+    sort(&mut warnings);
+    warnings
+}
+
+/// Orders `warnings` by kind, then by index.
+pub(crate) fn sort(warnings: &mut [SolveWarning]) {
+    warnings.sort_by_key(|w| {
+        let record = w.record();
+        (record.code, record.index)
+    });
+}
+
+impl Solution {
+    /// Warnings of the solve, see the [module documentation](crate::warnings).
+    pub fn warnings(&self) -> &[SolveWarning] {
+        &self.warnings
+    }
+}
+
+impl SolveStats {
+    /// Sets [`SolveStats::warnings`] to the number of `warnings`.
+    pub(crate) fn count_warnings(&mut self, warnings: &[SolveWarning]) {
+        self.warnings = warnings.len().min(c_int::MAX as usize) as c_int;
+    }
+}
+
+thread_local! {
+    /// Warnings of the last [`crate::solve_graph_least_squares_ex`] call of the thread.
+    static LAST_WARNINGS: RefCell<Vec<SolveWarning>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Keeps `warnings` as those of the last array solve of the thread.
+pub(crate) fn set_last_warnings(warnings: Vec<SolveWarning>) {
+    LAST_WARNINGS.with(|last| *last.borrow_mut() = warnings);
+}
+
+/// Copies the warnings of the last solve of the graph behind `handle`, or with a null handle
+/// those of the last [`crate::solve_graph_least_squares_ex`] call of the calling thread, into
+/// a caller buffer of `cap` records. The number of warnings is written to `out_count` either
+/// way, so that a first call with `cap` 0 sizes the buffer; it is 0 before any solve.
+///
+/// # Returns
+///
+/// * [`COMPASS_OK`].
+/// * [`COMPASS_ERR_BUFFER_TOO_SMALL`] if `cap` is below the number of warnings; nothing is
+///   written to `out_buf`.
+/// * [`COMPASS_ERR_INVALID_ARGUMENT`] for a null `out_count`, or a null `out_buf` with a
+///   positive `cap`.
+#[unsafe(no_mangle)]
+pub extern "C" fn compass_get_warnings(
+    handle: *const GraphContext,
+    out_buf: *mut WarningRecord,
+    cap: c_int,
+    out_count: *mut c_int,
+) -> c_int {
+    if out_count.is_null() || (out_buf.is_null() && cap > 0) {
+        return COMPASS_ERR_INVALID_ARGUMENT;
+    }
+    let records: Vec<WarningRecord> = match unsafe { handle.as_ref() } {
+        Some(ctx) => ctx.solution().map_or_else(Vec::new, |s| {
+            s.warnings.iter().map(|w| w.record()).collect()
+        }),
+        None => LAST_WARNINGS.with(|last| last.borrow().iter().map(|w| w.record()).collect()),
+    };
+    unsafe { *out_count = records.len().min(c_int::MAX as usize) as c_int };
+    if (cap.max(0) as usize) < records.len() {
+        return COMPASS_ERR_BUFFER_TOO_SMALL;
+    }
+    if !records.is_empty() {
+        // Safety: The caller guarantees a buffer of `cap` records.
+        unsafe { slice::from_raw_parts_mut(out_buf, records.len()) }.copy_from_slice(&records);
+    }
+    COMPASS_OK
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        COMPASS_WARN_CLAMPED, Graph, SolveOptions, graph_free, graph_solve,
+        solve_graph_least_squares_ex,
+    };
+
+    /// Copies the warnings behind `handle`, null for the last array solve of the thread.
+    fn records(handle: *const GraphContext) -> Vec<WarningRecord> {
+        let mut count = -1;
+        let code = compass_get_warnings(handle, std::ptr::null_mut(), 0, &mut count);
+        let mut records = vec![WarningRecord::default(); count as usize];
+        if count > 0 {
+            assert_eq!(code, COMPASS_ERR_BUFFER_TOO_SMALL);
+        }
+        let code = compass_get_warnings(handle, records.as_mut_ptr(), count, &mut count);
+        assert_eq!(code, COMPASS_OK);
+        records
+    }
+
+    #[test]
+    fn solutions_list_the_edges_and_networks_worth_a_look() {
+        // Shot 0-1 was imported twice and the closing shot 2-0 carries no weight; the pair
+        // 3-4 hangs from no anchor and station 5 from no shot.
+        let mut graph = Graph::default();
+        for v in 0..6 {
+            graph.add_vertex(v as f64, 0.0, 0.0, v == 0);
+        }
+        graph.add_edge(0, 1, 10.0, 0.0, 0.0, 1.0);
+        graph.add_edge(0, 1, 10.0, 0.0, 0.0, 1.0);
+        graph.add_edge(1, 2, 0.0, 10.0, 0.0, 1.0);
+        graph.add_edge(2, 0, -10.0, -10.0, 0.0, 0.0);
+        graph.add_edge(3, 4, 5.0, 0.0, 0.0, 1.0);
+        let solution = graph.solve(1000, 1e-12).unwrap();
+        assert_eq!(
+            solution.warnings(),
+            [
+                SolveWarning::ZeroWeightEdge { edge: 3 },
+                SolveWarning::DuplicateEdge {
+                    edge: 1,
+                    earlier: 0
+                },
+                SolveWarning::FreeNetwork {
+                    vertex: 3,
+                    vertices: 2
+                },
+                SolveWarning::IsolatedVertex { vertex: 5 },
+            ]
+        );
+        assert_eq!(solution.stats.warnings, 4);
+        let record = solution.warnings[1].record();
+        assert_eq!((record.code, record.index, record.value), (2, 1, 0.0));
+
+        // A shot of another weight is a second observation, not a copy.
+        graph.weight[1] = 2.0;
+        let solution = graph.solve(1000, 1e-12).unwrap();
+        assert_eq!(solution.warnings().len(), 3);
+
+        // One iteration leaves both axes of a misclosed loop short of the tolerance.
+        let mut graph = Graph::default();
+        for v in 0..4 {
+            graph.add_vertex(0.0, 0.0, 0.0, v == 0);
+        }
+        graph.add_edge(0, 1, 10.0, 0.0, 0.0, 1.0);
+        graph.add_edge(1, 2, 0.0, 10.0, 0.0, 1.0);
+        graph.add_edge(2, 3, -10.0, 0.0, 0.0, 1.0);
+        graph.add_edge(3, 0, 1.0, -9.0, 0.0, 1.0);
+        let solution = graph.solve(1, 1e-14).unwrap();
+        let axes: Vec<usize> = (solution.warnings.iter())
+            .map(|w| match *w {
+                SolveWarning::NotConverged {
+                    axis,
+                    residual_norm,
+                } => {
+                    assert!(residual_norm > 0.0);
+                    axis
+                }
+                _ => panic!("unexpected {w:?}"),
+            })
+            .collect();
+        assert_eq!(axes, [0, 1]);
+        assert!(graph.solve(1000, 1e-12).unwrap().warnings.is_empty());
+    }
+
+    #[test]
+    fn ffi_copies_the_warnings_of_the_last_solve() {
+        // Station 1 comes without a guess, and a shot in the wrong unit flings station 2 out
+        // of the box.
+        let x = [0.0, f64::NAN, 20.0];
+        let (mut xs, mut ys) = (x, [0.0; 3]);
+        let fixed = [1, 0, 0];
+        let (from, to) = ([0, 1], [1, 2]);
+        let (dx, dy, weight) = ([10.0, 1e15], [0.0; 2], [1.0; 2]);
+        let options = SolveOptions {
+            tolerance: 1e-12,
+            clamp_to_bounds: 1,
+            bounds_min_x: -100.0,
+            bounds_min_y: -100.0,
+            bounds_max_x: 100.0,
+            bounds_max_y: 100.0,
+            ..SolveOptions::default()
+        };
+        let mut stats = SolveStats {
+            struct_size: size_of::<SolveStats>(),
+            ..SolveStats::default()
+        };
+        let code = solve_graph_least_squares_ex(
+            3,
+            xs.as_mut_ptr(),
+            ys.as_mut_ptr(),
+            fixed.as_ptr(),
+            2,
+            from.as_ptr(),
+            to.as_ptr(),
+            dx.as_ptr(),
+            dy.as_ptr(),
+            weight.as_ptr(),
+            &options,
+            &mut stats,
+        );
+        assert_eq!((code, stats.warnings), (COMPASS_WARN_CLAMPED, 2));
+        let last = records(std::ptr::null());
+        assert_eq!(last[0], SolveWarning::ReseededVertex { vertex: 1 }.record());
+        assert_eq!(
+            (last[1].code, last[1].index),
+            (COMPASS_WARNING_CLAMPED_VERTEX, 2)
+        );
+        assert!(last[1].value > 1e14);
+
+        // A handle reports those of its own last solve, none before the first.
+        let mut graph = Graph::default();
+        graph.add_vertex(0.0, 0.0, 0.0, true);
+        graph.add_vertex(10.0, 0.0, 0.0, false);
+        graph.add_edge(0, 1, 10.0, 0.0, 0.0, 1.0);
+        graph.add_edge(0, 1, 10.0, 0.0, 0.0, 1.0);
+        let handle = GraphContext::into_raw(graph);
+        assert!(records(handle).is_empty());
+        let code = graph_solve(handle, &SolveOptions::default(), &mut stats);
+        assert_eq!((code, stats.warnings), (COMPASS_OK, 1));
+        let duplicate = SolveWarning::DuplicateEdge {
+            edge: 1,
+            earlier: 0,
+        };
+        assert_eq!(records(handle), [duplicate.record()]);
+        // The last array solve of the thread is left alone.
+        assert_eq!(records(std::ptr::null()).len(), 2);
+
+        let mut count = 0;
+        let mut one = [WarningRecord::default()];
+        assert_eq!(
+            compass_get_warnings(handle, one.as_mut_ptr(), 0, std::ptr::null_mut()),
+            COMPASS_ERR_INVALID_ARGUMENT
+        );
+        assert_eq!(
+            compass_get_warnings(handle, std::ptr::null_mut(), 1, &mut count),
+            COMPASS_ERR_INVALID_ARGUMENT
+        );
+        graph_free(handle);
+    }
+}
@@ -0,0 +1,149 @@
+//! WebAssembly bindings (wasm-bindgen) for the browser build of the viewer, targeting
+//! `wasm32-unknown-unknown`:
+//!
+//! ```text
+//! wasm-pack build --target web -- --features wasm
+//! ```
+//!
+//! The X and Y axes are solved one after the other on this target, since it cannot spawn
+//! threads. SIMD needs no code change: build with `RUSTFLAGS="-C target-feature=+simd128"`.
+//!
+//! wasm32 builds use the abort panic strategy, so `catch_unwind` cannot recover from a panic
+//! there. Inputs are therefore validated up front and reported as thrown `Error`s; a panic that
+//! still slips through is logged to the console by the panic hook and surfaces in JS as a
+//! `RuntimeError` from the wasm trap.
+
+use crate::{COMPASS_OK, GraphView, SolveOptions, SolveStats, solve_view};
+use std::ffi::c_int;
+use wasm_bindgen::prelude::*;
+
+/// Statistics returned by `solveGraph`.
+#[wasm_bindgen(js_name = SolveStats)]
+#[derive(Debug, Clone, Copy)]
+pub struct WasmSolveStats {
+    /// Number of vertices that were unknowns of the system.
+    #[wasm_bindgen(js_name = freeVertices)]
+    pub free_vertices: i32,
+    /// Number of passive vertices (always 0 here).
+    #[wasm_bindgen(js_name = passiveVertices)]
+    pub passive_vertices: i32,
+}
+
+impl From<SolveStats> for WasmSolveStats {
+    fn from(stats: SolveStats) -> Self {
+        WasmSolveStats {
+            free_vertices: stats.free_vertices,
+            passive_vertices: stats.passive_vertices,
+        }
+    }
+}
+
+#[wasm_bindgen(start)]
+fn start() {
+    console_error_panic_hook::set_once();
+}
+
+/// Solves the horizontal adjustment. Arguments mirror `solve_graph_least_squares`: `x` and `y`
+/// are `Float64Array`s holding the initial guess, overwritten with the adjusted coordinates;
+/// the other arrays are `Int32Array`s and `Float64Array`s. `iterations` defaults to 60000 and
+/// `tolerance` to 1e-8.
+#[wasm_bindgen(js_name = solveGraph)]
+#[allow(clippy::too_many_arguments)]
+pub fn solve_graph(
+    x: &mut [f64],
+    y: &mut [f64],
+    fixed: &[i32],
+    from: &[i32],
+    to: &[i32],
+    dx: &[f64],
+    dy: &[f64],
+    weight: &[f64],
+    iterations: Option<u32>,
+    tolerance: Option<f64>,
+) -> Result<WasmSolveStats, JsError> {
+    let n = x.len();
+    let m = from.len();
+    if y.len() != n || fixed.len() != n {
+        return Err(JsError::new("x, y and fixed must have the same length"));
+    }
+    if [to.len(), dx.len(), dy.len(), weight.len()]
+        .iter()
+        .any(|&len| len != m)
+    {
+        return Err(JsError::new(
+            "from, to, dx, dy and weight must have the same length",
+        ));
+    }
+    if let Some(e) = (0..m).find(|&e| [from[e], to[e]].iter().any(|&v| v < 0 || v as usize >= n)) {
+        return Err(JsError::new(&format!(
+            "edge {e} references a missing vertex"
+        )));
+    }
+
+    let graph = GraphView {
+        fixed,
+        from,
+        to,
+        dx,
+        dy,
+        weight,
+        enabled: None,
+    };
+    let options = SolveOptions {
+        iterations: iterations.unwrap_or(60_000).min(c_int::MAX as u32) as c_int,
+        tolerance: tolerance.unwrap_or(1e-8),
+        ..SolveOptions::default()
+    };
+    let mut stats = SolveStats::default();
+    match solve_view(
+        x,
+        y,
+        &graph,
+        None,
+        &options,
+        &mut stats,
+        None,
+        &mut Vec::new(),
+    ) {
+        COMPASS_OK => Ok(stats.into()),
+        code => Err(JsError::new(&format!("solve failed (code {code})"))),
+    }
+}
+
+/// Run on the wasm target with `wasm-pack test --node -- --features wasm`; natively they run as
+/// plain unit tests.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test::wasm_bindgen_test)]
+    #[cfg_attr(not(target_arch = "wasm32"), test)]
+    fn closes_a_small_loop_in_place() {
+        let mut x = [0.0, 10.3, 9.8, -0.1];
+        let mut y = [0.0, 0.2, 10.1, 9.7];
+        let stats = solve_graph(
+            &mut x,
+            &mut y,
+            &[1, 0, 0, 0],
+            &[0, 1, 2, 3],
+            &[1, 2, 3, 0],
+            &[10.0, 0.0, -10.0, 0.04],
+            &[0.0, 10.0, 0.0, -10.0],
+            &[1.0, 1.0, 1.0, 1.0],
+            None,
+            Some(1e-12),
+        )
+        .unwrap();
+        assert_eq!((stats.free_vertices, stats.passive_vertices), (3, 0));
+
+        // The misclosure of 0.04 in X is spread evenly over the four shots.
+        let expected_x = [0.0, 9.99, 9.98, -0.03];
+        for (actual, expected) in x.iter().zip(expected_x) {
+            assert!((actual - expected).abs() < 1e-9, "{actual} != {expected}");
+        }
+        let expected_y = [0.0, 0.0, 10.0, 10.0];
+        for (actual, expected) in y.iter().zip(expected_y) {
+            assert!((actual - expected).abs() < 1e-9, "{actual} != {expected}");
+        }
+    }
+}
@@ -0,0 +1,267 @@
+//! Change of a single edge weight, for trying out the weight of one shot interactively.
+//!
+//! The weight `w` of an edge between `u` and `v` enters the normal matrix as `w (e_u - e_v)
+//! (e_u - e_v)^T` over the free ends, and the right-hand sides through the observation and
+//! the fixed end. Changing it is an update of rank one to the matrix, in at most four cells
+//! of its pattern. [`GraphContext::update_edge_weight`] adds the difference to those cells
+//! and to the right-hand sides of the cached system in place, instead of a refill over all
+//! the edges, and updates the cached preconditioner along:
+//!
+//! * Jacobi: the inverse diagonal of the rows of the free ends.
+//! * Additive Schwarz: the dense factors of the blocks holding a free end, the others being
+//!   kept; the result is the preconditioner built from scratch.
+//! * Algebraic multigrid: its hierarchy is built again by the next solve.
+//!
+//! The next [`crate::graph_solve`] then starts from the current coordinates with the cached
+//! preconditioner. A weight set to or from 0 may cut a component off its anchors, and locked
+//! loops override the weights of their edges: such changes refill the whole system instead,
+//! like [`GraphContext::recompute_weights`].
+
+use crate::{
+    COMPASS_ERR_INVALID_ARGUMENT, COMPASS_ERR_PANIC, COMPASS_OK, GraphContext, PreconditionerOp,
+    SolveError, inverse_diagonal_entry,
+};
+use nalgebra_sparse::CsrMatrix;
+use std::ffi::{c_double, c_int};
+use std::sync::Arc;
+
+impl GraphContext {
+    /// Sets the weight of `edge` for the next solves, updating the cached normal equations
+    /// and their preconditioner in place. See the [module documentation](self).
+    ///
+    /// Fails with [`COMPASS_ERR_INVALID_ARGUMENT`], changing nothing, for an edge out of range
+    /// or a negative or non-finite weight.
+    pub fn update_edge_weight(&mut self, edge: usize, weight: f64) -> Result<(), SolveError> {
+        let invalid = |message: String| SolveError {
+            code: COMPASS_ERR_INVALID_ARGUMENT,
+            message,
+        };
+        if edge >= self.graph.num_edges() {
+            return Err(invalid(format!("edge {edge} out of range")));
+        }
+        if !(weight.is_finite() && weight >= 0.0) {
+            return Err(invalid(format!(
+                "weight {weight} of edge {edge} is negative or not finite"
+            )));
+        }
+        let previous = std::mem::replace(&mut self.graph.weight[edge], weight);
+        if previous == weight || !self.is_edge_enabled(edge) {
+            return Ok(());
+        }
+        if previous == 0.0 || weight == 0.0 || !self.locked.is_empty() {
+            self.refill();
+            return Ok(());
+        }
+        let multiplier = self
+            .classes
+            .as_ref()
+            .map_or(1.0, |classes| classes.multiplier(edge));
+        let m = self.transform_of(edge);
+        let Some(system) = &mut self.system else {
+            return Ok(());
+        };
+        let dw = (weight - previous) * multiplier;
+        let (u, v) = (self.graph.from[edge], self.graph.to[edge]);
+        let (dx, dy) = (self.graph.dx[edge], self.graph.dy[edge]);
+        let (dx, dy) = (m[0] * dx + m[1] * dy, m[2] * dx + m[3] * dy);
+        let (x, y) = (&self.graph.x, &self.graph.y);
+        let ends = (system.mapping[u], system.mapping[v]);
+        for (i, j, w) in edge_terms(ends, dw) {
+            if !add_to_cell(&mut system.matrix, i, j, w) {
+                unreachable!("the cells of every edge are in the pattern");
+            }
+        }
+        match ends {
+            (Some(ui), Some(vi)) => {
+                system.bx[ui] -= dw * dx;
+                system.bx[vi] += dw * dx;
+                system.by[ui] -= dw * dy;
+                system.by[vi] += dw * dy;
+            }
+            (Some(ui), None) => {
+                system.bx[ui] += dw * (x[v] - dx);
+                system.by[ui] += dw * (y[v] - dy);
+            }
+            (None, Some(vi)) => {
+                system.bx[vi] += dw * (x[u] + dx);
+                system.by[vi] += dw * (y[u] + dy);
+            }
+            (None, None) => return Ok(()),
+        }
+        let rows: Vec<usize> = [ends.0, ends.1].into_iter().flatten().collect();
+
+        let cache = system
+            .preconditioner
+            .get_mut()
+            .unwrap_or_else(|e| e.into_inner());
+        // A preconditioner still shared with a running solve is built again.
+        match cache.as_mut().and_then(|(_, op)| Arc::get_mut(op)) {
+            Some(PreconditionerOp::Jacobi(inv_diag)) => {
+                for &row in &rows {
+                    inv_diag[row] = inverse_diagonal_entry(&system.matrix, row);
+                }
+            }
+            Some(PreconditionerOp::Schwarz(schwarz)) => schwarz.refactor(&system.matrix, &rows),
+            _ => *cache = None,
+        }
+        Ok(())
+    }
+}
+
+/// Terms `(i, j, a)` of the normal matrix of an edge of weight `w` between the unknowns
+/// `ends`, `None` for an end without one.
+pub(crate) fn edge_terms(ends: (Option<usize>, Option<usize>), w: f64) -> Vec<(usize, usize, f64)> {
+    match ends {
+        (Some(ui), Some(vi)) => vec![(ui, ui, w), (vi, vi, w), (ui, vi, -w), (vi, ui, -w)],
+        (Some(i), None) | (None, Some(i)) => vec![(i, i, w)],
+        (None, None) => Vec::new(),
+    }
+}
+
+/// Adds `w` to the cell `(i, j)` of `matrix`. Returns `false`, changing nothing, if the
+/// pattern has no such cell.
+pub(crate) fn add_to_cell(matrix: &mut CsrMatrix<f64>, i: usize, j: usize, w: f64) -> bool {
+    let (offsets, columns, values) = matrix.csr_data_mut();
+    match columns[offsets[i]..offsets[i + 1]].binary_search(&j) {
+        Ok(k) => values[offsets[i] + k] += w,
+        Err(_) => return false,
+    }
+    true
+}
+
+/// Sets the weight of `edge` of the graph behind `handle` to `new_weight`, see
+/// [`GraphContext::update_edge_weight`]. Solve again with [`crate::graph_solve`].
+///
+/// # Returns
+///
+/// * [`COMPASS_OK`] on success.
+/// * [`COMPASS_ERR_INVALID_ARGUMENT`] for a null handle, an edge out of range or a negative or
+///   non-finite weight; nothing changes.
+#[unsafe(no_mangle)]
+pub extern "C" fn graph_update_edge_weight(
+    handle: *mut GraphContext,
+    edge: c_int,
+    new_weight: c_double,
+) -> c_int {
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        // Safety: We assume the caller guarantees a valid (or null) handle.
+        let (Some(ctx), Ok(edge)) = (unsafe { handle.as_mut() }, usize::try_from(edge)) else {
+            return COMPASS_ERR_INVALID_ARGUMENT;
+        };
+        match ctx.update_edge_weight(edge, new_weight) {
+            Ok(()) => COMPASS_OK,
+            Err(err) => err.code,
+        }
+    }));
+
+    result.unwrap_or_else(|_| {
+        eprintln!("Panic caught in graph_update_edge_weight");
+        COMPASS_ERR_PANIC
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::synthetic_cave;
+    use crate::{Graph, Preconditioner, SolveOptions, SolveStats, graph_free, graph_solve};
+
+    /// Solves the graph behind `handle` tightly with `preconditioner`.
+    fn solve(handle: *mut GraphContext, preconditioner: c_int) {
+        let options = SolveOptions {
+            preconditioner,
+            iterations: 10_000,
+            tolerance: 1e-13,
+            ..SolveOptions::default()
+        };
+        let mut stats = SolveStats {
+            struct_size: size_of::<SolveStats>(),
+            ..SolveStats::default()
+        };
+        assert_eq!(graph_solve(handle, &options, &mut stats), COMPASS_OK);
+    }
+
+    /// An edge of `graph` between two free stations.
+    fn free_edge(graph: &Graph) -> usize {
+        (0..graph.num_edges())
+            .rev()
+            .find(|&e| !graph.fixed[graph.from[e]] && !graph.fixed[graph.to[e]])
+            .unwrap()
+    }
+
+    #[test]
+    fn updated_system_solves_like_a_fresh_one() {
+        let graph = synthetic_cave(11, 150);
+        // Shot 0 leaves the fixed station 0.
+        let (edge, fixed_edge) = (free_edge(&graph), 0);
+        assert!(graph.fixed[graph.from[fixed_edge]] || graph.fixed[graph.to[fixed_edge]]);
+        let mut changed = graph.clone();
+        changed.weight[edge] *= 4.0;
+        changed.weight[fixed_edge] *= 0.5;
+        for preconditioner in [1, 3] {
+            let handle = GraphContext::into_raw(graph.clone());
+            solve(handle, preconditioner);
+            for e in [edge, fixed_edge] {
+                let code = graph_update_edge_weight(handle, e as c_int, changed.weight[e]);
+                assert_eq!(code, COMPASS_OK);
+            }
+
+            let fresh = changed.normal_equations(&[]).unwrap();
+            {
+                let ctx = unsafe { &*handle };
+                let system = ctx.system.as_ref().unwrap();
+                let scale = fresh
+                    .matrix
+                    .values()
+                    .iter()
+                    .fold(0.0, |m: f64, v| m.max(v.abs()));
+                for (a, b) in system.matrix.values().iter().zip(fresh.matrix.values()) {
+                    assert!((a - b).abs() < 1e-12 * scale);
+                }
+                assert!((&system.bx - &fresh.bx).amax() < 1e-10 * fresh.bx.amax());
+                assert!((&system.by - &fresh.by).amax() < 1e-10 * fresh.by.amax());
+
+                // The preconditioner is updated, not dropped, and matches a fresh one.
+                let kind = Preconditioner::from_code(preconditioner).unwrap();
+                let cached = system.preconditioner.lock().unwrap().clone().unwrap().1;
+                let rebuilt = PreconditionerOp::new(&fresh.matrix, kind).unwrap();
+                let r: Vec<f64> = (0..system.size()).map(|i| (i as f64).sin()).collect();
+                let (mut z, mut expected) = (vec![0.0; r.len()], vec![0.0; r.len()]);
+                crate::sparse::Preconditioner::apply(&*cached, &r, &mut z);
+                crate::sparse::Preconditioner::apply(&rebuilt, &r, &mut expected);
+                for (a, b) in z.iter().zip(&expected) {
+                    assert!((a - b).abs() < 1e-10 * b.abs().max(1.0));
+                }
+            }
+
+            solve(handle, preconditioner);
+            let scratch = GraphContext::into_raw(changed.clone());
+            solve(scratch, preconditioner);
+            let (updated, expected) =
+                unsafe { ((*handle).coordinates(), (*scratch).coordinates()) };
+            for v in 0..changed.num_vertices() {
+                assert!((updated.0[v] - expected.0[v]).abs() < 1e-10);
+                assert!((updated.1[v] - expected.1[v]).abs() < 1e-10);
+            }
+            graph_free(scratch);
+            graph_free(handle);
+        }
+    }
+
+    #[test]
+    fn invalid_updates_change_nothing() {
+        let graph = synthetic_cave(3, 20);
+        let handle = GraphContext::into_raw(graph.clone());
+        let edges = graph.num_edges() as c_int;
+        for (edge, weight) in [(edges, 1.0), (-1, 1.0), (0, -1.0), (0, f64::NAN)] {
+            let code = graph_update_edge_weight(handle, edge, weight);
+            assert_eq!(code, COMPASS_ERR_INVALID_ARGUMENT);
+        }
+        assert_eq!(unsafe { &*handle }.graph.weight, graph.weight);
+        graph_free(handle);
+        assert_eq!(
+            graph_update_edge_weight(std::ptr::null_mut(), 0, 1.0),
+            COMPASS_ERR_INVALID_ARGUMENT
+        );
+    }
+}
@@ -0,0 +1,622 @@
+//! Weight model presets, to reproduce the weighting assumptions of other survey tools.
+//!
+//! A [`WeightModel`] turns the length of a shot into its edge weight. With `L` the shot
+//! length in meters (clamped to [`MIN_SHOT_LENGTH`] for the length-based presets):
+//!
+//! | Model                  | Weight                                         |
+//! |------------------------|------------------------------------------------|
+//! | `EqualWeights`         | `1`                                            |
+//! | `InverseLength`        | `1 / L`                                        |
+//! | `InverseLengthSquared` | `1 / L²`                                       |
+//! | `Custom`               | `1 / (σ_L² + L² (σ_A² + σ_I²) / 2)`            |
+//! | `CompassDefault`       | `Custom` with σ_L = 0.1 ft, σ_A = σ_I = 2°     |
+//! | `SurvexDefault`        | `Custom` with σ_L = 0.25 m, σ_A = σ_I = 2.5°   |
+//!
+//! The `Custom` weight is the inverse variance of the shot endpoint under independent tape
+//! (σ_L), azimuth (σ_A) and inclination (σ_I) errors, the angular error being averaged over
+//! the two directions perpendicular to the shot. `CompassDefault` is the instrument model
+//! the Compass importer has always used; `SurvexDefault` uses the default `*sd` values of
+//! Survex.
+//!
+//! Surveys described by their grade rather than by instrument sigmas get a `Custom` model
+//! from [`SURVEY_GRADE_SIGMAS`] through [`SurveyGrade::weight_model`].
+//!
+//! Weight models depending on metadata kept by the caller, such as the instrument or the
+//! team of each shot, are applied to a handle edge by edge with [`graph_recompute_weights`].
+
+use crate::crs::FEET_TO_METERS;
+use crate::{
+    COMPASS_ERR_INVALID_ARGUMENT, COMPASS_ERR_PANIC, COMPASS_OK, Graph, GraphContext, SolveError,
+    write_message,
+};
+use std::ffi::{c_char, c_double, c_int, c_void};
+use std::slice;
+
+/// Shorter shots are treated as this long (in meters) by the length-based presets, so that
+/// zero-length shots do not get an infinite weight.
+pub const MIN_SHOT_LENGTH: f64 = 1e-3;
+
+/// How edge weights are derived from shot lengths. See the module documentation for the
+/// formulas.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum WeightModel {
+    /// Every shot has weight 1.
+    #[default]
+    EqualWeights,
+    /// Weight `1 / L`: the variance grows with the length.
+    InverseLength,
+    /// Weight `1 / L²`: the standard deviation grows with the length.
+    InverseLengthSquared,
+    /// Instrument model of the Compass importer: 0.1 ft on the tape, 2° on the angles.
+    CompassDefault,
+    /// Survex default standard deviations: 0.25 m on the tape, 2.5° on the angles.
+    SurvexDefault,
+    /// Instrument model with user standard deviations.
+    Custom {
+        /// Standard deviation of a tape reading, in meters.
+        length_sigma: f64,
+        /// Standard deviation of a compass reading, in degrees.
+        azimuth_sigma_deg: f64,
+        /// Standard deviation of a clinometer reading, in degrees.
+        inclination_sigma_deg: f64,
+    },
+}
+
+impl WeightModel {
+    /// Weight of a shot of `length` meters.
+    pub fn weight(&self, length: f64) -> f64 {
+        let clamped = length.abs().max(MIN_SHOT_LENGTH);
+        match *self {
+            WeightModel::EqualWeights => 1.0,
+            WeightModel::InverseLength => 1.0 / clamped,
+            WeightModel::InverseLengthSquared => 1.0 / (clamped * clamped),
+            WeightModel::CompassDefault => {
+                WeightModel::instrument(0.1 * FEET_TO_METERS, 2.0, 2.0).weight(length)
+            }
+            WeightModel::SurvexDefault => WeightModel::instrument(0.25, 2.5, 2.5).weight(length),
+            WeightModel::Custom {
+                length_sigma,
+                azimuth_sigma_deg,
+                inclination_sigma_deg,
+            } => {
+                let angular = (azimuth_sigma_deg.to_radians().powi(2)
+                    + inclination_sigma_deg.to_radians().powi(2))
+                    / 2.0;
+                1.0 / (length_sigma * length_sigma + length * length * angular)
+            }
+        }
+    }
+
+    /// Weights of shots of the given lengths, in meters.
+    pub fn weights(&self, lengths: &[f64]) -> Vec<f64> {
+        lengths.iter().map(|&length| self.weight(length)).collect()
+    }
+
+    /// FFI code of the model: 0 = equal, 1 = inverse length, 2 = inverse length squared,
+    /// 3 = Compass, 4 = Survex, 5 = custom with the given sigmas.
+    fn from_code(
+        code: c_int,
+        length_sigma: f64,
+        azimuth_sigma_deg: f64,
+        inclination_sigma_deg: f64,
+    ) -> Option<WeightModel> {
+        match code {
+            0 => Some(WeightModel::EqualWeights),
+            1 => Some(WeightModel::InverseLength),
+            2 => Some(WeightModel::InverseLengthSquared),
+            3 => Some(WeightModel::CompassDefault),
+            4 => Some(WeightModel::SurvexDefault),
+            5 => Some(WeightModel::instrument(
+                length_sigma,
+                azimuth_sigma_deg,
+                inclination_sigma_deg,
+            )),
+            _ => None,
+        }
+    }
+
+    fn instrument(length_sigma: f64, azimuth_sigma_deg: f64, inclination_sigma_deg: f64) -> Self {
+        WeightModel::Custom {
+            length_sigma,
+            azimuth_sigma_deg,
+            inclination_sigma_deg,
+        }
+    }
+}
+
+/// Survey grade on the BCRA scale. The UIS (UISv1) grades 1 to 6 share the same accuracy
+/// classes and map to the same variants.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SurveyGrade {
+    /// Sketch from memory, no measurements.
+    Grade1 = 1,
+    /// Sketch with rough measurements, between grades 1 and 3.
+    Grade2 = 2,
+    /// Rough magnetic survey: angles within ±2.5°, distances within ±0.5 m.
+    Grade3 = 3,
+    /// Between grades 3 and 5.
+    Grade4 = 4,
+    /// Magnetic survey with calibrated instruments: angles within ±1°, distances within
+    /// ±0.1 m.
+    Grade5 = 5,
+    /// Better than grade 5, e.g. theodolite survey.
+    Grade6 = 6,
+}
+
+/// Standard deviations assumed for the shots of one survey grade.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GradeSigmas {
+    /// Standard deviation of a tape reading, in meters.
+    pub length_sigma: f64,
+    /// Standard deviation of a compass reading, in degrees.
+    pub azimuth_sigma_deg: f64,
+    /// Standard deviation of a clinometer reading, in degrees.
+    pub inclination_sigma_deg: f64,
+}
+
+/// Default standard deviations of grades 1 to 6, in order. The BCRA tolerances of grades 3
+/// and 5 are taken as one standard deviation; the other grades are interpolated or
+/// extrapolated from them.
+pub const SURVEY_GRADE_SIGMAS: [GradeSigmas; 6] = [
+    GradeSigmas::new(5.0, 20.0, 20.0),
+    GradeSigmas::new(1.0, 5.0, 5.0),
+    GradeSigmas::new(0.5, 2.5, 2.5),
+    GradeSigmas::new(0.25, 1.75, 1.75),
+    GradeSigmas::new(0.1, 1.0, 1.0),
+    GradeSigmas::new(0.05, 0.5, 0.5),
+];
+
+impl GradeSigmas {
+    const fn new(length_sigma: f64, azimuth_sigma_deg: f64, inclination_sigma_deg: f64) -> Self {
+        GradeSigmas {
+            length_sigma,
+            azimuth_sigma_deg,
+            inclination_sigma_deg,
+        }
+    }
+}
+
+impl From<GradeSigmas> for WeightModel {
+    fn from(sigmas: GradeSigmas) -> Self {
+        WeightModel::instrument(
+            sigmas.length_sigma,
+            sigmas.azimuth_sigma_deg,
+            sigmas.inclination_sigma_deg,
+        )
+    }
+}
+
+impl SurveyGrade {
+    /// Grade with the given number, 1 to 6.
+    pub fn from_number(number: u32) -> Option<SurveyGrade> {
+        match number {
+            1 => Some(SurveyGrade::Grade1),
+            2 => Some(SurveyGrade::Grade2),
+            3 => Some(SurveyGrade::Grade3),
+            4 => Some(SurveyGrade::Grade4),
+            5 => Some(SurveyGrade::Grade5),
+            6 => Some(SurveyGrade::Grade6),
+            _ => None,
+        }
+    }
+
+    /// Number of the grade, 1 to 6.
+    pub fn number(self) -> u32 {
+        self as u32
+    }
+
+    /// Standard deviations of the grade in `table`, which lists grades 1 to 6 in order, e.g.
+    /// [`SURVEY_GRADE_SIGMAS`].
+    pub fn sigmas(self, table: &[GradeSigmas; 6]) -> GradeSigmas {
+        table[self as usize - 1]
+    }
+
+    /// Weight model of the grade with the default [`SURVEY_GRADE_SIGMAS`].
+    pub fn weight_model(self) -> WeightModel {
+        self.sigmas(&SURVEY_GRADE_SIGMAS).into()
+    }
+}
+
+impl Graph {
+    /// Replaces every edge weight by the one `model` gives for the 3D length of its observed
+    /// `(dx, dy, dz)` vector.
+    pub fn apply_weight_model(&mut self, model: &WeightModel) {
+        for e in 0..self.num_edges() {
+            let length = (self.dx[e].powi(2) + self.dy[e].powi(2) + self.dz[e].powi(2)).sqrt();
+            self.weight[e] = model.weight(length);
+        }
+    }
+}
+
+impl GraphContext {
+    /// Replaces the weight of each edge `e` by `weight(e, current)`. The cached normal
+    /// equations are refilled in place and their preconditioner rebuilt by the next solve,
+    /// which starts from the current coordinates. A negative or non-finite weight aborts
+    /// with [`COMPASS_ERR_INVALID_ARGUMENT`], naming the edge, before any weight changes.
+    pub fn recompute_weights(
+        &mut self,
+        mut weight: impl FnMut(usize, f64) -> f64,
+    ) -> Result<(), SolveError> {
+        let mut weights = Vec::with_capacity(self.graph.num_edges());
+        for (e, &current) in self.graph.weight.iter().enumerate() {
+            let w = weight(e, current);
+            if !(w.is_finite() && w >= 0.0) {
+                return Err(SolveError {
+                    code: COMPASS_ERR_INVALID_ARGUMENT,
+                    message: format!("weight {w} of edge {e} is negative or not finite"),
+                });
+            }
+            weights.push(w);
+        }
+        self.graph.weight = weights;
+        self.refill();
+        Ok(())
+    }
+}
+
+/// Computes edge weights from shot lengths according to a weight model preset.
+///
+/// # Arguments
+///
+/// * `model` - 0 = equal, 1 = inverse length, 2 = inverse length squared, 3 = Compass,
+///   4 = Survex, 5 = custom (see [`WeightModel`]).
+/// * `length_sigma`, `azimuth_sigma_deg`, `inclination_sigma_deg` - Standard deviations of the
+///   custom model, in meters and degrees. Ignored by the other models.
+/// * `num_edges` - Number of shots.
+/// * `lengths` - Shot lengths in meters.
+/// * `out_weight` - Receives the `num_edges` weights.
+///
+/// # Returns
+///
+/// * [`COMPASS_OK`] on success, [`COMPASS_ERR_INVALID_ARGUMENT`] for an unknown model or a
+///   negative `num_edges`.
+#[unsafe(no_mangle)]
+pub extern "C" fn compute_edge_weights(
+    model: c_int,
+    length_sigma: c_double,
+    azimuth_sigma_deg: c_double,
+    inclination_sigma_deg: c_double,
+    num_edges: c_int,
+    lengths: *const c_double,
+    out_weight: *mut c_double,
+) -> c_int {
+    let Some(model) = WeightModel::from_code(
+        model,
+        length_sigma,
+        azimuth_sigma_deg,
+        inclination_sigma_deg,
+    ) else {
+        return COMPASS_ERR_INVALID_ARGUMENT;
+    };
+    let Ok(n_edges) = usize::try_from(num_edges) else {
+        return COMPASS_ERR_INVALID_ARGUMENT;
+    };
+    let result = std::panic::catch_unwind(|| {
+        // Safety: The caller guarantees arrays of `num_edges` elements.
+        let lengths = unsafe { slice::from_raw_parts(lengths, n_edges) };
+        let out = unsafe { slice::from_raw_parts_mut(out_weight, n_edges) };
+        for (weight, &length) in out.iter_mut().zip(lengths) {
+            *weight = model.weight(length);
+        }
+        COMPASS_OK
+    });
+
+    result.unwrap_or_else(|_| {
+        eprintln!("Panic caught in compute_edge_weights");
+        COMPASS_ERR_PANIC
+    })
+}
+
+/// Copies the default standard deviations of survey grade `grade` (1 to 6, see
+/// [`SURVEY_GRADE_SIGMAS`]) into the output pointers, ready to be passed to
+/// [`compute_edge_weights`] with the custom model. Returns [`COMPASS_ERR_INVALID_ARGUMENT`] for
+/// an unknown grade or a null pointer.
+#[unsafe(no_mangle)]
+pub extern "C" fn survey_grade_sigmas(
+    grade: c_int,
+    out_length_sigma: *mut c_double,
+    out_azimuth_sigma_deg: *mut c_double,
+    out_inclination_sigma_deg: *mut c_double,
+) -> c_int {
+    let Some(grade) = SurveyGrade::from_number(grade.max(0) as u32) else {
+        return COMPASS_ERR_INVALID_ARGUMENT;
+    };
+    if out_length_sigma.is_null()
+        || out_azimuth_sigma_deg.is_null()
+        || out_inclination_sigma_deg.is_null()
+    {
+        return COMPASS_ERR_INVALID_ARGUMENT;
+    }
+    let sigmas = grade.sigmas(&SURVEY_GRADE_SIGMAS);
+    // Safety: The pointers were checked for null; the caller guarantees they are valid.
+    unsafe {
+        *out_length_sigma = sigmas.length_sigma;
+        *out_azimuth_sigma_deg = sigmas.azimuth_sigma_deg;
+        *out_inclination_sigma_deg = sigmas.inclination_sigma_deg;
+    }
+    COMPASS_OK
+}
+
+/// Re-weights every edge of the graph behind `handle` with a weight model preset, see
+/// [`Graph::apply_weight_model`]. Arguments are those of [`compute_edge_weights`]. The cached
+/// normal equations are dropped; the next solve starts from the current coordinates.
+#[unsafe(no_mangle)]
+pub extern "C" fn graph_apply_weight_model(
+    handle: *mut GraphContext,
+    model: c_int,
+    length_sigma: c_double,
+    azimuth_sigma_deg: c_double,
+    inclination_sigma_deg: c_double,
+) -> c_int {
+    let (Some(ctx), Some(model)) = (
+        unsafe { handle.as_mut() },
+        WeightModel::from_code(
+            model,
+            length_sigma,
+            azimuth_sigma_deg,
+            inclination_sigma_deg,
+        ),
+    ) else {
+        return COMPASS_ERR_INVALID_ARGUMENT;
+    };
+    ctx.graph.apply_weight_model(&model);
+    ctx.system = None;
+    COMPASS_OK
+}
+
+/// Gives the new weight of edge `edge`, currently `current_weight`, for
+/// [`graph_recompute_weights`]. `user_data` is passed through.
+pub type WeightCallback =
+    extern "C" fn(user_data: *mut c_void, edge: c_int, current_weight: c_double) -> c_double;
+
+/// Re-weights every edge of the graph behind `handle` with the weight `callback` returns for
+/// it, see [`GraphContext::recompute_weights`]. Unlike [`graph_apply_weight_model`] the
+/// cached normal equations are kept and refilled in place, so that a loop over weight models
+/// only pays for the solves.
+///
+/// * `err_buf` - Buffer receiving a NUL-terminated error message on failure. May be null.
+/// * `err_cap` - Capacity of `err_buf` in bytes.
+///
+/// # Returns
+///
+/// * [`COMPASS_OK`] on success.
+/// * [`COMPASS_ERR_INVALID_ARGUMENT`] for a null handle or callback, or when the callback
+///   returns a negative or non-finite weight, the edge being named in `err_buf`; the weights
+///   are then left unchanged.
+#[unsafe(no_mangle)]
+pub extern "C" fn graph_recompute_weights(
+    handle: *mut GraphContext,
+    callback: Option<WeightCallback>,
+    user_data: *mut c_void,
+    err_buf: *mut c_char,
+    err_cap: usize,
+) -> c_int {
+    let (Some(ctx), Some(callback)) = (unsafe { handle.as_mut() }, callback) else {
+        return COMPASS_ERR_INVALID_ARGUMENT;
+    };
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        ctx.recompute_weights(|e, current| callback(user_data, e as c_int, current))
+    }));
+
+    match result {
+        Ok(Ok(())) => COMPASS_OK,
+        Ok(Err(err)) => {
+            write_message(err_buf, err_cap, &err.message);
+            err.code
+        }
+        Err(_) => {
+            eprintln!("Panic caught in graph_recompute_weights");
+            COMPASS_ERR_PANIC
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LENGTHS: [f64; 4] = [0.0, 1.0, 10.0, 25.5];
+
+    fn assert_pinned(model: WeightModel, expected: [f64; 4]) {
+        for (weight, expected) in model.weights(&LENGTHS).into_iter().zip(expected) {
+            assert!(
+                (weight - expected).abs() <= 1e-12 * expected,
+                "{model:?}: {weight} != {expected}"
+            );
+        }
+    }
+
+    #[test]
+    fn presets_are_pinned() {
+        assert_pinned(WeightModel::EqualWeights, [1.0; 4]);
+        assert_pinned(
+            WeightModel::InverseLength,
+            [1000.0, 1.0, 0.1, 0.0392156862745098],
+        );
+        assert_pinned(
+            WeightModel::InverseLengthSquared,
+            [1e6, 1.0, 0.01, 0.0015378700499807767],
+        );
+        assert_pinned(
+            WeightModel::CompassDefault,
+            [
+                1076.391041670972,
+                465.65772439798144,
+                8.144914427304679,
+                1.2606541995105436,
+            ],
+        );
+        assert_pinned(
+            WeightModel::SurvexDefault,
+            [
+                16.0,
+                15.527019925336713,
+                3.954352733610505,
+                0.7689443477521201,
+            ],
+        );
+        assert_pinned(
+            WeightModel::Custom {
+                length_sigma: 0.05,
+                azimuth_sigma_deg: 1.0,
+                inclination_sigma_deg: 3.0,
+            },
+            [
+                399.99999999999994,
+                248.5653368690636,
+                6.459584868893274,
+                1.0071635655948192,
+            ],
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "compass_io")]
+    fn compass_preset_matches_the_importer_default() {
+        let options = crate::compass_io::CompassImportOptions::default();
+        let angle = options.angle_sigma_deg;
+        let model = WeightModel::Custom {
+            length_sigma: options.length_sigma,
+            azimuth_sigma_deg: angle,
+            inclination_sigma_deg: angle,
+        };
+        for length in LENGTHS {
+            assert_eq!(
+                WeightModel::CompassDefault.weight(length),
+                model.weight(length)
+            );
+        }
+    }
+
+    #[test]
+    fn applies_a_preset_to_a_graph_and_through_the_ffi() {
+        let mut graph = Graph::default();
+        graph.add_vertex(0.0, 0.0, 0.0, true);
+        graph.add_vertex(3.0, 4.0, 0.0, false);
+        graph.add_edge(0, 1, 3.0, 4.0, 12.0, 1.0);
+        graph.add_edge(1, 0, 0.0, 0.0, 0.0, 1.0);
+        graph.apply_weight_model(&WeightModel::InverseLength);
+        assert_eq!(graph.weight, [1.0 / 13.0, 1.0 / MIN_SHOT_LENGTH]);
+
+        let mut out = [0.0; 4];
+        let code = compute_edge_weights(4, 0.0, 0.0, 0.0, 4, LENGTHS.as_ptr(), out.as_mut_ptr());
+        assert_eq!(code, COMPASS_OK);
+        assert_eq!(out.to_vec(), WeightModel::SurvexDefault.weights(&LENGTHS));
+        let code = compute_edge_weights(5, 0.05, 1.0, 3.0, 4, LENGTHS.as_ptr(), out.as_mut_ptr());
+        assert_eq!(code, COMPASS_OK);
+        assert_eq!(out[2], WeightModel::instrument(0.05, 1.0, 3.0).weight(10.0));
+        let code = compute_edge_weights(6, 0.0, 0.0, 0.0, 4, LENGTHS.as_ptr(), out.as_mut_ptr());
+        assert_eq!(code, COMPASS_ERR_INVALID_ARGUMENT);
+
+        let handle = GraphContext::into_raw(graph.clone());
+        assert_eq!(
+            graph_apply_weight_model(handle, 2, 0.0, 0.0, 0.0),
+            COMPASS_OK
+        );
+        assert_eq!(unsafe { &*handle }.graph().weight, [1.0 / 169.0, 1e6]);
+        assert_eq!(
+            graph_apply_weight_model(handle, -1, 0.0, 0.0, 0.0),
+            COMPASS_ERR_INVALID_ARGUMENT
+        );
+        crate::graph_free(handle);
+    }
+
+    #[test]
+    fn finer_grades_weigh_more() {
+        let weights: Vec<f64> = (1..=6)
+            .map(|n| {
+                SurveyGrade::from_number(n)
+                    .unwrap()
+                    .weight_model()
+                    .weight(10.0)
+            })
+            .collect();
+        assert!(weights.windows(2).all(|w| w[0] < w[1]), "{weights:?}");
+        assert!(SurveyGrade::Grade5.weight_model().weight(10.0) > weights[1]);
+        assert_eq!(SurveyGrade::from_number(0), None);
+        assert_eq!(SurveyGrade::from_number(7), None);
+
+        let mut table = SURVEY_GRADE_SIGMAS;
+        table[4] = GradeSigmas::new(1.0, 5.0, 5.0);
+        assert_eq!(SurveyGrade::Grade5.sigmas(&table), SURVEY_GRADE_SIGMAS[1]);
+
+        let (mut length, mut azimuth, mut inclination) = (0.0, 0.0, 0.0);
+        let code = survey_grade_sigmas(3, &mut length, &mut azimuth, &mut inclination);
+        assert_eq!(code, COMPASS_OK);
+        assert_eq!(
+            GradeSigmas::new(length, azimuth, inclination),
+            SURVEY_GRADE_SIGMAS[2]
+        );
+        let code = survey_grade_sigmas(-3, &mut length, &mut azimuth, &mut inclination);
+        assert_eq!(code, COMPASS_ERR_INVALID_ARGUMENT);
+        let code = survey_grade_sigmas(3, std::ptr::null_mut(), &mut azimuth, &mut inclination);
+        assert_eq!(code, COMPASS_ERR_INVALID_ARGUMENT);
+    }
+
+    extern "C" fn by_team(user_data: *mut c_void, edge: c_int, current: c_double) -> c_double {
+        // Odd edges were shot by a team twice as precise, per the table behind `user_data`.
+        let factors = unsafe { &*(user_data as *const [f64; 2]) };
+        current * factors[edge as usize % 2]
+    }
+
+    extern "C" fn broken(_user_data: *mut c_void, edge: c_int, current: c_double) -> c_double {
+        if edge == 2 { f64::NAN } else { current }
+    }
+
+    #[test]
+    fn recomputes_the_weights_in_place_through_a_callback() {
+        let mut graph = Graph::default();
+        for (x, y, fixed) in [(0.0, 0.0, true), (10.2, 0.1, false), (9.9, 10.3, false)] {
+            graph.add_vertex(x, y, 0.0, fixed);
+        }
+        graph.add_vertex(0.1, 10.0, 0.0, false);
+        for (u, v, dx, dy) in [(0, 1, 10.0, 0.0), (1, 2, 0.0, 10.0), (2, 3, -10.0, 0.0)] {
+            graph.add_edge(u, v, dx, dy, 0.0, 1.0);
+        }
+        graph.add_edge(3, 0, 0.0, -10.05, 0.0, 1.0);
+        graph.add_edge(0, 2, 10.02, 10.0, 0.0, 0.5);
+
+        let handle = GraphContext::into_raw(graph.clone());
+        let ctx = unsafe { &mut *handle };
+        let solve = |ctx: &mut GraphContext| {
+            let method = Default::default();
+            ctx.solve(1000, 1e-12.into(), method, None, &|| false)
+                .unwrap()
+                .x
+                .clone()
+        };
+        solve(ctx);
+        let mut factors = [1.0, 2.0];
+        let user_data = (&mut factors as *mut [f64; 2]).cast();
+        let code =
+            graph_recompute_weights(handle, Some(by_team), user_data, std::ptr::null_mut(), 0);
+        assert_eq!(code, COMPASS_OK);
+        let expected: Vec<f64> = (0..graph.num_edges())
+            .map(|e| graph.weight[e] * factors[e % 2])
+            .collect();
+        assert_eq!(ctx.graph().weight, expected);
+        assert!(ctx.system.is_some(), "refilled, not dropped");
+
+        graph.weight = expected.clone();
+        let fresh = graph.solve(1000, 1e-12).unwrap();
+        for (a, b) in solve(ctx).iter().zip(&fresh.x) {
+            assert!((a - b).abs() < 1e-9, "{a} != {b}");
+        }
+
+        let mut message = [0 as c_char; 64];
+        let (buf, cap) = (message.as_mut_ptr(), message.len());
+        let code = graph_recompute_weights(handle, Some(broken), std::ptr::null_mut(), buf, cap);
+        assert_eq!(code, COMPASS_ERR_INVALID_ARGUMENT);
+        let message = unsafe { std::ffi::CStr::from_ptr(message.as_ptr()) };
+        assert_eq!(
+            message.to_str().unwrap(),
+            "weight NaN of edge 2 is negative or not finite"
+        );
+        assert_eq!(ctx.graph().weight, expected);
+        assert_eq!(
+            graph_recompute_weights(handle, None, std::ptr::null_mut(), buf, cap),
+            COMPASS_ERR_INVALID_ARGUMENT
+        );
+        crate::graph_free(handle);
+    }
+}
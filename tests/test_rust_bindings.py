@@ -0,0 +1,129 @@
+# -*- coding: utf-8 -*-
+"""Tests for the PyO3 bindings of the Rust loop-closure solver.
+
+The extension is built with ``make rust-bindings`` (maturin); these tests are
+skipped when it is not installed.
+"""
+
+import json
+import math
+from pathlib import Path
+
+import pytest
+
+np = pytest.importorskip("numpy")
+compass_loop_closure = pytest.importorskip("compass_loop_closure")
+
+
+def _triangle():
+    """One fixed anchor and two free stations closing a small loop."""
+    x = np.array([0.0, 1.2, 0.9])
+    y = np.array([0.0, 0.1, 1.1])
+    fixed = np.array([True, False, False])
+    edges = np.array(
+        [
+            [0, 1, 1.0, 0.0],
+            [1, 2, 0.0, 1.0],
+            [0, 2, 1.0, 1.0],
+        ]
+    )
+    weights = np.ones(3)
+    return x, y, fixed, edges, weights
+
+
+def _misclosed_triangle():
+    """The triangle with a misclosure of (0.05, -0.05) and unequal weights."""
+    x, y, fixed, edges, weights = _triangle()
+    edges[2, 2:] = [1.05, 0.95]
+    weights = np.array([1.0, 2.0, 4.0])
+    return x, y, fixed, edges, weights
+
+
+def _assert_text_matches(actual, expected, tolerance=1e-3):
+    """Compare two reports token by token, numbers within `tolerance`."""
+    actual_lines = actual.splitlines()
+    expected_lines = expected.splitlines()
+    assert len(actual_lines) == len(expected_lines)
+    for actual_line, expected_line in zip(actual_lines, expected_lines):
+        actual_tokens = actual_line.split()
+        expected_tokens = expected_line.split()
+        assert len(actual_tokens) == len(expected_tokens), actual_line
+        for a, e in zip(actual_tokens, expected_tokens):
+            try:
+                a_number, e_number = float(a.strip("[],")), float(e.strip("[],"))
+            except ValueError:
+                assert a == e, actual_line
+            else:
+                assert math.isclose(
+                    a_number, e_number, rel_tol=tolerance, abs_tol=tolerance
+                ), actual_line
+
+
+class TestSolve:
+    """Tests for compass_loop_closure.solve."""
+
+    def test_closes_consistent_loop(self):
+        x, y, stats = compass_loop_closure.solve(*_triangle(), tolerance=1e-12)
+        np.testing.assert_allclose(x, [0.0, 1.0, 1.0], atol=1e-9)
+        np.testing.assert_allclose(y, [0.0, 0.0, 1.0], atol=1e-9)
+        assert stats == {"free_vertices": 2, "passive_vertices": 0}
+
+    def test_inputs_are_not_modified(self):
+        x, y, fixed, edges, weights = _triangle()
+        compass_loop_closure.solve(x, y, fixed, edges, weights)
+        assert x[1] == 1.2
+        assert y[2] == 1.1
+
+    def test_fixed_vertices_stay_put(self):
+        x, y, fixed, edges, weights = _triangle()
+        fixed[:] = True
+        out_x, out_y, stats = compass_loop_closure.solve(x, y, fixed, edges, weights)
+        np.testing.assert_array_equal(out_x, x)
+        np.testing.assert_array_equal(out_y, y)
+        assert stats["free_vertices"] == 0
+
+    def test_length_mismatch_raises(self):
+        x, y, fixed, edges, weights = _triangle()
+        with pytest.raises(ValueError, match="same length"):
+            compass_loop_closure.solve(x, y[:2], fixed, edges, weights)
+
+    def test_bad_edge_shape_raises(self):
+        x, y, fixed, edges, weights = _triangle()
+        with pytest.raises(ValueError, match="shape"):
+            compass_loop_closure.solve(x, y, fixed, edges[:, :3], weights)
+
+    def test_out_of_range_vertex_raises(self):
+        x, y, fixed, edges, weights = _triangle()
+        edges[0, 1] = 7
+        with pytest.raises(ValueError, match="invalid vertex"):
+            compass_loop_closure.solve(x, y, fixed, edges, weights)
+
+    def test_solver_error_is_runtime_error(self):
+        assert issubclass(compass_loop_closure.SolverError, RuntimeError)
+
+
+class TestReport:
+    """Tests for compass_loop_closure.report."""
+
+    GOLDEN = Path(__file__).parent / "artifacts" / "triangle_report.txt"
+
+    def test_text_matches_golden_file(self):
+        report = compass_loop_closure.report(*_misclosed_triangle())
+        _assert_text_matches(report, self.GOLDEN.read_text())
+
+    def test_json_structure(self):
+        report = json.loads(
+            compass_loop_closure.report(*_misclosed_triangle(), format="json")
+        )
+        assert report["version"] == 5
+        assert report["network"]["loops"] == 1
+        (loop,) = report["loops"]
+        assert loop["edges"] == 3
+        assert loop["misclosure"] == pytest.approx(math.hypot(0.05, 0.05))
+        assert report["chi_square"]["degrees_of_freedom"] == 2
+        assert [r["edge"] for r in report["worst_residuals"]] == [0, 1, 2]
+        assert report["rejected"] == []
+
+    def test_unknown_format_raises(self):
+        with pytest.raises(ValueError, match="format"):
+            compass_loop_closure.report(*_triangle(), format="xml")